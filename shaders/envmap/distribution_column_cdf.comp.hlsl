@@ -0,0 +1,14 @@
+// One thread, builds the prefix sum over the row sums and writes the total sum.
+// Dispatched as (1, 1, 1).
+
+#include "distribution_common.hlsl"
+
+[numthreads(1, 1, 1)]
+void main() {
+  float column_sum = 0.0;
+  for (uint v = 0; v < g_push_constants.height; v++) {
+    column_sum += g_row_sum[v];
+    g_column_cdf[v] = column_sum;
+  }
+  g_total_sum[0] = column_sum;
+}
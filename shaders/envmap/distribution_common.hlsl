@@ -0,0 +1,39 @@
+// The shared bindings of the environment map distribution programs, see EnvMapDistributionProgramDescs in src/envmap.rs.
+// The programs build the same distributions as EnvMap::build_distribution_maps:
+//   the conditional distribution(width x height) maps the column u of the row v to the column found by the row CDF,
+//   the marginal distribution(1 x height) maps the row v to the row found by the column CDF of the row sums.
+
+[[vk::binding(0, 0)]] [[vk::combinedImageSampler]]
+Texture2D<float4> g_env_map;
+[[vk::binding(0, 0)]] [[vk::combinedImageSampler]]
+SamplerState g_env_map_sampler;
+
+// The prefix sums of the row luminance, not normalized(width x height floats).
+[[vk::binding(1, 0)]]
+RWStructuredBuffer<float> g_row_cdf;
+// The luminance sums of the rows(height floats).
+[[vk::binding(2, 0)]]
+RWStructuredBuffer<float> g_row_sum;
+// The prefix sums of the row sums, not normalized(height floats).
+[[vk::binding(3, 0)]]
+RWStructuredBuffer<float> g_column_cdf;
+// The total luminance(1 float).
+[[vk::binding(4, 0)]]
+RWStructuredBuffer<float> g_total_sum;
+
+[[vk::binding(5, 0)]]
+RWTexture2D<float> g_marginal_distribution;
+[[vk::binding(6, 0)]]
+RWTexture2D<float> g_conditional_distribution;
+
+struct PushConstants {
+  uint width;
+  uint height;
+};
+[[vk::push_constant]]
+PushConstants g_push_constants;
+
+// TV BT.709 for HDR.
+float Luminance(float3 c) {
+  return 0.212671 * c.r + 0.715160 * c.g + 0.072169 * c.b;
+}
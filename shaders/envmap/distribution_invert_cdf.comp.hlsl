@@ -0,0 +1,50 @@
+// One thread per pixel, inverts the normalized CDFs into the distribution images like EnvMap::build_distribution_maps.
+// Dispatched as (width / 8, height / 8, 1), the threads of the column 0 also write the marginal distribution.
+
+#include "distribution_common.hlsl"
+
+// The first index in [lower, upper) whose normalized CDF is not less than the value.
+uint LowerBoundRow(uint lower, uint upper, float row_sum, float value) {
+  while (lower < upper) {
+    const uint mid = (lower + upper) / 2;
+    if (g_row_cdf[mid] / row_sum < value) {
+      lower = mid + 1;
+    } else {
+      upper = mid;
+    }
+  }
+  return lower;
+}
+
+uint LowerBoundColumn(uint lower, uint upper, float column_sum, float value) {
+  while (lower < upper) {
+    const uint mid = (lower + upper) / 2;
+    if (g_column_cdf[mid] / column_sum < value) {
+      lower = mid + 1;
+    } else {
+      upper = mid;
+    }
+  }
+  return lower;
+}
+
+[numthreads(8, 8, 1)]
+void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
+  const uint width = g_push_constants.width;
+  const uint height = g_push_constants.height;
+  const uint u = dispatch_thread_id.x;
+  const uint v = dispatch_thread_id.y;
+  if (u >= width || v >= height) {
+    return;
+  }
+
+  const float inv_width = 1.0 / width;
+  const uint col = LowerBoundRow(v * width, (v + 1) * width, g_row_sum[v], (u + 1) * inv_width) - v * width;
+  g_conditional_distribution[uint2(u, v)] = col * inv_width;
+
+  if (u == 0) {
+    const float inv_height = 1.0 / height;
+    const uint row = LowerBoundColumn(0, height, g_column_cdf[height - 1], (v + 1) * inv_height);
+    g_marginal_distribution[uint2(0, v)] = row * inv_height;
+  }
+}
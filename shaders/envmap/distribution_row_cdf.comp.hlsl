@@ -0,0 +1,21 @@
+// One thread per row, builds the luminance prefix sum of the row and writes the row sum.
+// Dispatched as (height / 64, 1, 1).
+
+#include "distribution_common.hlsl"
+
+[numthreads(64, 1, 1)]
+void main(uint3 dispatch_thread_id : SV_DispatchThreadID) {
+  const uint width = g_push_constants.width;
+  const uint v = dispatch_thread_id.x;
+  if (v >= g_push_constants.height) {
+    return;
+  }
+
+  // The sum runs in the same order as the CPU build.
+  float row_sum = 0.0;
+  for (uint u = 0; u < width; u++) {
+    row_sum += Luminance(g_env_map.Load(int3(u, v, 0)).rgb);
+    g_row_cdf[v * width + u] = row_sum;
+  }
+  g_row_sum[v] = row_sum;
+}
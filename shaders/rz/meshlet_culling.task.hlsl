@@ -0,0 +1,185 @@
+// The reference task shader of the rasterization renderer for the meshlet culling.
+// Each task group has 32 threads, each thread tests one meshlet of the primitive with the
+// frustum(bounding sphere) and the normal cone, the visible meshlets are compacted into the payload
+// and emitted to the mesh shader.
+//
+// The bindings and the push constants follow the layout of the rasterization renderer:
+//   set 0 binding 0: The global uniform buffer.
+//   set 1 binding 1: The object uniform buffers(indexed by the mesh index).
+//   set 1 binding 4: The meshlet information storage buffers(indexed by the draw index).
+//   push constants: mesh_index, material_index, draw_index, meshlet_count and first_meshlet.
+//   The meshlet_count and first_meshlet are the meshlet range of the selected level of detail.
+//   Define USE_MEGA_BUFFERS if the scene is uploaded into the mega buffers, the storage buffer arrays have
+//   only one element and the first_meshlet is already offset by the primitive.
+//   Define USE_BINDLESS if the renderer uses the bindless descriptor set, the push constants have only the
+//   draw_index and the others are read from the primitive table in the set 2 binding 2 slot 0.
+//
+// The mesh shader reads the meshlet index by "payload.meshlet_indices[group_id.x]".
+
+#define TASK_GROUP_SIZE 32
+
+struct GlobalUniform {
+  float4x4 v_mtx;
+  float4x4 p_mtx;
+  float4x4 vp_mtx;
+  float4x4 i_vp_mtx;
+  float4 camera_position;
+};
+
+struct ObjectUniform {
+  float4x4 m_mtx;
+  float4x4 i_m_mtx;
+  float4x4 mv_mtx;
+  float4x4 t_mv_mtx;
+  float4x4 it_mv_mtx;
+  float4x4 mvp_mtx;
+  float4x4 prev_mvp_mtx;
+};
+
+struct Meshlet {
+  float3 center;
+  float radius;
+  float3 cone_apex;
+  float cone_cutoff;
+  float3 cone_axis;
+  uint num_of_vertices;
+  uint num_of_primitives;
+  uint offset_of_vertices;
+  uint offset_of_primitives;
+  uint draw_index;
+};
+
+#ifdef USE_BINDLESS
+struct BindlessPrimitive {
+  uint mesh_index;
+  uint material_index;
+  uint vertex_buffer;
+  uint index_buffer;
+  uint meshlet_buffer;
+  uint meshlet_vertex_buffer;
+  uint meshlet_primitive_buffer;
+  uint vertex_offset;
+  uint first_index;
+  uint index_count;
+  uint first_meshlet;
+  uint meshlet_count;
+};
+
+struct PushConstants {
+  uint draw_index;
+};
+#else
+struct PushConstants {
+  uint mesh_index;
+  uint material_index;
+  uint draw_index;
+  uint meshlet_count;
+  uint first_meshlet;
+};
+#endif
+
+struct TaskPayload {
+  uint meshlet_indices[TASK_GROUP_SIZE];
+};
+
+[[vk::binding(0, 0)]]
+cbuffer GlobalUniformBuffer {
+  GlobalUniform g_global_uniform;
+};
+
+[[vk::binding(1, 1)]]
+ConstantBuffer<ObjectUniform> g_object_uniforms[];
+
+#ifdef USE_BINDLESS
+[[vk::binding(2, 2)]]
+StructuredBuffer<BindlessPrimitive> g_primitives[];
+
+[[vk::binding(2, 2)]]
+StructuredBuffer<Meshlet> g_meshlets[];
+#else
+[[vk::binding(4, 1)]]
+StructuredBuffer<Meshlet> g_meshlets[];
+#endif
+
+[[vk::push_constant]]
+PushConstants g_push_constants;
+
+groupshared TaskPayload s_payload;
+
+// Test the bounding sphere against the frustum planes in the clip space.
+bool is_sphere_visible(float3 center, float radius) {
+  float4x4 vp = g_global_uniform.vp_mtx;
+  float4 row0 = float4(vp[0][0], vp[0][1], vp[0][2], vp[0][3]);
+  float4 row1 = float4(vp[1][0], vp[1][1], vp[1][2], vp[1][3]);
+  float4 row3 = float4(vp[3][0], vp[3][1], vp[3][2], vp[3][3]);
+  float4 planes[4] = {
+    row3 + row0,
+    row3 - row0,
+    row3 + row1,
+    row3 - row1,
+  };
+
+  for (uint i = 0; i < 4; i++) {
+    float4 plane = planes[i] / length(planes[i].xyz);
+    if (dot(plane.xyz, center) + plane.w < -radius) {
+      return false;
+    }
+  }
+  return true;
+}
+
+// Test the normal cone, the meshlet is back-facing if all triangles face away from the camera.
+bool is_cone_visible(float3 cone_apex, float3 cone_axis, float cone_cutoff) {
+  return dot(normalize(cone_apex - g_global_uniform.camera_position.xyz), cone_axis) < cone_cutoff;
+}
+
+[numthreads(TASK_GROUP_SIZE, 1, 1)]
+void main(uint3 group_id : SV_GroupID, uint3 group_thread_id : SV_GroupThreadID) {
+  const uint local_index = group_id.x * TASK_GROUP_SIZE + group_thread_id.x;
+#ifdef USE_BINDLESS
+  const BindlessPrimitive primitive = g_primitives[0][g_push_constants.draw_index];
+  const uint mesh_index = primitive.mesh_index;
+  const uint meshlet_count = primitive.meshlet_count;
+  const uint meshlet_index = primitive.first_meshlet + local_index;
+#else
+  const uint mesh_index = g_push_constants.mesh_index;
+  const uint meshlet_count = g_push_constants.meshlet_count;
+  const uint meshlet_index = g_push_constants.first_meshlet + local_index;
+#endif
+
+  bool is_visible = false;
+  if (local_index < meshlet_count) {
+#if defined(USE_BINDLESS)
+    const Meshlet meshlet = g_meshlets[NonUniformResourceIndex(primitive.meshlet_buffer)][meshlet_index];
+#elif defined(USE_MEGA_BUFFERS)
+    const Meshlet meshlet = g_meshlets[0][meshlet_index];
+#else
+    const Meshlet meshlet = g_meshlets[g_push_constants.draw_index][meshlet_index];
+#endif
+    const float4x4 m_mtx = g_object_uniforms[mesh_index].m_mtx;
+
+    // Transform the bounds from the object space to the world space.
+    // m_mtx[i] is the row i, the scale of each axis is the length of the column of the upper 3x3.
+    const float3 scale = float3(
+      length(float3(m_mtx[0][0], m_mtx[1][0], m_mtx[2][0])),
+      length(float3(m_mtx[0][1], m_mtx[1][1], m_mtx[2][1])),
+      length(float3(m_mtx[0][2], m_mtx[1][2], m_mtx[2][2])));
+    const float3 center = mul(m_mtx, float4(meshlet.center, 1.0)).xyz;
+    const float radius = meshlet.radius * max(scale.x, max(scale.y, scale.z));
+    const float3 cone_apex = mul(m_mtx, float4(meshlet.cone_apex, 1.0)).xyz;
+    const float3 cone_axis = normalize(mul((float3x3)m_mtx, meshlet.cone_axis));
+
+    // The cone cutoff 1 means the cone is degenerated, it can not be culled.
+    is_visible = is_sphere_visible(center, radius)
+      && (meshlet.cone_cutoff >= 1.0 || is_cone_visible(cone_apex, cone_axis, meshlet.cone_cutoff));
+  }
+
+  // Compact the visible meshlets.
+  if (is_visible) {
+    uint index = WavePrefixCountBits(is_visible);
+    s_payload.meshlet_indices[index] = meshlet_index;
+  }
+  const uint visible_count = WaveActiveCountBits(is_visible);
+
+  DispatchMesh(visible_count, 1, 1, s_payload);
+}
\ No newline at end of file
@@ -0,0 +1,26 @@
+// The octahedral normal encoding of the G-Buffer normal image of the rasterization renderer.
+// The unit normal is projected onto the octahedron |x| + |y| + |z| = 1, the lower half is folded over the
+// diagonals onto the upper half, and the xy of the result is stored in two signed channels in [-1, 1].
+// The default normal format R16G16_SNORM halves the normal target of R16G16B16A16_SFLOAT, and its error is
+// far below the R8G8B8A8_UNORM formats. The R8G8_SNORM, R16G16_SFLOAT and R32G32_SFLOAT formats work as well.
+//
+// The G-Buffer fragment shader writes "EncodeOctahedralNormal(normal)" to the normal target,
+// the lighting shader reads "DecodeOctahedralNormal(normal_image.Load(...).xy)".
+
+float2 OctahedralWrap(float2 v) {
+  return (1.0 - abs(v.yx)) * select(v >= 0.0, 1.0, -1.0);
+}
+
+// Encode a unit normal into [-1, 1]^2.
+float2 EncodeOctahedralNormal(float3 n) {
+  n /= abs(n.x) + abs(n.y) + abs(n.z);
+  return n.z >= 0.0 ? n.xy : OctahedralWrap(n.xy);
+}
+
+// Decode a unit normal from [-1, 1]^2.
+float3 DecodeOctahedralNormal(float2 e) {
+  float3 n = float3(e.xy, 1.0 - abs(e.x) - abs(e.y));
+  float t = saturate(-n.z);
+  n.xy += select(n.xy >= 0.0, -t, t);
+  return normalize(n);
+}
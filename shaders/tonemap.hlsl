@@ -0,0 +1,120 @@
+// The tone mapping operators of HalaToneMapType, they match src/tonemap.rs.
+// The shaders read "tonemap_type" of the settings uniform and call "ApplyToneMap(color, tonemap_type)",
+// the legacy flags enable_tonemap, enable_aces and use_simple_aces can not express Uncharted 2 and AgX.
+
+#define TONEMAP_LINEAR 0
+#define TONEMAP_REINHARD 1
+#define TONEMAP_ACES_FITTED 2
+#define TONEMAP_ACES_APPROX 3
+#define TONEMAP_UNCHARTED2 4
+#define TONEMAP_AGX 5
+
+float ToneMapLuminance(float3 c) {
+  return dot(c, float3(0.212671, 0.715160, 0.072169));
+}
+
+// The Reinhard tone mapping on luminance.
+float3 ToneMapReinhard(float3 c, float limit) {
+  return c * 1.0 / (1.0 + ToneMapLuminance(c) / limit);
+}
+
+// The fitted ACES tone mapping.
+float3 ToneMapACESFitted(float3 color) {
+  // The matrices are in the row major order.
+  const float3x3 ACES_INPUT_MATRIX = float3x3(
+    0.59719, 0.35458, 0.04823,
+    0.07600, 0.90834, 0.01566,
+    0.02840, 0.13383, 0.83777
+  );
+  const float3x3 ACES_OUTPUT_MATRIX = float3x3(
+    1.60475, -0.53108, -0.07367,
+    -0.10208, 1.10813, -0.00605,
+    -0.00327, -0.07276, 1.07602
+  );
+
+  color = mul(ACES_INPUT_MATRIX, color);
+  float3 a = color * (color + 0.0245786) - 0.000090537;
+  float3 b = color * (0.983729 * color + 0.432951) + 0.238081;
+  color = mul(ACES_OUTPUT_MATRIX, a / b);
+  return saturate(color);
+}
+
+// The approximated ACES tone mapping(Narkowicz).
+float3 ToneMapACESApprox(float3 c) {
+  const float A = 2.51;
+  const float B = 0.03;
+  const float Y = 2.43;
+  const float D = 0.59;
+  const float E = 0.14;
+
+  return saturate((c * (A * c + B)) / (c * (Y * c + D) + E));
+}
+
+float3 ToneMapUncharted2Partial(float3 x) {
+  const float A = 0.15;
+  const float B = 0.50;
+  const float C = 0.10;
+  const float D = 0.20;
+  const float E = 0.02;
+  const float F = 0.30;
+
+  return ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F;
+}
+
+// The Uncharted 2 filmic tone mapping(Hable).
+float3 ToneMapUncharted2(float3 c) {
+  const float W = 11.2;
+  const float EXPOSURE_BIAS = 2.0;
+
+  float3 white_scale = 1.0 / ToneMapUncharted2Partial(W);
+  return saturate(ToneMapUncharted2Partial(c * EXPOSURE_BIAS) * white_scale);
+}
+
+// The AgX tone mapping with the default look, the result is in the linear space.
+float3 ToneMapAgX(float3 c) {
+  // The matrices are in the row major order.
+  const float3x3 AGX_INSET_MATRIX = float3x3(
+    0.842479062253094, 0.0784335999999992, 0.0792237451477643,
+    0.0423282422610123, 0.878468636469772, 0.0791661274605434,
+    0.0423756549057051, 0.0784336, 0.879142973793104
+  );
+  const float3x3 AGX_OUTSET_MATRIX = float3x3(
+    1.19687900512017, -0.0980208811401368, -0.0990297440797205,
+    -0.0528968517574562, 1.15190312990417, -0.0989611768448433,
+    -0.0529716355144438, -0.0980434501171241, 1.15107367264116
+  );
+  const float MIN_EV = -12.47393;
+  const float MAX_EV = 4.026069;
+
+  // Encode to the log domain.
+  float3 v = mul(AGX_INSET_MATRIX, max(c, 1e-10));
+  v = clamp(log2(v), MIN_EV, MAX_EV);
+  float3 x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+
+  // Apply the sigmoid curve.
+  float3 x2 = x * x;
+  float3 x4 = x2 * x2;
+  v = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+  // Decode back to the linear domain.
+  v = saturate(mul(AGX_OUTSET_MATRIX, v));
+  return pow(v, 2.2);
+}
+
+// Apply the tone mapping operator of the tone map type to the linear HDR color.
+float3 ApplyToneMap(float3 color, uint tonemap_type) {
+  switch (tonemap_type) {
+    case TONEMAP_REINHARD:
+      return ToneMapReinhard(color, 1.5);
+    case TONEMAP_ACES_FITTED:
+      return ToneMapACESFitted(color);
+    case TONEMAP_ACES_APPROX:
+      return ToneMapACESApprox(color);
+    case TONEMAP_UNCHARTED2:
+      return ToneMapUncharted2(color);
+    case TONEMAP_AGX:
+      return ToneMapAgX(color);
+    default:
+      return color;
+  }
+}
@@ -0,0 +1,284 @@
+use glam::{Mat4, Vec2, Vec3};
+use winit::event::{
+  ElementState,
+  KeyEvent,
+  MouseButton,
+  MouseScrollDelta,
+  WindowEvent,
+};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+/// The max pitch of the controllers, it keeps the forward direction away from the up axis.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Get the forward direction from the yaw and the pitch, the yaw 0 and the pitch 0 look at -Z.
+/// param yaw: The rotation around the Y axis in radians.
+/// param pitch: The rotation around the X axis in radians.
+/// return: The normalized forward direction.
+fn get_forward(yaw: f32, pitch: f32) -> Vec3 {
+  Vec3::new(-yaw.sin() * pitch.cos(), pitch.sin(), -yaw.cos() * pitch.cos())
+}
+
+/// Get the yaw and the pitch of the forward direction.
+/// param forward: The forward direction.
+/// return: The yaw and the pitch in radians.
+fn get_yaw_pitch(forward: Vec3) -> (f32, f32) {
+  let forward = forward.normalize_or_zero();
+  ((-forward.x).atan2(-forward.z), forward.y.clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH))
+}
+
+/// Get the scroll lines of the mouse wheel, the pixel delta is converted with 1 line per 40 pixels.
+/// param delta: The mouse wheel delta.
+/// return: The scroll lines, positive is away from the user.
+fn get_scroll_lines(delta: &MouseScrollDelta) -> f32 {
+  match delta {
+    MouseScrollDelta::LineDelta(_, y) => *y,
+    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+  }
+}
+
+/// The orbit camera controller.
+/// The left button drag rotates around the target, the right or the middle button drag pans the target and the wheel zooms.
+pub struct HalaOrbitController {
+  pub target: Vec3,
+  pub distance: f32,
+  pub yaw: f32,
+  pub pitch: f32,
+  // The radians per pixel of the rotation.
+  pub rotate_speed: f32,
+  // The distance fraction per pixel of the panning.
+  pub pan_speed: f32,
+  // The distance fraction per line of the zooming.
+  pub zoom_speed: f32,
+  pub min_distance: f32,
+
+  is_rotating: bool,
+  is_panning: bool,
+  last_cursor_position: Option<Vec2>,
+}
+
+/// The implementation of the orbit camera controller.
+impl HalaOrbitController {
+
+  /// Create a new orbit camera controller.
+  /// param target: The world space position to orbit around.
+  /// param distance: The distance from the camera to the target.
+  /// param yaw: The rotation around the Y axis in radians, 0 looks at -Z.
+  /// param pitch: The rotation around the X axis in radians, positive looks up.
+  /// return: The orbit camera controller.
+  pub fn new(target: Vec3, distance: f32, yaw: f32, pitch: f32) -> Self {
+    Self {
+      target,
+      distance,
+      yaw,
+      pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+      rotate_speed: 0.005,
+      pan_speed: 0.001,
+      zoom_speed: 0.1,
+      min_distance: 0.01,
+      is_rotating: false,
+      is_panning: false,
+      last_cursor_position: None,
+    }
+  }
+
+  /// Create a new orbit camera controller from a view matrix, e.g. the camera of the scene.
+  /// param v_mtx: The view matrix.
+  /// param distance: The distance from the camera to the target in front of it.
+  /// return: The orbit camera controller.
+  pub fn from_view_matrix(v_mtx: Mat4, distance: f32) -> Self {
+    let world_mtx = v_mtx.inverse();
+    let position = world_mtx.w_axis.truncate();
+    let forward = -world_mtx.z_axis.truncate();
+    let (yaw, pitch) = get_yaw_pitch(forward);
+    Self::new(position + forward.normalize_or_zero() * distance, distance, yaw, pitch)
+  }
+
+  /// Handle a window event.
+  /// param event: The window event.
+  /// return: True if the view is changed.
+  pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+    match event {
+      WindowEvent::MouseInput { state, button, .. } => {
+        let is_pressed = *state == ElementState::Pressed;
+        match button {
+          MouseButton::Left => self.is_rotating = is_pressed,
+          MouseButton::Right | MouseButton::Middle => self.is_panning = is_pressed,
+          _ => (),
+        }
+        false
+      },
+      WindowEvent::CursorMoved { position, .. } => {
+        let position = Vec2::new(position.x as f32, position.y as f32);
+        let delta = self.last_cursor_position.map_or(Vec2::ZERO, |last_position| position - last_position);
+        self.last_cursor_position = Some(position);
+        if delta == Vec2::ZERO {
+          return false;
+        }
+
+        if self.is_rotating {
+          self.yaw -= delta.x * self.rotate_speed;
+          self.pitch = (self.pitch - delta.y * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+          true
+        } else if self.is_panning {
+          let forward = get_forward(self.yaw, self.pitch);
+          let right = forward.cross(Vec3::Y).normalize_or_zero();
+          let up = right.cross(forward);
+          let scale = self.distance * self.pan_speed;
+          self.target += (-right * delta.x + up * delta.y) * scale;
+          true
+        } else {
+          false
+        }
+      },
+      WindowEvent::CursorLeft { .. } => {
+        self.last_cursor_position = None;
+        false
+      },
+      WindowEvent::MouseWheel { delta, .. } => {
+        let lines = get_scroll_lines(delta);
+        self.distance = (self.distance * (1.0 - lines * self.zoom_speed).max(0.1)).max(self.min_distance);
+        lines != 0.0
+      },
+      _ => false,
+    }
+  }
+
+  /// Get the position of the camera.
+  /// return: The world space position.
+  pub fn get_position(&self) -> Vec3 {
+    self.target - get_forward(self.yaw, self.pitch) * self.distance
+  }
+
+  /// Get the view matrix, pass it to set_view_matrix of the renderers.
+  /// return: The view matrix.
+  pub fn get_view_matrix(&self) -> Mat4 {
+    Mat4::look_at_rh(self.get_position(), self.target, Vec3::Y)
+  }
+
+}
+
+/// The first person fly camera controller.
+/// WASD moves, Q and E move down and up, the shift key boosts and the right button drag looks around.
+/// The movement is applied by update with the frame time.
+pub struct HalaFlyController {
+  pub position: Vec3,
+  pub yaw: f32,
+  pub pitch: f32,
+  // The units per second of the movement.
+  pub move_speed: f32,
+  // The multiplier of the movement speed when the shift key is pressed.
+  pub boost_factor: f32,
+  // The radians per pixel of the looking.
+  pub look_speed: f32,
+
+  // The pressed keys: forward, backward, left, right, down, up and boost.
+  pressed_keys: [bool; 7],
+  is_looking: bool,
+  last_cursor_position: Option<Vec2>,
+}
+
+/// The implementation of the fly camera controller.
+impl HalaFlyController {
+
+  /// Create a new fly camera controller.
+  /// param position: The world space position of the camera.
+  /// param yaw: The rotation around the Y axis in radians, 0 looks at -Z.
+  /// param pitch: The rotation around the X axis in radians, positive looks up.
+  /// return: The fly camera controller.
+  pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+    Self {
+      position,
+      yaw,
+      pitch: pitch.clamp(-MAX_PITCH, MAX_PITCH),
+      move_speed: 5.0,
+      boost_factor: 4.0,
+      look_speed: 0.003,
+      pressed_keys: [false; 7],
+      is_looking: false,
+      last_cursor_position: None,
+    }
+  }
+
+  /// Create a new fly camera controller from a view matrix, e.g. the camera of the scene.
+  /// param v_mtx: The view matrix.
+  /// return: The fly camera controller.
+  pub fn from_view_matrix(v_mtx: Mat4) -> Self {
+    let world_mtx = v_mtx.inverse();
+    let (yaw, pitch) = get_yaw_pitch(-world_mtx.z_axis.truncate());
+    Self::new(world_mtx.w_axis.truncate(), yaw, pitch)
+  }
+
+  /// Handle a window event.
+  /// param event: The window event.
+  /// return: True if the view is changed, the movement keys change the view in update.
+  pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+    match event {
+      WindowEvent::KeyboardInput { event: KeyEvent { physical_key: PhysicalKey::Code(key_code), state, .. }, .. } => {
+        let key_index = match key_code {
+          KeyCode::KeyW => 0,
+          KeyCode::KeyS => 1,
+          KeyCode::KeyA => 2,
+          KeyCode::KeyD => 3,
+          KeyCode::KeyQ => 4,
+          KeyCode::KeyE => 5,
+          KeyCode::ShiftLeft | KeyCode::ShiftRight => 6,
+          _ => return false,
+        };
+        self.pressed_keys[key_index] = *state == ElementState::Pressed;
+        false
+      },
+      WindowEvent::MouseInput { state, button: MouseButton::Right, .. } => {
+        self.is_looking = *state == ElementState::Pressed;
+        false
+      },
+      WindowEvent::CursorMoved { position, .. } => {
+        let position = Vec2::new(position.x as f32, position.y as f32);
+        let delta = self.last_cursor_position.map_or(Vec2::ZERO, |last_position| position - last_position);
+        self.last_cursor_position = Some(position);
+        if !self.is_looking || delta == Vec2::ZERO {
+          return false;
+        }
+
+        self.yaw -= delta.x * self.look_speed;
+        self.pitch = (self.pitch - delta.y * self.look_speed).clamp(-MAX_PITCH, MAX_PITCH);
+        true
+      },
+      WindowEvent::CursorLeft { .. } => {
+        self.last_cursor_position = None;
+        false
+      },
+      // The keys released outside of the window are never reported.
+      WindowEvent::Focused(false) => {
+        self.pressed_keys = [false; 7];
+        self.is_looking = false;
+        false
+      },
+      _ => false,
+    }
+  }
+
+  /// Move the camera by the pressed keys.
+  /// param delta_time: The frame time in seconds.
+  /// return: True if the view is changed.
+  pub fn update(&mut self, delta_time: f64) -> bool {
+    let forward = get_forward(self.yaw, self.pitch);
+    let right = forward.cross(Vec3::Y).normalize_or_zero();
+    let axis = |negative: usize, positive: usize| self.pressed_keys[positive] as i32 as f32 - self.pressed_keys[negative] as i32 as f32;
+    let direction = forward * axis(1, 0) + right * axis(2, 3) + Vec3::Y * axis(4, 5);
+    if direction == Vec3::ZERO {
+      return false;
+    }
+
+    let speed = if self.pressed_keys[6] { self.move_speed * self.boost_factor } else { self.move_speed };
+    self.position += direction.normalize() * speed * delta_time as f32;
+    true
+  }
+
+  /// Get the view matrix, pass it to set_view_matrix of the renderers.
+  /// return: The view matrix.
+  pub fn get_view_matrix(&self) -> Mat4 {
+    Mat4::look_to_rh(self.position, get_forward(self.yaw, self.pitch), Vec3::Y)
+  }
+
+}
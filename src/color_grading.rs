@@ -0,0 +1,254 @@
+use std::rc::Rc;
+use std::path::Path;
+
+use hala_gfx::{
+  HalaContext,
+  HalaFormat,
+  HalaBuffer,
+  HalaCommandBufferSet,
+  HalaImage,
+  HalaSampler,
+};
+
+use crate::error::HalaRendererError;
+
+/// The 3D color grading LUT loaded from a .cube file.
+/// The LUT is uploaded as a 2D array image with size x size texels per layer and size layers,
+/// red along the X axis, green along the Y axis and blue along the layers, the same order as the .cube file.
+/// The shaders sample the two nearest layers and blend them to get the trilinear filtering.
+/// The LUT is applied to the display-referred color, so it must be sampled after the tone mapping.
+pub struct HalaColorGradingLUT {
+  pub size: u32,
+  pub domain_min: [f32; 3],
+  pub domain_max: [f32; 3],
+  pub image: HalaImage,
+  pub sampler: HalaSampler,
+}
+
+impl HalaColorGradingLUT {
+
+  /// Create a new color grading LUT with the given .cube file path.
+  /// param path: The file path.
+  /// param context: The GFX context.
+  /// param dst_stage_mask: The pipeline stages which sample the LUT.
+  /// param transfer_staging_buffer: The transfer staging buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// return: The result.
+  pub fn new_with_file<P: AsRef<Path>>(
+    path: P,
+    context: &HalaContext,
+    dst_stage_mask: hala_gfx::HalaPipelineStageFlags2,
+    transfer_staging_buffer: &HalaBuffer,
+    transfer_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<Self, HalaRendererError> {
+    let path = path.as_ref();
+    let file_name = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?;
+    let content = std::fs::read_to_string(path)
+      .map_err(|e| HalaRendererError::io("Failed to read the LUT file.", path, e))?;
+    let (size, domain_min, domain_max, data) = Self::parse_cube(&content)
+      .map_err(|e| HalaRendererError::new(&format!("Failed to parse the LUT file \"{}\": {}", path.to_string_lossy(), e.message()), None))?;
+
+    let data_size = std::mem::size_of_val(data.as_slice()) as u64;
+    if data_size > transfer_staging_buffer.size {
+      return Err(HalaRendererError::out_of_memory("The transfer staging buffer for the color grading LUT", data_size, transfer_staging_buffer.size));
+    }
+
+    let image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
+      HalaFormat::R32G32B32A32_SFLOAT,
+      size,
+      size,
+      1,
+      size,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      &format!("color_grading_lut_{}.image", file_name.to_string_lossy())
+    )?;
+    image.update_gpu_memory_with_buffer(
+      data.as_slice(),
+      dst_stage_mask,
+      hala_gfx::HalaAccessFlags2::SHADER_READ,
+      hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      transfer_staging_buffer,
+      transfer_command_buffers)?;
+
+    // The LUT must not wrap around, the edge texels are the domain bounds.
+    let sampler = HalaSampler::new(
+      Rc::clone(&context.logical_device),
+      (hala_gfx::HalaFilter::LINEAR, hala_gfx::HalaFilter::LINEAR),
+      hala_gfx::HalaSamplerMipmapMode::NEAREST,
+      (hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE),
+      0.0,
+      false,
+      0.0,
+      (0.0, 0.0),
+      &format!("color_grading_lut_{}.sampler", file_name.to_string_lossy())
+    )?;
+
+    Ok(Self {
+      size,
+      domain_min,
+      domain_max,
+      image,
+      sampler,
+    })
+  }
+
+  /// Parse the content of the .cube file.
+  /// Only the 3D LUT is supported, the keywords before the data are TITLE, LUT_3D_SIZE, DOMAIN_MIN, DOMAIN_MAX and LUT_3D_INPUT_RANGE.
+  /// param content: The content of the .cube file.
+  /// return: The size, the domain min, the domain max and the RGBA data in the file order(red changes fastest).
+  pub fn parse_cube(content: &str) -> Result<(u32, [f32; 3], [f32; 3], Vec<f32>), HalaRendererError> {
+    fn parse_values<const N: usize>(tokens: &[&str], line_number: usize) -> Result<[f32; N], HalaRendererError> {
+      if tokens.len() != N {
+        return Err(HalaRendererError::new(&format!("Line {}: expected {} values but got {}.", line_number, N, tokens.len()), None));
+      }
+      let mut values = [0f32; N];
+      for (value, token) in values.iter_mut().zip(tokens.iter()) {
+        *value = token.parse::<f32>()
+          .map_err(|e| HalaRendererError::new(&format!("Line {}: invalid value \"{}\".", line_number, token), Some(Box::new(e))))?;
+      }
+      Ok(values)
+    }
+
+    let mut size = 0u32;
+    let mut domain_min = [0f32; 3];
+    let mut domain_max = [1f32; 3];
+    let mut data = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+      let line_number = index + 1;
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      let tokens = line.split_whitespace().collect::<Vec<_>>();
+      match tokens[0] {
+        "TITLE" => (),
+        "LUT_1D_SIZE" | "LUT_1D_INPUT_RANGE" => {
+          return Err(HalaRendererError::new(&format!("Line {}: the 1D LUT is not supported.", line_number), None));
+        },
+        "LUT_3D_SIZE" => {
+          size = tokens.get(1)
+            .and_then(|token| token.parse::<u32>().ok())
+            .filter(|size| (2..=256).contains(size))
+            .ok_or(HalaRendererError::new(&format!("Line {}: invalid LUT size.", line_number), None))?;
+          data.reserve((size * size * size * 4) as usize);
+        },
+        "DOMAIN_MIN" => domain_min = parse_values::<3>(&tokens[1..], line_number)?,
+        "DOMAIN_MAX" => domain_max = parse_values::<3>(&tokens[1..], line_number)?,
+        "LUT_3D_INPUT_RANGE" => {
+          let [min, max] = parse_values::<2>(&tokens[1..], line_number)?;
+          domain_min = [min; 3];
+          domain_max = [max; 3];
+        },
+        _ => {
+          if size == 0 {
+            return Err(HalaRendererError::new(&format!("Line {}: the data appears before LUT_3D_SIZE.", line_number), None));
+          }
+          let rgb = parse_values::<3>(&tokens, line_number)?;
+          data.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 1.0]);
+        },
+      }
+    }
+
+    if size == 0 {
+      return Err(HalaRendererError::new("LUT_3D_SIZE is missing.", None));
+    }
+    let num_of_entries = (size * size * size) as usize;
+    if data.len() / 4 != num_of_entries {
+      return Err(HalaRendererError::new(&format!("Expected {} entries but got {}.", num_of_entries, data.len() / 4), None));
+    }
+    if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+      return Err(HalaRendererError::new("DOMAIN_MAX must be greater than DOMAIN_MIN.", None));
+    }
+
+    Ok((size, domain_min, domain_max, data))
+  }
+
+  /// Get the push constants of the color grading shaders.
+  /// The layout is the domain min with the LUT size in w and the domain max with 0 in w(2 x vec4).
+  /// return: The push constants.
+  pub fn get_push_constants(&self) -> [f32; 8] {
+    [
+      self.domain_min[0], self.domain_min[1], self.domain_min[2], self.size as f32,
+      self.domain_max[0], self.domain_max[1], self.domain_max[2], 0.0,
+    ]
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Build the content of an identity .cube file.
+  /// param header: The lines before the data.
+  /// param size: The LUT size.
+  /// return: The content.
+  fn identity_cube(header: &str, size: u32) -> String {
+    let mut content = format!("# Identity LUT\n{}\n", header);
+    let scale = 1.0 / (size - 1) as f32;
+    for b in 0..size {
+      for g in 0..size {
+        for r in 0..size {
+          content.push_str(&format!("{} {} {}\n", r as f32 * scale, g as f32 * scale, b as f32 * scale));
+        }
+      }
+    }
+    content
+  }
+
+  #[test]
+  fn test_parse_valid() {
+    let (size, domain_min, domain_max, data) = HalaColorGradingLUT::parse_cube(&identity_cube("TITLE \"identity\"\nLUT_3D_SIZE 2", 2)).unwrap();
+    assert_eq!(size, 2);
+    assert_eq!(domain_min, [0.0; 3]);
+    assert_eq!(domain_max, [1.0; 3]);
+    assert_eq!(data.len(), 2 * 2 * 2 * 4);
+    // Red changes fastest and the alpha is 1.
+    assert_eq!(&data[0..8], &[0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(&data[data.len() - 4..], &[1.0, 1.0, 1.0, 1.0]);
+  }
+
+  #[test]
+  fn test_parse_domain() {
+    let (_, domain_min, domain_max, _) = HalaColorGradingLUT::parse_cube(
+      &identity_cube("LUT_3D_SIZE 2\nDOMAIN_MIN 0 0.1 0.2\nDOMAIN_MAX 1 2 3", 2)).unwrap();
+    assert_eq!(domain_min, [0.0, 0.1, 0.2]);
+    assert_eq!(domain_max, [1.0, 2.0, 3.0]);
+
+    let (_, domain_min, domain_max, _) = HalaColorGradingLUT::parse_cube(
+      &identity_cube("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -0.5 4", 2)).unwrap();
+    assert_eq!(domain_min, [-0.5; 3]);
+    assert_eq!(domain_max, [4.0; 3]);
+  }
+
+  #[test]
+  fn test_parse_malformed() {
+    let malformed = [
+      // The size is missing or out of range.
+      identity_cube("", 2),
+      identity_cube("LUT_3D_SIZE 1", 2),
+      identity_cube("LUT_3D_SIZE abc", 2),
+      // The 1D LUT is not supported.
+      identity_cube("LUT_1D_SIZE 2\nLUT_3D_SIZE 2", 2),
+      // The entry count does not match the size.
+      identity_cube("LUT_3D_SIZE 3", 2),
+      // The input range needs exactly 2 values.
+      identity_cube("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0", 2),
+      identity_cube("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 1 2", 2),
+      identity_cube("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 0 x", 2),
+      // The domain is empty.
+      identity_cube("LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 0 1", 2),
+      identity_cube("LUT_3D_SIZE 2\nDOMAIN_MIN 0 0", 2),
+      // An entry has 2 values or a bad value.
+      "LUT_3D_SIZE 2\n0 0\n".to_string(),
+      "LUT_3D_SIZE 2\n0 0 zero\n".to_string(),
+    ];
+    for content in malformed.iter() {
+      assert!(HalaColorGradingLUT::parse_cube(content).is_err(), "The content is accepted:\n{}", content);
+    }
+  }
+
+}
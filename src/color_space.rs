@@ -0,0 +1,145 @@
+/// The RGB color space(primaries and white point) of the linear color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaColorSpace(u8);
+impl HalaColorSpace {
+  pub const SRGB: Self = Self(0);
+  pub const ACESCG: Self = Self(1);
+  pub const ACES2065_1: Self = Self(2);
+  pub const REC2020: Self = Self(3);
+  pub const DISPLAY_P3: Self = Self(4);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::SRGB,
+      1 => Self::ACESCG,
+      2 => Self::ACES2065_1,
+      3 => Self::REC2020,
+      4 => Self::DISPLAY_P3,
+      _ => panic!("Invalid color space."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Get the chromaticities of the primaries and the white point.
+  /// return: The xy chromaticities(red, green, blue, white).
+  fn get_chromaticities(&self) -> [glam::Vec2; 4] {
+    const D65: glam::Vec2 = glam::Vec2::new(0.3127, 0.3290);
+    const D60: glam::Vec2 = glam::Vec2::new(0.32168, 0.33767);
+    match *self {
+      Self::SRGB => [glam::Vec2::new(0.640, 0.330), glam::Vec2::new(0.300, 0.600), glam::Vec2::new(0.150, 0.060), D65],
+      Self::ACESCG => [glam::Vec2::new(0.713, 0.293), glam::Vec2::new(0.165, 0.830), glam::Vec2::new(0.128, 0.044), D60],
+      Self::ACES2065_1 => [glam::Vec2::new(0.7347, 0.2653), glam::Vec2::new(0.0000, 1.0000), glam::Vec2::new(0.0001, -0.0770), D60],
+      Self::REC2020 => [glam::Vec2::new(0.708, 0.292), glam::Vec2::new(0.170, 0.797), glam::Vec2::new(0.131, 0.046), D65],
+      Self::DISPLAY_P3 => [glam::Vec2::new(0.680, 0.320), glam::Vec2::new(0.265, 0.690), glam::Vec2::new(0.150, 0.060), D65],
+      _ => panic!("Invalid color space."),
+    }
+  }
+
+  /// Get the matrix converting the linear RGB color to the CIE XYZ color.
+  /// return: The RGB to XYZ matrix.
+  pub fn get_rgb_to_xyz_matrix(&self) -> glam::Mat3 {
+    let [r, g, b, w] = self.get_chromaticities();
+    let primaries = glam::Mat3::from_cols(xy_to_xyz(r), xy_to_xyz(g), xy_to_xyz(b));
+    let scale = primaries.inverse() * xy_to_xyz(w);
+    glam::Mat3::from_cols(primaries.x_axis * scale.x, primaries.y_axis * scale.y, primaries.z_axis * scale.z)
+  }
+
+  /// Get the matrix converting the linear color from this color space to the target color space.
+  /// The Bradford chromatic adaptation is applied if the white points are different.
+  /// param target: The target color space.
+  /// return: The conversion matrix.
+  pub fn get_conversion_matrix(&self, target: Self) -> glam::Mat3 {
+    if *self == target {
+      return glam::Mat3::IDENTITY;
+    }
+
+    const BRADFORD_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
+      glam::Vec3::new(0.8951, -0.7502, 0.0389),
+      glam::Vec3::new(0.2664, 1.7135, -0.0685),
+      glam::Vec3::new(-0.1614, 0.0367, 1.0296)
+    );
+    let src_white = BRADFORD_MATRIX * xy_to_xyz(self.get_chromaticities()[3]);
+    let dst_white = BRADFORD_MATRIX * xy_to_xyz(target.get_chromaticities()[3]);
+    let adaptation = BRADFORD_MATRIX.inverse() * glam::Mat3::from_diagonal(dst_white / src_white) * BRADFORD_MATRIX;
+
+    target.get_rgb_to_xyz_matrix().inverse() * adaptation * self.get_rgb_to_xyz_matrix()
+  }
+
+  /// Convert the linear color from this color space to the target color space.
+  /// param color: The linear color.
+  /// param target: The target color space.
+  /// return: The converted linear color.
+  pub fn convert(&self, color: glam::Vec3, target: Self) -> glam::Vec3 {
+    self.get_conversion_matrix(target) * color
+  }
+}
+
+/// The file format of the saved images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaImageFileFormat(u8);
+impl HalaImageFileFormat {
+  /// The linear 32-bit float portable float map.
+  pub const PFM: Self = Self(0);
+  /// The sRGB encoded 8-bit PNG.
+  pub const PNG: Self = Self(1);
+  /// The linear 32-bit float OpenEXR.
+  pub const EXR: Self = Self(2);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::PFM,
+      1 => Self::PNG,
+      2 => Self::EXR,
+      _ => panic!("Invalid image file format."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Get the file extension of the format.
+  /// return: The file extension.
+  pub fn get_extension(&self) -> &'static str {
+    match *self {
+      Self::PFM => "pfm",
+      Self::PNG => "png",
+      Self::EXR => "exr",
+      _ => panic!("Invalid image file format."),
+    }
+  }
+
+  /// Get the format from the file extension.
+  /// param extension: The file extension.
+  /// return: The format, none if the extension is not supported.
+  pub fn from_extension(extension: &str) -> Option<Self> {
+    match extension.to_ascii_lowercase().as_str() {
+      "pfm" => Some(Self::PFM),
+      "png" => Some(Self::PNG),
+      "exr" => Some(Self::EXR),
+      _ => None,
+    }
+  }
+}
+
+/// Encode the linear value with the sRGB transfer function.
+/// param v: The linear value.
+/// return: The encoded value.
+pub fn srgb_encode(v: f32) -> f32 {
+  let v = v.clamp(0.0, 1.0);
+  if v <= 0.0031308 {
+    v * 12.92
+  } else {
+    1.055 * v.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+/// Convert the xy chromaticity to the XYZ color with Y = 1.
+/// param xy: The xy chromaticity.
+/// return: The XYZ color.
+fn xy_to_xyz(xy: glam::Vec2) -> glam::Vec3 {
+  glam::Vec3::new(xy.x / xy.y, 1.0, (1.0 - xy.x - xy.y) / xy.y)
+}
\ No newline at end of file
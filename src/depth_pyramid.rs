@@ -0,0 +1,311 @@
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaImage,
+  HalaPipelineCache,
+  HalaSampler,
+};
+
+use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+use crate::scene::HalaDepthConvention;
+use crate::renderer::get_depth_aspect_mask;
+
+/// The hierarchical depth pyramid(Hi-Z) built from the scene depth for the occlusion culling.
+/// Each level is a R32_SFLOAT storage image, the level 0 is the largest power of two not greater than the render size
+/// and each next level halves it down to 1 x 1. Each texel keeps the farthest depth of its footprint,
+/// the minimum for the reverse Z(GREATER compare) and the maximum for the standard Z(LESS compare),
+/// so an object is occluded if its nearest depth is farther than the pyramid depth.
+/// The levels are separate images like the bloom chain, bind them as an image array indexed by the level.
+pub struct HalaDepthPyramid {
+  pub(crate) width: u32,
+  pub(crate) height: u32,
+  pub(crate) depth_convention: HalaDepthConvention,
+  pub(crate) mip_images: Vec<HalaImage>,
+  pub(crate) sampler: HalaSampler,
+  // The set of each level reads the depth image(the level 0) or the previous level and writes the level.
+  pub(crate) descriptor_sets: Vec<HalaDescriptorSet>,
+  pub(crate) program: HalaComputeProgram,
+  pub(crate) is_ready: Cell<bool>,
+}
+
+/// The implementation of the depth pyramid.
+impl HalaDepthPyramid {
+
+  /// Create a new depth pyramid.
+  /// The compute shader runs 8 x 8 groups over the destination level with the push constants:
+  /// the source width and height, the destination width and height, the reduce mode(0: min, 1: max) and the source kind(0: depth image, 1: previous level).
+  /// Each destination texel must reduce the whole source footprint, which is up to 3 x 3 texels when the source size is odd.
+  /// The bindings are 0: the depth image(sampled), 1: the source level(storage) and 2: the destination level(storage).
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param shader_file_path: The compute shader file path.
+  /// param depth_image: The scene depth image, it must be sampled.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_convention: The depth convention of the scene depth.
+  /// return: The depth pyramid.
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    shader_file_path: &str,
+    depth_image: &HalaImage,
+    width: u32,
+    height: u32,
+    depth_convention: HalaDepthConvention,
+  ) -> Result<Self, HalaRendererError> {
+    let (pyramid_width, pyramid_height) = Self::get_size_for(width, height);
+    let num_of_mips = Self::get_mip_count_for(width, height);
+
+    let mut mip_images = Vec::with_capacity(num_of_mips);
+    for mip_index in 0..num_of_mips {
+      mip_images.push(HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::SAMPLED,
+        hala_gfx::HalaFormat::R32_SFLOAT,
+        (pyramid_width >> mip_index).max(1),
+        (pyramid_height >> mip_index).max(1),
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        &format!("depth_pyramid_{}.image", mip_index),
+      )?);
+    }
+
+    // The depth must not be filtered across the texels, the reduction is done by the shader.
+    let sampler = HalaSampler::new(
+      Rc::clone(&context.logical_device),
+      (hala_gfx::HalaFilter::NEAREST, hala_gfx::HalaFilter::NEAREST),
+      hala_gfx::HalaSamplerMipmapMode::NEAREST,
+      (hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE),
+      0.0,
+      false,
+      0.0,
+      (0.0, 0.0),
+      "depth_pyramid.sampler",
+    )?;
+
+    let binding = |binding_index: u32, descriptor_type: hala_gfx::HalaDescriptorType| hala_gfx::HalaDescriptorSetLayoutBinding {
+      binding_index,
+      descriptor_type,
+      descriptor_count: 1,
+      stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+      binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+    };
+    let mut descriptor_sets = Vec::with_capacity(num_of_mips);
+    for mip_index in 0..num_of_mips {
+      let descriptor_set = HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE),  // Depth image.
+            binding(1, hala_gfx::HalaDescriptorType::STORAGE_IMAGE),  // Source level.
+            binding(2, hala_gfx::HalaDescriptorType::STORAGE_IMAGE),  // Destination level.
+          ],
+          &format!("depth_pyramid_{}.descriptor_set_layout", mip_index),
+        )?,
+        0,
+        &format!("depth_pyramid_{}.descriptor_set", mip_index),
+      )?;
+      descriptor_set.update_sampled_images(0, 0, &[depth_image]);
+      descriptor_set.update_storage_images(0, 1, &[&mip_images[mip_index.saturating_sub(1)]]);
+      descriptor_set.update_storage_images(0, 2, &[&mip_images[mip_index]]);
+      descriptor_sets.push(descriptor_set);
+    }
+
+    let program = HalaComputeProgram::new(
+      Rc::clone(&context.logical_device),
+      &[&descriptor_sets[0].layout],
+      &HalaComputeProgramDesc {
+        shader_file_path: shader_file_path.to_string(),
+        push_constant_size: 24, // Source size, Destination size, Reduce mode and Source kind.
+        bindings: Vec::new(),
+      },
+      Some(pipeline_cache),
+      "depth_pyramid",
+    )?;
+
+    Ok(Self {
+      width: pyramid_width,
+      height: pyramid_height,
+      depth_convention,
+      mip_images,
+      sampler,
+      descriptor_sets,
+      program,
+      is_ready: Cell::new(false),
+    })
+  }
+
+  /// Get the size of the level 0, the largest power of two not greater than the render size.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The width and the height.
+  pub fn get_size_for(width: u32, height: u32) -> (u32, u32) {
+    (1 << width.max(1).ilog2(), 1 << height.max(1).ilog2())
+  }
+
+  /// Get the level count of the full chain down to 1 x 1.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The level count.
+  pub fn get_mip_count_for(width: u32, height: u32) -> usize {
+    let (width, height) = Self::get_size_for(width, height);
+    width.max(height).ilog2() as usize + 1
+  }
+
+  /// Record the pyramid build after the depth producing pass.
+  /// The depth image is sampled in the SHADER_READ_ONLY_OPTIMAL layout and restored to its layout after the build,
+  /// it is ready for the depth test if restored to the DEPTH_STENCIL_ATTACHMENT_OPTIMAL layout, otherwise for the shader reads.
+  /// The levels are left in the GENERAL layout and visible to the compute, the task and the mesh shaders.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param depth_image: The depth image which the pyramid is created with.
+  /// param depth_layout: The layout of the depth image.
+  /// return: The pipeline bind count.
+  pub fn record(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    depth_image: &HalaImage,
+    depth_layout: hala_gfx::HalaImageLayout,
+  ) -> Result<u64, HalaRendererError> {
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Depth Pyramid", [0.5, 0.5, 0.5, 1.0]);
+    }
+
+    // The levels are fully written every frame, so their previous contents are discarded.
+    let mut barriers = vec![
+      hala_gfx::HalaImageBarrierInfo {
+        old_layout: depth_layout,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: get_depth_aspect_mask(depth_image.format),
+        image: depth_image.raw,
+        ..Default::default()
+      },
+    ];
+    for mip_image in self.mip_images.iter() {
+      barriers.push(hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: mip_image.raw,
+        ..Default::default()
+      });
+    }
+    command_buffers.set_image_barriers(index, barriers.as_slice());
+
+    let reduce_mode = if self.depth_convention.is_reverse_z() { 0u32 } else { 1u32 };
+    let mut source_size = (depth_image.extent.width, depth_image.extent.height);
+    for (mip_index, (descriptor_set, mip_image)) in self.descriptor_sets.iter().zip(self.mip_images.iter()).enumerate() {
+      let destination_size = ((self.width >> mip_index).max(1), (self.height >> mip_index).max(1));
+      let push_constants = [
+        source_size.0.to_le_bytes(),
+        source_size.1.to_le_bytes(),
+        destination_size.0.to_le_bytes(),
+        destination_size.1.to_le_bytes(),
+        reduce_mode.to_le_bytes(),
+        ((mip_index > 0) as u32).to_le_bytes(),
+      ].concat();
+      self.program.bind(index, command_buffers, &[descriptor_set]);
+      self.program.push_constants(index, command_buffers, 0, push_constants.as_slice());
+      self.program.dispatch(index, command_buffers, (destination_size.0 + 7) / 8, (destination_size.1 + 7) / 8, 1);
+
+      // The next level reads this one, the last one is read by the culling.
+      command_buffers.set_image_barriers(
+        index,
+        &[hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::GENERAL,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: mip_image.raw,
+          ..Default::default()
+        }],
+      );
+      source_size = destination_size;
+    }
+
+    let (dst_access_mask, dst_stage_mask) = if depth_layout == hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
+      (
+        hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ | hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+      )
+    } else {
+      (
+        hala_gfx::HalaAccessFlags2::INPUT_ATTACHMENT_READ | hala_gfx::HalaAccessFlags2::SHADER_READ,
+        hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      )
+    };
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        new_layout: depth_layout,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        dst_access_mask,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        dst_stage_mask,
+        aspect_mask: get_depth_aspect_mask(depth_image.format),
+        image: depth_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    self.is_ready.set(true);
+    Ok(1)
+  }
+
+  /// Whether the pyramid has been built, the culling must skip the occlusion test before the first build.
+  /// return: True if the pyramid has been recorded at least once.
+  pub fn is_ready(&self) -> bool {
+    self.is_ready.get()
+  }
+
+  /// Get the size of the level 0.
+  /// return: The width and the height.
+  pub fn get_size(&self) -> (u32, u32) {
+    (self.width, self.height)
+  }
+
+  /// Get the images of the levels, the level 0 first.
+  /// return: The images.
+  pub fn get_mip_images(&self) -> &[HalaImage] {
+    &self.mip_images
+  }
+
+  /// Get the nearest clamping sampler of the pyramid.
+  /// return: The sampler.
+  pub fn get_sampler(&self) -> &HalaSampler {
+    &self.sampler
+  }
+
+}
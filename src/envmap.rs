@@ -1,4 +1,5 @@
 use std::rc::Rc;
+use std::cell::RefCell;
 use std::path::Path;
 
 use image::GenericImageView;
@@ -16,6 +17,86 @@ use hala_gfx::{
 };
 
 use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+
+/// The compute program descriptions to build the environment map distribution on the GPU.
+/// The HLSL sources are in shaders/envmap.
+/// All programs share one descriptor set:
+/// 0: the environment map(combined image sampler), 1: the row CDF buffer(width x height floats),
+/// 2: the row sum buffer(height floats), 3: the column CDF buffer(height floats), 4: the total sum buffer(1 float),
+/// 5: the marginal distribution image(storage, R32), 6: the conditional distribution image(storage, R32).
+/// The push constants are the width and the height of the environment map(2 x u32).
+pub struct EnvMapDistributionProgramDescs {
+  /// One thread per row, builds the luminance prefix sum of the row and writes the row sum. Dispatched as (height / 64, 1, 1).
+  pub row_cdf: HalaComputeProgramDesc,
+  /// One thread, builds the prefix sum over the row sums and writes the total sum. Dispatched as (1, 1, 1).
+  pub column_cdf: HalaComputeProgramDesc,
+  /// One thread per pixel, inverts the normalized CDFs into the distribution images like build_distribution_maps.
+  /// Dispatched as (width / 8, height / 8, 1), the threads of the column 0 also write the marginal distribution.
+  pub invert_cdf: HalaComputeProgramDesc,
+}
+
+/// The compute programs to build the environment map distribution and their descriptor set layout.
+struct EnvMapDistributionPrograms {
+  descriptor_set_layout: hala_gfx::HalaDescriptorSetLayout,
+  row_cdf_program: HalaComputeProgram,
+  column_cdf_program: HalaComputeProgram,
+  invert_cdf_program: HalaComputeProgram,
+}
+
+/// The implementation of the environment map distribution programs.
+impl EnvMapDistributionPrograms {
+  /// Create the descriptor set layout and the compute programs.
+  /// param context: The GFX context.
+  /// param program_descs: The compute program descriptions.
+  /// return: The programs.
+  fn new(context: &HalaContext, program_descs: &EnvMapDistributionProgramDescs) -> Result<Self, HalaRendererError> {
+    let logical_device = &context.logical_device;
+    let binding = |binding_index: u32, descriptor_type: hala_gfx::HalaDescriptorType| hala_gfx::HalaDescriptorSetLayoutBinding {
+      binding_index,
+      descriptor_type,
+      descriptor_count: 1,
+      stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+      binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+    };
+    let descriptor_set_layout = hala_gfx::HalaDescriptorSetLayout::new(
+      Rc::clone(logical_device),
+      &[
+        binding(0, hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER), // Environment map.
+        binding(1, hala_gfx::HalaDescriptorType::STORAGE_BUFFER),         // Row CDF.
+        binding(2, hala_gfx::HalaDescriptorType::STORAGE_BUFFER),         // Row sum.
+        binding(3, hala_gfx::HalaDescriptorType::STORAGE_BUFFER),         // Column CDF.
+        binding(4, hala_gfx::HalaDescriptorType::STORAGE_BUFFER),         // Total sum.
+        binding(5, hala_gfx::HalaDescriptorType::STORAGE_IMAGE),          // Marginal distribution.
+        binding(6, hala_gfx::HalaDescriptorType::STORAGE_IMAGE),          // Conditional distribution.
+      ],
+      "env_distribution.descriptor_set_layout",
+    )?;
+
+    let create_program = |desc: &HalaComputeProgramDesc, name: &str| {
+      HalaComputeProgram::new(
+        Rc::clone(logical_device),
+        &[&descriptor_set_layout],
+        desc,
+        None,
+        &format!("env_distribution_{}", name),
+      )
+    };
+    let row_cdf_program = create_program(&program_descs.row_cdf, "row_cdf")?;
+    let column_cdf_program = create_program(&program_descs.column_cdf, "column_cdf")?;
+    let invert_cdf_program = create_program(&program_descs.invert_cdf, "invert_cdf")?;
+
+    Ok(Self {
+      descriptor_set_layout,
+      row_cdf_program,
+      column_cdf_program,
+      invert_cdf_program,
+    })
+  }
+}
 
 /// Environment map.
 pub struct EnvMap {
@@ -30,6 +111,7 @@ pub struct EnvMap {
 impl EnvMap {
 
   /// Create a new environment map with the given file path.
+  /// The distribution maps are built on the CPU and cached in the "./out" directory.
   /// param path: The file path.
   /// param context: The GFX context.
   /// param transfer_staging_buffer: The transfer staging buffer.
@@ -42,76 +124,59 @@ impl EnvMap {
     transfer_command_buffers: &HalaCommandBufferSet,
   ) -> Result<Self, HalaRendererError> {
     let path = path.as_ref();
-    let file_name = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?;
+    let file_name = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?.to_string_lossy().to_string();
+    let (width, height, format, img_buf) = Self::load_image(path)?;
 
-    // Open the image.
-    let img = ImageReader::open(path)
-      .map_err(|e| HalaRendererError::new(&format!("Failed to open image \"{}\".", path.to_string_lossy()), Some(Box::new(e))))?
-      .with_guessed_format()
-      .map_err(|e| HalaRendererError::new(&format!("Failed to guess the format of image \"{}\".", path.to_string_lossy()), Some(Box::new(e))))?
-      .decode()
-      .map_err(|e| HalaRendererError::new(&format!("Failed to decode image \"{}\".", path.to_string_lossy()), Some(Box::new(e))))?;
-    let (width, height) = img.dimensions();
-
-    // Check the color type.
-    let format = match img.color() {
-      image::ColorType::Rgba32F | image::ColorType::Rgb32F => HalaFormat::R32G32B32A32_SFLOAT,
-      color_type => return Err(HalaRendererError::new(&format!("Unsupported color type \"{:?}\" for environment map.", color_type), None)),
-    };
-
-    // Perpare the image data.
-    let validate_pixel_ch = |v: f32| -> Result<f32, HalaRendererError> {
-      if v.is_nan() {
-        return Err(HalaRendererError::new("The pixel value is NaN!", None));
-      }
-      if v.is_infinite() {
-        return Err(HalaRendererError::new("The pixel value is infinite!", None));
-      }
-      Ok(v)
-    };
-    let img_buf = img.into_rgba32f();
-    let mut data = Vec::new();
-    for y in 0..height {
-      for x in 0..width {
-        let pixel = img_buf.get_pixel(x, y);
-
-        let r = validate_pixel_ch(pixel[0])?;
-        data.push(r);
-
-        let g = validate_pixel_ch(pixel[1])?;
-        data.push(g);
-
-        let b = validate_pixel_ch(pixel[2])?;
-        data.push(b);
+    Self::build_on_cpu(&file_name, width, height, format, &img_buf, context, transfer_staging_buffer, transfer_command_buffers)
+  }
 
-        data.push(1.0);//pixel[3]);
-      }
-    }
-    let cache_file_path = format!("./out/{}.dist_cache", file_name.to_string_lossy());
+  /// Build the environment map with the distribution maps on the CPU.
+  /// The distribution maps are cached in the "./out" directory.
+  /// param file_name: The file name used in the cache file path and the debug names.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param format: The format of the image.
+  /// param img_buf: The decoded image.
+  /// param context: The GFX context.
+  /// param transfer_staging_buffer: The transfer staging buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  fn build_on_cpu(
+    file_name: &str,
+    width: u32,
+    height: u32,
+    format: HalaFormat,
+    img_buf: &image::ImageBuffer<image::Rgba<f32>, Vec<f32>>,
+    context: &HalaContext,
+    transfer_staging_buffer: &HalaBuffer,
+    transfer_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<Self, HalaRendererError> {
+    let cache_file_path = format!("./out/{}.dist_cache", file_name);
     let (total_sum, marginal_distribution, conditional_distribution) = if Path::new(&cache_file_path).exists() {
       let mut marginal_distribution: Vec<f32> = vec![0f32; height as usize];
       let mut conditional_distribution = vec![0f32; width as usize * height as usize];
 
       let file = std::fs::File::open(&cache_file_path)
-        .map_err(|e| HalaRendererError::new(&format!("Failed to open file \"{}\".", &cache_file_path), Some(Box::new(e))))?;
+        .map_err(|e| HalaRendererError::io("Failed to open file.", Path::new(&cache_file_path), e))?;
       let mut reader = std::io::BufReader::new(file);
 
       let mut total_sum_buf = [0u8; 4];
       std::io::Read::read_exact(&mut reader, &mut total_sum_buf)
-        .map_err(|e| HalaRendererError::new("Failed to read from file.", Some(Box::new(e))))?;
+        .map_err(|e| HalaRendererError::io("Failed to read from file.", Path::new(&cache_file_path), e))?;
       let total_sum = f32::from_ne_bytes(total_sum_buf);
 
       for i in 0..height {
         let mut x = [0u8; 4];
         std::io::Read::read_exact(&mut reader, &mut x)
-          .map_err(|e| HalaRendererError::new("Failed to read from file.", Some(Box::new(e))))?;
+          .map_err(|e| HalaRendererError::io("Failed to read from file.", Path::new(&cache_file_path), e))?;
         marginal_distribution[i as usize] = f32::from_ne_bytes(x);
       }
 
       for i in 0..(width * height) {
         let mut x = [0u8; 4];
         std::io::Read::read_exact(&mut reader, &mut x)
-          .map_err(|e| HalaRendererError::new("Failed to read from file.", Some(Box::new(e))))?;
+          .map_err(|e| HalaRendererError::io("Failed to read from file.", Path::new(&cache_file_path), e))?;
         conditional_distribution[i as usize] = f32::from_ne_bytes(x);
       }
       (total_sum, marginal_distribution, conditional_distribution)
@@ -119,79 +184,381 @@ impl EnvMap {
       let (total_sum, marginal_distribution, conditional_distribution) = Self::build_distribution_maps(
         width as usize,
         height as usize,
-        &img_buf
+        img_buf
       )?;
       let file = std::fs::File::create(&cache_file_path)
-        .map_err(|e| HalaRendererError::new(&format!("Failed to create file \"{}\".", &cache_file_path), Some(Box::new(e))))?;
+        .map_err(|e| HalaRendererError::io("Failed to create file.", Path::new(&cache_file_path), e))?;
       let mut writer = std::io::BufWriter::new(file);
 
       std::io::Write::write_all(&mut writer, &total_sum.to_ne_bytes())
-        .map_err(|e| HalaRendererError::new("Failed to write to file.", Some(Box::new(e))))?;
+        .map_err(|e| HalaRendererError::io("Failed to write to file.", Path::new(&cache_file_path), e))?;
 
       for v in marginal_distribution.iter() {
         std::io::Write::write_all(&mut writer, &v.to_ne_bytes())
-          .map_err(|e| HalaRendererError::new("Failed to write to file.", Some(Box::new(e))))?;
+          .map_err(|e| HalaRendererError::io("Failed to write to file.", Path::new(&cache_file_path), e))?;
       }
 
       for v in conditional_distribution.iter() {
         std::io::Write::write_all(&mut writer, &v.to_ne_bytes())
-          .map_err(|e| HalaRendererError::new("Failed to write to file.", Some(Box::new(e))))?;
+          .map_err(|e| HalaRendererError::io("Failed to write to file.", Path::new(&cache_file_path), e))?;
       }
 
       (total_sum, marginal_distribution, conditional_distribution)
     };
 
     // Create and upload the image.
-    let image = HalaImage::new_2d(
-      Rc::clone(&context.logical_device),
-      hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
+    let (image, sampler, distribution_sampler) = Self::create_image_and_samplers(
+      context,
+      file_name,
+      width,
+      height,
       format,
+      img_buf.as_raw(),
+      transfer_staging_buffer,
+      transfer_command_buffers,
+    )?;
+    let (marginal_distribution_image, conditional_distribution_image) = Self::create_distribution_images(
+      context,
+      file_name,
       width,
       height,
-      1,
-      1,
-      hala_gfx::HalaMemoryLocation::GpuOnly,
-      &format!("env_texture_{}.image", file_name.to_string_lossy())
+      hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
     )?;
-    image.update_gpu_memory_with_buffer(
-      data.as_slice(),
+    marginal_distribution_image.update_gpu_memory_with_buffer(
+      marginal_distribution.as_slice(),
       hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
       hala_gfx::HalaAccessFlags2::SHADER_READ,
       hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
       transfer_staging_buffer,
       transfer_command_buffers)?;
-    let marginal_distribution_image = HalaImage::new_2d(
-      Rc::clone(&context.logical_device),
-      hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
-      HalaFormat::R32_SFLOAT,
-      1,
-      height,
-      1,
-      1,
-      hala_gfx::HalaMemoryLocation::GpuOnly,
-      &format!("env_texture_{}_marginal_distribution.image", file_name.to_string_lossy())
-    )?;
-    marginal_distribution_image.update_gpu_memory_with_buffer(
-      marginal_distribution.as_slice(),
+    conditional_distribution_image.update_gpu_memory_with_buffer(
+      conditional_distribution.as_slice(),
       hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
       hala_gfx::HalaAccessFlags2::SHADER_READ,
       hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
       transfer_staging_buffer,
       transfer_command_buffers)?;
-    let conditional_distribution_image = HalaImage::new_2d(
+
+    Ok(Self {
+      total_luminance: total_sum,
+      image,
+      sampler,
+      marginal_distribution_image,
+      conditional_distribution_image,
+      distribution_sampler,
+    })
+  }
+
+  /// Create a new environment map with the given file path.
+  /// The distribution maps are built by the compute programs on the GPU.
+  /// The image is decoded once on the CPU, the distribution maps are built on the CPU like new_with_file
+  /// if the compute programs can not be created, or with the "envmap-cpu-distribution" feature.
+  /// The errors of the GPU build are returned.
+  /// param path: The file path.
+  /// param context: The GFX context.
+  /// param transfer_staging_buffer: The transfer staging buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param program_descs: The compute program descriptions to build the distribution maps.
+  /// return: The result.
+  pub fn new_with_file_on_gpu<P: AsRef<Path>>(
+    path: P,
+    context: &HalaContext,
+    transfer_staging_buffer: &HalaBuffer,
+    transfer_command_buffers: &HalaCommandBufferSet,
+    program_descs: &EnvMapDistributionProgramDescs,
+  ) -> Result<Self, HalaRendererError> {
+    let path = path.as_ref();
+    let file_name = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?.to_string_lossy().to_string();
+    let (width, height, format, img_buf) = Self::load_image(path)?;
+
+    if cfg!(feature = "envmap-cpu-distribution") {
+      log::info!("The \"envmap-cpu-distribution\" feature is enabled, build the distribution of \"{}\" on the CPU.", file_name);
+      return Self::build_on_cpu(&file_name, width, height, format, &img_buf, context, transfer_staging_buffer, transfer_command_buffers);
+    }
+
+    let programs = match EnvMapDistributionPrograms::new(context, program_descs) {
+      Ok(programs) => programs,
+      Err(err) => {
+        // Log the whole error chain, the shader errors are in the sources.
+        let mut reason = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(err) = source {
+          reason.push_str(&format!(": {}", err));
+          source = err.source();
+        }
+        log::warn!("The distribution programs are not available, build the distribution of \"{}\" on the CPU: {}", file_name, reason);
+        return Self::build_on_cpu(&file_name, width, height, format, &img_buf, context, transfer_staging_buffer, transfer_command_buffers);
+      }
+    };
+
+    Self::build_on_gpu(&file_name, width, height, format, &img_buf, context, transfer_staging_buffer, transfer_command_buffers, programs)
+  }
+
+  /// Build the environment map with the distribution maps on the GPU.
+  /// The three passes are recorded into one command buffer with the barriers between them, then submitted and waited once.
+  /// param file_name: The file name used in the debug names.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param format: The format of the image.
+  /// param img_buf: The decoded image.
+  /// param context: The GFX context.
+  /// param transfer_staging_buffer: The transfer staging buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param programs: The distribution programs.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  fn build_on_gpu(
+    file_name: &str,
+    width: u32,
+    height: u32,
+    format: HalaFormat,
+    img_buf: &image::ImageBuffer<image::Rgba<f32>, Vec<f32>>,
+    context: &HalaContext,
+    transfer_staging_buffer: &HalaBuffer,
+    transfer_command_buffers: &HalaCommandBufferSet,
+    programs: EnvMapDistributionPrograms,
+  ) -> Result<Self, HalaRendererError> {
+    let (image, sampler, distribution_sampler) = Self::create_image_and_samplers(
+      context,
+      file_name,
+      width,
+      height,
+      format,
+      img_buf.as_raw(),
+      transfer_staging_buffer,
+      transfer_command_buffers,
+    )?;
+    // The transfer source usage lets the distribution maps be read back, e.g. to compare them with the CPU build.
+    let (marginal_distribution_image, conditional_distribution_image) = Self::create_distribution_images(
+      context,
+      file_name,
+      width,
+      height,
+      hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+    )?;
+
+    // Create the intermediate buffers.
+    let logical_device = &context.logical_device;
+    let create_buffer = |size: u64, location: hala_gfx::HalaMemoryLocation, name: &str| -> Result<HalaBuffer, HalaRendererError> {
+      Ok(HalaBuffer::new(
+        Rc::clone(logical_device),
+        size,
+        hala_gfx::HalaBufferUsageFlags::STORAGE_BUFFER,
+        location,
+        &format!("env_texture_{}_{}.buffer", file_name, name),
+      )?)
+    };
+    let row_cdf_buffer = create_buffer(4 * width as u64 * height as u64, hala_gfx::HalaMemoryLocation::GpuOnly, "row_cdf")?;
+    let row_sum_buffer = create_buffer(4 * height as u64, hala_gfx::HalaMemoryLocation::GpuOnly, "row_sum")?;
+    let column_cdf_buffer = create_buffer(4 * height as u64, hala_gfx::HalaMemoryLocation::GpuOnly, "column_cdf")?;
+    let total_sum_buffer = create_buffer(4, hala_gfx::HalaMemoryLocation::GpuToCpu, "total_sum")?;
+
+    // Create the descriptor set shared by all passes.
+    let descriptor_pool = Rc::new(RefCell::new(hala_gfx::HalaDescriptorPool::new(
+      Rc::clone(logical_device),
+      &[
+        (hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+        (hala_gfx::HalaDescriptorType::STORAGE_BUFFER, 4),
+        (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, 2),
+      ],
+      1,
+      "env_distribution.descriptor_pool",
+    )?));
+    let EnvMapDistributionPrograms { descriptor_set_layout, row_cdf_program, column_cdf_program, invert_cdf_program } = programs;
+    let descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+      Rc::clone(logical_device),
+      Rc::clone(&descriptor_pool),
+      descriptor_set_layout,
+      0,
+      "env_distribution.descriptor_set",
+    )?;
+    descriptor_set.update_combined_image_samplers(0, 0, &[(&image, &sampler)]);
+    descriptor_set.update_storage_buffers(0, 1, &[&row_cdf_buffer]);
+    descriptor_set.update_storage_buffers(0, 2, &[&row_sum_buffer]);
+    descriptor_set.update_storage_buffers(0, 3, &[&column_cdf_buffer]);
+    descriptor_set.update_storage_buffers(0, 4, &[&total_sum_buffer]);
+    descriptor_set.update_storage_images(0, 5, &[&marginal_distribution_image]);
+    descriptor_set.update_storage_images(0, 6, &[&conditional_distribution_image]);
+
+    let command_buffers = HalaCommandBufferSet::new(
+      Rc::clone(logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "env_distribution.command_buffers",
+    )?;
+    let push_constants = [width.to_ne_bytes(), height.to_ne_bytes()].concat();
+    let image_barrier = |image: &HalaImage, old_layout, new_layout, src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask| hala_gfx::HalaImageBarrierInfo {
+      image: image.raw,
+      old_layout,
+      new_layout,
+      src_access_mask,
+      dst_access_mask,
+      src_stage_mask,
+      dst_stage_mask,
+      aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+      ..Default::default()
+    };
+    let buffer_barrier = |buffer: &HalaBuffer, dst_access_mask, dst_stage_mask| hala_gfx::HalaBufferBarrierInfo {
+      src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+      dst_access_mask,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      dst_stage_mask,
+      buffer: buffer.raw,
+      size: buffer.size,
+      ..Default::default()
+    };
+    let dispatch = |program: &HalaComputeProgram, group_count_x: u32, group_count_y: u32| {
+      program.bind(0, &command_buffers, &[&descriptor_set]);
+      program.push_constants(0, &command_buffers, 0, &push_constants);
+      program.dispatch(0, &command_buffers, group_count_x, group_count_y, 1);
+    };
+
+    command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    command_buffers.set_image_barriers(
+      0,
+      &[&marginal_distribution_image, &conditional_distribution_image].map(|image| image_barrier(
+        image,
+        hala_gfx::HalaImageLayout::UNDEFINED,
+        hala_gfx::HalaImageLayout::GENERAL,
+        hala_gfx::HalaAccessFlags2::NONE,
+        hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+        hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      )),
+    );
+
+    dispatch(&row_cdf_program, height.div_ceil(64), 1);
+
+    // The column pass reads the row sums, the invert pass reads the row CDFs and the row sums.
+    command_buffers.set_buffer_barriers(
+      0,
+      &[&row_cdf_buffer, &row_sum_buffer].map(|buffer| buffer_barrier(
+        buffer, hala_gfx::HalaAccessFlags2::SHADER_READ, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER)),
+    );
+
+    dispatch(&column_cdf_program, 1, 1);
+
+    // The invert pass reads the column CDFs, the total sum is read back by the host.
+    command_buffers.set_buffer_barriers(
+      0,
+      &[
+        buffer_barrier(&column_cdf_buffer, hala_gfx::HalaAccessFlags2::SHADER_READ, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER),
+        buffer_barrier(&total_sum_buffer, hala_gfx::HalaAccessFlags2::HOST_READ, hala_gfx::HalaPipelineStageFlags2::HOST),
+      ],
+    );
+
+    dispatch(&invert_cdf_program, width.div_ceil(8), height.div_ceil(8));
+
+    command_buffers.set_image_barriers(
+      0,
+      &[&marginal_distribution_image, &conditional_distribution_image].map(|image| image_barrier(
+        image,
+        hala_gfx::HalaImageLayout::GENERAL,
+        hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        hala_gfx::HalaAccessFlags2::SHADER_READ,
+        hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
+      )),
+    );
+    command_buffers.end(0)?;
+
+    logical_device.borrow().graphics_submit(&command_buffers, 0, 0)?;
+    logical_device.borrow().graphics_wait(0)?;
+
+    // Read back the total luminance for the settings uniform.
+    let mut total_sum = [0f32; 1];
+    total_sum_buffer.download_memory(0, &mut total_sum)?;
+
+    Ok(Self {
+      total_luminance: total_sum[0],
+      image,
+      sampler,
+      marginal_distribution_image,
+      conditional_distribution_image,
+      distribution_sampler,
+    })
+  }
+
+  /// Load and validate the HDR image.
+  /// The alpha of the decoded image is set to 1, so its buffer is uploaded as the RGBA pixels without a copy.
+  /// param path: The file path.
+  /// return: The width, the height, the GPU format and the image buffer.
+  #[allow(clippy::type_complexity)]
+  fn load_image(path: &Path) -> Result<(u32, u32, HalaFormat, image::ImageBuffer<image::Rgba<f32>, Vec<f32>>), HalaRendererError> {
+    // Open the image.
+    let img = ImageReader::open(path)
+      .map_err(|e| HalaRendererError::io("Failed to open image.", path, e))?
+      .with_guessed_format()
+      .map_err(|e| HalaRendererError::io("Failed to guess the format of image.", path, e))?
+      .decode()
+      .map_err(|e| HalaRendererError::scene_load("Failed to decode image.", path, Some(Box::new(e))))?;
+    let (width, height) = img.dimensions();
+
+    // Check the color type.
+    let format = match img.color() {
+      image::ColorType::Rgba32F | image::ColorType::Rgb32F => HalaFormat::R32G32B32A32_SFLOAT,
+      color_type => return Err(HalaRendererError::new(&format!("Unsupported color type \"{:?}\" for environment map.", color_type), None)),
+    };
+
+    // Perpare the image data.
+    let validate_pixel_ch = |v: f32| -> Result<f32, HalaRendererError> {
+      if v.is_nan() {
+        return Err(HalaRendererError::new("The pixel value is NaN!", None));
+      }
+      if v.is_infinite() {
+        return Err(HalaRendererError::new("The pixel value is infinite!", None));
+      }
+      Ok(v)
+    };
+    let mut img_buf = img.into_rgba32f();
+    for pixel in img_buf.pixels_mut() {
+      validate_pixel_ch(pixel[0])?;
+      validate_pixel_ch(pixel[1])?;
+      validate_pixel_ch(pixel[2])?;
+      pixel[3] = 1.0;
+    }
+
+    Ok((width, height, format, img_buf))
+  }
+
+  /// Create and upload the environment image and create the samplers.
+  /// param context: The GFX context.
+  /// param file_name: The file name used in the debug names.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param format: The format of the image.
+  /// param data: The RGBA pixels.
+  /// param transfer_staging_buffer: The transfer staging buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// return: The image, the sampler and the distribution sampler.
+  #[allow(clippy::too_many_arguments)]
+  fn create_image_and_samplers(
+    context: &HalaContext,
+    file_name: &str,
+    width: u32,
+    height: u32,
+    format: HalaFormat,
+    data: &[f32],
+    transfer_staging_buffer: &HalaBuffer,
+    transfer_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<(HalaImage, HalaSampler, HalaSampler), HalaRendererError> {
+    let image = HalaImage::new_2d(
       Rc::clone(&context.logical_device),
       hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
-      HalaFormat::R32_SFLOAT,
+      format,
       width,
       height,
       1,
       1,
       hala_gfx::HalaMemoryLocation::GpuOnly,
-      &format!("env_texture_{}_conditional_distribution.image", file_name.to_string_lossy())
+      &format!("env_texture_{}.image", file_name)
     )?;
-    conditional_distribution_image.update_gpu_memory_with_buffer(
-      conditional_distribution.as_slice(),
-      hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
+    image.update_gpu_memory_with_buffer(
+      data,
+      hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
       hala_gfx::HalaAccessFlags2::SHADER_READ,
       hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
       transfer_staging_buffer,
@@ -207,7 +574,7 @@ impl EnvMap {
       false,
       0.0,
       (0.0, 0.0),
-      &format!("env_texture_{}.sampler", file_name.to_string_lossy())
+      &format!("env_texture_{}.sampler", file_name)
     )?;
     let distribution_sampler = HalaSampler::new(
       Rc::clone(&context.logical_device),
@@ -218,17 +585,50 @@ impl EnvMap {
       false,
       0.0,
       (0.0, 0.0),
-      &format!("env_distribution_texture_{}.sampler", file_name.to_string_lossy())
+      &format!("env_distribution_texture_{}.sampler", file_name)
     )?;
 
-    Ok(Self {
-      total_luminance: total_sum,
-      image,
-      sampler,
-      marginal_distribution_image,
-      conditional_distribution_image,
-      distribution_sampler,
-    })
+    Ok((image, sampler, distribution_sampler))
+  }
+
+  /// Create the marginal and conditional distribution images.
+  /// param context: The GFX context.
+  /// param file_name: The file name used in the debug names.
+  /// param width: The width of the environment map.
+  /// param height: The height of the environment map.
+  /// param usage: The usage of the images.
+  /// return: The marginal and conditional distribution images.
+  fn create_distribution_images(
+    context: &HalaContext,
+    file_name: &str,
+    width: u32,
+    height: u32,
+    usage: hala_gfx::HalaImageUsageFlags,
+  ) -> Result<(HalaImage, HalaImage), HalaRendererError> {
+    let marginal_distribution_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      usage,
+      HalaFormat::R32_SFLOAT,
+      1,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      &format!("env_texture_{}_marginal_distribution.image", file_name)
+    )?;
+    let conditional_distribution_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      usage,
+      HalaFormat::R32_SFLOAT,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      &format!("env_texture_{}_conditional_distribution.image", file_name)
+    )?;
+
+    Ok((marginal_distribution_image, conditional_distribution_image))
   }
 
   /// Build the marginal and conditional distribution maps.
@@ -387,4 +787,160 @@ impl EnvMap {
     Ok((total_sum, marginal_distribution, conditional_distribution))
   }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WIDTH: u32 = 32;
+  const HEIGHT: u32 = 16;
+
+  /// Write a small synthetic HDR image, a horizontal gradient with a bright spot.
+  /// return: The file path.
+  fn write_synthetic_hdr() -> std::path::PathBuf {
+    let img = image::Rgb32FImage::from_fn(WIDTH, HEIGHT, |x, y| {
+      let gradient = 0.1 + x as f32 / WIDTH as f32;
+      if (20..23).contains(&x) && (4..6).contains(&y) {
+        image::Rgb([50.0, 40.0, 30.0])
+      } else {
+        image::Rgb([gradient, gradient * 0.5, 0.2 + y as f32 * 0.01])
+      }
+    });
+    let path = std::env::temp_dir().join("hala_renderer_envmap_synthetic.hdr");
+    img.save(&path).unwrap();
+    path
+  }
+
+  /// Read back a distribution image in the shader read only layout.
+  /// param context: The GFX context.
+  /// param image: The distribution image.
+  /// return: The values.
+  fn read_distribution_image(context: &HalaContext, image: &HalaImage) -> Vec<f32> {
+    let num_of_values = image.extent.width as usize * image.extent.height as usize;
+    let readback_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      4 * num_of_values as u64,
+      hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+      hala_gfx::HalaMemoryLocation::GpuToCpu,
+      "env_distribution_readback.buffer",
+    ).unwrap();
+    let command_buffers = HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "env_distribution_readback.command_buffers",
+    ).unwrap();
+    command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT).unwrap();
+    command_buffers.set_image_barriers(
+      0,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: image.raw,
+        ..Default::default()
+      }],
+    );
+    command_buffers.copy_image_2_buffer(0, image, hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL, &readback_buffer);
+    command_buffers.end(0).unwrap();
+    context.logical_device.borrow().graphics_submit(&command_buffers, 0, 0).unwrap();
+    context.logical_device.borrow().graphics_wait(0).unwrap();
+
+    let mut values = vec![0f32; num_of_values];
+    readback_buffer.download_memory(0, values.as_mut_slice()).unwrap();
+    values
+  }
+
+  /// Check the distribution values are the same up to one step of the inverted CDF.
+  /// The prefix sums are accumulated in a different order on the GPU, so a lower bound may land on the neighbour.
+  /// param name: The name of the distribution.
+  /// param cpu: The CPU values.
+  /// param gpu: The GPU values.
+  /// param step: The step of the values.
+  fn assert_distribution_eq(name: &str, cpu: &[f32], gpu: &[f32], step: f32) {
+    assert_eq!(cpu.len(), gpu.len(), "The {} distribution sizes differ.", name);
+    let mut sum_of_diff = 0f32;
+    for (i, (c, g)) in cpu.iter().zip(gpu.iter()).enumerate() {
+      let diff = (c - g).abs();
+      assert!(diff <= step + 1e-5, "The {} distribution differs at {}: CPU {} GPU {}.", name, i, c, g);
+      sum_of_diff += diff;
+    }
+    let mean_diff = sum_of_diff / cpu.len() as f32;
+    assert!(mean_diff <= 0.25 * step, "The mean {} distribution difference {} is too large.", name, mean_diff);
+  }
+
+  #[test]
+  #[ignore = "requires a GPU and the distribution shaders in shaders/envmap compiled to SPIR-V"]
+  fn test_gpu_distribution_matches_cpu() {
+    let path = write_synthetic_hdr();
+    let gpu_req = hala_gfx::HalaGPURequirements {
+      width: WIDTH,
+      height: HEIGHT,
+      ..Default::default()
+    };
+    let context = HalaContext::new_headless("envmap_distribution_test", &gpu_req).unwrap();
+    let transfer_staging_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      4 * 4 * WIDTH as u64 * HEIGHT as u64,
+      hala_gfx::HalaBufferUsageFlags::TRANSFER_SRC | hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+      hala_gfx::HalaMemoryLocation::CpuToGpu,
+      "transfer_staging.buffer",
+    ).unwrap();
+    let transfer_command_buffers = HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.command_pools),
+      hala_gfx::HalaCommandBufferType::TRANSFER,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "transfer.cmd_buffer",
+    ).unwrap();
+
+    let (width, height, format, img_buf) = EnvMap::load_image(&path).unwrap();
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    let (cpu_total_sum, cpu_marginal, cpu_conditional) = EnvMap::build_distribution_maps(
+      width as usize,
+      height as usize,
+      &img_buf,
+    ).unwrap();
+
+    let program_desc = |name: &str| HalaComputeProgramDesc {
+      shader_file_path: format!("{}/shaders/envmap/distribution_{}.comp.spv", env!("CARGO_MANIFEST_DIR"), name),
+      push_constant_size: 8,
+      bindings: Vec::new(),
+    };
+    let program_descs = EnvMapDistributionProgramDescs {
+      row_cdf: program_desc("row_cdf"),
+      column_cdf: program_desc("column_cdf"),
+      invert_cdf: program_desc("invert_cdf"),
+    };
+    let programs = EnvMapDistributionPrograms::new(&context, &program_descs).unwrap();
+    let envmap = EnvMap::build_on_gpu(
+      "synthetic",
+      width,
+      height,
+      format,
+      &img_buf,
+      &context,
+      &transfer_staging_buffer,
+      &transfer_command_buffers,
+      programs,
+    ).unwrap();
+
+    let relative_diff = (envmap.total_luminance - cpu_total_sum).abs() / cpu_total_sum;
+    assert!(relative_diff < 1e-3, "The total luminance differs: CPU {} GPU {}.", cpu_total_sum, envmap.total_luminance);
+
+    let gpu_marginal = read_distribution_image(&context, &envmap.marginal_distribution_image);
+    let gpu_conditional = read_distribution_image(&context, &envmap.conditional_distribution_image);
+    assert_distribution_eq("marginal", &cpu_marginal, &gpu_marginal, 1.0 / HEIGHT as f32);
+    assert_distribution_eq("conditional", &cpu_conditional, &gpu_conditional, 1.0 / WIDTH as f32);
+
+    std::fs::remove_file(&path).ok();
+  }
+
+}
@@ -1,35 +1,177 @@
+use std::path::{Path, PathBuf};
+
 use thiserror::Error;
 
 /// The error type of the hala-renderer crate.
 #[derive(Error, Debug)]
-pub struct HalaRendererError {
-  msg: String,
-  #[source]
-  source: Option<Box<dyn std::error::Error + Send + Sync>>,
+pub enum HalaRendererError {
+  /// The generic error with a message.
+  Generic {
+    msg: String,
+    #[source]
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+  },
+  /// The error from the hala-gfx crate.
+  Gfx(#[source] hala_gfx::HalaGfxError),
+  /// The I/O error of a file.
+  Io {
+    msg: String,
+    path: PathBuf,
+    #[source]
+    source: std::io::Error,
+  },
+  /// The error of loading a scene or an image file.
+  SceneLoad {
+    msg: String,
+    path: PathBuf,
+    #[source]
+    source: Option<Box<dyn std::error::Error + Send + Sync>>,
+  },
+  /// The renderer or the scene is invalid.
+  Validation {
+    msg: String,
+    errors: Vec<HalaRendererError>,
+  },
+  /// The GPU memory is not enough for the allocation.
+  OutOfMemory {
+    msg: String,
+    required: u64,
+    available: u64,
+  },
+  /// The device is lost.
+  DeviceLost,
+  /// The method is called in the wrong state, e.g. a setup method after commit.
+  InvalidState {
+    msg: String,
+  },
 }
 
 /// The implementation of the error type of the hala-renderer crate.
 impl HalaRendererError {
-  /// Create a new error.
+  /// Create a new generic error.
   /// param msg: The message of the error.
   /// param source: The source of the error.
   /// return: The error.
   pub fn new(msg: &str, source: Option<Box<dyn std::error::Error + Send + Sync>>) -> Self {
-    Self {
+    Self::Generic {
+      msg: msg.to_string(),
+      source,
+    }
+  }
+
+  /// Create a new I/O error.
+  /// param msg: The message of the error.
+  /// param path: The file path.
+  /// param source: The I/O error.
+  /// return: The error.
+  pub fn io(msg: &str, path: &Path, source: std::io::Error) -> Self {
+    Self::Io {
+      msg: msg.to_string(),
+      path: path.to_path_buf(),
+      source,
+    }
+  }
+
+  /// Create a new scene loading error.
+  /// param msg: The message of the error.
+  /// param path: The file path.
+  /// param source: The source of the error.
+  /// return: The error.
+  pub fn scene_load(msg: &str, path: &Path, source: Option<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+    Self::SceneLoad {
       msg: msg.to_string(),
+      path: path.to_path_buf(),
       source,
     }
   }
+
+  /// Create a new validation error.
+  /// param msg: The message of the error.
+  /// return: The error.
+  pub fn validation(msg: &str) -> Self {
+    Self::Validation {
+      msg: msg.to_string(),
+      errors: Vec::new(),
+    }
+  }
+
+  /// Create a new validation error which lists all errors.
+  /// param msg: The message of the error.
+  /// param errors: The errors.
+  /// return: The error.
+  pub fn with_errors(msg: &str, errors: Vec<HalaRendererError>) -> Self {
+    let mut msg = msg.to_string();
+    for err in errors.iter() {
+      msg.push_str("\n  - ");
+      msg.push_str(err.message());
+    }
+    Self::Validation {
+      msg,
+      errors,
+    }
+  }
+
+  /// Create a new invalid state error.
+  /// param msg: The message of the error.
+  /// return: The error.
+  pub fn invalid_state(msg: &str) -> Self {
+    Self::InvalidState {
+      msg: msg.to_string(),
+    }
+  }
+
+  /// Create a new out of memory error.
+  /// param what: The description of the allocation.
+  /// param required: The required bytes.
+  /// param available: The available bytes.
+  /// return: The error.
+  pub fn out_of_memory(what: &str, required: u64, available: u64) -> Self {
+    const MB: f64 = 1024.0 * 1024.0;
+    Self::OutOfMemory {
+      msg: format!("The {} needs {:.1} MB, only {:.1} MB is available.", what, required as f64 / MB, available as f64 / MB),
+      required,
+      available,
+    }
+  }
+
+  /// Get the message of the error.
+  /// return: The message.
   pub fn message(&self) -> &str {
-    &self.msg
+    match self {
+      Self::Generic { msg, .. } => msg,
+      Self::Gfx(err) => err.message(),
+      Self::Io { msg, .. } => msg,
+      Self::SceneLoad { msg, .. } => msg,
+      Self::Validation { msg, .. } => msg,
+      Self::OutOfMemory { msg, .. } => msg,
+      Self::DeviceLost => "The device is lost!",
+      Self::InvalidState { msg } => msg,
+    }
+  }
+
+  /// Check whether the error is caused by the device lost.
+  /// return: True if the device is lost.
+  pub fn is_device_lost(&self) -> bool {
+    match self {
+      Self::Gfx(err) => err.is_device_lost(),
+      Self::DeviceLost => true,
+      _ => false,
+    }
   }
 }
 
 impl std::convert::From<hala_gfx::HalaGfxError> for HalaRendererError {
   fn from(err: hala_gfx::HalaGfxError) -> Self {
-    Self {
-      msg: err.message().to_string(),
-      source: Some(Box::new(err)),
+    Self::Gfx(err)
+  }
+}
+
+impl std::convert::From<std::io::Error> for HalaRendererError {
+  fn from(err: std::io::Error) -> Self {
+    Self::Io {
+      msg: err.to_string(),
+      path: PathBuf::new(),
+      source: err,
     }
   }
 }
@@ -47,6 +189,9 @@ impl std::fmt::Display for HalaRendererError {
   /// param f: The formatter.
   /// return: The result.
   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-    write!(f, "{}", self.msg)
+    match self {
+      Self::Io { msg, path, .. } | Self::SceneLoad { msg, path, .. } if !path.as_os_str().is_empty() => write!(f, "{} ({:?})", msg, path),
+      _ => write!(f, "{}", self.message()),
+    }
   }
 }
\ No newline at end of file
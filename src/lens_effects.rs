@@ -0,0 +1,39 @@
+/// The lens effects applied to the final display-referred image, after the tone mapping and the color grading.
+/// The rasterization and the ray tracing renderers push the same constants, so one shader function serves both passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HalaLensEffects {
+  /// The strength of the radial darkening, 0 to disable.
+  pub vignette: f32,
+  /// The channel offset toward the edges in the fraction of the image width, 0 to disable.
+  pub chromatic_aberration: f32,
+}
+
+impl HalaLensEffects {
+
+  /// Create the lens effects, the negative values are clamped to 0.
+  /// param vignette: The strength of the radial darkening.
+  /// param chromatic_aberration: The channel offset toward the edges in the fraction of the image width.
+  /// return: The lens effects.
+  pub fn new(vignette: f32, chromatic_aberration: f32) -> Self {
+    Self {
+      vignette: vignette.max(0.0),
+      chromatic_aberration: chromatic_aberration.max(0.0),
+    }
+  }
+
+  /// Whether any effect is enabled, the lens pass is skipped if not.
+  /// return: True if any effect is enabled.
+  pub fn is_enabled(&self) -> bool {
+    self.vignette > 0.0 || self.chromatic_aberration > 0.0
+  }
+
+  /// Get the push constants of the lens shaders.
+  /// The layout is the vignette, the chromatic aberration, the width and the height of the image(vec4).
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// return: The push constants.
+  pub fn get_push_constants(&self, width: u32, height: u32) -> [f32; 4] {
+    [self.vignette, self.chromatic_aberration, width as f32, height as f32]
+  }
+
+}
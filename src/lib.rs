@@ -4,6 +4,16 @@ pub mod renderer;
 pub mod rz_renderer;
 pub mod rt_renderer;
 pub mod envmap;
+pub mod tonemap;
+pub mod color_space;
+pub mod color_grading;
+pub mod lens_effects;
+pub mod depth_pyramid;
+pub mod shadow;
+pub mod texture_watcher;
+pub mod streaming_uploader;
+pub mod secondary_recording;
+pub mod camera_controller;
 pub mod scene;
 pub mod shader_cache;
 pub mod compute_program;
@@ -1,6 +1,32 @@
 pub use crate::error::HalaRendererError;
 pub use crate::renderer::HalaRendererTrait;
+pub use crate::renderer::HalaMemoryBudget;
+pub use crate::renderer::HalaPresentMode;
+pub use crate::renderer::HalaDebugView;
 pub use crate::shader_cache::HalaShaderCache;
+pub use crate::texture_watcher::HalaTextureWatcher;
+pub use crate::streaming_uploader::{
+  HalaStreamingUploader,
+  HalaUploadTarget,
+};
+pub use crate::secondary_recording::HalaSecondaryCommandPool;
+pub use crate::camera_controller::{
+  HalaOrbitController,
+  HalaFlyController,
+};
+pub use crate::tonemap::HalaToneMapType;
+pub use crate::scene::HalaDepthConvention;
+pub use crate::color_grading::HalaColorGradingLUT;
+pub use crate::lens_effects::HalaLensEffects;
+pub use crate::depth_pyramid::HalaDepthPyramid;
+pub use crate::shadow::{
+  HalaShadowConfig,
+  HalaShadowAtlas,
+};
+pub use crate::color_space::{
+  HalaColorSpace,
+  HalaImageFileFormat,
+};
 pub use crate::compute_program::{
   HalaComputeProgramDesc,
   HalaComputeProgram,
@@ -15,4 +41,9 @@ pub use crate::graphics_program::{
   HalaGraphicsProgram,
 };
 pub use crate::rz_renderer::HalaRenderer as HalaRasterizationRenderer;
-pub use crate::rt_renderer::HalaRenderer as HalaRayTracingRenderer;
\ No newline at end of file
+pub use crate::rt_renderer::HalaRenderer as HalaRayTracingRenderer;
+pub use crate::rt_renderer::HalaImageKind;
+pub use crate::rz_renderer::HalaRenderPath;
+pub use crate::rz_renderer::HalaMaterialPass;
+pub use crate::rz_renderer::HalaDepthPickResult;
+pub use crate::rz_renderer::HalaGridSettings;
\ No newline at end of file
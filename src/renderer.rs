@@ -1,11 +1,30 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::any::Any;
+use std::collections::VecDeque;
 
 use anyhow::Result;
 
+use ash::vk;
+
 use hala_gfx::HalaContext;
+use hala_gfx::renderpass::HalaRenderPassAttachmentDesc;
 
 use crate::error::HalaRendererError;
+use crate::scene::loader::{
+  HalaTransferBatch,
+  HalaPendingTransfer,
+};
+use crate::streaming_uploader::{
+  HalaStreamingUploader,
+  HalaUploadTarget,
+};
+use crate::secondary_recording::{
+  HalaSecondaryCommandPool,
+  HalaSecondaryDraw,
+  HalaSecondaryInheritance,
+  record_draws_in_parallel,
+};
 
 /// The renderer informaton.
 pub struct HalaRendererInfo {
@@ -32,14 +51,211 @@ impl HalaRendererInfo {
 
 }
 
+/// The number of the frames in flight of the headless renderer.
+pub const HEADLESS_NUM_OF_FRAMES: usize = 2;
+
+/// The GPU memory budget.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HalaMemoryBudget {
+  pub used: u64,
+  pub available: u64,
+}
+
+/// The GPU memory budget implementation.
+impl HalaMemoryBudget {
+
+  /// Sum the budget and the usage of the device local memory heaps.
+  /// The heap budget and the heap usage come from VK_EXT_memory_budget, they include the other applications and the driver.
+  /// Without the extension the whole heap is the budget and nothing is counted as used.
+  /// param heaps: The size and the optional budget and usage of each device local heap.
+  /// return: The memory budget.
+  pub fn from_heaps(heaps: &[(u64, Option<(u64, u64)>)]) -> Self {
+    let mut budget = 0u64;
+    let mut used = 0u64;
+    for (size, budget_and_usage) in heaps.iter() {
+      match budget_and_usage {
+        Some((heap_budget, heap_usage)) => {
+          budget += (*heap_budget).min(*size);
+          used += *heap_usage;
+        },
+        None => budget += *size,
+      }
+    }
+
+    Self {
+      used,
+      available: budget.saturating_sub(used),
+    }
+  }
+
+  /// Count the memory of the retired resources as available, they will be dropped after the in-flight frames.
+  /// Only the counted usage can be reclaimed, so the budget never exceeds the heaps.
+  /// param num_of_bytes: The bytes of the retired resources.
+  pub fn reclaim(&mut self, num_of_bytes: u64) {
+    let reclaimed = num_of_bytes.min(self.used);
+    self.used -= reclaimed;
+    self.available += reclaimed;
+  }
+
+}
+
+/// The present mode of the swapchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaPresentMode(u8);
+impl HalaPresentMode {
+  /// Wait for the vertical blank, no tearing, the frame rate is capped by the display.
+  pub const FIFO: Self = Self(0);
+  /// Replace the queued image at the vertical blank, no tearing with the low latency.
+  pub const MAILBOX: Self = Self(1);
+  /// Present immediately, it may tear.
+  pub const IMMEDIATE: Self = Self(2);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::FIFO,
+      1 => Self::MAILBOX,
+      2 => Self::IMMEDIATE,
+      _ => panic!("Invalid present mode."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Get the requested present mode of the GPU requirements.
+  /// The immediate flag is preferred to the low latency flag, the FIFO is used if none of them is set.
+  /// param gpu_req: The GPU requirements.
+  /// return: The present mode.
+  pub fn from_gpu_req(gpu_req: &hala_gfx::HalaGPURequirements) -> Self {
+    if gpu_req.is_immediate {
+      Self::IMMEDIATE
+    } else if gpu_req.is_low_latency {
+      Self::MAILBOX
+    } else {
+      Self::FIFO
+    }
+  }
+
+  /// Write the present mode into the GPU requirements.
+  /// The swapchain falls back to the FIFO if the surface does not support the mode, the FIFO is always supported.
+  /// param gpu_req: The GPU requirements.
+  pub fn apply_to_gpu_req(&self, gpu_req: &mut hala_gfx::HalaGPURequirements) {
+    gpu_req.is_immediate = *self == Self::IMMEDIATE;
+    gpu_req.is_low_latency = *self == Self::MAILBOX;
+  }
+}
+
+/// The debug view of the renderer output, the shaders read it to replace the shading with the raw data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaDebugView(u8);
+impl HalaDebugView {
+  /// The normal shading.
+  pub const NONE: Self = Self(0);
+  /// The base color of the material.
+  pub const ALBEDO: Self = Self(1);
+  /// The world space normal mapped to [0, 1].
+  pub const NORMAL: Self = Self(2);
+  /// The depth linearized by the camera near and far planes.
+  pub const DEPTH: Self = Self(3);
+  /// The checker pattern of the first texture coordinates.
+  pub const UV: Self = Self(4);
+  /// The random color of the meshlet or the primitive id.
+  pub const MESHLET_ID: Self = Self(5);
+  /// The heat map of the fragments drawn per pixel.
+  pub const OVERDRAW: Self = Self(6);
+
+  /// The number of the debug views.
+  pub const COUNT: u8 = 7;
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::NONE,
+      1 => Self::ALBEDO,
+      2 => Self::NORMAL,
+      3 => Self::DEPTH,
+      4 => Self::UV,
+      5 => Self::MESHLET_ID,
+      6 => Self::OVERDRAW,
+      _ => panic!("Invalid debug view."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Get the next debug view, it wraps to NONE after the last one, used to cycle the views by a key.
+  /// return: The next debug view.
+  pub fn next(&self) -> Self {
+    Self::from_u8((self.0 + 1) % Self::COUNT)
+  }
+}
+
+/// The Default implementation of the debug view.
+impl Default for HalaDebugView {
+  fn default() -> Self {
+    Self::NONE
+  }
+}
+
 /// The renderer resources.
 pub struct HalaRendererResources {
+  // The retired resources, the frame counter after which they can be dropped and their estimated bytes.
+  // Keep it as the first field, so the resources are dropped before the context.
+  pub retired_resources: VecDeque<(u64, u64, Box<dyn Any>)>,
+  pub frame_counter: u64,
+
   pub graphics_command_buffers: hala_gfx::HalaCommandBufferSet,
   pub compute_command_buffers: hala_gfx::HalaCommandBufferSet,
+  // The timeline semaphores order the async compute submits and the graphics submits on the GPU, the values are the last submitted ones.
+  // Each frame keeps the values to wait on the CPU before its command buffers are reused.
+  pub graphics_timeline: hala_gfx::HalaSemaphore,
+  pub graphics_timeline_value: u64,
+  pub graphics_frame_values: Vec<u64>,
+  pub compute_timeline: hala_gfx::HalaSemaphore,
+  pub compute_timeline_value: u64,
+  pub compute_frame_values: Vec<u64>,
+  // The compute value waited by the next graphics submit.
+  pub pending_compute_wait: Option<u64>,
+  // The exclusive buffers shared by the async compute and the graphics work, the ownership is transferred if the queue families differ.
+  // The compute work has released them and the next graphics frame acquires them.
+  pub async_compute_buffers: Vec<(vk::Buffer, u64)>,
+  pub is_async_compute_released: bool,
+  // The transfers submitted to the transfer queue, and the acquire barriers of the finished ones recorded by the next graphics frame.
+  pub pending_transfers: Vec<HalaPendingTransfer>,
+  pub transfer_acquire_barriers: Vec<hala_gfx::HalaBufferBarrierInfo>,
+  // The uploads through the transfer command buffers and the staging buffer are synchronous,
+  // hala-gfx submits each copy and waits it, so the uploaded resources can be referenced right after the call.
   pub transfer_command_buffers: hala_gfx::HalaCommandBufferSet,
   pub transfer_staging_buffer: hala_gfx::HalaBuffer,
+  // The streaming uploads are recorded without waiting and flushed once per frame, see enqueue_upload.
+  pub streaming_uploader: HalaStreamingUploader,
+  // The secondary command pools of the recording threads for each frame, empty if the draws are recorded on the calling thread.
+  // The secondary command buffers of each frame record the draws after the parallel draws in the same rendering on the calling thread.
+  pub secondary_command_pools: RefCell<Vec<Vec<HalaSecondaryCommandPool>>>,
+  pub secondary_command_buffers: Option<hala_gfx::HalaCommandBufferSet>,
+
+  // The number of the frames in flight, all per-frame resources are allocated with it.
+  pub num_of_frames: usize,
+
+  // The headless renderer has no surface and swapchain, the frames are submitted without the acquire and the present.
+  // The color format replaces the swapchain format for the offscreen color targets.
+  pub is_headless: bool,
+  pub color_format: hala_gfx::HalaFormat,
 
   pub descriptor_pool: Rc<RefCell<hala_gfx::HalaDescriptorPool>>,
+  // The capacity and the reserved descriptor counts of the current descriptor pool.
+  pub descriptor_sizes: Vec<(hala_gfx::HalaDescriptorType, usize)>,
+  pub descriptor_reserved_sizes: Vec<(hala_gfx::HalaDescriptorType, usize)>,
+  pub max_descriptor_sets: usize,
+  pub num_of_reserved_descriptor_sets: usize,
+
+  // Whether the physical device supports VK_EXT_memory_budget, the heap budget and usage are queried from it.
+  pub has_memory_budget: bool,
+
+  // The present mode requested by the GPU requirements or set_present_mode.
+  pub present_mode: HalaPresentMode,
 
   pub context: Rc<RefCell<HalaContext>>,
 }
@@ -47,6 +263,12 @@ pub struct HalaRendererResources {
 /// The renderer resources implementation.
 impl HalaRendererResources {
 
+  /// Create the renderer resources with the swapchain of the window.
+  /// param name: The name of the renderer.
+  /// param gpu_req: The GPU requirements.
+  /// param window: The window.
+  /// param descriptor_sizes: The descriptor counts of the descriptor pool.
+  /// return: The renderer resources.
   pub fn new(
     name: &str,
     gpu_req: &hala_gfx::HalaGPURequirements,
@@ -54,6 +276,53 @@ impl HalaRendererResources {
     descriptor_sizes: &[(hala_gfx::HalaDescriptorType, usize)],
   ) -> Result<Self, HalaRendererError> {
     let context = HalaContext::new(name, gpu_req, window)?;
+    let num_of_frames = context.swapchain.num_of_images;
+    let color_format = context.swapchain.format;
+
+    Self::with_context(context, gpu_req, num_of_frames, false, color_format, descriptor_sizes)
+  }
+
+  /// Create the renderer resources without the window, e.g. for the tests and the batch rendering.
+  /// There is no surface and swapchain, the frames are driven with HEADLESS_NUM_OF_FRAMES frames in flight.
+  /// param name: The name of the renderer.
+  /// param gpu_req: The GPU requirements, the width and the height are the size of the offscreen targets.
+  /// param descriptor_sizes: The descriptor counts of the descriptor pool.
+  /// return: The renderer resources.
+  pub fn new_headless(
+    name: &str,
+    gpu_req: &hala_gfx::HalaGPURequirements,
+    descriptor_sizes: &[(hala_gfx::HalaDescriptorType, usize)],
+  ) -> Result<Self, HalaRendererError> {
+    let context = HalaContext::new_headless(name, gpu_req)?;
+
+    Self::with_context(context, gpu_req, HEADLESS_NUM_OF_FRAMES, true, hala_gfx::HalaFormat::R8G8B8A8_UNORM, descriptor_sizes)
+  }
+
+  /// Create the renderer resources with the context.
+  /// param context: The gfx context.
+  /// param gpu_req: The GPU requirements.
+  /// param num_of_frames: The number of the frames in flight.
+  /// param is_headless: Whether the context has no swapchain.
+  /// param color_format: The format of the color targets.
+  /// param descriptor_sizes: The descriptor counts of the descriptor pool.
+  /// return: The renderer resources.
+  fn with_context(
+    context: HalaContext,
+    gpu_req: &hala_gfx::HalaGPURequirements,
+    num_of_frames: usize,
+    is_headless: bool,
+    color_format: hala_gfx::HalaFormat,
+    descriptor_sizes: &[(hala_gfx::HalaDescriptorType, usize)],
+  ) -> Result<Self, HalaRendererError> {
+    // The memory budget properties are a physical device query, the extension only needs to be supported.
+    let has_memory_budget = unsafe {
+      context.instance.raw.enumerate_device_extension_properties(context.physical_device.raw)
+    }
+      .map(|extensions| extensions.iter().any(|extension| extension.extension_name_as_c_str() == Ok(ash::ext::memory_budget::NAME)))
+      .unwrap_or(false);
+    if !has_memory_budget {
+      log::warn!("VK_EXT_memory_budget is not supported, the memory budget falls back to the heap size.");
+    }
 
     // Craete command buffers.
     let graphics_command_buffers = hala_gfx::HalaCommandBufferSet::new(
@@ -61,7 +330,7 @@ impl HalaRendererResources {
       Rc::clone(&context.command_pools),
       hala_gfx::HalaCommandBufferType::GRAPHICS,
       hala_gfx::HalaCommandBufferLevel::PRIMARY,
-      context.swapchain.num_of_images,
+      num_of_frames,
       "main_graphics.cmd_buffer",
     )?;
     let compute_command_buffers = hala_gfx::HalaCommandBufferSet::new(
@@ -69,7 +338,7 @@ impl HalaRendererResources {
       Rc::clone(&context.command_pools),
       hala_gfx::HalaCommandBufferType::COMPUTE,
       hala_gfx::HalaCommandBufferLevel::PRIMARY,
-      context.swapchain.num_of_images,
+      num_of_frames,
       "main_compute.cmd_buffer",
     )?;
     let transfer_command_buffers = hala_gfx::HalaCommandBufferSet::new(
@@ -77,9 +346,19 @@ impl HalaRendererResources {
       Rc::clone(&context.command_pools),
       hala_gfx::HalaCommandBufferType::TRANSFER,
       hala_gfx::HalaCommandBufferLevel::PRIMARY,
-      context.swapchain.num_of_images,
+      num_of_frames,
       "main_transfer.cmd_buffer",
     )?;
+    let graphics_timeline = hala_gfx::HalaSemaphore::new_timeline(
+      Rc::clone(&context.logical_device),
+      0,
+      "graphics.timeline_semaphore",
+    )?;
+    let compute_timeline = hala_gfx::HalaSemaphore::new_timeline(
+      Rc::clone(&context.logical_device),
+      0,
+      "compute.timeline_semaphore",
+    )?;
     let transfer_staging_buffer = hala_gfx::HalaBuffer::new(
       Rc::clone(&context.logical_device),
       256 * 1024 * 1024, // 4096 * 4096 * RGBA32F = 256MB
@@ -88,28 +367,605 @@ impl HalaRendererResources {
       "transfer_staging.buffer",
     )?;
 
+    let streaming_uploader = HalaStreamingUploader::new(&context, num_of_frames)?;
+
     // Create descriptors.
+    let max_descriptor_sets = 512;
     let descriptor_pool = Rc::new(RefCell::new(hala_gfx::HalaDescriptorPool::new(
       Rc::clone(&context.logical_device),
       descriptor_sizes,
-      512,
+      max_descriptor_sets,
       "main.descriptor_pool"
     )?));
 
     Ok(
       Self {
+        retired_resources: VecDeque::new(),
+        frame_counter: 0,
+
         context: Rc::new(RefCell::new(context)),
 
         graphics_command_buffers,
         compute_command_buffers,
+        graphics_timeline,
+        graphics_timeline_value: 0,
+        graphics_frame_values: vec![0; num_of_frames],
+        compute_timeline,
+        compute_timeline_value: 0,
+        compute_frame_values: vec![0; num_of_frames],
+        pending_compute_wait: None,
+        async_compute_buffers: Vec::new(),
+        is_async_compute_released: false,
+        pending_transfers: Vec::new(),
+        transfer_acquire_barriers: Vec::new(),
         transfer_command_buffers,
         transfer_staging_buffer,
+        streaming_uploader,
+        secondary_command_pools: RefCell::new(Vec::new()),
+        secondary_command_buffers: None,
+
+        num_of_frames,
+
+        is_headless,
+        color_format,
 
         descriptor_pool,
+        descriptor_sizes: descriptor_sizes.to_vec(),
+        descriptor_reserved_sizes: Vec::new(),
+        max_descriptor_sets,
+        num_of_reserved_descriptor_sets: 0,
+
+        has_memory_budget,
+
+        present_mode: HalaPresentMode::from_gpu_req(gpu_req),
       }
     )
   }
 
+  /// Start a headless frame, it replaces the swapchain image acquiring of prepare_frame.
+  /// The frames cycle through the frames in flight, the last submit of the frame is waited on the graphics timeline.
+  /// return: The index of the frame.
+  pub fn prepare_headless_frame(&self) -> Result<usize, hala_gfx::HalaGfxError> {
+    let index = (self.frame_counter % self.num_of_frames as u64) as usize;
+    self.graphics_timeline.wait(self.graphics_frame_values[index], u64::MAX)?;
+
+    Ok(index)
+  }
+
+  /// Submit the graphics command buffer of the frame and present it, the headless frame is submitted without the present.
+  /// The submit waits the pending async compute work and the flushed streaming uploads,
+  /// and signals the graphics timeline for the following async compute work.
+  /// param index: The index of the frame.
+  /// return: The result.
+  pub fn submit_frame(&mut self, index: usize) -> Result<(), hala_gfx::HalaGfxError> {
+    let pending_upload_wait = self.streaming_uploader.take_graphics_wait();
+    let mut wait_semaphores = Vec::with_capacity(2);
+    if let Some(value) = self.pending_compute_wait.take() {
+      wait_semaphores.push((&self.compute_timeline, value, hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS));
+    }
+    if let Some(value) = pending_upload_wait {
+      wait_semaphores.push((&self.streaming_uploader.timeline, value, hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS));
+    }
+    let graphics_timeline_value = self.graphics_timeline_value + 1;
+    let signal_semaphores = [(&self.graphics_timeline, graphics_timeline_value, hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS)];
+
+    if self.is_headless {
+      self.context.borrow().logical_device.borrow().graphics_submit_with_semaphores(
+        &self.graphics_command_buffers,
+        index,
+        0,
+        &wait_semaphores,
+        &signal_semaphores,
+      )?;
+    } else {
+      self.context.borrow_mut().submit_and_present_frame_with_semaphores(
+        index,
+        &self.graphics_command_buffers,
+        &wait_semaphores,
+        &signal_semaphores,
+      )?;
+    }
+    self.graphics_timeline_value = graphics_timeline_value;
+    self.graphics_frame_values[index] = graphics_timeline_value;
+    // The frame has acquired the buffers released by the async compute work and the transfers.
+    self.is_async_compute_released = false;
+    self.transfer_acquire_barriers.clear();
+
+    Ok(())
+  }
+
+  /// Submit the transfer batch to the transfer queue without waiting.
+  /// The next frame waits its fence in pre_update and acquires the uploaded buffers, so they can be used from the next frame.
+  /// param transfer_batch: The transfer batch.
+  /// return: The result.
+  pub fn submit_transfer_batch(&mut self, transfer_batch: HalaTransferBatch) -> Result<(), HalaRendererError> {
+    if transfer_batch.is_empty() {
+      return Ok(());
+    }
+
+    let pending_transfer = transfer_batch.submit()?;
+    self.pending_transfers.push(pending_transfer);
+
+    Ok(())
+  }
+
+  /// Wait for the pending transfers and keep their acquire barriers for the next graphics frame.
+  /// return: The result.
+  pub fn finish_transfers(&mut self) -> Result<(), HalaRendererError> {
+    for pending_transfer in self.pending_transfers.drain(..) {
+      let acquire_barriers = pending_transfer.finish()?;
+      self.transfer_acquire_barriers.extend(acquire_barriers);
+    }
+
+    Ok(())
+  }
+
+  /// Record the acquire barriers of the finished transfers and the flushed streaming uploads at the beginning of the graphics frame.
+  /// Nothing is recorded if the transfer queue family is the same as the graphics one.
+  /// param index: The index of the frame.
+  /// param command_buffers: The graphics command buffers.
+  pub fn record_transfer_acquire(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if !self.transfer_acquire_barriers.is_empty() {
+      command_buffers.set_buffer_barriers(index, &self.transfer_acquire_barriers);
+    }
+    self.streaming_uploader.record_acquire(index, command_buffers);
+  }
+
+  /// Create the secondary command pools of the recording threads, one pool per thread for each frame in flight.
+  /// The device is waited idle, the pools of the in-flight frames are dropped.
+  /// param num_of_threads: The number of the recording threads, 1 drops the pools and records on the calling thread.
+  /// return: The result.
+  pub fn set_recording_threads(&mut self, num_of_threads: usize) -> Result<(), HalaRendererError> {
+    if num_of_threads == 0 {
+      return Err(HalaRendererError::validation("The number of the recording threads must be at least 1."));
+    }
+
+    let context = self.context.borrow();
+    let logical_device = context.logical_device.borrow();
+    logical_device.wait_idle()?;
+    self.secondary_command_pools.borrow_mut().clear();
+    self.secondary_command_buffers = None;
+    if num_of_threads == 1 {
+      return Ok(());
+    }
+
+    // The functions are only called if the mesh shader pipelines are used, which requires the extension.
+    let mesh_shader_device = ash::ext::mesh_shader::Device::new(&context.instance.raw, &logical_device.raw);
+    let mut secondary_command_pools = Vec::with_capacity(self.num_of_frames);
+    for _ in 0..self.num_of_frames {
+      let command_pools = (0..num_of_threads)
+        .map(|_| HalaSecondaryCommandPool::new(&logical_device.raw, Some(&mesh_shader_device), logical_device.graphics_queue_family_index))
+        .collect::<Result<Vec<_>, _>>()?;
+      secondary_command_pools.push(command_pools);
+    }
+    *self.secondary_command_pools.borrow_mut() = secondary_command_pools;
+    self.secondary_command_buffers = Some(hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::SECONDARY,
+      self.num_of_frames,
+      "main_graphics_secondary.cmd_buffer",
+    )?);
+
+    Ok(())
+  }
+
+  /// Whether the draws are recorded into the secondary command buffers by the recording threads.
+  /// return: True if the secondary command pools are created.
+  pub fn is_parallel_recording(&self) -> bool {
+    !self.secondary_command_pools.borrow().is_empty()
+  }
+
+  /// Reset the secondary command pools of the frame before recording it.
+  /// The frame must have completed on the GPU, like its primary command buffer.
+  /// param index: The index of the frame.
+  /// return: The result.
+  pub fn reset_secondary_command_pools(&self, index: usize) -> Result<(), HalaRendererError> {
+    if let Some(command_pools) = self.secondary_command_pools.borrow_mut().get_mut(index) {
+      for command_pool in command_pools.iter_mut() {
+        command_pool.reset()?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Record the draws into the secondary command buffers by the recording threads and execute them in the primary command buffer.
+  /// The primary command buffer must be in the render pass or the rendering of the inheritance, begun with the secondary contents.
+  /// The state of the primary command buffer is undefined after it.
+  /// param index: The index of the frame.
+  /// param command_buffers: The primary graphics command buffers.
+  /// param inheritance: The render pass or the rendering scope.
+  /// param draws: The draws in the order of the serial recording.
+  /// return: The pipeline bind count.
+  pub(crate) fn execute_draws_in_parallel(
+    &self,
+    index: usize,
+    command_buffers: &hala_gfx::HalaCommandBufferSet,
+    inheritance: &HalaSecondaryInheritance,
+    draws: &[HalaSecondaryDraw],
+  ) -> Result<u64, HalaRendererError> {
+    let mut secondary_command_pools = self.secondary_command_pools.borrow_mut();
+    let command_pools = secondary_command_pools.get_mut(index)
+      .ok_or(HalaRendererError::invalid_state("The secondary command pools are none! Call set_recording_threads with more than 1 thread."))?;
+    let (secondary_command_buffers, num_of_pipeline_binds) = record_draws_in_parallel(command_pools, inheritance, draws)?;
+
+    if !secondary_command_buffers.is_empty() {
+      let context = self.context.borrow();
+      let logical_device = context.logical_device.borrow();
+      unsafe {
+        logical_device.raw.cmd_execute_commands(command_buffers.raw[index], &secondary_command_buffers);
+      }
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Record the draws of the calling thread into the secondary command buffer of the frame and execute it in the primary command buffer.
+  /// The draws after the parallel draws in the same rendering can not be recorded inline, the rendering has the secondary contents.
+  /// param index: The index of the frame.
+  /// param command_buffers: The primary graphics command buffers.
+  /// param inheritance: The render pass or the rendering scope.
+  /// param record_fn: The function records the draws into the secondary command buffers, it returns the pipeline bind count.
+  /// return: The pipeline bind count.
+  pub(crate) fn execute_secondary_draws<F>(
+    &self,
+    index: usize,
+    command_buffers: &hala_gfx::HalaCommandBufferSet,
+    inheritance: &HalaSecondaryInheritance,
+    record_fn: F,
+  ) -> Result<u64, HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError>
+  {
+    let secondary_command_buffers = self.secondary_command_buffers.as_ref()
+      .ok_or(HalaRendererError::invalid_state("The secondary command buffers are none! Call set_recording_threads with more than 1 thread."))?;
+
+    secondary_command_buffers.reset(index, false)?;
+    {
+      let context = self.context.borrow();
+      let logical_device = context.logical_device.borrow();
+      inheritance.begin(&logical_device.raw, secondary_command_buffers.raw[index])?;
+    }
+    let num_of_pipeline_binds = record_fn(index, secondary_command_buffers)?;
+    secondary_command_buffers.end(index)?;
+
+    let context = self.context.borrow();
+    let logical_device = context.logical_device.borrow();
+    unsafe {
+      logical_device.raw.cmd_execute_commands(command_buffers.raw[index], &[secondary_command_buffers.raw[index]]);
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Copy the data to the staging ring and record its upload on the transfer queue without waiting.
+  /// The target must be a new resource which is not used by the graphics queue yet, it can be referenced
+  /// in the descriptors from the frame recorded after flush_uploads, or when is_upload_complete returns true.
+  /// param data: The data.
+  /// param dst: The upload target.
+  /// return: The timeline value of the upload.
+  pub fn enqueue_upload(&mut self, data: &[u8], dst: HalaUploadTarget) -> Result<u64, HalaRendererError> {
+    self.streaming_uploader.enqueue_upload(data, dst)
+  }
+
+  /// Submit the enqueued uploads to the transfer queue, the next graphics submit waits them.
+  /// The renderers call it once per frame before recording, so the frame acquires the uploaded resources.
+  /// return: The timeline value of the submitted batch, none if nothing is enqueued.
+  pub fn flush_uploads(&mut self) -> Result<Option<u64>, HalaRendererError> {
+    self.streaming_uploader.flush_uploads()
+  }
+
+  /// Whether the upload of the timeline value is complete on the GPU.
+  /// param timeline_value: The timeline value returned by enqueue_upload.
+  /// return: True if the upload is complete.
+  pub fn is_upload_complete(&self, timeline_value: u64) -> Result<bool, HalaRendererError> {
+    self.streaming_uploader.is_upload_complete(timeline_value)
+  }
+
+  /// Set the exclusive buffers shared by the async compute work and the graphics work, e.g. the particle buffers.
+  /// If the compute queue family differs from the graphics one, their ownership is transferred between the queues:
+  /// every graphics frame releases them at its end, and submit_async_compute must be called once after each render
+  /// with both waits, it acquires and releases them, then the next graphics frame acquires them at its beginning.
+  /// The buffers created with the concurrent sharing mode need no ownership transfer and should not be set.
+  /// param buffers: The shared buffers.
+  pub fn set_async_compute_buffers(&mut self, buffers: &[&hala_gfx::HalaBuffer]) {
+    self.async_compute_buffers = buffers.iter().map(|buffer| (buffer.raw, buffer.size)).collect();
+    self.is_async_compute_released = false;
+  }
+
+  /// Whether the ownership of the shared buffers is transferred between the graphics queue and the compute queue.
+  /// return: The graphics queue family index and the compute queue family index if the ownership is transferred.
+  fn get_ownership_transfer(&self) -> Option<(u32, u32)> {
+    if self.async_compute_buffers.is_empty() {
+      return None;
+    }
+
+    let context = self.context.borrow();
+    let logical_device = context.logical_device.borrow();
+    let graphics_queue_family_index = logical_device.graphics_queue_family_index;
+    let compute_queue_family_index = logical_device.compute_queue_family_index;
+    if graphics_queue_family_index == compute_queue_family_index {
+      None
+    } else {
+      Some((graphics_queue_family_index, compute_queue_family_index))
+    }
+  }
+
+  /// Get the queue family ownership transfer barriers of the shared buffers.
+  /// The release barrier only has the source scope and the acquire barrier only has the destination scope.
+  /// param src_queue_family_index: The queue family index releasing the buffers.
+  /// param dst_queue_family_index: The queue family index acquiring the buffers.
+  /// param is_release: Whether the barriers are recorded on the releasing queue.
+  /// param stage_mask: The stages accessing the buffers on the recording queue.
+  /// return: The buffer barriers.
+  fn get_ownership_barriers(
+    &self,
+    src_queue_family_index: u32,
+    dst_queue_family_index: u32,
+    is_release: bool,
+    stage_mask: hala_gfx::HalaPipelineStageFlags2,
+  ) -> Vec<hala_gfx::HalaBufferBarrierInfo> {
+    let access_mask = hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE;
+    self.async_compute_buffers.iter().map(|(buffer, size)| hala_gfx::HalaBufferBarrierInfo {
+      src_access_mask: if is_release { access_mask } else { hala_gfx::HalaAccessFlags2::NONE },
+      dst_access_mask: if is_release { hala_gfx::HalaAccessFlags2::NONE } else { access_mask },
+      src_stage_mask: if is_release { stage_mask } else { hala_gfx::HalaPipelineStageFlags2::NONE },
+      dst_stage_mask: if is_release { hala_gfx::HalaPipelineStageFlags2::NONE } else { stage_mask },
+      src_queue_family_index,
+      dst_queue_family_index,
+      buffer: *buffer,
+      size: *size,
+      ..Default::default()
+    }).collect()
+  }
+
+  /// Record the acquire barriers of the shared buffers released by the async compute work at the beginning of the graphics frame.
+  /// param index: The index of the frame.
+  /// param command_buffers: The graphics command buffers.
+  pub fn record_async_compute_acquire(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if !self.is_async_compute_released {
+      return;
+    }
+    if let Some((graphics_queue_family_index, compute_queue_family_index)) = self.get_ownership_transfer() {
+      command_buffers.set_buffer_barriers(
+        index,
+        &self.get_ownership_barriers(compute_queue_family_index, graphics_queue_family_index, false, hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS),
+      );
+    }
+  }
+
+  /// Record the release barriers of the shared buffers to the async compute work at the end of the graphics frame.
+  /// param index: The index of the frame.
+  /// param command_buffers: The graphics command buffers.
+  pub fn record_async_compute_release(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if let Some((graphics_queue_family_index, compute_queue_family_index)) = self.get_ownership_transfer() {
+      command_buffers.set_buffer_barriers(
+        index,
+        &self.get_ownership_barriers(graphics_queue_family_index, compute_queue_family_index, true, hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS),
+      );
+    }
+  }
+
+  /// Whether the device supports the descriptor indexing features of the bindless descriptor set.
+  /// The runtime sized arrays of the sampled images, the samplers and the storage buffers are partially bound,
+  /// updated after bind and the last one has the variable descriptor count, they are all Vulkan 1.2 features.
+  /// return: True if all features are supported.
+  pub fn supports_bindless(&self) -> bool {
+    let context = self.context.borrow();
+    let mut vulkan12_features = vk::PhysicalDeviceVulkan12Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan12_features);
+    unsafe {
+      context.instance.raw.get_physical_device_features2(context.physical_device.raw, &mut features);
+    }
+
+    vulkan12_features.runtime_descriptor_array == vk::TRUE
+      && vulkan12_features.descriptor_binding_partially_bound == vk::TRUE
+      && vulkan12_features.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+      && vulkan12_features.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE
+      && vulkan12_features.descriptor_binding_variable_descriptor_count == vk::TRUE
+  }
+
+  /// Get the GPU memory budget of the device local heaps.
+  /// The budget and the usage are queried from VK_EXT_memory_budget every call, so they follow the allocations of the whole process.
+  /// return: The used and the available bytes.
+  pub fn memory_budget(&self) -> HalaMemoryBudget {
+    let context = self.context.borrow();
+    let mut budget_properties = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+    let mut memory_properties = vk::PhysicalDeviceMemoryProperties2::default();
+    if self.has_memory_budget {
+      memory_properties = memory_properties.push_next(&mut budget_properties);
+    }
+    unsafe {
+      context.instance.raw.get_physical_device_memory_properties2(context.physical_device.raw, &mut memory_properties);
+    }
+    let memory_heaps = {
+      let properties = &memory_properties.memory_properties;
+      properties.memory_heaps[..properties.memory_heap_count as usize].to_vec()
+    };
+
+    let mut heaps = Vec::with_capacity(memory_heaps.len());
+    for (heap_index, heap) in memory_heaps.iter().enumerate() {
+      if !heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL) {
+        continue;
+      }
+      let budget_and_usage = if self.has_memory_budget {
+        Some((budget_properties.heap_budget[heap_index], budget_properties.heap_usage[heap_index]))
+      } else {
+        None
+      };
+      heaps.push((heap.size, budget_and_usage));
+    }
+
+    HalaMemoryBudget::from_heaps(&heaps)
+  }
+
+  /// Check whether the current descriptor pool has enough capacity for the required descriptors.
+  /// param required_sizes: The required descriptor counts.
+  /// param num_of_sets: The required number of descriptor sets.
+  /// return: The result, the error describes which descriptor type exceeds the capacity and by how much.
+  pub fn check_descriptor_capacity(
+    &self,
+    required_sizes: &[(hala_gfx::HalaDescriptorType, usize)],
+    num_of_sets: usize,
+  ) -> Result<(), HalaRendererError> {
+    let mut deficits = Vec::new();
+    for (descriptor_type, required) in Self::merge_descriptor_sizes(required_sizes).iter() {
+      let capacity = Self::get_descriptor_size(&self.descriptor_sizes, *descriptor_type);
+      let reserved = Self::get_descriptor_size(&self.descriptor_reserved_sizes, *descriptor_type);
+      let available = capacity.saturating_sub(reserved);
+      if *required > available {
+        deficits.push(format!(
+          "{:?} requires {} but only {} of {} are available(exceeds by {})",
+          descriptor_type, required, available, capacity, required - available,
+        ));
+      }
+    }
+    let available_sets = self.max_descriptor_sets.saturating_sub(self.num_of_reserved_descriptor_sets);
+    if num_of_sets > available_sets {
+      deficits.push(format!(
+        "descriptor sets requires {} but only {} of {} are available(exceeds by {})",
+        num_of_sets, available_sets, self.max_descriptor_sets, num_of_sets - available_sets,
+      ));
+    }
+
+    if deficits.is_empty() {
+      Ok(())
+    } else {
+      Err(HalaRendererError::new(&format!("The descriptor pool is out of capacity: {}.", deficits.join(", ")), None))
+    }
+  }
+
+  /// Reserve the descriptors before allocating the descriptor sets from the descriptor pool.
+  /// If the current descriptor pool does not have enough capacity, a new descriptor pool sized to the requirement will be created.
+  /// The old descriptor pool is kept alive by the descriptor sets allocated from it.
+  /// param required_sizes: The required descriptor counts.
+  /// param num_of_sets: The required number of descriptor sets.
+  /// return: The result.
+  pub fn reserve_descriptors(
+    &mut self,
+    required_sizes: &[(hala_gfx::HalaDescriptorType, usize)],
+    num_of_sets: usize,
+  ) -> Result<(), HalaRendererError> {
+    let required_sizes = Self::merge_descriptor_sizes(required_sizes);
+
+    if let Err(err) = self.check_descriptor_capacity(&required_sizes, num_of_sets) {
+      log::warn!("{} Create a new descriptor pool.", err.message());
+
+      // Keep the default capacity for the following allocations and add the requirement.
+      let mut descriptor_sizes = self.descriptor_sizes.clone();
+      for (descriptor_type, required) in required_sizes.iter() {
+        match descriptor_sizes.iter_mut().find(|(t, _)| *t == *descriptor_type) {
+          Some((_, size)) => *size += *required,
+          None => descriptor_sizes.push((*descriptor_type, *required)),
+        }
+      }
+      let max_descriptor_sets = self.max_descriptor_sets.max(num_of_sets);
+      let descriptor_pool = hala_gfx::HalaDescriptorPool::new(
+        Rc::clone(&self.context.borrow().logical_device),
+        descriptor_sizes.as_slice(),
+        max_descriptor_sets,
+        "main.descriptor_pool"
+      ).map_err(|e| HalaRendererError::new(err.message(), Some(Box::new(e))))?;
+
+      self.descriptor_pool = Rc::new(RefCell::new(descriptor_pool));
+      self.descriptor_sizes = descriptor_sizes;
+      self.descriptor_reserved_sizes.clear();
+      self.max_descriptor_sets = max_descriptor_sets;
+      self.num_of_reserved_descriptor_sets = 0;
+    }
+
+    for (descriptor_type, required) in required_sizes.iter() {
+      match self.descriptor_reserved_sizes.iter_mut().find(|(t, _)| *t == *descriptor_type) {
+        Some((_, size)) => *size += *required,
+        None => self.descriptor_reserved_sizes.push((*descriptor_type, *required)),
+      }
+    }
+    self.num_of_reserved_descriptor_sets += num_of_sets;
+
+    Ok(())
+  }
+
+  /// Reset the descriptor reservations when the descriptor sets of the scene are recreated, e.g. the scene is changed or the renderer is committed again.
+  /// Without it the reservations of the released descriptor sets are counted forever and the descriptor pool keeps growing.
+  /// A new descriptor pool with the current capacity is created, the old one is kept alive by the descriptor sets allocated from it.
+  /// return: The result.
+  pub fn reset_descriptor_reservations(&mut self) -> Result<(), HalaRendererError> {
+    let descriptor_pool = hala_gfx::HalaDescriptorPool::new(
+      Rc::clone(&self.context.borrow().logical_device),
+      self.descriptor_sizes.as_slice(),
+      self.max_descriptor_sets,
+      "main.descriptor_pool"
+    )?;
+
+    self.descriptor_pool = Rc::new(RefCell::new(descriptor_pool));
+    self.descriptor_reserved_sizes.clear();
+    self.num_of_reserved_descriptor_sets = 0;
+
+    Ok(())
+  }
+
+  /// Merge the descriptor counts with the same type.
+  /// param sizes: The descriptor counts.
+  /// return: The merged descriptor counts.
+  fn merge_descriptor_sizes(sizes: &[(hala_gfx::HalaDescriptorType, usize)]) -> Vec<(hala_gfx::HalaDescriptorType, usize)> {
+    let mut merged: Vec<(hala_gfx::HalaDescriptorType, usize)> = Vec::with_capacity(sizes.len());
+    for (descriptor_type, size) in sizes.iter() {
+      match merged.iter_mut().find(|(t, _)| *t == *descriptor_type) {
+        Some((_, merged_size)) => *merged_size += *size,
+        None => merged.push((*descriptor_type, *size)),
+      }
+    }
+    merged
+  }
+
+  /// Get the descriptor count of the type.
+  /// param sizes: The descriptor counts.
+  /// param descriptor_type: The descriptor type.
+  /// return: The descriptor count.
+  fn get_descriptor_size(sizes: &[(hala_gfx::HalaDescriptorType, usize)], descriptor_type: hala_gfx::HalaDescriptorType) -> usize {
+    sizes.iter().filter(|(t, _)| *t == descriptor_type).map(|(_, size)| *size).sum()
+  }
+
+  /// Retire a resource.
+  /// The resource will be dropped after all in-flight frames which may use it are finished.
+  /// param resource: The resource.
+  pub fn retire(&mut self, resource: Box<dyn Any>) {
+    self.retire_with_size(resource, 0);
+  }
+
+  /// Retire a resource with its estimated memory size.
+  /// The size is counted as reclaimable by the memory budget until the resource is dropped.
+  /// param resource: The resource.
+  /// param num_of_bytes: The estimated bytes of the resource.
+  pub fn retire_with_size(&mut self, resource: Box<dyn Any>, num_of_bytes: u64) {
+    self.retired_resources.push_back((self.frame_counter + self.num_of_frames as u64, num_of_bytes, resource));
+  }
+
+  /// Get the estimated bytes of the retired resources which are not dropped yet.
+  /// return: The bytes.
+  pub fn get_retired_bytes(&self) -> u64 {
+    self.retired_resources.iter().map(|(_, num_of_bytes, _)| *num_of_bytes).sum()
+  }
+
+  /// Advance the frame counter and drop the retired resources whose frames are finished.
+  /// It should be called after the fence of the current frame is signaled.
+  pub fn release_retired_resources(&mut self) {
+    self.frame_counter += 1;
+    while let Some((frame, _, _)) = self.retired_resources.front() {
+      if *frame > self.frame_counter {
+        break;
+      }
+      self.retired_resources.pop_front();
+    }
+  }
+
+  /// Drop all retired resources immediately.
+  /// The caller must make sure the device is idle.
+  pub fn flush_retired_resources(&mut self) {
+    self.retired_resources.clear();
+  }
+
 }
 
 /// The renderer data.
@@ -117,6 +973,15 @@ impl HalaRendererResources {
 pub struct HalaRendererData {
   pub image_index: usize,
   pub is_device_lost: bool,
+  // The swapchain must be recreated at the next frame boundary, e.g. the present mode is changed, the device is not lost.
+  pub is_swapchain_dirty: bool,
+  // The present mode applied at the next frame boundary.
+  pub pending_present_mode: Option<HalaPresentMode>,
+  // The CPU frame limiter, the start time of the last frame is used to pace the next one.
+  pub target_fps: Option<f32>,
+  pub last_frame_time: Option<std::time::Instant>,
+  // The window size waiting to be stable for a frame before the resources are resized.
+  pub pending_size: Option<(u32, u32)>,
 }
 
 /// The renderer data implementation.
@@ -131,6 +996,56 @@ impl HalaRendererData {
 }
 
 
+/// The draw counters of the last recorded frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HalaDrawCounters {
+  pub draw_calls: u64,
+  pub triangles: u64,
+  pub mesh_task_groups: u64,
+  // The primitives skipped by the culling or the visibility without a draw call.
+  pub skipped_primitives: u64,
+}
+
+/// The draw counters implementation.
+impl HalaDrawCounters {
+
+  /// Count an indexed draw call.
+  /// param index_count: The index count of the draw call.
+  pub fn add_indexed_draw(&mut self, index_count: u32) {
+    self.draw_calls += 1;
+    self.triangles += (index_count / 3) as u64;
+  }
+
+  /// Count a mesh tasks draw call.
+  /// param group_count: The task group count of the draw call.
+  pub fn add_mesh_tasks_draw(&mut self, group_count: u32) {
+    self.draw_calls += 1;
+    self.mesh_task_groups += group_count as u64;
+  }
+
+  /// Count a skipped primitive.
+  pub fn add_skipped_primitive(&mut self) {
+    self.skipped_primitives += 1;
+  }
+
+}
+
+/// The Display implementation of the draw counters.
+impl std::fmt::Display for HalaDrawCounters {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Draw Calls: {}, Triangles: {}, Task Groups: {}, Skipped Primitives: {}",
+      self.draw_calls,
+      self.triangles,
+      self.mesh_task_groups,
+      self.skipped_primitives,
+    )
+  }
+
+}
+
 /// The renderer statistics.
 pub struct HalaRendererStatistics {
   pub total_frames: u64,
@@ -138,6 +1053,19 @@ pub struct HalaRendererStatistics {
   pub elapsed_time: std::time::Duration,
   pub total_gpu_nanoseconds: u128,
   pub total_gpu_frames: u64,
+  pub pipeline_binds: u64,
+  pub occluded_primitives: u64,
+  // The meshlets drawn by the first and the second phase of the occlusion culling of the last finished frame.
+  pub occlusion_culling_meshlets: [u64; 2],
+  pub draw_counters: HalaDrawCounters,
+  // The primary rays of a frame of the ray tracing renderer, each frame accumulates one sample per pixel.
+  pub rays_per_frame: u64,
+  // The start time of the accumulation, the accumulated samples are the total frames since it.
+  pub accumulation_start_time: std::time::Instant,
+  // The values of the last stat interval.
+  pub fps: u64,
+  pub gpu_time_ms: f64,
+  pub cpu_time_ms: f64,
 }
 
 /// The renderer statistics default implementation.
@@ -150,6 +1078,15 @@ impl Default for HalaRendererStatistics {
       elapsed_time: std::time::Duration::new(0, 0),
       total_gpu_nanoseconds: 0,
       total_gpu_frames: 0,
+      pipeline_binds: 0,
+      occluded_primitives: 0,
+      occlusion_culling_meshlets: [0; 2],
+      draw_counters: HalaDrawCounters::default(),
+      rays_per_frame: 0,
+      accumulation_start_time: std::time::Instant::now(),
+      fps: 0,
+      gpu_time_ms: 0.0,
+      cpu_time_ms: 0.0,
     }
   }
 
@@ -171,6 +1108,53 @@ impl HalaRendererStatistics {
     self.elapsed_time = std::time::Duration::new(0, 0);
     self.total_gpu_nanoseconds = 0;
     self.total_gpu_frames = 0;
+    self.pipeline_binds = 0;
+    self.occluded_primitives = 0;
+    self.occlusion_culling_meshlets = [0; 2];
+    self.draw_counters = HalaDrawCounters::default();
+    self.rays_per_frame = 0;
+    self.accumulation_start_time = std::time::Instant::now();
+  }
+
+  /// Set the pipeline bind count of the last recorded frame.
+  /// param pipeline_binds: The pipeline bind count.
+  pub fn set_pipeline_binds(&mut self, pipeline_binds: u64) {
+    self.pipeline_binds = pipeline_binds;
+  }
+
+  /// Set the draw counters of the last recorded frame.
+  /// The counters are kept if a frame is not recorded, so they always describe the commands in flight.
+  /// param draw_counters: The draw counters.
+  pub fn set_draw_counters(&mut self, draw_counters: HalaDrawCounters) {
+    self.draw_counters = draw_counters;
+  }
+
+  /// Set the primary rays of a frame.
+  /// param rays_per_frame: The primary ray count.
+  pub fn set_rays_per_frame(&mut self, rays_per_frame: u64) {
+    self.rays_per_frame = rays_per_frame;
+  }
+
+  /// Get the wall time per accumulated sample since the last reset.
+  /// return: The time per sample, zero if no frame is accumulated.
+  pub fn get_time_per_sample(&self) -> std::time::Duration {
+    if self.total_frames == 0 {
+      std::time::Duration::ZERO
+    } else {
+      self.accumulation_start_time.elapsed() / self.total_frames.min(u32::MAX as u64) as u32
+    }
+  }
+
+  /// Set the fully occluded primitive count of the last finished frame.
+  /// param occluded_primitives: The fully occluded primitive count.
+  pub fn set_occluded_primitives(&mut self, occluded_primitives: u64) {
+    self.occluded_primitives = occluded_primitives;
+  }
+
+  /// Set the meshlets drawn by each phase of the occlusion culling of the last finished frame.
+  /// param meshlets: The meshlet counts of the first and the second phase.
+  pub fn set_occlusion_culling_meshlets(&mut self, meshlets: [u64; 2]) {
+    self.occlusion_culling_meshlets = meshlets;
   }
 
   /// Set the GPU time.
@@ -185,13 +1169,10 @@ impl HalaRendererStatistics {
     self.elapsed_time += interval;
     if self.elapsed_time > std::time::Duration::from_secs(1) {
       let elapsed_time_nanos = self.elapsed_time.as_nanos();
-      log::info!(
-        "FPS: {}, GPU Time: {:.4}ms, CPU Time: {:.4}ms, Total Frames: {}",
-        self.total_gpu_frames * elapsed_time_nanos as u64 / 1000000000,
-        self.total_gpu_nanoseconds as f64 / self.total_gpu_frames as f64 / 1000000.0,
-        elapsed_time_nanos as f64 / self.total_gpu_frames as f64 / 1000000.0,
-        self.total_frames + 1,
-      );
+      self.fps = self.total_gpu_frames * elapsed_time_nanos as u64 / 1000000000;
+      self.gpu_time_ms = self.total_gpu_nanoseconds as f64 / self.total_gpu_frames as f64 / 1000000.0;
+      self.cpu_time_ms = elapsed_time_nanos as f64 / self.total_gpu_frames as f64 / 1000000.0;
+      log::info!("{}", self);
       self.total_gpu_nanoseconds = 0;
       self.total_gpu_frames = 0;
       self.elapsed_time -= std::time::Duration::from_secs(1);
@@ -204,6 +1185,60 @@ impl HalaRendererStatistics {
     self.total_frames += 1;
   }
 
+  /// Get the summary of the last stat interval and the last recorded frame, used by the logging and the debug overlay.
+  /// return: The summary.
+  pub fn get_summary(&self) -> String {
+    self.to_string()
+  }
+
+  /// Get the variance reduction of the accumulated image, the ratio of the variance of the N sample mean to the variance of one sample.
+  /// It is 1 / N for the independent samples, it is not measured from the image.
+  /// return: The variance reduction, 1 if no frame is accumulated.
+  pub fn get_variance_reduction(&self) -> f32 {
+    if self.total_frames == 0 {
+      1.0
+    } else {
+      1.0 / self.total_frames as f32
+    }
+  }
+
+}
+
+/// The Display implementation of the renderer statistics.
+impl std::fmt::Display for HalaRendererStatistics {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "FPS: {}, GPU Time: {:.4}ms, CPU Time: {:.4}ms, Pipeline Binds: {}, Occluded Primitives: {}, {}, Total Frames: {}",
+      self.fps,
+      self.gpu_time_ms,
+      self.cpu_time_ms,
+      self.pipeline_binds,
+      self.occluded_primitives,
+      self.draw_counters,
+      self.total_frames,
+    )?;
+    if self.occlusion_culling_meshlets != [0; 2] {
+      write!(
+        f,
+        ", Meshlets: {} + {}",
+        self.occlusion_culling_meshlets[0],
+        self.occlusion_culling_meshlets[1],
+      )?;
+    }
+    if self.rays_per_frame > 0 {
+      write!(
+        f,
+        ", Rays/Frame: {}, Time/Sample: {:.4}ms",
+        self.rays_per_frame,
+        self.get_time_per_sample().as_secs_f64() * 1000.0,
+      )?;
+    }
+
+    Ok(())
+  }
+
 }
 
 /// The renderer trait.
@@ -220,11 +1255,19 @@ pub trait HalaRendererTrait {
 
   fn get_descriptor_sizes() -> Vec<(hala_gfx::HalaDescriptorType, usize)>;
 
+  /// Validate the renderer before creating any GPU resource in commit.
+  /// return: The result, the errors list all problems found.
+  fn validate(&self) -> Result<(), Vec<HalaRendererError>> {
+    Ok(())
+  }
+
   /// Commit all GPU resources.
   /// return: The result.
   fn commit(&mut self) -> Result<(), HalaRendererError>;
 
   /// Check and restore the device.
+  /// The swapchain is recreated and on_resize recreates the resources referencing it(e.g. the G-Buffer, the multisample images
+  /// and the framebuffers), the pipelines and the scene buffers are kept.
   /// param width: The width of the swapchain.
   /// param height: The height of the swapchain.
   /// return: The result.
@@ -232,20 +1275,93 @@ pub trait HalaRendererTrait {
     self.check_and_restore_swapchain(width, height)
   }
   fn check_and_restore_swapchain(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
-    if self.data().is_device_lost {
-      self.resources().context.borrow_mut().reset_swapchain(width, height)?;
+    if self.data().is_device_lost || self.data().is_swapchain_dirty {
+      if !self.resources().is_headless {
+        self.resources().context.borrow_mut().reset_swapchain(width, height)?;
+      }
 
+      // The resources referencing the swapchain images(e.g. the framebuffers) are recreated even the size is not changed.
       self.info_mut().width = width;
       self.info_mut().height = height;
+      self.on_resize(width, height)?;
 
       self.statistics_mut().reset();
 
       self.data_mut().is_device_lost = false;
+      self.data_mut().is_swapchain_dirty = false;
+    }
+
+    Ok(())
+  }
+
+  /// Check the window size and resize the swapchain and the size dependent resources.
+  /// The new size must be stable for a frame, so the continuous resizing(e.g. dragging the border) is coalesced.
+  /// The headless renderer keeps the size of the GPU requirements.
+  /// param width: The width of the window.
+  /// param height: The height of the window.
+  /// return: The result.
+  fn check_and_resize(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
+    if self.resources().is_headless {
+      return Ok(());
+    }
+    // The minimized window has no size, keep the old resources.
+    if width == 0 || height == 0 || (width == self.info().width && height == self.info().height) {
+      self.data_mut().pending_size = None;
+      return Ok(());
+    }
+    if self.data().pending_size != Some((width, height)) {
+      self.data_mut().pending_size = Some((width, height));
+      return Ok(());
     }
+    self.data_mut().pending_size = None;
+
+    // The size dependent resources may still be used by the in-flight frames.
+    self.wait_idle()?;
+    self.resources().context.borrow_mut().reset_swapchain(width, height)?;
+
+    self.info_mut().width = width;
+    self.info_mut().height = height;
+    self.on_resize(width, height)?;
+
+    self.statistics_mut().reset();
+    log::debug!("The renderer \"{}\" is resized to [{} x {}].", self.info().name, width, height);
 
     Ok(())
   }
 
+  /// Recreate the size dependent resources and the resources referencing the swapchain images after the swapchain is recreated.
+  /// The renderer information already has the new size and the device is idle.
+  /// param width: The width of the swapchain.
+  /// param height: The height of the swapchain.
+  /// return: The result.
+  fn on_resize(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError>;
+
+  /// Mark the device as lost after a device lost error, the frame is skipped and the device is restored on the next frame update.
+  /// The command buffer of the current image may be left in the recording or the pending state, so it is reset.
+  fn mark_device_lost(&mut self) {
+    {
+      let context = self.resources().context.borrow();
+      let logical_device = context.logical_device.borrow();
+      if let Err(err) = logical_device.wait_idle() {
+        log::error!("Failed to wait the device idle: {}", err);
+      }
+    }
+    if let Err(err) = self.resources().graphics_command_buffers.reset(self.data().image_index, true) {
+      log::error!("Failed to reset the graphics command buffer: {}", err);
+    }
+    log::warn!("The device is lost!");
+    self.data_mut().is_device_lost = true;
+  }
+
+  /// Fake a device lost to exercise the restoring path without hanging the GPU.
+  /// Call it between the frames, the next frame update restores the device by check_and_restore_device.
+  /// It is only compiled for the tests and with the "device-lost-simulation" feature.
+  #[cfg(any(test, feature = "device-lost-simulation"))]
+  fn simulate_device_lost(&mut self) {
+    log::warn!("The device lost is simulated.");
+    self.data_mut().is_device_lost = true;
+  }
+
   /// Wait the renderer idle.
   /// return: The result.
   fn wait_idle(&self) -> Result<(), HalaRendererError> {
@@ -255,7 +1371,94 @@ pub trait HalaRendererTrait {
     Ok(())
   }
 
+  /// Record and submit the compute work of a frame on the compute queue, e.g. the GPU particles of the next frame.
+  /// The cross queue dependencies are the timeline semaphores waited and signaled on the GPU, no queue is waited on the CPU:
+  /// the independent compute work overlaps the graphics work if neither wait is requested.
+  /// Call it after render, so the compute work can wait the submitted graphics frame.
+  /// The compute command buffers use the compute command pool, hala-gfx selects the graphics queue family if there is no separate one.
+  /// The exclusive buffers set by set_async_compute_buffers are acquired from and released to the graphics queue family if it differs.
+  /// param frame_index: The index of the frame in flight.
+  /// param record_fn: The function to record the compute commands, the command buffer is begun and ended outside.
+  /// param wait_for_graphics: Whether the compute work waits the last submitted graphics frame on the GPU.
+  /// param signal_graphics_wait: Whether the next graphics submit waits the compute work on the GPU, so it can read its results.
+  /// return: The result.
+  fn submit_async_compute<F>(&mut self, frame_index: usize, record_fn: F, wait_for_graphics: bool, signal_graphics_wait: bool) -> Result<(), HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), HalaRendererError>
+  {
+    if self.data().is_device_lost {
+      return Ok(());
+    }
+
+    let resources = self.resources();
+    if frame_index >= resources.num_of_frames {
+      return Err(HalaRendererError::new(&format!("The frame index {} is out of range!", frame_index), None));
+    }
+    let ownership_transfer = resources.get_ownership_transfer();
+    if ownership_transfer.is_some() && !(wait_for_graphics && signal_graphics_wait) {
+      return Err(HalaRendererError::invalid_state(
+        "The async compute work must wait the graphics work and be waited by it to transfer the ownership of the shared buffers!"));
+    }
+
+    let compute_timeline_value = resources.compute_timeline_value + 1;
+    {
+      let context = resources.context.borrow();
+      let logical_device = context.logical_device.borrow();
+      let command_buffers = &resources.compute_command_buffers;
+
+      // The previous compute work of the frame must be finished before its command buffer is reset.
+      resources.compute_timeline.wait(resources.compute_frame_values[frame_index], u64::MAX)?;
+
+      command_buffers.reset(frame_index, false)?;
+      command_buffers.begin(frame_index, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+      if cfg!(debug_assertions) {
+        command_buffers.begin_debug_label(frame_index, "Async Compute", [0.0, 0.5, 1.0, 1.0]);
+      }
+      if let Some((graphics_queue_family_index, compute_queue_family_index)) = ownership_transfer {
+        command_buffers.set_buffer_barriers(
+          frame_index,
+          &resources.get_ownership_barriers(graphics_queue_family_index, compute_queue_family_index, false, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER),
+        );
+      }
+      record_fn(frame_index, command_buffers)?;
+      if let Some((graphics_queue_family_index, compute_queue_family_index)) = ownership_transfer {
+        command_buffers.set_buffer_barriers(
+          frame_index,
+          &resources.get_ownership_barriers(compute_queue_family_index, graphics_queue_family_index, true, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER),
+        );
+      }
+      if cfg!(debug_assertions) {
+        command_buffers.end_debug_label(frame_index);
+      }
+      command_buffers.end(frame_index)?;
+
+      let wait_semaphores = if wait_for_graphics {
+        vec![(&resources.graphics_timeline, resources.graphics_timeline_value, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER)]
+      } else {
+        Vec::new()
+      };
+      logical_device.compute_submit_with_semaphores(
+        command_buffers,
+        frame_index,
+        0,
+        &wait_semaphores,
+        &[(&resources.compute_timeline, compute_timeline_value, hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER)],
+      )?;
+    }
+
+    let resources = self.resources_mut();
+    resources.compute_timeline_value = compute_timeline_value;
+    resources.compute_frame_values[frame_index] = compute_timeline_value;
+    resources.is_async_compute_released = ownership_transfer.is_some();
+    if signal_graphics_wait {
+      resources.pending_compute_wait = Some(compute_timeline_value);
+    }
+
+    Ok(())
+  }
+
   /// Update the renderer.
+  /// The ui_fn is called in a single sample rendering on the swapchain image with the swapchain color and depth stencil formats,
+  /// whatever the multisample count of the context is. The headless renderer has no swapchain image and does not call it.
   /// param delta_time: The delta time.
   /// param width: The width of the window.
   /// param height: The height of the window.
@@ -264,14 +1467,36 @@ pub trait HalaRendererTrait {
   fn update<F>(&mut self, _delta_time: f64, width: u32, height: u32, ui_fn: F) -> Result<(), HalaRendererError>
     where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>;
   fn pre_update(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
+    self.limit_frame_rate();
+    self.apply_pending_present_mode()?;
     self.check_and_restore_device(width, height)?;
+    self.check_and_resize(width, height)?;
 
-    // Get a new image index.
-    let image_index = self.resources().context.borrow().prepare_frame()?;
+    // Get a new image index, the frame is skipped if the device is lost.
+    let result = if self.resources().is_headless {
+      self.resources().prepare_headless_frame()
+    } else {
+      self.resources().context.borrow().prepare_frame()
+    };
+    let image_index = match result {
+      Ok(image_index) => image_index,
+      Err(err) if err.is_device_lost() => {
+        self.mark_device_lost();
+        return Ok(());
+      },
+      Err(err) => return Err(err.into()),
+    };
     self.data_mut().image_index = image_index;
 
+    // The uploaded buffers are used by this frame, so the transfers must be finished.
+    self.resources_mut().finish_transfers()?;
+
+    // The fence of this frame is signaled. So we can drop the retired resources safely.
+    self.resources_mut().release_retired_resources();
+
     // This image is finished. So we can get statistic data safely.
-    if self.statistics_mut().total_frames > self.resources().context.borrow().swapchain.num_of_images as u64 {
+    // The timestamps are written by the frame recording of hala-gfx, the headless frames have none.
+    if !self.resources().is_headless && self.statistics_mut().total_frames > self.resources().num_of_frames as u64 {
       let gpu_time = self.resources().context.borrow().get_gpu_frame_time(self.data().image_index)?;
       self.statistics_mut().set_gpu_time(&gpu_time);
     }
@@ -280,6 +1505,76 @@ pub trait HalaRendererTrait {
     Ok(())
   }
 
+  /// Set the present mode of the swapchain.
+  /// The swapchain is marked dirty and recreated at the next frame boundary, so the statistics are reset.
+  /// param mode: The present mode.
+  fn set_present_mode(&mut self, mode: HalaPresentMode) {
+    if mode == self.resources().present_mode && self.data().pending_present_mode.is_none() {
+      return;
+    }
+    self.data_mut().pending_present_mode = Some(mode);
+  }
+
+  /// Get the present mode of the swapchain, it includes the pending one.
+  /// return: The present mode.
+  fn get_present_mode(&self) -> HalaPresentMode {
+    self.data().pending_present_mode.unwrap_or(self.resources().present_mode)
+  }
+
+  /// Apply the pending present mode, it is called by pre_update before acquiring the image.
+  /// The requirements of the context are changed and the swapchain is marked dirty, check_and_restore_swapchain recreates it.
+  /// return: The result.
+  fn apply_pending_present_mode(&mut self) -> Result<(), HalaRendererError> {
+    let mode = match self.data_mut().pending_present_mode.take() {
+      Some(mode) => mode,
+      None => return Ok(()),
+    };
+    // The headless renderer has no swapchain to present.
+    if self.resources().is_headless {
+      return Ok(());
+    }
+
+    // The swapchain images may still be used by the frames in flight.
+    self.wait_idle()?;
+    mode.apply_to_gpu_req(&mut self.resources().context.borrow_mut().gpu_req);
+    self.resources_mut().present_mode = mode;
+    self.data_mut().is_swapchain_dirty = true;
+    log::debug!("The present mode is changed to {:?}.", mode);
+
+    Ok(())
+  }
+
+  /// Set the target frame rate of the CPU frame limiter.
+  /// The limiter sleeps in pre_update, so it also throttles the offscreen rendering and no frame is skipped.
+  /// param fps: The target frames per second, none or a non-positive value to disable the limiter.
+  fn set_target_fps(&mut self, fps: Option<f32>) {
+    self.data_mut().target_fps = fps.filter(|fps| *fps > 0.0);
+    self.data_mut().last_frame_time = None;
+  }
+
+  /// Get the target frame rate of the CPU frame limiter.
+  /// return: The target frames per second, none if the limiter is disabled.
+  fn get_target_fps(&self) -> Option<f32> {
+    self.data().target_fps
+  }
+
+  /// Sleep until the frame time of the target frame rate is elapsed since the last frame.
+  fn limit_frame_rate(&mut self) {
+    let target_fps = match self.data().target_fps {
+      Some(target_fps) => target_fps,
+      None => return,
+    };
+
+    let frame_time = std::time::Duration::from_secs_f64(1.0 / target_fps as f64);
+    if let Some(last_frame_time) = self.data().last_frame_time {
+      let elapsed = last_frame_time.elapsed();
+      if elapsed < frame_time {
+        std::thread::sleep(frame_time - elapsed);
+      }
+    }
+    self.data_mut().last_frame_time = Some(std::time::Instant::now());
+  }
+
   /// Render the renderer.
   /// return: The result.
   fn render(&mut self) -> Result<(), HalaRendererError> {
@@ -288,30 +1583,15 @@ pub trait HalaRendererTrait {
       return Ok(());
     }
 
-    let result = {
-      let mut context = self.resources().context.borrow_mut();
-
-      // Render the renderer.
-      context.submit_and_present_frame(self.data().image_index, &self.resources().graphics_command_buffers)
-    };
+    // Render the renderer, the headless frame is submitted without the present.
+    let image_index = self.data().image_index;
+    let result = self.resources_mut().submit_frame(image_index);
 
     match result {
       Ok(_) => (),
       Err(err) => {
         if err.is_device_lost() {
-          {
-            let context = self.resources().context.borrow_mut();
-            let logical_device = context.logical_device.borrow();
-            match logical_device.wait_idle() {
-              Ok(_) => (),
-              Err(err) => {
-                log::error!("Failed to wait the device idle: {}", err);
-              }
-            }
-          }
-          self.resources().graphics_command_buffers.reset(self.data().image_index, true)?;
-          log::warn!("The device is lost!");
-          self.data_mut().is_device_lost = true;
+          self.mark_device_lost();
         } else {
           return Err(err.into());
         }
@@ -321,4 +1601,272 @@ pub trait HalaRendererTrait {
     Ok(())
   }
 
-}
\ No newline at end of file
+}
+
+/// Whether the depth format has the stencil aspect.
+/// param format: The depth format.
+/// return: True if the format has the stencil aspect.
+pub(crate) fn is_stencil_format(format: hala_gfx::HalaFormat) -> bool {
+  let stencil_formats = [
+    hala_gfx::HalaFormat::D16_UNORM_S8_UINT,
+    hala_gfx::HalaFormat::D24_UNORM_S8_UINT,
+    hala_gfx::HalaFormat::D32_SFLOAT_S8_UINT,
+  ];
+  stencil_formats.contains(&format)
+}
+
+/// Get the aspect mask of the depth attachment and the layout transitions of the depth image.
+/// param format: The depth format.
+/// return: The aspect mask, it includes the stencil if the format has the stencil aspect.
+pub(crate) fn get_depth_aspect_mask(format: hala_gfx::HalaFormat) -> hala_gfx::HalaImageAspectFlags {
+  if is_stencil_format(format) {
+    hala_gfx::HalaImageAspectFlags::DEPTH | hala_gfx::HalaImageAspectFlags::STENCIL
+  } else {
+    hala_gfx::HalaImageAspectFlags::DEPTH
+  }
+}
+
+/// Create the render pass of the deferred subpasses, the G-Buffer subpass and the lighting subpass read it by the input attachments.
+/// The attachments are 0: swapchain color, 1: albedo, 2: normal, 3: swapchain depth and 4: G-Buffer depth,
+/// the framebuffers of create_deferred_framebuffers follow the same order.
+/// param context: The gfx context.
+/// param albedo_format: The format of the albedo image.
+/// param normal_format: The format of the normal image.
+/// param depth_format: The format of the G-Buffer depth image.
+/// return: The render pass.
+pub(crate) fn create_deferred_render_pass(
+  context: &HalaContext,
+  albedo_format: hala_gfx::HalaFormat,
+  normal_format: hala_gfx::HalaFormat,
+  depth_format: hala_gfx::HalaFormat,
+) -> Result<hala_gfx::HalaRenderPass, HalaRendererError> {
+  let subpasses = vec![
+    hala_gfx::HalaSubpassDescription {
+      pipeline_bind_point: hala_gfx::HalaPipelineBindPoint::GRAPHICS,
+      input_attachments: vec![],
+      color_attachments: vec![
+        hala_gfx::HalaAttachmentReference {
+          attachment: 1,
+          layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        },
+        hala_gfx::HalaAttachmentReference {
+          attachment: 2,
+          layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        },
+      ],
+      resolve_attachments: vec![],
+      depth_stencil_attachment: Some(
+        hala_gfx::HalaAttachmentReference {
+          attachment: 4,
+          layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+          aspect_mask: get_depth_aspect_mask(depth_format),
+        }
+      ),
+      preserve_attachments: vec![],
+    },
+    hala_gfx::HalaSubpassDescription {
+      pipeline_bind_point: hala_gfx::HalaPipelineBindPoint::GRAPHICS,
+      input_attachments: vec![
+        hala_gfx::HalaAttachmentReference {
+          attachment: 1,
+          layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        },
+        hala_gfx::HalaAttachmentReference {
+          attachment: 2,
+          layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        },
+        hala_gfx::HalaAttachmentReference {
+          attachment: 4,
+          layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH,
+        },
+      ],
+      color_attachments: vec![
+        hala_gfx::HalaAttachmentReference {
+          attachment: 0,
+          layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        },
+      ],
+      resolve_attachments: vec![],
+      depth_stencil_attachment: Some(
+        hala_gfx::HalaAttachmentReference {
+          attachment: 3,
+          layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+        }
+      ),
+      preserve_attachments: vec![],
+    }
+  ];
+
+  let subpass_deps = vec![
+    hala_gfx::HalaSubpassDependency {
+      src_subpass: hala_gfx::SUBPASS_EXTERNAL,
+      dst_subpass: 0,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags::BOTTOM_OF_PIPE,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | hala_gfx::HalaPipelineStageFlags::EARLY_FRAGMENT_TESTS,
+      src_access_mask: hala_gfx::HalaAccessFlags::MEMORY_READ,
+      dst_access_mask: hala_gfx::HalaAccessFlags::COLOR_ATTACHMENT_WRITE | hala_gfx::HalaAccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      dependency_flags: hala_gfx::HalaDependencyFlags::BY_REGION,
+    },
+    hala_gfx::HalaSubpassDependency {
+      src_subpass: 0,
+      dst_subpass: 1,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags::FRAGMENT_SHADER,
+      src_access_mask: hala_gfx::HalaAccessFlags::COLOR_ATTACHMENT_WRITE,
+      dst_access_mask: hala_gfx::HalaAccessFlags::INPUT_ATTACHMENT_READ,
+      dependency_flags: hala_gfx::HalaDependencyFlags::BY_REGION,
+    },
+    hala_gfx::HalaSubpassDependency {
+      src_subpass: 1,
+      dst_subpass: hala_gfx::SUBPASS_EXTERNAL,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | hala_gfx::HalaPipelineStageFlags::EARLY_FRAGMENT_TESTS,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | hala_gfx::HalaPipelineStageFlags::EARLY_FRAGMENT_TESTS,
+      src_access_mask: hala_gfx::HalaAccessFlags::COLOR_ATTACHMENT_WRITE | hala_gfx::HalaAccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      dst_access_mask: hala_gfx::HalaAccessFlags::COLOR_ATTACHMENT_WRITE | hala_gfx::HalaAccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      dependency_flags: hala_gfx::HalaDependencyFlags::BY_REGION,
+    }
+  ];
+
+  let render_pass = hala_gfx::HalaRenderPass::with_subpasses(
+    Rc::clone(&context.logical_device),
+    &[
+      HalaRenderPassAttachmentDesc::default()
+        .format(context.swapchain.format)
+        .load_op(hala_gfx::HalaAttachmentLoadOp::DONT_CARE)
+        .store_op(hala_gfx::HalaAttachmentStoreOp::STORE)
+        .initial_layout(hala_gfx::HalaImageLayout::UNDEFINED)
+        .final_layout(hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+      HalaRenderPassAttachmentDesc::default()
+        .format(albedo_format)
+        .load_op(hala_gfx::HalaAttachmentLoadOp::CLEAR)
+        .store_op(hala_gfx::HalaAttachmentStoreOp::DONT_CARE)
+        .initial_layout(hala_gfx::HalaImageLayout::UNDEFINED)
+        .final_layout(hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+      HalaRenderPassAttachmentDesc::default()
+        .format(normal_format)
+        .load_op(hala_gfx::HalaAttachmentLoadOp::CLEAR)
+        .store_op(hala_gfx::HalaAttachmentStoreOp::DONT_CARE)
+        .initial_layout(hala_gfx::HalaImageLayout::UNDEFINED)
+        .final_layout(hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+    ],
+    Some(&[
+      // The stencil of the swapchain depth is cleared for the stencil tests of the lighting subpass and the following passes.
+      HalaRenderPassAttachmentDesc::default()
+        .format(context.swapchain.depth_stencil_format)
+        .load_op(hala_gfx::HalaAttachmentLoadOp::DONT_CARE)
+        .store_op(hala_gfx::HalaAttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(if context.swapchain.has_stencil { hala_gfx::HalaAttachmentLoadOp::CLEAR } else { hala_gfx::HalaAttachmentLoadOp::DONT_CARE })
+        .stencil_store_op(if context.swapchain.has_stencil { hala_gfx::HalaAttachmentStoreOp::STORE } else { hala_gfx::HalaAttachmentStoreOp::DONT_CARE })
+        .initial_layout(hala_gfx::HalaImageLayout::UNDEFINED)
+        .final_layout(hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+      // The stencil of the G-Buffer depth is written by the G-Buffer subpass and tested by the lighting subpass.
+      HalaRenderPassAttachmentDesc::default()
+        .format(depth_format)
+        .load_op(hala_gfx::HalaAttachmentLoadOp::CLEAR)
+        .store_op(hala_gfx::HalaAttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(if is_stencil_format(depth_format) { hala_gfx::HalaAttachmentLoadOp::CLEAR } else { hala_gfx::HalaAttachmentLoadOp::DONT_CARE })
+        .stencil_store_op(hala_gfx::HalaAttachmentStoreOp::DONT_CARE)
+        .initial_layout(hala_gfx::HalaImageLayout::UNDEFINED)
+        .final_layout(hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+    ]),
+    &subpasses,
+    &subpass_deps,
+    "deferred.render_pass",
+  )?;
+
+  Ok(render_pass)
+}
+
+/// Create the framebuffers of the deferred subpasses, one for each swapchain image.
+/// They reference the swapchain image views, so they should be recreated with the swapchain.
+/// param context: The gfx context.
+/// param render_pass: The render pass of create_deferred_render_pass.
+/// param albedo_image: The albedo image.
+/// param normal_image: The normal image.
+/// param depth_image: The G-Buffer depth image.
+/// return: The framebuffers.
+pub(crate) fn create_deferred_framebuffers(
+  context: &HalaContext,
+  render_pass: &hala_gfx::HalaRenderPass,
+  albedo_image: &hala_gfx::HalaImage,
+  normal_image: &hala_gfx::HalaImage,
+  depth_image: &hala_gfx::HalaImage,
+) -> Result<hala_gfx::HalaFrameBufferSet, HalaRendererError> {
+  let mut attachments_list = Vec::with_capacity(context.swapchain.num_of_images);
+  for swapchain_image_view in context.swapchain.image_views.iter() {
+    attachments_list.push([
+      *swapchain_image_view,
+      albedo_image.view,
+      normal_image.view,
+      context.swapchain.depth_stencil_image_view,
+      depth_image.view,
+    ]);
+  }
+  let framebuffers = hala_gfx::HalaFrameBufferSet::new(
+    Rc::clone(&context.logical_device),
+    render_pass,
+    attachments_list.iter().map(|attachments| attachments.as_ref()).collect::<Vec<_>>().as_slice(),
+    context.swapchain.dims,
+    "deferred",
+  )?;
+
+  Ok(framebuffers)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_memory_budget_from_heaps() {
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    // The budget and the usage of VK_EXT_memory_budget are summed over the device local heaps.
+    let memory_budget = HalaMemoryBudget::from_heaps(&[(8 * GB, Some((6 * GB, 2 * GB))), (GB / 4, Some((GB / 4, 0)))]);
+    assert_eq!(memory_budget.used, 2 * GB);
+    assert_eq!(memory_budget.available, 4 * GB + GB / 4);
+
+    // The budget never exceeds the heap size.
+    let memory_budget = HalaMemoryBudget::from_heaps(&[(4 * GB, Some((5 * GB, GB)))]);
+    assert_eq!(memory_budget.available, 3 * GB);
+
+    // The usage of the other applications may exceed the budget.
+    let memory_budget = HalaMemoryBudget::from_heaps(&[(4 * GB, Some((2 * GB, 3 * GB)))]);
+    assert_eq!(memory_budget.used, 3 * GB);
+    assert_eq!(memory_budget.available, 0);
+
+    // Without the extension the whole heap is available.
+    let memory_budget = HalaMemoryBudget::from_heaps(&[(8 * GB, None)]);
+    assert_eq!(memory_budget.used, 0);
+    assert_eq!(memory_budget.available, 8 * GB);
+  }
+
+  #[test]
+  fn test_memory_budget_reclaim() {
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    // The retired bytes move from the usage to the available memory.
+    let mut memory_budget = HalaMemoryBudget::from_heaps(&[(8 * GB, Some((6 * GB, 2 * GB)))]);
+    memory_budget.reclaim(GB);
+    assert_eq!(memory_budget.used, GB);
+    assert_eq!(memory_budget.available, 5 * GB);
+
+    // Never reclaim more than the counted usage.
+    memory_budget.reclaim(4 * GB);
+    assert_eq!(memory_budget.used, 0);
+    assert_eq!(memory_budget.available, 6 * GB);
+
+    // Without the extension nothing is counted as used, so nothing can be reclaimed.
+    let mut memory_budget = HalaMemoryBudget::from_heaps(&[(8 * GB, None)]);
+    memory_budget.reclaim(GB);
+    assert_eq!(memory_budget.available, 8 * GB);
+  }
+
+}
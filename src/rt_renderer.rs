@@ -1,15 +1,32 @@
 use std::rc::Rc;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::Write;
 
 use hala_gfx::HalaGPURequirements;
 
 use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
 use crate::scene::{
   cpu,
   gpu,
   loader,
+  HalaMeshletConfig,
+  HalaLodConfig,
+  HalaDepthConvention,
+};
+
+use crate::texture_watcher::HalaTextureWatcher;
+use crate::tonemap::HalaToneMapType;
+use crate::color_grading::HalaColorGradingLUT;
+use crate::lens_effects::HalaLensEffects;
+use crate::color_space::{
+  HalaColorSpace,
+  HalaImageFileFormat,
+  srgb_encode,
 };
 
 use crate::renderer::{
@@ -18,8 +35,15 @@ use crate::renderer::{
   HalaRendererData,
   HalaRendererStatistics,
   HalaRendererTrait,
+  HalaDebugView,
 };
 
+/// The maximum size of the push constants, it is the minimum limit guaranteed by Vulkan.
+const MAX_PUSH_CONSTANT_SIZE: u32 = 128;
+
+/// The default max number of the emissive triangle lights.
+const DEFAULT_MAX_TRIANGLE_LIGHTS: u32 = 1 << 20;
+
 /// The type of the environment.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct HalaEnvType(u8);
@@ -40,17 +64,87 @@ impl HalaEnvType {
   }
 }
 
+/// The kind of the output image of the renderer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaImageKind(u8);
+impl HalaImageKind {
+  /// The accumulated radiance image, it is the color output of the renderer.
+  pub const ACCUM: Self = Self(0);
+  /// The albedo image.
+  pub const ALBEDO: Self = Self(1);
+  /// The normal image.
+  pub const NORMAL: Self = Self(2);
+  /// The integer ID image, the object(primitive) ID in R and the material ID in G, u32::MAX if missed.
+  pub const ID: Self = Self(3);
+  /// The UV image, the texture coordinates in RG and the barycentric coordinates in BA.
+  pub const UV: Self = Self(4);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::ACCUM,
+      1 => Self::ALBEDO,
+      2 => Self::NORMAL,
+      3 => Self::ID,
+      4 => Self::UV,
+      _ => panic!("Invalid image kind."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+}
+
+/// The CPU snapshot of an image to be saved to the file.
+struct HalaImageSnapshot {
+  path: PathBuf,
+  alpha_path: PathBuf,
+  width: u32,
+  height: u32,
+  pixels: Vec<f32>,
+  kind: HalaImageKind,
+}
+
+/// The settings to encode the image snapshots.
+#[derive(Clone, Copy)]
+struct HalaImageSaveSettings {
+  format: HalaImageFileFormat,
+  tonemap_type: HalaToneMapType,
+  color_space_matrix: glam::Mat3,
+  transparent_background: bool,
+}
+
 
+/// The per-frame uniform, it is bound at the binding 0 of the dynamic descriptor set and updated every frame.
+/// Layout(std140): resolution(vec2), frame_index, camera_index, num_of_lights, tile_offset_x, tile_offset_y, blue_noise_slice_index,
+/// num_of_triangle_lights, triangle_light_total_power.
 #[repr(C, align(4))]
 #[derive(Debug, Clone, Copy)]
 pub struct HalaGlobalUniform {
+  pub resolution: glam::Vec2,
+  pub frame_index: u32,
+  pub camera_index: u32,
+  pub num_of_lights: u32,
+  pub tile_offset_x: u32,
+  pub tile_offset_y: u32,
+  pub blue_noise_slice_index: u32,
+  pub num_of_triangle_lights: u32,
+  pub triangle_light_total_power: f32,
+}
+
+/// The settings uniform, it is bound at the binding 6 of the dynamic descriptor set.
+/// It is only updated when a setter changes the settings, new settings must be appended at the end.
+/// Layout(std140): ground_color(vec4), sky_color(vec4), max_depth, rr_depth, env_type, env_map_width, env_map_height,
+/// env_total_sum, env_rotation, env_intensity, exposure_value, enable_tonemap, enable_aces, use_simple_aces, tonemap_type,
+/// transparent_background, keep_background_color, num_of_callables, max_diffuse_depth, max_specular_depth,
+/// max_transmission_depth, glossy_roughness_threshold, blue_noise_frame_count, debug_view.
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaSettingsUniform {
   pub ground_color: glam::Vec4,
   pub sky_color: glam::Vec4,
-  pub resolution: glam::Vec2,
   pub max_depth: u32,
   pub rr_depth: u32,
-  pub frame_index: u32,
-  pub camera_index: u32,
   pub env_type: u32,
   pub env_map_width: u32,
   pub env_map_height: u32,
@@ -58,10 +152,21 @@ pub struct HalaGlobalUniform {
   pub env_rotation: f32,
   pub env_intensity: f32,
   pub exposure_value: f32,
+  // The legacy flags for the shaders which do not read tonemap_type, Uncharted 2 and AgX are Reinhard in them.
   pub enable_tonemap: u32,
   pub enable_aces: u32,
   pub use_simple_aces: u32,
-  pub num_of_lights: u32,
+  // The HalaToneMapType, the shaders apply it by ApplyToneMap of shaders/tonemap.hlsl.
+  pub tonemap_type: u32,
+  pub transparent_background: u32,
+  pub keep_background_color: u32,
+  pub num_of_callables: u32,
+  pub max_diffuse_depth: u32,
+  pub max_specular_depth: u32,
+  pub max_transmission_depth: u32,
+  pub glossy_roughness_threshold: f32,
+  pub blue_noise_frame_count: u32,
+  pub debug_view: u32,
 }
 
 /// The implementation of the renderer trait.
@@ -132,11 +237,133 @@ impl HalaRendererTrait for HalaRenderer {
     ]
   }
 
+  /// Validate the renderer before creating any GPU resource in commit.
+  /// return: The result, the errors list all problems found.
+  fn validate(&self) -> Result<(), Vec<HalaRendererError>> {
+    let mut errors = Vec::new();
+
+    match self.scene_in_gpu.as_ref() {
+      Some(scene) => {
+        if scene.camera_view_matrices.is_empty() || scene.camera_proj_matrices.is_empty() {
+          errors.push(HalaRendererError::validation("There is no camera in the scene!"));
+        }
+        if scene.tplas.is_none() {
+          errors.push(HalaRendererError::validation("The top level acceleration structure is none! Upload the scene with the ray tracing flag."));
+        }
+        if scene.samplers.len() < scene.textures.len() {
+          errors.push(HalaRendererError::validation(
+            &format!("The scene has {} textures but only {} samplers!", scene.textures.len(), scene.samplers.len())));
+        }
+        if let Some(image_index) = scene.textures.iter().find(|image_index| **image_index as usize >= scene.images.len()) {
+          errors.push(HalaRendererError::validation(
+            &format!("The texture image index {} is out of range, the scene has {} images!", image_index, scene.images.len())));
+        }
+      },
+      None => errors.push(HalaRendererError::validation("The scene in GPU is none! Call set_scene before commit.")),
+    }
+
+    if self.raygen_shaders.is_empty() {
+      errors.push(HalaRendererError::validation("There is no ray generation shader! Push one before commit."));
+    }
+    if self.miss_shaders.is_empty() {
+      errors.push(HalaRendererError::validation("There is no miss shader! Push one before commit."));
+    }
+    if self.hit_shaders.is_empty() {
+      errors.push(HalaRendererError::validation("There is no hit shader group! Push one before commit."));
+    }
+    let num_of_hit_groups = loader::HalaSceneGPUUploader::get_light_hit_group(&self.material_hit_groups) as usize + 1;
+    if !self.hit_shaders.is_empty() && self.hit_shaders.len() < num_of_hit_groups {
+      errors.push(HalaRendererError::validation(
+        &format!("The material hit groups and the light hit group need {} hit shader groups, only {} are pushed!", num_of_hit_groups, self.hit_shaders.len())));
+    }
+    if self.blue_noise_image.is_none() {
+      errors.push(HalaRendererError::validation("The blue noise image is none! Call load_blue_noise_texture before commit."));
+    }
+    if !self.material_callables.is_empty() || self.default_material_callable.is_some() {
+      for (material_type, callable_index) in self.material_callables.iter().enumerate() {
+        if let Some(callable_index) = callable_index {
+          if *callable_index as usize >= self.callable_shaders.len() {
+            errors.push(HalaRendererError::validation(
+              &format!("The callable index {} of the material type {} is out of range, only {} callable shaders are pushed!", callable_index, material_type, self.callable_shaders.len())));
+          }
+        }
+      }
+      if let Some(callable_index) = self.default_material_callable {
+        if callable_index as usize >= self.callable_shaders.len() {
+          errors.push(HalaRendererError::validation(
+            &format!("The default callable index {} is out of range, only {} callable shaders are pushed!", callable_index, self.callable_shaders.len())));
+        }
+      }
+      if let Some(scene) = self.scene_in_gpu.as_ref() {
+        let mut material_types = scene.material_types.clone();
+        material_types.sort_unstable();
+        material_types.dedup();
+        for material_type in material_types.iter() {
+          if self.get_material_callable(*material_type).is_none() {
+            errors.push(HalaRendererError::validation(
+              &format!("The material type {} in the scene has no callable shader! Call push_callable_shader_for_material or set_default_material_callable before commit.", material_type)));
+          }
+        }
+      }
+    }
+
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(errors)
+    }
+  }
+
   /// Commit all GPU resources.
   fn commit(&mut self) -> Result<(), HalaRendererError> {
+    self.validate().map_err(|errors| HalaRendererError::with_errors("The renderer is invalid:", errors))?;
+
+    // Reserve the descriptors required by the scene before creating the descriptor sets.
+    // The descriptor sets of the previous commit are replaced, so their reservations are reset.
+    self.resources.reset_descriptor_reservations()?;
+    {
+      let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+      let num_of_frames = self.resources.num_of_frames;
+      let required_sizes = [
+        (hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, (5 + scene.materials.len() + scene.primitives.len()) * num_of_frames),
+        (hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, scene.textures.len()),
+        (hala_gfx::HalaDescriptorType::STORAGE_BUFFER, if scene.triangle_lights.is_some() { 2 * num_of_frames } else { 0 }),
+        (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, if self.color_grading_file_path.is_some() { 1 } else { 0 }),
+        (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, if self.lens_file_path.is_some() { 2 } else { 0 }),
+        (hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, if self.color_grading_file_path.is_some() { 1 } else { 0 }),
+      ];
+      self.resources.reserve_descriptors(
+        &required_sizes,
+        num_of_frames + 1
+          + if self.color_grading_file_path.is_some() { 1 } else { 0 }
+          + if self.lens_file_path.is_some() { 1 } else { 0 },
+      )?;
+    }
+
+    // Retire the old material callable buffer, it may still be used by the in-flight frames.
+    if let Some(material_callable_buffer) = self.material_callable_buffer.take() {
+      self.resources.retire(Box::new(material_callable_buffer));
+    }
+
     let context = self.resources.context.borrow();
     let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
 
+    // Create the material type to callable index buffer, u32::MAX means no callable shader.
+    let num_of_material_types = scene.material_types.iter().map(|material_type| *material_type as usize + 1).max().unwrap_or(0)
+      .max(self.material_callables.len())
+      .max(1);
+    let material_callables = (0..num_of_material_types as u32)
+      .map(|material_type| [self.get_material_callable(material_type).unwrap_or(u32::MAX), 0, 0, 0])
+      .collect::<Vec<_>>();
+    let material_callable_buffer = hala_gfx::HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      std::mem::size_of_val(material_callables.as_slice()) as u64,
+      hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
+      hala_gfx::HalaMemoryLocation::CpuToGpu,
+      "material_callables.uniform_buffer",
+    )?;
+    material_callable_buffer.update_memory(0, material_callables.as_slice())?;
+
     // Create dynamic descriptor set.
     let dynamic_descriptor_set = hala_gfx::HalaDescriptorSet::new(
       Rc::clone(&context.logical_device),
@@ -179,10 +406,38 @@ impl HalaRendererTrait for HalaRenderer {
             stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT,
             binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
           },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Material type to callable index uniform buffer.
+            binding_index: 5,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT | hala_gfx::HalaShaderStageFlags::CALLABLE,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Settings uniform buffer.
+            binding_index: 6,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT | hala_gfx::HalaShaderStageFlags::CALLABLE,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Triangle light storage buffer, unbound if the scene has no emissive materials.
+            binding_index: 7,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CALLABLE,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Triangle light CDF storage buffer, unbound if the scene has no emissive materials.
+            binding_index: 8,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CALLABLE,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
         ],
         "main_dynamic.descriptor_set_layout",
       )?,
-      context.swapchain.num_of_images,
+      self.resources.num_of_frames,
       0,
       "main_dynamic.descriptor_set",
     )?;
@@ -240,10 +495,21 @@ impl HalaRendererTrait for HalaRenderer {
     };
 
     // Create pipeline.
-    let pipeline = hala_gfx::HalaRayTracingPipeline::new::<_, &hala_gfx::HalaPushConstantRange, _>(
+    let push_constant_ranges = if self.push_constant_size > 0 {
+      vec![
+        hala_gfx::HalaPushConstantRange {
+          stage_flags: Self::get_push_constant_stages(),
+          offset: 0,
+          size: self.push_constant_size,
+        },
+      ]
+    } else {
+      Vec::new()
+    };
+    let pipeline = hala_gfx::HalaRayTracingPipeline::new(
       Rc::clone(&context.logical_device),
       &[&self.static_descriptor_set.layout, &dynamic_descriptor_set.layout, &textures_descriptor_set.layout],
-      &[],
+      push_constant_ranges.as_slice(),
       self.raygen_shaders.as_slice(),
       self.miss_shaders.as_slice(),
       self.hit_shaders.as_slice(),
@@ -254,6 +520,104 @@ impl HalaRendererTrait for HalaRenderer {
       "main.pipeline",
     )?;
 
+    // Create the color grading program.
+    if let Some(color_grading_file_path) = self.color_grading_file_path.as_ref() {
+      let color_grading_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&self.resources.descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            hala_gfx::HalaDescriptorSetLayoutBinding { // Final image.
+              binding_index: 0,
+              descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+            hala_gfx::HalaDescriptorSetLayoutBinding { // LUT image.
+              binding_index: 1,
+              descriptor_type: hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+          ],
+          "color_grading.descriptor_set_layout",
+        )?,
+        0,
+        "color_grading.descriptor_set",
+      )?;
+      color_grading_descriptor_set.update_storage_images(0, 0, std::slice::from_ref(self.final_image.as_ref()));
+      if let Some(lut) = self.color_grading_lut.as_ref() {
+        color_grading_descriptor_set.update_combined_image_samplers(0, 1, &[(&lut.image, &lut.sampler)]);
+      }
+
+      let color_grading_program = HalaComputeProgram::new(
+        Rc::clone(&context.logical_device),
+        &[&color_grading_descriptor_set.layout],
+        &HalaComputeProgramDesc {
+          shader_file_path: color_grading_file_path.clone(),
+          push_constant_size: 40,
+          bindings: Vec::new(),
+        },
+        Some(&pipeline_cache),
+        "color_grading",
+      )?;
+
+      self.color_grading_descriptor_set = Some(color_grading_descriptor_set);
+      self.color_grading_program = Some(color_grading_program);
+    }
+
+    // Create the lens image and program.
+    if let Some(lens_file_path) = self.lens_file_path.as_ref() {
+      let lens_image = Self::create_lens_image(&context, self.resources.color_format)?;
+      let lens_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&self.resources.descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            hala_gfx::HalaDescriptorSetLayoutBinding { // Final image.
+              binding_index: 0,
+              descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+            hala_gfx::HalaDescriptorSetLayoutBinding { // Lens image.
+              binding_index: 1,
+              descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+          ],
+          "lens.descriptor_set_layout",
+        )?,
+        0,
+        "lens.descriptor_set",
+      )?;
+      lens_descriptor_set.update_storage_images(0, 0, std::slice::from_ref(self.final_image.as_ref()));
+      lens_descriptor_set.update_storage_images(0, 1, &[&lens_image]);
+
+      let lens_program = HalaComputeProgram::new(
+        Rc::clone(&context.logical_device),
+        &[&lens_descriptor_set.layout],
+        &HalaComputeProgramDesc {
+          shader_file_path: lens_file_path.clone(),
+          push_constant_size: 16,
+          bindings: Vec::new(),
+        },
+        Some(&pipeline_cache),
+        "lens",
+      )?;
+
+      self.lens_image = Some(lens_image);
+      self.lens_descriptor_set = Some(lens_descriptor_set);
+      self.lens_program = Some(lens_program);
+    }
+
     // Save pipeline cache.
     pipeline_cache.save("./out/pipeline_cache.bin")?;
 
@@ -316,41 +680,40 @@ impl HalaRendererTrait for HalaRenderer {
     );
     static_binding_index += 1;
 
+    self.blue_noise_image_binding_index = static_binding_index;
     let blue_noise_image = self.blue_noise_image.as_ref().ok_or(HalaRendererError::new("The blue noise image is none!", None))?;
     self.static_descriptor_set.update_sampled_images(
       0,
-      static_binding_index,
+      self.blue_noise_image_binding_index,
       std::slice::from_ref(blue_noise_image),
     );
     static_binding_index += 1;
 
-    if let Some(envmap) = self.envmap.as_ref() {
-      self.static_descriptor_set.update_combined_image_samplers(
-        0,
-        static_binding_index,
-        &[(&envmap.image, &envmap.sampler)],
-      );
-      static_binding_index += 1;
-      self.static_descriptor_set.update_sampled_images(
-        0,
-        static_binding_index,
-        &[&envmap.marginal_distribution_image, &envmap.conditional_distribution_image],
-      );
-      static_binding_index += 1;
-      self.static_descriptor_set.update_samplers(
-        0,
-        static_binding_index,
-        &[&envmap.distribution_sampler],
-      );
-      // static_binding_index += 1;
-    }
+    self.envmap_binding_index = static_binding_index;
+    self.update_envmap_bindings();
+    static_binding_index += 3;
+
+    // The AOV images follow the environment map bindings.
+    self.id_image_binding_index = static_binding_index;
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.id_image_binding_index,
+      std::slice::from_ref(&self.id_image.as_ref()),
+    );
+    static_binding_index += 1;
+    self.uv_image_binding_index = static_binding_index;
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.uv_image_binding_index,
+      std::slice::from_ref(&self.uv_image.as_ref()),
+    );
 
     // Update dynamic descriptor set.
-    for index in 0..context.swapchain.num_of_images {
+    for index in 0..self.resources.num_of_frames {
       dynamic_descriptor_set.update_uniform_buffers(
         index,
         0,
-        &[self.global_uniform_buffer.as_ref()],
+        &[self.global_uniform_buffers[index].as_ref()],
       );
       dynamic_descriptor_set.update_uniform_buffers(
         index,
@@ -372,8 +735,34 @@ impl HalaRendererTrait for HalaRenderer {
         4,
         scene.primitives.as_slice(),
       );
+      dynamic_descriptor_set.update_uniform_buffers(
+        index,
+        5,
+        &[&material_callable_buffer],
+      );
+      dynamic_descriptor_set.update_uniform_buffers(
+        index,
+        6,
+        &[self.settings_uniform_buffer.as_ref()],
+      );
+      if let (Some(triangle_lights), Some(triangle_light_cdf)) = (scene.triangle_lights.as_ref(), scene.triangle_light_cdf.as_ref()) {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          7,
+          &[triangle_lights],
+        );
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          8,
+          &[triangle_light_cdf],
+        );
+      }
     }
     self.dynamic_descriptor_set = Some(dynamic_descriptor_set);
+    self.material_callable_buffer = Some(material_callable_buffer);
+
+    // The callable count is known now.
+    self.settings_dirty = true;
 
     Ok(())
   }
@@ -388,6 +777,15 @@ impl HalaRendererTrait for HalaRenderer {
     where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>
   {
     self.pre_update(width, height)?;
+
+    // Skip the update and wait to reset the device on the next frame update.
+    if self.data.is_device_lost {
+      return Ok(());
+    }
+
+    self.stream_next_texture()?;
+    // Submit the streaming uploads enqueued since the last frame, this frame waits and acquires them.
+    self.resources.flush_uploads()?;
     let context = self.resources.context.borrow();
 
     // Skip the update if the total frames is greater than the max frames.
@@ -395,77 +793,135 @@ impl HalaRendererTrait for HalaRenderer {
       return Ok(());
     }
 
+    // Update settings uniform buffer only if the settings are changed.
+    if self.settings_dirty {
+      self.settings_uniform_buffer.update_memory(0, &[self.get_settings_uniform()])?;
+      self.settings_dirty = false;
+    }
+
     // Update global uniform buffer.
-    let (use_hdri, env_total_sum, env_map_width, env_map_height) = match self.envmap.as_ref() {
-      Some(envmap) => (true, envmap.total_luminance, envmap.image.extent.width, envmap.image.extent.height),
-      None => (false, 0f32, 0, 0),
-    };
-    let num_of_lights = if let Some(scene_in_gpu) = self.scene_in_gpu.as_ref() {
-      scene_in_gpu.light_data.len() as u32
+    let (num_of_lights, num_of_triangle_lights, triangle_light_total_power) = if let Some(scene_in_gpu) = self.scene_in_gpu.as_ref() {
+      (scene_in_gpu.light_data.len() as u32, scene_in_gpu.num_of_triangle_lights, scene_in_gpu.triangle_light_total_power)
     } else {
-      0
+      (0, 0, 0.0)
     };
-    self.global_uniform_buffer.update_memory(0, &[HalaGlobalUniform {
-      ground_color: self.env_ground_color,
-      sky_color: self.env_sky_color,
+    let (render_x, render_y, render_width, render_height) = self.get_render_rect();
+    // Each frame traces one primary ray per pixel of the render area.
+    self.statistics.set_rays_per_frame(render_width as u64 * render_height as u64);
+    let global_uniform = HalaGlobalUniform {
       resolution: glam::Vec2::new(self.info.width as f32, self.info.height as f32),
-      max_depth: self.max_depth,
-      rr_depth: self.rr_depth,
       frame_index: (self.statistics.total_frames - 1) as u32,
       camera_index: 0,
-      env_type: if use_hdri { HalaEnvType::MAP.to_u8() as u32 } else { HalaEnvType::SKY.to_u8() as u32 },
-      env_map_width,
-      env_map_height,
-      env_total_sum,
-      env_rotation: self.env_rotation / 360f32,
-      env_intensity: self.env_intensity,
-      exposure_value: self.exposure_value,
-      enable_tonemap: self.enable_tonemap as u32,
-      enable_aces: self.enable_aces as u32,
-      use_simple_aces: self.use_simple_aces as u32,
       num_of_lights,
-    }])?;
+      tile_offset_x: render_x,
+      tile_offset_y: render_y,
+      blue_noise_slice_index: (self.statistics.total_frames - 1) as u32 % self.blue_noise_frame_count.max(1),
+      num_of_triangle_lights,
+      triangle_light_total_power,
+    };
+    // Only write the slot of the current image, the other slots may still be read by the in-flight frames.
+    self.global_uniform_buffers[self.data.image_index].update_memory(0, &[global_uniform])?;
+
+    // Trace the tiles with separate submissions.
+    let use_tiles = self.use_tiles();
+    if use_tiles {
+      match self.trace_tiles(&context, global_uniform) {
+        Ok(_) => (),
+        Err(err) => {
+          if err.is_device_lost() {
+            log::warn!("The device is lost!");
+            self.data.is_device_lost = true;
+            return Ok(());
+          } else {
+            return Err(err.into());
+          }
+        }
+      }
+    }
 
-    // Update the renderer.
-    context.record_graphics_command_buffer(
-      self.data.image_index,
-      &self.resources.graphics_command_buffers,
-      None,
-      None,
-      None,
-      |index, command_buffers| {
-        ui_fn(index, command_buffers)?;
+    // The lens image replaces the final image as the source of the swapchain copy.
+    let use_lens = self.is_lens_active();
+    let present_image = match (use_lens, self.lens_image.as_ref()) {
+      (true, Some(lens_image)) => lens_image,
+      _ => &self.final_image,
+    };
 
-        Ok(())
-      },
-      Some(&self.final_image),
-      |index, command_buffers| {
-        let _pipline = self.pipeline.as_ref().ok_or(hala_gfx::HalaGfxError::new("The pipeline is none!", None))?;
-        let _sbt = self.sbt.as_ref().ok_or(hala_gfx::HalaGfxError::new("The shader binding table is none!", None))?;
+    // Trace the rays, grade and apply the lens effects to the final image.
+    let record_fn = |index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet| -> Result<bool, hala_gfx::HalaGfxError> {
+      // The shared buffers released by the async compute work and the buffers uploaded by the transfer queue are acquired before any pass reads them.
+      self.resources.record_async_compute_acquire(index, command_buffers);
+      self.resources.record_transfer_acquire(index, command_buffers);
+
+      // The tiles are already traced, only grade and copy the final image.
+      if use_tiles {
+        self.apply_color_grading(index, command_buffers, (render_x, render_y, render_width, render_height))?;
+        if use_lens {
+          self.apply_lens_effects(index, command_buffers)?;
+        }
+        self.resources.record_async_compute_release(index, command_buffers);
+        return Ok(true);
+      }
 
-        command_buffers.bind_ray_tracing_pipeline(index, _pipline);
-        command_buffers.bind_ray_tracing_descriptor_sets(
-          index,
-          _pipline,
-          0,
-          &[
-            self.static_descriptor_set.as_ref(),
-            self.dynamic_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The dynamic descriptor set is none!", None))?,
-            self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?,
-          ],
-          &[],
-        );
-        command_buffers.trace_rays(
-          index,
-          _sbt,
-          self.info.width,
-          self.info.height,
-          1,
-        );
+      let _pipline = self.pipeline.as_ref().ok_or(hala_gfx::HalaGfxError::new("The pipeline is none!", None))?;
+      let _sbt = self.sbt.as_ref().ok_or(hala_gfx::HalaGfxError::new("The shader binding table is none!", None))?;
 
-        Ok(true)
-      },
-    )?;
+      command_buffers.bind_ray_tracing_pipeline(index, _pipline);
+      command_buffers.bind_ray_tracing_descriptor_sets(
+        index,
+        _pipline,
+        0,
+        &[
+          self.static_descriptor_set.as_ref(),
+          self.dynamic_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The dynamic descriptor set is none!", None))?,
+          self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?,
+        ],
+        &[],
+      );
+      self.push_constants(index, command_buffers, _pipline);
+      command_buffers.trace_rays(
+        index,
+        _sbt,
+        render_width,
+        render_height,
+        1,
+      );
+      self.apply_color_grading(index, command_buffers, (render_x, render_y, render_width, render_height))?;
+      if use_lens {
+        self.apply_lens_effects(index, command_buffers)?;
+      }
+
+      // The shared buffers are released to the async compute work submitted after this frame,
+      // the swapchain copy and the UI after the trace do not access them.
+      self.resources.record_async_compute_release(index, command_buffers);
+
+      Ok(true)
+    };
+
+    // Update the renderer.
+    // The headless frame has no swapchain image to copy the final image to and to draw the UI on.
+    if self.resources.is_headless {
+      let index = self.data.image_index;
+      let command_buffers = &self.resources.graphics_command_buffers;
+      command_buffers.reset(index, false)?;
+      command_buffers.begin(index, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+      record_fn(index, command_buffers)?;
+      command_buffers.end(index)?;
+    } else {
+      context.record_graphics_command_buffer(
+        self.data.image_index,
+        &self.resources.graphics_command_buffers,
+        None,
+        None,
+        None,
+        |index, command_buffers| {
+          ui_fn(index, command_buffers)?;
+
+          Ok(())
+        },
+        Some(present_image),
+        record_fn,
+      )?;
+    }
 
     Ok(())
   }
@@ -473,8 +929,6 @@ impl HalaRendererTrait for HalaRenderer {
   /// Render the renderer.
   /// return: The result.
   fn render(&mut self) -> Result<(), HalaRendererError> {
-    let mut context = self.resources.context.borrow_mut();
-
     // Skip the rendering and wait to reset the device on the next frame update.
     if self.data.is_device_lost {
       return Ok(());
@@ -485,8 +939,9 @@ impl HalaRendererTrait for HalaRenderer {
       return Ok(());
     }
 
-    // Render the renderer.
-    match context.submit_and_present_frame(self.data.image_index, &self.resources.graphics_command_buffers) {
+    // Render the renderer, the headless frame is submitted without the present.
+    let result = self.resources.submit_frame(self.data.image_index);
+    match result {
       Ok(_) => (),
       Err(err) => {
         if err.is_device_lost() {
@@ -498,6 +953,13 @@ impl HalaRendererTrait for HalaRenderer {
       }
     }
 
+    // Report the progress after the frame is accumulated.
+    if !self.data.is_device_lost {
+      if let Some(callback) = self.frame_callback.as_mut() {
+        callback(self.statistics.total_frames, self.max_frames, self.statistics.get_variance_reduction());
+      }
+    }
+
     Ok(())
   }
 
@@ -506,62 +968,32 @@ impl HalaRendererTrait for HalaRenderer {
   /// param height: The height of the swapchain.
   /// return: The result.
   fn check_and_restore_device(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
-    let mut context = self.resources.context.borrow_mut();
-
-    if self.data.is_device_lost {
-      context.reset_swapchain(width, height)?;
+    if self.data.is_device_lost || self.data.is_swapchain_dirty {
+      if !self.resources.is_headless {
+        self.resources.context.borrow_mut().reset_swapchain(width, height)?;
+      }
 
       self.info.width = width;
       self.info.height = height;
-      unsafe {
-        std::mem::ManuallyDrop::drop(&mut self.host_accessible_buffer);
-        std::mem::ManuallyDrop::drop(&mut self.normal_image);
-        std::mem::ManuallyDrop::drop(&mut self.albedo_image);
-        std::mem::ManuallyDrop::drop(&mut self.accum_image);
-        std::mem::ManuallyDrop::drop(&mut self.final_image);
-      }
-      let (
-        final_image,
-        accum_image,
-        albedo_image,
-        normal_image,
-        host_accessible_buffer,
-      ) = Self::create_storage_images(&context)?;
-      self.final_image = std::mem::ManuallyDrop::new(final_image);
-      self.accum_image = std::mem::ManuallyDrop::new(accum_image);
-      self.albedo_image = std::mem::ManuallyDrop::new(albedo_image);
-      self.normal_image = std::mem::ManuallyDrop::new(normal_image);
-      self.host_accessible_buffer = std::mem::ManuallyDrop::new(host_accessible_buffer);
-
-      self.static_descriptor_set.update_storage_images(
-        0,
-        self.final_image_binding_index,
-        std::slice::from_ref(self.final_image.as_ref()),
-      );
-      self.static_descriptor_set.update_storage_images(
-        0,
-        self.accum_image_binding_index,
-        std::slice::from_ref(&self.accum_image.as_ref()),
-      );
-      self.static_descriptor_set.update_storage_images(
-        0,
-        self.albedo_image_binding_index,
-        std::slice::from_ref(&self.albedo_image.as_ref()),
-      );
-      self.static_descriptor_set.update_storage_images(
-        0,
-        self.normal_image_binding_index,
-        std::slice::from_ref(&self.normal_image.as_ref()),
-      );
+      self.recreate_storage_images()?;
 
       self.statistics.reset();
 
       self.data.is_device_lost = false;
+      self.data.is_swapchain_dirty = false;
     }
 
     Ok(())
   }
 
+  /// Recreate the storage images and the host accessible buffer with the new size, the accumulation restarts.
+  /// param width: The width of the swapchain.
+  /// param height: The height of the swapchain.
+  /// return: The result.
+  fn on_resize(&mut self, _width: u32, _height: u32) -> Result<(), HalaRendererError> {
+    self.recreate_storage_images()
+  }
+
 }
 
 /// The ray tracing renderer.
@@ -571,15 +1003,21 @@ pub struct HalaRenderer {
 
   pub(crate) max_depth: u32,
   pub(crate) rr_depth: u32,
+  pub(crate) max_diffuse_depth: u32,
+  pub(crate) max_specular_depth: u32,
+  pub(crate) max_transmission_depth: u32,
+  pub(crate) glossy_roughness_threshold: f32,
   pub(crate) exposure_value: f32,
-  pub(crate) enable_tonemap: bool,
-  pub(crate) enable_aces: bool,
-  pub(crate) use_simple_aces: bool,
+  pub(crate) tonemap_type: HalaToneMapType,
+  pub(crate) working_color_space: HalaColorSpace,
+  pub(crate) display_color_space: HalaColorSpace,
   pub(crate) max_frames: u64,
 
   pub(crate) static_descriptor_set: hala_gfx::HalaDescriptorSet,
   pub(crate) dynamic_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
-  pub(crate) global_uniform_buffer: hala_gfx::HalaBuffer,
+  pub(crate) global_uniform_buffers: Vec<hala_gfx::HalaBuffer>,
+  pub(crate) settings_uniform_buffer: hala_gfx::HalaBuffer,
+  pub(crate) settings_dirty: bool,
   pub(crate) final_image: std::mem::ManuallyDrop<hala_gfx::HalaImage>,
   pub(crate) final_image_binding_index: u32,
   pub(crate) accum_image: std::mem::ManuallyDrop<hala_gfx::HalaImage>,
@@ -588,22 +1026,60 @@ pub struct HalaRenderer {
   pub(crate) albedo_image_binding_index: u32,
   pub(crate) normal_image: std::mem::ManuallyDrop<hala_gfx::HalaImage>,
   pub(crate) normal_image_binding_index: u32,
+  pub(crate) id_image: std::mem::ManuallyDrop<hala_gfx::HalaImage>,
+  pub(crate) id_image_binding_index: u32,
+  pub(crate) uv_image: std::mem::ManuallyDrop<hala_gfx::HalaImage>,
+  pub(crate) uv_image_binding_index: u32,
 
   pub(crate) raygen_shaders: Vec<hala_gfx::HalaShader>,
   pub(crate) miss_shaders: Vec<hala_gfx::HalaShader>,
   pub(crate) hit_shaders: Vec<(Option<hala_gfx::HalaShader>, Option<hala_gfx::HalaShader>, Option<hala_gfx::HalaShader>)>,
   pub(crate) callable_shaders: Vec<hala_gfx::HalaShader>,
+  pub(crate) material_hit_groups: Vec<u32>,
+  pub(crate) material_callables: Vec<Option<u32>>,
+  pub(crate) default_material_callable: Option<u32>,
+  pub(crate) material_callable_buffer: Option<hala_gfx::HalaBuffer>,
+  pub(crate) push_constant_size: u32,
+  pub(crate) push_constant_data: Vec<u8>,
   pub(crate) pipeline: Option<hala_gfx::HalaRayTracingPipeline>,
   pub(crate) sbt: Option<hala_gfx::HalaShaderBindingTable>,
 
   pub(crate) blue_noise_image: Option<hala_gfx::HalaImage>,
+  pub(crate) blue_noise_image_binding_index: u32,
+  pub(crate) blue_noise_frame_count: u32,
+  pub(crate) debug_view: HalaDebugView,
   pub(crate) scene_in_gpu: Option<gpu::HalaScene>,
+  // The visibility and the override material index of each mesh, the meshes out of range are visible with their own materials.
+  pub(crate) mesh_visibilities: Vec<bool>,
+  pub(crate) mesh_material_overrides: Vec<Option<u32>>,
+  pub(crate) stream_textures: bool,
+  pub(crate) max_triangle_lights: u32,
 
   pub(crate) envmap: Option<crate::envmap::EnvMap>,
+  pub(crate) envmap_binding_index: u32,
   pub(crate) env_rotation: f32,
   pub(crate) env_ground_color: glam::Vec4,
   pub(crate) env_sky_color: glam::Vec4,
   pub(crate) env_intensity: f32,
+  pub(crate) transparent_background: bool,
+  pub(crate) keep_background_color: bool,
+
+  pub(crate) tile_size: Option<(u32, u32)>,
+  pub(crate) region_of_interest: Option<(u32, u32, u32, u32)>,
+  pub(crate) tile_command_buffers: Option<hala_gfx::HalaCommandBufferSet>,
+
+  // The color grading pass applies the LUT to the tone mapped final image in place before it is copied to the swapchain.
+  pub(crate) color_grading_file_path: Option<String>,
+  pub(crate) color_grading_lut: Option<HalaColorGradingLUT>,
+  pub(crate) color_grading_program: Option<HalaComputeProgram>,
+  pub(crate) color_grading_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
+
+  // The lens pass reads the final image and writes the lens image, which is copied to the swapchain instead of the final image.
+  pub(crate) lens_effects: HalaLensEffects,
+  pub(crate) lens_file_path: Option<String>,
+  pub(crate) lens_program: Option<HalaComputeProgram>,
+  pub(crate) lens_image: Option<hala_gfx::HalaImage>,
+  pub(crate) lens_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
 
   pub(crate) textures_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
 
@@ -611,6 +1087,7 @@ pub struct HalaRenderer {
 
   pub(crate) data: HalaRendererData,
   pub(crate) statistics: HalaRendererStatistics,
+  pub(crate) frame_callback: Option<Box<dyn FnMut(u64, u64, f32)>>,
 
   pub(crate) resources: HalaRendererResources,
 
@@ -622,6 +1099,8 @@ impl Drop for HalaRenderer {
   fn drop(&mut self) {
     unsafe {
       std::mem::ManuallyDrop::drop(&mut self.host_accessible_buffer);
+      std::mem::ManuallyDrop::drop(&mut self.uv_image);
+      std::mem::ManuallyDrop::drop(&mut self.id_image);
       std::mem::ManuallyDrop::drop(&mut self.normal_image);
       std::mem::ManuallyDrop::drop(&mut self.albedo_image);
       std::mem::ManuallyDrop::drop(&mut self.accum_image);
@@ -641,9 +1120,9 @@ impl HalaRenderer {
   /// param window: The window of the renderer.
   /// param max_depth: The max depth of the ray tracing.
   /// param rr_depth: The Russian Roulette depth of the ray tracing.
-  /// param enable_tonemap: Enable the tonemap or not.
-  /// param enable_aces: Enable the ACES tonemap or not.
-  /// param use_simple_aces: Use the simple ACES tonemap or not.
+  /// param enable_tonemap: Enable the tonemap or not(Deprecated, use set_tonemap_type instead).
+  /// param enable_aces: Enable the ACES tonemap or not(Deprecated, use set_tonemap_type instead).
+  /// param use_simple_aces: Use the simple ACES tonemap or not(Deprecated, use set_tonemap_type instead).
   /// param max_frames: The max frames of the renderer.
   /// return: The renderer.
   #[allow(clippy::too_many_arguments)]
@@ -658,9 +1137,6 @@ impl HalaRenderer {
     use_simple_aces: bool,
     max_frames: u64,
   ) -> Result<Self, HalaRendererError> {
-    let width = gpu_req.width;
-    let height = gpu_req.height;
-
     let resources = HalaRendererResources::new(
       name,
       gpu_req,
@@ -668,11 +1144,87 @@ impl HalaRenderer {
       &Self::get_descriptor_sizes(),
     )?;
 
-    let static_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
-      Rc::clone(&resources.context.borrow().logical_device),
-      Rc::clone(&resources.descriptor_pool),
-      hala_gfx::HalaDescriptorSetLayout::new(
-        Rc::clone(&resources.context.borrow().logical_device),
+    Self::with_resources(
+      name,
+      gpu_req,
+      resources,
+      max_depth,
+      rr_depth,
+      HalaToneMapType::from_flags(enable_tonemap, enable_aces, use_simple_aces),
+      max_frames,
+    )
+  }
+
+  /// Create a new renderer without the window, e.g. for the image regression tests and the batch path tracing.
+  /// The frames are traced into the final image, the UI function of update is not called and render submits without the present.
+  /// Read the result with save_images, the tone map is the fitted ACES until set_tonemap_type is called.
+  /// param name: The name of the renderer.
+  /// param gpu_req: The GPU requirements of the renderer, the width and the height are the size of the images.
+  /// param max_depth: The max depth of the ray tracing.
+  /// param rr_depth: The Russian Roulette depth of the ray tracing.
+  /// param max_frames: The max frames of the renderer.
+  /// return: The renderer.
+  pub fn new_headless(
+    name: &str,
+    gpu_req: &HalaGPURequirements,
+    max_depth: u32,
+    rr_depth: u32,
+    max_frames: u64,
+  ) -> Result<Self, HalaRendererError> {
+    let resources = HalaRendererResources::new_headless(
+      name,
+      gpu_req,
+      &Self::get_descriptor_sizes(),
+    )?;
+
+    Self::with_resources(
+      name,
+      gpu_req,
+      resources,
+      max_depth,
+      rr_depth,
+      HalaToneMapType::ACES_FITTED,
+      max_frames,
+    )
+  }
+
+  /// Create a new renderer with the renderer resources.
+  /// param name: The name of the renderer.
+  /// param gpu_req: The GPU requirements of the renderer.
+  /// param resources: The renderer resources.
+  /// param max_depth: The max depth of the ray tracing.
+  /// param rr_depth: The Russian Roulette depth of the ray tracing.
+  /// param tonemap_type: The tone map type.
+  /// param max_frames: The max frames of the renderer.
+  /// return: The renderer.
+  fn with_resources(
+    name: &str,
+    gpu_req: &HalaGPURequirements,
+    mut resources: HalaRendererResources,
+    max_depth: u32,
+    rr_depth: u32,
+    tonemap_type: HalaToneMapType,
+    max_frames: u64,
+  ) -> Result<Self, HalaRendererError> {
+    let width = gpu_req.width;
+    let height = gpu_req.height;
+
+    resources.reserve_descriptors(
+      &[
+        (hala_gfx::HalaDescriptorType::ACCELERATION_STRUCTURE, 1),
+        (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, 6),
+        (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, 3),
+        (hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+        (hala_gfx::HalaDescriptorType::SAMPLER, 1),
+      ],
+      1,
+    )?;
+
+    let static_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+      Rc::clone(&resources.context.borrow().logical_device),
+      Rc::clone(&resources.descriptor_pool),
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&resources.context.borrow().logical_device),
         &[
           hala_gfx::HalaDescriptorSetLayoutBinding {  // Acceleration structure.
             binding_index: 0,
@@ -737,6 +1289,20 @@ impl HalaRenderer {
             stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CALLABLE,
             binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
           },
+          hala_gfx::HalaDescriptorSetLayoutBinding {  // ID image.
+            binding_index: 9,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding {  // UV image.
+            binding_index: 10,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::RAYGEN | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
         ],
         "main_static.descriptor_set_layout",
       )?,
@@ -744,13 +1310,25 @@ impl HalaRenderer {
       "main_static.descriptor_set",
     )?;
 
-    // Create global uniform buffer.
-    let uniform_buffer = hala_gfx::HalaBuffer::new(
+    // Create global uniform buffers, one for each swapchain image.
+    let mut global_uniform_buffers = Vec::with_capacity(resources.num_of_frames);
+    for index in 0..resources.num_of_frames {
+      global_uniform_buffers.push(hala_gfx::HalaBuffer::new(
+        Rc::clone(&resources.context.borrow().logical_device),
+        std::mem::size_of::<HalaGlobalUniform>() as u64,
+        hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
+        hala_gfx::HalaMemoryLocation::CpuToGpu,
+        &format!("global_{}.uniform_buffer", index),
+      )?);
+    }
+
+    // Create settings uniform buffer.
+    let settings_uniform_buffer = hala_gfx::HalaBuffer::new(
       Rc::clone(&resources.context.borrow().logical_device),
-      std::mem::size_of::<HalaGlobalUniform>() as u64,
+      std::mem::size_of::<HalaSettingsUniform>() as u64,
       hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
       hala_gfx::HalaMemoryLocation::CpuToGpu,
-      "global.uniform_buffer",
+      "settings.uniform_buffer",
     )?;
 
     // Create storage image.
@@ -759,8 +1337,10 @@ impl HalaRenderer {
       accum_image,
       albedo_image,
       normal_image,
+      id_image,
+      uv_image,
       host_accessible_buffer,
-    ) = Self::create_storage_images(&resources.context.borrow())?;
+    ) = Self::create_storage_images(&resources.context.borrow(), resources.color_format)?;
 
     // Return the renderer.
     log::debug!("A HalaRenderer \"{}\"[{} x {}] is created.", name, width, height);
@@ -768,16 +1348,22 @@ impl HalaRenderer {
       info: HalaRendererInfo::new(name, width, height),
       max_depth,
       rr_depth,
-      enable_tonemap,
-      enable_aces,
-      use_simple_aces,
+      max_diffuse_depth: u32::MAX,
+      max_specular_depth: u32::MAX,
+      max_transmission_depth: u32::MAX,
+      glossy_roughness_threshold: 1.0,
+      tonemap_type,
+      working_color_space: HalaColorSpace::SRGB,
+      display_color_space: HalaColorSpace::SRGB,
       max_frames: if max_frames == 0 { u64::MAX } else { max_frames },
 
       resources,
 
       static_descriptor_set,
       dynamic_descriptor_set: None,
-      global_uniform_buffer: uniform_buffer,
+      global_uniform_buffers,
+      settings_uniform_buffer,
+      settings_dirty: true,
       final_image: std::mem::ManuallyDrop::new(final_image),
       final_image_binding_index: 0,
       accum_image: std::mem::ManuallyDrop::new(accum_image),
@@ -786,19 +1372,54 @@ impl HalaRenderer {
       albedo_image_binding_index: 0,
       normal_image: std::mem::ManuallyDrop::new(normal_image),
       normal_image_binding_index: 0,
+      id_image: std::mem::ManuallyDrop::new(id_image),
+      id_image_binding_index: 0,
+      uv_image: std::mem::ManuallyDrop::new(uv_image),
+      uv_image_binding_index: 0,
       raygen_shaders: Vec::new(),
       miss_shaders: Vec::new(),
       hit_shaders: Vec::new(),
       callable_shaders: Vec::new(),
+      material_hit_groups: Vec::new(),
+      material_callables: Vec::new(),
+      default_material_callable: None,
+      material_callable_buffer: None,
+      push_constant_size: 0,
+      push_constant_data: Vec::new(),
       pipeline: None,
       sbt: None,
       blue_noise_image: None,
+      blue_noise_image_binding_index: 0,
+      blue_noise_frame_count: 0,
+      debug_view: HalaDebugView::NONE,
       scene_in_gpu: None,
+      mesh_visibilities: Vec::new(),
+      mesh_material_overrides: Vec::new(),
+      stream_textures: false,
+      max_triangle_lights: DEFAULT_MAX_TRIANGLE_LIGHTS,
       envmap: None,
+      envmap_binding_index: 0,
       env_rotation: 0.0,
       env_ground_color: glam::Vec4::new(1.0, 1.0, 1.0, 1.0),
       env_sky_color: glam::Vec4::new(0.5, 0.7, 1.0, 1.0),
       env_intensity: 1.0,
+      transparent_background: false,
+      keep_background_color: false,
+
+      tile_size: None,
+      region_of_interest: None,
+      tile_command_buffers: None,
+
+      color_grading_file_path: None,
+      color_grading_lut: None,
+      color_grading_program: None,
+      color_grading_descriptor_set: None,
+
+      lens_effects: HalaLensEffects::default(),
+      lens_file_path: None,
+      lens_program: None,
+      lens_image: None,
+      lens_descriptor_set: None,
 
       exposure_value: 1.0,
 
@@ -809,19 +1430,103 @@ impl HalaRenderer {
       data: HalaRendererData::new(),
 
       statistics: HalaRendererStatistics::new(),
+      frame_callback: None,
     })
   }
 
+  /// Recreate the storage images and the host accessible buffer with the swapchain size, and rebind them in the static descriptor set.
+  /// The device must be idle.
+  /// return: The result.
+  fn recreate_storage_images(&mut self) -> Result<(), HalaRendererError> {
+    let (
+      final_image,
+      accum_image,
+      albedo_image,
+      normal_image,
+      id_image,
+      uv_image,
+      host_accessible_buffer,
+    ) = Self::create_storage_images(&self.resources.context.borrow(), self.resources.color_format)?;
+
+    // The new images are created first, so the old ones are never dropped twice if the creation fails.
+    unsafe {
+      std::mem::ManuallyDrop::drop(&mut self.host_accessible_buffer);
+      std::mem::ManuallyDrop::drop(&mut self.uv_image);
+      std::mem::ManuallyDrop::drop(&mut self.id_image);
+      std::mem::ManuallyDrop::drop(&mut self.normal_image);
+      std::mem::ManuallyDrop::drop(&mut self.albedo_image);
+      std::mem::ManuallyDrop::drop(&mut self.accum_image);
+      std::mem::ManuallyDrop::drop(&mut self.final_image);
+    }
+    self.final_image = std::mem::ManuallyDrop::new(final_image);
+    self.accum_image = std::mem::ManuallyDrop::new(accum_image);
+    self.albedo_image = std::mem::ManuallyDrop::new(albedo_image);
+    self.normal_image = std::mem::ManuallyDrop::new(normal_image);
+    self.id_image = std::mem::ManuallyDrop::new(id_image);
+    self.uv_image = std::mem::ManuallyDrop::new(uv_image);
+    self.host_accessible_buffer = std::mem::ManuallyDrop::new(host_accessible_buffer);
+
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.final_image_binding_index,
+      std::slice::from_ref(self.final_image.as_ref()),
+    );
+    if let Some(color_grading_descriptor_set) = self.color_grading_descriptor_set.as_ref() {
+      color_grading_descriptor_set.update_storage_images(0, 0, std::slice::from_ref(self.final_image.as_ref()));
+    }
+    if let Some(lens_descriptor_set) = self.lens_descriptor_set.as_ref() {
+      let lens_image = Self::create_lens_image(&self.resources.context.borrow(), self.resources.color_format)?;
+      lens_descriptor_set.update_storage_images(0, 0, std::slice::from_ref(self.final_image.as_ref()));
+      lens_descriptor_set.update_storage_images(0, 1, &[&lens_image]);
+      self.lens_image = Some(lens_image);
+    }
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.accum_image_binding_index,
+      std::slice::from_ref(&self.accum_image.as_ref()),
+    );
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.albedo_image_binding_index,
+      std::slice::from_ref(&self.albedo_image.as_ref()),
+    );
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.normal_image_binding_index,
+      std::slice::from_ref(&self.normal_image.as_ref()),
+    );
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.id_image_binding_index,
+      std::slice::from_ref(&self.id_image.as_ref()),
+    );
+    self.static_descriptor_set.update_storage_images(
+      0,
+      self.uv_image_binding_index,
+      std::slice::from_ref(&self.uv_image.as_ref()),
+    );
+    if let Some(blue_noise_image) = self.blue_noise_image.as_ref() {
+      self.static_descriptor_set.update_sampled_images(
+        0,
+        self.blue_noise_image_binding_index,
+        std::slice::from_ref(blue_noise_image),
+      );
+    }
+
+    Ok(())
+  }
+
   /// Create storage images.
   /// param context: The context.
+  /// param color_format: The format of the final image, the swapchain format or the headless color format.
   /// return: The result(final_image, accum_image, albedo_image, normal_image).
-  fn create_storage_images(context: &hala_gfx::HalaContext)
-    -> Result<(hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaBuffer), HalaRendererError>
+  fn create_storage_images(context: &hala_gfx::HalaContext, color_format: hala_gfx::HalaFormat)
+    -> Result<(hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaImage, hala_gfx::HalaBuffer), HalaRendererError>
   {
     let final_image = hala_gfx::HalaImage::new_2d(
       Rc::clone(&context.logical_device),
       hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
-      context.swapchain.format,
+      color_format,
       context.gpu_req.width,
       context.gpu_req.height,
       1,
@@ -862,6 +1567,28 @@ impl HalaRenderer {
       hala_gfx::HalaMemoryLocation::GpuOnly,
       "normal.image",
     )?;
+    let id_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+      hala_gfx::HalaFormat::R32G32B32A32_UINT,
+      context.gpu_req.width,
+      context.gpu_req.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "id.image",
+    )?;
+    let uv_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+      hala_gfx::HalaFormat::R32G32B32A32_SFLOAT,
+      context.gpu_req.width,
+      context.gpu_req.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "uv.image",
+    )?;
 
     let host_accessible_buffer = hala_gfx::HalaBuffer::new(
       Rc::clone(&context.logical_device),
@@ -884,7 +1611,7 @@ impl HalaRenderer {
 
       command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
 
-      let images = [final_image.raw, accum_image.raw, albedo_image.raw, normal_image.raw];
+      let images = [final_image.raw, accum_image.raw, albedo_image.raw, normal_image.raw, id_image.raw, uv_image.raw];
       for image in images.into_iter() {
         command_buffers.set_image_barriers(
           0,
@@ -913,7 +1640,7 @@ impl HalaRenderer {
       context.logical_device.borrow().graphics_wait(0)?;
     }
 
-    Ok((final_image, accum_image, albedo_image, normal_image, host_accessible_buffer))
+    Ok((final_image, accum_image, albedo_image, normal_image, id_image, uv_image, host_accessible_buffer))
   }
 
   /// Push a general shader to the renderer.
@@ -994,6 +1721,47 @@ impl HalaRenderer {
     Ok(())
   }
 
+  /// Push a callable shader with file for the material type.
+  /// The callable index of the material type is uploaded to the shader at commit.
+  /// param material_type: The material type.
+  /// param file_path: The shader file path.
+  /// param debug_name: The debug name.
+  /// return: The result.
+  pub fn push_callable_shader_for_material(
+    &mut self,
+    material_type: cpu::material::HalaMaterialType,
+    file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    self.push_general_shader_with_file(
+      file_path,
+      hala_gfx::HalaShaderStageFlags::CALLABLE,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      debug_name,
+    )?;
+
+    let material_type = material_type.to_u8() as usize;
+    if self.material_callables.len() <= material_type {
+      self.material_callables.resize(material_type + 1, None);
+    }
+    self.material_callables[material_type] = Some(self.callable_shaders.len() as u32 - 1);
+
+    Ok(())
+  }
+
+  /// Set the callable shader used by the material types without a registered callable shader.
+  /// param callable_index: The index of the callable shader.
+  pub fn set_default_material_callable(&mut self, callable_index: u32) {
+    self.default_material_callable = Some(callable_index);
+  }
+
+  /// Get the callable shader index of the material type.
+  /// param material_type: The material type.
+  /// return: The callable shader index, none if the material type has no callable shader.
+  fn get_material_callable(&self, material_type: u32) -> Option<u32> {
+    self.material_callables.get(material_type as usize).copied().flatten().or(self.default_material_callable)
+  }
+
   /// Push a hit shaders to the renderer.
   /// param closest_code: The compiled closest hit shader code.
   /// param any_code: The compiled any hit shader code.
@@ -1111,38 +1879,156 @@ impl HalaRenderer {
     Ok(())
   }
 
-  /// Load blue noise texture.
+  /// Set the callback invoked after each frame is accumulated.
+  /// param callback: The callback receives the total frames, the max frames(u64::MAX if unlimited) and the variance reduction(1 / N) of the accumulation.
+  pub fn set_frame_callback<F>(&mut self, callback: F)
+    where F: FnMut(u64, u64, f32) + 'static
+  {
+    self.frame_callback = Some(Box::new(callback));
+  }
+
+  /// Clear the frame callback.
+  pub fn clear_frame_callback(&mut self) {
+    self.frame_callback = None;
+  }
+
+  /// Set the size of the push constants of the ray tracing pipeline.
+  /// The push constants are visible in the ray generation, miss, closest hit and callable shaders.
+  /// Must be called before commit, 0 means no push constants.
+  /// param size: The size of the push constants in bytes, it must be a multiple of 4.
+  /// return: The result.
+  pub fn set_push_constant_size(&mut self, size: u32) -> Result<(), HalaRendererError> {
+    if self.pipeline.is_some() {
+      return Err(HalaRendererError::new("The pipeline is already created! Call set_push_constant_size before commit.", None));
+    }
+    if size > MAX_PUSH_CONSTANT_SIZE {
+      return Err(HalaRendererError::new(
+        &format!("The push constant size {} exceeds the maximum size {}!", size, MAX_PUSH_CONSTANT_SIZE), None));
+    }
+    if size % 4 != 0 {
+      return Err(HalaRendererError::new(&format!("The push constant size {} is not a multiple of 4!", size), None));
+    }
+
+    self.push_constant_size = size;
+    self.push_constant_data = vec![0u8; size as usize];
+
+    Ok(())
+  }
+
+  /// Set the push constants data used by the following trace rays.
+  /// param data: The data, it can be smaller than the push constant size.
+  /// return: The result.
+  pub fn set_push_constants(&mut self, data: &[u8]) -> Result<(), HalaRendererError> {
+    if data.len() > self.push_constant_size as usize {
+      return Err(HalaRendererError::new(
+        &format!("The push constants data size {} exceeds the push constant size {}!", data.len(), self.push_constant_size), None));
+    }
+
+    self.push_constant_data[..data.len()].copy_from_slice(data);
+
+    Ok(())
+  }
+
+  /// Get the shader stages of the push constants.
+  /// return: The shader stages.
+  fn get_push_constant_stages() -> hala_gfx::HalaShaderStageFlags {
+    hala_gfx::HalaShaderStageFlags::RAYGEN
+      | hala_gfx::HalaShaderStageFlags::MISS
+      | hala_gfx::HalaShaderStageFlags::CLOSEST_HIT
+      | hala_gfx::HalaShaderStageFlags::CALLABLE
+  }
+
+  /// Record the push constants if the pipeline has them.
+  /// param index: The index of the command buffer.
+  /// param command_buffers: The command buffers.
+  /// param pipeline: The ray tracing pipeline.
+  fn push_constants(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, pipeline: &hala_gfx::HalaRayTracingPipeline) {
+    if self.push_constant_size > 0 {
+      command_buffers.push_constants(index, pipeline.layout, Self::get_push_constant_stages(), 0, &self.push_constant_data);
+    }
+  }
+
+  /// Map the material type to the hit group in the shader binding table.
+  /// The unmapped material types use the hit group 0, the lights use the hit group following the last mapped one.
+  /// Must be called before set_scene.
+  /// param material_type: The material type.
+  /// param hit_group_index: The index of the hit shader group.
+  /// return: The result.
+  pub fn set_material_hit_group(&mut self, material_type: u32, hit_group_index: u32) -> Result<(), HalaRendererError> {
+    if self.scene_in_gpu.is_some() {
+      return Err(HalaRendererError::new("The scene is already set! Call set_material_hit_group before set_scene.", None));
+    }
+
+    let material_type = material_type as usize;
+    if self.material_hit_groups.len() <= material_type {
+      self.material_hit_groups.resize(material_type + 1, 0);
+    }
+    self.material_hit_groups[material_type] = hit_group_index;
+
+    Ok(())
+  }
+
+  /// Load the blue noise texture.
+  /// The texture is uploaded as a single slice array, see load_blue_noise_textures.
   /// param path: The path of the blue noise texture.
   /// return: The result.
   pub fn load_blue_noise_texture<P: AsRef<Path>>(&mut self, path: P) -> Result<(), HalaRendererError> {
-    let context = self.resources.context.borrow();
-    let path = path.as_ref();
-    let file_name = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?;
+    self.load_blue_noise_textures(std::slice::from_ref(&path))
+  }
 
-    let tex_in_cpu = cpu::image_data::HalaImageData::new_with_file(path)?;
+  /// Load the blue noise textures into a 2D array image.
+  /// The shader picks the slice with the blue noise slice index of the global uniform, it cycles through the slices per frame.
+  /// param paths: The paths of the blue noise textures, all textures must have the same size and format.
+  /// return: The result.
+  pub fn load_blue_noise_textures<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<(), HalaRendererError> {
+    let first_path = paths.first().ok_or(HalaRendererError::new("The blue noise texture paths are empty!", None))?.as_ref();
+    let file_name = first_path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?;
+
+    let mut format = None;
+    let mut extent = (0, 0);
+    let mut data = Vec::new();
+    for path in paths.iter() {
+      let path = path.as_ref();
+      let tex_in_cpu = cpu::image_data::HalaImageData::new_with_file(path)?;
+      match format {
+        None => {
+          format = Some(tex_in_cpu.format);
+          extent = (tex_in_cpu.width, tex_in_cpu.height);
+        },
+        Some(format) => {
+          if format != tex_in_cpu.format || extent != (tex_in_cpu.width, tex_in_cpu.height) {
+            return Err(HalaRendererError::new(
+              &format!(
+                "The blue noise texture {:?}[{} x {}, {:?}] does not match the first one[{} x {}, {:?}]!",
+                path, tex_in_cpu.width, tex_in_cpu.height, tex_in_cpu.format, extent.0, extent.1, format),
+              None));
+          }
+        },
+      }
+      match tex_in_cpu.data_type {
+        cpu::image_data::HalaImageDataType::ByteData(bytes) => data.extend_from_slice(bytes.as_slice()),
+        cpu::image_data::HalaImageDataType::FloatData(floats) => {
+          for f in floats {
+            data.extend_from_slice(&f.to_ne_bytes());
+          }
+        },
+      }
+    }
+    let format = format.ok_or(HalaRendererError::new("The blue noise texture format is none!", None))?;
 
     // Create the blue noise image.
+    let context = self.resources.context.borrow();
     let image = hala_gfx::HalaImage::new_2d(
       Rc::clone(&context.logical_device),
       hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_DST,
-      tex_in_cpu.format,
-      tex_in_cpu.width,
-      tex_in_cpu.height,
-      1,
+      format,
+      extent.0,
+      extent.1,
       1,
+      paths.len() as u32,
       hala_gfx::HalaMemoryLocation::GpuOnly,
       &format!("texture_{}.image", file_name.to_string_lossy())
     )?;
-    let data = match tex_in_cpu.data_type {
-      cpu::image_data::HalaImageDataType::ByteData(data) => data,
-      cpu::image_data::HalaImageDataType::FloatData(data) => {
-        let mut byte_data = Vec::with_capacity(data.len() * 4);
-        for f in data {
-          byte_data.extend_from_slice(&f.to_ne_bytes());
-        }
-        byte_data
-      },
-    };
     image.update_gpu_memory_with_buffer(
       data.as_slice(),
       hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
@@ -1150,18 +2036,68 @@ impl HalaRenderer {
       hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
       &self.resources.transfer_staging_buffer,
       &self.resources.transfer_command_buffers)?;
-    self.blue_noise_image = Some(image);
+    drop(context);
+
+    // Retire the old blue noise image, it may still be used by the in-flight frames.
+    if let Some(old_image) = self.blue_noise_image.replace(image) {
+      self.resources.retire(Box::new(old_image));
+    }
+    self.blue_noise_frame_count = paths.len() as u32;
+    self.settings_dirty = true;
+
+    // Rebind the image if the static descriptor set is already written.
+    if self.pipeline.is_some() {
+      self.wait_idle()?;
+      if let Some(blue_noise_image) = self.blue_noise_image.as_ref() {
+        self.static_descriptor_set.update_sampled_images(
+          0,
+          self.blue_noise_image_binding_index,
+          std::slice::from_ref(blue_noise_image),
+        );
+      }
+      self.statistics.reset();
+    }
 
     Ok(())
   }
 
+  /// Enable or disable streaming the textures when uploading the scene.
+  /// The textures start as 1x1 placeholders with their average color, one full resolution texture is uploaded per frame.
+  /// Each streamed texture restarts the accumulation.
+  /// It takes effect on the next set_scene call.
+  /// param enable: Enable the texture streaming or not.
+  pub fn set_texture_streaming(&mut self, enable: bool) {
+    self.stream_textures = enable;
+  }
+
+  /// Set the max number of the emissive triangle lights.
+  /// The triangles of the emissive materials are sampled as the mesh lights, the weakest ones are dropped with a warning if exceeded.
+  /// It takes effect on the next set_scene call.
+  /// param max_triangle_lights: The max number of the triangle lights.
+  pub fn set_max_triangle_lights(&mut self, max_triangle_lights: u32) {
+    self.max_triangle_lights = max_triangle_lights;
+  }
+
   /// Set the scene to be rendered.
   /// param scene_in_cpu: The scene in the CPU.
   /// return: The result.
   pub fn set_scene(&mut self, scene_in_cpu: &mut cpu::HalaScene) -> Result<(), HalaRendererError> {
+    // Retire the old scene in the GPU, it is dropped after the in-flight frames which may use it.
+    if let Some(scene_in_gpu) = self.scene_in_gpu.take() {
+      let num_of_bytes = scene_in_gpu.stats.get_total_bytes();
+      self.resources.retire_with_size(Box::new(scene_in_gpu), num_of_bytes);
+    }
+    // The mesh toggles refer to the meshes of the old scene.
+    self.mesh_visibilities.clear();
+    self.mesh_material_overrides.clear();
+
+    // Check the memory budget of the device before allocating, the retired scenes are counted as reclaimable.
+    let mut memory_budget = self.resources.memory_budget();
+    memory_budget.reclaim(self.resources.get_retired_bytes());
+    let memory_size = loader::HalaSceneGPUUploader::check_memory_budget(scene_in_cpu, false, &memory_budget)?;
+    log::debug!("The scene requires about {} bytes, {} bytes are available.", memory_size, memory_budget.available);
+
     let context = self.resources.context.borrow();
-    // Release the old scene in the GPU.
-    self.scene_in_gpu = None;
 
     // Upload the new scene to the GPU.
     let scene_in_gpu = loader::HalaSceneGPUUploader::upload(
@@ -1170,185 +2106,1391 @@ impl HalaRenderer {
       &self.resources.transfer_command_buffers,
       scene_in_cpu,
       false,
+      &HalaLodConfig::default(),
+      HalaDepthConvention::default(),
       false,
-      true)?;
+      self.stream_textures,
+      false,
+      false,
+      &HalaMeshletConfig::default(),
+      true,
+      &self.material_hit_groups,
+      self.max_triangle_lights,
+      None)?;
+    log::debug!("{}", scene_in_gpu.stats);
     self.scene_in_gpu = Some(scene_in_gpu);
 
     Ok(())
   }
 
-  /// Set the environment map.
-  /// param path: The path of the environment map.
-  /// param rotation: The rotation of the environment map.
+  /// Get the statistics and the memory usage of the scene in the GPU.
+  /// return: The statistics, none if no scene is set.
+  pub fn get_scene_stats(&self) -> Option<&gpu::HalaSceneGPUStats> {
+    self.scene_in_gpu.as_ref().map(|scene| &scene.stats)
+  }
+
+  /// Show or hide the mesh, the instances of the hidden meshes are masked out of the top level acceleration structure.
+  /// The emissive triangles of the hidden meshes are still sampled as the lights.
+  /// The top level acceleration structure is rebuilt after waiting the device idle, and the accumulation restarts.
+  /// param mesh_index: The index of the mesh.
+  /// param visible: Whether the mesh is visible.
   /// return: The result.
-  pub fn set_envmap<P: AsRef<Path>>(&mut self, path: P, rotation: f32) -> Result<(), HalaRendererError> {
-    let context = self.resources.context.borrow();
-    self.envmap = Some(crate::envmap::EnvMap::new_with_file(
-      path,
-      &context,
-      &self.resources.transfer_staging_buffer,
-      &self.resources.transfer_command_buffers,
-    )?);
-    self.env_rotation = rotation;
+  pub fn set_mesh_visible(&mut self, mesh_index: usize, visible: bool) -> Result<(), HalaRendererError> {
+    let num_of_meshes = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?.meshes.len();
+    if mesh_index >= num_of_meshes {
+      return Err(HalaRendererError::new(&format!("The mesh index {} is out of range!", mesh_index), None));
+    }
+    if self.is_mesh_visible(mesh_index) == visible {
+      return Ok(());
+    }
 
-    Ok(())
+    if self.mesh_visibilities.len() < num_of_meshes {
+      self.mesh_visibilities.resize(num_of_meshes, true);
+    }
+    self.mesh_visibilities[mesh_index] = visible;
+    self.rebuild_instances(false)
   }
 
-  /// Set the ground color.
-  /// param color: The color.
-  pub fn set_ground_color(&mut self, color: glam::Vec4) {
-    self.env_ground_color = color;
+  /// Whether the mesh is visible.
+  /// param mesh_index: The index of the mesh.
+  /// return: True if the mesh is visible.
+  pub fn is_mesh_visible(&self, mesh_index: usize) -> bool {
+    self.mesh_visibilities.get(mesh_index).copied().unwrap_or(true)
   }
 
-  /// Set the sky color.
-  /// param color: The color.
-  pub fn set_sky_color(&mut self, color: glam::Vec4) {
-    self.env_sky_color = color;
+  /// Override the material of all primitives of the mesh.
+  /// The primitive buffers are rewritten and the hit groups follow the override material type,
+  /// so the top level acceleration structure is rebuilt after waiting the device idle, and the accumulation restarts.
+  /// param mesh_index: The index of the mesh.
+  /// param material_index: The index of the override material, none to use the materials of the primitives.
+  /// return: The result.
+  pub fn set_mesh_material_override(&mut self, mesh_index: usize, material_index: Option<u32>) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    if mesh_index >= scene.meshes.len() {
+      return Err(HalaRendererError::new(&format!("The mesh index {} is out of range!", mesh_index), None));
+    }
+    if let Some(material_index) = material_index {
+      if material_index as usize >= scene.material_types.len() {
+        return Err(HalaRendererError::new(&format!("The override material index {} is out of range!", material_index), None));
+      }
+    }
+    let num_of_meshes = scene.meshes.len();
+
+    if self.mesh_material_overrides.len() < num_of_meshes {
+      self.mesh_material_overrides.resize(num_of_meshes, None);
+    }
+    self.mesh_material_overrides[mesh_index] = material_index;
+    self.rebuild_instances(true)
   }
 
-  /// Set the intensity of the environment.
+  /// Get the override material of the mesh.
+  /// param mesh_index: The index of the mesh.
+  /// return: The index of the override material, none if the mesh uses the materials of its primitives.
+  pub fn get_mesh_material_override(&self, mesh_index: usize) -> Option<u32> {
+    self.mesh_material_overrides.get(mesh_index).copied().flatten()
+  }
+
+  /// Rebuild the top level acceleration structure with the mesh toggles and rebind it.
+  /// The static descriptor set and the primitive buffers are shared by all frames, so the device is waited idle first.
+  /// param update_materials: Whether to rewrite the material index of the primitive buffers.
+  /// return: The result.
+  fn rebuild_instances(&mut self, update_materials: bool) -> Result<(), HalaRendererError> {
+    self.wait_idle()?;
+
+    {
+      let context = self.resources.context.borrow();
+      let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+      if update_materials {
+        loader::HalaSceneGPUUploader::update_primitive_materials(
+          &context,
+          &self.resources.transfer_command_buffers,
+          scene,
+          &self.mesh_material_overrides,
+        )?;
+      }
+      loader::HalaSceneGPUUploader::build_top_level_acceleration_structure(
+        &context,
+        &self.resources.graphics_command_buffers,
+        &self.resources.transfer_command_buffers,
+        scene,
+        &self.material_hit_groups,
+        &self.mesh_visibilities,
+        &self.mesh_material_overrides,
+      )?;
+
+      // The acceleration structure is the binding 0 of the static descriptor set, it is written by commit.
+      if self.pipeline.is_some() {
+        self.static_descriptor_set.update_acceleration_structures(
+          0,
+          0,
+          &[scene.tplas.as_ref().ok_or(HalaRendererError::new("The top level acceleration structure is none!", None))?],
+        );
+      }
+    }
+    self.mark_settings_dirty(true);
+
+    Ok(())
+  }
+
+  /// Set the environment map.
+  /// It can be called after commit, the old environment map is retired and the accumulation is reset.
+  /// param path: The path of the environment map.
+  /// param rotation: The rotation of the environment map.
+  /// return: The result.
+  pub fn set_envmap<P: AsRef<Path>>(&mut self, path: P, rotation: f32) -> Result<(), HalaRendererError> {
+    let envmap = {
+      let context = self.resources.context.borrow();
+      crate::envmap::EnvMap::new_with_file(
+        path,
+        &context,
+        &self.resources.transfer_staging_buffer,
+        &self.resources.transfer_command_buffers,
+      )?
+    };
+    self.replace_envmap(Some(envmap))?;
+    self.env_rotation = rotation;
+    self.mark_settings_dirty(false);
+
+    Ok(())
+  }
+
+  /// Set the environment map with the distribution maps built on the GPU.
+  /// The distribution maps are built on the CPU if the compute programs are not available, see EnvMap::new_with_file_on_gpu.
+  /// It can be called after commit, the old environment map is retired and the accumulation is reset.
+  /// param path: The path of the environment map.
+  /// param rotation: The rotation of the environment map.
+  /// param program_descs: The compute program descriptions to build the distribution maps.
+  /// return: The result.
+  pub fn set_envmap_on_gpu<P: AsRef<Path>>(
+    &mut self,
+    path: P,
+    rotation: f32,
+    program_descs: &crate::envmap::EnvMapDistributionProgramDescs) -> Result<(), HalaRendererError>
+  {
+    let envmap = {
+      let context = self.resources.context.borrow();
+      crate::envmap::EnvMap::new_with_file_on_gpu(
+        path,
+        &context,
+        &self.resources.transfer_staging_buffer,
+        &self.resources.transfer_command_buffers,
+        program_descs,
+      )?
+    };
+    self.replace_envmap(Some(envmap))?;
+    self.env_rotation = rotation;
+    self.mark_settings_dirty(false);
+
+    Ok(())
+  }
+
+  /// Clear the environment map and go back to the procedural sky.
+  /// return: The result.
+  pub fn clear_envmap(&mut self) -> Result<(), HalaRendererError> {
+    self.replace_envmap(None)
+  }
+
+  /// Replace the environment map.
+  /// After commit, it waits the in-flight frames, rewrites the static descriptor bindings and resets the accumulation.
+  /// param envmap: The new environment map, none for the procedural sky.
+  /// return: The result.
+  fn replace_envmap(&mut self, envmap: Option<crate::envmap::EnvMap>) -> Result<(), HalaRendererError> {
+    let is_committed = self.pipeline.is_some();
+    if is_committed {
+      // The static descriptor set is used by the in-flight frames.
+      self.wait_idle()?;
+    }
+
+    if let Some(old_envmap) = std::mem::replace(&mut self.envmap, envmap) {
+      self.resources.retire(Box::new(old_envmap));
+    }
+    self.settings_dirty = true;
+
+    if is_committed {
+      // The bindings are partially bound, the procedural sky never reads the old ones.
+      self.update_envmap_bindings();
+      self.statistics.reset();
+    }
+
+    Ok(())
+  }
+
+  /// Write the environment map bindings of the static descriptor set.
+  fn update_envmap_bindings(&self) {
+    if let Some(envmap) = self.envmap.as_ref() {
+      self.static_descriptor_set.update_combined_image_samplers(
+        0,
+        self.envmap_binding_index,
+        &[(&envmap.image, &envmap.sampler)],
+      );
+      self.static_descriptor_set.update_sampled_images(
+        0,
+        self.envmap_binding_index + 1,
+        &[&envmap.marginal_distribution_image, &envmap.conditional_distribution_image],
+      );
+      self.static_descriptor_set.update_samplers(
+        0,
+        self.envmap_binding_index + 2,
+        &[&envmap.distribution_sampler],
+      );
+    }
+  }
+
+  /// Set the ground color.
+  /// param color: The color.
+  pub fn set_ground_color(&mut self, color: glam::Vec4) {
+    if self.env_ground_color != color {
+      self.env_ground_color = color;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the sky color.
+  /// param color: The color.
+  pub fn set_sky_color(&mut self, color: glam::Vec4) {
+    if self.env_sky_color != color {
+      self.env_sky_color = color;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the intensity of the environment.
   /// param intensity: The intensity.
   pub fn set_env_intensity(&mut self, intensity: f32) {
-    self.env_intensity = intensity;
+    if self.env_intensity != intensity {
+      self.env_intensity = intensity;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the background transparent or not.
+  /// If enabled, the primary rays which miss all geometry write alpha 0 into the final and accum images.
+  /// The secondary rays still see the environment for lighting.
+  /// param transparent_background: Make the background transparent or not.
+  pub fn set_transparent_background(&mut self, transparent_background: bool) {
+    if self.transparent_background != transparent_background {
+      self.transparent_background = transparent_background;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Keep the environment color of the transparent background or not.
+  /// If enabled, the environment color is kept in the RGB channels with alpha 0, otherwise the color is black.
+  /// param keep_background_color: Keep the background color or not.
+  pub fn set_keep_background_color(&mut self, keep_background_color: bool) {
+    if self.keep_background_color != keep_background_color {
+      self.keep_background_color = keep_background_color;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the bounce budgets of the path tracing.
+  /// The budgets are counted separately and the path also stops at the max depth.
+  /// param max_diffuse_depth: The max number of the diffuse bounces, u32::MAX for no limit.
+  /// param max_specular_depth: The max number of the specular(glossy) bounces, u32::MAX for no limit.
+  /// param max_transmission_depth: The max number of the transmission bounces, u32::MAX for no limit.
+  pub fn set_bounce_budgets(&mut self, max_diffuse_depth: u32, max_specular_depth: u32, max_transmission_depth: u32) {
+    if self.max_diffuse_depth != max_diffuse_depth || self.max_specular_depth != max_specular_depth || self.max_transmission_depth != max_transmission_depth {
+      self.max_diffuse_depth = max_diffuse_depth;
+      self.max_specular_depth = max_specular_depth;
+      self.max_transmission_depth = max_transmission_depth;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the roughness threshold above which the glossy bounces are treated as the diffuse bounces.
+  /// param threshold: The roughness threshold in [0, 1], 1 means never.
+  pub fn set_glossy_roughness_threshold(&mut self, threshold: f32) {
+    let threshold = threshold.clamp(0.0, 1.0);
+    if self.glossy_roughness_threshold != threshold {
+      self.glossy_roughness_threshold = threshold;
+      self.mark_settings_dirty(true);
+    }
+  }
+
+  /// Set the tile size of the tiled rendering.
+  /// Each tile is traced with a separate submission, so very high resolutions do not trigger the device timeout.
+  /// The shaders must add the tile offset of the global uniform to the launch ID.
+  /// param width: The width of the tile, 0 to disable the tiled rendering.
+  /// param height: The height of the tile, 0 to disable the tiled rendering.
+  /// return: The result.
+  pub fn set_tile_size(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
+    if width == 0 || height == 0 {
+      self.tile_size = None;
+      return Ok(());
+    }
+
+    if self.tile_command_buffers.is_none() {
+      let context = self.resources.context.borrow();
+      self.tile_command_buffers = Some(hala_gfx::HalaCommandBufferSet::new(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&context.command_pools),
+        hala_gfx::HalaCommandBufferType::GRAPHICS,
+        hala_gfx::HalaCommandBufferLevel::PRIMARY,
+        1,
+        "tile.cmd_buffer",
+      )?);
+    }
+    self.tile_size = Some((width, height));
+
+    Ok(())
+  }
+
+  /// Check whether the tiled rendering is used.
+  /// return: True if the image is larger than a tile.
+  fn use_tiles(&self) -> bool {
+    match self.tile_size {
+      Some((tile_width, tile_height)) => {
+        let (_, _, render_width, render_height) = self.get_render_rect();
+        tile_width < render_width || tile_height < render_height
+      },
+      None => false,
+    }
+  }
+
+  /// Set the region of interest, only the pixels in the region are traced and the rest holds its last result.
+  /// The accumulation restarts when the region changes, so the region converges from its first frame.
+  /// param rect: The region(x, y, width, height) in pixels, none to render the full frame.
+  pub fn set_region_of_interest(&mut self, rect: Option<(u32, u32, u32, u32)>) {
+    if self.region_of_interest != rect {
+      self.region_of_interest = rect;
+      self.statistics.reset();
+    }
+  }
+
+  /// Mark the settings uniform to be rewritten in the next update.
+  /// param reset_accumulation: Whether the changed settings invalidate the accumulated samples.
+  fn mark_settings_dirty(&mut self, reset_accumulation: bool) {
+    self.settings_dirty = true;
+    if reset_accumulation {
+      self.statistics.reset();
+    }
+  }
+
+  /// Build the settings uniform from the current settings.
+  /// return: The settings uniform.
+  fn get_settings_uniform(&self) -> HalaSettingsUniform {
+    let (use_hdri, env_total_sum, env_map_width, env_map_height) = match self.envmap.as_ref() {
+      Some(envmap) => (true, envmap.total_luminance, envmap.image.extent.width, envmap.image.extent.height),
+      None => (false, 0f32, 0, 0),
+    };
+    let (enable_tonemap, enable_aces, use_simple_aces) = self.tonemap_type.to_flags();
+    HalaSettingsUniform {
+      ground_color: self.env_ground_color,
+      sky_color: self.env_sky_color,
+      max_depth: self.max_depth,
+      rr_depth: self.rr_depth,
+      env_type: if use_hdri { HalaEnvType::MAP.to_u8() as u32 } else { HalaEnvType::SKY.to_u8() as u32 },
+      env_map_width,
+      env_map_height,
+      env_total_sum,
+      env_rotation: self.env_rotation / 360f32,
+      env_intensity: self.env_intensity,
+      exposure_value: self.exposure_value,
+      enable_tonemap: enable_tonemap as u32,
+      enable_aces: enable_aces as u32,
+      use_simple_aces: use_simple_aces as u32,
+      tonemap_type: self.tonemap_type.to_u8() as u32,
+      transparent_background: self.transparent_background as u32,
+      keep_background_color: self.keep_background_color as u32,
+      num_of_callables: self.callable_shaders.len() as u32,
+      max_diffuse_depth: self.max_diffuse_depth,
+      max_specular_depth: self.max_specular_depth,
+      max_transmission_depth: self.max_transmission_depth,
+      glossy_roughness_threshold: self.glossy_roughness_threshold,
+      blue_noise_frame_count: self.blue_noise_frame_count,
+      debug_view: self.debug_view.to_u8() as u32,
+    }
+  }
+
+  /// Get the rectangle to trace.
+  /// return: The region of interest clamped to the image, or the full image.
+  fn get_render_rect(&self) -> (u32, u32, u32, u32) {
+    match self.region_of_interest {
+      Some((x, y, width, height)) => {
+        let x = x.min(self.info.width);
+        let y = y.min(self.info.height);
+        (x, y, width.min(self.info.width - x), height.min(self.info.height - y))
+      },
+      None => (0, 0, self.info.width, self.info.height),
+    }
+  }
+
+  /// Trace all tiles of the region to render, one submission per tile.
+  /// All tiles use the same frame index, so the accumulation stays correct for each pixel.
+  /// param context: The context.
+  /// param global_uniform: The global uniform of the current frame.
+  /// return: The result.
+  fn trace_tiles(&self, context: &hala_gfx::HalaContext, mut global_uniform: HalaGlobalUniform) -> Result<(), hala_gfx::HalaGfxError> {
+    let (tile_width, tile_height) = self.tile_size.ok_or(hala_gfx::HalaGfxError::new("The tile size is none!", None))?;
+    let command_buffers = self.tile_command_buffers.as_ref().ok_or(hala_gfx::HalaGfxError::new("The tile command buffers are none!", None))?;
+    let pipeline = self.pipeline.as_ref().ok_or(hala_gfx::HalaGfxError::new("The pipeline is none!", None))?;
+    let sbt = self.sbt.as_ref().ok_or(hala_gfx::HalaGfxError::new("The shader binding table is none!", None))?;
+    let (render_x, render_y, render_width, render_height) = self.get_render_rect();
+
+    for y in (render_y..render_y + render_height).step_by(tile_height as usize) {
+      for x in (render_x..render_x + render_width).step_by(tile_width as usize) {
+        // The previous tile is finished, so the uniform buffer of the slot 0 bound by the tiles can be updated.
+        global_uniform.tile_offset_x = x;
+        global_uniform.tile_offset_y = y;
+        self.global_uniform_buffers[0].update_memory(0, &[global_uniform])?;
+
+        command_buffers.reset(0, false)?;
+        command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        command_buffers.bind_ray_tracing_pipeline(0, pipeline);
+        command_buffers.bind_ray_tracing_descriptor_sets(
+          0,
+          pipeline,
+          0,
+          &[
+            self.static_descriptor_set.as_ref(),
+            self.dynamic_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The dynamic descriptor set is none!", None))?,
+            self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?,
+          ],
+          &[],
+        );
+        self.push_constants(0, command_buffers, pipeline);
+        command_buffers.trace_rays(
+          0,
+          sbt,
+          tile_width.min(render_x + render_width - x),
+          tile_height.min(render_y + render_height - y),
+          1,
+        );
+        command_buffers.end(0)?;
+
+        context.logical_device.borrow().graphics_submit(command_buffers, 0, 0)?;
+        context.logical_device.borrow().graphics_wait(0)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Apply the color grading LUT to the final image in place.
+  /// The final image holds the tone mapped display-referred color, the grading is skipped if the LUT or the program is none.
+  /// Only the render rectangle is graded, the rest of the final image holds its last graded result.
+  /// param index: The index of the command buffer.
+  /// param command_buffers: The command buffers.
+  /// param render_rect: The render rectangle(x, y, width, height).
+  /// return: The result.
+  fn apply_color_grading(
+    &self,
+    index: usize,
+    command_buffers: &hala_gfx::HalaCommandBufferSet,
+    render_rect: (u32, u32, u32, u32)) -> Result<(), hala_gfx::HalaGfxError>
+  {
+    let (program, descriptor_set, lut) = match (self.color_grading_program.as_ref(), self.color_grading_descriptor_set.as_ref(), self.color_grading_lut.as_ref()) {
+      (Some(program), Some(descriptor_set), Some(lut)) => (program, descriptor_set, lut),
+      _ => return Ok(()),
+    };
+    let (x, y, width, height) = render_rect;
+
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::GENERAL,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: self.final_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    // The push constants are the domain min with the LUT size in w, the domain max and the offset of the render rectangle(2 x vec4 + 2 x u32).
+    let mut push_constants = lut.get_push_constants().iter().flat_map(|value| value.to_ne_bytes()).collect::<Vec<_>>();
+    push_constants.extend_from_slice(&x.to_ne_bytes());
+    push_constants.extend_from_slice(&y.to_ne_bytes());
+    program.bind(index, command_buffers, &[descriptor_set]);
+    program.push_constants(index, command_buffers, 0, push_constants.as_slice());
+    program.dispatch(index, command_buffers, (width + 7) / 8, (height + 7) / 8, 1);
+
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::GENERAL,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: self.final_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    Ok(())
+  }
+
+  /// Apply the vignette and the chromatic aberration from the final image into the lens image.
+  /// The whole image is processed, so the pixels out of the region of interest keep their last result.
+  /// param index: The index of the command buffer.
+  /// param command_buffers: The command buffers.
+  /// return: The result.
+  fn apply_lens_effects(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError> {
+    let program = self.lens_program.as_ref().ok_or(hala_gfx::HalaGfxError::new("The lens program is none!", None))?;
+    let descriptor_set = self.lens_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The lens descriptor set is none!", None))?;
+    let lens_image = self.lens_image.as_ref().ok_or(hala_gfx::HalaGfxError::new("The lens image is none!", None))?;
+
+    command_buffers.set_image_barriers(
+      index,
+      &[
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::GENERAL,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::RAY_TRACING_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: self.final_image.raw,
+          ..Default::default()
+        },
+        // The lens image is fully overwritten, the last content is discarded.
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: lens_image.raw,
+          ..Default::default()
+        },
+      ],
+    );
+
+    program.bind(index, command_buffers, &[descriptor_set]);
+    program.push_constants_f32(index, command_buffers, 0, &self.lens_effects.get_push_constants(self.info.width, self.info.height));
+    program.dispatch(index, command_buffers, (self.info.width + 7) / 8, (self.info.height + 7) / 8, 1);
+
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::GENERAL,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: lens_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    Ok(())
+  }
+
+  /// Whether any lens effect is enabled and the lens resources are committed.
+  /// return: True if the lens effects are active.
+  fn is_lens_active(&self) -> bool {
+    self.lens_effects.is_enabled() && self.lens_program.is_some()
+  }
+
+  /// Create the lens image with the size and the format of the final image.
+  /// param context: The GFX context.
+  /// param color_format: The format of the final image.
+  /// return: The lens image.
+  fn create_lens_image(context: &hala_gfx::HalaContext, color_format: hala_gfx::HalaFormat) -> Result<hala_gfx::HalaImage, HalaRendererError> {
+    let lens_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::STORAGE | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+      color_format,
+      context.gpu_req.width,
+      context.gpu_req.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "lens.image",
+    )?;
+
+    Ok(lens_image)
+  }
+
+  /// Set the lens effect compute shader.
+  /// The shader reads the final image and writes the lens image with one thread per pixel in 8 x 8 groups.
+  /// The set 0 is the final image(storage) at the binding 0 and the lens image(storage) at the binding 1,
+  /// the push constants are the vignette, the chromatic aberration, the width and the height(vec4).
+  /// It must be called before commit.
+  /// param file_path: The compute shader file path.
+  /// return: The result.
+  pub fn set_lens_shader_with_file(&mut self, file_path: &str) -> Result<(), HalaRendererError> {
+    if self.pipeline.is_some() {
+      return Err(HalaRendererError::new("The lens shader must be set before commit!", None));
+    }
+
+    self.lens_file_path = Some(file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Set the lens effects, the lens pass is skipped if both are 0.
+  /// The lens effects are applied after the tone mapping, so the accumulation is not reset.
+  /// param vignette: The strength of the radial darkening.
+  /// param chromatic: The channel offset toward the edges in the fraction of the image width.
+  pub fn set_lens_effects(&mut self, vignette: f32, chromatic: f32) {
+    self.lens_effects = HalaLensEffects::new(vignette, chromatic);
+  }
+
+  /// Get the lens effects.
+  /// return: The lens effects.
+  pub fn get_lens_effects(&self) -> HalaLensEffects {
+    self.lens_effects
+  }
+
+  /// Set the color grading compute shader.
+  /// The shader applies the LUT to the final image in place with one thread per pixel in 8 x 8 groups.
+  /// The set 0 is the final image(storage) at the binding 0 and the LUT(combined image sampler) at the binding 1,
+  /// the push constants are the domain min with the LUT size in w, the domain max and the offset of the render rectangle(2 x vec4 + 2 x u32).
+  /// It must be called before commit.
+  /// param file_path: The compute shader file path.
+  /// return: The result.
+  pub fn set_color_grading_shader_with_file(&mut self, file_path: &str) -> Result<(), HalaRendererError> {
+    if self.pipeline.is_some() {
+      return Err(HalaRendererError::new("The color grading shader must be set before commit!", None));
+    }
+
+    self.color_grading_file_path = Some(file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Set the 3D color grading LUT from a .cube file, it can be called before or after commit.
+  /// The LUT is applied after the tone mapping, so the accumulation is not reset.
+  /// param path: The path of the .cube file.
+  /// return: The result.
+  pub fn set_color_grading_lut<P: AsRef<Path>>(&mut self, path: P) -> Result<(), HalaRendererError> {
+    let lut = {
+      let context = self.resources.context.borrow();
+      HalaColorGradingLUT::new_with_file(
+        path,
+        &context,
+        hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        &self.resources.transfer_staging_buffer,
+        &self.resources.transfer_command_buffers,
+      )?
+    };
+    self.replace_color_grading_lut(Some(lut))
+  }
+
+  /// Clear the color grading LUT, the tone mapped final image is presented directly.
+  /// return: The result.
+  pub fn clear_color_grading_lut(&mut self) -> Result<(), HalaRendererError> {
+    self.replace_color_grading_lut(None)
+  }
+
+  /// Replace the color grading LUT.
+  /// After commit, it waits the in-flight frames and rewrites the LUT binding of the color grading descriptor set.
+  /// param lut: The new LUT, none to disable the color grading.
+  /// return: The result.
+  fn replace_color_grading_lut(&mut self, lut: Option<HalaColorGradingLUT>) -> Result<(), HalaRendererError> {
+    if self.color_grading_descriptor_set.is_some() {
+      // The color grading descriptor set is used by the in-flight frames.
+      self.wait_idle()?;
+    }
+
+    if let Some(old_lut) = std::mem::replace(&mut self.color_grading_lut, lut) {
+      self.resources.retire(Box::new(old_lut));
+    }
+
+    if let (Some(descriptor_set), Some(lut)) = (self.color_grading_descriptor_set.as_ref(), self.color_grading_lut.as_ref()) {
+      descriptor_set.update_combined_image_samplers(0, 1, &[(&lut.image, &lut.sampler)]);
+    }
+
+    Ok(())
   }
 
   /// Set the exposure value.
   /// param exposure_value: The exposure value.
   pub fn set_exposure_value(&mut self, exposure_value: f32) {
     self.exposure_value = exposure_value;
+    self.mark_settings_dirty(false);
+  }
+
+  /// Set the tone map type.
+  /// param tonemap_type: The tone map type.
+  pub fn set_tonemap_type(&mut self, tonemap_type: HalaToneMapType) {
+    self.tonemap_type = tonemap_type;
+    self.mark_settings_dirty(false);
+  }
+
+  /// Get the tone map type.
+  /// return: The tone map type.
+  pub fn get_tonemap_type(&self) -> HalaToneMapType {
+    self.tonemap_type
+  }
+
+  /// Enable the tone mapping or not.
+  /// param enable_tonemap: Enable the tonemap or not.
+  #[deprecated(note = "Use set_tonemap_type instead.")]
+  pub fn set_enable_tonemap(&mut self, enable_tonemap: bool) {
+    let (_, enable_aces, use_simple_aces) = self.tonemap_type.to_flags();
+    self.set_tonemap_type(HalaToneMapType::from_flags(enable_tonemap, enable_aces, use_simple_aces));
+  }
+
+  /// Enable the ACES tone mapping or not.
+  /// param enable_aces: Enable the ACES tonemap or not.
+  #[deprecated(note = "Use set_tonemap_type instead.")]
+  pub fn set_enable_aces(&mut self, enable_aces: bool) {
+    let (enable_tonemap, _, use_simple_aces) = self.tonemap_type.to_flags();
+    self.set_tonemap_type(HalaToneMapType::from_flags(enable_tonemap, enable_aces, use_simple_aces));
   }
 
-  /// Save the images to the file.
+  /// Use the simple ACES tone mapping or not.
+  /// param use_simple_aces: Use the simple ACES tonemap or not.
+  #[deprecated(note = "Use set_tonemap_type instead.")]
+  pub fn set_use_simple_aces(&mut self, use_simple_aces: bool) {
+    let (enable_tonemap, enable_aces, _) = self.tonemap_type.to_flags();
+    self.set_tonemap_type(HalaToneMapType::from_flags(enable_tonemap, enable_aces, use_simple_aces));
+  }
+
+  /// Get the legacy tone mapping flags.
+  /// return: The flags(enable_tonemap, enable_aces, use_simple_aces).
+  #[deprecated(note = "Use get_tonemap_type instead.")]
+  pub fn get_tonemap_flags(&self) -> (bool, bool, bool) {
+    self.tonemap_type.to_flags()
+  }
+
+  /// Set the debug view, the ray generation shader writes the first hit data instead of the path traced color.
+  /// The albedo and the normal come from the albedo and the normal images, the UV checker and the primitive id coloring from the UV and the id images,
+  /// and the depth is the first hit distance linearized by the camera near and far planes.
+  /// The accumulation restarts, the overdraw has no meaning for the ray tracing and is not supported.
+  /// param view: The debug view.
+  /// return: The result.
+  pub fn set_debug_view(&mut self, view: HalaDebugView) -> Result<(), HalaRendererError> {
+    if view == HalaDebugView::OVERDRAW {
+      return Err(HalaRendererError::new("The overdraw debug view is not supported by the ray tracing renderer!", None));
+    }
+
+    self.debug_view = view;
+    self.mark_settings_dirty(true);
+
+    Ok(())
+  }
+
+  /// Get the debug view.
+  /// return: The debug view.
+  pub fn get_debug_view(&self) -> HalaDebugView {
+    self.debug_view
+  }
+
+  /// Set the working color space of the scene.
+  /// param color_space: The color space of the rendered linear colors.
+  pub fn set_working_color_space(&mut self, color_space: HalaColorSpace) {
+    self.working_color_space = color_space;
+  }
+
+  /// Get the working color space of the scene.
+  /// return: The working color space.
+  pub fn get_working_color_space(&self) -> HalaColorSpace {
+    self.working_color_space
+  }
+
+  /// Set the display color space of the saved images.
+  /// param color_space: The color space of the saved images.
+  pub fn set_display_color_space(&mut self, color_space: HalaColorSpace) {
+    self.display_color_space = color_space;
+  }
+
+  /// Get the display color space of the saved images.
+  /// return: The display color space.
+  pub fn get_display_color_space(&self) -> HalaColorSpace {
+    self.display_color_space
+  }
+
+  /// Save the images to the files.
+  /// The format is chosen by the file extension of the path(".exr" or ".png"), otherwise the linear PFM is used.
   /// param path: The output path of the image.
   /// return: The result.
   pub fn save_images<P: AsRef<Path>>(&self, path: P) -> Result<(), HalaRendererError> {
-    let context = self.resources.context.borrow();
+    let path = path.as_ref();
+    let format = path.extension()
+      .and_then(|extension| HalaImageFileFormat::from_extension(&extension.to_string_lossy()))
+      .unwrap_or(HalaImageFileFormat::PFM);
+    self.save_images_as(path, format)
+  }
 
+  /// Save the images to the files with the format.
+  /// The colors are converted from the working color space to the display color space.
+  /// If the background is transparent, the color image is saved with the alpha channel.
+  /// The PNG and EXR formats write RGBA pixels, the PFM format writes the alpha channel into a separate grayscale file.
+  /// param path: The output path of the image.
+  /// param format: The file format of the images.
+  /// return: The result.
+  pub fn save_images_as<P: AsRef<Path>>(&self, path: P, format: HalaImageFileFormat) -> Result<(), HalaRendererError> {
     if self.data.is_device_lost {
       // Skip the saving and wait to reset the device on the next frame update.
       log::warn!("The device is lost! Please wait to reset the device and try again.");
       return Ok(());
     }
 
+    let snapshots = self.snapshot_images(path.as_ref(), format)?;
+    Self::write_snapshots(snapshots, self.get_image_save_settings(format))
+  }
+
+  /// Save the images to the files on a background thread.
+  /// The images are copied to the CPU on the calling thread, the tone mapping and the file writing run on the worker thread.
+  /// param path: The output path of the image.
+  /// return: The join handle of the worker thread, none if the device is lost.
+  pub fn save_images_async<P: AsRef<Path>>(&self, path: P) -> Result<Option<std::thread::JoinHandle<Result<(), HalaRendererError>>>, HalaRendererError> {
     let path = path.as_ref();
+    let format = path.extension()
+      .and_then(|extension| HalaImageFileFormat::from_extension(&extension.to_string_lossy()))
+      .unwrap_or(HalaImageFileFormat::PFM);
+    self.save_images_async_as(path, format)
+  }
+
+  /// Save the images to the files with the format on a background thread.
+  /// The images are copied to the CPU on the calling thread, the tone mapping and the file writing run on the worker thread.
+  /// param path: The output path of the image.
+  /// param format: The file format of the images.
+  /// return: The join handle of the worker thread, none if the device is lost.
+  pub fn save_images_async_as<P: AsRef<Path>>(&self, path: P, format: HalaImageFileFormat) -> Result<Option<std::thread::JoinHandle<Result<(), HalaRendererError>>>, HalaRendererError> {
+    if self.data.is_device_lost {
+      // Skip the saving and wait to reset the device on the next frame update.
+      log::warn!("The device is lost! Please wait to reset the device and try again.");
+      return Ok(None);
+    }
+
+    let snapshots = self.snapshot_images(path.as_ref(), format)?;
+    let settings = self.get_image_save_settings(format);
+    let handle = std::thread::Builder::new()
+      .name("hala-renderer-image-saver".to_string())
+      .spawn(move || Self::write_snapshots(snapshots, settings))
+      .map_err(|err| HalaRendererError::new("Failed to spawn the image saving thread.", Some(Box::new(err))))?;
+
+    Ok(Some(handle))
+  }
+
+  /// Read back the raw pixels of the output image.
+  /// The pixels are linear RGBA floats in the working color space from top to bottom, without the tone mapping.
+  /// The ID image is integer, read it with read_id_image.
+  /// param kind: The kind of the image.
+  /// return: The pixels.
+  pub fn read_image(&self, kind: HalaImageKind) -> Result<Vec<f32>, HalaRendererError> {
+    let image: &hala_gfx::HalaImage = match kind {
+      HalaImageKind::ACCUM => &self.accum_image,
+      HalaImageKind::ALBEDO => &self.albedo_image,
+      HalaImageKind::NORMAL => &self.normal_image,
+      HalaImageKind::UV => &self.uv_image,
+      HalaImageKind::ID => return Err(HalaRendererError::new("The ID image is integer! Call read_id_image instead.", None)),
+      _ => return Err(HalaRendererError::new(&format!("Unsupported image kind: {:?}", kind), None)),
+    };
+
+    self.download_image(image)
+  }
+
+  /// Read back the raw pixels of the ID image.
+  /// The object(primitive) ID is in R and the material ID is in G, u32::MAX if the primary ray missed.
+  /// return: The RGBA integer pixels from top to bottom.
+  pub fn read_id_image(&self) -> Result<Vec<u32>, HalaRendererError> {
+    self.download_image(&self.id_image)
+  }
+
+  /// Download the RGBA 32-bit pixels of the storage image.
+  /// param image: The storage image.
+  /// return: The pixels.
+  fn download_image<T: Copy + Default>(&self, image: &hala_gfx::HalaImage) -> Result<Vec<T>, HalaRendererError> {
+    if self.data.is_device_lost {
+      return Err(HalaRendererError::DeviceLost);
+    }
+
+    let mut pixels = vec![T::default(); 4 * image.extent.width as usize * image.extent.height as usize];
+
+    self.wait_idle()?;
+    let context = self.resources.context.borrow();
+    context.logical_device.borrow().transfer_execute_and_submit(
+      &self.resources.transfer_command_buffers,
+      0,
+      |_logical_device, command_buffers, index| {
+        command_buffers.copy_image_2_buffer(
+          index,
+          image,
+          hala_gfx::HalaImageLayout::GENERAL,
+          &self.host_accessible_buffer);
+      },
+      0)?;
+    self.host_accessible_buffer.download_memory(0, pixels.as_mut_slice())?;
+
+    Ok(pixels)
+  }
+
+  /// Get the settings to encode the image snapshots.
+  /// param format: The file format of the images.
+  /// return: The settings.
+  fn get_image_save_settings(&self, format: HalaImageFileFormat) -> HalaImageSaveSettings {
+    HalaImageSaveSettings {
+      format,
+      tonemap_type: self.tonemap_type,
+      color_space_matrix: self.working_color_space.get_conversion_matrix(self.display_color_space),
+      transparent_background: self.transparent_background,
+    }
+  }
+
+  /// Copy the color, albedo and normal images to the CPU.
+  /// param path: The output path of the image.
+  /// param format: The file format of the images.
+  /// return: The snapshots of the images.
+  fn snapshot_images(&self, path: &Path, format: HalaImageFileFormat) -> Result<Vec<HalaImageSnapshot>, HalaRendererError> {
     let filename = path.file_stem().ok_or(HalaRendererError::new("The file name is none!", None))?;
-    let color_image_path = path.with_file_name(format!("{}_color.pfm", filename.to_string_lossy()));
-    let albedo_image_path = path.with_file_name(format!("{}_albedo.pfm", filename.to_string_lossy()));
-    let normal_image_path = path.with_file_name(format!("{}_normal.pfm", filename.to_string_lossy()));
+    let extension = format.get_extension();
+    let color_image_path = path.with_file_name(format!("{}_color.{}", filename.to_string_lossy(), extension));
+    let albedo_image_path = path.with_file_name(format!("{}_albedo.{}", filename.to_string_lossy(), extension));
+    let normal_image_path = path.with_file_name(format!("{}_normal.{}", filename.to_string_lossy(), extension));
+    let id_image_path = path.with_file_name(format!("{}_id.{}", filename.to_string_lossy(), extension));
+    let uv_image_path = path.with_file_name(format!("{}_uv.{}", filename.to_string_lossy(), extension));
+    let alpha_image_path = path.with_file_name(format!("{}_alpha.{}", filename.to_string_lossy(), extension));
+
+    let snapshot_image = |kind: HalaImageKind, path: &Path| -> Result<HalaImageSnapshot, HalaRendererError> {
+      let pixels = if kind == HalaImageKind::ID {
+        // The IDs are exact in the float up to 2^24, the missed pixels are written as -1.
+        self.read_id_image()?.into_iter().map(|id| if id == u32::MAX { -1.0 } else { id as f32 }).collect()
+      } else {
+        self.read_image(kind)?
+      };
+
+      Ok(HalaImageSnapshot {
+        path: path.to_path_buf(),
+        alpha_path: alpha_image_path.clone(),
+        width: self.info.width,
+        height: self.info.height,
+        pixels,
+        kind,
+      })
+    };
 
-    let save_image_2_file = |image: &hala_gfx::HalaImage, path: &Path, is_color: bool| -> Result<(), HalaRendererError> {
-      let mut pixels = vec![0f32; 4 * self.info.width as usize * self.info.height as usize];
+    log::debug!("Begin to download the images...");
+    Ok(vec![
+      snapshot_image(HalaImageKind::ACCUM, &color_image_path)?,
+      snapshot_image(HalaImageKind::ALBEDO, &albedo_image_path)?,
+      snapshot_image(HalaImageKind::NORMAL, &normal_image_path)?,
+      snapshot_image(HalaImageKind::ID, &id_image_path)?,
+      snapshot_image(HalaImageKind::UV, &uv_image_path)?,
+    ])
+  }
 
-      self.wait_idle()?;
-      context.logical_device.borrow().transfer_execute_and_submit(
-        &self.resources.transfer_command_buffers,
-        0,
-        |_logical_device, command_buffers, index| {
-          command_buffers.copy_image_2_buffer(
-            index,
-            image,
-            hala_gfx::HalaImageLayout::GENERAL,
-            &self.host_accessible_buffer);
-        },
-        0)?;
-      self.host_accessible_buffer.download_memory(0, pixels.as_mut_slice())?;
-
-      if is_color {
-        let luminance = |c: glam::Vec3| -> f32 {
-          0.212671 * c.x + 0.715160 * c.y + 0.072169 * c.z
-        };
-        let rrt_odt_fit = |v: glam::Vec3| -> glam::Vec3 {
-          let a = v * (v + 0.0245786) - 0.000090537;
-          let b = v * (0.983729 * v + 0.432951) + 0.238081;
-          a / b
-        };
-        let aces_fitted = |color: glam::Vec3| -> glam::Vec3 {
-          const ACES_INPUT_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
-            glam::Vec3::new(0.59719, 0.07600, 0.02840),
-            glam::Vec3::new(0.35458, 0.90834, 0.13383),
-            glam::Vec3::new(0.04823, 0.01566, 0.83777)
-          );
-          const ACES_OUTPUT_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
-            glam::Vec3::new(1.60475, -0.10208, -0.00327),
-            glam::Vec3::new(-0.53108, 1.10813, -0.07276),
-            glam::Vec3::new(-0.07367, -0.00605, 1.07602)
-          );
-          let mut color = ACES_INPUT_MATRIX * color;
-          color = rrt_odt_fit(color);
-          color = ACES_OUTPUT_MATRIX * color;
-          color = color.clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
-          color
-        };
-        let aces = |c: glam::Vec3| -> glam::Vec3 {
-          const A: f32 = 2.51;
-          const B: f32 = 0.03;
-          const Y: f32 = 2.43;
-          const D: f32 = 0.59;
-          const E: f32 = 0.14;
-
-          let r = (c * (A * c + B)) / (c * (Y * c + D) + E);
-          r.clamp(glam::Vec3::ZERO, glam::Vec3::ONE)
-        };
-        let tonemap = |c: glam::Vec3, limit: f32| -> glam::Vec3 {
-          c * 1.0 / (1.0 + luminance(c) / limit)
-        };
-
-        // Convert the color image to sRGB.
-        for pixel in pixels.chunks_exact_mut(4) {
-          let color = glam::Vec3::new(pixel[0], pixel[1], pixel[2]);
-          let color = if self.enable_tonemap {
-            if self.enable_aces {
-              if self.use_simple_aces {
-                aces(color)
-              } else {
-                aces_fitted(color)
-              }
-            } else {
-              tonemap(color, 1.5)
-            }
-          } else {
-            color
-          };
-          pixel[0] = color.x;
-          pixel[1] = color.y;
-          pixel[2] = color.z;
+  /// Write the image snapshots to the files.
+  /// param snapshots: The snapshots of the images.
+  /// param settings: The settings to encode the images.
+  /// return: The result.
+  fn write_snapshots(snapshots: Vec<HalaImageSnapshot>, settings: HalaImageSaveSettings) -> Result<(), HalaRendererError> {
+    for snapshot in snapshots.into_iter() {
+      let path = snapshot.path.clone();
+      Self::write_snapshot(snapshot, settings)?;
+      log::info!("Save the image to file: {:?}", path);
+    }
+
+    Ok(())
+  }
+
+  /// Write the image snapshot to the file.
+  /// param snapshot: The snapshot of the image.
+  /// param settings: The settings to encode the image.
+  /// return: The result.
+  fn write_snapshot(snapshot: HalaImageSnapshot, settings: HalaImageSaveSettings) -> Result<(), HalaRendererError> {
+    let HalaImageSnapshot { path, alpha_path, width, height, mut pixels, kind } = snapshot;
+    let is_color = kind == HalaImageKind::ACCUM;
+
+    // Only the color images are converted, the data images are written as exact values.
+    if kind == HalaImageKind::ACCUM || kind == HalaImageKind::ALBEDO {
+      for pixel in pixels.chunks_exact_mut(4) {
+        let mut color = glam::Vec3::new(pixel[0], pixel[1], pixel[2]);
+        // Apply the tone mapping in the working color space.
+        if is_color {
+          color = settings.tonemap_type.apply(color);
         }
+        // Convert to the display color space.
+        color = settings.color_space_matrix * color;
+        pixel[0] = color.x;
+        pixel[1] = color.y;
+        pixel[2] = color.z;
       }
+    }
 
-      let image_file = std::fs::File::create(path)
-        .map_err(|err| HalaRendererError::new(&format!("Failed to create the image file: {:?}", path), Some(Box::new(err))))?;
-      let mut writer = std::io::BufWriter::new(image_file);
-      writeln!(&mut writer, "PF\n{} {}\n-1.0", image.extent.width, image.extent.height)
-        .map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
-      for row in pixels.chunks_exact(4 * image.extent.width as usize).rev() {
-        for pixel in row.chunks_exact(4) {
-          writer.write_all(&pixel[0].to_le_bytes())
-            .map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
-          writer.write_all(&pixel[1].to_le_bytes())
-            .map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
-          writer.write_all(&pixel[2].to_le_bytes())
-            .map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
+    let with_alpha = is_color && settings.transparent_background;
+    match settings.format {
+      HalaImageFileFormat::PFM => {
+        Self::write_pfm(&path, width, height, &pixels, false)?;
+        if with_alpha {
+          Self::write_pfm(&alpha_path, width, height, &pixels, true)?;
+          log::info!("Save the alpha image to file: {:?}", alpha_path);
+        }
+        Ok(())
+      },
+      HalaImageFileFormat::EXR => Self::write_exr(&path, width, height, pixels, with_alpha),
+      HalaImageFileFormat::PNG => {
+        if with_alpha {
+          let data = pixels.chunks_exact(4)
+            .flat_map(|pixel| [srgb_encode(pixel[0]), srgb_encode(pixel[1]), srgb_encode(pixel[2]), pixel[3].clamp(0.0, 1.0)])
+            .map(|v| (v * 255.0 + 0.5) as u8)
+            .collect::<Vec<_>>();
+          Self::write_png(&path, width, height, data, true)
+        } else if kind == HalaImageKind::NORMAL {
+          // Remap the normal from [-1, 1] to [0, 1] without encoding.
+          let data = pixels.chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .map(|v| ((v * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+            .collect::<Vec<_>>();
+          Self::write_png(&path, width, height, data, false)
+        } else if kind == HalaImageKind::ID {
+          // Write the object and material IDs as 16-bit values, the missed pixels are 65535.
+          let data = pixels.chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], 0.0])
+            .map(|v| if v < 0.0 { u16::MAX } else { v.min(u16::MAX as f32) as u16 })
+            .collect::<Vec<_>>();
+          Self::write_png16(&path, width, height, data)
+        } else if kind == HalaImageKind::UV {
+          // Write the UV and the first barycentric coordinate without encoding.
+          let data = pixels.chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+            .collect::<Vec<_>>();
+          Self::write_png(&path, width, height, data, false)
+        } else {
+          let data = pixels.chunks_exact(4)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .map(|v| (srgb_encode(v) * 255.0 + 0.5) as u8)
+            .collect::<Vec<_>>();
+          Self::write_png(&path, width, height, data, false)
+        }
+      },
+      _ => Err(HalaRendererError::new(&format!("Unsupported image file format: {:?}", settings.format), None)),
+    }
+  }
+
+  /// Write the RGBA float pixels to the PFM file.
+  /// param path: The file path.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param pixels: The RGBA float pixels from top to bottom.
+  /// param is_alpha: Write the alpha channel as a grayscale image or write the RGB channels.
+  /// return: The result.
+  fn write_pfm(path: &Path, width: u32, height: u32, pixels: &[f32], is_alpha: bool) -> Result<(), HalaRendererError> {
+    let image_file = std::fs::File::create(path)
+      .map_err(|err| HalaRendererError::io("Failed to create the image file.", path, err))?;
+    let mut writer = std::io::BufWriter::new(image_file);
+    writeln!(&mut writer, "{}\n{} {}\n-1.0", if is_alpha { "Pf" } else { "PF" }, width, height)
+      .map_err(|err| HalaRendererError::io("Failed to write the image file.", path, err))?;
+    let channels = if is_alpha { 3..4 } else { 0..3 };
+    for row in pixels.chunks_exact(4 * width as usize).rev() {
+      for pixel in row.chunks_exact(4) {
+        for value in pixel[channels.clone()].iter() {
+          writer.write_all(&value.to_le_bytes())
+            .map_err(|err| HalaRendererError::io("Failed to write the image file.", path, err))?;
         }
       }
-      writer.flush()
-        .map_err(|err| HalaRendererError::new(&format!("Failed to flush the image file: {:?}", path), Some(Box::new(err))))?;
+    }
+    writer.flush()
+      .map_err(|err| HalaRendererError::io("Failed to flush the image file.", path, err))?;
 
-      Ok(())
+    Ok(())
+  }
+
+  /// Write the RGBA float pixels to the OpenEXR file.
+  /// param path: The file path.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param pixels: The RGBA float pixels from top to bottom.
+  /// param with_alpha: Write the alpha channel or not.
+  /// return: The result.
+  fn write_exr(path: &Path, width: u32, height: u32, pixels: Vec<f32>, with_alpha: bool) -> Result<(), HalaRendererError> {
+    let result = if with_alpha {
+      image::Rgba32FImage::from_raw(width, height, pixels)
+        .ok_or(HalaRendererError::new(&format!("Failed to create the image buffer: {:?}", path), None))?
+        .save_with_format(path, image::ImageFormat::OpenExr)
+    } else {
+      let data = pixels.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect::<Vec<_>>();
+      image::Rgb32FImage::from_raw(width, height, data)
+        .ok_or(HalaRendererError::new(&format!("Failed to create the image buffer: {:?}", path), None))?
+        .save_with_format(path, image::ImageFormat::OpenExr)
+    };
+    result.map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
+
+    Ok(())
+  }
+
+  /// Write the RGB or RGBA 8-bit pixels to the PNG file.
+  /// param path: The file path.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param data: The 8-bit pixels from top to bottom.
+  /// param with_alpha: The pixels have the alpha channel or not.
+  /// return: The result.
+  fn write_png(path: &Path, width: u32, height: u32, data: Vec<u8>, with_alpha: bool) -> Result<(), HalaRendererError> {
+    let result = if with_alpha {
+      image::RgbaImage::from_raw(width, height, data)
+        .ok_or(HalaRendererError::new(&format!("Failed to create the image buffer: {:?}", path), None))?
+        .save_with_format(path, image::ImageFormat::Png)
+    } else {
+      image::RgbImage::from_raw(width, height, data)
+        .ok_or(HalaRendererError::new(&format!("Failed to create the image buffer: {:?}", path), None))?
+        .save_with_format(path, image::ImageFormat::Png)
     };
+    result.map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
+
+    Ok(())
+  }
+
+  /// Write the RGB 16-bit pixels to the PNG file.
+  /// param path: The file path.
+  /// param width: The width of the image.
+  /// param height: The height of the image.
+  /// param data: The 16-bit pixels from top to bottom.
+  /// return: The result.
+  fn write_png16(path: &Path, width: u32, height: u32, data: Vec<u16>) -> Result<(), HalaRendererError> {
+    image::ImageBuffer::<image::Rgb<u16>, Vec<u16>>::from_raw(width, height, data)
+      .ok_or(HalaRendererError::new(&format!("Failed to create the image buffer: {:?}", path), None))?
+      .save_with_format(path, image::ImageFormat::Png)
+      .map_err(|err| HalaRendererError::new(&format!("Failed to write the image file: {:?}", path), Some(Box::new(err))))?;
+
+    Ok(())
+  }
+
+  /// Set the perspective parameters of the camera, the projection matrix is rebuilt and the accumulation is reset.
+  /// param camera_index: The index of the camera.
+  /// param yfov: The vertical field of view in radians.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance.
+  /// return: The result.
+  pub fn set_camera_perspective(&mut self, camera_index: usize, yfov: f32, znear: f32, zfar: f32) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_perspective(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      yfov,
+      znear,
+      zfar,
+    )?;
+
+    // The accumulated samples are invalid now.
+    self.statistics.reset();
+
+    Ok(())
+  }
+
+  /// Set the view matrix of the camera, e.g. from HalaOrbitController or HalaFlyController, the accumulation is reset.
+  /// It waits the device idle to update the camera buffer.
+  /// param camera_index: The index of the camera.
+  /// param v_mtx: The view matrix.
+  /// return: The result.
+  pub fn set_view_matrix(&mut self, camera_index: usize, v_mtx: glam::Mat4) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_view_matrix(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      v_mtx,
+    )?;
+
+    // The accumulated samples are invalid now.
+    self.statistics.reset();
+
+    Ok(())
+  }
+
+  /// Set the aperture and the focal distance of the camera, the accumulation is reset.
+  /// param camera_index: The index of the camera.
+  /// param aperture: The aperture, 0 disables the depth of field.
+  /// param focal_distance: The focal distance.
+  /// return: The result.
+  pub fn set_camera_aperture_focal(&mut self, camera_index: usize, aperture: f32, focal_distance: f32) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_aperture_focal(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      aperture,
+      focal_distance,
+    )?;
+
+    // The accumulated samples are invalid now.
+    self.statistics.reset();
+
+    Ok(())
+  }
+
+  /// Reload a texture image of the scene from the file.
+  /// param image_index: The index of the image in the scene.
+  /// param path: The file path of the new image.
+  /// return: The result.
+  pub fn reload_texture_from_file(&mut self, image_index: usize, path: &Path) -> Result<(), HalaRendererError> {
+    let image_data = cpu::image_data::HalaImageData::new_with_file(path)?;
+
+    // The textures descriptor set is not created with the update after bind flag,
+    // so it can not be rewritten while any in-flight frame uses it.
+    self.wait_idle()?;
+
+    let context = self.resources.context.borrow();
+    let command_buffers = hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "reload_texture.command_buffers",
+    )?;
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let old_image = loader::HalaSceneGPUUploader::reload_image(
+      &context,
+      &command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      image_index,
+      &image_data,
+    )?;
+
+    drop(context);
+
+    // The image is recreated, so we need to rewrite the descriptor entries.
+    // The device is idle, so the old image is dropped directly.
+    self.update_textures_descriptor_set()?;
+    drop(old_image);
+
+    // The accumulated samples are invalid now.
+    self.statistics.reset();
+
+    log::info!("Reload the texture {} from file: {:?}", image_index, path);
+    Ok(())
+  }
+
+  /// Upload the next pending texture of the scene and replace its placeholder.
+  /// The textures descriptor set is not created with the update after bind flag, so it waits for the device idle before rewriting.
+  /// return: The result.
+  fn stream_next_texture(&mut self) -> Result<(), HalaRendererError> {
+    if !matches!(self.scene_in_gpu.as_ref(), Some(scene) if scene.has_pending_images()) {
+      return Ok(());
+    }
+
+    // Make sure the placeholder is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let context = self.resources.context.borrow();
+    let command_buffers = hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "stream_texture.command_buffers",
+    )?;
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let placeholder = loader::HalaSceneGPUUploader::stream_next_image(
+      &context,
+      &command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+    )?;
+    drop(context);
 
-    log::debug!("Begin to save the color image...");
-    save_image_2_file(&self.accum_image, &color_image_path, true)?;
-    log::info!("Save the color image to file: {:?}", color_image_path);
+    // The device is idle, so the placeholder is dropped directly.
+    if let Some(placeholder) = placeholder {
+      self.update_textures_descriptor_set()?;
+      drop(placeholder);
 
-    log::debug!("Begin to save the albedo image...");
-    save_image_2_file(&self.albedo_image, &albedo_image_path, false)?;
-    log::info!("Save the albedo image to file: {:?}", albedo_image_path);
+      // The accumulated samples used the placeholder.
+      self.statistics.reset();
+    }
+
+    Ok(())
+  }
 
-    log::debug!("Begin to save the normal image...");
-    save_image_2_file(&self.normal_image, &normal_image_path, false)?;
-    log::info!("Save the normal image to file: {:?}", normal_image_path);
+  /// Rewrite the descriptor entries of the textures with the current images of the scene.
+  /// The caller must make sure the textures descriptor set is not used by any in-flight frame.
+  /// return: The result.
+  fn update_textures_descriptor_set(&self) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    if let Some(textures_descriptor_set) = self.textures_descriptor_set.as_ref() {
+      let mut combined_textures = Vec::with_capacity(scene.textures.len());
+      for (sampler_index, image_index) in scene.textures.iter().enumerate() {
+        let sampler = scene.samplers.get(sampler_index).ok_or(HalaRendererError::new("The sampler is none!", None))?;
+        let image = scene.images.get(*image_index as usize).ok_or(HalaRendererError::new("The image is none!", None))?;
+        combined_textures.push((image, sampler));
+      }
+      if !combined_textures.is_empty() {
+        textures_descriptor_set.update_combined_image_samplers(0, 0, combined_textures.as_slice());
+      }
+    }
 
     Ok(())
   }
 
-}
\ No newline at end of file
+  /// Reload all changed texture images reported by the texture watcher.
+  /// param watcher: The texture watcher.
+  /// return: The number of the reloaded textures.
+  pub fn reload_changed_textures(&mut self, watcher: &mut HalaTextureWatcher) -> Result<usize, HalaRendererError> {
+    let changed = watcher.poll();
+    for (image_index, path) in changed.iter() {
+      self.reload_texture_from_file(*image_index, path)?;
+    }
+
+    Ok(changed.len())
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WIDTH: u32 = 64;
+  const HEIGHT: u32 = 32;
+
+  /// Create a small headless renderer.
+  /// return: The renderer.
+  fn create_headless_renderer() -> HalaRenderer {
+    let gpu_req = HalaGPURequirements {
+      width: WIDTH,
+      height: HEIGHT,
+      require_ray_tracing: true,
+      ..Default::default()
+    };
+    HalaRenderer::new_headless("device_lost_test", &gpu_req, 4, 2, 0).unwrap()
+  }
+
+  #[test]
+  #[ignore = "requires a GPU with the ray tracing support"]
+  fn test_device_lost_recovery() {
+    let mut renderer = create_headless_renderer();
+    renderer.pre_update(WIDTH, HEIGHT).unwrap();
+    renderer.pre_update(WIDTH, HEIGHT).unwrap();
+    assert_eq!(renderer.statistics.total_frames, 2);
+
+    renderer.simulate_device_lost();
+    assert!(renderer.data.is_device_lost);
+
+    // The frame is skipped while the device is lost.
+    renderer.render().unwrap();
+    assert!(renderer.data.is_device_lost);
+
+    // The next frame update restores the device and restarts the accumulation.
+    renderer.pre_update(WIDTH, HEIGHT).unwrap();
+    assert!(!renderer.data.is_device_lost);
+    assert_eq!(renderer.statistics.total_frames, 1);
+    assert_eq!((renderer.info.width, renderer.info.height), (WIDTH, HEIGHT));
+    assert_eq!((renderer.final_image.extent.width, renderer.final_image.extent.height), (WIDTH, HEIGHT));
+    assert_eq!((renderer.accum_image.extent.width, renderer.accum_image.extent.height), (WIDTH, HEIGHT));
+
+    renderer.wait_idle().unwrap();
+  }
+
+}
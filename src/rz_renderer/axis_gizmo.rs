@@ -0,0 +1,223 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+
+use super::post_process::load_vertex_fragment_shaders;
+use super::{
+  HalaViewDesc,
+  set_view_viewport,
+};
+
+/// The axis gizmo pass draws the world axes rotated by the first view in a square viewport at the bottom left corner of the view,
+/// on top of the scene without the depth test. The size is the side of the viewport in pixels, 0 disables it.
+pub(crate) struct HalaAxisGizmoPass {
+  pub(crate) size: u32,
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+}
+
+/// The Default implementation of the axis gizmo pass.
+impl Default for HalaAxisGizmoPass {
+  fn default() -> Self {
+    Self {
+      size: 0,
+      shaders: Vec::new(),
+      graphics_pipeline: None,
+    }
+  }
+}
+
+/// The implementation of the axis gizmo pass.
+impl HalaAxisGizmoPass {
+
+  /// Get the gizmo matrix, it maps the world axes rotated by the view into the gizmo viewport, the translation of the view is dropped.
+  /// The unit axes fit in the viewport with a margin, the depth is unused.
+  /// param v_mtx: The view matrix.
+  /// return: The gizmo matrix.
+  pub(crate) fn get_gizmo_mtx_for(v_mtx: glam::Mat4) -> glam::Mat4 {
+    glam::Mat4::orthographic_rh(-1.2, 1.2, -1.2, 1.2, -2.0, 2.0) * glam::Mat4::from_mat3(glam::Mat3::from_mat4(v_mtx))
+  }
+
+  /// Get the square gizmo viewport at the bottom left corner of the view.
+  /// param view: The view.
+  /// param size: The side of the gizmo viewport in pixels, it is clamped to the view.
+  /// return: The gizmo view.
+  pub(crate) fn get_gizmo_view_for(view: &HalaViewDesc, size: u32) -> HalaViewDesc {
+    let (x, y, width, height) = view.viewport;
+    let size = (size as f32).min(width).min(height);
+    let (gizmo_x, gizmo_y) = (x, y + height - size);
+    HalaViewDesc {
+      camera_index: view.camera_index,
+      viewport: (gizmo_x, gizmo_y, size, size),
+      scissor: (gizmo_x as i32, gizmo_y as i32, size as u32, size as u32),
+    }
+  }
+
+  /// Set the shaders of the axis gizmo.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+
+    Ok(())
+  }
+
+  /// Enable the axis gizmo.
+  /// param size: The side of the gizmo viewport in pixels.
+  /// return: The result.
+  pub(crate) fn enable(&mut self, size: u32) -> Result<(), HalaRendererError> {
+    if size == 0 {
+      return Err(HalaRendererError::new("The axis gizmo size must be positive!", None));
+    }
+
+    self.size = size;
+
+    Ok(())
+  }
+
+  /// Create the graphics pipeline if the axis gizmo is enabled.
+  /// It draws on the scene target of the forward pass or on the swapchain after the lighting.
+  /// param context: The GFX context.
+  /// param use_deferred: Whether the gizmo is drawn after the deferred lighting.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    use_deferred: bool,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    if self.size == 0 || self.shaders.is_empty() {
+      return Ok(());
+    }
+
+    let shaders = self.shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+    let multisample_state = if use_deferred {
+      hala_gfx::HalaMultisampleState::default()
+    } else {
+      hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false)
+    };
+    self.graphics_pipeline = Some(
+      HalaGraphicsPipeline::new(
+        Rc::clone(&context.logical_device),
+        &context.swapchain,
+        &[] as &[&hala_gfx::HalaDescriptorSetLayout],
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+        &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+        &[hala_gfx::HalaPushConstantRange {
+          stage_flags: hala_gfx::HalaShaderStageFlags::VERTEX,
+          offset: 0,
+          size: 64, // Gizmo matrix.
+        }],
+        hala_gfx::HalaPrimitiveTopology::LINE_LIST,
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &multisample_state,
+        &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+        None,
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(pipeline_cache),
+        "axis_gizmo.graphics_pipeline",
+      )?
+    );
+
+    Ok(())
+  }
+
+  /// Whether the axis gizmo is enabled and its pipeline is committed.
+  /// return: True if the axis gizmo is active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.size > 0 && self.graphics_pipeline.is_some()
+  }
+
+  /// Draw the axis gizmo in the bottom left corner of the view, the viewport is left at the gizmo viewport.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param view: The first view.
+  /// param v_mtx: The view matrix of the first view.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    view: &HalaViewDesc,
+    v_mtx: glam::Mat4,
+  ) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = match self.graphics_pipeline.as_ref() {
+      Some(graphics_pipeline) if self.size > 0 => graphics_pipeline,
+      _ => return Ok(0),
+    };
+
+    set_view_viewport(index, command_buffers, &Self::get_gizmo_view_for(view, self.size));
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.push_constants(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      0,
+      &Self::get_gizmo_mtx_for(v_mtx).to_cols_array().iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>(),
+    );
+    command_buffers.draw(index, 6, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_gizmo_view() {
+    let view = HalaViewDesc {
+      camera_index: 1,
+      viewport: (10.0, 20.0, 200.0, 100.0),
+      scissor: (10, 20, 200, 100),
+    };
+    let gizmo_view = HalaAxisGizmoPass::get_gizmo_view_for(&view, 64);
+    assert_eq!(gizmo_view.camera_index, 1);
+    assert_eq!(gizmo_view.viewport, (10.0, 56.0, 64.0, 64.0));
+    assert_eq!(gizmo_view.scissor, (10, 56, 64, 64));
+
+    // The gizmo is clamped to the view.
+    let gizmo_view = HalaAxisGizmoPass::get_gizmo_view_for(&view, 512);
+    assert_eq!(gizmo_view.viewport, (10.0, 20.0, 100.0, 100.0));
+  }
+
+  #[test]
+  fn test_gizmo_mtx_drops_translation() {
+    let v_mtx = glam::Mat4::look_at_rh(glam::Vec3::new(5.0, 3.0, 7.0), glam::Vec3::ZERO, glam::Vec3::Y);
+    let gizmo_mtx = HalaAxisGizmoPass::get_gizmo_mtx_for(v_mtx);
+    let origin = gizmo_mtx * glam::Vec4::new(0.0, 0.0, 0.0, 1.0);
+    assert!(origin.truncate().truncate().length() < 1e-6);
+    // The unit axes stay inside the viewport.
+    for axis in [glam::Vec3::X, glam::Vec3::Y, glam::Vec3::Z] {
+      let point = gizmo_mtx * axis.extend(1.0);
+      assert!(point.x.abs() <= 1.0 && point.y.abs() <= 1.0);
+    }
+  }
+
+}
@@ -0,0 +1,414 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaImage,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+
+use super::post_process::{
+  HalaPostProcessTarget,
+  load_vertex_fragment_shaders,
+  create_full_screen_graphics_pipeline,
+  post_process_binding,
+  bind_full_screen_pipeline,
+};
+
+/// The max mip count of the bloom chain, the first mip is half of the window.
+const BLOOM_MAX_MIP_COUNT: u32 = 6;
+
+/// The bloom pass, the scene is rendered into the bloom color image, the compute program thresholds it into the mip chain,
+/// downsamples and upsamples the chain, then the composite pass adds the first mip to the scene color in the next target.
+pub(crate) struct HalaBloomPass {
+  pub(crate) is_enabled: bool,
+  pub(crate) threshold: f32,
+  pub(crate) intensity: f32,
+  pub(crate) file_path: Option<String>,
+  pub(crate) composite_shaders: Vec<HalaShader>,
+  pub(crate) program: Option<HalaComputeProgram>,
+  pub(crate) composite_graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) target: Option<HalaPostProcessTarget>,
+  pub(crate) mip_images: Vec<HalaImage>,
+  // The downsample sets of each mip, followed by the upsample sets of each mip except the first one.
+  pub(crate) descriptor_sets: Vec<HalaDescriptorSet>,
+}
+
+/// The Default implementation of the bloom pass.
+impl Default for HalaBloomPass {
+  fn default() -> Self {
+    Self {
+      is_enabled: false,
+      threshold: 1.0,
+      intensity: 0.0,
+      file_path: None,
+      composite_shaders: Vec::new(),
+      program: None,
+      composite_graphics_pipeline: None,
+      target: None,
+      mip_images: Vec::new(),
+      descriptor_sets: Vec::new(),
+    }
+  }
+}
+
+/// The implementation of the bloom pass.
+impl HalaBloomPass {
+
+  /// Get the mip count of the bloom chain, the smallest mip is at least 2 pixels.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The mip count.
+  pub(crate) fn get_mip_count_for(width: u32, height: u32) -> usize {
+    let min_size = width.min(height).max(4);
+    (min_size.ilog2() - 1).clamp(1, BLOOM_MAX_MIP_COUNT) as usize
+  }
+
+  /// Get the size of the bloom mip, the mip 0 is half of the render size.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param mip_index: The index of the mip.
+  /// return: The width and the height.
+  pub(crate) fn get_mip_size_for(width: u32, height: u32, mip_index: usize) -> (u32, u32) {
+    ((width >> (mip_index + 1)).max(1), (height >> (mip_index + 1)).max(1))
+  }
+
+  /// Get the source mip, the destination mip and the name of each pass, the index is the descriptor set index.
+  /// The downsample passes of each mip are followed by the upsample passes of each mip except the first one.
+  /// The downsample pass of the mip 0 reads the color image(the source mip is itself and not used).
+  /// param num_of_mips: The mip count.
+  /// return: The passes.
+  pub(crate) fn get_passes(num_of_mips: usize) -> Vec<(usize, usize, &'static str)> {
+    (0..num_of_mips).map(|mip_index| (mip_index.saturating_sub(1), mip_index, "down"))
+      .chain((1..num_of_mips).map(|mip_index| (mip_index, mip_index - 1, "up")))
+      .collect()
+  }
+
+  /// Get the descriptor set index, the destination mip and the mode of each dispatch in the recording order.
+  /// The mode 0 thresholds the color image into the mip 0, the mode 1 downsamples the source mip into the destination mip,
+  /// and the mode 2 upsamples the source mip and adds it to the destination mip.
+  /// It downsamples from the color image to the last mip, then upsamples back to the mip 0.
+  /// param num_of_mips: The mip count.
+  /// return: The dispatches.
+  pub(crate) fn get_dispatches(num_of_mips: usize) -> Vec<(usize, usize, u32)> {
+    (0..num_of_mips).map(|mip_index| (mip_index, mip_index, if mip_index == 0 { 0u32 } else { 1u32 }))
+      .chain((1..num_of_mips).rev().map(|mip_index| (num_of_mips + mip_index - 1, mip_index - 1, 2u32)))
+      .collect()
+  }
+
+  /// Set the shaders of the bloom, they must be set before commit.
+  /// param context: The GFX context.
+  /// param file_path: The compute shader file path.
+  /// param composite_vertex_file_path: The composite pass vertex shader file path.
+  /// param composite_fragment_file_path: The composite pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    file_path: &str,
+    composite_vertex_file_path: &str,
+    composite_fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if self.program.is_some() {
+      return Err(HalaRendererError::new("The bloom shaders must be set before commit!", None));
+    }
+
+    self.composite_shaders = load_vertex_fragment_shaders(
+      context,
+      composite_vertex_file_path,
+      composite_fragment_file_path,
+      &format!("{}_composite", debug_name),
+    )?;
+    self.file_path = Some(file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Get the descriptors and the descriptor set count reserved by commit.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The descriptor sizes and the set count.
+  pub(crate) fn get_descriptor_sizes(&self, width: u32, height: u32) -> (Vec<(hala_gfx::HalaDescriptorType, usize)>, usize) {
+    if self.file_path.is_some() {
+      let num_of_sets = Self::get_mip_count_for(width, height) * 2 - 1;
+      (
+        vec![
+          (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, num_of_sets),
+          (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, num_of_sets * 2),
+        ],
+        num_of_sets,
+      )
+    } else {
+      (Vec::new(), 0)
+    }
+  }
+
+  /// Create the target, the mip chain, the descriptor sets, the program and the composite pipeline, the previous ones are replaced.
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+  ) -> Result<(), HalaRendererError> {
+    let file_path = match self.file_path.as_ref() {
+      Some(file_path) => file_path,
+      None => return Ok(()),
+    };
+
+    // The compute program reads the color image and the composite pass reads it again.
+    let target = HalaPostProcessTarget::new(
+      context,
+      width,
+      height,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      false,
+      "bloom",
+    )?;
+    let num_of_mips = Self::get_mip_count_for(width, height);
+    let mut mip_images = Vec::with_capacity(num_of_mips);
+    for mip_index in 0..num_of_mips {
+      let (mip_width, mip_height) = Self::get_mip_size_for(width, height, mip_index);
+      mip_images.push(HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::STORAGE,
+        hala_gfx::HalaFormat::R16G16B16A16_SFLOAT,
+        mip_width,
+        mip_height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        &format!("bloom_mip_{}.image", mip_index),
+      )?);
+    }
+
+    // Each pass reads the source mip and writes the destination mip, so each one is a static descriptor set.
+    let stage_flags = hala_gfx::HalaShaderStageFlags::COMPUTE | hala_gfx::HalaShaderStageFlags::FRAGMENT;
+    let passes = Self::get_passes(num_of_mips);
+    let mut descriptor_sets = Vec::with_capacity(passes.len());
+    for (src_mip_index, dst_mip_index, pass_name) in passes.into_iter() {
+      let descriptor_set = HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            post_process_binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Color image.
+            post_process_binding(1, hala_gfx::HalaDescriptorType::STORAGE_IMAGE, stage_flags),  // Source mip image.
+            post_process_binding(2, hala_gfx::HalaDescriptorType::STORAGE_IMAGE, stage_flags),  // Destination mip image.
+          ],
+          &format!("bloom_{}_{}.descriptor_set_layout", pass_name, dst_mip_index),
+        )?,
+        0,
+        &format!("bloom_{}_{}.descriptor_set", pass_name, dst_mip_index),
+      )?;
+      descriptor_set.update_sampled_images(0, 0, &[&target.color_image]);
+      descriptor_set.update_storage_images(0, 1, &[&mip_images[src_mip_index]]);
+      descriptor_set.update_storage_images(0, 2, &[&mip_images[dst_mip_index]]);
+      descriptor_sets.push(descriptor_set);
+    }
+
+    let program = HalaComputeProgram::new(
+      Rc::clone(&context.logical_device),
+      &[&descriptor_sets[0].layout],
+      &HalaComputeProgramDesc {
+        shader_file_path: file_path.clone(),
+        push_constant_size: 16, // Mode, Threshold, Intensity and Padding.
+        bindings: Vec::new(),
+      },
+      Some(pipeline_cache),
+      "bloom",
+    )?;
+    let composite_graphics_pipeline = create_full_screen_graphics_pipeline(
+      context,
+      &[&descriptor_sets[0].layout],
+      &[hala_gfx::HalaPushConstantRange {
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: 16, // Mode, Threshold, Intensity and Padding.
+      }],
+      self.composite_shaders.as_slice(),
+      depth_compare_op,
+      pipeline_cache,
+      "bloom_composite",
+    )?;
+
+    self.target = Some(target);
+    self.mip_images = mip_images;
+    self.descriptor_sets = descriptor_sets;
+    self.program = Some(program);
+    self.composite_graphics_pipeline = Some(composite_graphics_pipeline);
+
+    Ok(())
+  }
+
+  /// Whether the bloom is enabled and its resources are committed.
+  /// return: True if the bloom is active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.is_enabled && self.program.is_some()
+  }
+
+  /// Get the target which the scene renders into.
+  /// return: The target.
+  pub(crate) fn get_target(&self) -> Result<&HalaPostProcessTarget, HalaRendererError> {
+    self.target.as_ref().ok_or(HalaRendererError::new("The bloom target is none!", None))
+  }
+
+  /// Get the push constants of the program and the composite pass.
+  /// param mode: The mode.
+  /// return: The push constants.
+  fn get_push_constants(&self, mode: u32) -> Vec<u8> {
+    [
+      mode.to_le_bytes(),
+      self.threshold.to_le_bytes(),
+      self.intensity.to_le_bytes(),
+      0u32.to_le_bytes(),
+    ].concat()
+  }
+
+  /// Build the mip chain from the bloom color image.
+  /// The bloom program runs 8 x 8 groups over the destination mip with the push constants: mode, threshold, intensity and padding.
+  /// The rendering into the bloom color image must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The pipeline bind count.
+  pub(crate) fn prepare(&self, index: usize, command_buffers: &HalaCommandBufferSet, width: u32, height: u32) -> Result<u64, HalaRendererError> {
+    let program = self.program.as_ref().ok_or(HalaRendererError::new("The bloom program is none!", None))?;
+    let target = self.get_target()?;
+    let num_of_mips = self.mip_images.len();
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Bloom", [1.0, 1.0, 0.0, 1.0]);
+    }
+
+    let mut barriers = vec![target.get_read_barrier()];
+    for mip_image in self.mip_images.iter() {
+      barriers.push(hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: mip_image.raw,
+        ..Default::default()
+      });
+    }
+    command_buffers.set_image_barriers(index, barriers.as_slice());
+
+    let dispatches = Self::get_dispatches(num_of_mips);
+    let num_of_dispatches = dispatches.len();
+    for (set_index, dst_mip_index, mode) in dispatches.into_iter() {
+      let descriptor_set = self.descriptor_sets.get(set_index).ok_or(HalaRendererError::new("The bloom descriptor set is none!", None))?;
+      let (mip_width, mip_height) = Self::get_mip_size_for(width, height, dst_mip_index);
+      program.bind(index, command_buffers, &[descriptor_set]);
+      program.push_constants(index, command_buffers, 0, self.get_push_constants(mode).as_slice());
+      program.dispatch(index, command_buffers, (mip_width + 7) / 8, (mip_height + 7) / 8, 1);
+
+      command_buffers.set_image_barriers(
+        index,
+        &[hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::GENERAL,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: self.mip_images[dst_mip_index].raw,
+          ..Default::default()
+        }],
+      );
+    }
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    Ok(num_of_dispatches as u64)
+  }
+
+  /// Composite the bloom with the bloom color into the current rendering.
+  /// The composite pass reads the color image and the mip 0(the destination of the set 0) with the push constants of the mode 0,
+  /// and draws a full screen triangle strip of 4 vertices.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.composite_graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The bloom composite graphics pipeline is none!", None))?;
+    let descriptor_set = self.descriptor_sets.first().ok_or(HalaRendererError::new("The bloom descriptor set is none!", None))?;
+
+    bind_full_screen_pipeline(index, command_buffers, graphics_pipeline, descriptor_set);
+    command_buffers.push_constants(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      self.get_push_constants(0).as_slice(),
+    );
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_mip_count() {
+    // The smallest mip is at least 2 pixels and the chain is clamped to [1, BLOOM_MAX_MIP_COUNT].
+    assert_eq!(HalaBloomPass::get_mip_count_for(1920, 1080), BLOOM_MAX_MIP_COUNT as usize);
+    assert_eq!(HalaBloomPass::get_mip_count_for(32, 64), 4);
+    assert_eq!(HalaBloomPass::get_mip_count_for(8, 8), 2);
+    assert_eq!(HalaBloomPass::get_mip_count_for(1, 1), 1);
+  }
+
+  #[test]
+  fn test_mip_size() {
+    assert_eq!(HalaBloomPass::get_mip_size_for(1920, 1080, 0), (960, 540));
+    assert_eq!(HalaBloomPass::get_mip_size_for(1920, 1080, 5), (30, 16));
+    assert_eq!(HalaBloomPass::get_mip_size_for(3, 2, 3), (1, 1));
+  }
+
+  #[test]
+  fn test_passes_and_dispatches() {
+    let passes = HalaBloomPass::get_passes(3);
+    assert_eq!(passes, [(0, 0, "down"), (0, 1, "down"), (1, 2, "down"), (1, 0, "up"), (2, 1, "up")]);
+
+    // Each dispatch writes the destination mip of its descriptor set.
+    let dispatches = HalaBloomPass::get_dispatches(3);
+    assert_eq!(dispatches, [(0, 0, 0), (1, 1, 1), (2, 2, 1), (4, 1, 2), (3, 0, 2)]);
+    for (set_index, dst_mip_index, _) in dispatches.iter() {
+      assert_eq!(passes[*set_index].1, *dst_mip_index);
+    }
+  }
+
+}
@@ -0,0 +1,230 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::color_grading::HalaColorGradingLUT;
+
+use super::post_process::{
+  HalaPostProcessTarget,
+  load_vertex_fragment_shaders,
+  create_full_screen_graphics_pipeline,
+  post_process_binding,
+  bind_full_screen_pipeline,
+};
+
+/// The color grading pass, the tone mapped color is rendered into the color grading color image,
+/// then the color grading pass samples the LUT into the next target.
+pub(crate) struct HalaColorGradingPass {
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) lut: Option<HalaColorGradingLUT>,
+  pub(crate) target: Option<HalaPostProcessTarget>,
+  pub(crate) descriptor_set: Option<HalaDescriptorSet>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+}
+
+/// The Default implementation of the color grading pass.
+impl Default for HalaColorGradingPass {
+  fn default() -> Self {
+    Self {
+      shaders: Vec::new(),
+      lut: None,
+      target: None,
+      descriptor_set: None,
+      graphics_pipeline: None,
+    }
+  }
+}
+
+/// The implementation of the color grading pass.
+impl HalaColorGradingPass {
+
+  /// Set the shaders of the color grading, they must be set before commit.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if self.graphics_pipeline.is_some() {
+      return Err(HalaRendererError::new("The color grading shaders must be set before commit!", None));
+    }
+
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+
+    Ok(())
+  }
+
+  /// Whether the shaders are set, the resources are created by commit if they are.
+  /// return: True if the shaders are set.
+  pub(crate) fn has_shaders(&self) -> bool {
+    !self.shaders.is_empty()
+  }
+
+  /// Whether the descriptor set is committed, it is used by the in-flight frames.
+  /// return: True if the descriptor set is committed.
+  pub(crate) fn is_committed(&self) -> bool {
+    self.descriptor_set.is_some()
+  }
+
+  /// Replace the LUT and rewrite the LUT binding of the committed descriptor set.
+  /// The descriptor set must not be used by the in-flight frames.
+  /// param lut: The new LUT, none to disable the color grading.
+  /// return: The old LUT.
+  pub(crate) fn replace_lut(&mut self, lut: Option<HalaColorGradingLUT>) -> Option<HalaColorGradingLUT> {
+    let old_lut = std::mem::replace(&mut self.lut, lut);
+
+    if let (Some(descriptor_set), Some(lut)) = (self.descriptor_set.as_ref(), self.lut.as_ref()) {
+      descriptor_set.update_combined_image_samplers(0, 1, &[(&lut.image, &lut.sampler)]);
+    }
+
+    old_lut
+  }
+
+  /// Get the descriptors and the descriptor set count reserved by commit.
+  /// return: The descriptor sizes and the set count.
+  pub(crate) fn get_descriptor_sizes(&self) -> (Vec<(hala_gfx::HalaDescriptorType, usize)>, usize) {
+    if self.has_shaders() {
+      (
+        vec![
+          (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, 1),
+          (hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+        ],
+        1,
+      )
+    } else {
+      (Vec::new(), 0)
+    }
+  }
+
+  /// Create the target, the descriptor set and the pipeline, the previous ones are replaced.
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+  ) -> Result<(), HalaRendererError> {
+    if !self.has_shaders() {
+      return Ok(());
+    }
+
+    let target = HalaPostProcessTarget::new(
+      context,
+      width,
+      height,
+      hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      false,
+      "color_grading",
+    )?;
+    let descriptor_set = HalaDescriptorSet::new_static(
+      Rc::clone(&context.logical_device),
+      descriptor_pool,
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&context.logical_device),
+        &[
+          post_process_binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, hala_gfx::HalaShaderStageFlags::FRAGMENT),  // Color image.
+          post_process_binding(1, hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER, hala_gfx::HalaShaderStageFlags::FRAGMENT),  // LUT image.
+        ],
+        "color_grading.descriptor_set_layout",
+      )?,
+      0,
+      "color_grading.descriptor_set",
+    )?;
+    descriptor_set.update_sampled_images(0, 0, &[&target.color_image]);
+    if let Some(lut) = self.lut.as_ref() {
+      descriptor_set.update_combined_image_samplers(0, 1, &[(&lut.image, &lut.sampler)]);
+    }
+
+    let graphics_pipeline = create_full_screen_graphics_pipeline(
+      context,
+      &[&descriptor_set.layout],
+      &[hala_gfx::HalaPushConstantRange {
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: 8 * std::mem::size_of::<f32>() as u32,
+      }],
+      self.shaders.as_slice(),
+      depth_compare_op,
+      pipeline_cache,
+      "color_grading",
+    )?;
+
+    self.target = Some(target);
+    self.descriptor_set = Some(descriptor_set);
+    self.graphics_pipeline = Some(graphics_pipeline);
+
+    Ok(())
+  }
+
+  /// Whether the LUT is set and the color grading resources are committed.
+  /// return: True if the color grading is active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.lut.is_some() && self.graphics_pipeline.is_some()
+  }
+
+  /// Get the target which the previous pass renders into.
+  /// return: The target.
+  pub(crate) fn get_target(&self) -> Result<&HalaPostProcessTarget, HalaRendererError> {
+    self.target.as_ref().ok_or(HalaRendererError::new("The color grading target is none!", None))
+  }
+
+  /// Prepare the color grading color image to be read, the rendering into it must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn prepare(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    self.get_target()?.set_read_barrier(index, command_buffers);
+
+    Ok(0)
+  }
+
+  /// Draw the color grading into the current rendering.
+  /// The color grading shaders draw a full screen triangle strip of 4 vertices with the color grading set at the set 0,
+  /// the push constants are the domain min with the LUT size in w and the domain max(2 x vec4).
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The color grading graphics pipeline is none!", None))?;
+    let descriptor_set = self.descriptor_set.as_ref().ok_or(HalaRendererError::new("The color grading descriptor set is none!", None))?;
+    let lut = self.lut.as_ref().ok_or(HalaRendererError::new("The color grading LUT is none!", None))?;
+
+    bind_full_screen_pipeline(index, command_buffers, graphics_pipeline, descriptor_set);
+    command_buffers.push_constants_f32(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      &lut.get_push_constants(),
+    );
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
@@ -0,0 +1,292 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorSet,
+  HalaDescriptorSetLayout,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaRenderPass,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+
+use super::post_process::load_vertex_fragment_shaders;
+use super::{
+  HalaOverlayView,
+  set_view_viewport,
+};
+
+/// The settings of the infinite grid on the XZ plane.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaGridSettings {
+  /// The size of the grid cells in the world space.
+  pub cell_size: f32,
+  /// The color of the grid lines, the alpha is used for the blending.
+  pub color: [f32; 4],
+  /// The distance from the camera where the grid is faded out.
+  pub fade_distance: f32,
+}
+
+/// The Default implementation of the grid settings.
+impl Default for HalaGridSettings {
+  fn default() -> Self {
+    Self {
+      cell_size: 1.0,
+      color: [0.5, 0.5, 0.5, 1.0],
+      fade_distance: 100.0,
+    }
+  }
+}
+
+/// The implementation of the grid settings.
+impl HalaGridSettings {
+
+  /// Check the settings.
+  /// return: The result.
+  pub(crate) fn validate(&self) -> Result<(), HalaRendererError> {
+    if self.cell_size <= 0.0 || self.fade_distance <= 0.0 {
+      return Err(HalaRendererError::new(
+        &format!("The grid cell size {} and fade distance {} must be positive!", self.cell_size, self.fade_distance), None));
+    }
+
+    Ok(())
+  }
+
+  /// Get the push constants of the grid pass: the grid color, the cell size and the fade distance.
+  /// return: The push constants.
+  pub(crate) fn get_push_constants(&self) -> Vec<u8> {
+    let mut push_constants = Vec::with_capacity(24);
+    for value in self.color.iter().chain([self.cell_size, self.fade_distance].iter()) {
+      push_constants.extend_from_slice(&value.to_le_bytes());
+    }
+    push_constants
+  }
+
+}
+
+/// The grid pass draws an infinite grid on the XZ plane with a full screen triangle strip after the scene.
+/// The forward fragment shader writes the depth of the plane, so the depth test keeps the scene in front of the grid.
+/// The deferred fragment shader reads the G-Buffer depth from the lighting descriptor set and discards the occluded pixels.
+pub(crate) struct HalaGridPass {
+  pub(crate) is_enabled: bool,
+  pub(crate) settings: HalaGridSettings,
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+}
+
+/// The Default implementation of the grid pass.
+impl Default for HalaGridPass {
+  fn default() -> Self {
+    Self {
+      is_enabled: false,
+      settings: HalaGridSettings::default(),
+      shaders: Vec::new(),
+      graphics_pipeline: None,
+    }
+  }
+}
+
+/// The implementation of the grid pass.
+impl HalaGridPass {
+
+  /// Set the shaders of the grid pass.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+
+    Ok(())
+  }
+
+  /// Enable the grid with the settings.
+  /// param settings: The grid settings.
+  /// return: The result.
+  pub(crate) fn enable(&mut self, settings: HalaGridSettings) -> Result<(), HalaRendererError> {
+    settings.validate()?;
+
+    self.is_enabled = true;
+    self.settings = settings;
+
+    Ok(())
+  }
+
+  /// Create the graphics pipeline if the grid is enabled.
+  /// The deferred subpasses draw the grid in the lighting subpass, it reads the G-Buffer depth as the input attachment there.
+  /// param context: The GFX context.
+  /// param descriptor_set_layouts: The static layout, followed by the dynamic and the lighting layouts for the deferred rendering.
+  /// param use_deferred: Whether the grid is drawn after the deferred lighting.
+  /// param deferred_subpass: The deferred render pass and the depth format of the lighting subpass(the swapchain depth) if the deferred subpasses are used.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_set_layouts: &[&HalaDescriptorSetLayout],
+    use_deferred: bool,
+    deferred_subpass: Option<(&HalaRenderPass, hala_gfx::HalaFormat)>,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    if !self.is_enabled || self.shaders.is_empty() {
+      return Ok(());
+    }
+
+    let shaders = self.shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+    let multisample_state = if use_deferred {
+      hala_gfx::HalaMultisampleState::default()
+    } else {
+      hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false)
+    };
+    let push_constant_ranges = [hala_gfx::HalaPushConstantRange {
+      stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      offset: 0,
+      size: 24, // Grid color, cell size and fade distance.
+    }];
+    let graphics_pipeline = if let Some((deferred_render_pass, depth_format)) = deferred_subpass {
+      HalaGraphicsPipeline::with_renderpass_format_and_size(
+        Rc::clone(&context.logical_device),
+        &[context.swapchain.format],
+        Some(depth_format),
+        width,
+        height,
+        descriptor_set_layouts,
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+        &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+        &push_constant_ranges,
+        hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+        &[
+          hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+        ],
+        &[
+          hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        ],
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &multisample_state,
+        &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+        None,
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(deferred_render_pass),
+        1,
+        Some(pipeline_cache),
+        "grid_subpass.graphics_pipeline",
+      )?
+    } else {
+      HalaGraphicsPipeline::new(
+        Rc::clone(&context.logical_device),
+        &context.swapchain,
+        descriptor_set_layouts,
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+        &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+        &push_constant_ranges,
+        hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &multisample_state,
+        &hala_gfx::HalaDepthState::new(!use_deferred, false, depth_compare_op),
+        None,
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(pipeline_cache),
+        "grid.graphics_pipeline",
+      )?
+    };
+    self.graphics_pipeline = Some(graphics_pipeline);
+
+    Ok(())
+  }
+
+  /// Draw the infinite grid with a full screen triangle strip for each view.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param views: The views.
+  /// param lighting_descriptor_set: The lighting descriptor set of the deferred rendering, none for the forward rendering.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    views: &[HalaOverlayView],
+    lighting_descriptor_set: Option<&HalaDescriptorSet>,
+  ) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = match self.graphics_pipeline.as_ref() {
+      Some(graphics_pipeline) if self.is_enabled => graphics_pipeline,
+      _ => return Ok(0),
+    };
+
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.push_constants(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      self.settings.get_push_constants().as_slice(),
+    );
+    for view in views.iter() {
+      set_view_viewport(index, command_buffers, &view.view);
+      let mut descriptor_sets = vec![view.static_descriptor_set];
+      if let Some(lighting_descriptor_set) = lighting_descriptor_set {
+        descriptor_sets.push(view.dynamic_descriptor_set);
+        descriptor_sets.push(lighting_descriptor_set);
+      }
+      command_buffers.bind_graphics_descriptor_sets(
+        index,
+        graphics_pipeline,
+        0,
+        descriptor_sets.as_slice(),
+        &[],
+      );
+      command_buffers.draw(index, 4, 1, 0, 0);
+    }
+
+    Ok(1)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_grid_settings_validate() {
+    assert!(HalaGridSettings::default().validate().is_ok());
+    assert!(HalaGridSettings { cell_size: 0.0, ..Default::default() }.validate().is_err());
+    assert!(HalaGridSettings { fade_distance: -1.0, ..Default::default() }.validate().is_err());
+  }
+
+  #[test]
+  fn test_grid_push_constants() {
+    let settings = HalaGridSettings {
+      cell_size: 2.0,
+      color: [0.1, 0.2, 0.3, 0.4],
+      fade_distance: 50.0,
+    };
+    let push_constants = settings.get_push_constants();
+    assert_eq!(push_constants.len(), 24);
+    let values = push_constants.chunks_exact(4).map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap())).collect::<Vec<_>>();
+    assert_eq!(values, vec![0.1, 0.2, 0.3, 0.4, 2.0, 50.0]);
+  }
+
+}
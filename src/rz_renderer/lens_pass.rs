@@ -0,0 +1,201 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::lens_effects::HalaLensEffects;
+
+use super::post_process::{
+  HalaPostProcessTarget,
+  load_vertex_fragment_shaders,
+  create_full_screen_graphics_pipeline,
+  post_process_binding,
+  bind_full_screen_pipeline,
+};
+
+/// The lens pass, the final color is rendered into the lens color image,
+/// then the lens pass applies the vignette and the chromatic aberration into the swapchain.
+pub(crate) struct HalaLensPass {
+  pub(crate) effects: HalaLensEffects,
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) target: Option<HalaPostProcessTarget>,
+  pub(crate) descriptor_set: Option<HalaDescriptorSet>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+}
+
+/// The Default implementation of the lens pass.
+impl Default for HalaLensPass {
+  fn default() -> Self {
+    Self {
+      effects: HalaLensEffects::default(),
+      shaders: Vec::new(),
+      target: None,
+      descriptor_set: None,
+      graphics_pipeline: None,
+    }
+  }
+}
+
+/// The implementation of the lens pass.
+impl HalaLensPass {
+
+  /// Set the shaders of the lens effects, they must be set before commit.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if self.graphics_pipeline.is_some() {
+      return Err(HalaRendererError::new("The lens shaders must be set before commit!", None));
+    }
+
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+
+    Ok(())
+  }
+
+  /// Whether the shaders are set, the resources are created by commit if they are.
+  /// return: True if the shaders are set.
+  pub(crate) fn has_shaders(&self) -> bool {
+    !self.shaders.is_empty()
+  }
+
+  /// Get the descriptors and the descriptor set count reserved by commit.
+  /// return: The descriptor sizes and the set count.
+  pub(crate) fn get_descriptor_sizes(&self) -> (Vec<(hala_gfx::HalaDescriptorType, usize)>, usize) {
+    if self.has_shaders() {
+      (vec![(hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, 1)], 1)
+    } else {
+      (Vec::new(), 0)
+    }
+  }
+
+  /// Create the target, the descriptor set and the pipeline, the previous ones are replaced.
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+  ) -> Result<(), HalaRendererError> {
+    if !self.has_shaders() {
+      return Ok(());
+    }
+
+    let target = HalaPostProcessTarget::new(
+      context,
+      width,
+      height,
+      hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      false,
+      "lens",
+    )?;
+    let descriptor_set = HalaDescriptorSet::new_static(
+      Rc::clone(&context.logical_device),
+      descriptor_pool,
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&context.logical_device),
+        &[
+          post_process_binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, hala_gfx::HalaShaderStageFlags::FRAGMENT), // Color image.
+        ],
+        "lens.descriptor_set_layout",
+      )?,
+      0,
+      "lens.descriptor_set",
+    )?;
+    descriptor_set.update_sampled_images(0, 0, &[&target.color_image]);
+
+    let graphics_pipeline = create_full_screen_graphics_pipeline(
+      context,
+      &[&descriptor_set.layout],
+      &[hala_gfx::HalaPushConstantRange {
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+        offset: 0,
+        size: 4 * std::mem::size_of::<f32>() as u32,
+      }],
+      self.shaders.as_slice(),
+      depth_compare_op,
+      pipeline_cache,
+      "lens",
+    )?;
+
+    self.target = Some(target);
+    self.descriptor_set = Some(descriptor_set);
+    self.graphics_pipeline = Some(graphics_pipeline);
+
+    Ok(())
+  }
+
+  /// Whether any lens effect is enabled and the lens resources are committed.
+  /// return: True if the lens effects are active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.effects.is_enabled() && self.graphics_pipeline.is_some()
+  }
+
+  /// Get the target which the previous pass renders into.
+  /// return: The target.
+  pub(crate) fn get_target(&self) -> Result<&HalaPostProcessTarget, HalaRendererError> {
+    self.target.as_ref().ok_or(HalaRendererError::new("The lens target is none!", None))
+  }
+
+  /// Prepare the lens color image to be read, the rendering into it must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn prepare(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    self.get_target()?.set_read_barrier(index, command_buffers);
+
+    Ok(0)
+  }
+
+  /// Draw the lens effects into the current rendering.
+  /// The lens shaders draw a full screen triangle strip of 4 vertices with the lens set at the set 0,
+  /// the push constants are the vignette, the chromatic aberration, the width and the height(vec4).
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet, width: u32, height: u32) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The lens graphics pipeline is none!", None))?;
+    let descriptor_set = self.descriptor_set.as_ref().ok_or(HalaRendererError::new("The lens descriptor set is none!", None))?;
+
+    bind_full_screen_pipeline(index, command_buffers, graphics_pipeline, descriptor_set);
+    command_buffers.push_constants_f32(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      &self.effects.get_push_constants(width, height),
+    );
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
@@ -0,0 +1,360 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaBuffer,
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorSetLayout,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::scene::HalaBounds;
+
+use super::post_process::load_vertex_fragment_shaders;
+use super::{
+  HalaOverlayView,
+  set_view_viewport,
+};
+
+/// The segment count of the circles drawn by draw_sphere and draw_cone.
+const LINE_SPHERE_SEGMENTS: usize = 32;
+
+/// The vertex of the immediate lines, drawn as a line list.
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaLineVertex {
+  // The position in the world space.
+  pub position: [f32; 3],
+  // The color, the alpha is used for the blending.
+  pub color: [f32; 4],
+}
+
+/// The immediate lines are accumulated by draw_line, draw_aabb and draw_sphere, uploaded and cleared by each update.
+/// The vertex buffers are per swapchain image and grow with the line count.
+pub(crate) struct HalaLinePass {
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) vertices: Vec<HalaLineVertex>,
+  pub(crate) vertex_buffers: Vec<Option<HalaBuffer>>,
+  pub(crate) vertex_count: u32,
+  // The ranges of the point lights and the spot lights are drawn by the immediate lines in each update.
+  pub(crate) draw_light_ranges: bool,
+  pub(crate) light_range_color: [f32; 4],
+}
+
+/// The Default implementation of the line pass.
+impl Default for HalaLinePass {
+  fn default() -> Self {
+    Self {
+      shaders: Vec::new(),
+      graphics_pipeline: None,
+      vertices: Vec::new(),
+      vertex_buffers: Vec::new(),
+      vertex_count: 0,
+      draw_light_ranges: false,
+      light_range_color: [1.0, 0.8, 0.2, 1.0],
+    }
+  }
+}
+
+/// The implementation of the line pass.
+impl HalaLinePass {
+
+  /// Set the shaders of the immediate lines.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+
+    Ok(())
+  }
+
+  /// Create the graphics pipeline if the shaders are set.
+  /// The deferred lighting pass has no scene depth, so the lines are drawn on top.
+  /// param context: The GFX context.
+  /// param static_descriptor_set_layout: The static descriptor set layout of the renderer.
+  /// param use_deferred: Whether the lines are drawn after the deferred lighting.
+  /// param overlay_compare_op: The depth compare operation of the overlays, the equal depth passes.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    static_descriptor_set_layout: &HalaDescriptorSetLayout,
+    use_deferred: bool,
+    overlay_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    if self.shaders.is_empty() {
+      return Ok(());
+    }
+
+    let shaders = self.shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+    let multisample_state = if use_deferred {
+      hala_gfx::HalaMultisampleState::default()
+    } else {
+      hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false)
+    };
+    self.graphics_pipeline = Some(
+      HalaGraphicsPipeline::new(
+        Rc::clone(&context.logical_device),
+        &context.swapchain,
+        &[static_descriptor_set_layout],
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[
+          hala_gfx::HalaVertexInputAttributeDescription {
+            binding: 0,
+            location: 0,
+            offset: 0,
+            format: hala_gfx::HalaFormat::R32G32B32_SFLOAT, // Position.
+          },
+          hala_gfx::HalaVertexInputAttributeDescription {
+            binding: 0,
+            location: 1,
+            offset: 12,
+            format: hala_gfx::HalaFormat::R32G32B32A32_SFLOAT, // Color.
+          },
+        ],
+        &[
+          hala_gfx::HalaVertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<HalaLineVertex>() as u32,
+            input_rate: hala_gfx::HalaVertexInputRate::VERTEX,
+          }
+        ],
+        &[] as &[hala_gfx::HalaPushConstantRange],
+        hala_gfx::HalaPrimitiveTopology::LINE_LIST,
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &multisample_state,
+        &hala_gfx::HalaDepthState::new(!use_deferred, false, overlay_compare_op),
+        None,
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(pipeline_cache),
+        "line.graphics_pipeline",
+      )?
+    );
+
+    Ok(())
+  }
+
+  /// Add a line in the world space.
+  /// param a: The start point.
+  /// param b: The end point.
+  /// param color: The color of the line.
+  pub(crate) fn draw_line(&mut self, a: [f32; 3], b: [f32; 3], color: [f32; 4]) {
+    self.vertices.push(HalaLineVertex { position: a, color });
+    self.vertices.push(HalaLineVertex { position: b, color });
+  }
+
+  /// Add the 12 edges of an AABB in the world space.
+  /// param bounds: The AABB.
+  /// param color: The color of the lines.
+  pub(crate) fn draw_aabb(&mut self, bounds: &HalaBounds, color: [f32; 4]) {
+    let min = bounds.get_min();
+    let max = bounds.get_max();
+    let corner = |i: usize| [
+      if i & 1 == 0 { min[0] } else { max[0] },
+      if i & 2 == 0 { min[1] } else { max[1] },
+      if i & 4 == 0 { min[2] } else { max[2] },
+    ];
+    for i in 0..8 {
+      for axis in [1, 2, 4] {
+        if i & axis == 0 {
+          self.draw_line(corner(i), corner(i | axis), color);
+        }
+      }
+    }
+  }
+
+  /// Add a sphere as the 3 circles on the XY, the YZ and the XZ planes in the world space.
+  /// param center: The center of the sphere.
+  /// param radius: The radius of the sphere.
+  /// param color: The color of the lines.
+  pub(crate) fn draw_sphere(&mut self, center: [f32; 3], radius: f32, color: [f32; 4]) {
+    let point = |axes: (usize, usize), segment: usize| {
+      let angle = segment as f32 / LINE_SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+      let mut point = center;
+      point[axes.0] += radius * angle.cos();
+      point[axes.1] += radius * angle.sin();
+      point
+    };
+    for axes in [(0, 1), (1, 2), (0, 2)] {
+      for segment in 0..LINE_SPHERE_SEGMENTS {
+        self.draw_line(point(axes, segment), point(axes, segment + 1), color);
+      }
+    }
+  }
+
+  /// Add a cone with the apex, the axis direction, the height and the base radius in the world space.
+  /// param apex: The apex of the cone.
+  /// param direction: The direction from the apex to the base center.
+  /// param height: The distance from the apex to the base.
+  /// param radius: The radius of the base.
+  /// param color: The color of the lines.
+  pub(crate) fn draw_cone(&mut self, apex: [f32; 3], direction: [f32; 3], height: f32, radius: f32, color: [f32; 4]) {
+    let apex = glam::Vec3::from(apex);
+    let direction = glam::Vec3::from(direction).normalize_or_zero();
+    if direction == glam::Vec3::ZERO {
+      return;
+    }
+    let (tangent, bitangent) = direction.any_orthonormal_pair();
+    let center = apex + direction * height;
+    let point = |segment: usize| {
+      let angle = segment as f32 / LINE_SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+      (center + (tangent * angle.cos() + bitangent * angle.sin()) * radius).into()
+    };
+    for segment in 0..LINE_SPHERE_SEGMENTS {
+      self.draw_line(point(segment), point(segment + 1), color);
+    }
+    for segment in (0..LINE_SPHERE_SEGMENTS).step_by(LINE_SPHERE_SEGMENTS / 4) {
+      self.draw_line(apex.into(), point(segment), color);
+    }
+  }
+
+  /// Add the range spheres of the point lights and the range cones of the spot lights.
+  /// param lights: The light type(0 point, 2 spot), the position, the direction, the cosine of the outer angle and the range of the lights.
+  pub(crate) fn draw_lights(&mut self, lights: &[(u32, glam::Vec3, glam::Vec3, f32, f32)]) {
+    let color = self.light_range_color;
+    for &(light_type, position, direction, cos_outer, range) in lights {
+      if light_type == 0 {
+        self.draw_sphere(position.into(), range, color);
+      } else {
+        let cos_outer = cos_outer.clamp(0.0, 1.0);
+        let sin_outer = (1.0 - cos_outer * cos_outer).sqrt();
+        self.draw_cone(position.into(), direction.into(), range * cos_outer, range * sin_outer, color);
+      }
+    }
+  }
+
+  /// Upload the lines of this frame into the vertex buffer of the image and clear them.
+  /// param context: The GFX context.
+  /// param image_index: The index of the image.
+  /// param num_of_frames: The count of the swapchain images.
+  /// return: The replaced vertex buffer, it may be used by the in-flight frames.
+  pub(crate) fn upload(&mut self, context: &HalaContext, image_index: usize, num_of_frames: usize) -> Result<Option<HalaBuffer>, HalaRendererError> {
+    self.vertex_count = 0;
+    if self.graphics_pipeline.is_none() || self.vertices.is_empty() {
+      self.vertices.clear();
+      return Ok(None);
+    }
+
+    if self.vertex_buffers.len() < num_of_frames {
+      self.vertex_buffers.resize_with(num_of_frames, || None);
+    }
+    let size = (self.vertices.len() * std::mem::size_of::<HalaLineVertex>()) as u64;
+    let mut old_buffer = None;
+    if self.vertex_buffers[image_index].as_ref().map_or(true, |buffer| buffer.size < size) {
+      // Grow to the next power of two, so the buffer is not recreated for every new line.
+      let buffer = HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        size.next_power_of_two(),
+        hala_gfx::HalaBufferUsageFlags::VERTEX_BUFFER,
+        hala_gfx::HalaMemoryLocation::CpuToGpu,
+        &format!("line_{}.vertex_buffer", image_index),
+      )?;
+      old_buffer = self.vertex_buffers[image_index].replace(buffer);
+    }
+
+    let buffer = self.vertex_buffers[image_index].as_ref().ok_or(HalaRendererError::new("The line vertex buffer is none!", None))?;
+    buffer.update_memory(0, self.vertices.as_slice())?;
+    self.vertex_count = self.vertices.len() as u32;
+    self.vertices.clear();
+
+    Ok(old_buffer)
+  }
+
+  /// Draw the uploaded lines for each view.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param views: The views.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet, views: &[HalaOverlayView]) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = match self.graphics_pipeline.as_ref() {
+      Some(graphics_pipeline) if self.vertex_count > 0 => graphics_pipeline,
+      _ => return Ok(0),
+    };
+    let vertex_buffer = self.vertex_buffers.get(index).and_then(|buffer| buffer.as_ref())
+      .ok_or(HalaRendererError::new("The line vertex buffer is none!", None))?;
+
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.bind_vertex_buffers(index, 0, &[vertex_buffer], &[0]);
+    for view in views.iter() {
+      set_view_viewport(index, command_buffers, &view.view);
+      command_buffers.bind_graphics_descriptor_sets(
+        index,
+        graphics_pipeline,
+        0,
+        &[view.static_descriptor_set],
+        &[],
+      );
+      command_buffers.draw(index, self.vertex_count, 1, 0, 0);
+    }
+
+    Ok(1)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_line_vertex_counts() {
+    let color = [1.0, 1.0, 1.0, 1.0];
+    let mut pass = HalaLinePass::default();
+
+    pass.draw_line([0.0; 3], [1.0, 0.0, 0.0], color);
+    assert_eq!(pass.vertices.len(), 2);
+
+    pass.vertices.clear();
+    pass.draw_sphere([0.0; 3], 1.0, color);
+    assert_eq!(pass.vertices.len(), 3 * LINE_SPHERE_SEGMENTS * 2);
+
+    pass.vertices.clear();
+    pass.draw_cone([0.0; 3], [0.0, -1.0, 0.0], 1.0, 0.5, color);
+    assert_eq!(pass.vertices.len(), (LINE_SPHERE_SEGMENTS + 4) * 2);
+
+    // The cone without a direction is skipped.
+    pass.vertices.clear();
+    pass.draw_cone([0.0; 3], [0.0; 3], 1.0, 0.5, color);
+    assert!(pass.vertices.is_empty());
+  }
+
+  #[test]
+  fn test_sphere_points_on_radius() {
+    let mut pass = HalaLinePass::default();
+    pass.draw_sphere([1.0, 2.0, 3.0], 2.0, [1.0; 4]);
+    for vertex in pass.vertices.iter() {
+      let distance = (glam::Vec3::from(vertex.position) - glam::Vec3::new(1.0, 2.0, 3.0)).length();
+      assert!((distance - 2.0).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn test_light_ranges() {
+    let mut pass = HalaLinePass::default();
+    pass.draw_lights(&[
+      (0, glam::Vec3::ZERO, glam::Vec3::Z, 0.0, 5.0),
+      (2, glam::Vec3::ZERO, glam::Vec3::NEG_Y, 0.5, 5.0),
+    ]);
+    assert_eq!(pass.vertices.len(), 3 * LINE_SPHERE_SEGMENTS * 2 + (LINE_SPHERE_SEGMENTS + 4) * 2);
+    assert!(pass.vertices.iter().all(|vertex| vertex.color == pass.light_range_color));
+  }
+
+}
@@ -0,0 +1,6882 @@
+mod post_process;
+mod taa;
+mod ssr;
+mod bloom;
+mod fxaa;
+mod color_grading_pass;
+mod lens_pass;
+mod grid;
+mod axis_gizmo;
+mod lines;
+mod outline;
+mod overlay;
+mod shadow_pass;
+
+pub use grid::HalaGridSettings;
+pub use lines::HalaLineVertex;
+
+use std::rc::Rc;
+use std::path::Path;
+
+use ash::vk;
+
+use hala_gfx::{
+  HalaGPURequirements,
+  HalaSampleCountFlags,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+use crate::scene::{
+  cpu,
+  gpu,
+  loader,
+  picking,
+  HalaMeshletConfig,
+  HalaLod,
+  HalaLodConfig,
+  HalaDepthConvention,
+  HalaBounds,
+};
+
+use crate::texture_watcher::HalaTextureWatcher;
+use crate::color_grading::HalaColorGradingLUT;
+use crate::lens_effects::HalaLensEffects;
+use crate::depth_pyramid::HalaDepthPyramid;
+use crate::shadow::{
+  HalaShadowConfig,
+  HalaShadowAtlas,
+};
+use crate::secondary_recording::{
+  HalaSecondaryDraw,
+  HalaSecondaryDrawCall,
+  HalaSecondaryInheritance,
+  get_raw_descriptor_set,
+};
+
+use crate::renderer::{
+  HalaRendererInfo,
+  HalaRendererResources,
+  HalaRendererData,
+  HalaRendererStatistics,
+  HalaDrawCounters,
+  HalaRendererTrait,
+  HalaDebugView,
+  is_stencil_format,
+  get_depth_aspect_mask,
+  create_deferred_render_pass,
+  create_deferred_framebuffers,
+};
+
+use post_process::{
+  HalaPostProcessStage,
+  begin_swapchain_rendering,
+};
+use taa::HalaTAAPass;
+use ssr::{
+  HalaSSRGBuffer,
+  HalaSSRPass,
+};
+use bloom::HalaBloomPass;
+use fxaa::HalaFXAAPass;
+use color_grading_pass::HalaColorGradingPass;
+use lens_pass::HalaLensPass;
+use grid::HalaGridPass;
+use axis_gizmo::HalaAxisGizmoPass;
+use lines::HalaLinePass;
+use outline::HalaOutlinePass;
+use overlay::HalaOverlayPass;
+use shadow_pass::HalaShadowPass;
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaGlobalUniform {
+  // The view matrix.
+  pub v_mtx: glam::Mat4,
+  // The projection matrix.
+  pub p_mtx: glam::Mat4,
+  // The view-projection matrix.
+  pub vp_mtx: glam::Mat4,
+  // The inverse view-projection matrix.
+  pub i_vp_mtx: glam::Mat4,
+  // The camera position in the world space(w is unused), used by the task shader for meshlet cone culling.
+  pub camera_position: glam::Vec4,
+  // The debug view, the fragment and the lighting shaders output the debug data instead of the lit color if it is not 0.
+  pub debug_view: u32,
+}
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaObjectUniform {
+  // The model matrix.
+  pub m_mtx: glam::Mat4,
+  // The inverse model matrix.
+  pub i_m_mtx: glam::Mat4,
+  // The model-view matrix.
+  pub mv_mtx: glam::Mat4,
+  // The transposed model-view matrix.
+  pub t_mv_mtx: glam::Mat4,
+  // The inverse transposed model-view matrix.
+  pub it_mv_mtx: glam::Mat4,
+  // The model-view-projection matrix.
+  pub mvp_mtx: glam::Mat4,
+  // The model-view-projection matrix of the previous frame, jittered by the current TAA jitter.
+  // The motion vector is the difference of the positions transformed by mvp_mtx and prev_mvp_mtx.
+  pub prev_mvp_mtx: glam::Mat4,
+}
+
+/// The render path of the scene drawing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaRenderPath(u8);
+impl HalaRenderPath {
+  pub const TRADITIONAL: Self = Self(0);
+  pub const MESH_SHADER: Self = Self(1);
+  pub const SOFTWARE_MESHLET: Self = Self(2);
+  pub const VISIBILITY_BUFFER: Self = Self(3);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::TRADITIONAL,
+      1 => Self::MESH_SHADER,
+      2 => Self::SOFTWARE_MESHLET,
+      3 => Self::VISIBILITY_BUFFER,
+      _ => panic!("Invalid render path."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+}
+
+/// The pass which draws a primitive of the material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaMaterialPass(u8);
+impl HalaMaterialPass {
+  pub const FORWARD: Self = Self(0);
+  pub const DEFERRED: Self = Self(1);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::FORWARD,
+      1 => Self::DEFERRED,
+      _ => panic!("Invalid material pass."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Route a material to its pass, all materials are forward without the deferred rendering,
+  /// otherwise the opaque materials are deferred and the translucent ones are forward.
+  /// param use_deferred: Whether the deferred rendering is used.
+  /// param material_deferred: The deferred flag of the material.
+  /// return: The pass.
+  pub fn from_material(use_deferred: bool, material_deferred: bool) -> Self {
+    if use_deferred && material_deferred {
+      Self::DEFERRED
+    } else {
+      Self::FORWARD
+    }
+  }
+}
+
+/// The primitives of a material type in a pass, they are drawn after one pipeline bind.
+pub(crate) struct HalaDrawGroup {
+  pub(crate) material_type: usize,
+  // The mesh index, the primitive index and the draw index in the scene order.
+  pub(crate) draws: Vec<(usize, usize, u32)>,
+}
+
+/// The result of the depth readback picking, it matches what is on the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaDepthPickResult {
+  /// The depth in [0, 1] of the depth convention.
+  pub depth: f32,
+  /// The world space position reconstructed by the inverse view-projection matrix.
+  pub position: glam::Vec3,
+  /// The draw index read from the object ID image of the G-Buffer, none if the G-Buffer has no object ID image.
+  pub object_id: Option<u32>,
+}
+
+/// The max storage buffer count of the bindless descriptor set.
+const MAX_BINDLESS_STORAGE_BUFFERS: usize = 65536;
+
+/// The invalid storage buffer slot of the bindless primitive.
+const INVALID_BINDLESS_SLOT: u32 = u32::MAX;
+
+/// The default format of the G-Buffer normal image, the normals are octahedral encoded into two channels.
+/// See shaders/rz/octahedral_normal.hlsl for the encoding.
+const GBUFFER_PACKED_NORMAL_FORMAT: hala_gfx::HalaFormat = hala_gfx::HalaFormat::R16G16_SNORM;
+
+/// The sample counts from the highest to the lowest.
+const SAMPLE_COUNTS: [HalaSampleCountFlags; 7] = [
+  HalaSampleCountFlags::TYPE_64,
+  HalaSampleCountFlags::TYPE_32,
+  HalaSampleCountFlags::TYPE_16,
+  HalaSampleCountFlags::TYPE_8,
+  HalaSampleCountFlags::TYPE_4,
+  HalaSampleCountFlags::TYPE_2,
+  HalaSampleCountFlags::TYPE_1,
+];
+
+#[repr(C, align(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaBindlessPrimitive {
+  // The index of the object uniform buffer.
+  pub mesh_index: u32,
+  // The index of the material uniform buffer.
+  pub material_index: u32,
+  // The storage buffer slots, INVALID_BINDLESS_SLOT if the buffer is none.
+  pub vertex_buffer: u32,
+  pub index_buffer: u32,
+  pub meshlet_buffer: u32,
+  pub meshlet_vertex_buffer: u32,
+  pub meshlet_primitive_buffer: u32,
+  // The ranges of the primitive in the buffers.
+  pub vertex_offset: u32,
+  pub first_index: u32,
+  pub index_count: u32,
+  pub first_meshlet: u32,
+  pub meshlet_count: u32,
+}
+
+/// The view description of the multi-viewport rendering.
+#[derive(Debug, Clone, Copy)]
+pub struct HalaViewDesc {
+  // The camera index in the scene.
+  pub camera_index: u32,
+  // The viewport(x, y, width, height) in pixels.
+  pub viewport: (f32, f32, f32, f32),
+  // The scissor(x, y, width, height) in pixels.
+  pub scissor: (i32, i32, u32, u32),
+}
+
+/// The GPU resources of an additional view.
+pub(crate) struct HalaViewResources {
+  pub(crate) global_uniform_buffers: Vec<hala_gfx::HalaBuffer>,
+  pub(crate) static_descriptor_set: hala_gfx::HalaDescriptorSet,
+  pub(crate) dynamic_descriptor_set: hala_gfx::HalaDescriptorSet,
+  pub(crate) object_uniform_buffers: Vec<Vec<hala_gfx::HalaBuffer>>,
+}
+
+/// A view and its descriptor sets, the overlay passes draw each view with them.
+pub(crate) struct HalaOverlayView<'a> {
+  pub(crate) view: HalaViewDesc,
+  pub(crate) static_descriptor_set: &'a hala_gfx::HalaDescriptorSet,
+  pub(crate) dynamic_descriptor_set: &'a hala_gfx::HalaDescriptorSet,
+}
+
+/// The pipeline layout and the vertex input shared by the scene graphics pipelines, the overlay and the shadow passes.
+pub(crate) struct HalaScenePipelineLayout<'a> {
+  pub(crate) descriptor_set_layouts: [&'a hala_gfx::HalaDescriptorSetLayout; 3],
+  pub(crate) flags: hala_gfx::HalaPipelineCreateFlags,
+  pub(crate) vertex_attribute_descriptions: &'a [hala_gfx::HalaVertexInputAttributeDescription],
+  pub(crate) vertex_binding_descriptions: &'a [hala_gfx::HalaVertexInputBindingDescription],
+  // The draw push constants, the first range covers the draw stages.
+  pub(crate) push_constant_ranges: &'a [hala_gfx::HalaPushConstantRange],
+}
+
+/// Set the viewport and the scissor of a view, the viewport is flipped so the Y axis of the NDC is up.
+/// param index: The index of the current image.
+/// param command_buffers: The command buffers.
+/// param view: The view.
+pub(crate) fn set_view_viewport(index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, view: &HalaViewDesc) {
+  let (x, y, width, height) = view.viewport;
+  command_buffers.set_viewport(
+    index,
+    0,
+    &[
+      (
+        x,
+        y + height,
+        width,
+        -height, // For vulkan y is down.
+        0.,
+        1.
+      ),
+    ],
+  );
+  command_buffers.set_scissor(
+    index,
+    0,
+    &[view.scissor],
+  );
+}
+
+/// The renderer.
+pub struct HalaRenderer {
+
+  pub(crate) info: HalaRendererInfo,
+
+  pub(crate) use_mesh_shader: bool,
+  pub(crate) meshlet_config: HalaMeshletConfig,
+  pub(crate) optimize_meshes: bool,
+  pub(crate) use_mega_buffers: bool,
+  pub(crate) stream_textures: bool,
+  pub(crate) async_upload: bool,
+  pub(crate) depth_convention: HalaDepthConvention,
+  pub(crate) lod_config: HalaLodConfig,
+  pub(crate) lod_bias: f32,
+
+  pub(crate) color_multisample_image: Option<hala_gfx::HalaImage>,
+  pub(crate) depth_stencil_multisample_image: Option<hala_gfx::HalaImage>,
+
+  pub(crate) use_deferred: bool,
+  pub(crate) depth_image: Option<hala_gfx::HalaImage>,
+  pub(crate) albedo_image: Option<hala_gfx::HalaImage>,
+  pub(crate) normal_image: Option<hala_gfx::HalaImage>,
+  pub(crate) motion_vector_image: Option<hala_gfx::HalaImage>,
+  // The object IDs(draw index + 1, 0 is the background) of the G-buffer, the last color target if it exists.
+  pub(crate) object_id_image: Option<hala_gfx::HalaImage>,
+  // The visibility buffer replaces the albedo and normal images with the draw and triangle ids.
+  pub(crate) use_visibility_buffer: bool,
+  pub(crate) visibility_buffer_image: Option<hala_gfx::HalaImage>,
+
+  pub(crate) use_deferred_subpasses: bool,
+  pub(crate) deferred_render_pass: Option<hala_gfx::HalaRenderPass>,
+  pub(crate) deferred_framebuffers: Option<hala_gfx::HalaFrameBufferSet>,
+
+  pub(crate) lighting_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
+  pub(crate) lighting_vertex_shader: Option<hala_gfx::HalaShader>,
+  pub(crate) lighting_fragment_shader: Option<hala_gfx::HalaShader>,
+  pub(crate) lighting_graphics_pipeline: Option<hala_gfx::HalaGraphicsPipeline>,
+
+  // The software meshlet path rasterizes the meshlets into the visibility images by the compute shader,
+  // then the material pass shades the window by a full screen triangle strip.
+  pub(crate) software_raster_file_path: Option<String>,
+  pub(crate) software_material_shaders: Vec<hala_gfx::HalaShader>,
+  pub(crate) software_raster_program: Option<HalaComputeProgram>,
+  pub(crate) software_material_graphics_pipeline: Option<hala_gfx::HalaGraphicsPipeline>,
+  pub(crate) visibility_depth_image: Option<hala_gfx::HalaImage>,
+  pub(crate) visibility_id_image: Option<hala_gfx::HalaImage>,
+  pub(crate) visibility_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
+
+  // The TAA renders the scene with the jittered projection and resolves it into the history.
+  pub(crate) taa: HalaTAAPass,
+  pub(crate) use_transient_gbuffer: bool,
+  // The format of the G-Buffer depth image, the G-Buffer pass can write the stencil references if it has a stencil aspect.
+  pub(crate) gbuffer_depth_format: hala_gfx::HalaFormat,
+  // Whether the next create_gbuffer_images creates the object ID image.
+  pub(crate) use_gbuffer_object_id: bool,
+
+  // The SSR traces the G-buffer depth and composites the reflections over the deferred lighting.
+  pub(crate) ssr: HalaSSRPass,
+
+  // The depth pyramid reduces the G-buffer depth after the G-buffer pass for the occlusion culling.
+  pub(crate) depth_pyramid_file_path: Option<String>,
+  pub(crate) depth_pyramid: Option<HalaDepthPyramid>,
+
+  // The shadow pass renders the depth of the lights into the tiles of the shadow atlas before the scene.
+  // The atlas is bound at the bindings 3, 4 and 5 of the static descriptor sets, so all passes can sample the shadows.
+  pub(crate) shadow: HalaShadowPass,
+
+  // The post-process chain after the scene, see HalaPostProcessStage for the order.
+  pub(crate) bloom: HalaBloomPass,
+  pub(crate) fxaa: HalaFXAAPass,
+  pub(crate) color_grading: HalaColorGradingPass,
+  pub(crate) lens: HalaLensPass,
+
+  // The output images replace the swapchain as the scene target, the host samples the output color image after the frame.
+  pub(crate) output_size: Option<(u32, u32)>,
+  pub(crate) output_color_image: Option<hala_gfx::HalaImage>,
+  pub(crate) output_depth_image: Option<hala_gfx::HalaImage>,
+
+  // The unjittered view-projection matrices of the views and the mesh transforms of the previous frame, used by the motion vectors.
+  pub(crate) prev_vp_mtxs: Vec<glam::Mat4>,
+  pub(crate) prev_mesh_transforms: Vec<glam::Mat4>,
+
+  pub(crate) static_descriptor_set: hala_gfx::HalaDescriptorSet,
+  pub(crate) global_uniform_buffers: Vec<hala_gfx::HalaBuffer>,
+  pub(crate) dynamic_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
+  pub(crate) object_uniform_buffers: Vec<Vec<hala_gfx::HalaBuffer>>,
+
+  // The views and the resources of the additional views(the first view uses the main resources).
+  pub(crate) views: Vec<HalaViewDesc>,
+  // The view and the projection matrices of the first view set by the app, they replace its scene camera.
+  pub(crate) view_projection_override: Option<(glam::Mat4, glam::Mat4)>,
+  pub(crate) render_area: Option<(i32, i32, u32, u32)>,
+  // The clear values of the scene, they are read when the command buffers are recorded.
+  // The clear depth and stencil are none to clear to the far plane of the depth convention and 0.
+  pub(crate) clear_color: [f32; 4],
+  pub(crate) gbuffer_clear_color: [f32; 4],
+  pub(crate) clear_depth_stencil: Option<(f32, u32)>,
+  pub(crate) view_resources: Vec<HalaViewResources>,
+
+  // Vertex Shader, Fragment Shader.
+  pub(crate) traditional_shaders: Vec<(hala_gfx::HalaShader, hala_gfx::HalaShader)>,
+  // Task Shader, Mesh Shader and Fragment Shader.
+  pub(crate) shaders: Vec<(Option<hala_gfx::HalaShader>, hala_gfx::HalaShader, hala_gfx::HalaShader)>,
+  // Compute Shader.
+  pub(crate) compute_shaders: Vec<hala_gfx::HalaShader>,
+  // The optional stencil state of each material type pipeline.
+  pub(crate) stencil_states: Vec<Option<hala_gfx::HalaStencilState>>,
+
+  // The selection pass redraws the selected primitives(mesh index, primitive index) with the selection pipeline.
+  // The shaders are Vertex and Fragment Shader, or optional Task, Mesh and Fragment Shader.
+  pub(crate) selection_shaders: Vec<hala_gfx::HalaShader>,
+  pub(crate) selection_stencil_state: Option<hala_gfx::HalaStencilState>,
+  pub(crate) selection_graphics_pipeline: Option<hala_gfx::HalaGraphicsPipeline>,
+  pub(crate) selection: Vec<(usize, usize)>,
+
+  // The visibility and the override material index of each mesh, the meshes out of range are visible with their own materials.
+  pub(crate) mesh_visibilities: Vec<bool>,
+  pub(crate) mesh_material_overrides: Vec<Option<u32>>,
+  // The draw groups of each pass indexed by HalaMaterialPass::to_u8, sorted by the material type.
+  // They are rebuilt when the scene, the material overrides or the deferred flag change.
+  pub(crate) draw_groups: [Vec<HalaDrawGroup>; 2],
+
+  // The overlay passes are drawn over the scene, the outline after the selection pass.
+  pub(crate) outline: HalaOutlinePass,
+  pub(crate) overlay: HalaOverlayPass,
+  pub(crate) grid: HalaGridPass,
+  pub(crate) axis_gizmo: HalaAxisGizmoPass,
+  pub(crate) lines: HalaLinePass,
+
+  // The occlusion queries of each image are in the range [image index * max, (image index + 1) * max), one query per draw index.
+  // The query counts record how many queries of each image are written by its last recorded frame.
+  pub(crate) occlusion_query_pool: Option<hala_gfx::HalaQueryPool>,
+  pub(crate) max_occlusion_queries: usize,
+  pub(crate) occlusion_query_counts: Vec<usize>,
+  pub(crate) occlusion_results: Vec<u64>,
+
+  // The two phase occlusion culling of the mesh shader path, the task shaders cull the meshlets against the depth pyramid.
+  // The visibility buffer keeps one bit per meshlet of each primitive from its visibility offset, it is persistent across the frames.
+  // The stats buffers of each image count the meshlets drawn by the first and the second phase.
+  pub(crate) use_occlusion_culling: bool,
+  pub(crate) occlusion_culling_visibility_buffer: Option<hala_gfx::HalaBuffer>,
+  pub(crate) occlusion_culling_visibility_offsets: Vec<u32>,
+  pub(crate) occlusion_culling_stats_buffers: Vec<hala_gfx::HalaBuffer>,
+
+  pub(crate) scene_in_gpu: Option<gpu::HalaScene>,
+
+  pub(crate) forward_graphics_pipelines: Vec<hala_gfx::HalaGraphicsPipeline>,
+  pub(crate) deferred_graphics_pipelines: Vec<hala_gfx::HalaGraphicsPipeline>,
+  // The overdraw pipelines add every fragment to the color without the depth test, one for each material type.
+  pub(crate) use_overdraw_view: bool,
+  pub(crate) overdraw_graphics_pipelines: Vec<hala_gfx::HalaGraphicsPipeline>,
+  pub(crate) debug_view: HalaDebugView,
+  // The textures descriptor set has one set for each frame, a replaced image marks all sets dirty,
+  // and the set of a frame is rewritten after its fence is signaled.
+  pub(crate) textures_descriptor_set: Option<hala_gfx::HalaDescriptorSet>,
+  pub(crate) textures_descriptor_dirty: Vec<bool>,
+
+  // The bindless mode replaces the textures descriptor set with the bindless descriptor set,
+  // and the primitive table is the first storage buffer of it.
+  // The bindless descriptor set is allocated from its own pool with the update after bind flag.
+  pub(crate) use_bindless: bool,
+  pub(crate) bindless_primitive_buffer: Option<hala_gfx::HalaBuffer>,
+  pub(crate) bindless_descriptor_pool: Option<Rc<std::cell::RefCell<hala_gfx::HalaDescriptorPool>>>,
+
+  // The pipelines are built with the setup state, so the setup methods are rejected after commit until recommit.
+  pub(crate) is_committed: bool,
+
+  pub(crate) data: HalaRendererData,
+  pub(crate) statistics: HalaRendererStatistics,
+  // The draw counters of the recording frame, the recording functions only borrow the renderer.
+  pub(crate) draw_counters: std::cell::Cell<HalaDrawCounters>,
+
+  pub(crate) resources: HalaRendererResources,
+
+}
+
+/// The Drop implementation of the renderer.
+impl Drop for HalaRenderer {
+
+  fn drop(&mut self) {
+    log::debug!("A HalaRenderer \"{}\" is dropped.", self.info().name);
+  }
+
+}
+
+/// The implementation of the renderer trait.
+impl HalaRendererTrait for HalaRenderer {
+
+  fn info(&self) -> &HalaRendererInfo {
+    &self.info
+  }
+
+  fn info_mut(&mut self) -> &mut HalaRendererInfo {
+    &mut self.info
+  }
+
+  fn resources(&self) -> &HalaRendererResources {
+    &self.resources
+  }
+
+  fn resources_mut(&mut self) -> &mut HalaRendererResources {
+    &mut self.resources
+  }
+
+  fn data(&self) -> &HalaRendererData {
+    &self.data
+  }
+
+  fn data_mut(&mut self) -> &mut HalaRendererData {
+    &mut self.data
+  }
+
+  fn statistics(&self) -> &HalaRendererStatistics {
+    &self.statistics
+  }
+
+  fn statistics_mut(&mut self) -> &mut HalaRendererStatistics {
+    &mut self.statistics
+  }
+
+  fn get_descriptor_sizes() -> Vec<(hala_gfx::HalaDescriptorType, usize)> {
+    vec![
+      (
+        hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+        8,
+      ),
+      (
+        hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+        32,
+      ),
+      (
+        hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+        256,
+      ),
+      (
+        hala_gfx::HalaDescriptorType::SAMPLED_IMAGE,
+        256,
+      ),
+      (
+        hala_gfx::HalaDescriptorType::SAMPLER,
+        256,
+      ),
+      (
+        hala_gfx::HalaDescriptorType::COMBINED_IMAGE_SAMPLER,
+        256,
+      ),
+    ]
+  }
+
+  /// Validate the renderer before creating any GPU resource in commit.
+  /// return: The result, the errors list all problems found.
+  fn validate(&self) -> Result<(), Vec<HalaRendererError>> {
+    let mut errors = Vec::new();
+
+    let scene = match self.scene_in_gpu.as_ref() {
+      Some(scene) => scene,
+      None => return Err(vec![HalaRendererError::validation("The scene in GPU is none! Call set_scene before commit.")]),
+    };
+
+    if scene.camera_view_matrices.is_empty() || scene.camera_proj_matrices.is_empty() {
+      errors.push(HalaRendererError::validation("There is no camera in the scene!"));
+    }
+
+    // Every material type referenced by the scene needs a shader(pipeline).
+    let num_of_shaders = if self.use_mesh_shader { self.shaders.len() } else { self.traditional_shaders.len() };
+    for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+      for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+        let material_type = match scene.material_types.get(primitive.material_index as usize) {
+          Some(material_type) => *material_type as usize,
+          None => {
+            errors.push(HalaRendererError::validation(
+              &format!("The material index {} of the primitive {} of the mesh {} is out of range!", primitive.material_index, primitive_index, mesh_index)));
+            continue;
+          },
+        };
+        if material_type >= num_of_shaders {
+          errors.push(HalaRendererError::validation(
+            &format!(
+              "The material type {} of the primitive {} of the mesh {} has no shader, only {} {} shaders are pushed!",
+              material_type, primitive_index, mesh_index, num_of_shaders, if self.use_mesh_shader { "mesh" } else { "traditional" })));
+        }
+        if self.use_meshlets() && !scene.use_mega_buffers() && (primitive.meshlet_vertex_buffer.is_none() || primitive.meshlet_primitive_buffer.is_none()) {
+          errors.push(HalaRendererError::validation(
+            &format!("The meshlet buffers of the primitive {} of the mesh {} are none, but the meshlets are used! Set the render path before set_scene.", primitive_index, mesh_index)));
+        }
+      }
+    }
+    if self.use_meshlets() && scene.use_mega_buffers() && (scene.mega_meshlet_vertex_buffer.is_none() || scene.mega_meshlet_primitive_buffer.is_none()) {
+      errors.push(HalaRendererError::validation("The mega meshlet buffers of the scene are none, but the meshlets are used! Set the render path before set_scene."));
+    }
+    if self.get_render_path() == HalaRenderPath::SOFTWARE_MESHLET {
+      if self.use_deferred {
+        errors.push(HalaRendererError::validation("The software meshlet path has its own material pass, it can not be used with the deferred rendering."));
+      }
+      if self.views.len() > 1 {
+        errors.push(HalaRendererError::validation("The software meshlet path only supports one view."));
+      }
+      if self.use_bindless {
+        errors.push(HalaRendererError::validation("The software meshlet path reads the meshlet buffers from the dynamic descriptor set, it can not be used with the bindless mode."));
+      }
+    }
+    if self.use_bindless && !self.resources.supports_bindless() {
+      errors.push(HalaRendererError::validation("The bindless mode requires the runtime descriptor array, the partially bound, the update after bind and the variable descriptor count features."));
+    }
+    if self.stencil_states.len() > num_of_shaders {
+      errors.push(HalaRendererError::validation(
+        &format!("The stencil states are set for {} material types, but only {} shaders are pushed!", self.stencil_states.len(), num_of_shaders)));
+    }
+    if !self.selection_shaders.is_empty() && self.use_deferred {
+      errors.push(HalaRendererError::validation("The selection pass is only supported by the forward rendering, the G-Buffer depth has no selection stencil."));
+    }
+    if !self.overlay.wireframe_shaders.is_empty() || !self.overlay.normal_line_shaders.is_empty() {
+      if self.use_deferred {
+        errors.push(HalaRendererError::validation("The overlay pass is only supported by the forward rendering, the overlay lines test the depth of the scene."));
+      }
+      if self.software_raster_file_path.is_some() {
+        errors.push(HalaRendererError::validation("The overlay pass is not supported by the software meshlet path, the scene depth is in the visibility image."));
+      }
+    }
+    if !self.lines.shaders.is_empty() && self.use_deferred_subpasses {
+      errors.push(HalaRendererError::validation("The immediate lines do not support the deferred subpasses."));
+    }
+    if self.grid.is_enabled {
+      if self.grid.shaders.is_empty() {
+        errors.push(HalaRendererError::validation("The grid is enabled, but the grid shaders are none! Call set_grid_shaders_with_file before commit."));
+      }
+      if self.software_raster_file_path.is_some() {
+        errors.push(HalaRendererError::validation("The grid is not supported by the software meshlet path, the scene depth is in the visibility image."));
+      }
+    }
+    if self.axis_gizmo.size > 0 && self.axis_gizmo.shaders.is_empty() {
+      errors.push(HalaRendererError::validation("The axis gizmo is enabled, but the axis gizmo shaders are none! Call set_axis_gizmo_shaders_with_file before commit."));
+    }
+    if self.shadow.config.is_some() {
+      if self.shadow.shaders.is_empty() {
+        errors.push(HalaRendererError::validation("The shadows are enabled, but the shadow shaders are none! Call set_shadow_shaders_with_file before commit."));
+      }
+      if self.software_raster_file_path.is_some() {
+        errors.push(HalaRendererError::validation("The shadows are not supported by the software meshlet path, it has no raster pipeline for the scene."));
+      }
+    }
+    if !self.outline.shaders.is_empty() {
+      if self.selection_shaders.is_empty() {
+        errors.push(HalaRendererError::validation("The outline pass tests the stencil written by the selection pass, call set_selection_shaders_with_file before commit."));
+      }
+      if !self.resources.context.borrow().swapchain.has_stencil {
+        errors.push(HalaRendererError::validation("The outline pass requires the stencil aspect, the depth stencil format of the swapchain has no stencil."));
+      }
+    }
+
+    if self.use_deferred {
+      if self.depth_image.is_none() || self.get_gbuffer_color_images().is_err() {
+        errors.push(HalaRendererError::validation("The deferred flag is setted, but the G-Buffer images are none! Call create_gbuffer_images or create_visibility_buffer_images before commit."));
+      }
+      if self.lighting_vertex_shader.is_none() || self.lighting_fragment_shader.is_none() || self.lighting_descriptor_set.is_none() {
+        errors.push(HalaRendererError::validation("The deferred flag is setted, but the lighting pass shaders are none! Call create_gbuffer_images before commit."));
+      }
+      if self.deferred_render_pass.is_none() && !self.use_visibility_buffer {
+        errors.push(HalaRendererError::validation("The deferred flag is setted, but the deferred render pass is none! Call create_deferred_render_pass before commit."));
+      }
+      // The G-Buffer and the deferred pipelines are single sample, enable_multisample rejects the deferred path but not the reverse order.
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("Deferred rendering does not support multisample, call disable_multisample before creating the G-Buffer images, use the FXAA or the TAA instead."));
+      }
+    }
+    if self.use_deferred_subpasses && self.use_visibility_buffer {
+      errors.push(HalaRendererError::validation("The visibility buffer does not support the deferred subpasses."));
+    }
+    if self.ssr.is_enabled {
+      if self.ssr.trace_file_path.is_none() {
+        errors.push(HalaRendererError::validation("The SSR is enabled, but the SSR shaders are none! Call set_ssr_shaders_with_file before commit."));
+      }
+      if !self.use_deferred || self.use_visibility_buffer {
+        errors.push(HalaRendererError::validation("The SSR reads the depth and the normal of the G-Buffer, call create_gbuffer_images before commit."));
+      }
+      if self.use_transient_gbuffer {
+        errors.push(HalaRendererError::validation("The SSR samples the G-Buffer, create the G-Buffer images without the transient flag."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The SSR does not support the deferred subpasses."));
+      }
+      if self.views.len() > 1 {
+        errors.push(HalaRendererError::validation("The SSR traces with the first view, it only supports one view."));
+      }
+    }
+    if self.depth_pyramid_file_path.is_some() {
+      if !self.use_deferred || self.use_visibility_buffer {
+        errors.push(HalaRendererError::validation("The depth pyramid reduces the G-Buffer depth, call create_gbuffer_images before commit."));
+      }
+      if self.use_transient_gbuffer {
+        errors.push(HalaRendererError::validation("The depth pyramid samples the G-Buffer depth, create the G-Buffer images without the transient flag."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The depth pyramid does not support the deferred subpasses."));
+      }
+    }
+    if self.use_occlusion_culling {
+      if !self.use_mesh_shader {
+        errors.push(HalaRendererError::validation("The occlusion culling runs in the task shaders, it requires the mesh shader."));
+      }
+      if self.depth_pyramid_file_path.is_none() {
+        errors.push(HalaRendererError::validation("The occlusion culling is enabled, but the depth pyramid shader is none! Call set_depth_pyramid_shader_with_file before commit."));
+      }
+      if self.views.len() > 1 {
+        errors.push(HalaRendererError::validation("The occlusion culling keeps the meshlet visibility of the first view, it only supports one view."));
+      }
+    }
+    if self.bloom.is_enabled {
+      if self.bloom.file_path.is_none() {
+        errors.push(HalaRendererError::validation("The bloom is enabled, but the bloom shaders are none! Call set_bloom_shaders_with_file before commit."));
+      }
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("The bloom renders the scene into the single sample bloom color image, call disable_multisample before enabling the bloom."));
+      }
+      if self.taa.is_enabled {
+        errors.push(HalaRendererError::validation("The bloom does not support the TAA, the TAA present pass writes the swapchain directly."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The bloom does not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+      }
+    }
+    if self.fxaa.is_enabled {
+      if self.fxaa.shaders.is_empty() {
+        errors.push(HalaRendererError::validation("The FXAA is enabled, but the FXAA shaders are none! Call set_fxaa_shaders_with_file before commit."));
+      }
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("The FXAA replaces the multisample, call disable_multisample before enabling the FXAA."));
+      }
+      if self.taa.is_enabled {
+        errors.push(HalaRendererError::validation("The FXAA does not support the TAA, the TAA present pass writes the swapchain directly."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The FXAA does not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+      }
+    }
+    if !self.color_grading.shaders.is_empty() {
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("The color grading renders the scene into the single sample color grading image, call disable_multisample before setting the color grading shaders."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The color grading does not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+      }
+    }
+    if !self.lens.shaders.is_empty() {
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("The lens effects render the scene into the single sample lens color image, call disable_multisample before setting the lens shaders."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The lens effects do not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+      }
+    }
+    if self.use_deferred_subpasses && self.motion_vector_image.is_some() {
+      errors.push(HalaRendererError::validation("The motion vectors do not support the deferred subpasses, the subpass attachments are not stored."));
+    }
+    if self.use_deferred_subpasses && self.object_id_image.is_some() {
+      errors.push(HalaRendererError::validation("The object IDs do not support the deferred subpasses, the subpass attachments are not stored."));
+    }
+    if self.taa.is_enabled {
+      if self.taa.resolve_file_path.is_none() {
+        errors.push(HalaRendererError::validation("The TAA is enabled, but the TAA shaders are none! Call set_taa_shaders_with_file before commit."));
+      }
+      if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        errors.push(HalaRendererError::validation("The TAA replaces the multisample, call disable_multisample before enabling the TAA."));
+      }
+      if self.views.len() > 1 {
+        errors.push(HalaRendererError::validation("The TAA reprojects the history with the first view, it only supports one view."));
+      }
+      if self.use_deferred_subpasses {
+        errors.push(HalaRendererError::validation("The TAA does not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+      }
+      if self.use_deferred && self.use_transient_gbuffer {
+        errors.push(HalaRendererError::validation("The TAA reads the G-Buffer depth, create the G-Buffer images without the transient flag."));
+      }
+    }
+    errors.extend(self.validate_output_image());
+    if self.use_deferred_subpasses && (self.deferred_render_pass.is_none() || self.deferred_framebuffers.is_none()) {
+      errors.push(HalaRendererError::validation("The deferred subpasses flag is setted, but the deferred render pass or framebuffers are none! Call create_deferred_render_pass and create_deferred_framebuffers before commit."));
+    }
+
+    if errors.is_empty() {
+      Ok(())
+    } else {
+      Err(errors)
+    }
+  }
+
+  /// Validate the output image against the other features.
+  /// The post-processing passes write the swapchain directly, so they can not be combined with the output image.
+  /// return: The validation errors.
+  fn validate_output_image(&self) -> Vec<HalaRendererError> {
+    let mut errors = Vec::new();
+    let (width, height) = match self.output_size {
+      Some(size) => size,
+      None => return errors,
+    };
+
+    if self.resources.context.borrow().multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+      errors.push(HalaRendererError::validation("The output image is single sample, call disable_multisample before setting the output image."));
+    }
+    if self.taa.is_enabled || self.bloom.is_enabled || self.fxaa.is_enabled || self.ssr.trace_file_path.is_some() || !self.color_grading.shaders.is_empty() || !self.lens.shaders.is_empty() {
+      errors.push(HalaRendererError::validation("The output image does not support the TAA, the SSR, the bloom, the FXAA, the color grading and the lens effects, their passes write the swapchain directly."));
+    }
+    if self.use_deferred_subpasses {
+      errors.push(HalaRendererError::validation("The output image does not support the deferred subpasses, the lighting subpass writes the swapchain directly."));
+    }
+    if self.use_deferred && (width > self.info.width || height > self.info.height) {
+      errors.push(HalaRendererError::validation(
+        &format!("The output image size {}x{} is larger than the G-Buffer size {}x{}.", width, height, self.info.width, self.info.height)));
+    }
+
+    errors
+  }
+
+  /// Commit all GPU resources.
+  /// return: The result.
+  fn commit(&mut self) -> Result<(), HalaRendererError> {
+    self.validate().map_err(|errors| HalaRendererError::with_errors("The renderer is invalid:", errors))?;
+
+    // Reserve the descriptors required by the scene before creating the descriptor sets.
+    // The descriptor sets of the previous commit are replaced, so their reservations are reset.
+    self.resources.reset_descriptor_reservations()?;
+    {
+      let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+      let num_of_frames = self.resources.num_of_frames;
+      let mut required_sizes = self.get_dynamic_descriptor_sizes()?;
+      // The bindless descriptor set replaces the textures descriptor set and is allocated from the bindless descriptor pool.
+      if !self.use_bindless {
+        required_sizes.push((hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, scene.textures.len() * num_of_frames));
+        required_sizes.push((hala_gfx::HalaDescriptorType::SAMPLER, scene.textures.len() * num_of_frames));
+      }
+      if self.software_raster_file_path.is_some() {
+        required_sizes.push((hala_gfx::HalaDescriptorType::STORAGE_IMAGE, 2));
+      }
+      let num_of_depth_pyramid_sets = if self.depth_pyramid_file_path.is_some() { HalaDepthPyramid::get_mip_count_for(self.info.width, self.info.height) } else { 0 };
+      if num_of_depth_pyramid_sets > 0 {
+        required_sizes.push((hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, num_of_depth_pyramid_sets));
+        required_sizes.push((hala_gfx::HalaDescriptorType::STORAGE_IMAGE, num_of_depth_pyramid_sets * 2));
+      }
+      // The post-process passes reserve their own descriptor sets.
+      let mut num_of_post_process_sets = 0;
+      for (sizes, num_of_sets) in [
+        self.taa.get_descriptor_sizes(),
+        self.ssr.get_descriptor_sizes(),
+        self.bloom.get_descriptor_sizes(self.info.width, self.info.height),
+        self.fxaa.get_descriptor_sizes(),
+        self.color_grading.get_descriptor_sizes(),
+        self.lens.get_descriptor_sizes(),
+      ] {
+        required_sizes.extend(sizes);
+        num_of_post_process_sets += num_of_sets;
+      }
+      self.resources.reserve_descriptors(
+        &required_sizes,
+        num_of_frames * if self.use_bindless { 1 } else { 2 }
+          + if self.software_raster_file_path.is_some() { 1 } else { 0 }
+          + num_of_depth_pyramid_sets
+          + num_of_post_process_sets,
+      )?;
+    }
+
+    let context = self.resources.context.borrow();
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let depth_compare_op = self.get_depth_compare_op();
+
+    // Assert camera count.
+    if scene.camera_view_matrices.is_empty() || scene.camera_proj_matrices.is_empty() {
+      return Err(HalaRendererError::validation("There is no camera in the scene!"));
+    }
+
+    // Create the visibility and the stats buffers of the occlusion culling before the dynamic descriptor set.
+    // The zero visibility draws nothing in the first phase of the first frame, the second phase tests all meshlets.
+    if self.use_occlusion_culling {
+      let mut visibility_offsets = Vec::new();
+      let mut num_of_meshlets = 0u32;
+      for mesh in scene.meshes.iter() {
+        for primitive in mesh.primitives.iter() {
+          visibility_offsets.push(num_of_meshlets);
+          // The levels of detail are in the meshlet range of the primitive.
+          num_of_meshlets += primitive.lods.iter()
+            .map(|lod| lod.first_meshlet + lod.meshlet_count)
+            .fold(primitive.meshlet_count, u32::max);
+        }
+      }
+      let visibility_words = vec![0u32; (num_of_meshlets as usize + 31) / 32 + 1];
+      let visibility_buffer = hala_gfx::HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (visibility_words.len() * std::mem::size_of::<u32>()) as u64,
+        hala_gfx::HalaBufferUsageFlags::STORAGE_BUFFER | hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "occlusion_culling_visibility.buffer",
+      )?;
+      visibility_buffer.update_gpu_memory_with_buffer(
+        visibility_words.as_slice(),
+        &self.resources.transfer_staging_buffer,
+        &self.resources.transfer_command_buffers,
+      )?;
+      let mut stats_buffers = Vec::with_capacity(self.resources.num_of_frames);
+      for index in 0..self.resources.num_of_frames {
+        let stats_buffer = hala_gfx::HalaBuffer::new(
+          Rc::clone(&context.logical_device),
+          (2 * std::mem::size_of::<u32>()) as u64,
+          hala_gfx::HalaBufferUsageFlags::STORAGE_BUFFER,
+          hala_gfx::HalaMemoryLocation::GpuToCpu,
+          &format!("occlusion_culling_stats_{}.buffer", index),
+        )?;
+        stats_buffer.update_memory(0, &[0u32; 2])?;
+        stats_buffers.push(stats_buffer);
+      }
+      self.occlusion_culling_visibility_buffer = Some(visibility_buffer);
+      self.occlusion_culling_visibility_offsets = visibility_offsets;
+      self.occlusion_culling_stats_buffers = stats_buffers;
+    }
+
+    // Create object uniform buffers and dynamic descriptor set.
+    let object_uniform_buffers = self.create_object_uniform_buffers("object")?;
+    let dynamic_descriptor_set = self.create_dynamic_descriptor_set(&object_uniform_buffers, "main")?;
+    self.object_uniform_buffers = object_uniform_buffers;
+
+    // Update static descriptor set.
+    Self::update_static_descriptor_set(&self.static_descriptor_set, &self.global_uniform_buffers, scene);
+
+    // Create texture descriptor set.
+    // In the bindless mode, it is the bindless descriptor set and the shaders declare the set 2 as:
+    //   [[vk::binding(0, 2)]] Texture2D g_textures[];
+    //   [[vk::binding(1, 2)]] SamplerState g_samplers[];
+    //   [[vk::binding(2, 2)]] StructuredBuffer<T> g_buffers[];
+    // The g_buffers[0] is the primitive table(HalaBindlessPrimitive) indexed by the draw index in the push constants,
+    // the other slots of the primitive are read from the table, the same binding can be aliased by different element types.
+    // The device features are checked by validate, the layout is created with the update after bind pool flag,
+    // so it is allocated from the bindless descriptor pool which has the matching flag.
+    let (bindless_buffers, bindless_primitives) = if self.use_bindless {
+      self.collect_bindless_buffers(scene)?
+    } else {
+      (Vec::new(), Vec::new())
+    };
+    let mut textures_bindings = vec![
+      hala_gfx::HalaDescriptorSetLayoutBinding { // All textures in the scene.
+        binding_index: 0,
+        descriptor_type: hala_gfx::HalaDescriptorType::SAMPLED_IMAGE,
+        descriptor_count: scene.textures.len() as u32,
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+          | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+        binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          | (if self.use_bindless { hala_gfx::HalaDescriptorBindingFlags::UPDATE_AFTER_BIND } else { hala_gfx::HalaDescriptorBindingFlags::default() }),
+      },
+      hala_gfx::HalaDescriptorSetLayoutBinding { // All samplers in the scene.
+        binding_index: 1,
+        descriptor_type: hala_gfx::HalaDescriptorType::SAMPLER,
+        descriptor_count: scene.textures.len() as u32,
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+          | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+        binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          | (if self.use_bindless { hala_gfx::HalaDescriptorBindingFlags::UPDATE_AFTER_BIND } else { hala_gfx::HalaDescriptorBindingFlags::default() }),
+      },
+    ];
+    if self.use_bindless {
+      // Only the last binding can have the variable descriptor count.
+      textures_bindings.push(hala_gfx::HalaDescriptorSetLayoutBinding { // The primitive table and all storage buffers in the scene.
+        binding_index: 2,
+        descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+        descriptor_count: MAX_BINDLESS_STORAGE_BUFFERS as u32,
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+          | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+        binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          | hala_gfx::HalaDescriptorBindingFlags::UPDATE_AFTER_BIND
+          | hala_gfx::HalaDescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+      });
+    }
+    let (textures_descriptor_pool, textures_descriptor_set_layout) = if self.use_bindless {
+      let num_of_frames = self.resources.num_of_frames;
+      let descriptor_pool = Rc::new(std::cell::RefCell::new(hala_gfx::HalaDescriptorPool::with_flags(
+        Rc::clone(&context.logical_device),
+        &[
+          (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, scene.textures.len().max(1) * num_of_frames),
+          (hala_gfx::HalaDescriptorType::SAMPLER, scene.textures.len().max(1) * num_of_frames),
+          (hala_gfx::HalaDescriptorType::STORAGE_BUFFER, (bindless_buffers.len() + 1) * num_of_frames),
+        ],
+        num_of_frames,
+        hala_gfx::HalaDescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+        "bindless.descriptor_pool",
+      )?));
+      let descriptor_set_layout = hala_gfx::HalaDescriptorSetLayout::with_flags(
+        Rc::clone(&context.logical_device),
+        textures_bindings.as_slice(),
+        hala_gfx::HalaDescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+        "bindless.descriptor_set_layout",
+      )?;
+      (descriptor_pool, descriptor_set_layout)
+    } else {
+      let descriptor_set_layout = hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&context.logical_device),
+        textures_bindings.as_slice(),
+        "textures.descriptor_set_layout",
+      )?;
+      (Rc::clone(&self.resources.descriptor_pool), descriptor_set_layout)
+    };
+    let textures_descriptor_set = hala_gfx::HalaDescriptorSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&textures_descriptor_pool),
+      textures_descriptor_set_layout,
+      self.resources.num_of_frames,
+      if self.use_bindless { (bindless_buffers.len() + 1) as u32 } else { 0 },
+      if self.use_bindless { "bindless.descriptor_set" } else { "textures.descriptor_set" },
+    )?;
+
+    let textures: &Vec<_> = scene.textures.as_ref();
+    let samplers: &Vec<_> = scene.samplers.as_ref();
+    let images: &Vec<_> = scene.images.as_ref();
+    let mut final_images = Vec::new();
+    let mut final_samplers = Vec::new();
+    for (sampler_index, image_index) in textures.iter().enumerate() {
+      let image = images.get(*image_index as usize).ok_or(HalaRendererError::new("The image is none!", None))?;
+      let sampler = samplers.get(sampler_index).ok_or(HalaRendererError::new("The sampler is none!", None))?;
+      final_images.push(image);
+      final_samplers.push(sampler);
+    }
+    if !final_images.is_empty() && !final_samplers.is_empty() {
+      for index in 0..self.resources.num_of_frames {
+        textures_descriptor_set.update_sampled_images(index, 0, final_images.as_slice());
+        textures_descriptor_set.update_samplers(index, 1, final_samplers.as_slice());
+      }
+    }
+
+    // Upload the primitive table and write all storage buffers of the bindless descriptor set.
+    let bindless_primitive_buffer = if self.use_bindless {
+      let primitive_buffer = hala_gfx::HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (std::mem::size_of::<HalaBindlessPrimitive>() * bindless_primitives.len().max(1)) as u64,
+        hala_gfx::HalaBufferUsageFlags::STORAGE_BUFFER | hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "bindless_primitives.buffer",
+      )?;
+      if !bindless_primitives.is_empty() {
+        primitive_buffer.update_gpu_memory_with_buffer_raw(
+          bindless_primitives.as_ptr() as *const u8,
+          std::mem::size_of::<HalaBindlessPrimitive>() * bindless_primitives.len(),
+          &self.resources.transfer_staging_buffer,
+          &self.resources.transfer_command_buffers)?;
+      }
+
+      let mut storage_buffers = Vec::with_capacity(bindless_buffers.len() + 1);
+      storage_buffers.push(&primitive_buffer);
+      storage_buffers.extend(bindless_buffers.iter().copied());
+      for index in 0..self.resources.num_of_frames {
+        textures_descriptor_set.update_storage_buffers(index, 2, storage_buffers.as_slice());
+      }
+
+      Some(primitive_buffer)
+    } else {
+      None
+    };
+
+    // If we have cache file at ./out/pipeline_cache.bin, we can load it.
+    let pipeline_cache = if std::path::Path::new("./out/pipeline_cache.bin").exists() {
+      log::debug!("Load pipeline cache from file: ./out/pipeline_cache.bin");
+      hala_gfx::HalaPipelineCache::with_cache_file(
+        Rc::clone(&context.logical_device),
+        "./out/pipeline_cache.bin",
+      )?
+    } else {
+      log::debug!("Create a new pipeline cache.");
+      hala_gfx::HalaPipelineCache::new(
+        Rc::clone(&context.logical_device),
+      )?
+    };
+
+    let mut pso_shader_list: Vec<Vec<&HalaShader>> = Vec::new();
+    if self.use_mesh_shader {
+      // Collect modern graphics shaders.
+      for (task_shader, mesh_shader, fragment_shader) in self.shaders.iter() {
+        let mut shaders = Vec::with_capacity(3);
+        if let Some(task_shader) = task_shader {
+          shaders.push(task_shader.as_ref());
+        }
+        shaders.push(mesh_shader.as_ref());
+        shaders.push(fragment_shader.as_ref());
+        pso_shader_list.push(shaders);
+      }
+    } else {
+      // Collect traditional graphics shaders.
+      for shaders in self.traditional_shaders.iter() {
+        pso_shader_list.push(vec![shaders.0.as_ref(), shaders.1.as_ref()])
+      }
+    }
+
+    // The pipeline layout and the vertex input are shared by all graphics pipelines.
+    let descriptor_set_layouts = [&self.static_descriptor_set.layout, &dynamic_descriptor_set.layout, &textures_descriptor_set.layout];
+    let flags = hala_gfx::HalaPipelineCreateFlags::default();
+    let vertex_attribute_descriptions = [
+      hala_gfx::HalaVertexInputAttributeDescription {
+        binding: 0,
+        location: 0,
+        offset: 0,
+        format: hala_gfx::HalaFormat::R32G32B32_SFLOAT, // Position.
+      },
+      hala_gfx::HalaVertexInputAttributeDescription {
+        binding: 0,
+        location: 1,
+        offset: 12,
+        format: hala_gfx::HalaFormat::R32G32B32_SFLOAT, // Normal.
+      },
+      hala_gfx::HalaVertexInputAttributeDescription {
+        binding: 0,
+        location: 2,
+        offset: 24,
+        format: hala_gfx::HalaFormat::R32G32B32_SFLOAT, // Tangent.
+      },
+      hala_gfx::HalaVertexInputAttributeDescription {
+        binding: 0,
+        location: 3,
+        offset: 36,
+        format: hala_gfx::HalaFormat::R32G32_SFLOAT,  // UV.
+      },
+    ];
+    let vertex_binding_descriptions = [
+      hala_gfx::HalaVertexInputBindingDescription {
+        binding: 0,
+        stride: 44,
+        input_rate: hala_gfx::HalaVertexInputRate::VERTEX,
+      }
+    ];
+    let push_constant_ranges = [
+      hala_gfx::HalaPushConstantRange {
+        stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT
+          | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+        offset: 0,
+        size: self.get_draw_push_constant_size(scene),
+      },
+    ];
+
+    // Create graphics pipelines.
+    for (i, shaders) in pso_shader_list.iter().enumerate() {
+      self.forward_graphics_pipelines.push(
+        hala_gfx::HalaGraphicsPipeline::new(
+          Rc::clone(&context.logical_device),
+          &context.swapchain,
+          &descriptor_set_layouts,
+          flags,
+          &vertex_attribute_descriptions,
+          &vertex_binding_descriptions,
+          &push_constant_ranges,
+          hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::BACK, hala_gfx::HalaPolygonMode::FILL, 1.0),
+          &hala_gfx::HalaMultisampleState::new(context.multisample_count, true, 0.3, &[], false, false),
+          &hala_gfx::HalaDepthState::new(true, true, depth_compare_op),
+          self.stencil_states.get(i).and_then(|stencil_state| stencil_state.as_ref()),
+          shaders.as_slice(),
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(&pipeline_cache),
+          &if self.use_mesh_shader {
+            format!("modern_forward_{}.graphics_pipeline", i)
+          } else {
+            format!("traditional_forward_{}.graphics_pipeline", i)
+          },
+        )?
+      );
+      if self.use_overdraw_view {
+        self.overdraw_graphics_pipelines.push(
+          hala_gfx::HalaGraphicsPipeline::new(
+            Rc::clone(&context.logical_device),
+            &context.swapchain,
+            &descriptor_set_layouts,
+            flags,
+            &vertex_attribute_descriptions,
+            &vertex_binding_descriptions,
+            &push_constant_ranges,
+            hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+            &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendOp::ADD),
+            &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendOp::ADD),
+            &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::BACK, hala_gfx::HalaPolygonMode::FILL, 1.0),
+            &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+            &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+            None,
+            shaders.as_slice(),
+            &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+            Some(&pipeline_cache),
+            &if self.use_mesh_shader {
+              format!("modern_overdraw_{}.graphics_pipeline", i)
+            } else {
+              format!("traditional_overdraw_{}.graphics_pipeline", i)
+            },
+          )?
+        );
+      }
+      if self.use_deferred {
+        let depth_image = self.depth_image.as_ref().ok_or(
+          HalaRendererError::new("The deferred flag is setted, but the G-Buffer depth image is none!", None)
+        )?;
+        let gbuffer_color_formats = self.get_gbuffer_color_images()?.iter().map(|image| image.format).collect::<Vec<_>>();
+        let gbuffer_blend_state = hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD);
+        let gbuffer_blend_states = gbuffer_color_formats.iter().map(|_| &gbuffer_blend_state).collect::<Vec<_>>();
+        if self.use_deferred_subpasses {
+          let deferred_render_pass = self.deferred_render_pass.as_ref().ok_or(
+            HalaRendererError::new("The deferred subpasses flag is setted, but the deferred render pass is none!", None)
+          )?;
+          self.deferred_graphics_pipelines.push(
+            hala_gfx::HalaGraphicsPipeline::with_renderpass_format_and_size(
+              Rc::clone(&context.logical_device),
+              gbuffer_color_formats.as_slice(),
+              Some(depth_image.format),
+              self.info.width,
+              self.info.height,
+              &descriptor_set_layouts,
+              flags,
+              &vertex_attribute_descriptions,
+              &vertex_binding_descriptions,
+              &push_constant_ranges,
+              hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+              gbuffer_blend_states.as_slice(),
+              gbuffer_blend_states.as_slice(),
+              &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::BACK, hala_gfx::HalaPolygonMode::FILL, 1.0),
+              &hala_gfx::HalaMultisampleState::default(),
+              &hala_gfx::HalaDepthState::new(true, true, depth_compare_op),
+              self.stencil_states.get(i).and_then(|stencil_state| stencil_state.as_ref()),
+              shaders.as_slice(),
+              &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+              Some(deferred_render_pass),
+              0,
+              Some(&pipeline_cache),
+              &if self.use_mesh_shader {
+                format!("modern_deferred_subpass_{}.graphics_pipeline", i)
+              } else {
+                format!("traditional_deferred_subpass_{}.graphics_pipeline", i)
+              },
+            )?
+          );
+        } else {
+          self.deferred_graphics_pipelines.push(
+            hala_gfx::HalaGraphicsPipeline::with_format_and_size(
+              Rc::clone(&context.logical_device),
+              gbuffer_color_formats.as_slice(),
+              Some(depth_image.format),
+              self.info.width,
+              self.info.height,
+              &descriptor_set_layouts,
+              flags,
+              &vertex_attribute_descriptions,
+              &vertex_binding_descriptions,
+              &push_constant_ranges,
+              hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+              gbuffer_blend_states.as_slice(),
+              gbuffer_blend_states.as_slice(),
+              &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::BACK, hala_gfx::HalaPolygonMode::FILL, 1.0),
+              &hala_gfx::HalaMultisampleState::default(),
+              &hala_gfx::HalaDepthState::new(true, true, depth_compare_op),
+              self.stencil_states.get(i).and_then(|stencil_state| stencil_state.as_ref()),
+              shaders.as_slice(),
+              &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+              Some(&pipeline_cache),
+              &if self.use_mesh_shader {
+                format!("modern_deferred_{}.graphics_pipeline", i)
+              } else {
+                format!("traditional_deferred_{}.graphics_pipeline", i)
+              },
+            )?
+          );
+        }
+      }
+    }
+
+    // Create the selection graphics pipeline, it is drawn after the scene in the forward pass.
+    if !self.selection_shaders.is_empty() {
+      let selection_shaders = self.selection_shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+      self.selection_graphics_pipeline = Some(
+        hala_gfx::HalaGraphicsPipeline::new(
+          Rc::clone(&context.logical_device),
+          &context.swapchain,
+          &descriptor_set_layouts,
+          flags,
+          &vertex_attribute_descriptions,
+          &vertex_binding_descriptions,
+          &push_constant_ranges,
+          hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+          &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+          &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+          self.selection_stencil_state.as_ref(),
+          selection_shaders.as_slice(),
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(&pipeline_cache),
+          "selection.graphics_pipeline",
+        )?
+      );
+    }
+
+    // The overlay and the shadow pipelines share the layout and the vertex input of the scene pipelines.
+    let scene_pipeline_layout = HalaScenePipelineLayout {
+      descriptor_set_layouts,
+      flags,
+      vertex_attribute_descriptions: &vertex_attribute_descriptions,
+      vertex_binding_descriptions: &vertex_binding_descriptions,
+      push_constant_ranges: &push_constant_ranges,
+    };
+
+    // Create the shadow atlas and the depth only graphics pipeline, the tile index is pushed after the draw push constants.
+    self.shadow.commit(
+      &context,
+      &scene_pipeline_layout,
+      scene,
+      &self.static_descriptor_set,
+      self.resources.num_of_frames,
+      self.depth_convention,
+      depth_compare_op,
+      &pipeline_cache,
+    )?;
+
+    // Create the outline graphics pipeline, it is drawn after the selection pass with the same attachments.
+    self.outline.commit(&context, depth_compare_op, &pipeline_cache)?;
+
+    // Create the overlay graphics pipelines, the lines are tested against the scene depth but do not write it.
+    // The equal depth passes the test, so the wireframe is not hidden by the surface it is drawn from.
+    let overlay_compare_op = if self.depth_convention.is_reverse_z() {
+      hala_gfx::HalaCompareOp::GREATER_OR_EQUAL
+    } else {
+      hala_gfx::HalaCompareOp::LESS_OR_EQUAL
+    };
+    self.overlay.commit(&context, &scene_pipeline_layout, overlay_compare_op, &pipeline_cache)?;
+
+    // Create the immediate line graphics pipeline.
+    self.lines.commit(&context, &self.static_descriptor_set.layout, self.use_deferred, overlay_compare_op, &pipeline_cache)?;
+
+    // Create the visibility images and the pipelines of the software meshlet path.
+    if let Some(raster_file_path) = self.software_raster_file_path.as_ref() {
+      let visibility_depth_image = hala_gfx::HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::STORAGE,
+        hala_gfx::HalaFormat::R32_UINT,
+        self.info.width,
+        self.info.height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "visibility_depth.image",
+      )?;
+      let visibility_id_image = hala_gfx::HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::STORAGE,
+        hala_gfx::HalaFormat::R32_UINT,
+        self.info.width,
+        self.info.height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "visibility_id.image",
+      )?;
+      let visibility_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&self.resources.descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            hala_gfx::HalaDescriptorSetLayoutBinding { // Visibility depth image.
+              binding_index: 0,
+              descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE | hala_gfx::HalaShaderStageFlags::FRAGMENT,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+            hala_gfx::HalaDescriptorSetLayoutBinding { // Visibility id image.
+              binding_index: 1,
+              descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+              descriptor_count: 1,
+              stage_flags: hala_gfx::HalaShaderStageFlags::COMPUTE | hala_gfx::HalaShaderStageFlags::FRAGMENT,
+              binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+            },
+          ],
+          "visibility.descriptor_set_layout",
+        )?,
+        0,
+        "visibility.descriptor_set",
+      )?;
+      visibility_descriptor_set.update_storage_images(0, 0, &[&visibility_depth_image]);
+      visibility_descriptor_set.update_storage_images(0, 1, &[&visibility_id_image]);
+
+      let visibility_descriptor_set_layouts = [
+        &self.static_descriptor_set.layout,
+        &dynamic_descriptor_set.layout,
+        &textures_descriptor_set.layout,
+        &visibility_descriptor_set.layout,
+      ];
+      let software_raster_program = HalaComputeProgram::new(
+        Rc::clone(&context.logical_device),
+        &visibility_descriptor_set_layouts,
+        &HalaComputeProgramDesc {
+          shader_file_path: raster_file_path.clone(),
+          push_constant_size: 24, // Pass, Mesh index, Material index, Primitive index, Meshlet count and First meshlet.
+          bindings: Vec::new(),
+        },
+        Some(&pipeline_cache),
+        "software_raster",
+      )?;
+      let material_shaders = self.software_material_shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+      let software_material_graphics_pipeline = hala_gfx::HalaGraphicsPipeline::new(
+        Rc::clone(&context.logical_device),
+        &context.swapchain,
+        &visibility_descriptor_set_layouts,
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+        &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+        &[] as &[hala_gfx::HalaPushConstantRange],
+        hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+        &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+        None,
+        material_shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(&pipeline_cache),
+        "software_material.graphics_pipeline",
+      )?;
+
+      self.visibility_depth_image = Some(visibility_depth_image);
+      self.visibility_id_image = Some(visibility_id_image);
+      self.visibility_descriptor_set = Some(visibility_descriptor_set);
+      self.software_raster_program = Some(software_raster_program);
+      self.software_material_graphics_pipeline = Some(software_material_graphics_pipeline);
+    }
+
+    // Create the images and the pipelines of the TAA, the deferred resolve reprojects with the G-Buffer depth.
+    let gbuffer_depth_image = if self.use_deferred {
+      Some(self.depth_image.as_ref().ok_or(HalaRendererError::new("The G-Buffer depth image is none!", None))?)
+    } else {
+      None
+    };
+    self.taa.commit(
+      &context,
+      Rc::clone(&self.resources.descriptor_pool),
+      &pipeline_cache,
+      self.info.width,
+      self.info.height,
+      depth_compare_op,
+      gbuffer_depth_image,
+    )?;
+
+    // Create the images and the pipelines of the SSR.
+    if self.use_deferred && !self.use_visibility_buffer {
+      let gbuffer = HalaSSRGBuffer {
+        depth_image: self.depth_image.as_ref().ok_or(HalaRendererError::new("The G-Buffer depth image is none!", None))?,
+        albedo_image: self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?,
+        normal_image: self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?,
+      };
+      self.ssr.commit(
+        &context,
+        Rc::clone(&self.resources.descriptor_pool),
+        &pipeline_cache,
+        self.info.width,
+        self.info.height,
+        depth_compare_op,
+        &gbuffer,
+        [&self.static_descriptor_set.layout, &dynamic_descriptor_set.layout],
+      )?;
+    }
+
+    // Create the depth pyramid of the G-Buffer depth, it is rebuilt with the G-Buffer on resize.
+    if let (Some(depth_pyramid_file_path), true) = (self.depth_pyramid_file_path.as_ref(), self.use_deferred && !self.use_visibility_buffer) {
+      let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The G-Buffer depth image is none!", None))?;
+      let depth_pyramid = HalaDepthPyramid::new(
+        &context,
+        Rc::clone(&self.resources.descriptor_pool),
+        &pipeline_cache,
+        depth_pyramid_file_path,
+        depth_image,
+        self.info.width,
+        self.info.height,
+        self.depth_convention,
+      )?;
+      if self.use_occlusion_culling {
+        let mip_images = depth_pyramid.get_mip_images().iter().collect::<Vec<_>>();
+        for index in 0..self.resources.num_of_frames {
+          dynamic_descriptor_set.update_storage_images(index, 9, mip_images.as_slice());
+        }
+      }
+      self.depth_pyramid = Some(depth_pyramid);
+    }
+
+    // Create the images and the pipelines of the bloom, the FXAA, the color grading and the lens effects.
+    self.bloom.commit(&context, Rc::clone(&self.resources.descriptor_pool), &pipeline_cache, self.info.width, self.info.height, depth_compare_op)?;
+    self.fxaa.commit(&context, Rc::clone(&self.resources.descriptor_pool), &pipeline_cache, self.info.width, self.info.height, depth_compare_op)?;
+    self.color_grading.commit(&context, Rc::clone(&self.resources.descriptor_pool), &pipeline_cache, self.info.width, self.info.height, depth_compare_op)?;
+    self.lens.commit(&context, Rc::clone(&self.resources.descriptor_pool), &pipeline_cache, self.info.width, self.info.height, depth_compare_op)?;
+
+    if self.use_deferred {
+      let vertex_shader = self.lighting_vertex_shader.as_ref().ok_or(HalaRendererError::new("The lighting pass vertex shader is none!", None))?;
+      let fragment_shader = self.lighting_fragment_shader.as_ref().ok_or(HalaRendererError::new("The lighting pass fragment shader is none!", None))?;
+      let descriptor_set = self.lighting_descriptor_set.as_ref().ok_or(HalaRendererError::new("The lighting pass descriptor set is none!", None))?;
+      // The material pass of the visibility buffer also fetches the textures.
+      let mut lighting_descriptor_set_layouts = vec![
+        &self.static_descriptor_set.layout,
+        &dynamic_descriptor_set.layout,
+        &descriptor_set.layout,
+      ];
+      if self.use_visibility_buffer {
+        lighting_descriptor_set_layouts.push(&textures_descriptor_set.layout);
+      }
+
+      let lighting_graphics_pipeline = if self.use_deferred_subpasses {
+        let deferred_render_pass = self.deferred_render_pass.as_ref().ok_or(HalaRendererError::new("The deferred render pass is none!", None))?;
+        hala_gfx::HalaGraphicsPipeline::with_renderpass_format_and_size(
+          Rc::clone(&context.logical_device),
+          &[context.swapchain.format],
+          Some(context.swapchain.depth_stencil_format),
+          self.info.width,
+          self.info.height,
+          lighting_descriptor_set_layouts.as_slice(),
+          hala_gfx::HalaPipelineCreateFlags::default(),
+          &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+          &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+          &[] as &[hala_gfx::HalaPushConstantRange],
+          hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+          &[
+            hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          ],
+          &[
+            hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          ],
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+          &hala_gfx::HalaMultisampleState::default(),
+          &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+          None,
+          &[&vertex_shader, &fragment_shader],
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(deferred_render_pass),
+          1,
+          Some(&pipeline_cache),
+          "lighting_subpass.graphics_pipeline",
+        )?
+      } else {
+        hala_gfx::HalaGraphicsPipeline::new(
+          Rc::clone(&context.logical_device),
+          &context.swapchain,
+          lighting_descriptor_set_layouts.as_slice(),
+          hala_gfx::HalaPipelineCreateFlags::default(),
+          &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+          &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+          &[] as &[hala_gfx::HalaPushConstantRange],
+          hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+          &hala_gfx::HalaMultisampleState::default(),
+          &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+          None,
+          &[&vertex_shader, &fragment_shader],
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(&pipeline_cache),
+          "lighting_pass.graphics_pipeline",
+        )?
+      };
+
+      self.lighting_graphics_pipeline = Some(lighting_graphics_pipeline);
+    }
+
+    // Create the grid graphics pipeline, the deferred one also binds the dynamic and the lighting descriptor sets for the G-Buffer depth.
+    if self.grid.is_enabled {
+      let mut grid_descriptor_set_layouts = vec![&self.static_descriptor_set.layout];
+      if self.use_deferred {
+        let descriptor_set = self.lighting_descriptor_set.as_ref().ok_or(HalaRendererError::new("The lighting pass descriptor set is none!", None))?;
+        grid_descriptor_set_layouts.push(&dynamic_descriptor_set.layout);
+        grid_descriptor_set_layouts.push(&descriptor_set.layout);
+      }
+      // The deferred subpasses draw the grid in the lighting subpass, it reads the G-Buffer depth as the input attachment there
+      // and tests the swapchain depth, which is the depth stencil attachment of the lighting subpass.
+      let deferred_subpass = if self.use_deferred_subpasses {
+        let deferred_render_pass = self.deferred_render_pass.as_ref().ok_or(HalaRendererError::new("The deferred render pass is none!", None))?;
+        Some((deferred_render_pass, context.swapchain.depth_stencil_format))
+      } else {
+        None
+      };
+      self.grid.commit(
+        &context,
+        grid_descriptor_set_layouts.as_slice(),
+        self.use_deferred,
+        deferred_subpass,
+        self.info.width,
+        self.info.height,
+        depth_compare_op,
+        &pipeline_cache,
+      )?;
+    }
+
+    // Create the axis gizmo graphics pipeline, it draws on the scene target of the forward pass or on the swapchain after the lighting.
+    self.axis_gizmo.commit(&context, self.use_deferred, depth_compare_op, &pipeline_cache)?;
+
+    // Save pipeline cache.
+    pipeline_cache.save("./out/pipeline_cache.bin")?;
+
+    self.dynamic_descriptor_set = Some(dynamic_descriptor_set);
+    self.textures_descriptor_set = Some(textures_descriptor_set);
+    self.textures_descriptor_dirty = vec![false; self.resources.num_of_frames];
+    self.bindless_primitive_buffer = bindless_primitive_buffer;
+    self.bindless_descriptor_pool = if self.use_bindless { Some(textures_descriptor_pool) } else { None };
+
+    // Create the resources of the additional views.
+    drop(context);
+    self.create_view_resources()?;
+
+    self.is_committed = true;
+
+    Ok(())
+  }
+
+  /// Update the renderer.
+  /// The ui_fn is always called in a single sample rendering on the swapchain image with the swapchain color and depth stencil formats,
+  /// the multisample scene is resolved before it, so the UI pipelines are created against the single sample swapchain.
+  /// A device lost error skips the frame, the device is restored by the next update(see check_and_restore_device).
+  /// param delta_time: The delta time.
+  /// param width: The width of the window.
+  /// param height: The height of the window.
+  /// param ui_fn: The draw UI function.
+  /// return: The result.
+  fn update<F>(&mut self, _delta_time: f64, width: u32, height: u32, ui_fn: F) -> Result<(), HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>
+  {
+    self.pre_update(width, height)?;
+
+    // Skip the frame and wait to restore the device on the next frame update.
+    if self.data.is_device_lost {
+      return Ok(());
+    }
+
+    match self.update_frame(ui_fn) {
+      Err(err) if err.is_device_lost() => {
+        self.mark_device_lost();
+        Ok(())
+      },
+      result => result,
+    }
+  }
+
+  /// Recreate the G-buffer, the multisample images and the deferred framebuffers with the new size.
+  /// The images of the TAA, the SSR, the bloom, the FXAA and the software meshlet raster are created by commit,
+  /// so the renderer is committed again if it has any of them. The output image keeps its own size.
+  /// param width: The width of the swapchain.
+  /// param height: The height of the swapchain.
+  /// return: The result.
+  fn on_resize(&mut self, _width: u32, _height: u32) -> Result<(), HalaRendererError> {
+    if self.use_visibility_buffer {
+      self.create_visibility_buffer_resources(self.use_transient_gbuffer)?;
+    } else if self.use_deferred {
+      let albedo_format = self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?.format;
+      let normal_format = self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?.format;
+      let motion_vector_format = self.motion_vector_image.as_ref().map(|image| image.format);
+      let use_object_id = self.object_id_image.is_some();
+      self.create_gbuffer_resources(self.use_transient_gbuffer, albedo_format, normal_format, motion_vector_format, use_object_id)?;
+    }
+
+    if self.color_multisample_image.is_some() {
+      let sample_count = self.resources.context.borrow().multisample_count;
+      self.create_multisample_images(sample_count)?;
+    }
+
+    // The framebuffers reference the swapchain images, so they are recreated even the G-buffer is not changed.
+    if self.deferred_framebuffers.is_some() {
+      self.deferred_framebuffers = None;
+      self.build_deferred_framebuffers()?;
+    }
+
+    let has_committed_images = self.taa.target.is_some()
+      || self.ssr.ssr_image.is_some()
+      || self.bloom.target.is_some()
+      || self.fxaa.target.is_some()
+      || self.color_grading.target.is_some()
+      || self.lens.target.is_some()
+      || self.depth_pyramid.is_some()
+      || self.visibility_depth_image.is_some();
+    if self.dynamic_descriptor_set.is_some() && has_committed_images {
+      self.commit()?;
+    }
+
+    Ok(())
+  }
+
+}
+
+/// The implementation of the renderer.
+impl HalaRenderer {
+
+  /// Create a new renderer.
+  /// param name: The name of the renderer.
+  /// param gpu_req: The GPU requirements of the renderer.
+  /// param window: The window of the renderer.
+  /// return: The renderer.
+  pub fn new(
+    name: &str,
+    gpu_req: &HalaGPURequirements,
+    window: &winit::window::Window,
+  ) -> Result<Self, HalaRendererError> {
+    let width = gpu_req.width;
+    let height = gpu_req.height;
+
+    // Fall back to the traditional vertex pipeline if the context can not be created with mesh shader.
+    let (mut resources, use_mesh_shader) = match HalaRendererResources::new(
+      name,
+      gpu_req,
+      window,
+      &Self::get_descriptor_sizes(),
+    ) {
+      Ok(resources) => (resources, gpu_req.require_mesh_shader),
+      Err(err) if gpu_req.require_mesh_shader => {
+        log::warn!("Failed to create the context with mesh shader: {}. Fall back to the traditional vertex pipeline.", err.message());
+        let fallback_gpu_req = HalaGPURequirements {
+          require_mesh_shader: false,
+          ..gpu_req.clone()
+        };
+        let resources = HalaRendererResources::new(
+          name,
+          &fallback_gpu_req,
+          window,
+          &Self::get_descriptor_sizes(),
+        )?;
+        (resources, false)
+      },
+      Err(err) => return Err(err),
+    };
+    resources.reserve_descriptors(&[(hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, 3 * resources.num_of_frames)], resources.num_of_frames)?;
+
+    let static_descriptor_set = Self::create_static_descriptor_set(&resources, "main")?;
+
+    // Create global uniform buffers.
+    let global_uniform_buffers = Self::create_global_uniform_buffers(&resources, "global")?;
+
+    // Return the renderer.
+    log::debug!("A HalaRenderer \"{}\"[{} x {}] is created.", name, width, height);
+    Ok(Self {
+      info: HalaRendererInfo::new(name, width, height),
+      use_mesh_shader,
+      meshlet_config: HalaMeshletConfig::default(),
+      optimize_meshes: true,
+      use_mega_buffers: false,
+      stream_textures: false,
+      async_upload: false,
+      depth_convention: HalaDepthConvention::default(),
+      lod_config: HalaLodConfig::default(),
+      lod_bias: 0.0,
+
+      resources,
+
+      color_multisample_image: None,
+      depth_stencil_multisample_image: None,
+
+      use_deferred: false,
+      depth_image: None,
+      albedo_image: None,
+      normal_image: None,
+      motion_vector_image: None,
+      object_id_image: None,
+      use_visibility_buffer: false,
+      visibility_buffer_image: None,
+
+      use_deferred_subpasses: false,
+      deferred_render_pass: None,
+      deferred_framebuffers: None,
+
+      lighting_descriptor_set: None,
+      lighting_vertex_shader: None,
+      lighting_fragment_shader: None,
+      lighting_graphics_pipeline: None,
+
+      software_raster_file_path: None,
+      software_material_shaders: Vec::new(),
+      software_raster_program: None,
+      software_material_graphics_pipeline: None,
+      visibility_depth_image: None,
+      visibility_id_image: None,
+      visibility_descriptor_set: None,
+
+      taa: HalaTAAPass::default(),
+      use_transient_gbuffer: false,
+      gbuffer_depth_format: hala_gfx::HalaFormat::D32_SFLOAT,
+      use_gbuffer_object_id: false,
+
+      ssr: HalaSSRPass::default(),
+
+      depth_pyramid_file_path: None,
+      depth_pyramid: None,
+      shadow: HalaShadowPass::default(),
+
+      bloom: HalaBloomPass::default(),
+      fxaa: HalaFXAAPass::default(),
+      color_grading: HalaColorGradingPass::default(),
+      lens: HalaLensPass::default(),
+
+      output_size: None,
+      output_color_image: None,
+      output_depth_image: None,
+
+      prev_vp_mtxs: Vec::new(),
+      prev_mesh_transforms: Vec::new(),
+
+      static_descriptor_set,
+      dynamic_descriptor_set: None,
+      global_uniform_buffers,
+      object_uniform_buffers: Vec::new(),
+
+      views: Vec::new(),
+      view_projection_override: None,
+      clear_color: [25.0 / 255.0, 118.0 / 255.0, 210.0 / 255.0, 1.0],
+      gbuffer_clear_color: [0.0, 0.0, 0.0, 1.0],
+      clear_depth_stencil: None,
+      render_area: None,
+      view_resources: Vec::new(),
+
+      traditional_shaders: Vec::new(),
+      shaders: Vec::new(),
+      compute_shaders: Vec::new(),
+      stencil_states: Vec::new(),
+
+      selection_shaders: Vec::new(),
+      selection_stencil_state: None,
+      selection_graphics_pipeline: None,
+      selection: Vec::new(),
+
+      mesh_visibilities: Vec::new(),
+      mesh_material_overrides: Vec::new(),
+      draw_groups: Default::default(),
+
+      outline: HalaOutlinePass::default(),
+      overlay: HalaOverlayPass::default(),
+      grid: HalaGridPass::default(),
+      axis_gizmo: HalaAxisGizmoPass::default(),
+      lines: HalaLinePass::default(),
+
+      occlusion_query_pool: None,
+      max_occlusion_queries: 0,
+      occlusion_query_counts: Vec::new(),
+      occlusion_results: Vec::new(),
+
+      use_occlusion_culling: false,
+      occlusion_culling_visibility_buffer: None,
+      occlusion_culling_visibility_offsets: Vec::new(),
+      occlusion_culling_stats_buffers: Vec::new(),
+
+      scene_in_gpu: None,
+
+      forward_graphics_pipelines: Vec::new(),
+      deferred_graphics_pipelines: Vec::new(),
+      use_overdraw_view: false,
+      overdraw_graphics_pipelines: Vec::new(),
+      debug_view: HalaDebugView::NONE,
+
+      textures_descriptor_set: None,
+      textures_descriptor_dirty: Vec::new(),
+
+      use_bindless: false,
+      bindless_primitive_buffer: None,
+      bindless_descriptor_pool: None,
+
+      is_committed: false,
+
+      data: HalaRendererData::new(),
+      statistics: HalaRendererStatistics::new(),
+      draw_counters: std::cell::Cell::new(HalaDrawCounters::default()),
+    })
+  }
+
+  /// Create the global uniform buffers, one for each swapchain image.
+  /// param resources: The renderer resources.
+  /// param prefix: The name prefix of the buffers.
+  /// return: The global uniform buffers.
+  fn create_global_uniform_buffers(resources: &HalaRendererResources, prefix: &str) -> Result<Vec<hala_gfx::HalaBuffer>, HalaRendererError> {
+    let mut global_uniform_buffers = Vec::with_capacity(resources.num_of_frames);
+    for index in 0..resources.num_of_frames {
+      global_uniform_buffers.push(hala_gfx::HalaBuffer::new(
+        Rc::clone(&resources.context.borrow().logical_device),
+        std::mem::size_of::<HalaGlobalUniform>() as u64,
+        hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
+        hala_gfx::HalaMemoryLocation::CpuToGpu,
+        &format!("{}_{}.uniform_buffer", prefix, index),
+      )?);
+    }
+
+    Ok(global_uniform_buffers)
+  }
+
+  /// Write the uniform buffers of each swapchain image into the static descriptor set.
+  /// param static_descriptor_set: The static descriptor set.
+  /// param global_uniform_buffers: The global uniform buffers.
+  /// param scene: The scene in the GPU.
+  fn update_static_descriptor_set(
+    static_descriptor_set: &hala_gfx::HalaDescriptorSet,
+    global_uniform_buffers: &[hala_gfx::HalaBuffer],
+    scene: &gpu::HalaScene,
+  ) {
+    for (index, global_uniform_buffer) in global_uniform_buffers.iter().enumerate() {
+      static_descriptor_set.update_uniform_buffers(index, 0, &[global_uniform_buffer]);
+      static_descriptor_set.update_uniform_buffers(index, 1, &[scene.cameras.as_ref()]);
+      static_descriptor_set.update_uniform_buffers(index, 2, &[scene.lights.as_ref()]);
+    }
+  }
+
+  /// Create the static descriptor set, it has one set for each swapchain image to bind the global uniform buffers.
+  /// The bindings 3, 4 and 5 are the shadow atlas, its sampler and the shadow uniform buffer(see HalaShadowAtlas).
+  /// param resources: The renderer resources.
+  /// param prefix: The name prefix of the descriptor set.
+  /// return: The static descriptor set.
+  fn create_static_descriptor_set(resources: &HalaRendererResources, prefix: &str) -> Result<hala_gfx::HalaDescriptorSet, HalaRendererError> {
+    let static_descriptor_set = hala_gfx::HalaDescriptorSet::new(
+      Rc::clone(&resources.context.borrow().logical_device),
+      Rc::clone(&resources.descriptor_pool),
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&resources.context.borrow().logical_device),
+        &[
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Global uniform buffer.
+            binding_index: 0,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Cameras uniform buffer.
+            binding_index: 1,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Lights uniform buffer.
+            binding_index: 2,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Shadow atlas, written only if the shadows are enabled.
+            binding_index: 3,
+            descriptor_type: hala_gfx::HalaDescriptorType::SAMPLED_IMAGE,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Shadow atlas sampler.
+            binding_index: 4,
+            descriptor_type: hala_gfx::HalaDescriptorType::SAMPLER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Shadow uniform buffer.
+            binding_index: 5,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if resources.context.borrow().gpu_req.require_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+        ],
+        &format!("{}_static.descriptor_set_layout", prefix),
+      )?,
+      resources.num_of_frames,
+      0,
+      &format!("{}_static.descriptor_set", prefix),
+    )?;
+
+    Ok(static_descriptor_set)
+  }
+
+  /// Get the descriptor counts of the dynamic descriptor set of the current scene.
+  /// return: The descriptor counts.
+  fn get_dynamic_descriptor_sizes(&self) -> Result<Vec<(hala_gfx::HalaDescriptorType, usize)>, HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let num_of_frames = self.resources.num_of_frames;
+    let num_of_primitives = scene.meshes.iter().map(|mesh| mesh.primitives.len()).sum::<usize>();
+    let num_of_storage_buffers = if self.use_bindless {
+      0 // The storage buffers are in the bindless descriptor set.
+    } else if self.use_meshlets() {
+      num_of_primitives * 5
+    } else {
+      num_of_primitives * 2
+    };
+    // The visibility buffer, the stats buffer and the depth pyramid levels of the occlusion culling.
+    let (num_of_culling_buffers, num_of_culling_images) = if self.use_occlusion_culling {
+      (2, HalaDepthPyramid::get_mip_count_for(self.info.width, self.info.height))
+    } else {
+      (0, 0)
+    };
+    Ok(vec![
+      (hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, (scene.materials.len() + scene.meshes.len()) * num_of_frames),
+      (hala_gfx::HalaDescriptorType::STORAGE_BUFFER, (num_of_storage_buffers + num_of_culling_buffers) * num_of_frames),
+      (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, num_of_culling_images * num_of_frames),
+    ])
+  }
+
+  /// Collect the storage buffers and the primitive table of the bindless descriptor set.
+  /// The slot 0 is reserved for the primitive table, so the buffers start from the slot 1.
+  /// param scene: The scene in the GPU.
+  /// return: The storage buffers from the slot 1 and the primitive table in the draw index order.
+  fn collect_bindless_buffers<'a>(
+    &self,
+    scene: &'a gpu::HalaScene,
+  ) -> Result<(Vec<&'a hala_gfx::HalaBuffer>, Vec<HalaBindlessPrimitive>), HalaRendererError> {
+    let mut buffers = Vec::new();
+    let mut push_buffer = |buffer: Option<&'a hala_gfx::HalaBuffer>| match buffer {
+      Some(buffer) => {
+        buffers.push(buffer);
+        buffers.len() as u32
+      },
+      None => INVALID_BINDLESS_SLOT,
+    };
+
+    // The mega buffers are shared by all primitives.
+    let mega_slots = if scene.use_mega_buffers() {
+      Some([
+        push_buffer(scene.mega_vertex_buffer.as_ref()),
+        push_buffer(scene.mega_index_buffer.as_ref()),
+        push_buffer(scene.mega_meshlet_buffer.as_ref()),
+        push_buffer(scene.mega_meshlet_vertex_buffer.as_ref()),
+        push_buffer(scene.mega_meshlet_primitive_buffer.as_ref()),
+      ])
+    } else {
+      None
+    };
+
+    let mut primitives = Vec::new();
+    for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+      for primitive in mesh.primitives.iter() {
+        let slots = match mega_slots {
+          Some(slots) => slots,
+          None => [
+            push_buffer(primitive.vertex_buffer.as_ref()),
+            push_buffer(primitive.index_buffer.as_ref()),
+            push_buffer(primitive.meshlet_buffer.as_ref()),
+            push_buffer(primitive.meshlet_vertex_buffer.as_ref()),
+            push_buffer(primitive.meshlet_primitive_buffer.as_ref()),
+          ],
+        };
+        primitives.push(HalaBindlessPrimitive {
+          mesh_index: mesh_index as u32,
+          material_index: primitive.material_index,
+          vertex_buffer: slots[0],
+          index_buffer: slots[1],
+          meshlet_buffer: slots[2],
+          meshlet_vertex_buffer: slots[3],
+          meshlet_primitive_buffer: slots[4],
+          vertex_offset: primitive.vertex_offset,
+          first_index: primitive.first_index,
+          index_count: primitive.index_count,
+          first_meshlet: primitive.first_meshlet,
+          meshlet_count: primitive.meshlet_count,
+        });
+      }
+    }
+
+    if buffers.len() + 1 > MAX_BINDLESS_STORAGE_BUFFERS {
+      return Err(HalaRendererError::new(
+        &format!("The scene has {} storage buffers, the bindless descriptor set supports at most {}!", buffers.len() + 1, MAX_BINDLESS_STORAGE_BUFFERS), None));
+    }
+
+    Ok((buffers, primitives))
+  }
+
+  /// Create the object uniform buffers of all meshes in the scene.
+  /// param prefix: The name prefix of the buffers.
+  /// return: The object uniform buffers for each mesh and each frame.
+  fn create_object_uniform_buffers(&self, prefix: &str) -> Result<Vec<Vec<hala_gfx::HalaBuffer>>, HalaRendererError> {
+    let context = self.resources.context.borrow();
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+
+    let mut object_uniform_buffers = Vec::with_capacity(scene.meshes.len());
+    for (mesh_index, _mesh) in scene.meshes.iter().enumerate() {
+      // Create object uniform buffer.
+      let mut buffers = Vec::with_capacity(self.resources.num_of_frames);
+      for index in 0..self.resources.num_of_frames {
+        let buffer = hala_gfx::HalaBuffer::new(
+          Rc::clone(&context.logical_device),
+          std::mem::size_of::<HalaObjectUniform>() as u64,
+          hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
+          hala_gfx::HalaMemoryLocation::CpuToGpu,
+          &format!("{}_{}_{}.uniform_buffer", prefix, mesh_index, index),
+        )?;
+
+        buffers.push(buffer);
+      }
+
+      object_uniform_buffers.push(buffers);
+    }
+
+    Ok(object_uniform_buffers)
+  }
+
+  /// Create the dynamic descriptor set.
+  /// param object_uniform_buffers: The object uniform buffers.
+  /// param prefix: The name prefix of the descriptor set.
+  /// return: The dynamic descriptor set.
+  fn create_dynamic_descriptor_set(&self, object_uniform_buffers: &[Vec<hala_gfx::HalaBuffer>], prefix: &str) -> Result<hala_gfx::HalaDescriptorSet, HalaRendererError> {
+    let context = self.resources.context.borrow();
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+
+    // Collect vertex and index buffers.
+    // The mega buffers are bound as the single element of each array.
+    let mut vertex_buffers = Vec::new();
+    let mut index_buffers = Vec::new();
+    let mut meshlet_buffers = Vec::new();
+    let mut meshlet_vertex_buffers = Vec::new();
+    let mut meshlet_primitive_buffers = Vec::new();
+    if self.use_bindless {
+      // The storage buffers are in the bindless descriptor set.
+    } else if scene.use_mega_buffers() {
+      vertex_buffers.push(scene.mega_vertex_buffer.as_ref().ok_or(HalaRendererError::new("The mega vertex buffer is none!", None))?);
+      index_buffers.push(scene.mega_index_buffer.as_ref().ok_or(HalaRendererError::new("The mega index buffer is none!", None))?);
+      if self.use_meshlets() {
+        if let Some(meshlet_buffer) = &scene.mega_meshlet_buffer {
+          meshlet_buffers.push(meshlet_buffer);
+        }
+        meshlet_vertex_buffers.push(scene.mega_meshlet_vertex_buffer.as_ref().ok_or(HalaRendererError::new("The mega meshlet vertex buffer is none!", None))?);
+        meshlet_primitive_buffers.push(scene.mega_meshlet_primitive_buffer.as_ref().ok_or(HalaRendererError::new("The mega meshlet primitive buffer is none!", None))?);
+      }
+    } else {
+      for mesh in scene.meshes.iter() {
+        for primitive in mesh.primitives.iter() {
+          vertex_buffers.push(primitive.vertex_buffer.as_ref().ok_or(HalaRendererError::new("The vertex buffer is none!", None))?);
+          index_buffers.push(primitive.index_buffer.as_ref().ok_or(HalaRendererError::new("The index buffer is none!", None))?);
+          if self.use_meshlets() {
+            if let Some(meshlet_buffer) = &primitive.meshlet_buffer {
+              meshlet_buffers.push(meshlet_buffer);
+            }
+            meshlet_vertex_buffers.push(primitive.meshlet_vertex_buffer.as_ref().ok_or(HalaRendererError::new("The meshlet vertex buffer is none!", None))?);
+            meshlet_primitive_buffers.push(primitive.meshlet_primitive_buffer.as_ref().ok_or(HalaRendererError::new("The meshlet primitive buffer is none!", None))?);
+          }
+        }
+      }
+    }
+
+    // Create dynamic descriptor set.
+    let dynamic_descriptor_set = hala_gfx::HalaDescriptorSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&self.resources.descriptor_pool),
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&context.logical_device),
+        &[
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Materials uniform buffers.
+            binding_index: 0,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: scene.materials.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Object uniform buffers.
+            binding_index: 1,
+            descriptor_type: hala_gfx::HalaDescriptorType::UNIFORM_BUFFER,
+            descriptor_count: scene.meshes.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Vertex storage buffers.
+            binding_index: 2,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: vertex_buffers.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Index storage buffers.
+            binding_index: 3,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: index_buffers.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Meshlet information storage buffers.
+            binding_index: 4,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: meshlet_buffers.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Meshlet vertex storage buffers.
+            binding_index: 5,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: meshlet_vertex_buffers.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Meshlet primitive storage buffers.
+            binding_index: 6,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: meshlet_primitive_buffers.len() as u32,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT | hala_gfx::HalaShaderStageFlags::COMPUTE
+              | (if self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH } else { hala_gfx::HalaShaderStageFlags::VERTEX }),
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Meshlet visibility storage buffer of the occlusion culling.
+            binding_index: 7,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Meshlet stats storage buffer of the occlusion culling.
+            binding_index: 8,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_BUFFER,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Depth pyramid storage images of the occlusion culling, the level 0 first.
+            binding_index: 9,
+            descriptor_type: hala_gfx::HalaDescriptorType::STORAGE_IMAGE,
+            descriptor_count: if self.use_occlusion_culling { HalaDepthPyramid::get_mip_count_for(self.info.width, self.info.height) as u32 } else { 1 },
+            stage_flags: hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+        ],
+        &format!("{}_dynamic.descriptor_set_layout", prefix),
+      )?,
+      self.resources.num_of_frames,
+      0,
+      &format!("{}_dynamic.descriptor_set", prefix),
+    )?;
+
+    for index in 0..self.resources.num_of_frames {
+      dynamic_descriptor_set.update_uniform_buffers(
+        index,
+        0,
+        scene.materials.as_slice(),
+      );
+      dynamic_descriptor_set.update_uniform_buffers(
+        index,
+        1,
+        object_uniform_buffers.iter().map(|buffers| &buffers[index]).collect::<Vec<_>>().as_slice(),
+      );
+      if !vertex_buffers.is_empty() {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          2,
+          vertex_buffers.as_slice(),
+        );
+      }
+      if !index_buffers.is_empty() {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          3,
+          index_buffers.as_slice(),
+        );
+      }
+      if !meshlet_buffers.is_empty() {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          4,
+          meshlet_buffers.as_slice(),
+        );
+      }
+      if !meshlet_vertex_buffers.is_empty() {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          5,
+          meshlet_vertex_buffers.as_slice(),
+        );
+      }
+      if !meshlet_primitive_buffers.is_empty() {
+        dynamic_descriptor_set.update_storage_buffers(
+          index,
+          6,
+          meshlet_primitive_buffers.as_slice(),
+        );
+      }
+      // The depth pyramid levels are bound by commit after the depth pyramid is created.
+      if let Some(visibility_buffer) = self.occlusion_culling_visibility_buffer.as_ref() {
+        dynamic_descriptor_set.update_storage_buffers(index, 7, &[visibility_buffer]);
+      }
+      if let Some(stats_buffer) = self.occlusion_culling_stats_buffers.get(index) {
+        dynamic_descriptor_set.update_storage_buffers(index, 8, &[stats_buffer]);
+      }
+    }
+
+    Ok(dynamic_descriptor_set)
+  }
+
+  /// Create the descriptor sets and uniform buffers of the additional views.
+  /// The first view uses the main descriptor sets and uniform buffers.
+  /// return: The result.
+  fn create_view_resources(&mut self) -> Result<(), HalaRendererError> {
+    // Retire the old view resources, they may still be used by the in-flight frames.
+    for view_resources in std::mem::take(&mut self.view_resources) {
+      self.resources.retire(Box::new(view_resources));
+    }
+    if self.dynamic_descriptor_set.is_none() || self.views.len() <= 1 {
+      return Ok(());
+    }
+
+    let num_of_frames = self.resources.num_of_frames;
+    let mut required_sizes = self.get_dynamic_descriptor_sizes()?;
+    required_sizes.push((hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, 4 * num_of_frames));
+    required_sizes.push((hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, num_of_frames));
+    required_sizes.push((hala_gfx::HalaDescriptorType::SAMPLER, num_of_frames));
+    for view_index in 1..self.views.len() {
+      self.resources.reserve_descriptors(&required_sizes, num_of_frames * 2)?;
+
+      let global_uniform_buffers = Self::create_global_uniform_buffers(&self.resources, &format!("view_{}_global", view_index))?;
+      let static_descriptor_set = Self::create_static_descriptor_set(&self.resources, &format!("view_{}", view_index))?;
+      let object_uniform_buffers = self.create_object_uniform_buffers(&format!("view_{}_object", view_index))?;
+      let dynamic_descriptor_set = self.create_dynamic_descriptor_set(&object_uniform_buffers, &format!("view_{}", view_index))?;
+
+      let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+      Self::update_static_descriptor_set(&static_descriptor_set, &global_uniform_buffers, scene);
+      if let Some(shadow_atlas) = self.shadow.atlas.as_ref() {
+        shadow_atlas.update_descriptor_set(&static_descriptor_set, num_of_frames);
+      }
+
+      self.view_resources.push(HalaViewResources {
+        global_uniform_buffers,
+        static_descriptor_set,
+        dynamic_descriptor_set,
+        object_uniform_buffers,
+      });
+    }
+
+    Ok(())
+  }
+
+  /// Get the default view with the No.1 camera, it covers the render area or the full window.
+  /// return: The view.
+  fn get_full_view(&self) -> HalaViewDesc {
+    let (target_width, target_height) = self.get_target_size();
+    match self.render_area {
+      Some((x, y, width, height)) => {
+        let x = x.clamp(0, target_width as i32);
+        let y = y.clamp(0, target_height as i32);
+        let width = width.min(target_width - x as u32);
+        let height = height.min(target_height - y as u32);
+        HalaViewDesc {
+          camera_index: 0,
+          viewport: (x as f32, y as f32, width as f32, height as f32),
+          scissor: (x, y, width, height),
+        }
+      },
+      None => self.get_window_view(),
+    }
+  }
+
+  /// Get the full window view with the No.1 camera, it covers the output image if it is set.
+  /// return: The view.
+  fn get_window_view(&self) -> HalaViewDesc {
+    let (width, height) = self.get_target_size();
+    HalaViewDesc {
+      camera_index: 0,
+      viewport: (0., 0., width as f32, height as f32),
+      scissor: (0, 0, width, height),
+    }
+  }
+
+  /// Get the size of the scene target, the output image or the window.
+  /// return: The width and the height.
+  fn get_target_size(&self) -> (u32, u32) {
+    match (self.output_size, self.output_color_image.as_ref()) {
+      (Some(size), Some(_)) => size,
+      _ => (self.info.width, self.info.height),
+    }
+  }
+
+  /// Select the level of detail of the primitive.
+  /// The coarsest level whose simplification error projected on the screen is less than 2^lod_bias pixels is selected.
+  /// param mesh: The mesh of the primitive.
+  /// param primitive: The primitive.
+  /// param v_mtx: The view matrix.
+  /// param p_mtx: The projection matrix.
+  /// param viewport_height: The viewport height in pixels.
+  /// return: The level of detail, none if the primitive has no level of detail.
+  fn select_lod<'a>(
+    &self,
+    mesh: &gpu::HalaMesh,
+    primitive: &'a gpu::HalaPrimitive,
+    v_mtx: &glam::Mat4,
+    p_mtx: &glam::Mat4,
+    viewport_height: f32,
+  ) -> Option<&'a HalaLod> {
+    if primitive.lods.len() <= 1 {
+      return primitive.lods.first();
+    }
+
+    // The bounding sphere in the view space.
+    let mv_mtx = *v_mtx * mesh.transform;
+    let max_scale = mv_mtx.x_axis.truncate().length()
+      .max(mv_mtx.y_axis.truncate().length())
+      .max(mv_mtx.z_axis.truncate().length());
+    let radius = glam::Vec3::from(primitive.bounds.extents).length() * max_scale;
+    let center = mv_mtx.transform_point3(glam::Vec3::from(primitive.bounds.center));
+
+    // The pixels per world unit at the distance of the sphere, the perspective projection has 0 at w_axis.w.
+    let is_perspective = p_mtx.w_axis.w == 0.0;
+    let distance = if is_perspective { (center.length() - radius).max(1e-4) } else { 1.0 };
+    let pixels_per_unit = p_mtx.y_axis.y.abs() * 0.5 * viewport_height / distance;
+
+    let threshold = 2f32.powf(self.lod_bias);
+    primitive.lods.iter().rev()
+      .find(|lod| lod.error * radius * 2.0 * pixels_per_unit <= threshold)
+      .or(primitive.lods.first())
+  }
+
+  /// Get the views to render.
+  /// return: The views, the full window view if no view is set.
+  fn get_views(&self) -> Vec<HalaViewDesc> {
+    if self.views.is_empty() {
+      vec![self.get_full_view()]
+    } else {
+      self.views.clone()
+    }
+  }
+
+  /// Get the view and the projection matrices of the view.
+  /// The first view uses the override of set_view_projection if it is set, the others use their scene cameras.
+  /// param scene: The scene in the GPU.
+  /// param view_index: The index of the view.
+  /// param view: The view.
+  /// return: The view and the projection matrices.
+  fn get_view_matrices(&self, scene: &gpu::HalaScene, view_index: usize, view: &HalaViewDesc) -> Result<(glam::Mat4, glam::Mat4), HalaRendererError> {
+    if let (0, Some(view_projection)) = (view_index, self.view_projection_override) {
+      return Ok(view_projection);
+    }
+
+    let camera_index = view.camera_index as usize;
+    match (scene.camera_view_matrices.get(camera_index), scene.camera_proj_matrices.get(camera_index)) {
+      (Some(v_mtx), Some(p_mtx)) => Ok((*v_mtx, *p_mtx)),
+      _ => Err(HalaRendererError::new(&format!("The camera index {} of the view {} is out of range!", camera_index, view_index), None)),
+    }
+  }
+
+  /// Get the static and dynamic descriptor sets of the view.
+  /// param view_index: The index of the view.
+  /// return: The static and dynamic descriptor sets.
+  fn get_view_descriptor_sets(&self, view_index: usize) -> Result<(&hala_gfx::HalaDescriptorSet, &hala_gfx::HalaDescriptorSet), HalaRendererError> {
+    if view_index == 0 {
+      Ok((
+        &self.static_descriptor_set,
+        self.dynamic_descriptor_set.as_ref().ok_or(HalaRendererError::new("The dynamic descriptor set is none!", None))?,
+      ))
+    } else {
+      let view_resources = self.view_resources.get(view_index - 1).ok_or(HalaRendererError::new("The view resources are none!", None))?;
+      Ok((&view_resources.static_descriptor_set, &view_resources.dynamic_descriptor_set))
+    }
+  }
+
+  /// Get the views with their descriptor sets for the overlay passes.
+  /// return: The overlay views.
+  fn get_overlay_views(&self) -> Result<Vec<HalaOverlayView<'_>>, HalaRendererError> {
+    let mut overlay_views = Vec::with_capacity(self.views.len().max(1));
+    for (view_index, view) in self.get_views().into_iter().enumerate() {
+      let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(view_index)?;
+      overlay_views.push(HalaOverlayView {
+        view,
+        static_descriptor_set,
+        dynamic_descriptor_set,
+      });
+    }
+
+    Ok(overlay_views)
+  }
+
+  /// Set the viewport and scissor of the view.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param view: The view.
+  fn set_view_viewport(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, view: &HalaViewDesc) {
+    set_view_viewport(index, command_buffers, view);
+  }
+
+  /// Restore the full window viewport for the following passes after a pass set the viewports of the views.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  fn restore_window_viewport(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if !self.views.is_empty() || self.render_area.is_some() {
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+    }
+  }
+
+  /// Get the size of the push constants of draw_primitive.
+  /// param scene: The scene in the GPU.
+  /// return: The size in bytes.
+  fn get_draw_push_constant_size(&self, scene: &gpu::HalaScene) -> u32 {
+    if self.use_bindless {
+      4 // Primitive index, the others are in the primitive table.
+    } else if !self.use_mesh_shader {
+      12  // Mesh index, Material index and Primitive index.
+    } else {
+      if scene.meshlets.is_none() {
+        20  // Mesh index, Material index, Primitive index, Meshlet count and First meshlet.
+      } else {
+        // If we use global meshlets, we only need Meshlet count.
+        4
+      }
+    } + if self.use_occlusion_culling { 8 } else { 0 } // Culling phase and Visibility offset.
+  }
+
+  /// Build the push constants and the ranges of the primitive draw, and count it.
+  /// param mesh_index: The index of the mesh.
+  /// param primitive: The primitive.
+  /// param draw_index: The index of the primitive in the scene order.
+  /// param lod: The level of detail, none to draw the full primitive.
+  /// param culling_phase: The phase of the occlusion culling, 0 draws all meshlets without the occlusion test.
+  /// return: The push constants, the first index, the index count and the task group count, none if the primitive is empty.
+  fn resolve_primitive_draw(
+    &self,
+    mesh_index: usize,
+    primitive: &gpu::HalaPrimitive,
+    draw_index: u32,
+    lod: Option<&HalaLod>,
+    culling_phase: u32,
+  ) -> Option<(Vec<u8>, u32, u32, u32)> {
+    // The task shader reads the meshlet range from the primitive table in the bindless mode, so it draws the level 0.
+    let lod = if self.use_bindless && self.use_mesh_shader { None } else { lod };
+    let (first_index, index_count, first_meshlet, meshlet_count) = match lod {
+      Some(lod) => (lod.first_index, lod.index_count, lod.first_meshlet, lod.meshlet_count),
+      None => (0, primitive.index_count, 0, primitive.meshlet_count),
+    };
+    // The ranges are relative to the primitive, offset them into the mega buffers.
+    let first_index = primitive.first_index + first_index;
+    let first_meshlet = primitive.first_meshlet + first_meshlet;
+
+    // Skip the empty primitive without a draw call.
+    if (!self.use_mesh_shader && index_count == 0) || (self.use_mesh_shader && meshlet_count == 0) {
+      self.count_draws(|counters| counters.add_skipped_primitive());
+      return None;
+    }
+
+    // Build push constants.
+    // 32 threads per task group, each thread tests one meshlet and the surviving meshlets are emitted to the mesh shader.
+    // See shaders/rz/meshlet_culling.task.hlsl for the reference culling task shader.
+    let dispatch_size_x = (meshlet_count + 32 - 1) / 32;
+    let mut push_constants = Vec::new();
+    if self.use_bindless {
+      push_constants.extend_from_slice(&draw_index.to_le_bytes());
+    } else {
+      push_constants.extend_from_slice(&(mesh_index as u32).to_le_bytes());
+      push_constants.extend_from_slice(&self.get_primitive_material_index(mesh_index, primitive).to_le_bytes());
+      push_constants.extend_from_slice(&draw_index.to_le_bytes());
+    }
+    if self.use_mesh_shader && !self.use_bindless {
+      push_constants.extend_from_slice(&meshlet_count.to_le_bytes());
+      push_constants.extend_from_slice(&first_meshlet.to_le_bytes());
+    }
+    // The phase 1 draws the meshlets visible in the last frame, the phase 2 tests the others against the depth pyramid
+    // and both update the visibility bit from the visibility offset plus the meshlet index relative to the primitive.
+    if self.use_occlusion_culling {
+      let visibility_offset = self.occlusion_culling_visibility_offsets.get(draw_index as usize).copied().unwrap_or(0);
+      push_constants.extend_from_slice(&culling_phase.to_le_bytes());
+      push_constants.extend_from_slice(&visibility_offset.to_le_bytes());
+    }
+
+    if !self.use_mesh_shader {
+      self.count_draws(|counters| counters.add_indexed_draw(index_count));
+    } else {
+      self.count_draws(|counters| counters.add_mesh_tasks_draw(dispatch_size_x));
+    }
+
+    Some((push_constants, first_index, index_count, dispatch_size_x))
+  }
+
+  /// Push the constants and draw the primitive with the bound graphics pipeline.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param graphics_pipeline: The bound graphics pipeline.
+  /// param scene: The scene in the GPU.
+  /// param mesh_index: The index of the mesh.
+  /// param primitive: The primitive.
+  /// param draw_index: The index of the primitive in the scene order.
+  /// param lod: The level of detail, none to draw the full primitive.
+  /// param culling_phase: The phase of the occlusion culling, 0 draws all meshlets without the occlusion test.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  fn draw_primitive(
+    &self,
+    index: usize,
+    command_buffers: &hala_gfx::HalaCommandBufferSet,
+    graphics_pipeline: &hala_gfx::HalaGraphicsPipeline,
+    scene: &gpu::HalaScene,
+    mesh_index: usize,
+    primitive: &gpu::HalaPrimitive,
+    draw_index: u32,
+    lod: Option<&HalaLod>,
+    culling_phase: u32,
+  ) -> Result<(), HalaRendererError> {
+    let (push_constants, first_index, index_count, dispatch_size_x) = match self.resolve_primitive_draw(mesh_index, primitive, draw_index, lod, culling_phase) {
+      Some(draw) => draw,
+      None => return Ok(()),
+    };
+
+    // Push constants.
+    command_buffers.push_constants(
+      index,
+      graphics_pipeline.layout,
+      if !self.use_mesh_shader { hala_gfx::HalaShaderStageFlags::VERTEX } else { hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH }
+        | hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      push_constants.as_slice(),
+    );
+
+    // Draw.
+    if !self.use_mesh_shader {
+      // Bind vertex buffers.
+      command_buffers.bind_vertex_buffers(
+        index,
+        0,
+        &[scene.get_vertex_buffer(primitive).ok_or(HalaRendererError::new("The vertex buffer is none!", None))?],
+        &[0]);
+
+      // Bind index buffer.
+      command_buffers.bind_index_buffers(
+        index,
+        &[scene.get_index_buffer(primitive).ok_or(HalaRendererError::new("The index buffer is none!", None))?],
+        &[0],
+        hala_gfx::HalaIndexType::UINT32);
+
+      command_buffers.draw_indexed(
+        index,
+        index_count,
+        1,
+        first_index,
+        primitive.vertex_offset as i32,
+        0
+      );
+    } else {
+      command_buffers.draw_mesh_tasks(
+        index,
+        dispatch_size_x,
+        1,
+        1,
+      );
+    }
+
+    Ok(())
+  }
+
+  /// Update the draw counters of the recording frame.
+  /// param f: The function to update the counters.
+  fn count_draws<F>(&self, f: F)
+    where F: FnOnce(&mut HalaDrawCounters)
+  {
+    let mut counters = self.draw_counters.get();
+    f(&mut counters);
+    self.draw_counters.set(counters);
+  }
+
+  /// Draw the primitives of the scene routed to the pass.
+  /// The draws are precomputed by build_draw_groups, so each pipeline and the descriptor sets are bound once per view.
+  /// They are recorded into the primary command buffer on the calling thread, or by the recording threads if set_recording_threads
+  /// is called with more than 1 thread, see draw_scene_in_parallel.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param pass: The material pass to draw.
+  /// param culling_phase: The phase of the occlusion culling, 0 draws all meshlets without the occlusion test.
+  /// return: The pipeline bind count.
+  fn draw_scene(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, pass: HalaMaterialPass, culling_phase: u32) -> Result<u64, HalaRendererError> {
+    if self.resources.is_parallel_recording() {
+      return self.draw_scene_in_parallel(index, command_buffers, pass, culling_phase);
+    }
+
+    let scene = self.scene_in_gpu.as_ref().ok_or(hala_gfx::HalaGfxError::new("The scene in GPU is none!", None))?;
+    let is_forward = pass == HalaMaterialPass::FORWARD;
+    let graphics_pipelines = if is_forward && self.debug_view == HalaDebugView::OVERDRAW {
+      &self.overdraw_graphics_pipelines
+    } else if is_forward {
+      &self.forward_graphics_pipelines
+    } else {
+      &self.deferred_graphics_pipelines
+    };
+
+    let draw_groups = &self.draw_groups[pass.to_u8() as usize];
+
+    let mut num_of_pipeline_binds = 0u64;
+    for (view_index, view) in self.get_views().iter().enumerate() {
+      self.set_view_viewport(index, command_buffers, view);
+      let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(view_index)?;
+      let (v_mtx, p_mtx) = self.get_view_matrices(scene, view_index, view)?;
+
+      // Render the scene.
+      // The pipelines of the material types share the layout, so the descriptor sets are bound once for the view.
+      let mut is_descriptor_sets_bound = false;
+      for group in draw_groups.iter() {
+        // The hidden meshes keep their draw indices, so the occlusion results do not shift.
+        if !group.draws.iter().any(|&(mesh_index, _, _)| self.is_mesh_visible(mesh_index)) {
+          continue;
+        }
+
+        // Use specific material type pipeline state object.
+        let graphics_pipeline = graphics_pipelines.get(group.material_type).ok_or(HalaRendererError::new(
+          &format!("The graphics pipeline of the material type {} is none! Push a shader for each material type.", group.material_type), None))?;
+        command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+        num_of_pipeline_binds += 1;
+
+        // Bind descriptor sets.
+        if !is_descriptor_sets_bound {
+          command_buffers.bind_graphics_descriptor_sets(
+            index,
+            graphics_pipeline,
+            0,
+            &[
+              static_descriptor_set,
+              dynamic_descriptor_set,
+              self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?],
+            &[],
+          );
+          is_descriptor_sets_bound = true;
+        }
+
+        for &(mesh_index, primitive_index, draw_index) in group.draws.iter() {
+          if !self.is_mesh_visible(mesh_index) {
+            continue;
+          }
+          let mesh = &scene.meshes[mesh_index];
+          let primitive = &mesh.primitives[primitive_index];
+
+          // Select the level of detail by the projected error.
+          // Only the first view is queried, a query can not begin twice before it is reset.
+          let lod = self.select_lod(mesh, primitive, &v_mtx, &p_mtx, view.viewport.3);
+          let occlusion_query = match self.occlusion_query_pool.as_ref() {
+            Some(query_pool) if view_index == 0 && (draw_index as usize) < self.max_occlusion_queries =>
+              Some((query_pool, (index * self.max_occlusion_queries) as u32 + draw_index)),
+            _ => None,
+          };
+          if let Some((query_pool, query_index)) = occlusion_query {
+            command_buffers.begin_query(index, query_pool, query_index, hala_gfx::HalaQueryControlFlags::PRECISE);
+          }
+          self.draw_primitive(index, command_buffers, graphics_pipeline, scene, mesh_index, primitive, draw_index, lod, culling_phase)?;
+          if let Some((query_pool, query_index)) = occlusion_query {
+            command_buffers.end_query(index, query_pool, query_index);
+          }
+        }
+      }
+    }
+
+    // Restore the full window viewport for the following passes.
+    if !self.views.is_empty() || self.render_area.is_some() {
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Draw the primitives of the scene routed to the pass by the recording threads.
+  /// The draws are resolved in the order of the serial recording with their pipelines, descriptor sets and viewports,
+  /// then split into contiguous chunks which are recorded into the secondary command buffers and executed in order,
+  /// so the image and the draw counters do not depend on the thread count.
+  /// The rendering or the render pass must be begun with the secondary contents, and the state of the primary command buffer
+  /// is undefined after it, see restore_scene_viewport.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param pass: The material pass to draw.
+  /// param culling_phase: The phase of the occlusion culling, 0 draws all meshlets without the occlusion test.
+  /// return: The pipeline bind count.
+  fn draw_scene_in_parallel(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, pass: HalaMaterialPass, culling_phase: u32) -> Result<u64, HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(hala_gfx::HalaGfxError::new("The scene in GPU is none!", None))?;
+    let is_forward = pass == HalaMaterialPass::FORWARD;
+    let graphics_pipelines = if is_forward && self.debug_view == HalaDebugView::OVERDRAW {
+      &self.overdraw_graphics_pipelines
+    } else if is_forward {
+      &self.forward_graphics_pipelines
+    } else {
+      &self.deferred_graphics_pipelines
+    };
+    let textures_descriptor_set = self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?;
+    let push_constant_stages = if !self.use_mesh_shader { vk::ShaderStageFlags::VERTEX } else { vk::ShaderStageFlags::TASK_EXT | vk::ShaderStageFlags::MESH_EXT }
+      | vk::ShaderStageFlags::FRAGMENT;
+
+    let draw_groups = &self.draw_groups[pass.to_u8() as usize];
+
+    let mut draws = Vec::new();
+    for (view_index, view) in self.get_views().iter().enumerate() {
+      let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(view_index)?;
+      let (v_mtx, p_mtx) = self.get_view_matrices(scene, view_index, view)?;
+      let descriptor_sets = [
+        get_raw_descriptor_set(static_descriptor_set, index),
+        get_raw_descriptor_set(dynamic_descriptor_set, index),
+        get_raw_descriptor_set(textures_descriptor_set, index),
+      ];
+      // The same flipped viewport as set_view_viewport.
+      let (x, y, width, height) = view.viewport;
+      let viewport = (x, y + height, width, -height);
+
+      for group in draw_groups.iter() {
+        let graphics_pipeline = graphics_pipelines.get(group.material_type).ok_or(HalaRendererError::new(
+          &format!("The graphics pipeline of the material type {} is none! Push a shader for each material type.", group.material_type), None))?;
+
+        for &(mesh_index, primitive_index, draw_index) in group.draws.iter() {
+          if !self.is_mesh_visible(mesh_index) {
+            continue;
+          }
+          let mesh = &scene.meshes[mesh_index];
+          let primitive = &mesh.primitives[primitive_index];
+
+          // Only the first view is queried, a query can not begin twice before it is reset.
+          let lod = self.select_lod(mesh, primitive, &v_mtx, &p_mtx, view.viewport.3);
+          let occlusion_query = match self.occlusion_query_pool.as_ref() {
+            Some(query_pool) if view_index == 0 && (draw_index as usize) < self.max_occlusion_queries =>
+              Some((query_pool.raw, (index * self.max_occlusion_queries) as u32 + draw_index)),
+            _ => None,
+          };
+          let (push_constants, first_index, index_count, dispatch_size_x) = match self.resolve_primitive_draw(mesh_index, primitive, draw_index, lod, culling_phase) {
+            Some(draw) => draw,
+            None => continue,
+          };
+          let call = if !self.use_mesh_shader {
+            HalaSecondaryDrawCall::Indexed {
+              vertex_buffer: scene.get_vertex_buffer(primitive).ok_or(HalaRendererError::new("The vertex buffer is none!", None))?.raw,
+              index_buffer: scene.get_index_buffer(primitive).ok_or(HalaRendererError::new("The index buffer is none!", None))?.raw,
+              index_count,
+              first_index,
+              vertex_offset: primitive.vertex_offset as i32,
+            }
+          } else {
+            HalaSecondaryDrawCall::MeshTasks { group_count_x: dispatch_size_x }
+          };
+
+          draws.push(HalaSecondaryDraw {
+            pipeline: graphics_pipeline.raw,
+            pipeline_layout: graphics_pipeline.layout,
+            descriptor_sets,
+            viewport,
+            scissor: view.scissor,
+            push_constant_stages,
+            push_constants,
+            call,
+            occlusion_query,
+          });
+        }
+      }
+    }
+
+    let inheritance = self.get_scene_inheritance(pass)?;
+    self.resources.execute_draws_in_parallel(index, command_buffers, &inheritance, &draws)
+  }
+
+  /// Get the render pass or the rendering scope of the scene draws of the pass for the secondary command buffers.
+  /// The forward scene is rendered into the images of the first active post process with the swapchain formats,
+  /// or into the swapchain with the multisample count.
+  /// param pass: The material pass.
+  /// return: The inheritance.
+  fn get_scene_inheritance(&self, pass: HalaMaterialPass) -> Result<HalaSecondaryInheritance, HalaRendererError> {
+    let context = self.resources.context.borrow();
+
+    if pass == HalaMaterialPass::FORWARD {
+      let is_swapchain_rendering = !self.is_output_image_active() && !self.is_post_process_chain_active();
+      let depth_format = vk::Format::from(context.swapchain.depth_stencil_format);
+      Ok(HalaSecondaryInheritance::Rendering {
+        color_formats: vec![vk::Format::from(context.swapchain.format)],
+        depth_format,
+        stencil_format: if context.swapchain.has_stencil { depth_format } else { vk::Format::UNDEFINED },
+        samples: if is_swapchain_rendering { vk::SampleCountFlags::from(context.multisample_count) } else { vk::SampleCountFlags::TYPE_1 },
+      })
+    } else if self.use_deferred_subpasses {
+      let render_pass = self.deferred_render_pass.as_ref().ok_or(HalaRendererError::new("The deferred render pass is none!", None))?;
+      Ok(HalaSecondaryInheritance::RenderPass {
+        render_pass: render_pass.raw,
+        subpass: 0,
+      })
+    } else {
+      let depth_format = vk::Format::from(self.gbuffer_depth_format);
+      Ok(HalaSecondaryInheritance::Rendering {
+        color_formats: self.get_gbuffer_color_images()?.iter().map(|image| vk::Format::from(image.format)).collect(),
+        depth_format,
+        stencil_format: if self.has_gbuffer_stencil() { depth_format } else { vk::Format::UNDEFINED },
+        samples: vk::SampleCountFlags::TYPE_1,
+      })
+    }
+  }
+
+  /// Set the contents of the next rendering of the scene draws.
+  /// The draws of the recording threads are executed from the secondary command buffers, which can not be mixed with the inline commands.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  fn set_scene_rendering_contents(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if self.resources.is_parallel_recording() {
+      command_buffers.set_next_rendering_contents(index, hala_gfx::HalaSubpassContents::SECONDARY_COMMAND_BUFFERS);
+    }
+  }
+
+  /// Get the contents of the subpass of the scene draws.
+  /// return: The subpass contents.
+  fn get_scene_subpass_contents(&self) -> hala_gfx::HalaSubpassContents {
+    if self.resources.is_parallel_recording() {
+      hala_gfx::HalaSubpassContents::SECONDARY_COMMAND_BUFFERS
+    } else {
+      hala_gfx::HalaSubpassContents::INLINE
+    }
+  }
+
+  /// Restore the window viewport after the scene rendering has ended.
+  /// The state of the primary command buffer is undefined after the secondary command buffers are executed,
+  /// so the following passes get the viewport of the serial recording.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  fn restore_scene_viewport(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) {
+    if self.resources.is_parallel_recording() {
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+    }
+  }
+
+  /// Render the shadow maps of the lights into the tiles of the shadow atlas.
+  /// The visible meshes of both passes are drawn at the level 0 into each tile, the hidden meshes cast no shadow.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn render_shadows(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    if self.shadow.graphics_pipeline.is_none() {
+      return Ok(0);
+    }
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let draw_stage_flags = if !self.use_mesh_shader {
+      hala_gfx::HalaShaderStageFlags::VERTEX
+    } else {
+      hala_gfx::HalaShaderStageFlags::TASK | hala_gfx::HalaShaderStageFlags::MESH
+    } | hala_gfx::HalaShaderStageFlags::FRAGMENT;
+
+    self.shadow.render(
+      index,
+      command_buffers,
+      [
+        &self.static_descriptor_set,
+        self.dynamic_descriptor_set.as_ref().ok_or(HalaRendererError::new("The dynamic descriptor set is none!", None))?,
+        self.textures_descriptor_set.as_ref().ok_or(HalaRendererError::new("The textures descriptor set is none!", None))?,
+      ],
+      draw_stage_flags,
+      self.get_draw_push_constant_size(scene),
+      self.depth_convention.get_clear_depth(),
+      &|graphics_pipeline| {
+        for &(mesh_index, primitive_index, draw_index) in self.draw_groups.iter().flatten().flat_map(|group| group.draws.iter()) {
+          if !self.is_mesh_visible(mesh_index) {
+            continue;
+          }
+          let primitive = &scene.meshes[mesh_index].primitives[primitive_index];
+          self.draw_primitive(index, command_buffers, graphics_pipeline, scene, mesh_index, primitive, draw_index, None, 0)?;
+        }
+        Ok(())
+      },
+    )
+  }
+
+  /// Rasterize the meshlets into the visibility images by the software raster program.
+  /// The program is dispatched three times with the pass in the push constants:
+  /// 0 clears the visibility images, 1 writes the nearest depth by the atomic operation and 2 writes the id of the nearest triangle.
+  /// The depth and the id passes dispatch one group per meshlet of each primitive at the level 0.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The result.
+  fn rasterize_software_meshlets(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let program = self.software_raster_program.as_ref().ok_or(HalaRendererError::new("The software raster program is none!", None))?;
+    let visibility_depth_image = self.visibility_depth_image.as_ref().ok_or(HalaRendererError::new("The visibility depth image is none!", None))?;
+    let visibility_id_image = self.visibility_id_image.as_ref().ok_or(HalaRendererError::new("The visibility id image is none!", None))?;
+    let visibility_descriptor_set = self.visibility_descriptor_set.as_ref().ok_or(HalaRendererError::new("The visibility descriptor set is none!", None))?;
+    let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(0)?;
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Software Meshlet Raster", [1.0, 0.5, 0.0, 1.0]);
+    }
+
+    let set_visibility_barriers = |old_layout, src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask| {
+      let barrier = |image: &hala_gfx::HalaImage| hala_gfx::HalaImageBarrierInfo {
+        old_layout,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask,
+        dst_access_mask,
+        src_stage_mask,
+        dst_stage_mask,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: image.raw,
+        ..Default::default()
+      };
+      command_buffers.set_image_barriers(index, &[barrier(visibility_depth_image), barrier(visibility_id_image)]);
+    };
+    let compute_to_compute = |old_layout| set_visibility_barriers(
+      old_layout,
+      hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+      hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+    );
+
+    program.bind(
+      index,
+      command_buffers,
+      &[
+        static_descriptor_set,
+        dynamic_descriptor_set,
+        self.textures_descriptor_set.as_ref().ok_or(HalaRendererError::new("The textures descriptor set is none!", None))?,
+        visibility_descriptor_set,
+      ],
+    );
+
+    // Clear the visibility images, the previous frame may still read them in the material pass.
+    compute_to_compute(hala_gfx::HalaImageLayout::UNDEFINED);
+    program.push_constants(index, command_buffers, 0, &[0u32; 6].iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>());
+    program.dispatch(index, command_buffers, (self.info.width + 7) / 8, (self.info.height + 7) / 8, 1);
+
+    // Write the depth, then write the id of the triangle whose depth equals the nearest depth.
+    for pass in 1..=2u32 {
+      compute_to_compute(hala_gfx::HalaImageLayout::GENERAL);
+      let mut draw_index = 0u32;
+      for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+        for primitive in mesh.primitives.iter() {
+          if primitive.meshlet_count > 0 && self.is_mesh_visible(mesh_index) {
+            let push_constants = [
+              pass,
+              mesh_index as u32,
+              self.get_primitive_material_index(mesh_index, primitive),
+              draw_index,
+              primitive.meshlet_count,
+              primitive.first_meshlet,
+            ];
+            program.push_constants(index, command_buffers, 0, &push_constants.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>());
+            program.dispatch(index, command_buffers, primitive.meshlet_count, 1, 1);
+          }
+          draw_index += 1;
+        }
+      }
+    }
+
+    // The material pass reads the visibility images.
+    set_visibility_barriers(
+      hala_gfx::HalaImageLayout::GENERAL,
+      hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+      hala_gfx::HalaAccessFlags2::SHADER_READ,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    Ok(())
+  }
+
+  /// Draw the material pass of the software meshlet path.
+  /// The fragment shader reads the triangle id from the visibility images and shades it with the scene buffers.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_software_meshlet_material(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.software_material_graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The software material graphics pipeline is none!", None))?;
+    let visibility_descriptor_set = self.visibility_descriptor_set.as_ref().ok_or(HalaRendererError::new("The visibility descriptor set is none!", None))?;
+    let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(0)?;
+
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.bind_graphics_descriptor_sets(
+      index,
+      graphics_pipeline,
+      0,
+      &[
+        static_descriptor_set,
+        dynamic_descriptor_set,
+        self.textures_descriptor_set.as_ref().ok_or(HalaRendererError::new("The textures descriptor set is none!", None))?,
+        visibility_descriptor_set,
+      ],
+      &[],
+    );
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+  /// Draw the infinite grid for each view, the deferred grid reads the G-Buffer depth by the lighting descriptor set.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_grid(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let lighting_descriptor_set = if self.use_deferred {
+      Some(self.lighting_descriptor_set.as_ref().ok_or(HalaRendererError::new("The lighting pass descriptor set is none!", None))?)
+    } else {
+      None
+    };
+    let num_of_pipeline_binds = self.grid.draw(index, command_buffers, &self.get_overlay_views()?, lighting_descriptor_set)?;
+    if num_of_pipeline_binds > 0 {
+      self.restore_window_viewport(index, command_buffers);
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Draw the axis gizmo in the bottom left corner of the first view.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_axis_gizmo(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    if !self.axis_gizmo.is_active() {
+      return Ok(0);
+    }
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let views = self.get_views();
+    let view = views.first().ok_or(HalaRendererError::new("There is no view!", None))?;
+    let (v_mtx, _) = self.get_view_matrices(scene, 0, view)?;
+
+    let num_of_pipeline_binds = self.axis_gizmo.draw(index, command_buffers, view, v_mtx)?;
+
+    // Restore the full window viewport for the following passes.
+    self.set_view_viewport(index, command_buffers, &self.get_window_view());
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Add the range spheres of the point lights and the range cones of the spot lights to the immediate lines.
+  /// The lights without the range are skipped.
+  fn draw_light_range_lines(&mut self) {
+    let lights = match self.scene_in_gpu.as_ref() {
+      Some(scene) => scene.light_data.iter()
+        .filter(|light| light.range > 0.0 && (light._type == 0 || light._type == 2))
+        .map(|light| (light._type, glam::Vec3::from(light.position), glam::Vec3::from(light.u), light.v.y, light.range))
+        .collect::<Vec<_>>(),
+      None => return,
+    };
+
+    self.lines.draw_lights(&lights);
+  }
+
+  /// Upload the immediate lines of this frame into the vertex buffer of the current image and clear them.
+  /// return: The result.
+  fn upload_lines(&mut self) -> Result<(), HalaRendererError> {
+    let old_buffer = {
+      let context = self.resources.context.borrow();
+      self.lines.upload(&context, self.data.image_index, self.resources.num_of_frames)?
+    };
+    if let Some(old_buffer) = old_buffer {
+      self.resources.retire(Box::new(old_buffer));
+    }
+
+    Ok(())
+  }
+
+  /// Draw the immediate lines of this frame for each view.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_lines(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let num_of_pipeline_binds = self.lines.draw(index, command_buffers, &self.get_overlay_views()?)?;
+    if num_of_pipeline_binds > 0 {
+      self.restore_window_viewport(index, command_buffers);
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Draw the wireframe and the normal lines of the scene on top of the shaded image.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_overlay(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    if !self.overlay.is_active() {
+      return Ok(0);
+    }
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let textures_descriptor_set = self.textures_descriptor_set.as_ref().ok_or(HalaRendererError::new("The textures descriptor set is none!", None))?;
+
+    let num_of_pipeline_binds = self.overlay.draw(
+      index,
+      command_buffers,
+      scene,
+      &self.get_overlay_views()?,
+      textures_descriptor_set,
+      &|mesh_index| self.is_mesh_visible(mesh_index),
+      &|graphics_pipeline, mesh_index, primitive, draw_index| {
+        self.draw_primitive(index, command_buffers, graphics_pipeline, scene, mesh_index, primitive, draw_index, None, 0)
+      },
+    )?;
+    self.restore_window_viewport(index, command_buffers);
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Draw the selected primitives with the selection graphics pipeline.
+  /// It is drawn after the scene, so the stencil written by the scene pipelines can be tested for the outline.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  fn draw_selection(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = match self.selection_graphics_pipeline.as_ref() {
+      Some(graphics_pipeline) if !self.selection.is_empty() => graphics_pipeline,
+      _ => return Ok(0),
+    };
+    let scene = self.scene_in_gpu.as_ref().ok_or(hala_gfx::HalaGfxError::new("The scene in GPU is none!", None))?;
+
+    let mut num_of_pipeline_binds = 0u64;
+    for (view_index, view) in self.get_views().iter().enumerate() {
+      self.set_view_viewport(index, command_buffers, view);
+      let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(view_index)?;
+
+      command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+      command_buffers.bind_graphics_descriptor_sets(
+        index,
+        graphics_pipeline,
+        0,
+        &[
+          static_descriptor_set,
+          dynamic_descriptor_set,
+          self.textures_descriptor_set.as_ref().ok_or(hala_gfx::HalaGfxError::new("The textures descriptor set is none!", None))?],
+        &[],
+      );
+      num_of_pipeline_binds += 1;
+
+      // The selected primitives are drawn at the level 0, the outline should not change with the level of detail.
+      for &(mesh_index, primitive_index) in self.selection.iter().filter(|(mesh_index, _)| self.is_mesh_visible(*mesh_index)) {
+        let mesh = scene.meshes.get(mesh_index).ok_or(HalaRendererError::new("The selected mesh index is out of range!", None))?;
+        let primitive = mesh.primitives.get(primitive_index).ok_or(HalaRendererError::new("The selected primitive index is out of range!", None))?;
+        let draw_index = scene.meshes[..mesh_index].iter().map(|mesh| mesh.primitives.len() as u32).sum::<u32>() + primitive_index as u32;
+        self.draw_primitive(index, command_buffers, graphics_pipeline, scene, mesh_index, primitive, draw_index, None, 0)?;
+      }
+    }
+
+    // Restore the full window viewport for the following passes.
+    if !self.views.is_empty() || self.render_area.is_some() {
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+    }
+
+    // The outline covers the full window, the stencil test keeps the pixels where the stencil differs from the selection.
+    num_of_pipeline_binds += self.outline.draw(index, command_buffers);
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Whether the stage of the post-process chain is active.
+  /// param stage: The stage.
+  /// return: True if the stage is active.
+  fn is_post_process_active(&self, stage: HalaPostProcessStage) -> bool {
+    match stage {
+      HalaPostProcessStage::Scene => true,
+      HalaPostProcessStage::SSR => self.ssr.is_active(),
+      HalaPostProcessStage::TAA => self.taa.is_active(),
+      HalaPostProcessStage::Bloom => self.bloom.is_active(),
+      HalaPostProcessStage::FXAA => self.fxaa.is_active(),
+      HalaPostProcessStage::ColorGrading => self.color_grading.is_active(),
+      HalaPostProcessStage::Lens => self.lens.is_active(),
+    }
+  }
+
+  /// Get the first active stage of the post-process chain after the stage.
+  /// The SSR is only the target of the deferred lighting, so it is skipped after the scene.
+  /// param after: The stage.
+  /// return: The next active stage, none if the chain ends at the swapchain.
+  fn get_next_post_process_stage(&self, after: HalaPostProcessStage) -> Option<HalaPostProcessStage> {
+    [
+      HalaPostProcessStage::TAA,
+      HalaPostProcessStage::Bloom,
+      HalaPostProcessStage::FXAA,
+      HalaPostProcessStage::ColorGrading,
+      HalaPostProcessStage::Lens,
+    ].into_iter().find(|&stage| stage > after && self.is_post_process_active(stage))
+  }
+
+  /// Whether any pass of the post-process chain is active, then the scene does not render into the swapchain.
+  /// return: True if the post-process chain is active.
+  fn is_post_process_chain_active(&self) -> bool {
+    self.get_next_post_process_stage(HalaPostProcessStage::Scene).is_some()
+  }
+
+  /// Begin the rendering into the target of the first active pass after the stage.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param after: The stage which renders into the target.
+  /// param clear_color: The clear color.
+  /// return: True if the rendering is begun, false if the chain ends at the swapchain.
+  fn begin_post_process_target(
+    &self,
+    index: usize,
+    command_buffers: &hala_gfx::HalaCommandBufferSet,
+    after: HalaPostProcessStage,
+    clear_color: [f32; 4],
+  ) -> Result<bool, HalaRendererError> {
+    let target = match self.get_next_post_process_stage(after) {
+      Some(HalaPostProcessStage::TAA) => self.taa.get_target()?,
+      Some(HalaPostProcessStage::Bloom) => self.bloom.get_target()?,
+      Some(HalaPostProcessStage::FXAA) => self.fxaa.get_target()?,
+      Some(HalaPostProcessStage::ColorGrading) => self.color_grading.get_target()?,
+      Some(HalaPostProcessStage::Lens) => self.lens.get_target()?,
+      _ => return Ok(false),
+    };
+
+    let context = self.resources.context.borrow();
+    target.begin_rendering(index, command_buffers, &context, self.info.width, self.info.height, clear_color, self.get_clear_depth_stencil());
+
+    Ok(true)
+  }
+
+  /// Record the active passes of the post-process chain after the scene or the lighting.
+  /// Each pass ends the rendering into its target, prepares the target to be read, begins the rendering into the target
+  /// of the next active pass or the swapchain, and draws into it. The swapchain rendering is left begun for the UI.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param restore_viewport: Whether to restore the viewport after the scene draws of the recording threads.
+  /// return: The pipeline bind count.
+  fn record_post_process(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, restore_viewport: bool) -> Result<u64, HalaRendererError> {
+    // The SSR is only committed for the deferred lighting.
+    let mut stage = if self.is_post_process_active(HalaPostProcessStage::SSR) {
+      HalaPostProcessStage::SSR
+    } else {
+      match self.get_next_post_process_stage(HalaPostProcessStage::Scene) {
+        Some(stage) => stage,
+        None => return Ok(0),
+      }
+    };
+
+    let mut num_of_pipeline_binds = 0u64;
+
+    loop {
+      command_buffers.end_rendering(index);
+      if restore_viewport {
+        self.restore_scene_viewport(index, command_buffers);
+      }
+
+      num_of_pipeline_binds += match stage {
+        HalaPostProcessStage::SSR => self.ssr.prepare(index, command_buffers)?,
+        HalaPostProcessStage::TAA => {
+          let gbuffer_depth_image = if self.use_deferred { self.depth_image.as_ref() } else { None };
+          self.taa.prepare(index, command_buffers, self.info.width, self.info.height, gbuffer_depth_image)?
+        },
+        HalaPostProcessStage::Bloom => self.bloom.prepare(index, command_buffers, self.info.width, self.info.height)?,
+        HalaPostProcessStage::FXAA => self.fxaa.prepare(index, command_buffers)?,
+        HalaPostProcessStage::ColorGrading => self.color_grading.prepare(index, command_buffers)?,
+        HalaPostProcessStage::Lens => self.lens.prepare(index, command_buffers)?,
+        HalaPostProcessStage::Scene => 0,
+      };
+
+      // The SSR composite keeps the background of the lighting, the following passes cover the full window.
+      let clear_color = if stage == HalaPostProcessStage::SSR { self.clear_color } else { [0.0, 0.0, 0.0, 1.0] };
+      if !self.begin_post_process_target(index, command_buffers, stage, clear_color)? {
+        let context = self.resources.context.borrow();
+        let (clear_depth, clear_stencil) = self.get_clear_depth_stencil();
+        begin_swapchain_rendering(index, command_buffers, &context, self.info.width, self.info.height, None, Some(clear_depth), Some(clear_stencil));
+      }
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+
+      num_of_pipeline_binds += match stage {
+        HalaPostProcessStage::SSR => self.ssr.draw(index, command_buffers)?,
+        HalaPostProcessStage::TAA => self.taa.draw(index, command_buffers)?,
+        HalaPostProcessStage::Bloom => self.bloom.draw(index, command_buffers)?,
+        HalaPostProcessStage::FXAA => self.fxaa.draw(index, command_buffers)?,
+        HalaPostProcessStage::ColorGrading => self.color_grading.draw(index, command_buffers)?,
+        HalaPostProcessStage::Lens => self.lens.draw(index, command_buffers, self.info.width, self.info.height)?,
+        HalaPostProcessStage::Scene => 0,
+      };
+
+      match self.get_next_post_process_stage(stage) {
+        Some(next_stage) => stage = next_stage,
+        None => break,
+      }
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Begin the rendering into the output color and depth images.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param clear_color: The clear color.
+  /// return: The result.
+  fn begin_output_rendering(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, clear_color: [f32; 4]) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    let color_image = self.output_color_image.as_ref().ok_or(HalaRendererError::new("The output color image is none!", None))?;
+    let depth_image = self.output_depth_image.as_ref().ok_or(HalaRendererError::new("The output depth image is none!", None))?;
+    let (width, height) = self.get_target_size();
+
+    command_buffers.set_image_barriers(
+      index,
+      &[
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+          new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: color_image.raw,
+          ..Default::default()
+        },
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+          new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+          image: depth_image.raw,
+          ..Default::default()
+        },
+      ],
+    );
+
+    command_buffers.begin_rendering_with(
+      index,
+      &[color_image],
+      Some(depth_image),
+      (0, 0, width, height),
+      &[Some(clear_color)],
+      Some(self.get_clear_depth_stencil().0),
+      Some(self.get_clear_depth_stencil().1),
+      hala_gfx::HalaAttachmentStoreOp::STORE,
+      hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+      hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+    );
+    self.set_view_viewport(index, command_buffers, &self.get_window_view());
+
+    Ok(())
+  }
+
+  /// Finish the output color image for the host and begin the swapchain rendering for the UI.
+  /// The output color image is left in the SHADER_READ_ONLY_OPTIMAL layout.
+  /// The swapchain image is still acquired by the frame, so it is cleared, drawn by the UI and presented.
+  /// The rendering into the output images must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The result.
+  fn finish_output_rendering(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    let color_image = self.output_color_image.as_ref().ok_or(HalaRendererError::new("The output color image is none!", None))?;
+
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: color_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    command_buffers.set_swapchain_image_barrier(
+      index,
+      &context.swapchain,
+      &hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        ..Default::default()
+      },
+      &hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+        ..Default::default()
+      }
+    );
+
+    command_buffers.begin_rendering_with_swapchain(
+      index,
+      &context.swapchain,
+      (0, 0, self.info.width, self.info.height),
+      Some([0.0, 0.0, 0.0, 1.0]),
+      Some(self.get_clear_depth_stencil().0),
+      Some(self.get_clear_depth_stencil().1),
+    );
+    self.set_view_viewport(
+      index,
+      command_buffers,
+      &HalaViewDesc {
+        camera_index: 0,
+        viewport: (0., 0., self.info.width as f32, self.info.height as f32),
+        scissor: (0, 0, self.info.width, self.info.height),
+      },
+    );
+
+    Ok(())
+  }
+
+  /// Whether the output image is set and created.
+  /// return: True if the scene is rendered into the output image.
+  fn is_output_image_active(&self) -> bool {
+    self.output_size.is_some() && self.output_color_image.is_some()
+  }
+
+  /// Begin the single sample rendering of the UI on the resolved swapchain image.
+  /// The color is loaded, the depth stencil is cleared because the UI does not test the scene depth.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The result.
+  fn begin_ui_rendering(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+
+    command_buffers.set_swapchain_image_barrier(
+      index,
+      &context.swapchain,
+      &hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_READ | hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        ..Default::default()
+      },
+      &hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+        ..Default::default()
+      }
+    );
+    command_buffers.begin_rendering_with_swapchain(
+      index,
+      &context.swapchain,
+      (0, 0, self.info.width, self.info.height),
+      None,
+      Some(self.get_clear_depth_stencil().0),
+      Some(self.get_clear_depth_stencil().1),
+    );
+    self.set_view_viewport(index, command_buffers, &self.get_window_view());
+
+    Ok(())
+  }
+
+  /// Record the forward rendering command buffer.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param ui_fn: The draw UI function.
+  /// return: The pipeline bind count of the scene drawing.
+  fn record_forward_command_buffer<F>(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, ui_fn: F) -> Result<u64, HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>
+  {
+    let context = self.resources.context.borrow();
+
+    // Prepare the command buffer and timestamp.
+    command_buffers.reset(index, false)?;
+    command_buffers.begin(index, hala_gfx::HalaCommandBufferUsageFlags::empty())?;
+    self.resources.reset_secondary_command_pools(index)?;
+    command_buffers.reset_query_pool(index, &context.timestamp_query_pool, (index * 2) as u32, 2);
+    command_buffers.write_timestamp(index, hala_gfx::HalaPipelineStageFlags2::NONE, &context.timestamp_query_pool, (index * 2) as u32);
+    if let Some(query_pool) = self.occlusion_query_pool.as_ref() {
+      command_buffers.reset_query_pool(index, query_pool, (index * self.max_occlusion_queries) as u32, self.max_occlusion_queries as u32);
+    }
+
+    // The shared buffers released by the async compute work are acquired before any pass reads them.
+    self.resources.record_async_compute_acquire(index, command_buffers);
+    self.resources.record_transfer_acquire(index, command_buffers);
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Draw", [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    // The software meshlet path rasterizes the visibility images before the rendering.
+    if self.software_raster_program.is_some() {
+      self.rasterize_software_meshlets(index, command_buffers)?;
+    }
+
+    // The shadow maps are rendered before the scene samples them.
+    let num_of_shadow_pipeline_binds = self.render_shadows(index, command_buffers)?;
+
+    // The scene renders into the target of the first active post-process pass, the last pass writes the swapchain.
+    // The output image mode renders the scene into the output images, the swapchain is only drawn by the UI.
+    let use_post_process = self.is_post_process_chain_active();
+    let use_output_image = self.is_output_image_active();
+    // The scene draws of the recording threads are executed in the rendering of the scene,
+    // so the following draws in the same rendering are recorded into a secondary command buffer too.
+    let is_parallel_recording = self.resources.is_parallel_recording() && self.software_raster_program.is_none();
+    if is_parallel_recording {
+      self.set_scene_rendering_contents(index, command_buffers);
+    }
+    if use_output_image {
+      self.begin_output_rendering(index, command_buffers, self.clear_color)?;
+    } else if use_post_process {
+      self.begin_post_process_target(index, command_buffers, HalaPostProcessStage::Scene, self.clear_color)?;
+    } else {
+      set_swapchain_attachment_barriers(index, command_buffers, &context);
+
+      if context.multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 {
+        let color_multisample_image = self.color_multisample_image.as_ref().ok_or(HalaRendererError::new("The color multisample image is none!", None))?;
+        let depth_stencil_multisample_image = self.depth_stencil_multisample_image.as_ref().ok_or(HalaRendererError::new("The depth stencil multisample image is none!", None))?;
+        command_buffers.set_image_barriers(
+          index,
+          &[
+            hala_gfx::HalaImageBarrierInfo {
+              old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+              new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+              src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+              dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+              src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+              dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+              aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+              image: color_multisample_image.raw,
+              ..Default::default()
+            },
+            hala_gfx::HalaImageBarrierInfo {
+              old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+              new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+              src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+              dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+              src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+              dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+              aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+              image: depth_stencil_multisample_image.raw,
+              ..Default::default()
+            },
+          ],
+        );
+
+        command_buffers.begin_rendering_with_swapchain_multisample(
+          index,
+          &context.swapchain,
+          (0, 0, context.gpu_req.width, context.gpu_req.height),
+          Some(self.clear_color),
+          Some(self.get_clear_depth_stencil().0),
+          Some(self.get_clear_depth_stencil().1),
+          hala_gfx::HalaResolveModeFlags::AVERAGE,
+          color_multisample_image,
+          Some(depth_stencil_multisample_image),
+        );
+      } else {
+        command_buffers.begin_rendering_with_swapchain(
+          index,
+          &context.swapchain,
+          (0, 0, context.gpu_req.width, context.gpu_req.height),
+          Some(self.clear_color),
+          Some(self.get_clear_depth_stencil().0),
+          Some(self.get_clear_depth_stencil().1),
+        );
+      }
+    }
+
+    let draw_after_scene = |index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet| -> Result<u64, HalaRendererError> {
+      Ok(self.draw_lines(index, command_buffers)? + self.draw_selection(index, command_buffers)? + self.draw_axis_gizmo(index, command_buffers)?)
+    };
+    let num_of_pipeline_binds = num_of_shadow_pipeline_binds + if self.software_raster_program.is_some() {
+      self.draw_software_meshlet_material(index, command_buffers)? + draw_after_scene(index, command_buffers)?
+    } else if is_parallel_recording {
+      let inheritance = self.get_scene_inheritance(HalaMaterialPass::FORWARD)?;
+      self.draw_scene(index, command_buffers, HalaMaterialPass::FORWARD, 0)?
+        + self.resources.execute_secondary_draws(index, command_buffers, &inheritance, |index, secondary_command_buffers| {
+          self.set_view_viewport(index, secondary_command_buffers, &self.get_window_view());
+          Ok(
+            self.draw_grid(index, secondary_command_buffers)? + self.draw_overlay(index, secondary_command_buffers)?
+              + draw_after_scene(index, secondary_command_buffers)?
+          )
+        })?
+    } else {
+      self.draw_scene(index, command_buffers, HalaMaterialPass::FORWARD, 0)? + self.draw_grid(index, command_buffers)? + self.draw_overlay(index, command_buffers)?
+        + draw_after_scene(index, command_buffers)?
+    };
+
+    let num_of_pipeline_binds = num_of_pipeline_binds + self.record_post_process(index, command_buffers, true)?;
+    if use_output_image {
+      command_buffers.end_rendering(index);
+      self.restore_scene_viewport(index, command_buffers);
+      self.finish_output_rendering(index, command_buffers)?;
+    }
+
+    // The multisample rendering is resolved to the swapchain image when it ends,
+    // then the UI is drawn in a single sample rendering on the swapchain image like the post process paths.
+    // The rendering with the secondary contents can not record the UI inline, so it is ended too.
+    if !use_output_image && !use_post_process
+      && (context.multisample_count != hala_gfx::HalaSampleCountFlags::TYPE_1 || is_parallel_recording)
+    {
+      command_buffers.end_rendering(index);
+      self.begin_ui_rendering(index, command_buffers)?;
+    }
+
+    ui_fn(index, command_buffers)?;
+
+    command_buffers.end_rendering(index);
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::PRESENT_SRC,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::BOTTOM_OF_PIPE,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: context.swapchain.images[index],
+        ..Default::default()
+      }],
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    // The shared buffers are released to the async compute work submitted after this frame.
+    self.resources.record_async_compute_release(index, command_buffers);
+
+    command_buffers.write_timestamp(
+      index,
+      hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+      &context.timestamp_query_pool,
+      (index * 2 + 1) as u32);
+    command_buffers.end(index)?;
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Record the deferred rendering command buffer.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param ui_fn: The draw UI function.
+  /// return: The pipeline bind count of the scene drawing.
+  fn record_deferred_command_buffer<F>(&self, index: usize, command_buffers: &hala_gfx::HalaCommandBufferSet, ui_fn: F) -> Result<u64, HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>
+  {
+    let context = self.resources.context.borrow();
+
+    // Prepare the command buffer and timestamp.
+    command_buffers.reset(index, false)?;
+    command_buffers.begin(index, hala_gfx::HalaCommandBufferUsageFlags::empty())?;
+    self.resources.reset_secondary_command_pools(index)?;
+    command_buffers.reset_query_pool(index, &context.timestamp_query_pool, (index * 2) as u32, 2);
+    command_buffers.write_timestamp(index, hala_gfx::HalaPipelineStageFlags2::NONE, &context.timestamp_query_pool, (index * 2) as u32);
+    if let Some(query_pool) = self.occlusion_query_pool.as_ref() {
+      command_buffers.reset_query_pool(index, query_pool, (index * self.max_occlusion_queries) as u32, self.max_occlusion_queries as u32);
+    }
+
+    // The shared buffers released by the async compute work are acquired before any pass reads them.
+    self.resources.record_async_compute_acquire(index, command_buffers);
+    self.resources.record_transfer_acquire(index, command_buffers);
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Draw", [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    // The shadow maps are rendered before the G-Buffer, the lighting and the forward passes sample them.
+    let num_of_shadow_pipeline_binds = self.render_shadows(index, command_buffers)?;
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Draw G-Buffer", [1.0, 0.0, 0.0, 1.0]);
+    }
+
+    let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The depth image is none!", None))?;
+    let gbuffer_color_images = self.get_gbuffer_color_images()?;
+
+    // The occlusion culling reads the visibility written by the second phase of the last frame.
+    let occlusion_culling = match (self.use_occlusion_culling, self.depth_pyramid.as_ref(), self.occlusion_culling_visibility_buffer.as_ref()) {
+      (true, Some(depth_pyramid), Some(visibility_buffer)) if !self.use_deferred_subpasses => Some((depth_pyramid, visibility_buffer)),
+      _ => None,
+    };
+    if let Some((_, visibility_buffer)) = occlusion_culling {
+      command_buffers.set_buffer_barriers(
+        index,
+        &[hala_gfx::HalaBufferBarrierInfo {
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TASK_SHADER | hala_gfx::HalaPipelineStageFlags2::MESH_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::TASK_SHADER | hala_gfx::HalaPipelineStageFlags2::MESH_SHADER,
+          buffer: visibility_buffer.raw,
+          size: visibility_buffer.size,
+          ..Default::default()
+        }],
+      );
+    }
+
+    if self.use_deferred_subpasses {
+      let render_pass = self.deferred_render_pass.as_ref().ok_or(HalaRendererError::new("The deferred render pass is none!", None))?;
+      let (clear_depth, clear_stencil) = self.get_clear_depth_stencil();
+      let frame_buffers = self.deferred_framebuffers.as_ref().ok_or(HalaRendererError::new("The deferred frame buffers is none!", None))?;
+      command_buffers.begin_render_pass(
+        index,
+        render_pass,
+        frame_buffers,
+        (0, 0, self.info.width, self.info.height),
+        &[
+          hala_gfx::HalaClearValue { color: hala_gfx::HalaClearColorValue { float32: self.clear_color }, },
+          hala_gfx::HalaClearValue { color: hala_gfx::HalaClearColorValue { float32: self.gbuffer_clear_color }, },
+          hala_gfx::HalaClearValue { color: hala_gfx::HalaClearColorValue { float32: self.gbuffer_clear_color }, },
+          hala_gfx::HalaClearValue { depth_stencil: hala_gfx::HalaClearDepthStencilValue { depth: clear_depth, stencil: clear_stencil }, },
+          hala_gfx::HalaClearValue { depth_stencil: hala_gfx::HalaClearDepthStencilValue { depth: clear_depth, stencil: clear_stencil }, },
+        ],
+        self.get_scene_subpass_contents(),
+      );
+    } else {
+      // Setup deferred G-buffer write barriers.
+      let mut barriers = gbuffer_color_images.iter().map(|image| hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: image.raw,
+        ..Default::default()
+      }).collect::<Vec<_>>();
+      barriers.push(hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        aspect_mask: get_depth_aspect_mask(depth_image.format),
+        image: depth_image.raw,
+        ..Default::default()
+      });
+      command_buffers.set_image_barriers(index, barriers.as_slice());
+
+      // The zero ids of the visibility buffer and the object ID image mark the background.
+      let clear_colors = gbuffer_color_images.iter().map(|image| {
+        let is_id_image = self.use_visibility_buffer || self.object_id_image.as_ref().is_some_and(|object_id_image| object_id_image.raw == image.raw);
+        Some(if is_id_image { [0.0, 0.0, 0.0, 0.0] } else { self.gbuffer_clear_color })
+      }).collect::<Vec<_>>();
+      self.set_scene_rendering_contents(index, command_buffers);
+      command_buffers.begin_rendering_with(
+        index,
+        gbuffer_color_images.as_slice(),
+        Some(depth_image),
+        (0, 0, self.info.width, self.info.height),
+        clear_colors.as_slice(),
+        Some(self.get_clear_depth_stencil().0),
+        if self.has_gbuffer_stencil() { Some(self.get_clear_depth_stencil().1) } else { None },
+        hala_gfx::HalaAttachmentStoreOp::STORE,
+        hala_gfx::HalaAttachmentStoreOp::STORE,
+        hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+      );
+    }
+
+    let mut num_of_pipeline_binds = if let Some((depth_pyramid, _)) = occlusion_culling {
+      // The phase 1 draws the meshlets visible in the last frame, the depth pyramid is built from their depth,
+      // then the phase 2 draws the remaining meshlets which pass the depth pyramid test into the same G-buffer.
+      let mut num_of_pipeline_binds = self.draw_scene(index, command_buffers, HalaMaterialPass::DEFERRED, 1)?;
+      command_buffers.end_rendering(index);
+
+      num_of_pipeline_binds += depth_pyramid.record(index, command_buffers, depth_image, hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)?;
+
+      let barriers = gbuffer_color_images.iter().map(|image| hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_READ | hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: image.raw,
+        ..Default::default()
+      }).collect::<Vec<_>>();
+      command_buffers.set_image_barriers(index, barriers.as_slice());
+
+      // Load the G-buffer of the phase 1.
+      self.set_scene_rendering_contents(index, command_buffers);
+      command_buffers.begin_rendering_with(
+        index,
+        gbuffer_color_images.as_slice(),
+        Some(depth_image),
+        (0, 0, self.info.width, self.info.height),
+        gbuffer_color_images.iter().map(|_| None).collect::<Vec<_>>().as_slice(),
+        None,
+        None,
+        hala_gfx::HalaAttachmentStoreOp::STORE,
+        hala_gfx::HalaAttachmentStoreOp::STORE,
+        hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+      );
+      num_of_pipeline_binds + self.draw_scene(index, command_buffers, HalaMaterialPass::DEFERRED, 2)?
+    } else {
+      self.draw_scene(index, command_buffers, HalaMaterialPass::DEFERRED, 0)?
+    };
+    num_of_pipeline_binds += num_of_shadow_pipeline_binds;
+
+    if self.use_deferred_subpasses {
+      command_buffers.next_subpass(index, hala_gfx::HalaSubpassContents::INLINE);
+      self.restore_scene_viewport(index, command_buffers);
+    } else {
+      command_buffers.end_rendering(index);
+      self.restore_scene_viewport(index, command_buffers);
+
+      // Setup deferred G-buffer read barriers.
+      let mut barriers = gbuffer_color_images.iter().map(|image| hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::INPUT_ATTACHMENT_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: image.raw,
+        ..Default::default()
+      }).collect::<Vec<_>>();
+      barriers.push(hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::INPUT_ATTACHMENT_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        aspect_mask: get_depth_aspect_mask(depth_image.format),
+        image: depth_image.raw,
+        ..Default::default()
+      });
+      command_buffers.set_image_barriers(index, barriers.as_slice());
+    }
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    // The depth pyramid reduces the G-buffer depth for the occlusion culling of the next passes and the next frame.
+    // The occlusion culling has built it between its phases.
+    if let (Some(depth_pyramid), Some(depth_image), false, true) =
+      (self.depth_pyramid.as_ref(), self.depth_image.as_ref(), self.use_deferred_subpasses, occlusion_culling.is_none())
+    {
+      num_of_pipeline_binds += depth_pyramid.record(index, command_buffers, depth_image, hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)?;
+    }
+
+    // The SSR traces the G-buffer before the lighting.
+    let use_ssr = self.ssr.is_active();
+    if use_ssr {
+      let gbuffer = HalaSSRGBuffer {
+        depth_image,
+        albedo_image: self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?,
+        normal_image: self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?,
+      };
+      num_of_pipeline_binds += self.ssr.trace(index, command_buffers, &gbuffer, self.get_view_descriptor_sets(0)?, self.info.width, self.info.height)?;
+    }
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Lighting", [0.0, 1.0, 0.0, 1.0]);
+    }
+
+    // The SSR renders the lighting into the scene color image, the composite pass writes the target of the next active pass.
+    // Without the SSR, the lighting renders into the target of the first active post-process pass, the last pass writes the swapchain.
+    // The output image mode renders the lighting into the output images, the swapchain is only drawn by the UI.
+    let use_output_image = self.is_output_image_active();
+    if self.use_deferred_subpasses {
+      // No need to setup swapchain barrier.
+    } else if use_output_image {
+      self.begin_output_rendering(index, command_buffers, self.clear_color)?;
+    } else if use_ssr {
+      self.ssr.begin_lighting(index, command_buffers, &context, self.info.width, self.info.height, self.clear_color, self.get_clear_depth_stencil())?;
+    } else if !self.begin_post_process_target(index, command_buffers, HalaPostProcessStage::Scene, self.clear_color)? {
+      // Setup swapchain barrier and begin the rendering.
+      begin_swapchain_rendering(index, command_buffers, &context, self.info.width, self.info.height, Some(self.clear_color), None, Some(self.get_clear_depth_stencil().1));
+    }
+
+    // Bind lighting graphics pipeline.
+    let pipeline = self.lighting_graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The lighting pass graphics pipeline is none!", None))?;
+    command_buffers.bind_graphics_pipeline(index, pipeline);
+
+    let descriptor_set = self.lighting_descriptor_set.as_ref().ok_or(HalaRendererError::new("The lighting pass descriptor set is none!", None))?;
+    for (view_index, view) in self.get_views().iter().enumerate() {
+      // Setup viewport.
+      self.set_view_viewport(index, command_buffers, view);
+
+      // Bind descriptor sets.
+      let (static_descriptor_set, dynamic_descriptor_set) = self.get_view_descriptor_sets(view_index)?;
+      let mut descriptor_sets = vec![static_descriptor_set, dynamic_descriptor_set, descriptor_set];
+      if self.use_visibility_buffer {
+        descriptor_sets.push(self.textures_descriptor_set.as_ref().ok_or(HalaRendererError::new("The textures descriptor set is none!", None))?);
+      }
+      command_buffers.bind_graphics_descriptor_sets(
+        index,
+        pipeline,
+        0,
+        descriptor_sets.as_slice(),
+        &[],
+      );
+
+      // Draw.
+      command_buffers.draw(index, 4, 1, 0, 0);
+    }
+
+    // Restore the full window viewport for the following passes.
+    if !self.views.is_empty() || self.render_area.is_some() {
+      self.set_view_viewport(index, command_buffers, &self.get_window_view());
+    }
+
+    // The grid is drawn over the lighting result, it tests the G-Buffer depth in the fragment shader.
+    // With the deferred subpasses, it is still in the lighting subpass where the G-Buffer depth is the input attachment.
+    num_of_pipeline_binds += self.draw_grid(index, command_buffers)?;
+    if !self.use_deferred_subpasses {
+      num_of_pipeline_binds += self.draw_lines(index, command_buffers)?;
+      num_of_pipeline_binds += self.draw_axis_gizmo(index, command_buffers)?;
+    }
+
+    if self.use_deferred_subpasses {
+      command_buffers.end_render_pass(index);
+
+      command_buffers.begin_rendering_with_swapchain(
+        index,
+        &context.swapchain,
+        (0, 0, self.info.width, self.info.height),
+        None,
+        None,
+        None,
+      );
+
+      // The axis gizmo is drawn on the lit swapchain before the UI.
+      num_of_pipeline_binds += self.draw_axis_gizmo(index, command_buffers)?;
+
+      command_buffers.set_viewport(
+        index,
+        0,
+        &[
+          (
+            0.,
+            self.info.height as f32,
+            self.info.width as f32,
+            -(self.info.height as f32), // For vulkan y is down.
+            0.,
+            1.
+          ),
+        ],
+      );
+      command_buffers.set_scissor(
+        index,
+        0,
+        &[
+          (0, 0, self.info.width, self.info.height),
+        ],
+      );
+
+      // Draw UI.
+      if cfg!(debug_assertions) {
+        command_buffers.end_debug_label(index);
+        command_buffers.begin_debug_label(index, "Draw UI", [0.0, 0.0, 1.0, 1.0]);
+      }
+      ui_fn(index, command_buffers)?;
+      if cfg!(debug_assertions) {
+        command_buffers.end_debug_label(index);
+      }
+
+      command_buffers.end_rendering(index);
+
+      // Setup swapchain barrier.
+      command_buffers.set_image_barriers(
+        index,
+        &[
+          hala_gfx::HalaImageBarrierInfo {
+            old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: hala_gfx::HalaImageLayout::PRESENT_SRC,
+            src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+            dst_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+            src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::BOTTOM_OF_PIPE,
+            aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+            image: context.swapchain.images[index],
+            ..Default::default()
+          },
+        ],
+      );
+    } else {
+      num_of_pipeline_binds += self.record_post_process(index, command_buffers, false)?;
+      if use_output_image {
+        command_buffers.end_rendering(index);
+        self.finish_output_rendering(index, command_buffers)?;
+      }
+
+      // Draw UI.
+      if cfg!(debug_assertions) {
+        command_buffers.end_debug_label(index);
+        command_buffers.begin_debug_label(index, "Draw UI", [0.0, 0.0, 1.0, 1.0]);
+      }
+      ui_fn(index, command_buffers)?;
+      if cfg!(debug_assertions) {
+        command_buffers.end_debug_label(index);
+      }
+
+      command_buffers.end_rendering(index);
+
+      // Setup swapchain barrier.
+      command_buffers.set_image_barriers(
+        index,
+        &[hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+          new_layout: hala_gfx::HalaImageLayout::PRESENT_SRC,
+          src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::BOTTOM_OF_PIPE,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: context.swapchain.images[index],
+          ..Default::default()
+        }],
+      );
+    }
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    // The shared buffers are released to the async compute work submitted after this frame.
+    self.resources.record_async_compute_release(index, command_buffers);
+
+    // Write end timestamp and end command buffer.
+    command_buffers.write_timestamp(
+      index,
+      hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+      &context.timestamp_query_pool,
+      (index * 2 + 1) as u32);
+    command_buffers.end(index)?;
+
+    Ok(num_of_pipeline_binds)
+  }
+
+  /// Create G-buffer images.
+  /// param use_transient: Use transient images or not.
+  /// param albedo_format: The format of the albedo image.
+  /// param normal_format: The format of the normal image, none for R16G16_SNORM.
+  /// The two channel formats(see is_gbuffer_normal_packed) store the octahedral encoded normals in [-1, 1],
+  /// the G-buffer fragment shaders encode and the lighting shaders decode them with shaders/rz/octahedral_normal.hlsl.
+  /// The four channel formats(e.g. R16G16B16A16_SFLOAT) store the full precision normals in the xyz channels.
+  /// param motion_vector_format: The format of the motion vector image(e.g. R16G16_SFLOAT), none if no motion vectors are output.
+  /// The G-buffer fragment shaders write the NDC difference of the positions transformed by mvp_mtx and prev_mvp_mtx to the third color target.
+  /// The object ID image is created if set_gbuffer_object_id is enabled(see get_object_id_image).
+  /// param depth_format: The format of the depth image(e.g. D32_SFLOAT), the formats with the stencil aspect(e.g. D24_UNORM_S8_UINT)
+  /// let the G-Buffer pass write the stencil references.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// return: The result.
+  pub fn create_gbuffer_images(
+    &mut self,
+    use_transient: bool,
+    albedo_format: hala_gfx::HalaFormat,
+    normal_format: Option<hala_gfx::HalaFormat>,
+    motion_vector_format: Option<hala_gfx::HalaFormat>,
+    depth_format: hala_gfx::HalaFormat,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+  ) -> Result<(), HalaRendererError> {
+    let normal_format = normal_format.unwrap_or(GBUFFER_PACKED_NORMAL_FORMAT);
+    self.check_not_committed("create_gbuffer_images")?;
+    self.check_gbuffer_depth_format(depth_format, !use_transient)?;
+
+    let vertex_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      vertex_file_path,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "lighting_pass.vert",
+    )?;
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "lighting_pass.frag",
+    )?;
+
+    let prev_depth_format = self.gbuffer_depth_format;
+    self.gbuffer_depth_format = depth_format;
+    let use_object_id = self.use_gbuffer_object_id;
+    if let Err(err) = self.create_gbuffer_resources(use_transient, albedo_format, normal_format, motion_vector_format, use_object_id) {
+      self.gbuffer_depth_format = prev_depth_format;
+      return Err(err);
+    }
+    self.lighting_vertex_shader = Some(vertex_shader);
+    self.lighting_fragment_shader = Some(fragment_shader);
+
+    Ok(())
+  }
+
+  /// Create the G-buffer images and the lighting descriptor set with the window size.
+  /// The old G-buffer resources are retired, they may still be used by the in-flight frames.
+  /// param use_transient: Use transient images or not.
+  /// param albedo_format: The format of the albedo image.
+  /// param normal_format: The format of the normal image.
+  /// param motion_vector_format: The format of the motion vector image, none if no motion vectors are output.
+  /// param use_object_id: Create the object ID image or not.
+  /// return: The result.
+  fn create_gbuffer_resources(
+    &mut self,
+    use_transient: bool,
+    albedo_format: hala_gfx::HalaFormat,
+    normal_format: hala_gfx::HalaFormat,
+    motion_vector_format: Option<hala_gfx::HalaFormat>,
+    use_object_id: bool,
+  ) -> Result<(), HalaRendererError> {
+    // The images are sampled by the TAA resolve and the SSR trace if not transient.
+    let rt_usage_flags = if use_transient {
+      hala_gfx::HalaImageUsageFlags::INPUT_ATTACHMENT | hala_gfx::HalaImageUsageFlags::TRANSIENT_ATTACHMENT
+    } else {
+      hala_gfx::HalaImageUsageFlags::INPUT_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED
+    };
+
+    // Create depth image, it is copied by the depth readback picking if not transient.
+    let depth_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | rt_usage_flags
+        | if use_transient { hala_gfx::HalaImageUsageFlags::empty() } else { hala_gfx::HalaImageUsageFlags::TRANSFER_SRC },
+      self.get_gbuffer_depth_format(),
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "depth.image",
+    ).map_err(|err| HalaRendererError::new(
+      &format!(
+        "Failed to create the G-Buffer depth image, the format {:?} may not support the depth stencil and input attachment usage: {}",
+        self.get_gbuffer_depth_format(),
+        err,
+      ),
+      None,
+    ))?;
+
+    // Create albedo image.
+    let albedo_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | rt_usage_flags,
+      albedo_format,
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "albedo.image",
+    )?;
+
+    // Create normal image.
+    let normal_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | rt_usage_flags,
+      normal_format,
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "normal.image",
+    )?;
+
+    // Create motion vector image, it is never transient because it is consumed after the lighting pass.
+    let motion_vector_image = match motion_vector_format {
+      Some(format) => Some(hala_gfx::HalaImage::new_2d(
+        Rc::clone(&self.resources.context.borrow().logical_device),
+        hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED,
+        format,
+        self.info.width,
+        self.info.height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "motion_vector.image",
+      )?),
+      None => None,
+    };
+
+    // Create object ID image, it is never transient because it is consumed after the lighting pass and copied by the picking.
+    let object_id_image = if use_object_id {
+      Some(hala_gfx::HalaImage::new_2d(
+        Rc::clone(&self.resources.context.borrow().logical_device),
+        hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+        hala_gfx::HalaFormat::R32_UINT,
+        self.info.width,
+        self.info.height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        "object_id.image",
+      )?)
+    } else {
+      None
+    };
+
+    // Create lighting descriptor set.
+    self.resources.reserve_descriptors(&[(hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT, 3)], 1)?;
+    let lighting_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      Rc::clone(&self.resources.descriptor_pool),
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&self.resources.context.borrow().logical_device),
+        &[
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Depth image.
+            binding_index: 0,
+            descriptor_type: hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Albedo image.
+            binding_index: 1,
+            descriptor_type: hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Normal image.
+            binding_index: 2,
+            descriptor_type: hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+        ],
+        "lighting_pass.descriptor_set_layout",
+      )?,
+      0,
+      "lighting_pass.descriptor_set",
+    )?;
+    lighting_descriptor_set.update_input_attachments(0, 0, &[&depth_image]);
+    lighting_descriptor_set.update_input_attachments(0, 1, &[&albedo_image]);
+    lighting_descriptor_set.update_input_attachments(0, 2, &[&normal_image]);
+
+    self.retire_gbuffer_resources();
+
+    self.use_deferred = true;
+    self.use_visibility_buffer = false;
+    self.depth_image = Some(depth_image);
+    self.use_transient_gbuffer = use_transient;
+    self.albedo_image = Some(albedo_image);
+    self.normal_image = Some(normal_image);
+    self.motion_vector_image = motion_vector_image;
+    self.object_id_image = object_id_image;
+    self.lighting_descriptor_set = Some(lighting_descriptor_set);
+
+    self.build_draw_groups()
+  }
+
+  /// Create the visibility buffer images, the deferred rendering writes the ids instead of the albedo and the normal.
+  /// The scene fragment shaders write (draw index + 1, triangle index) into the R32G32_UINT visibility image, 0 marks the background.
+  /// The material pass binds the lighting descriptor set(0: depth, 1: visibility) at the set 2 and the textures at the set 3,
+  /// and fetches the attributes from the vertex and index buffers in the dynamic descriptor set.
+  /// The deferred subpasses are not supported, so create_deferred_render_pass is not needed.
+  /// param use_transient: Use transient images or not.
+  /// param vertex_file_path: The material pass vertex shader file path.
+  /// param fragment_file_path: The material pass fragment shader file path.
+  /// return: The result.
+  pub fn create_visibility_buffer_images(
+    &mut self,
+    use_transient: bool,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+  ) -> Result<(), HalaRendererError> {
+    self.check_not_committed("create_visibility_buffer_images")?;
+    self.check_gbuffer_depth_format(self.gbuffer_depth_format, !use_transient)?;
+    let vertex_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      vertex_file_path,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "material_pass.vert",
+    )?;
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "material_pass.frag",
+    )?;
+
+    self.create_visibility_buffer_resources(use_transient)?;
+    self.lighting_vertex_shader = Some(vertex_shader);
+    self.lighting_fragment_shader = Some(fragment_shader);
+
+    Ok(())
+  }
+
+  /// Create the visibility buffer images and the material descriptor set with the window size.
+  /// The old G-buffer resources are retired, they may still be used by the in-flight frames.
+  /// param use_transient: Use transient images or not.
+  /// return: The result.
+  fn create_visibility_buffer_resources(&mut self, use_transient: bool) -> Result<(), HalaRendererError> {
+    // The images are sampled by the TAA resolve and the SSR trace if not transient.
+    let rt_usage_flags = if use_transient {
+      hala_gfx::HalaImageUsageFlags::INPUT_ATTACHMENT | hala_gfx::HalaImageUsageFlags::TRANSIENT_ATTACHMENT
+    } else {
+      hala_gfx::HalaImageUsageFlags::INPUT_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED
+    };
+
+    // Create depth image, it is copied by the depth readback picking if not transient.
+    let depth_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | rt_usage_flags
+        | if use_transient { hala_gfx::HalaImageUsageFlags::empty() } else { hala_gfx::HalaImageUsageFlags::TRANSFER_SRC },
+      self.get_gbuffer_depth_format(),
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "depth.image",
+    )?;
+
+    // Create visibility image.
+    let visibility_buffer_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | rt_usage_flags,
+      hala_gfx::HalaFormat::R32G32_UINT,
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "visibility_buffer.image",
+    )?;
+
+    // Create material descriptor set.
+    self.resources.reserve_descriptors(&[(hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT, 2)], 1)?;
+    let lighting_descriptor_set = hala_gfx::HalaDescriptorSet::new_static(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      Rc::clone(&self.resources.descriptor_pool),
+      hala_gfx::HalaDescriptorSetLayout::new(
+        Rc::clone(&self.resources.context.borrow().logical_device),
+        &[
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Depth image.
+            binding_index: 0,
+            descriptor_type: hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+          hala_gfx::HalaDescriptorSetLayoutBinding { // Visibility image.
+            binding_index: 1,
+            descriptor_type: hala_gfx::HalaDescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+          },
+        ],
+        "material_pass.descriptor_set_layout",
+      )?,
+      0,
+      "material_pass.descriptor_set",
+    )?;
+    lighting_descriptor_set.update_input_attachments(0, 0, &[&depth_image]);
+    lighting_descriptor_set.update_input_attachments(0, 1, &[&visibility_buffer_image]);
+
+    self.retire_gbuffer_resources();
+
+    self.use_deferred = true;
+    self.use_visibility_buffer = true;
+    self.depth_image = Some(depth_image);
+    self.use_transient_gbuffer = use_transient;
+    self.visibility_buffer_image = Some(visibility_buffer_image);
+    self.lighting_descriptor_set = Some(lighting_descriptor_set);
+
+    self.build_draw_groups()
+  }
+
+  /// Get the color images of the G-buffer pass.
+  /// return: The visibility image or the albedo, the normal, the optional motion vector and the optional object ID images.
+  fn get_gbuffer_color_images(&self) -> Result<Vec<&hala_gfx::HalaImage>, HalaRendererError> {
+    if self.use_visibility_buffer {
+      Ok(vec![self.visibility_buffer_image.as_ref().ok_or(HalaRendererError::new("The visibility buffer image is none!", None))?])
+    } else {
+      let mut images = vec![
+        self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?,
+        self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?,
+      ];
+      if let Some(motion_vector_image) = self.motion_vector_image.as_ref() {
+        images.push(motion_vector_image);
+      }
+      if let Some(object_id_image) = self.object_id_image.as_ref() {
+        images.push(object_id_image);
+      }
+      Ok(images)
+    }
+  }
+
+  /// Get the motion vector image of the G-buffer.
+  /// It is in the SHADER_READ_ONLY_OPTIMAL layout after the G-buffer pass.
+  /// return: The motion vector image, none if the G-buffer has no motion vectors.
+  pub fn get_motion_vector_image(&self) -> Option<&hala_gfx::HalaImage> {
+    self.motion_vector_image.as_ref()
+  }
+
+  /// Get the object ID image of the G-buffer, it can feed the outline masks and the compositing.
+  /// The G-buffer fragment shaders write the draw index of the push constants + 1 to the last color target,
+  /// the draw index follows the scene order of the primitives and the clear value 0 marks the background.
+  /// It is in the SHADER_READ_ONLY_OPTIMAL layout after the G-buffer pass.
+  /// return: The R32_UINT object ID image, none if the G-buffer has no object IDs.
+  pub fn get_object_id_image(&self) -> Option<&hala_gfx::HalaImage> {
+    self.object_id_image.as_ref()
+  }
+
+  /// Replace the shaders of the lighting pass, or the material pass of the visibility buffer, without recreating the G-buffer.
+  /// The lighting pipeline is created by commit, so it takes effect on the next commit.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// return: The result.
+  pub fn set_lighting_shaders_with_file(&mut self, vertex_file_path: &str, fragment_file_path: &str) -> Result<(), HalaRendererError> {
+    if !self.use_deferred {
+      return Err(HalaRendererError::new("The G-buffer images are none! Call create_gbuffer_images or create_visibility_buffer_images first.", None));
+    }
+
+    let debug_name = if self.use_visibility_buffer { "material_pass" } else { "lighting_pass" };
+    let vertex_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      vertex_file_path,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.vert", debug_name),
+    )?;
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&self.resources.context.borrow().logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.frag", debug_name),
+    )?;
+
+    self.lighting_vertex_shader = Some(vertex_shader);
+    self.lighting_fragment_shader = Some(fragment_shader);
+
+    Ok(())
+  }
+
+  /// Destroy G-buffer images.
+  /// return: The result.
+  pub fn destroy_gbuffer_images(&mut self) -> Result<(), HalaRendererError> {
+    self.check_not_committed("destroy_gbuffer_images")?;
+    self.retire_gbuffer_resources();
+
+    self.use_deferred = false;
+    self.use_visibility_buffer = false;
+    self.lighting_vertex_shader = None;
+    self.lighting_fragment_shader = None;
+
+    self.build_draw_groups()
+  }
+
+  /// Retire the G-buffer images and the lighting descriptor set.
+  fn retire_gbuffer_resources(&mut self) {
+    let images = [
+      self.depth_image.take(),
+      self.albedo_image.take(),
+      self.normal_image.take(),
+      self.motion_vector_image.take(),
+      self.object_id_image.take(),
+      self.visibility_buffer_image.take(),
+    ];
+    for image in images.into_iter().flatten() {
+      self.resources.retire(Box::new(image));
+    }
+    if let Some(lighting_descriptor_set) = self.lighting_descriptor_set.take() {
+      self.resources.retire(Box::new(lighting_descriptor_set));
+    }
+  }
+
+  /// Retire the multisample images.
+  fn retire_multisample_images(&mut self) {
+    let images = [self.color_multisample_image.take(), self.depth_stencil_multisample_image.take()];
+    for image in images.into_iter().flatten() {
+      self.resources.retire(Box::new(image));
+    }
+  }
+
+  /// Check the renderer is not committed before changing the setup state.
+  /// param method: The name of the setup method.
+  /// return: The result.
+  fn check_not_committed(&self, method: &str) -> Result<(), HalaRendererError> {
+    if self.is_committed {
+      return Err(HalaRendererError::invalid_state(&format!(
+        "The {} must be called before commit, the committed pipelines are built with the old configuration! Call it in the configure function of recommit instead.",
+        method)));
+    }
+
+    Ok(())
+  }
+
+  /// Change the configuration of a committed renderer and commit it again.
+  /// It waits the device idle, runs the configure function with the setup methods enabled,
+  /// then rebuilds the pipelines and the descriptor sets, the uploaded scene is kept.
+  /// param configure: The configure function, e.g. calls enable_multisample or create_gbuffer_images.
+  /// return: The result.
+  pub fn recommit<F>(&mut self, configure: F) -> Result<(), HalaRendererError>
+    where F: FnOnce(&mut Self) -> Result<(), HalaRendererError>
+  {
+    self.wait_idle()?;
+    self.is_committed = false;
+    configure(self)?;
+    self.commit()
+  }
+
+  /// Enable multisample.
+  /// If the device does not support the sample count for both the color and the depth stencil attachments,
+  /// the nearest lower supported count is used with a warning.
+  /// The deferred path does not resolve the G-Buffer, use the FXAA or the TAA instead.
+  /// param sample_count: The sample count.
+  /// return: The result.
+  pub fn enable_multisample(&mut self, sample_count: HalaSampleCountFlags) -> Result<(), HalaRendererError> {
+    self.check_not_committed("enable_multisample")?;
+    if self.use_deferred {
+      return Err(HalaRendererError::new("Deferred rendering does not support multisample, use the FXAA or the TAA instead!", None));
+    }
+
+    let supported_sample_counts = self.get_supported_sample_counts();
+    let supported_sample_count = SAMPLE_COUNTS.iter()
+      .copied()
+      .skip_while(|&count| count != sample_count)
+      .find(|&count| supported_sample_counts.contains(count))
+      .ok_or(HalaRendererError::new(&format!("The sample count {:?} is invalid!", sample_count), None))?;
+    if supported_sample_count != sample_count {
+      log::warn!("The sample count {:?} is not supported by the device, use {:?} instead.", sample_count, supported_sample_count);
+    }
+    if supported_sample_count == HalaSampleCountFlags::TYPE_1 {
+      return self.disable_multisample();
+    }
+
+    self.create_multisample_images(supported_sample_count)
+  }
+
+  /// Get the sample counts supported by the device for both the color and the depth stencil attachments.
+  /// return: The supported sample counts.
+  pub fn get_supported_sample_counts(&self) -> HalaSampleCountFlags {
+    let context = self.resources.context.borrow();
+    let limits = &context.physical_device.properties.limits;
+    HalaSampleCountFlags::from_raw((limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts).as_raw())
+  }
+
+  /// Get the maximum sample count supported by the device for both the color and the depth stencil attachments.
+  /// return: The maximum sample count.
+  pub fn get_max_sample_count(&self) -> HalaSampleCountFlags {
+    let supported_sample_counts = self.get_supported_sample_counts();
+    SAMPLE_COUNTS.iter()
+      .copied()
+      .find(|&count| supported_sample_counts.contains(count))
+      .unwrap_or(HalaSampleCountFlags::TYPE_1)
+  }
+
+  /// Create the multisample images with the window size and set the sample count of the context.
+  /// param sample_count: The sample count.
+  /// return: The result.
+  fn create_multisample_images(&mut self, sample_count: HalaSampleCountFlags) -> Result<(), HalaRendererError> {
+    // Retire the old multisample images, they may still be used by the in-flight frames.
+    self.retire_multisample_images();
+
+    let mut context = self.resources.context.borrow_mut();
+
+    self.color_multisample_image = Some(hala_gfx::HalaImage::with_2d_multisample(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::TRANSIENT_ATTACHMENT,
+      context.swapchain.format,
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      sample_count,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "color_multisample.image",
+    )?);
+
+    self.depth_stencil_multisample_image = Some(hala_gfx::HalaImage::with_2d_multisample(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | hala_gfx::HalaImageUsageFlags::TRANSIENT_ATTACHMENT,
+      context.swapchain.depth_stencil_format,
+      self.info.width,
+      self.info.height,
+      1,
+      1,
+      sample_count,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "depth_stencil_multisample.image",
+    )?);
+
+    context.multisample_count = sample_count;
+
+    Ok(())
+  }
+
+  /// Disable multisample.
+  /// return: The result.
+  pub fn disable_multisample(&mut self) -> Result<(), HalaRendererError> {
+    self.check_not_committed("disable_multisample")?;
+    self.retire_multisample_images();
+
+    let mut context = self.resources.context.borrow_mut();
+    context.multisample_count = HalaSampleCountFlags::TYPE_1;
+
+    Ok(())
+  }
+
+  /// Create deferred render pass with subpasses.
+  /// The attachments are the swapchain color, the albedo, the normal, the swapchain depth and the G-Buffer depth,
+  /// see renderer::create_deferred_render_pass.
+  /// return: The result.
+  pub fn create_deferred_render_pass(&mut self) -> Result<(), HalaRendererError> {
+    self.check_not_committed("create_deferred_render_pass")?;
+    let context = self.resources.context.borrow();
+    let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The depth image is none!", None))?;
+    let albedo_image = self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?;
+    let normal_image = self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?;
+
+    let deferred_render_pass = create_deferred_render_pass(&context, albedo_image.format, normal_image.format, depth_image.format)?;
+
+    self.use_deferred_subpasses = true;
+    self.deferred_render_pass = Some(deferred_render_pass);
+
+    Ok(())
+  }
+
+  /// Destroy deferred render pass.
+  pub fn destroy_deferred_render_pass(&mut self) {
+    self.use_deferred_subpasses = false;
+    self.deferred_render_pass = None;
+  }
+
+  /// Create deferred framebuffers.
+  /// They reference the swapchain image views, so they are recreated with the swapchain.
+  pub fn create_deferred_framebuffers(&mut self) -> Result<(), HalaRendererError> {
+    self.check_not_committed("create_deferred_framebuffers")?;
+    self.build_deferred_framebuffers()
+  }
+
+  /// Build the deferred framebuffers with the current G-buffer images and swapchain.
+  /// return: The result.
+  fn build_deferred_framebuffers(&mut self) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The depth image is none!", None))?;
+    let albedo_image = self.albedo_image.as_ref().ok_or(HalaRendererError::new("The albedo image is none!", None))?;
+    let normal_image = self.normal_image.as_ref().ok_or(HalaRendererError::new("The normal image is none!", None))?;
+
+    let deferred_framebuffers = create_deferred_framebuffers(
+      &context,
+      self.deferred_render_pass.as_ref().ok_or(HalaRendererError::new("The deferred render pass is none!", None))?,
+      albedo_image,
+      normal_image,
+      depth_image,
+    )?;
+
+    self.deferred_framebuffers = Some(deferred_framebuffers);
+
+    Ok(())
+  }
+
+  /// Destroy deferred framebuffers.
+  pub fn destroy_deferred_framebuffers(&mut self) {
+    self.deferred_framebuffers = None;
+  }
+
+  /// Push traditional shaders to the renderer.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn push_traditional_shaders_with_file(
+    &mut self,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    assert!(!self.use_mesh_shader, "The renderer is not support mesh shader!");
+    self.check_not_committed("push_traditional_shaders_with_file")?;
+
+    let context = self.resources.context.borrow();
+
+    let vertex_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      vertex_file_path,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.vert", debug_name),
+    )?;
+
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.frag", debug_name),
+    )?;
+
+    self.traditional_shaders.push((vertex_shader, fragment_shader));
+
+    Ok(())
+  }
+
+  /// Set the shaders of the software meshlet path, the scene is drawn by the compute shader instead of the hardware pipelines.
+  /// The compute shader binds the set 0 to 2 of the renderer and the set 3 with the visibility depth and id images(R32_UINT),
+  /// and runs one group per meshlet with the push constants: pass, mesh_index, material_index, draw_index, meshlet_count and first_meshlet.
+  /// The pass 0 clears the images per pixel(8 x 8 groups), the pass 1 writes the depth by InterlockedMax(reverse Z) or InterlockedMin(standard Z),
+  /// and the pass 2 writes the draw index and the triangle index of the triangles whose depth equals the written depth.
+  /// The material shaders draw a full screen triangle strip of 4 vertices with the same descriptor sets.
+  /// It must be called before set_scene, so the meshlets are built when uploading the scene.
+  /// param raster_file_path: The raster compute shader file path.
+  /// param material_vertex_file_path: The material pass vertex shader file path.
+  /// param material_fragment_file_path: The material pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_software_meshlet_shaders_with_file(
+    &mut self,
+    raster_file_path: &str,
+    material_vertex_file_path: &str,
+    material_fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    if self.scene_in_gpu.is_some() {
+      return Err(HalaRendererError::new("The software meshlet shaders must be set before set_scene!", None));
+    }
+
+    let context = self.resources.context.borrow();
+    let vertex_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      material_vertex_file_path,
+      hala_gfx::HalaShaderStageFlags::VERTEX,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}_material.vert", debug_name),
+    )?;
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      material_fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}_material.frag", debug_name),
+    )?;
+    drop(context);
+
+    self.software_raster_file_path = Some(raster_file_path.to_string());
+    self.software_material_shaders = vec![vertex_shader, fragment_shader];
+
+    Ok(())
+  }
+
+  /// Set the shaders of the TAA(temporal anti-aliasing).
+  /// The resolve compute shader binds the set 0 with the current color(binding 0), the current depth(binding 1),
+  /// the previous history(binding 2, RGBA16F) and the current history(binding 3, RGBA16F) images,
+  /// and runs 8 x 8 groups with the push constants: reprojection matrix, jitter, reset and padding.
+  /// The reprojection matrix transforms the current NDC position(x, y, depth) to the previous clip space,
+  /// the NDC of a pixel is (uv.x * 2 - 1, 1 - uv.y * 2). The history must be clamped to the neighborhood of the current color,
+  /// and be ignored if reset is 1.
+  /// The present shaders draw a full screen triangle strip of 4 vertices with the same set and read the current history.
+  /// It must be called before commit.
+  /// param resolve_file_path: The resolve compute shader file path.
+  /// param present_vertex_file_path: The present pass vertex shader file path.
+  /// param present_fragment_file_path: The present pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_taa_shaders_with_file(
+    &mut self,
+    resolve_file_path: &str,
+    present_vertex_file_path: &str,
+    present_fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.taa.set_shaders_with_file(&context, resolve_file_path, present_vertex_file_path, present_fragment_file_path, debug_name)
+  }
+
+  /// Enable or disable the TAA, the history is reset.
+  /// The jitter is applied to the projection matrix of the first view, only one view without the multisample is supported.
+  /// param enable: Whether to enable the TAA.
+  pub fn enable_taa(&mut self, enable: bool) {
+    self.taa.enable(enable);
+  }
+
+  /// Whether the TAA is enabled.
+  /// return: True if the TAA is enabled.
+  pub fn is_taa_enabled(&self) -> bool {
+    self.taa.is_enabled
+  }
+
+  /// Set the shaders of the SSR(screen space reflections), the SSR needs the albedo and normal G-buffer.
+  /// The trace compute shader binds the set 0 and 1 of the renderer and the SSR set at the set 2 with the depth(binding 0),
+  /// the albedo(binding 1), the normal(binding 2), the previous scene color(binding 3), the SSR(binding 4, RGBA16F)
+  /// and the current scene color(binding 5) images, and runs 8 x 8 groups with the push constants: max steps, thickness, reset and padding.
+  /// It ray-marches the depth in the screen space for the smooth surfaces, and writes the previous scene color at the hit
+  /// with the weight in alpha. A miss writes 0 weight, so the lighting falls back to its environment term.
+  /// The previous scene color is invalid if reset is 1.
+  /// The composite shaders draw a full screen triangle strip of 4 vertices with the SSR set at the set 0,
+  /// and add the weighted reflections to the current scene color.
+  /// It must be called before commit.
+  /// param trace_file_path: The trace compute shader file path.
+  /// param composite_vertex_file_path: The composite pass vertex shader file path.
+  /// param composite_fragment_file_path: The composite pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_ssr_shaders_with_file(
+    &mut self,
+    trace_file_path: &str,
+    composite_vertex_file_path: &str,
+    composite_fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.ssr.set_shaders_with_file(&context, trace_file_path, composite_vertex_file_path, composite_fragment_file_path, debug_name)
+  }
+
+  /// Set the depth pyramid shader with the file, the depth pyramid is built after the G-Buffer pass.
+  /// See HalaDepthPyramid::new for the bindings and the push constants of the shader.
+  /// It must be called before commit.
+  /// param file_path: The reduction compute shader file path.
+  /// return: The result.
+  pub fn set_depth_pyramid_shader_with_file(&mut self, file_path: &str) -> Result<(), HalaRendererError> {
+    if self.depth_pyramid.is_some() {
+      return Err(HalaRendererError::new("The depth pyramid shader must be set before commit!", None));
+    }
+
+    self.depth_pyramid_file_path = Some(file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Get the depth pyramid of the G-Buffer depth.
+  /// The culling must check HalaDepthPyramid::is_ready, the pyramid has no data before the first frame.
+  /// return: The depth pyramid, none if it is not set or not committed.
+  pub fn get_depth_pyramid(&self) -> Option<&HalaDepthPyramid> {
+    self.depth_pyramid.as_ref()
+  }
+
+  /// Enable the SSR, the previous scene color is reset.
+  /// param max_steps: The max ray marching steps.
+  /// param thickness: The thickness of the depth in the view space, a step behind the depth within it is a hit.
+  pub fn enable_ssr(&mut self, max_steps: u32, thickness: f32) {
+    self.ssr.enable(max_steps, thickness);
+  }
+
+  /// Disable the SSR.
+  pub fn disable_ssr(&mut self) {
+    self.ssr.is_enabled = false;
+  }
+
+  /// Set the shaders of the bloom.
+  /// The compute shader binds the bloom set at the set 0 with the color(binding 0), the source mip(binding 1, RGBA16F)
+  /// and the destination mip(binding 2, RGBA16F) images, and runs 8 x 8 groups over the destination mip
+  /// with the push constants: mode, threshold, intensity and padding.
+  /// The mode 0 keeps the color above the threshold and downsamples it into the mip 0, the mode 1 downsamples and blurs
+  /// the source mip into the destination mip, and the mode 2 upsamples and blurs the source mip and adds it to the destination mip.
+  /// The composite shaders draw a full screen triangle strip of 4 vertices with the same set and push constants,
+  /// and add the mip 0(binding 2) scaled by the intensity to the color before the tone mapping of the lighting.
+  /// It must be called before commit.
+  /// param file_path: The compute shader file path.
+  /// param composite_vertex_file_path: The composite pass vertex shader file path.
+  /// param composite_fragment_file_path: The composite pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_bloom_shaders_with_file(
+    &mut self,
+    file_path: &str,
+    composite_vertex_file_path: &str,
+    composite_fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.bloom.set_shaders_with_file(&context, file_path, composite_vertex_file_path, composite_fragment_file_path, debug_name)
+  }
+
+  /// Enable the bloom, it is disabled by default.
+  /// param threshold: The luminance threshold of the bright pixels.
+  /// param intensity: The intensity of the bloom added to the color.
+  pub fn enable_bloom(&mut self, threshold: f32, intensity: f32) {
+    self.bloom.is_enabled = true;
+    self.bloom.threshold = threshold;
+    self.bloom.intensity = intensity;
+  }
+
+  /// Disable the bloom.
+  pub fn disable_bloom(&mut self) {
+    self.bloom.is_enabled = false;
+  }
+
+  /// Set the shaders of the FXAA.
+  /// The shaders draw a full screen triangle strip of 4 vertices with the FXAA set at the set 0, which has the final color(binding 0).
+  /// The fragment shader detects the edges by the luma of the neighborhood and blends the color along them.
+  /// It must be called before commit.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_fxaa_shaders_with_file(
+    &mut self,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.fxaa.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Enable or disable the FXAA, it works without the multisample in both the forward and the deferred rendering.
+  /// param enable: Whether to enable the FXAA.
+  pub fn enable_fxaa(&mut self, enable: bool) {
+    self.fxaa.is_enabled = enable;
+  }
+
+  /// Whether the FXAA is enabled.
+  /// return: True if the FXAA is enabled.
+  pub fn is_fxaa_enabled(&self) -> bool {
+    self.fxaa.is_enabled
+  }
+
+  /// Set the color grading shaders.
+  /// The color grading pass is the last pass before the UI, it samples the LUT with the tone mapped display-referred color.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_color_grading_shaders_with_file(
+    &mut self,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.color_grading.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Set the 3D color grading LUT from a .cube file, it can be called before or after commit.
+  /// The color grading is active when both the LUT and the color grading shaders are set.
+  /// param path: The path of the .cube file.
+  /// return: The result.
+  pub fn set_color_grading_lut<P: AsRef<Path>>(&mut self, path: P) -> Result<(), HalaRendererError> {
+    let lut = {
+      let context = self.resources.context.borrow();
+      HalaColorGradingLUT::new_with_file(
+        path,
+        &context,
+        hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        &self.resources.transfer_staging_buffer,
+        &self.resources.transfer_command_buffers,
+      )?
+    };
+    self.replace_color_grading_lut(Some(lut))
+  }
+
+  /// Clear the color grading LUT, the tone mapped color is presented directly.
+  /// return: The result.
+  pub fn clear_color_grading_lut(&mut self) -> Result<(), HalaRendererError> {
+    self.replace_color_grading_lut(None)
+  }
+
+  /// Replace the color grading LUT.
+  /// After commit, it waits the in-flight frames and rewrites the LUT binding of the color grading descriptor set.
+  /// param lut: The new LUT, none to disable the color grading.
+  /// return: The result.
+  fn replace_color_grading_lut(&mut self, lut: Option<HalaColorGradingLUT>) -> Result<(), HalaRendererError> {
+    if self.color_grading.is_committed() {
+      // The color grading descriptor set is used by the in-flight frames.
+      self.wait_idle()?;
+    }
+
+    if let Some(old_lut) = self.color_grading.replace_lut(lut) {
+      self.resources.retire(Box::new(old_lut));
+    }
+
+    Ok(())
+  }
+
+  /// Set the lens effect shaders.
+  /// The lens pass is the last pass before the UI, it applies the vignette and the chromatic aberration to the final color.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_lens_shaders_with_file(
+    &mut self,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.lens.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Set the lens effects, the lens pass is skipped if both are 0.
+  /// The lens shaders must be set before commit to apply the effects.
+  /// param vignette: The strength of the radial darkening.
+  /// param chromatic: The channel offset toward the edges in the fraction of the image width.
+  pub fn set_lens_effects(&mut self, vignette: f32, chromatic: f32) {
+    self.lens.effects = HalaLensEffects::new(vignette, chromatic);
+  }
+
+  /// Get the lens effects.
+  /// return: The lens effects.
+  pub fn get_lens_effects(&self) -> HalaLensEffects {
+    self.lens.effects
+  }
+
+  /// Set the output image mode, the scene is rendered into the renderer owned output images instead of the swapchain.
+  /// The output color image is in the SHADER_READ_ONLY_OPTIMAL layout after the frame, so the host can sample it(e.g. in an editor viewport).
+  /// The frame still acquires and presents the swapchain image, it is cleared and only drawn by the UI function.
+  /// The output images are created immediately, the old images are retired after the frames in flight.
+  /// param size: The width and the height of the output images, none to render into the swapchain.
+  /// return: The result.
+  pub fn set_output_image_size(&mut self, size: Option<(u32, u32)>) -> Result<(), HalaRendererError> {
+    let images = [self.output_color_image.take(), self.output_depth_image.take()];
+    for image in images.into_iter().flatten() {
+      self.resources.retire(Box::new(image));
+    }
+    self.output_size = size;
+
+    let (width, height) = match size {
+      Some(size) => size,
+      None => return Ok(()),
+    };
+    if width == 0 || height == 0 {
+      self.output_size = None;
+      return Err(HalaRendererError::new(&format!("The output image size {}x{} is invalid!", width, height), None));
+    }
+    let errors = self.validate_output_image();
+    if !errors.is_empty() {
+      self.output_size = None;
+      return Err(HalaRendererError::with_errors("The output image is invalid:", errors));
+    }
+
+    // The color and depth images use the swapchain formats, so the scene pipelines can render into them.
+    let context = self.resources.context.borrow();
+    let output_color_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED | hala_gfx::HalaImageUsageFlags::TRANSFER_SRC,
+      context.swapchain.format,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "output_color.image",
+    )?;
+    let output_depth_image = hala_gfx::HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+      context.swapchain.depth_stencil_format,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "output_depth.image",
+    )?;
+    drop(context);
+
+    self.output_color_image = Some(output_color_image);
+    self.output_depth_image = Some(output_depth_image);
+
+    Ok(())
+  }
+
+  /// Resize the output images, the output image mode must be set.
+  /// The projection of the cameras is not changed, the host should update the aspect of the camera.
+  /// param width: The width of the output images.
+  /// param height: The height of the output images.
+  /// return: The result.
+  pub fn resize_output_image(&mut self, width: u32, height: u32) -> Result<(), HalaRendererError> {
+    if self.output_size.is_none() {
+      return Err(HalaRendererError::new("The output image is not set! Call set_output_image_size first.", None));
+    }
+    if self.output_size == Some((width, height)) {
+      return Ok(());
+    }
+
+    self.set_output_image_size(Some((width, height)))
+  }
+
+  /// Get the output color image.
+  /// It is in the SHADER_READ_ONLY_OPTIMAL layout after the frame.
+  /// return: The output color image, none if the scene is rendered into the swapchain.
+  pub fn get_output_image(&self) -> Option<&hala_gfx::HalaImage> {
+    self.output_color_image.as_ref()
+  }
+
+  /// Get the size of the output images.
+  /// return: The width and the height, none if the scene is rendered into the swapchain.
+  pub fn get_output_image_size(&self) -> Option<(u32, u32)> {
+    self.output_size
+  }
+
+  /// Get the render path of the scene drawing.
+  /// return: The render path.
+  pub fn get_render_path(&self) -> HalaRenderPath {
+    if self.software_raster_file_path.is_some() {
+      HalaRenderPath::SOFTWARE_MESHLET
+    } else if self.use_visibility_buffer {
+      HalaRenderPath::VISIBILITY_BUFFER
+    } else if self.use_mesh_shader {
+      HalaRenderPath::MESH_SHADER
+    } else {
+      HalaRenderPath::TRADITIONAL
+    }
+  }
+
+  /// Whether the meshlets are built and bound, they are used by the mesh shader and the software meshlet path.
+  /// return: True if the meshlets are used.
+  fn use_meshlets(&self) -> bool {
+    self.use_mesh_shader || self.software_raster_file_path.is_some()
+  }
+
+  /// Whether the renderer draws with mesh shaders.
+  /// It is false if the mesh shader is not required, or it is required but not supported by the device.
+  /// return: True if the mesh shader is used.
+  pub fn supports_mesh_shader(&self) -> bool {
+    self.use_mesh_shader
+  }
+
+  /// Push shaders to the renderer.
+  /// param task_file_path: The task shader file path.
+  /// param mesh_file_path: The mesh shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn push_shaders_with_file(
+    &mut self,
+    task_file_path: Option<&str>,
+    mesh_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    if !self.use_mesh_shader {
+      return Err(HalaRendererError::new(
+        "The renderer does not use mesh shader, push the traditional shaders or the shaders with a fallback vertex shader instead!", None));
+    }
+    self.check_not_committed("push_shaders_with_file")?;
+
+    let context = self.resources.context.borrow();
+
+    let task_shader = match task_file_path {
+      Some(file_path) => Some(hala_gfx::HalaShader::with_file(
+        Rc::clone(&context.logical_device),
+        file_path,
+        hala_gfx::HalaShaderStageFlags::TASK,
+        hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+        &format!("{}.task", debug_name),
+      )?),
+      None => None,
+    };
+
+    let mesh_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      mesh_file_path,
+      hala_gfx::HalaShaderStageFlags::MESH,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.mesh", debug_name),
+    )?;
+
+    let fragment_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.frag", debug_name),
+    )?;
+
+    self.shaders.push((task_shader, mesh_shader, fragment_shader));
+
+    Ok(())
+  }
+
+  /// Push shaders to the renderer with a fallback vertex shader.
+  /// The vertex shader is used with the original indices if the renderer falls back to the traditional vertex pipeline.
+  /// The fragment shader is shared, so the outputs of the mesh shader and the vertex shader must match.
+  /// param task_file_path: The task shader file path.
+  /// param mesh_file_path: The mesh shader file path.
+  /// param vertex_file_path: The fallback vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn push_shaders_with_file_or_fallback(
+    &mut self,
+    task_file_path: Option<&str>,
+    mesh_file_path: &str,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    if self.use_mesh_shader {
+      self.push_shaders_with_file(task_file_path, mesh_file_path, fragment_file_path, debug_name)
+    } else {
+      self.push_traditional_shaders_with_file(vertex_file_path, fragment_file_path, debug_name)
+    }
+  }
+
+  /// Set the stencil state of the material type pipeline.
+  /// It takes effect on the next commit.
+  /// param material_type: The material type, it is the index of the pushed shaders.
+  /// param stencil_state: The stencil state, none to disable the stencil test.
+  pub fn set_stencil_state(&mut self, material_type: usize, stencil_state: Option<hala_gfx::HalaStencilState>) {
+    if self.stencil_states.len() <= material_type {
+      self.stencil_states.resize_with(material_type + 1, || None);
+    }
+    self.stencil_states[material_type] = stencil_state;
+  }
+
+  /// Set the shaders of the selection pass.
+  /// The selected primitives are redrawn after the scene with the depth test disabled and the stencil state, e.g. not equal to the reference written by the scene for an outline.
+  /// It takes effect on the next commit, only the forward rendering supports the selection pass.
+  /// param task_file_path: The task shader file path, only for mesh shader.
+  /// param vertex_or_mesh_file_path: The vertex shader file path, or the mesh shader file path for mesh shader.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param stencil_state: The stencil state of the selection pipeline.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_selection_shaders_with_file(
+    &mut self,
+    task_file_path: Option<&str>,
+    vertex_or_mesh_file_path: &str,
+    fragment_file_path: &str,
+    stencil_state: hala_gfx::HalaStencilState,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    if !self.use_mesh_shader && task_file_path.is_some() {
+      return Err(HalaRendererError::new("The task shader is only supported by the mesh shader!", None));
+    }
+
+    let context = self.resources.context.borrow();
+
+    let mut selection_shaders = Vec::with_capacity(3);
+    if let Some(file_path) = task_file_path {
+      selection_shaders.push(hala_gfx::HalaShader::with_file(
+        Rc::clone(&context.logical_device),
+        file_path,
+        hala_gfx::HalaShaderStageFlags::TASK,
+        hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+        &format!("{}.task", debug_name),
+      )?);
+    }
+    let (stage, extension) = if self.use_mesh_shader {
+      (hala_gfx::HalaShaderStageFlags::MESH, "mesh")
+    } else {
+      (hala_gfx::HalaShaderStageFlags::VERTEX, "vert")
+    };
+    selection_shaders.push(hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      vertex_or_mesh_file_path,
+      stage,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.{}", debug_name, extension),
+    )?);
+    selection_shaders.push(hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.frag", debug_name),
+    )?);
+    drop(context);
+
+    self.selection_shaders = selection_shaders;
+    self.selection_stencil_state = Some(stencil_state);
+
+    Ok(())
+  }
+
+  /// Set the shaders of the overlay pass, the pipelines are created by commit.
+  /// The wireframe pipeline rasterizes the primitives as lines, the device should support the non-solid fill mode.
+  /// param wireframe_task_file_path: The task shader file path of the wireframe, only for mesh shader.
+  /// param wireframe_vertex_or_mesh_file_path: The vertex shader file path of the wireframe, or the mesh shader file path for mesh shader.
+  /// param wireframe_fragment_file_path: The fragment shader file path of the wireframe.
+  /// param normal_line_vertex_file_path: The vertex shader file path of the normal lines.
+  /// param normal_line_fragment_file_path: The fragment shader file path of the normal lines.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_overlay_shaders_with_file(
+    &mut self,
+    wireframe_task_file_path: Option<&str>,
+    wireframe_vertex_or_mesh_file_path: &str,
+    wireframe_fragment_file_path: &str,
+    normal_line_vertex_file_path: &str,
+    normal_line_fragment_file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.overlay.set_shaders_with_file(
+      &context,
+      self.use_mesh_shader,
+      wireframe_task_file_path,
+      wireframe_vertex_or_mesh_file_path,
+      wireframe_fragment_file_path,
+      normal_line_vertex_file_path,
+      normal_line_fragment_file_path,
+      debug_name,
+    )
+  }
+
+  /// Set the shaders of the immediate lines, the pipeline is created by commit.
+  /// The vertex shader transforms the world space position at the location 0 by vp_mtx of the global uniform buffer,
+  /// and passes the color at the location 1 to the fragment shader.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_line_shaders_with_file(&mut self, vertex_file_path: &str, fragment_file_path: &str, debug_name: &str) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    self.lines.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Draw a line in the world space in the next update, the lines are cleared after each update.
+  /// param a: The start point.
+  /// param b: The end point.
+  /// param color: The color of the line.
+  pub fn draw_line(&mut self, a: [f32; 3], b: [f32; 3], color: [f32; 4]) {
+    self.lines.draw_line(a, b, color);
+  }
+
+  /// Draw the 12 edges of an AABB in the world space in the next update.
+  /// param bounds: The AABB.
+  /// param color: The color of the lines.
+  pub fn draw_aabb(&mut self, bounds: &HalaBounds, color: [f32; 4]) {
+    self.lines.draw_aabb(bounds, color);
+  }
+
+  /// Draw a sphere as the 3 circles on the XY, the YZ and the XZ planes in the world space in the next update.
+  /// param center: The center of the sphere.
+  /// param radius: The radius of the sphere.
+  /// param color: The color of the lines.
+  pub fn draw_sphere(&mut self, center: [f32; 3], radius: f32, color: [f32; 4]) {
+    self.lines.draw_sphere(center, radius, color);
+  }
+
+  /// Draw a cone with the apex, the axis direction, the height and the base radius in the world space in the next update.
+  /// param apex: The apex of the cone.
+  /// param direction: The direction from the apex to the base center.
+  /// param height: The distance from the apex to the base.
+  /// param radius: The radius of the base.
+  /// param color: The color of the lines.
+  pub fn draw_cone(&mut self, apex: [f32; 3], direction: [f32; 3], height: f32, radius: f32, color: [f32; 4]) {
+    self.lines.draw_cone(apex, direction, height, radius, color);
+  }
+
+  /// Draw the ranges of the point lights and the spot lights by the immediate lines, it requires set_line_shaders_with_file before commit.
+  /// The point lights are drawn as the range spheres, the spot lights are drawn as the cones of the outer angle, the lights without the range are skipped.
+  /// param enable: Whether to draw the light ranges.
+  /// param color: The color of the lines.
+  pub fn set_light_range_visualization(&mut self, enable: bool, color: [f32; 4]) {
+    self.lines.draw_light_ranges = enable;
+    self.lines.light_range_color = color;
+  }
+
+  /// Set the shaders of the grid pass, the pipeline is created by commit if the grid is enabled.
+  /// The vertex shader outputs a full screen triangle strip of 4 vertices, the fragment shader intersects the view ray with the XZ plane
+  /// by i_vp_mtx of the global uniform buffer and gets the grid color, the cell size and the fade distance from the push constants.
+  /// The forward fragment shader writes the depth of the plane, the deferred one reads the G-Buffer depth at the binding 0 of the set 2.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_grid_shaders_with_file(&mut self, vertex_file_path: &str, fragment_file_path: &str, debug_name: &str) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    self.grid.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Enable the infinite grid on the XZ plane, it takes effect on the next commit.
+  /// The settings can be changed after commit by calling it again.
+  /// param settings: The grid settings.
+  /// return: The result.
+  pub fn enable_grid(&mut self, settings: HalaGridSettings) -> Result<(), HalaRendererError> {
+    self.grid.enable(settings)
+  }
+
+  /// Get the grid settings.
+  /// return: The grid settings.
+  pub fn get_grid_settings(&self) -> &HalaGridSettings {
+    &self.grid.settings
+  }
+
+  /// Disable the infinite grid.
+  pub fn disable_grid(&mut self) {
+    self.grid.is_enabled = false;
+  }
+
+  /// Set the shaders of the axis gizmo, the pipeline is created by commit if the axis gizmo is enabled.
+  /// The vertex shader outputs the 3 axis lines of a line list by the vertex index, the line i goes from the origin to the unit axis i,
+  /// both transformed by the gizmo matrix(mat4) in the push constants, and colors the X, Y and Z axes in red, green and blue.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_axis_gizmo_shaders_with_file(&mut self, vertex_file_path: &str, fragment_file_path: &str, debug_name: &str) -> Result<(), HalaRendererError> {
+    let context = self.resources.context.borrow();
+    self.axis_gizmo.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, debug_name)
+  }
+
+  /// Enable the axis gizmo in the bottom left corner of the first view, it takes effect on the next commit.
+  /// The size can be changed after commit by calling it again.
+  /// param size: The side of the gizmo viewport in pixels.
+  /// return: The result.
+  pub fn enable_axis_gizmo(&mut self, size: u32) -> Result<(), HalaRendererError> {
+    self.axis_gizmo.enable(size)
+  }
+
+  /// Disable the axis gizmo.
+  pub fn disable_axis_gizmo(&mut self) {
+    self.axis_gizmo.size = 0;
+  }
+
+  /// Set the shaders of the shadow pass, the depth only pipeline is created by commit if the shadows are enabled.
+  /// The pipeline shares the layout of the scene pipelines, the tile index(uint) follows the draw push constants.
+  /// The vertex or the mesh shader transforms the position by m_mtx of the object uniform and vp_mtx of the tile
+  /// in the shadow uniform buffer(the binding 5 of the set 0, see HalaShadowUniform), the position must be written with the same
+  /// precision as the scene pipelines. The optional fragment shader only discards the alpha tested texels.
+  /// param task_file_path: The task shader file path, only for mesh shader.
+  /// param vertex_or_mesh_file_path: The vertex shader file path, or the mesh shader file path for mesh shader.
+  /// param fragment_file_path: The optional fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn set_shadow_shaders_with_file(
+    &mut self,
+    task_file_path: Option<&str>,
+    vertex_or_mesh_file_path: &str,
+    fragment_file_path: Option<&str>,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    self.check_not_committed("set_shadow_shaders_with_file")?;
+
+    let context = self.resources.context.borrow();
+    self.shadow.set_shaders_with_file(
+      &context,
+      self.use_mesh_shader,
+      task_file_path,
+      vertex_or_mesh_file_path,
+      fragment_file_path,
+      debug_name,
+    )
+  }
+
+  /// Enable the shadows of the lights, the shadow atlas is created and the tiles are allocated by commit.
+  /// See HalaShadowAtlas for the tiles of each light type.
+  /// param config: The shadow config.
+  /// return: The result.
+  pub fn enable_shadows(&mut self, config: HalaShadowConfig) -> Result<(), HalaRendererError> {
+    self.check_not_committed("enable_shadows")?;
+    self.shadow.enable(config)
+  }
+
+  /// Disable the shadows, the shadow atlas is kept without any tile until the renderer is dropped.
+  /// return: The result.
+  pub fn disable_shadows(&mut self) -> Result<(), HalaRendererError> {
+    self.check_not_committed("disable_shadows")?;
+    self.shadow.config = None;
+
+    Ok(())
+  }
+
+  /// Set the sample counts of the percentage-closer soft shadows, it can be called after commit to change the quality.
+  /// The light sizes come from the radius and the area of the lights, see HalaShadowAtlas.
+  /// param blocker_search_samples: The sample count of the blocker search, 0 disables PCSS.
+  /// param filter_samples: The sample count of the filter, 1 is a single hard shadow test.
+  /// return: The result.
+  pub fn set_soft_shadow_samples(&mut self, blocker_search_samples: u32, filter_samples: u32) -> Result<(), HalaRendererError> {
+    self.shadow.set_soft_shadow_samples(blocker_search_samples, filter_samples, self.scene_in_gpu.as_ref())
+  }
+
+  /// Get the shadow atlas.
+  /// return: The shadow atlas, none if the shadows are not enabled or not committed.
+  pub fn get_shadow_atlas(&self) -> Option<&HalaShadowAtlas> {
+    self.shadow.get_atlas()
+  }
+
+  /// Set the overlay of the shaded image, it requires set_overlay_shaders_with_file before commit.
+  /// param wireframe: Draw the wireframe of the scene or not.
+  /// param normals: Draw the normal lines of the vertices or not.
+  /// param length: The length of the normal lines in the object space.
+  /// return: The result.
+  pub fn set_overlay(&mut self, wireframe: bool, normals: bool, length: f32) -> Result<(), HalaRendererError> {
+    self.overlay.set(wireframe, normals, length)
+  }
+
+  /// Set the selected primitives drawn by the selection pass.
+  /// param primitives: The selected primitives(mesh index, primitive index), empty to clear the selection.
+  /// return: The result.
+  pub fn set_selection(&mut self, primitives: &[(usize, usize)]) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    for &(mesh_index, primitive_index) in primitives.iter() {
+      match scene.meshes.get(mesh_index) {
+        Some(mesh) if primitive_index < mesh.primitives.len() => (),
+        _ => return Err(HalaRendererError::new(
+          &format!("The selected primitive {} of the mesh {} is out of range!", primitive_index, mesh_index), None)),
+      }
+    }
+    self.selection = primitives.to_vec();
+
+    Ok(())
+  }
+
+  /// Show or hide the mesh, the hidden meshes are skipped by the scene, the overlay and the selection passes.
+  /// param mesh_index: The index of the mesh.
+  /// param visible: Whether the mesh is visible.
+  /// return: The result.
+  pub fn set_mesh_visible(&mut self, mesh_index: usize, visible: bool) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    if mesh_index >= scene.meshes.len() {
+      return Err(HalaRendererError::new(&format!("The mesh index {} is out of range!", mesh_index), None));
+    }
+
+    if self.mesh_visibilities.len() < scene.meshes.len() {
+      self.mesh_visibilities.resize(scene.meshes.len(), true);
+    }
+    self.mesh_visibilities[mesh_index] = visible;
+
+    Ok(())
+  }
+
+  /// Whether the mesh is visible.
+  /// param mesh_index: The index of the mesh.
+  /// return: True if the mesh is visible.
+  pub fn is_mesh_visible(&self, mesh_index: usize) -> bool {
+    self.mesh_visibilities.get(mesh_index).copied().unwrap_or(true)
+  }
+
+  /// Override the material of all primitives of the mesh, the override material index is pushed with the push constants.
+  /// The bindless mode reads the material index from the primitive table, so the override is not supported by it.
+  /// The pipeline of the override material type must exist, the deferred flag of the override material selects the pass.
+  /// param mesh_index: The index of the mesh.
+  /// param material_index: The index of the override material, none to use the materials of the primitives.
+  /// return: The result.
+  pub fn set_mesh_material_override(&mut self, mesh_index: usize, material_index: Option<u32>) -> Result<(), HalaRendererError> {
+    if self.use_bindless {
+      return Err(HalaRendererError::new("The material override is not supported by the bindless mode, the material index is in the primitive table.", None));
+    }
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    if mesh_index >= scene.meshes.len() {
+      return Err(HalaRendererError::new(&format!("The mesh index {} is out of range!", mesh_index), None));
+    }
+    if let Some(material_index) = material_index {
+      let material_type = scene.material_types.get(material_index as usize).ok_or(
+        HalaRendererError::new(&format!("The override material index {} is out of range!", material_index), None)
+      )?;
+      let num_of_shaders = if self.use_mesh_shader { self.shaders.len() } else { self.traditional_shaders.len() };
+      if *material_type as usize >= num_of_shaders {
+        return Err(HalaRendererError::new(
+          &format!("The material type {} of the override material {} has no shader!", material_type, material_index), None));
+      }
+    }
+
+    if self.mesh_material_overrides.len() < scene.meshes.len() {
+      self.mesh_material_overrides.resize(scene.meshes.len(), None);
+    }
+    self.mesh_material_overrides[mesh_index] = material_index;
+
+    self.build_draw_groups()
+  }
+
+  /// Get the override material of the mesh.
+  /// param mesh_index: The index of the mesh.
+  /// return: The index of the override material, none if the mesh uses the materials of its primitives.
+  pub fn get_mesh_material_override(&self, mesh_index: usize) -> Option<u32> {
+    self.mesh_material_overrides.get(mesh_index).copied().flatten()
+  }
+
+  /// Group the primitives of the scene by the pass and the material type, so draw_scene binds each pipeline once.
+  /// The primitives of a group keep the scene order, the hidden meshes are skipped when drawing.
+  /// return: The result.
+  fn build_draw_groups(&mut self) -> Result<(), HalaRendererError> {
+    let draw_groups = match self.scene_in_gpu.as_ref() {
+      Some(scene) => {
+        let mut primitives = Vec::new();
+        for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+          for (primitive_index, primitive) in mesh.primitives.iter().enumerate() {
+            primitives.push((mesh_index, primitive_index, self.get_primitive_material_index(mesh_index, primitive) as usize));
+          }
+        }
+        Self::group_draws(self.use_deferred, &primitives, &scene.material_types, &scene.material_deferred_flags, scene.materials.len())?
+      },
+      None => Default::default(),
+    };
+    self.draw_groups = draw_groups;
+
+    Ok(())
+  }
+
+  /// Route the primitives to the passes and group them by the material type.
+  /// The draw index of a primitive is its order in the scene, each primitive is in exactly one pass.
+  /// param use_deferred: Whether the deferred rendering is used.
+  /// param primitives: The mesh index, the primitive index and the material index of the primitives in the scene order.
+  /// param material_types: The material type of each material.
+  /// param material_deferred_flags: The deferred flag of each material.
+  /// param num_of_materials: The material count, the material types must be less than it.
+  /// return: The draw groups of the forward and the deferred passes, sorted by the material type.
+  fn group_draws(
+    use_deferred: bool,
+    primitives: &[(usize, usize, usize)],
+    material_types: &[u32],
+    material_deferred_flags: &[bool],
+    num_of_materials: usize,
+  ) -> Result<[Vec<HalaDrawGroup>; 2], HalaRendererError> {
+    let mut draw_groups: [Vec<HalaDrawGroup>; 2] = Default::default();
+    for (draw_index, &(mesh_index, primitive_index, material_index)) in primitives.iter().enumerate() {
+      let material_type = material_types[material_index] as usize;
+      if material_type >= num_of_materials {
+        return Err(HalaRendererError::new("The material type index is out of range!", None));
+      }
+      let pass = HalaMaterialPass::from_material(use_deferred, material_deferred_flags[material_index]);
+
+      let groups = &mut draw_groups[pass.to_u8() as usize];
+      let draw_index = draw_index as u32;
+      match groups.iter_mut().find(|group| group.material_type == material_type) {
+        Some(group) => group.draws.push((mesh_index, primitive_index, draw_index)),
+        None => groups.push(HalaDrawGroup { material_type, draws: vec![(mesh_index, primitive_index, draw_index)] }),
+      }
+    }
+    for groups in draw_groups.iter_mut() {
+      groups.sort_by_key(|group| group.material_type);
+    }
+
+    Ok(draw_groups)
+  }
+
+  /// Get the material index of the primitive with the override of its mesh.
+  /// param mesh_index: The index of the mesh.
+  /// param primitive: The primitive.
+  /// return: The material index.
+  fn get_primitive_material_index(&self, mesh_index: usize, primitive: &gpu::HalaPrimitive) -> u32 {
+    self.get_mesh_material_override(mesh_index).unwrap_or(primitive.material_index)
+  }
+
+  /// Get the selected primitives.
+  /// return: The selected primitives(mesh index, primitive index).
+  pub fn get_selection(&self) -> &[(usize, usize)] {
+    &self.selection
+  }
+
+  /// Set the shaders of the outline pass.
+  /// The shaders draw a full screen triangle strip of 4 vertices without the descriptor sets, the fragment shader gets the outline color in the push constants.
+  /// It is drawn after the selection pass, the stencil state selects the outline pixels, e.g. the scene pipelines write 1 with set_stencil_state,
+  /// the selection pass writes 2 over the extruded selected primitives where the stencil is not 1, and the outline pass draws where the stencil equals 2.
+  /// It takes effect on the next commit, the swapchain depth stencil format must have the stencil aspect.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param stencil_state: The stencil state of the outline pipeline.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub fn set_outline_shaders_with_file(
+    &mut self,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    stencil_state: hala_gfx::HalaStencilState,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    let context = self.resources.context.borrow();
+    self.outline.set_shaders_with_file(&context, vertex_file_path, fragment_file_path, stencil_state, debug_name)
+  }
+
+  /// Set the color of the outline pass.
+  /// param color: The outline color, the alpha is used for the blending.
+  pub fn set_outline_color(&mut self, color: [f32; 4]) {
+    self.outline.color = color;
+  }
+
+  /// Enable or disable the stencil aspect of the G-Buffer depth image, it selects D32_SFLOAT_S8_UINT or D32_SFLOAT.
+  /// The G-Buffer pass clears the stencil to 0, so the scene pipelines can write the references with set_stencil_state.
+  /// It takes effect on the next create_visibility_buffer_images, create_gbuffer_images takes the depth format as the parameter.
+  /// param enable: Whether the G-Buffer depth image has the stencil aspect.
+  pub fn set_gbuffer_stencil(&mut self, enable: bool) {
+    self.gbuffer_depth_format = if enable {
+      hala_gfx::HalaFormat::D32_SFLOAT_S8_UINT
+    } else {
+      hala_gfx::HalaFormat::D32_SFLOAT
+    };
+  }
+
+  /// Enable or disable the R32_UINT object ID target of the G-Buffer, it costs an extra color attachment.
+  /// It takes effect on the next create_gbuffer_images, the visibility buffer has the draw indices already.
+  /// param enable: Whether the G-Buffer has the object ID image.
+  pub fn set_gbuffer_object_id(&mut self, enable: bool) {
+    self.use_gbuffer_object_id = enable;
+  }
+
+  /// Enable or disable the occlusion queries of the primitives.
+  /// Each primitive drawn by the first view is wrapped in an occlusion query, the primitives beyond the max count are not queried.
+  /// param max_primitives: The max primitive count per frame, 0 to disable the occlusion queries.
+  /// return: The result.
+  pub fn enable_occlusion_queries(&mut self, max_primitives: usize) -> Result<(), HalaRendererError> {
+    // The old query pool may still be used by the in-flight frames.
+    if let Some(query_pool) = self.occlusion_query_pool.take() {
+      self.resources.retire(Box::new(query_pool));
+    }
+    self.max_occlusion_queries = max_primitives;
+    self.occlusion_query_counts = vec![0; self.resources.num_of_frames];
+    self.occlusion_results.clear();
+    self.statistics.set_occluded_primitives(0);
+    if max_primitives == 0 {
+      return Ok(());
+    }
+
+    let query_pool = {
+      let context = self.resources.context.borrow();
+      hala_gfx::HalaQueryPool::new(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaQueryType::OCCLUSION,
+        (max_primitives * self.resources.num_of_frames) as u32,
+        "occlusion.query_pool",
+      )?
+    };
+    self.occlusion_query_pool = Some(query_pool);
+
+    Ok(())
+  }
+
+  /// Get the occlusion query results of the last finished frame.
+  /// return: The visible sample count of each primitive indexed by the draw index, empty if the occlusion queries are disabled.
+  pub fn get_occlusion_results(&self) -> &[u64] {
+    &self.occlusion_results
+  }
+
+  /// Update the uniform buffers and record the command buffer of the acquired image.
+  /// param ui_fn: The draw UI function.
+  /// return: The result.
+  fn update_frame<F>(&mut self, ui_fn: F) -> Result<(), HalaRendererError>
+    where F: FnOnce(usize, &hala_gfx::HalaCommandBufferSet) -> Result<(), hala_gfx::HalaGfxError>
+  {
+    self.stream_next_texture()?;
+    self.update_textures_descriptor_set(self.data.image_index)?;
+    // Submit the streaming uploads enqueued since the last frame, this frame waits and acquires them.
+    self.resources.flush_uploads()?;
+    self.read_occlusion_results()?;
+    self.read_occlusion_culling_stats()?;
+
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let use_taa = self.taa.is_active();
+
+    for (view_index, view) in self.get_views().iter().enumerate() {
+      let (v_mtx, p_mtx) = self.get_view_matrices(scene, view_index, view)?;
+
+      let unjittered_vp_mtx = p_mtx * v_mtx;
+
+      // The TAA jitters the projection of the first view and reprojects the history by the unjittered matrices.
+      let jitter_mtx = if use_taa && view_index == 0 {
+        self.taa.update_jitter(unjittered_vp_mtx, view.viewport.2, view.viewport.3)
+      } else {
+        glam::Mat4::IDENTITY
+      };
+      let p_mtx = jitter_mtx * p_mtx;
+
+      // The previous matrices are jittered by the current jitter, so the motion vectors do not contain the jitter.
+      // The first frame of a view or a mesh has no motion.
+      let prev_vp_mtx = jitter_mtx * self.prev_vp_mtxs.get(view_index).copied().unwrap_or(unjittered_vp_mtx);
+      if view_index < self.prev_vp_mtxs.len() {
+        self.prev_vp_mtxs[view_index] = unjittered_vp_mtx;
+      } else {
+        self.prev_vp_mtxs.push(unjittered_vp_mtx);
+      }
+      let (global_uniform_buffers, object_uniform_buffers) = if view_index == 0 {
+        (&self.global_uniform_buffers, &self.object_uniform_buffers)
+      } else {
+        let view_resources = self.view_resources.get(view_index - 1).ok_or(HalaRendererError::new("The view resources are none!", None))?;
+        (&view_resources.global_uniform_buffers, &view_resources.object_uniform_buffers)
+      };
+
+      // Only write the slot of the current image, the other slots may still be read by the in-flight frames.
+      let image_index = self.data.image_index;
+
+      // Update global uniform buffer.
+      let vp_mtx = p_mtx * v_mtx;
+      global_uniform_buffers[image_index].update_memory(0, &[HalaGlobalUniform {
+        v_mtx,
+        p_mtx,
+        vp_mtx,
+        i_vp_mtx: vp_mtx.inverse(),
+        camera_position: v_mtx.inverse().w_axis,
+        debug_view: self.debug_view.to_u8() as u32,
+      }])?;
+
+      // Update object uniform buffers.
+      for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+        // Prepare object data.
+        let mv_mtx = v_mtx * mesh.transform;
+        let object_uniform = HalaObjectUniform {
+          m_mtx: mesh.transform,
+          i_m_mtx: mesh.transform.inverse(),
+          mv_mtx,
+          t_mv_mtx: mv_mtx.transpose(),
+          it_mv_mtx: mv_mtx.inverse().transpose(),
+          mvp_mtx: p_mtx * mv_mtx,
+          prev_mvp_mtx: prev_vp_mtx * self.prev_mesh_transforms.get(mesh_index).copied().unwrap_or(mesh.transform),
+        };
+
+        object_uniform_buffers[mesh_index][image_index].update_memory(0, &[object_uniform])?;
+      }
+    }
+    self.prev_mesh_transforms = scene.meshes.iter().map(|mesh| mesh.transform).collect();
+
+    if self.lines.draw_light_ranges {
+      self.draw_light_range_lines();
+    }
+    self.upload_lines()?;
+
+    self.draw_counters.set(HalaDrawCounters::default());
+    let num_of_pipeline_binds = if self.use_deferred {
+      self.record_deferred_command_buffer(
+        self.data.image_index,
+        &self.resources.graphics_command_buffers,
+        ui_fn,
+      )?
+    } else {
+      self.record_forward_command_buffer(
+        self.data.image_index,
+        &self.resources.graphics_command_buffers,
+        ui_fn,
+      )?
+    };
+    self.statistics.set_pipeline_binds(num_of_pipeline_binds);
+    self.statistics.set_draw_counters(self.draw_counters.get());
+
+    if use_taa {
+      self.taa.advance();
+    }
+    if self.ssr.is_active() {
+      self.ssr.advance();
+    }
+
+    if self.occlusion_query_pool.is_some() {
+      let num_of_primitives = self.scene_in_gpu.as_ref().map_or(0, |scene| scene.meshes.iter().map(|mesh| mesh.primitives.len()).sum::<usize>());
+      self.occlusion_query_counts[self.data.image_index] = num_of_primitives.min(self.max_occlusion_queries);
+    }
+
+    Ok(())
+  }
+
+  /// Read the occlusion query results of the current image.
+  /// The fence of the image is signaled in pre_update, so the results are available without blocking.
+  /// return: The result.
+  fn read_occlusion_results(&mut self) -> Result<(), HalaRendererError> {
+    let query_pool = match self.occlusion_query_pool.as_ref() {
+      Some(query_pool) => query_pool,
+      None => return Ok(()),
+    };
+    let image_index = self.data.image_index;
+    let num_of_queries = self.occlusion_query_counts[image_index];
+    if num_of_queries == 0 {
+      return Ok(());
+    }
+
+    self.occlusion_results = query_pool.get_query_results((image_index * self.max_occlusion_queries) as u32, num_of_queries as u32)?;
+    let num_of_occluded = self.occlusion_results.iter().filter(|&&samples| samples == 0).count();
+    self.statistics.set_occluded_primitives(num_of_occluded as u64);
+
+    Ok(())
+  }
+
+  /// Enable or disable the two phase occlusion culling of the meshlets, it must be called before commit.
+  /// It requires the mesh shader, the G-buffer and the depth pyramid shader(see set_depth_pyramid_shader_with_file).
+  /// The task shaders get the culling phase and the visibility offset after the other push constants,
+  /// the phase 1 draws the meshlets whose visibility bit is set, the phase 2 tests the others against the depth pyramid
+  /// and writes the visibility bits of all meshlets for the next frame, the phase 0 draws all meshlets(e.g. the overlay).
+  /// The dynamic descriptor set binds the visibility buffer at 7, the stats buffer(phase 1 and phase 2 meshlet counts) at 8
+  /// and the depth pyramid levels at 9, the stats are reported by the statistics.
+  /// param enable: Whether the occlusion culling is enabled.
+  /// return: The result.
+  pub fn enable_occlusion_culling(&mut self, enable: bool) -> Result<(), HalaRendererError> {
+    self.check_not_committed("enable_occlusion_culling")?;
+    self.use_occlusion_culling = enable;
+    if !enable {
+      self.occlusion_culling_visibility_buffer = None;
+      self.occlusion_culling_visibility_offsets.clear();
+      self.occlusion_culling_stats_buffers.clear();
+      self.statistics.set_occlusion_culling_meshlets([0; 2]);
+    }
+
+    Ok(())
+  }
+
+  /// Read the meshlet counts of the occlusion culling of the current image and clear them for the next recording.
+  /// The fence of the image is signaled in pre_update, so the counts are available without blocking.
+  /// return: The result.
+  fn read_occlusion_culling_stats(&mut self) -> Result<(), HalaRendererError> {
+    let stats_buffer = match self.occlusion_culling_stats_buffers.get(self.data.image_index) {
+      Some(stats_buffer) => stats_buffer,
+      None => return Ok(()),
+    };
+
+    let mut meshlets = [0u32; 2];
+    stats_buffer.download_memory(0, &mut meshlets)?;
+    stats_buffer.update_memory(0, &[0u32; 2])?;
+    self.statistics.set_occlusion_culling_meshlets([meshlets[0] as u64, meshlets[1] as u64]);
+
+    Ok(())
+  }
+
+  /// Push compute shaders to the renderer.
+  /// param file_path: The compute shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub fn push_compute_shaders_with_file(
+    &mut self,
+    file_path: &str,
+    debug_name: &str) -> Result<(), HalaRendererError>
+  {
+    self.check_not_committed("push_compute_shaders_with_file")?;
+    let context = self.resources.context.borrow();
+
+    let compute_shader = hala_gfx::HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      file_path,
+      hala_gfx::HalaShaderStageFlags::COMPUTE,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.comp", debug_name),
+    )?;
+
+    self.compute_shaders.push(compute_shader);
+
+    Ok(())
+  }
+
+  /// Set the meshlet building configuration of the mesh shader path.
+  /// It takes effect on the next set_scene call.
+  /// param config: The meshlet configuration.
+  /// return: The result.
+  pub fn set_meshlet_config(&mut self, config: HalaMeshletConfig) -> Result<(), HalaRendererError> {
+    config.validate()?;
+    self.meshlet_config = config;
+
+    Ok(())
+  }
+
+  /// Enable or disable the meshopt optimization of the primitives when uploading the scene.
+  /// It reorders the vertices and indices of the scene in the CPU, disable it if the original order is required.
+  /// It takes effect on the next set_scene call.
+  /// param enable: Enable the optimization or not.
+  pub fn set_mesh_optimization(&mut self, enable: bool) {
+    self.optimize_meshes = enable;
+  }
+
+  /// Enable or disable suballocating all primitives from the mega buffers when uploading the scene.
+  /// The mesh shaders index the single element of the storage buffer arrays and offset by the first meshlet in the push constants.
+  /// It takes effect on the next set_scene call.
+  /// param enable: Enable the mega buffers or not.
+  pub fn set_mega_buffers(&mut self, enable: bool) {
+    self.use_mega_buffers = enable;
+  }
+
+  /// Enable or disable streaming the textures when uploading the scene.
+  /// The textures start as 1x1 placeholders with their average color, one full resolution texture is uploaded per frame.
+  /// It takes effect on the next set_scene call.
+  /// param enable: Enable the texture streaming or not.
+  pub fn set_texture_streaming(&mut self, enable: bool) {
+    self.stream_textures = enable;
+  }
+
+  /// Enable or disable uploading the scene buffers by the transfer queue without waiting.
+  /// set_scene returns after submitting the copies, they run with the CPU work and the in-flight frames,
+  /// and the next frame waits them and acquires the buffers for the graphics queue.
+  /// It takes effect on the next set_scene call.
+  /// param enable: Enable the async upload or not.
+  pub fn set_async_upload(&mut self, enable: bool) {
+    self.async_upload = enable;
+  }
+
+  /// Set the number of the threads which record the scene draws.
+  /// With more than 1 thread, the draw lists are split into contiguous chunks, which are recorded into the secondary command buffers
+  /// in parallel and executed in order, so the image is the same for any thread count. 1 records the draws on the calling thread.
+  /// The device is waited idle.
+  /// param num_of_threads: The number of the recording threads.
+  /// return: The result.
+  pub fn set_recording_threads(&mut self, num_of_threads: usize) -> Result<(), HalaRendererError> {
+    self.resources.set_recording_threads(num_of_threads)
+  }
+
+  /// Enable or disable the bindless descriptor set.
+  /// All storage buffers of the scene are in one runtime sized array and the shaders find them by the primitive table,
+  /// so the push constants only have the draw index. The mesh shaders always draw the level 0 in the bindless mode.
+  /// It takes effect on the next commit, the device must support the descriptor indexing features of the bindless descriptor set.
+  /// param enable: Enable the bindless descriptor set or not.
+  /// return: The result.
+  pub fn set_bindless(&mut self, enable: bool) -> Result<(), HalaRendererError> {
+    if enable && !self.resources.supports_bindless() {
+      return Err(HalaRendererError::validation("The bindless mode requires the runtime descriptor array, the partially bound, the update after bind and the variable descriptor count features."));
+    }
+    self.use_bindless = enable;
+
+    Ok(())
+  }
+
+  /// Set the depth convention of the projection matrices, the depth tests and the depth clear values.
+  /// It must be called before set_scene and commit, so the projection and the depth state can not disagree.
+  /// param depth_convention: The depth convention.
+  /// return: The result.
+  pub fn set_depth_convention(&mut self, depth_convention: HalaDepthConvention) -> Result<(), HalaRendererError> {
+    if self.scene_in_gpu.is_some() || self.dynamic_descriptor_set.is_some() {
+      return Err(HalaRendererError::new("The depth convention must be set before set_scene and commit.", None));
+    }
+    self.depth_convention = depth_convention;
+
+    Ok(())
+  }
+
+  /// Get the depth convention.
+  /// return: The depth convention.
+  pub fn get_depth_convention(&self) -> HalaDepthConvention {
+    self.depth_convention
+  }
+
+  /// Enable or disable the overdraw debug view, it creates the additive pipelines of the forward pass.
+  /// It takes effect on the next commit.
+  /// param enable: Enable the overdraw debug view or not.
+  pub fn enable_overdraw_view(&mut self, enable: bool) {
+    self.use_overdraw_view = enable;
+  }
+
+  /// Set the debug view, the material and the lighting shaders read it from the global uniform buffer.
+  /// The overdraw view draws the forward pass with the additive pipelines, so it requires enable_overdraw_view before commit
+  /// and it is not supported by the deferred path.
+  /// param view: The debug view.
+  /// return: The result.
+  pub fn set_debug_view(&mut self, view: HalaDebugView) -> Result<(), HalaRendererError> {
+    if view == HalaDebugView::OVERDRAW {
+      if self.use_deferred {
+        return Err(HalaRendererError::new("The overdraw debug view is not supported by the deferred path!", None));
+      }
+      if self.overdraw_graphics_pipelines.is_empty() {
+        return Err(HalaRendererError::new("The overdraw debug view is not enabled or the renderer is not committed!", None));
+      }
+    }
+    self.debug_view = view;
+
+    Ok(())
+  }
+
+  /// Get the debug view.
+  /// return: The debug view.
+  pub fn get_debug_view(&self) -> HalaDebugView {
+    self.debug_view
+  }
+
+  /// Enable or disable the reverse Z, it is the shortcut of set_depth_convention.
+  /// The reverse Z is enabled by default, disable it for the imported projections which map the near plane to 0.
+  /// param enable: Whether to use the reverse Z.
+  /// return: The result.
+  pub fn set_reverse_z(&mut self, enable: bool) -> Result<(), HalaRendererError> {
+    self.set_depth_convention(if enable { HalaDepthConvention::REVERSE_Z } else { HalaDepthConvention::STANDARD_Z })
+  }
+
+  /// Whether the reverse Z is used.
+  /// return: True if the reverse Z is used.
+  pub fn is_reverse_z(&self) -> bool {
+    self.depth_convention.is_reverse_z()
+  }
+
+  /// Whether the G-Buffer normal image stores the octahedral encoded normals.
+  /// The lighting shaders must decode the normals if it is true, see create_gbuffer_images.
+  /// return: True if the normal image has a two channel format.
+  pub fn is_gbuffer_normal_packed(&self) -> bool {
+    self.normal_image.as_ref().is_some_and(|image| matches!(
+      image.format,
+      hala_gfx::HalaFormat::R8G8_SNORM
+        | hala_gfx::HalaFormat::R16G16_SNORM
+        | hala_gfx::HalaFormat::R16G16_SFLOAT
+        | hala_gfx::HalaFormat::R32G32_SFLOAT
+    ))
+  }
+
+  /// Get the format of the G-Buffer depth image.
+  /// return: The format, it has the stencil aspect if the G-Buffer stencil is enabled.
+  pub fn get_gbuffer_depth_format(&self) -> hala_gfx::HalaFormat {
+    self.gbuffer_depth_format
+  }
+
+  /// Whether the G-Buffer depth image has the stencil aspect.
+  /// return: True if the G-Buffer depth format has the stencil aspect.
+  fn has_gbuffer_stencil(&self) -> bool {
+    is_stencil_format(self.gbuffer_depth_format)
+  }
+
+  /// Check the format of the G-Buffer depth image against the format features of the device.
+  /// The G-Buffer pass writes it as the depth stencil attachment and the lighting reads it as the input attachment,
+  /// both need the DEPTH_STENCIL_ATTACHMENT feature of the optimal tiling. The image which is not transient is also sampled
+  /// by the TAA resolve, the SSR trace and the depth pyramid, so it needs the SAMPLED_IMAGE feature too.
+  /// param format: The depth format.
+  /// param is_sampled: Whether the image is sampled.
+  /// return: The result.
+  fn check_gbuffer_depth_format(&self, format: hala_gfx::HalaFormat, is_sampled: bool) -> Result<(), HalaRendererError> {
+    let depth_formats = [
+      hala_gfx::HalaFormat::D16_UNORM,
+      hala_gfx::HalaFormat::X8_D24_UNORM_PACK32,
+      hala_gfx::HalaFormat::D32_SFLOAT,
+      hala_gfx::HalaFormat::D16_UNORM_S8_UINT,
+      hala_gfx::HalaFormat::D24_UNORM_S8_UINT,
+      hala_gfx::HalaFormat::D32_SFLOAT_S8_UINT,
+    ];
+    if !depth_formats.contains(&format) {
+      return Err(HalaRendererError::new(&format!("The G-Buffer depth format {:?} is not a depth format!", format), None));
+    }
+
+    let context = self.resources.context.borrow();
+    let format_properties = unsafe {
+      context.instance.raw.get_physical_device_format_properties(context.physical_device.raw, vk::Format::from(format))
+    };
+    if !format_properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT) {
+      return Err(HalaRendererError::new(
+        &format!("The G-Buffer depth format {:?} does not support the depth stencil attachment and the input attachment usage on this device!", format), None));
+    }
+    if is_sampled && !format_properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+      return Err(HalaRendererError::new(
+        &format!("The G-Buffer depth format {:?} can not be sampled on this device, create the G-Buffer images with the transient flag or pick another format!", format), None));
+    }
+
+    Ok(())
+  }
+
+  /// Get the depth compare operation of the depth convention.
+  /// return: The depth compare operation.
+  fn get_depth_compare_op(&self) -> hala_gfx::HalaCompareOp {
+    if self.depth_convention.is_reverse_z() {
+      // We use reverse Z, so greater is less.
+      hala_gfx::HalaCompareOp::GREATER
+    } else {
+      hala_gfx::HalaCompareOp::LESS
+    }
+  }
+
+  /// Set the level of detail building configuration.
+  /// It takes effect on the next set_scene call.
+  /// param config: The level of detail configuration.
+  /// return: The result.
+  pub fn set_lod_config(&mut self, config: HalaLodConfig) -> Result<(), HalaRendererError> {
+    config.validate()?;
+    self.lod_config = config;
+
+    Ok(())
+  }
+
+  /// Set the level of detail bias.
+  /// The coarsest level whose projected error is less than 2^bias pixels is drawn, so the positive bias prefers the coarser levels.
+  /// param bias: The level of detail bias.
+  pub fn set_lod_bias(&mut self, bias: f32) {
+    self.lod_bias = bias;
+  }
+
+  /// Get the level of detail bias.
+  /// return: The level of detail bias.
+  pub fn get_lod_bias(&self) -> f32 {
+    self.lod_bias
+  }
+
+  /// Set the scene to be rendered.
+  /// param scene_in_cpu: The scene in the CPU.
+  /// return: The result.
+  pub fn set_scene(&mut self, scene_in_cpu: &mut cpu::HalaScene) -> Result<(), HalaRendererError> {
+    // Retire the old scene in the GPU, it is dropped after the in-flight frames which may use it.
+    if let Some(scene_in_gpu) = self.scene_in_gpu.take() {
+      let num_of_bytes = scene_in_gpu.stats.get_total_bytes();
+      self.resources.retire_with_size(Box::new(scene_in_gpu), num_of_bytes);
+    }
+    // The selection, the mesh toggles and the occlusion results refer to the primitives of the old scene.
+    self.selection.clear();
+    self.mesh_visibilities.clear();
+    self.mesh_material_overrides.clear();
+    self.prev_mesh_transforms.clear();
+    self.occlusion_results.clear();
+    self.occlusion_query_counts.iter_mut().for_each(|count| *count = 0);
+
+    // Check the memory budget of the device before allocating, the retired scenes are counted as reclaimable.
+    let mut memory_budget = self.resources.memory_budget();
+    memory_budget.reclaim(self.resources.get_retired_bytes());
+    let memory_size = loader::HalaSceneGPUUploader::check_memory_budget(scene_in_cpu, self.use_meshlets(), &memory_budget)?;
+    log::debug!("The scene requires about {} bytes, {} bytes are available.", memory_size, memory_budget.available);
+
+    let context = self.resources.context.borrow();
+
+    // Upload the new scene to the GPU, the buffer copies are recorded to the transfer batch in the async mode.
+    let mut transfer_batch = if self.async_upload {
+      Some(loader::HalaTransferBatch::new(&context, "scene_upload")?)
+    } else {
+      None
+    };
+    let scene_in_gpu = loader::HalaSceneGPUUploader::upload(
+      &context,
+      &self.resources.graphics_command_buffers,
+      &self.resources.transfer_command_buffers,
+      scene_in_cpu,
+      self.optimize_meshes,
+      &self.lod_config,
+      self.depth_convention,
+      self.use_mega_buffers,
+      self.stream_textures,
+      self.use_meshlets(),
+      false,
+      &self.meshlet_config,
+      false,
+      &[],
+      0,
+      transfer_batch.as_mut())?;
+    drop(context);
+    if let Some(transfer_batch) = transfer_batch {
+      self.resources.submit_transfer_batch(transfer_batch)?;
+    }
+
+    log::debug!("{}", scene_in_gpu.stats);
+    self.scene_in_gpu = Some(scene_in_gpu);
+
+    self.build_draw_groups()
+  }
+
+  /// Get the statistics and the memory usage of the scene in the GPU.
+  /// return: The statistics, none if no scene is set.
+  pub fn get_scene_stats(&self) -> Option<&gpu::HalaSceneGPUStats> {
+    self.scene_in_gpu.as_ref().map(|scene| &scene.stats)
+  }
+
+  /// Get the world space ray through a pixel for picking, pass it to scene::picking::pick with the CPU scene.
+  /// The ray uses the camera of the view which contains the pixel, the TAA jitter is not applied.
+  /// param x: The x coordinate in the window pixels.
+  /// param y: The y coordinate in the window pixels.
+  /// return: The origin and the normalized direction of the ray.
+  pub fn get_pick_ray(&self, x: f32, y: f32) -> Result<(glam::Vec3, glam::Vec3), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let (view_index, view) = self.get_views().into_iter()
+      .enumerate()
+      .find(|(_, view)| {
+        let (view_x, view_y, width, height) = view.viewport;
+        x >= view_x && x < view_x + width && y >= view_y && y < view_y + height
+      })
+      .ok_or(HalaRendererError::new(&format!("The pixel ({}, {}) is outside of the views!", x, y), None))?;
+    let (v_mtx, p_mtx) = self.get_view_matrices(scene, view_index, &view)?;
+
+    Ok(picking::get_screen_ray(v_mtx, p_mtx, scene.depth_convention, view.viewport, x, y))
+  }
+
+  /// Pick the pixel by reading back the G-Buffer depth of the last rendered frame.
+  /// The depth image is copied on the graphics queue and waited, so call it between the frames, e.g. on a click.
+  /// The position uses the camera of the view which contains the pixel, the TAA jitter is not applied.
+  /// It requires the G-Buffer images without the transient flag and the deferred subpasses.
+  /// The object ID image is read back too if the G-Buffer has it(see set_gbuffer_object_id).
+  /// param x: The x coordinate in the window pixels.
+  /// param y: The y coordinate in the window pixels.
+  /// return: The pick result, none if the pixel is the background or no frame is rendered.
+  pub fn pick(&self, x: u32, y: u32) -> Result<Option<HalaDepthPickResult>, HalaRendererError> {
+    if self.data.is_device_lost {
+      return Err(HalaRendererError::DeviceLost);
+    }
+    if !self.use_deferred || self.use_transient_gbuffer || self.use_deferred_subpasses {
+      return Err(HalaRendererError::new("The picking reads the G-Buffer depth, create the G-Buffer images without the transient flag and the deferred subpasses.", None));
+    }
+    let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The G-Buffer depth image is none!", None))?;
+    if x >= depth_image.extent.width || y >= depth_image.extent.height {
+      return Err(HalaRendererError::new(&format!("The pixel ({}, {}) is outside of the G-Buffer!", x, y), None));
+    }
+    if self.statistics.total_frames == 0 {
+      return Ok(None);
+    }
+
+    // The bytes of a texel of the depth aspect.
+    let texel_size = match depth_image.format {
+      hala_gfx::HalaFormat::D16_UNORM | hala_gfx::HalaFormat::D16_UNORM_S8_UINT => 2,
+      _ => 4,
+    };
+
+    self.wait_idle()?;
+    let context = self.resources.context.borrow();
+    let readback_buffer = hala_gfx::HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      (depth_image.extent.width * depth_image.extent.height * texel_size) as u64,
+      hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+      hala_gfx::HalaMemoryLocation::GpuToCpu,
+      "pick_readback.buffer",
+    )?;
+    let command_buffers = hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "pick.command_buffers",
+    )?;
+
+    // The G-Buffer pass leaves the depth in the read only layout, restore it after the copy.
+    let depth_barrier = |old_layout, new_layout, src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask| hala_gfx::HalaImageBarrierInfo {
+      old_layout,
+      new_layout,
+      src_access_mask,
+      dst_access_mask,
+      src_stage_mask,
+      dst_stage_mask,
+      aspect_mask: get_depth_aspect_mask(depth_image.format),
+      image: depth_image.raw,
+      ..Default::default()
+    };
+    command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    command_buffers.set_image_barriers(
+      0,
+      &[depth_barrier(
+        hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL,
+        hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+        hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+        hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+      )],
+    );
+    command_buffers.copy_image_2_buffer(0, depth_image, hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL, &readback_buffer);
+    command_buffers.set_image_barriers(
+      0,
+      &[depth_barrier(
+        hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL,
+        hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+        hala_gfx::HalaAccessFlags2::NONE,
+        hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+        hala_gfx::HalaPipelineStageFlags2::BOTTOM_OF_PIPE,
+      )],
+    );
+
+    // The G-Buffer pass leaves the object IDs in the shader read only layout, restore it after the copy.
+    let object_id_readback_buffer = match self.object_id_image.as_ref() {
+      Some(object_id_image) => {
+        let object_id_readback_buffer = hala_gfx::HalaBuffer::new(
+          Rc::clone(&context.logical_device),
+          (object_id_image.extent.width * object_id_image.extent.height * 4) as u64,
+          hala_gfx::HalaBufferUsageFlags::TRANSFER_DST,
+          hala_gfx::HalaMemoryLocation::GpuToCpu,
+          "pick_object_id_readback.buffer",
+        )?;
+        let object_id_barrier = |old_layout, new_layout, src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask| hala_gfx::HalaImageBarrierInfo {
+          old_layout,
+          new_layout,
+          src_access_mask,
+          dst_access_mask,
+          src_stage_mask,
+          dst_stage_mask,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: object_id_image.raw,
+          ..Default::default()
+        };
+        command_buffers.set_image_barriers(
+          0,
+          &[object_id_barrier(
+            hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL,
+            hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+            hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+            hala_gfx::HalaPipelineStageFlags2::ALL_COMMANDS,
+            hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+          )],
+        );
+        command_buffers.copy_image_2_buffer(0, object_id_image, hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL, &object_id_readback_buffer);
+        command_buffers.set_image_barriers(
+          0,
+          &[object_id_barrier(
+            hala_gfx::HalaImageLayout::TRANSFER_SRC_OPTIMAL,
+            hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            hala_gfx::HalaAccessFlags2::TRANSFER_READ,
+            hala_gfx::HalaAccessFlags2::NONE,
+            hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+            hala_gfx::HalaPipelineStageFlags2::BOTTOM_OF_PIPE,
+          )],
+        );
+        Some(object_id_readback_buffer)
+      },
+      None => None,
+    };
+    command_buffers.end(0)?;
+    context.logical_device.borrow().graphics_submit(&command_buffers, 0, 0)?;
+    context.logical_device.borrow().graphics_wait(0)?;
+
+    let mut texel = [0u8; 4];
+    readback_buffer.download_memory(((y * depth_image.extent.width + x) * texel_size) as usize, &mut texel[..texel_size as usize])?;
+    let depth = match depth_image.format {
+      hala_gfx::HalaFormat::D16_UNORM | hala_gfx::HalaFormat::D16_UNORM_S8_UINT =>
+        u16::from_le_bytes([texel[0], texel[1]]) as f32 / u16::MAX as f32,
+      hala_gfx::HalaFormat::X8_D24_UNORM_PACK32 | hala_gfx::HalaFormat::D24_UNORM_S8_UINT =>
+        (u32::from_le_bytes(texel) & 0x00ff_ffff) as f32 / 0x00ff_ffff as f32,
+      _ => f32::from_le_bytes(texel),
+    };
+    if depth == self.get_clear_depth_stencil().0 {
+      return Ok(None);
+    }
+
+    // The object IDs are the draw index + 1.
+    let object_id = match object_id_readback_buffer.as_ref() {
+      Some(object_id_readback_buffer) => {
+        let mut texel = [0u8; 4];
+        object_id_readback_buffer.download_memory(((y * depth_image.extent.width + x) * 4) as usize, &mut texel)?;
+        u32::from_le_bytes(texel).checked_sub(1)
+      },
+      None => None,
+    };
+
+    // Reconstruct the position at the pixel center.
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+    let (view_index, view) = self.get_views().into_iter()
+      .enumerate()
+      .find(|(_, view)| {
+        let (view_x, view_y, width, height) = view.viewport;
+        x >= view_x && x < view_x + width && y >= view_y && y < view_y + height
+      })
+      .ok_or(HalaRendererError::new(&format!("The pixel ({}, {}) is outside of the views!", x, y), None))?;
+    let (v_mtx, p_mtx) = self.get_view_matrices(scene, view_index, &view)?;
+
+    Ok(Some(HalaDepthPickResult {
+      depth,
+      position: picking::get_screen_position(v_mtx, p_mtx, view.viewport, x, y, depth),
+      object_id,
+    }))
+  }
+
+  /// Set the render area to letterbox the scene.
+  /// The full window is still cleared and the UI still covers the full window, only the scene and lighting passes are restricted.
+  /// It is ignored by the views of the multi-viewport rendering, which are in the window pixels.
+  /// param area: The area(x, y, width, height) in pixels, none to render the full window.
+  pub fn set_render_area(&mut self, area: Option<(i32, i32, u32, u32)>) {
+    self.render_area = area;
+  }
+
+  /// Get the render area.
+  /// return: The area(x, y, width, height) in pixels, none if the full window is rendered.
+  pub fn get_render_area(&self) -> Option<(i32, i32, u32, u32)> {
+    self.render_area
+  }
+
+  /// Set the clear color of the scene targets, it is the background of the forward and the lighting passes.
+  /// The command buffers are recorded every frame, so it takes effect on the next update.
+  /// param color: The clear color, e.g. a zero alpha for the compositing.
+  pub fn set_clear_color(&mut self, color: [f32; 4]) {
+    self.clear_color = color;
+  }
+
+  /// Set the clear color of the albedo and the normal images of the G-Buffer, e.g. to debug the uncovered pixels.
+  /// The visibility buffer and the object ID image always clear to 0 which marks the background.
+  /// param color: The clear color.
+  pub fn set_gbuffer_clear_color(&mut self, color: [f32; 4]) {
+    self.gbuffer_clear_color = color;
+  }
+
+  /// Set the clear depth and stencil of the scene depth images.
+  /// The depth readback picking treats the clear depth as the background,
+  /// the depth pyramid and the SSR still expect the far plane of the depth convention.
+  /// param depth: The clear depth, 0 for the far plane of the default reverse Z convention.
+  /// param stencil: The clear stencil.
+  pub fn set_clear_depth_stencil(&mut self, depth: f32, stencil: u32) {
+    self.clear_depth_stencil = Some((depth, stencil));
+  }
+
+  /// Get the clear depth and stencil of the scene depth images.
+  /// return: The clear depth and stencil, the far plane of the depth convention and 0 if they are not set.
+  pub fn get_clear_depth_stencil(&self) -> (f32, u32) {
+    self.clear_depth_stencil.unwrap_or((self.depth_convention.get_clear_depth(), 0))
+  }
+
+  /// Set the views of the multi-viewport rendering, each view renders the scene with its camera into its viewport.
+  /// An empty list renders the render area or the full window with the No.1 camera.
+  /// param views: The views.
+  /// return: The result.
+  pub fn set_viewports(&mut self, views: &[HalaViewDesc]) -> Result<(), HalaRendererError> {
+    if let Some(scene) = self.scene_in_gpu.as_ref() {
+      for (view_index, view) in views.iter().enumerate() {
+        if view.camera_index as usize >= scene.camera_view_matrices.len() {
+          return Err(HalaRendererError::new(&format!("The camera index {} of the view {} is out of range!", view.camera_index, view_index), None));
+        }
+      }
+    }
+
+    self.views = views.to_vec();
+    self.create_view_resources()
+  }
+
+  /// Set the perspective parameters of the camera, the projection matrix is rebuilt.
+  /// param camera_index: The index of the camera.
+  /// param yfov: The vertical field of view in radians.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance.
+  /// return: The result.
+  pub fn set_camera_perspective(&mut self, camera_index: usize, yfov: f32, znear: f32, zfar: f32) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_perspective(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      yfov,
+      znear,
+      zfar,
+    )?;
+
+    Ok(())
+  }
+
+  /// Override the view and the projection matrices of the first view, e.g. from the camera system of the app.
+  /// They replace the scene camera in the global and the object uniforms, the level of detail selection and the picking from the next update.
+  /// The projection must follow the depth convention of the renderer, the camera buffer of the scene is not changed.
+  /// param v_mtx: The view matrix.
+  /// param p_mtx: The projection matrix.
+  pub fn set_view_projection(&mut self, v_mtx: glam::Mat4, p_mtx: glam::Mat4) {
+    self.view_projection_override = Some((v_mtx, p_mtx));
+  }
+
+  /// Clear the override of set_view_projection, the first view uses its scene camera again.
+  pub fn clear_view_projection(&mut self) {
+    self.view_projection_override = None;
+  }
+
+  /// Set the near and far planes of the camera, the projection matrix is rebuilt and used from the next update.
+  /// The perspective projection of the reverse Z convention is infinite, so only its near plane is used.
+  /// param camera_index: The index of the camera.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance.
+  /// return: The result.
+  pub fn set_camera_clip_planes(&mut self, camera_index: usize, znear: f32, zfar: f32) -> Result<(), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    scene.set_camera_clip_planes(camera_index, znear, zfar)
+  }
+
+  /// Fit the near and far planes of the camera to the scene bounds from the camera position,
+  /// it replaces the extreme planes of the imported cameras(e.g. 0.01 and 10000) which waste the depth precision.
+  /// Call it again after the camera or the scene moves.
+  /// param camera_index: The index of the camera.
+  /// return: The fitted near and far planes.
+  pub fn auto_fit_clip_planes(&mut self, camera_index: usize) -> Result<(f32, f32), HalaRendererError> {
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    scene.auto_fit_camera_clip_planes(camera_index)
+  }
+
+  /// Set the view matrix of the camera, e.g. from HalaOrbitController or HalaFlyController.
+  /// It waits the device idle to update the camera buffer.
+  /// param camera_index: The index of the camera.
+  /// param v_mtx: The view matrix.
+  /// return: The result.
+  pub fn set_view_matrix(&mut self, camera_index: usize, v_mtx: glam::Mat4) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_view_matrix(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      v_mtx,
+    )?;
+
+    Ok(())
+  }
+
+  /// Set the aperture and the focal distance of the camera.
+  /// param camera_index: The index of the camera.
+  /// param aperture: The aperture, 0 disables the depth of field.
+  /// param focal_distance: The focal distance.
+  /// return: The result.
+  pub fn set_camera_aperture_focal(&mut self, camera_index: usize, aperture: f32, focal_distance: f32) -> Result<(), HalaRendererError> {
+    // Make sure the camera buffer is not used by any in-flight frame.
+    self.wait_idle()?;
+
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    loader::HalaSceneGPUUploader::set_camera_aperture_focal(
+      &self.resources.transfer_command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      camera_index,
+      aperture,
+      focal_distance,
+    )?;
+
+    Ok(())
+  }
+
+  /// Reload a texture image of the scene from the file.
+  /// The replaced image is retired, so the in-flight frames can still read it.
+  /// param image_index: The index of the image in the scene.
+  /// param path: The file path of the new image.
+  /// return: The result.
+  pub fn reload_texture_from_file(&mut self, image_index: usize, path: &Path) -> Result<(), HalaRendererError> {
+    let image_data = cpu::image_data::HalaImageData::new_with_file(path)?;
+
+    let context = self.resources.context.borrow();
+    let command_buffers = hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "reload_texture.command_buffers",
+    )?;
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let old_image = loader::HalaSceneGPUUploader::reload_image(
+      &context,
+      &command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+      image_index,
+      &image_data,
+    )?;
+    drop(context);
+
+    self.resources.retire(Box::new(old_image));
+    self.textures_descriptor_dirty.fill(true);
+
+    log::info!("Reload the texture {} from file: {:?}", image_index, path);
+    Ok(())
+  }
+
+  /// Upload the next pending texture of the scene and replace its placeholder.
+  /// The placeholder is retired, so the in-flight frames can still read it.
+  /// return: The result.
+  fn stream_next_texture(&mut self) -> Result<(), HalaRendererError> {
+    if !matches!(self.scene_in_gpu.as_ref(), Some(scene) if scene.has_pending_images()) {
+      return Ok(());
+    }
+
+    let context = self.resources.context.borrow();
+    let command_buffers = hala_gfx::HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::GRAPHICS,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      "stream_texture.command_buffers",
+    )?;
+    let scene = self.scene_in_gpu.as_mut().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    let placeholder = loader::HalaSceneGPUUploader::stream_next_image(
+      &context,
+      &command_buffers,
+      &self.resources.transfer_staging_buffer,
+      scene,
+    )?;
+    drop(context);
+
+    if let Some(placeholder) = placeholder {
+      self.resources.retire(Box::new(placeholder));
+      self.textures_descriptor_dirty.fill(true);
+    }
+
+    Ok(())
+  }
+
+  /// Rewrite the descriptor entries of the textures of the frame with the current images of the scene if they are dirty.
+  /// The caller must make sure the frame is not in flight, it is called after the fence of the frame is signaled.
+  /// param index: The index of the frame.
+  /// return: The result.
+  fn update_textures_descriptor_set(&mut self, index: usize) -> Result<(), HalaRendererError> {
+    if !self.textures_descriptor_dirty.get(index).copied().unwrap_or(false) {
+      return Ok(());
+    }
+
+    let scene = self.scene_in_gpu.as_ref().ok_or(HalaRendererError::new("The scene in GPU is none!", None))?;
+    if let Some(textures_descriptor_set) = self.textures_descriptor_set.as_ref() {
+      let mut final_images = Vec::with_capacity(scene.textures.len());
+      for image_index in scene.textures.iter() {
+        final_images.push(scene.images.get(*image_index as usize).ok_or(HalaRendererError::new("The image is none!", None))?);
+      }
+      if !final_images.is_empty() {
+        textures_descriptor_set.update_sampled_images(index, 0, final_images.as_slice());
+      }
+    }
+    self.textures_descriptor_dirty[index] = false;
+
+    Ok(())
+  }
+
+  /// Reload all changed texture images reported by the texture watcher.
+  /// param watcher: The texture watcher.
+  /// return: The number of the reloaded textures.
+  pub fn reload_changed_textures(&mut self, watcher: &mut HalaTextureWatcher) -> Result<usize, HalaRendererError> {
+    let changed = watcher.poll();
+    for (image_index, path) in changed.iter() {
+      self.reload_texture_from_file(*image_index, path)?;
+    }
+
+    Ok(changed.len())
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 2 materials, the material 0 is an opaque deferred type 0, the material 1 is a translucent forward type 1.
+  const MATERIAL_TYPES: [u32; 2] = [0, 1];
+  const MATERIAL_DEFERRED_FLAGS: [bool; 2] = [true, false];
+  /// The mesh index, the primitive index and the material index of the test scene.
+  const PRIMITIVES: [(usize, usize, usize); 4] = [(0, 0, 0), (0, 1, 1), (1, 0, 1), (2, 0, 0)];
+
+  /// Collect the draws of a pass.
+  /// param draw_groups: The draw groups of both passes.
+  /// param pass: The pass.
+  /// return: The material type, the mesh index, the primitive index and the draw index of the draws.
+  fn collect_draws(draw_groups: &[Vec<HalaDrawGroup>; 2], pass: HalaMaterialPass) -> Vec<(usize, usize, usize, u32)> {
+    draw_groups[pass.to_u8() as usize].iter()
+      .flat_map(|group| group.draws.iter().map(move |&(mesh_index, primitive_index, draw_index)| (group.material_type, mesh_index, primitive_index, draw_index)))
+      .collect()
+  }
+
+  #[test]
+  fn test_material_pass_routing() {
+    assert_eq!(HalaMaterialPass::from_material(true, true), HalaMaterialPass::DEFERRED);
+    assert_eq!(HalaMaterialPass::from_material(true, false), HalaMaterialPass::FORWARD);
+    assert_eq!(HalaMaterialPass::from_material(false, true), HalaMaterialPass::FORWARD);
+    assert_eq!(HalaMaterialPass::from_material(false, false), HalaMaterialPass::FORWARD);
+  }
+
+  #[test]
+  fn test_group_draws_deferred() {
+    let draw_groups = HalaRenderer::group_draws(true, &PRIMITIVES, &MATERIAL_TYPES, &MATERIAL_DEFERRED_FLAGS, 2).unwrap();
+
+    // The deferred material is only in the deferred pass and the forward material is only in the forward pass.
+    assert_eq!(collect_draws(&draw_groups, HalaMaterialPass::DEFERRED), [(0, 0, 0, 0), (0, 2, 0, 3)]);
+    assert_eq!(collect_draws(&draw_groups, HalaMaterialPass::FORWARD), [(1, 0, 1, 1), (1, 1, 0, 2)]);
+
+    // Each primitive is drawn exactly once.
+    let mut draw_indices = draw_groups.iter().flatten().flat_map(|group| group.draws.iter().map(|draw| draw.2)).collect::<Vec<_>>();
+    draw_indices.sort();
+    assert_eq!(draw_indices, [0, 1, 2, 3]);
+  }
+
+  #[test]
+  fn test_group_draws_forward_only() {
+    let draw_groups = HalaRenderer::group_draws(false, &PRIMITIVES, &MATERIAL_TYPES, &MATERIAL_DEFERRED_FLAGS, 2).unwrap();
+
+    // Without the deferred rendering, all primitives are forward and grouped by the material type.
+    assert!(draw_groups[HalaMaterialPass::DEFERRED.to_u8() as usize].is_empty());
+    assert_eq!(
+      collect_draws(&draw_groups, HalaMaterialPass::FORWARD),
+      [(0, 0, 0, 0), (0, 2, 0, 3), (1, 0, 1, 1), (1, 1, 0, 2)],
+    );
+  }
+
+  #[test]
+  fn test_group_draws_invalid_material_type() {
+    assert!(HalaRenderer::group_draws(true, &PRIMITIVES, &[0, 2], &MATERIAL_DEFERRED_FLAGS, 2).is_err());
+  }
+
+  const WIDTH: u32 = 64;
+  const HEIGHT: u32 = 32;
+
+  /// Create a small renderer with a hidden window.
+  /// param event_loop: The event loop of the window.
+  /// return: The window and the renderer, the renderer must be dropped before the window.
+  fn create_renderer(event_loop: &winit::event_loop::EventLoop<()>) -> (winit::window::Window, HalaRenderer) {
+    let window_attributes = winit::window::Window::default_attributes()
+      .with_title("device_lost_test")
+      .with_inner_size(winit::dpi::PhysicalSize::new(WIDTH, HEIGHT))
+      .with_visible(false);
+    #[allow(deprecated)]
+    let window = event_loop.create_window(window_attributes).unwrap();
+    let gpu_req = HalaGPURequirements {
+      width: WIDTH,
+      height: HEIGHT,
+      ..Default::default()
+    };
+    let renderer = HalaRenderer::new("device_lost_test", &gpu_req, &window).unwrap();
+
+    (window, renderer)
+  }
+
+  #[test]
+  #[ignore = "requires a GPU and a display"]
+  fn test_device_lost_recovery() {
+    use winit::platform::x11::EventLoopBuilderExtX11;
+
+    let event_loop = winit::event_loop::EventLoop::builder().with_any_thread(true).build().unwrap();
+    let (_window, mut renderer) = create_renderer(&event_loop);
+
+    // The G-Buffer, the deferred framebuffers and the multisample images are the size dependent resources.
+    renderer.create_gbuffer_resources(false, hala_gfx::HalaFormat::R8G8B8A8_UNORM, GBUFFER_PACKED_NORMAL_FORMAT, None, false).unwrap();
+    renderer.create_deferred_render_pass().unwrap();
+    renderer.create_deferred_framebuffers().unwrap();
+    let sample_count = renderer.get_max_sample_count();
+    renderer.create_multisample_images(sample_count).unwrap();
+
+    renderer.simulate_device_lost();
+    assert!(renderer.data.is_device_lost);
+
+    // The frame is skipped while the device is lost.
+    renderer.render().unwrap();
+    assert!(renderer.data.is_device_lost);
+
+    // The next frame update restores the device and recreates the size dependent resources.
+    renderer.pre_update(WIDTH, HEIGHT).unwrap();
+    assert!(!renderer.data.is_device_lost);
+    assert_eq!(renderer.statistics.total_frames, 1);
+    assert_eq!((renderer.info.width, renderer.info.height), (WIDTH, HEIGHT));
+    for image in renderer.get_gbuffer_color_images().unwrap() {
+      assert_eq!((image.extent.width, image.extent.height), (WIDTH, HEIGHT));
+    }
+    let depth_image = renderer.depth_image.as_ref().unwrap();
+    assert_eq!((depth_image.extent.width, depth_image.extent.height), (WIDTH, HEIGHT));
+    for image in [renderer.color_multisample_image.as_ref().unwrap(), renderer.depth_stencil_multisample_image.as_ref().unwrap()] {
+      assert_eq!((image.extent.width, image.extent.height), (WIDTH, HEIGHT));
+    }
+    // The deferred framebuffers are created with the swapchain extent.
+    assert!(renderer.deferred_framebuffers.is_some());
+    let dims = renderer.resources.context.borrow().swapchain.dims;
+    assert_eq!((dims.width, dims.height), (WIDTH, HEIGHT));
+
+    renderer.wait_idle().unwrap();
+  }
+
+}
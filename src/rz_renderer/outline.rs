@@ -0,0 +1,129 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+  HalaStencilState,
+};
+
+use crate::error::HalaRendererError;
+
+use super::post_process::load_vertex_fragment_shaders;
+
+/// The outline pass draws a full screen triangle strip after the selection pass, the stencil test selects the outline pixels.
+pub(crate) struct HalaOutlinePass {
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) stencil_state: Option<HalaStencilState>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) color: [f32; 4],
+}
+
+/// The Default implementation of the outline pass.
+impl Default for HalaOutlinePass {
+  fn default() -> Self {
+    Self {
+      shaders: Vec::new(),
+      stencil_state: None,
+      graphics_pipeline: None,
+      color: [1.0, 0.5, 0.0, 1.0],
+    }
+  }
+}
+
+/// The implementation of the outline pass.
+impl HalaOutlinePass {
+
+  /// Set the shaders and the stencil state of the outline pass.
+  /// param context: The GFX context.
+  /// param vertex_file_path: The vertex shader file path.
+  /// param fragment_file_path: The fragment shader file path.
+  /// param stencil_state: The stencil state of the outline pipeline.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    vertex_file_path: &str,
+    fragment_file_path: &str,
+    stencil_state: HalaStencilState,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    self.shaders = load_vertex_fragment_shaders(context, vertex_file_path, fragment_file_path, debug_name)?;
+    self.stencil_state = Some(stencil_state);
+
+    Ok(())
+  }
+
+  /// Create the graphics pipeline if the shaders are set, it is drawn after the selection pass with the same attachments.
+  /// param context: The GFX context.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    if self.shaders.is_empty() {
+      return Ok(());
+    }
+
+    let shaders = self.shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+    self.graphics_pipeline = Some(
+      HalaGraphicsPipeline::new(
+        Rc::clone(&context.logical_device),
+        &context.swapchain,
+        &[] as &[&hala_gfx::HalaDescriptorSetLayout],
+        hala_gfx::HalaPipelineCreateFlags::default(),
+        &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+        &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+        &[hala_gfx::HalaPushConstantRange {
+          stage_flags: hala_gfx::HalaShaderStageFlags::FRAGMENT,
+          offset: 0,
+          size: 16, // Outline color.
+        }],
+        hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+        &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+        self.stencil_state.as_ref(),
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(pipeline_cache),
+        "outline.graphics_pipeline",
+      )?
+    );
+
+    Ok(())
+  }
+
+  /// Draw the outline over the full window, the stencil test keeps the pixels where the stencil differs from the selection.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> u64 {
+    let graphics_pipeline = match self.graphics_pipeline.as_ref() {
+      Some(graphics_pipeline) => graphics_pipeline,
+      None => return 0,
+    };
+
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.push_constants(
+      index,
+      graphics_pipeline.layout,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      0,
+      &self.color.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>(),
+    );
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    1
+  }
+
+}
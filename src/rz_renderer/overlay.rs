@@ -0,0 +1,362 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::scene::gpu;
+
+use super::post_process::load_vertex_fragment_shaders;
+use super::{
+  HalaOverlayView,
+  HalaScenePipelineLayout,
+  set_view_viewport,
+};
+
+/// The overlay pass draws the wireframe and the normal lines of the scene on top of the shaded image in the forward pass.
+/// The wireframe shaders are the same stages as the selection shaders, the primitives are rasterized as lines.
+/// The normal line shaders are Vertex and Fragment Shader, each vertex of the scene is one instance of a two points line,
+/// the vertex shader moves the second point along the normal by the length in the push constants.
+pub(crate) struct HalaOverlayPass {
+  pub(crate) wireframe_shaders: Vec<HalaShader>,
+  pub(crate) wireframe_graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) normal_line_shaders: Vec<HalaShader>,
+  pub(crate) normal_line_graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) wireframe: bool,
+  pub(crate) normals: bool,
+  pub(crate) normal_length: f32,
+}
+
+/// The Default implementation of the overlay pass.
+impl Default for HalaOverlayPass {
+  fn default() -> Self {
+    Self {
+      wireframe_shaders: Vec::new(),
+      wireframe_graphics_pipeline: None,
+      normal_line_shaders: Vec::new(),
+      normal_line_graphics_pipeline: None,
+      wireframe: false,
+      normals: false,
+      normal_length: 0.1,
+    }
+  }
+}
+
+/// The implementation of the overlay pass.
+impl HalaOverlayPass {
+
+  /// Set the shaders of the wireframe and the normal lines.
+  /// param context: The GFX context.
+  /// param use_mesh_shader: Whether the scene is drawn by the mesh shader.
+  /// param wireframe_task_file_path: The task shader file path of the wireframe, only for mesh shader.
+  /// param wireframe_vertex_or_mesh_file_path: The vertex shader file path of the wireframe, or the mesh shader file path for mesh shader.
+  /// param wireframe_fragment_file_path: The fragment shader file path of the wireframe.
+  /// param normal_line_vertex_file_path: The vertex shader file path of the normal lines.
+  /// param normal_line_fragment_file_path: The fragment shader file path of the normal lines.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    use_mesh_shader: bool,
+    wireframe_task_file_path: Option<&str>,
+    wireframe_vertex_or_mesh_file_path: &str,
+    wireframe_fragment_file_path: &str,
+    normal_line_vertex_file_path: &str,
+    normal_line_fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if !use_mesh_shader && wireframe_task_file_path.is_some() {
+      return Err(HalaRendererError::new("The task shader is only supported by the mesh shader!", None));
+    }
+
+    let mut wireframe_shaders = Vec::with_capacity(3);
+    if let Some(file_path) = wireframe_task_file_path {
+      wireframe_shaders.push(HalaShader::with_file(
+        Rc::clone(&context.logical_device),
+        file_path,
+        hala_gfx::HalaShaderStageFlags::TASK,
+        hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+        &format!("{}_wireframe.task", debug_name),
+      )?);
+    }
+    let (stage, extension) = if use_mesh_shader {
+      (hala_gfx::HalaShaderStageFlags::MESH, "mesh")
+    } else {
+      (hala_gfx::HalaShaderStageFlags::VERTEX, "vert")
+    };
+    wireframe_shaders.push(HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      wireframe_vertex_or_mesh_file_path,
+      stage,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}_wireframe.{}", debug_name, extension),
+    )?);
+    wireframe_shaders.push(HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      wireframe_fragment_file_path,
+      hala_gfx::HalaShaderStageFlags::FRAGMENT,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}_wireframe.frag", debug_name),
+    )?);
+
+    let normal_line_shaders = load_vertex_fragment_shaders(
+      context,
+      normal_line_vertex_file_path,
+      normal_line_fragment_file_path,
+      &format!("{}_normal_line", debug_name),
+    )?;
+
+    self.wireframe_shaders = wireframe_shaders;
+    self.normal_line_shaders = normal_line_shaders;
+
+    Ok(())
+  }
+
+  /// Set what the overlay draws.
+  /// param wireframe: Draw the wireframe of the scene or not.
+  /// param normals: Draw the normal lines of the vertices or not.
+  /// param length: The length of the normal lines in the object space.
+  /// return: The result.
+  pub(crate) fn set(&mut self, wireframe: bool, normals: bool, length: f32) -> Result<(), HalaRendererError> {
+    if !length.is_finite() || length < 0.0 {
+      return Err(HalaRendererError::new(&format!("The normal line length {} is invalid!", length), None));
+    }
+    if (wireframe || normals) && (self.wireframe_shaders.is_empty() || self.normal_line_shaders.is_empty()) {
+      return Err(HalaRendererError::new("The overlay shaders are none! Call set_overlay_shaders_with_file first.", None));
+    }
+
+    self.wireframe = wireframe;
+    self.normals = normals;
+    self.normal_length = length;
+
+    Ok(())
+  }
+
+  /// Create the graphics pipelines if the shaders are set, the lines are tested against the scene depth but do not write it.
+  /// param context: The GFX context.
+  /// param layout: The pipeline layout and the vertex input of the scene graphics pipelines.
+  /// param overlay_compare_op: The depth compare operation of the overlays, the equal depth passes,
+  /// so the wireframe is not hidden by the surface it is drawn from.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    layout: &HalaScenePipelineLayout,
+    overlay_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    if !self.wireframe_shaders.is_empty() {
+      let wireframe_shaders = self.wireframe_shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+      self.wireframe_graphics_pipeline = Some(
+        HalaGraphicsPipeline::new(
+          Rc::clone(&context.logical_device),
+          &context.swapchain,
+          &layout.descriptor_set_layouts,
+          layout.flags,
+          layout.vertex_attribute_descriptions,
+          layout.vertex_binding_descriptions,
+          layout.push_constant_ranges,
+          hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::LINE, 1.0),
+          &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+          &hala_gfx::HalaDepthState::new(true, false, overlay_compare_op),
+          None,
+          wireframe_shaders.as_slice(),
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(pipeline_cache),
+          "wireframe.graphics_pipeline",
+        )?
+      );
+    }
+
+    if !self.normal_line_shaders.is_empty() {
+      let normal_line_shaders = self.normal_line_shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+      // The vertices are read per instance, the line points are selected by the vertex index.
+      let normal_line_binding_descriptions = layout.vertex_binding_descriptions.iter()
+        .map(|description| hala_gfx::HalaVertexInputBindingDescription {
+          binding: description.binding,
+          stride: description.stride,
+          input_rate: hala_gfx::HalaVertexInputRate::INSTANCE,
+        })
+        .collect::<Vec<_>>();
+      self.normal_line_graphics_pipeline = Some(
+        HalaGraphicsPipeline::new(
+          Rc::clone(&context.logical_device),
+          &context.swapchain,
+          &layout.descriptor_set_layouts,
+          layout.flags,
+          layout.vertex_attribute_descriptions,
+          normal_line_binding_descriptions.as_slice(),
+          &[hala_gfx::HalaPushConstantRange {
+            stage_flags: hala_gfx::HalaShaderStageFlags::VERTEX | hala_gfx::HalaShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: 8, // Mesh index and line length.
+          }],
+          hala_gfx::HalaPrimitiveTopology::LINE_LIST,
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::SRC_ALPHA, hala_gfx::HalaBlendFactor::ONE_MINUS_SRC_ALPHA, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+          &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+          &hala_gfx::HalaMultisampleState::new(context.multisample_count, false, 0.0, &[], false, false),
+          &hala_gfx::HalaDepthState::new(true, false, overlay_compare_op),
+          None,
+          normal_line_shaders.as_slice(),
+          &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+          Some(pipeline_cache),
+          "normal_line.graphics_pipeline",
+        )?
+      );
+    }
+
+    Ok(())
+  }
+
+  /// Whether the overlay draws anything.
+  /// return: True if the wireframe or the normal lines are enabled and committed.
+  pub(crate) fn is_active(&self) -> bool {
+    (self.wireframe && self.wireframe_graphics_pipeline.is_some()) || (self.normals && self.normal_line_graphics_pipeline.is_some())
+  }
+
+  /// Get the push constants of the normal lines of a mesh.
+  /// param mesh_index: The mesh index.
+  /// return: The push constants, the mesh index and the line length.
+  fn get_normal_line_push_constants(&self, mesh_index: usize) -> Vec<u8> {
+    let mut push_constants = Vec::with_capacity(8);
+    push_constants.extend_from_slice(&(mesh_index as u32).to_le_bytes());
+    push_constants.extend_from_slice(&self.normal_length.to_le_bytes());
+    push_constants
+  }
+
+  /// Draw the wireframe and the normal lines of the scene for each view.
+  /// The primitives are drawn at the level 0, so the lines show the full resolution meshes.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param scene: The scene in GPU.
+  /// param views: The views.
+  /// param textures_descriptor_set: The textures descriptor set.
+  /// param is_mesh_visible: Whether the mesh of the index is visible.
+  /// param draw_primitive: Draw a primitive(mesh index, primitive, draw index) at the level 0 with the pipeline.
+  /// return: The pipeline bind count.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn draw(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    scene: &gpu::HalaScene,
+    views: &[HalaOverlayView],
+    textures_descriptor_set: &HalaDescriptorSet,
+    is_mesh_visible: &dyn Fn(usize) -> bool,
+    draw_primitive: &dyn Fn(&HalaGraphicsPipeline, usize, &gpu::HalaPrimitive, u32) -> Result<(), HalaRendererError>,
+  ) -> Result<u64, HalaRendererError> {
+    let wireframe_graphics_pipeline = self.wireframe_graphics_pipeline.as_ref().filter(|_| self.wireframe);
+    let normal_line_graphics_pipeline = self.normal_line_graphics_pipeline.as_ref().filter(|_| self.normals);
+    if wireframe_graphics_pipeline.is_none() && normal_line_graphics_pipeline.is_none() {
+      return Ok(0);
+    }
+
+    let mut num_of_pipeline_binds = 0u64;
+    for view in views.iter() {
+      set_view_viewport(index, command_buffers, &view.view);
+
+      if let Some(graphics_pipeline) = wireframe_graphics_pipeline {
+        command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+        command_buffers.bind_graphics_descriptor_sets(
+          index,
+          graphics_pipeline,
+          0,
+          &[view.static_descriptor_set, view.dynamic_descriptor_set, textures_descriptor_set],
+          &[],
+        );
+        num_of_pipeline_binds += 1;
+
+        let mut draw_index = 0u32;
+        for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+          for primitive in mesh.primitives.iter() {
+            if is_mesh_visible(mesh_index) {
+              draw_primitive(graphics_pipeline, mesh_index, primitive, draw_index)?;
+            }
+            draw_index += 1;
+          }
+        }
+      }
+
+      if let Some(graphics_pipeline) = normal_line_graphics_pipeline {
+        command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+        command_buffers.bind_graphics_descriptor_sets(
+          index,
+          graphics_pipeline,
+          0,
+          &[view.static_descriptor_set, view.dynamic_descriptor_set, textures_descriptor_set],
+          &[],
+        );
+        num_of_pipeline_binds += 1;
+
+        for (mesh_index, mesh) in scene.meshes.iter().enumerate() {
+          for primitive in mesh.primitives.iter() {
+            if primitive.vertex_count == 0 || !is_mesh_visible(mesh_index) {
+              continue;
+            }
+            command_buffers.push_constants(
+              index,
+              graphics_pipeline.layout,
+              hala_gfx::HalaShaderStageFlags::VERTEX | hala_gfx::HalaShaderStageFlags::FRAGMENT,
+              0,
+              self.get_normal_line_push_constants(mesh_index).as_slice(),
+            );
+            command_buffers.bind_vertex_buffers(
+              index,
+              0,
+              &[scene.get_vertex_buffer(primitive).ok_or(HalaRendererError::new("The vertex buffer is none!", None))?],
+              &[0]);
+            // The instances start at the vertex offset of the primitive in the vertex buffer.
+            command_buffers.draw(index, 2, primitive.vertex_count, 0, primitive.vertex_offset);
+          }
+        }
+      }
+    }
+
+    Ok(num_of_pipeline_binds)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_overlay_set() {
+    let mut pass = HalaOverlayPass::default();
+    assert!(pass.set(false, false, 0.5).is_ok());
+    assert_eq!(pass.normal_length, 0.5);
+    assert!(pass.set(false, false, -1.0).is_err());
+    assert!(pass.set(false, false, f32::NAN).is_err());
+    // The shaders are required to enable the overlay.
+    assert!(pass.set(true, false, 0.1).is_err());
+    assert!(!pass.wireframe);
+    assert!(!pass.is_active());
+  }
+
+  #[test]
+  fn test_normal_line_push_constants() {
+    let pass = HalaOverlayPass {
+      normal_length: 0.25,
+      ..Default::default()
+    };
+    let push_constants = pass.get_normal_line_push_constants(3);
+    assert_eq!(push_constants.len(), 8);
+    assert_eq!(u32::from_le_bytes(push_constants[0..4].try_into().unwrap()), 3);
+    assert_eq!(f32::from_le_bytes(push_constants[4..8].try_into().unwrap()), 0.25);
+  }
+
+}
@@ -0,0 +1,405 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorSetLayout,
+  HalaGraphicsPipeline,
+  HalaImage,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+
+/// The stages of the post-process chain in the recording order.
+/// The scene renders into the target of the first active pass, each pass reads its target
+/// and renders into the target of the next active pass, the last one renders into the swapchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum HalaPostProcessStage {
+  Scene,
+  SSR,
+  TAA,
+  Bloom,
+  FXAA,
+  ColorGrading,
+  Lens,
+}
+
+/// The color and depth images of a post-process pass, the scene or the previous pass renders into them.
+/// The images use the swapchain formats, so the scene and the post-process pipelines can render into them.
+pub(crate) struct HalaPostProcessTarget {
+  pub(crate) color_image: HalaImage,
+  pub(crate) depth_image: HalaImage,
+  // The stages which read the target after the rendering, the next rendering into it waits for them.
+  pub(crate) read_stage_mask: hala_gfx::HalaPipelineStageFlags2,
+  // Whether the depth is stored and sampled after the rendering.
+  pub(crate) is_depth_read: bool,
+}
+
+/// The implementation of the post-process target.
+impl HalaPostProcessTarget {
+
+  /// Create a new post-process target.
+  /// param context: The GFX context.
+  /// param width: The width of the images.
+  /// param height: The height of the images.
+  /// param read_stage_mask: The stages which read the target after the rendering.
+  /// param is_depth_read: Whether the depth is stored and sampled after the rendering.
+  /// param debug_name: The debug name of the images.
+  /// return: The target.
+  pub(crate) fn new(
+    context: &HalaContext,
+    width: u32,
+    height: u32,
+    read_stage_mask: hala_gfx::HalaPipelineStageFlags2,
+    is_depth_read: bool,
+    debug_name: &str,
+  ) -> Result<Self, HalaRendererError> {
+    let color_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED,
+      context.swapchain.format,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      &format!("{}_color.image", debug_name),
+    )?;
+    let depth_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      if is_depth_read {
+        hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED
+      } else {
+        hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+      },
+      context.swapchain.depth_stencil_format,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      &format!("{}_depth.image", debug_name),
+    )?;
+
+    Ok(Self {
+      color_image,
+      depth_image,
+      read_stage_mask,
+      is_depth_read,
+    })
+  }
+
+  /// Begin the rendering into the color and depth images.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param context: The GFX context.
+  /// param width: The width of the render area.
+  /// param height: The height of the render area.
+  /// param clear_color: The clear color.
+  /// param clear_depth_stencil: The clear depth and stencil.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn begin_rendering(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    context: &HalaContext,
+    width: u32,
+    height: u32,
+    clear_color: [f32; 4],
+    clear_depth_stencil: (f32, u32),
+  ) {
+    begin_post_process_rendering(
+      index,
+      command_buffers,
+      context,
+      &self.color_image,
+      &self.depth_image,
+      self.read_stage_mask,
+      self.is_depth_read,
+      width,
+      height,
+      clear_color,
+      clear_depth_stencil,
+    );
+  }
+
+  /// Transition the color image to be read by the read stages, the rendering into it must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  pub(crate) fn set_read_barrier(&self, index: usize, command_buffers: &HalaCommandBufferSet) {
+    command_buffers.set_image_barriers(index, &[self.get_read_barrier()]);
+  }
+
+  /// Get the barrier which transitions the color image to be read by the read stages.
+  /// return: The barrier.
+  pub(crate) fn get_read_barrier(&self) -> hala_gfx::HalaImageBarrierInfo {
+    hala_gfx::HalaImageBarrierInfo {
+      old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+      new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+      dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+      dst_stage_mask: self.read_stage_mask,
+      aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+      image: self.color_image.raw,
+      ..Default::default()
+    }
+  }
+
+}
+
+/// Begin the rendering into a color and a depth image which are read after the rendering.
+/// The color is stored, the depth is stored only if it is read.
+/// param index: The index of the current image.
+/// param command_buffers: The command buffers.
+/// param context: The GFX context.
+/// param color_image: The color image.
+/// param depth_image: The depth image.
+/// param read_stage_mask: The stages which read the images after the previous rendering.
+/// param is_depth_read: Whether the depth is stored and sampled after the rendering.
+/// param width: The width of the render area.
+/// param height: The height of the render area.
+/// param clear_color: The clear color.
+/// param clear_depth_stencil: The clear depth and stencil.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn begin_post_process_rendering(
+  index: usize,
+  command_buffers: &HalaCommandBufferSet,
+  context: &HalaContext,
+  color_image: &HalaImage,
+  depth_image: &HalaImage,
+  read_stage_mask: hala_gfx::HalaPipelineStageFlags2,
+  is_depth_read: bool,
+  width: u32,
+  height: u32,
+  clear_color: [f32; 4],
+  clear_depth_stencil: (f32, u32),
+) {
+  let (depth_src_access_mask, depth_src_stage_mask) = if is_depth_read {
+    (hala_gfx::HalaAccessFlags2::SHADER_READ, read_stage_mask)
+  } else {
+    (
+      hala_gfx::HalaAccessFlags2::NONE,
+      hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+    )
+  };
+  command_buffers.set_image_barriers(
+    index,
+    &[
+      hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        src_stage_mask: read_stage_mask,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: color_image.raw,
+        ..Default::default()
+      },
+      hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        src_access_mask: depth_src_access_mask,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        src_stage_mask: depth_src_stage_mask,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+        image: depth_image.raw,
+        ..Default::default()
+      },
+    ],
+  );
+
+  command_buffers.begin_rendering_with(
+    index,
+    &[color_image],
+    Some(depth_image),
+    (0, 0, width, height),
+    &[Some(clear_color)],
+    Some(clear_depth_stencil.0),
+    Some(clear_depth_stencil.1),
+    hala_gfx::HalaAttachmentStoreOp::STORE,
+    if is_depth_read { hala_gfx::HalaAttachmentStoreOp::STORE } else { hala_gfx::HalaAttachmentStoreOp::DONT_CARE },
+    hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+  );
+}
+
+/// Set the barriers of the swapchain color and depth images before the rendering into them.
+/// param index: The index of the current image.
+/// param command_buffers: The command buffers.
+/// param context: The GFX context.
+pub(crate) fn set_swapchain_attachment_barriers(index: usize, command_buffers: &HalaCommandBufferSet, context: &HalaContext) {
+  command_buffers.set_swapchain_image_barrier(
+    index,
+    &context.swapchain,
+    &hala_gfx::HalaImageBarrierInfo {
+      old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+      new_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+      src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+      dst_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags2::TOP_OF_PIPE,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+      aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+      ..Default::default()
+    },
+    &hala_gfx::HalaImageBarrierInfo {
+      old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+      new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+      src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+      dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+      aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH | if context.swapchain.has_stencil { hala_gfx::HalaImageAspectFlags::STENCIL } else { hala_gfx::HalaImageAspectFlags::empty() },
+      ..Default::default()
+    }
+  );
+}
+
+/// Set the barriers of the swapchain images and begin the rendering into them.
+/// param index: The index of the current image.
+/// param command_buffers: The command buffers.
+/// param context: The GFX context.
+/// param width: The width of the render area.
+/// param height: The height of the render area.
+/// param clear_color: The clear color, none to keep the color.
+/// param clear_depth: The clear depth, none to keep the depth.
+/// param clear_stencil: The clear stencil, none to keep the stencil.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn begin_swapchain_rendering(
+  index: usize,
+  command_buffers: &HalaCommandBufferSet,
+  context: &HalaContext,
+  width: u32,
+  height: u32,
+  clear_color: Option<[f32; 4]>,
+  clear_depth: Option<f32>,
+  clear_stencil: Option<u32>,
+) {
+  set_swapchain_attachment_barriers(index, command_buffers, context);
+  command_buffers.begin_rendering_with_swapchain(
+    index,
+    &context.swapchain,
+    (0, 0, width, height),
+    clear_color,
+    clear_depth,
+    clear_stencil,
+  );
+}
+
+/// Load the vertex and the fragment shaders of a pass.
+/// param context: The GFX context.
+/// param vertex_file_path: The vertex shader file path.
+/// param fragment_file_path: The fragment shader file path.
+/// param debug_name: The debug name of the shaders.
+/// return: The vertex and the fragment shaders.
+pub(crate) fn load_vertex_fragment_shaders(
+  context: &HalaContext,
+  vertex_file_path: &str,
+  fragment_file_path: &str,
+  debug_name: &str,
+) -> Result<Vec<HalaShader>, HalaRendererError> {
+  let vertex_shader = HalaShader::with_file(
+    Rc::clone(&context.logical_device),
+    vertex_file_path,
+    hala_gfx::HalaShaderStageFlags::VERTEX,
+    hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+    &format!("{}.vert", debug_name),
+  )?;
+  let fragment_shader = HalaShader::with_file(
+    Rc::clone(&context.logical_device),
+    fragment_file_path,
+    hala_gfx::HalaShaderStageFlags::FRAGMENT,
+    hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+    &format!("{}.frag", debug_name),
+  )?;
+
+  Ok(vec![vertex_shader, fragment_shader])
+}
+
+/// Create the graphics pipeline of a full screen pass.
+/// The pass draws a triangle strip of 4 vertices without the vertex input, the blending, the culling and the depth test,
+/// the viewport and the scissor are dynamic.
+/// param context: The GFX context.
+/// param descriptor_set_layouts: The descriptor set layouts.
+/// param push_constant_ranges: The push constant ranges.
+/// param shaders: The vertex and the fragment shaders.
+/// param depth_compare_op: The depth compare operation of the renderer.
+/// param pipeline_cache: The pipeline cache.
+/// param debug_name: The debug name of the pipeline.
+/// return: The graphics pipeline.
+pub(crate) fn create_full_screen_graphics_pipeline(
+  context: &HalaContext,
+  descriptor_set_layouts: &[&HalaDescriptorSetLayout],
+  push_constant_ranges: &[hala_gfx::HalaPushConstantRange],
+  shaders: &[HalaShader],
+  depth_compare_op: hala_gfx::HalaCompareOp,
+  pipeline_cache: &HalaPipelineCache,
+  debug_name: &str,
+) -> Result<HalaGraphicsPipeline, HalaRendererError> {
+  let shaders = shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+  let graphics_pipeline = HalaGraphicsPipeline::new(
+    Rc::clone(&context.logical_device),
+    &context.swapchain,
+    descriptor_set_layouts,
+    hala_gfx::HalaPipelineCreateFlags::default(),
+    &[] as &[hala_gfx::HalaVertexInputAttributeDescription],
+    &[] as &[hala_gfx::HalaVertexInputBindingDescription],
+    push_constant_ranges,
+    hala_gfx::HalaPrimitiveTopology::TRIANGLE_STRIP,
+    &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+    &hala_gfx::HalaBlendState::new(hala_gfx::HalaBlendFactor::ONE, hala_gfx::HalaBlendFactor::ZERO, hala_gfx::HalaBlendOp::ADD),
+    &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+    &hala_gfx::HalaMultisampleState::default(),
+    &hala_gfx::HalaDepthState::new(false, false, depth_compare_op),
+    None,
+    shaders.as_slice(),
+    &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+    Some(pipeline_cache),
+    &format!("{}.graphics_pipeline", debug_name),
+  )?;
+
+  Ok(graphics_pipeline)
+}
+
+/// Create a static descriptor set layout binding of a post-process pass.
+/// param binding_index: The binding index.
+/// param descriptor_type: The descriptor type.
+/// param stage_flags: The shader stages.
+/// return: The binding.
+pub(crate) fn post_process_binding(
+  binding_index: u32,
+  descriptor_type: hala_gfx::HalaDescriptorType,
+  stage_flags: hala_gfx::HalaShaderStageFlags,
+) -> hala_gfx::HalaDescriptorSetLayoutBinding {
+  hala_gfx::HalaDescriptorSetLayoutBinding {
+    binding_index,
+    descriptor_type,
+    descriptor_count: 1,
+    stage_flags,
+    binding_flags: hala_gfx::HalaDescriptorBindingFlags::PARTIALLY_BOUND
+  }
+}
+
+/// Bind the graphics pipeline and the descriptor set of a full screen pass, the push constants and the draw follow.
+/// param index: The index of the current image.
+/// param command_buffers: The command buffers.
+/// param graphics_pipeline: The graphics pipeline.
+/// param descriptor_set: The descriptor set at the set 0.
+pub(crate) fn bind_full_screen_pipeline(
+  index: usize,
+  command_buffers: &HalaCommandBufferSet,
+  graphics_pipeline: &HalaGraphicsPipeline,
+  descriptor_set: &hala_gfx::HalaDescriptorSet,
+) {
+  command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+  command_buffers.bind_graphics_descriptor_sets(
+    index,
+    graphics_pipeline,
+    0,
+    &[descriptor_set],
+    &[],
+  );
+}
@@ -0,0 +1,373 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::scene::{
+  gpu,
+  HalaDepthConvention,
+};
+use crate::shadow::{
+  HalaShadowConfig,
+  HalaShadowAtlas,
+};
+
+use super::{
+  HalaScenePipelineLayout,
+  HalaViewDesc,
+  set_view_viewport,
+};
+
+/// The shadow pass renders the depth of the lights into the tiles of the shadow atlas before the scene.
+/// The depth only pipeline shares the layout of the scene pipelines, the tile index follows the draw push constants.
+/// The atlas of the disabled shadows is kept with no tile, the static descriptor sets may still reference it.
+pub(crate) struct HalaShadowPass {
+  pub(crate) config: Option<HalaShadowConfig>,
+  pub(crate) shaders: Vec<HalaShader>,
+  pub(crate) graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) atlas: Option<HalaShadowAtlas>,
+}
+
+/// The Default implementation of the shadow pass.
+impl Default for HalaShadowPass {
+  fn default() -> Self {
+    Self {
+      config: None,
+      shaders: Vec::new(),
+      graphics_pipeline: None,
+      atlas: None,
+    }
+  }
+}
+
+/// The implementation of the shadow pass.
+impl HalaShadowPass {
+
+  /// Check the shadow config.
+  /// param config: The shadow config.
+  /// return: The result.
+  pub(crate) fn check_config(config: &HalaShadowConfig) -> Result<(), HalaRendererError> {
+    if config.tile_size == 0 || config.atlas_size < config.tile_size || config.atlas_size % config.tile_size != 0 {
+      return Err(HalaRendererError::new(
+        &format!("The shadow atlas size {} must be a multiple of the tile size {}!", config.atlas_size, config.tile_size), None));
+    }
+    if config.near_plane <= 0.0 {
+      return Err(HalaRendererError::new(&format!("The shadow near plane {} must be positive!", config.near_plane), None));
+    }
+    HalaShadowAtlas::check_soft_shadow_samples(config.blocker_search_samples, config.filter_samples)?;
+
+    Ok(())
+  }
+
+  /// Set the shaders of the shadow pass.
+  /// param context: The GFX context.
+  /// param use_mesh_shader: Whether the scene is drawn by the mesh shader.
+  /// param task_file_path: The task shader file path, only for mesh shader.
+  /// param vertex_or_mesh_file_path: The vertex shader file path, or the mesh shader file path for mesh shader.
+  /// param fragment_file_path: The optional fragment shader file path.
+  /// param debug_name: The debug name of the shader.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    use_mesh_shader: bool,
+    task_file_path: Option<&str>,
+    vertex_or_mesh_file_path: &str,
+    fragment_file_path: Option<&str>,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if !use_mesh_shader && task_file_path.is_some() {
+      return Err(HalaRendererError::new("The task shader is only supported by the mesh shader!", None));
+    }
+
+    let mut shaders = Vec::with_capacity(3);
+    if let Some(file_path) = task_file_path {
+      shaders.push(HalaShader::with_file(
+        Rc::clone(&context.logical_device),
+        file_path,
+        hala_gfx::HalaShaderStageFlags::TASK,
+        hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+        &format!("{}.task", debug_name),
+      )?);
+    }
+    let (stage, extension) = if use_mesh_shader {
+      (hala_gfx::HalaShaderStageFlags::MESH, "mesh")
+    } else {
+      (hala_gfx::HalaShaderStageFlags::VERTEX, "vert")
+    };
+    shaders.push(HalaShader::with_file(
+      Rc::clone(&context.logical_device),
+      vertex_or_mesh_file_path,
+      stage,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      &format!("{}.{}", debug_name, extension),
+    )?);
+    if let Some(file_path) = fragment_file_path {
+      shaders.push(HalaShader::with_file(
+        Rc::clone(&context.logical_device),
+        file_path,
+        hala_gfx::HalaShaderStageFlags::FRAGMENT,
+        hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+        &format!("{}.frag", debug_name),
+      )?);
+    }
+
+    self.shaders = shaders;
+
+    Ok(())
+  }
+
+  /// Enable the shadows with the config.
+  /// param config: The shadow config.
+  /// return: The result.
+  pub(crate) fn enable(&mut self, config: HalaShadowConfig) -> Result<(), HalaRendererError> {
+    Self::check_config(&config)?;
+
+    self.config = Some(config);
+
+    Ok(())
+  }
+
+  /// Set the sample counts of the percentage-closer soft shadows, the committed atlas is updated with the scene.
+  /// param blocker_search_samples: The sample count of the blocker search, 0 disables PCSS.
+  /// param filter_samples: The sample count of the filter, 1 is a single hard shadow test.
+  /// param scene: The scene in GPU, none if not committed.
+  /// return: The result.
+  pub(crate) fn set_soft_shadow_samples(&mut self, blocker_search_samples: u32, filter_samples: u32, scene: Option<&gpu::HalaScene>) -> Result<(), HalaRendererError> {
+    let config = self.config.as_mut().ok_or(HalaRendererError::invalid_state("The shadows are not enabled! Call enable_shadows first."))?;
+    HalaShadowAtlas::check_soft_shadow_samples(blocker_search_samples, filter_samples)?;
+    config.blocker_search_samples = blocker_search_samples;
+    config.filter_samples = filter_samples;
+
+    if let (Some(atlas), Some(scene)) = (self.atlas.as_mut(), scene) {
+      atlas.set_soft_shadow_samples(blocker_search_samples, filter_samples)?;
+      atlas.update(scene)?;
+    }
+
+    Ok(())
+  }
+
+  /// Get the shadow atlas.
+  /// return: The shadow atlas, none if the shadows are not enabled or not committed.
+  pub(crate) fn get_atlas(&self) -> Option<&HalaShadowAtlas> {
+    self.atlas.as_ref().filter(|_| self.config.is_some())
+  }
+
+  /// Create the shadow atlas and the depth only graphics pipeline if the shadows are enabled,
+  /// otherwise the tiles of the kept atlas are cleared.
+  /// param context: The GFX context.
+  /// param layout: The pipeline layout and the vertex input of the scene graphics pipelines.
+  /// param scene: The scene in GPU.
+  /// param static_descriptor_set: The static descriptor set of the renderer.
+  /// param num_of_frames: The count of the swapchain images.
+  /// param depth_convention: The depth convention of the renderer.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param pipeline_cache: The pipeline cache.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    layout: &HalaScenePipelineLayout,
+    scene: &gpu::HalaScene,
+    static_descriptor_set: &HalaDescriptorSet,
+    num_of_frames: usize,
+    depth_convention: HalaDepthConvention,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    pipeline_cache: &HalaPipelineCache,
+  ) -> Result<(), HalaRendererError> {
+    let config = match self.config {
+      Some(config) => config,
+      None => {
+        self.graphics_pipeline = None;
+        if let Some(atlas) = self.atlas.as_mut() {
+          atlas.clear()?;
+        }
+        return Ok(());
+      }
+    };
+
+    let mut atlas = HalaShadowAtlas::new(context, config, depth_convention)?;
+    atlas.update(scene)?;
+    atlas.update_descriptor_set(static_descriptor_set, num_of_frames);
+    let draw_push_constant_range = layout.push_constant_ranges.first()
+      .ok_or(HalaRendererError::new("The draw push constant range is none!", None))?;
+    let shaders = self.shaders.iter().map(|shader| shader.as_ref()).collect::<Vec<_>>();
+    self.graphics_pipeline = Some(
+      HalaGraphicsPipeline::with_format_and_size(
+        Rc::clone(&context.logical_device),
+        &[] as &[hala_gfx::HalaFormat],
+        Some(atlas.image.format),
+        config.atlas_size,
+        config.atlas_size,
+        &layout.descriptor_set_layouts,
+        layout.flags,
+        layout.vertex_attribute_descriptions,
+        layout.vertex_binding_descriptions,
+        &[hala_gfx::HalaPushConstantRange {
+          stage_flags: draw_push_constant_range.stage_flags,
+          offset: 0,
+          size: draw_push_constant_range.size + 4, // The draw push constants and Tile index.
+        }],
+        hala_gfx::HalaPrimitiveTopology::TRIANGLE_LIST,
+        &[] as &[&hala_gfx::HalaBlendState],
+        &[] as &[&hala_gfx::HalaBlendState],
+        &hala_gfx::HalaRasterizerState::new(hala_gfx::HalaFrontFace::COUNTER_CLOCKWISE, hala_gfx::HalaCullModeFlags::NONE, hala_gfx::HalaPolygonMode::FILL, 1.0),
+        &hala_gfx::HalaMultisampleState::default(),
+        &hala_gfx::HalaDepthState::new(true, true, depth_compare_op),
+        None,
+        shaders.as_slice(),
+        &[hala_gfx::HalaDynamicState::VIEWPORT, hala_gfx::HalaDynamicState::SCISSOR],
+        Some(pipeline_cache),
+        "shadow.graphics_pipeline",
+      )?
+    );
+    self.atlas = Some(atlas);
+
+    Ok(())
+  }
+
+  /// Get the flipped viewport of a tile, so the shaders map the NDC to the atlas UV by HalaShadowTile::uv_rect.
+  /// param tile_rect: The tile rect(x, y, width, height) in the atlas.
+  /// return: The tile view.
+  pub(crate) fn get_tile_view_for(tile_rect: (i32, i32, u32, u32)) -> HalaViewDesc {
+    let (x, y, width, height) = tile_rect;
+    HalaViewDesc {
+      camera_index: 0,
+      viewport: (x as f32, y as f32, width as f32, height as f32),
+      scissor: (x, y, width, height),
+    }
+  }
+
+  /// Render the shadow maps of the lights into the tiles of the shadow atlas.
+  /// The atlas is left in the SHADER_READ_ONLY_OPTIMAL layout for the scene and the lighting passes.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param descriptor_sets: The static, the dynamic and the textures descriptor sets.
+  /// param draw_stage_flags: The shader stages of the draw push constants.
+  /// param draw_push_constant_size: The size of the draw push constants, the tile index follows them.
+  /// param clear_depth: The clear depth of the depth convention.
+  /// param draw_shadow_casters: Draw the shadow casters with the pipeline into the current tile.
+  /// return: The pipeline bind count.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn render(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    descriptor_sets: [&HalaDescriptorSet; 3],
+    draw_stage_flags: hala_gfx::HalaShaderStageFlags,
+    draw_push_constant_size: u32,
+    clear_depth: f32,
+    draw_shadow_casters: &dyn Fn(&HalaGraphicsPipeline) -> Result<(), HalaRendererError>,
+  ) -> Result<u64, HalaRendererError> {
+    let (graphics_pipeline, atlas) = match (self.graphics_pipeline.as_ref(), self.atlas.as_ref()) {
+      (Some(graphics_pipeline), Some(atlas)) if atlas.get_tile_count() > 0 => (graphics_pipeline, atlas),
+      _ => return Ok(0),
+    };
+    let atlas_size = atlas.get_config().atlas_size;
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "Draw Shadows", [0.5, 0.5, 0.5, 1.0]);
+    }
+
+    // The previous shadows are not needed, all tiles are redrawn.
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+        new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH,
+        image: atlas.image.raw,
+        ..Default::default()
+      }],
+    );
+    command_buffers.begin_rendering_with(
+      index,
+      &[] as &[&hala_gfx::HalaImage],
+      Some(&atlas.image),
+      (0, 0, atlas_size, atlas_size),
+      &[],
+      Some(clear_depth),
+      None,
+      hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+      hala_gfx::HalaAttachmentStoreOp::STORE,
+      hala_gfx::HalaAttachmentStoreOp::DONT_CARE,
+    );
+
+    command_buffers.bind_graphics_pipeline(index, graphics_pipeline);
+    command_buffers.bind_graphics_descriptor_sets(
+      index,
+      graphics_pipeline,
+      0,
+      &descriptor_sets,
+      &[],
+    );
+
+    for tile_index in 0..atlas.get_tile_count() {
+      set_view_viewport(index, command_buffers, &Self::get_tile_view_for(atlas.get_tile_rect(tile_index)));
+      command_buffers.push_constants(
+        index,
+        graphics_pipeline.layout,
+        draw_stage_flags,
+        draw_push_constant_size,
+        &(tile_index as u32).to_le_bytes(),
+      );
+      draw_shadow_casters(graphics_pipeline)?;
+    }
+
+    command_buffers.end_rendering(index);
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER | hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::DEPTH,
+        image: atlas.image.raw,
+        ..Default::default()
+      }],
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    Ok(1)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_tile_view() {
+    let view = HalaShadowPass::get_tile_view_for((512, 256, 256, 256));
+    assert_eq!(view.camera_index, 0);
+    assert_eq!(view.viewport, (512.0, 256.0, 256.0, 256.0));
+    assert_eq!(view.scissor, (512, 256, 256, 256));
+  }
+
+  #[test]
+  fn test_soft_shadow_samples_require_config() {
+    let mut pass = HalaShadowPass::default();
+    assert!(pass.set_soft_shadow_samples(16, 16, None).is_err());
+    assert!(pass.get_atlas().is_none());
+  }
+
+}
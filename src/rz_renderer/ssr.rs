@@ -0,0 +1,500 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaDescriptorSetLayout,
+  HalaGraphicsPipeline,
+  HalaImage,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+use crate::renderer::get_depth_aspect_mask;
+
+use super::post_process::{
+  begin_post_process_rendering,
+  load_vertex_fragment_shaders,
+  create_full_screen_graphics_pipeline,
+  post_process_binding,
+  bind_full_screen_pipeline,
+};
+
+/// The G-Buffer images which the SSR traces.
+pub(crate) struct HalaSSRGBuffer<'a> {
+  pub(crate) depth_image: &'a HalaImage,
+  pub(crate) albedo_image: &'a HalaImage,
+  pub(crate) normal_image: &'a HalaImage,
+}
+
+/// The SSR(screen space reflections) pass, the trace program ray-marches the G-Buffer depth into the SSR image,
+/// the lighting is rendered into the current scene color image, then the composite pass adds the reflections into the next target.
+/// The previous scene color image is the source of the reflections.
+pub(crate) struct HalaSSRPass {
+  pub(crate) is_enabled: bool,
+  pub(crate) max_steps: u32,
+  pub(crate) thickness: f32,
+  pub(crate) trace_file_path: Option<String>,
+  pub(crate) composite_shaders: Vec<HalaShader>,
+  pub(crate) trace_program: Option<HalaComputeProgram>,
+  pub(crate) composite_graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) ssr_image: Option<HalaImage>,
+  pub(crate) scene_color_images: Vec<HalaImage>,
+  pub(crate) depth_image: Option<HalaImage>,
+  pub(crate) descriptor_sets: Vec<HalaDescriptorSet>,
+  // The frames since the last reset, 0 means the previous scene color is invalid.
+  pub(crate) frame_index: u64,
+}
+
+/// The Default implementation of the SSR pass.
+impl Default for HalaSSRPass {
+  fn default() -> Self {
+    Self {
+      is_enabled: false,
+      max_steps: 0,
+      thickness: 0.0,
+      trace_file_path: None,
+      composite_shaders: Vec::new(),
+      trace_program: None,
+      composite_graphics_pipeline: None,
+      ssr_image: None,
+      scene_color_images: Vec::new(),
+      depth_image: None,
+      descriptor_sets: Vec::new(),
+      frame_index: 0,
+    }
+  }
+}
+
+/// The implementation of the SSR pass.
+impl HalaSSRPass {
+
+  /// Set the shaders of the SSR, they must be set before commit.
+  /// param context: The GFX context.
+  /// param trace_file_path: The trace compute shader file path.
+  /// param composite_vertex_file_path: The composite pass vertex shader file path.
+  /// param composite_fragment_file_path: The composite pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    trace_file_path: &str,
+    composite_vertex_file_path: &str,
+    composite_fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if self.trace_program.is_some() {
+      return Err(HalaRendererError::new("The SSR shaders must be set before commit!", None));
+    }
+
+    self.composite_shaders = load_vertex_fragment_shaders(
+      context,
+      composite_vertex_file_path,
+      composite_fragment_file_path,
+      &format!("{}_composite", debug_name),
+    )?;
+    self.trace_file_path = Some(trace_file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Enable the SSR, the previous scene color is reset.
+  /// param max_steps: The max ray marching steps.
+  /// param thickness: The thickness of the depth in the view space.
+  pub(crate) fn enable(&mut self, max_steps: u32, thickness: f32) {
+    self.is_enabled = true;
+    self.max_steps = max_steps;
+    self.thickness = thickness;
+    self.frame_index = 0;
+  }
+
+  /// Get the descriptors and the descriptor set count reserved by commit.
+  /// return: The descriptor sizes and the set count.
+  pub(crate) fn get_descriptor_sizes(&self) -> (Vec<(hala_gfx::HalaDescriptorType, usize)>, usize) {
+    if self.trace_file_path.is_some() {
+      (
+        vec![
+          (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, 10),
+          (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, 2),
+        ],
+        2,
+      )
+    } else {
+      (Vec::new(), 0)
+    }
+  }
+
+  /// Create the images, the descriptor sets, the trace program and the composite pipeline, the previous ones are replaced.
+  /// The previous scene color is reset.
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param gbuffer: The G-Buffer images.
+  /// param view_descriptor_set_layouts: The static and the dynamic descriptor set layouts of the renderer, they are the set 0 and 1 of the trace program.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    gbuffer: &HalaSSRGBuffer,
+    view_descriptor_set_layouts: [&HalaDescriptorSetLayout; 2],
+  ) -> Result<(), HalaRendererError> {
+    let trace_file_path = match self.trace_file_path.as_ref() {
+      Some(trace_file_path) => trace_file_path,
+      None => return Ok(()),
+    };
+
+    // The scene color and depth images use the swapchain formats, so the lighting pipeline can render into them.
+    let ssr_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::STORAGE,
+      hala_gfx::HalaFormat::R16G16B16A16_SFLOAT,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "ssr.image",
+    )?;
+    let mut scene_color_images = Vec::with_capacity(2);
+    for color_index in 0..2 {
+      scene_color_images.push(HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::COLOR_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED,
+        context.swapchain.format,
+        width,
+        height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        &format!("ssr_scene_color_{}.image", color_index),
+      )?);
+    }
+    let depth_image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+      context.swapchain.depth_stencil_format,
+      width,
+      height,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "ssr_depth.image",
+    )?;
+
+    // The set 0 reads the scene color 1 and writes the scene color 0, the set 1 is the reverse.
+    let stage_flags = hala_gfx::HalaShaderStageFlags::COMPUTE | hala_gfx::HalaShaderStageFlags::FRAGMENT;
+    let mut descriptor_sets = Vec::with_capacity(2);
+    for color_index in 0..2 {
+      let descriptor_set = HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&descriptor_pool),
+        HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            post_process_binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Depth image.
+            post_process_binding(1, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Albedo image.
+            post_process_binding(2, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Normal image.
+            post_process_binding(3, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Previous scene color image.
+            post_process_binding(4, hala_gfx::HalaDescriptorType::STORAGE_IMAGE, stage_flags),  // SSR image.
+            post_process_binding(5, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Current scene color image.
+          ],
+          &format!("ssr_{}.descriptor_set_layout", color_index),
+        )?,
+        0,
+        &format!("ssr_{}.descriptor_set", color_index),
+      )?;
+      descriptor_set.update_sampled_images(0, 0, &[gbuffer.depth_image]);
+      descriptor_set.update_sampled_images(0, 1, &[gbuffer.albedo_image]);
+      descriptor_set.update_sampled_images(0, 2, &[gbuffer.normal_image]);
+      descriptor_set.update_sampled_images(0, 3, &[&scene_color_images[1 - color_index]]);
+      descriptor_set.update_storage_images(0, 4, &[&ssr_image]);
+      descriptor_set.update_sampled_images(0, 5, &[&scene_color_images[color_index]]);
+      descriptor_sets.push(descriptor_set);
+    }
+
+    let trace_program = HalaComputeProgram::new(
+      Rc::clone(&context.logical_device),
+      &[view_descriptor_set_layouts[0], view_descriptor_set_layouts[1], &descriptor_sets[0].layout],
+      &HalaComputeProgramDesc {
+        shader_file_path: trace_file_path.clone(),
+        push_constant_size: 16, // Max steps, Thickness, Reset and Padding.
+        bindings: Vec::new(),
+      },
+      Some(pipeline_cache),
+      "ssr_trace",
+    )?;
+    let composite_graphics_pipeline = create_full_screen_graphics_pipeline(
+      context,
+      &[&descriptor_sets[0].layout],
+      &[],
+      self.composite_shaders.as_slice(),
+      depth_compare_op,
+      pipeline_cache,
+      "ssr_composite",
+    )?;
+
+    self.ssr_image = Some(ssr_image);
+    self.scene_color_images = scene_color_images;
+    self.depth_image = Some(depth_image);
+    self.descriptor_sets = descriptor_sets;
+    self.trace_program = Some(trace_program);
+    self.composite_graphics_pipeline = Some(composite_graphics_pipeline);
+    self.frame_index = 0;
+
+    Ok(())
+  }
+
+  /// Whether the SSR is enabled and its resources are committed.
+  /// return: True if the SSR is active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.is_enabled && self.trace_program.is_some()
+  }
+
+  /// Advance the scene color after the frame is recorded.
+  pub(crate) fn advance(&mut self) {
+    self.frame_index += 1;
+  }
+
+  /// Get the index of the current scene color image, the other one is the previous scene color.
+  /// return: The index.
+  fn get_color_index(&self) -> usize {
+    (self.frame_index % 2) as usize
+  }
+
+  /// Get the current scene color image.
+  /// return: The image.
+  fn get_scene_color_image(&self) -> Result<&HalaImage, HalaRendererError> {
+    self.scene_color_images.get(self.get_color_index()).ok_or(HalaRendererError::new("The SSR scene color image is none!", None))
+  }
+
+  /// Get the descriptor set of the current frame.
+  /// return: The descriptor set.
+  fn get_descriptor_set(&self) -> Result<&HalaDescriptorSet, HalaRendererError> {
+    self.descriptor_sets.get(self.get_color_index()).ok_or(HalaRendererError::new("The SSR descriptor set is none!", None))
+  }
+
+  /// Trace the SSR into the SSR image.
+  /// The trace program binds the set 0 and 1 of the renderer and the SSR set at the set 2, and runs 8 x 8 groups
+  /// with the push constants: max steps, thickness, reset and padding.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param gbuffer: The G-Buffer images.
+  /// param view_descriptor_sets: The static and the dynamic descriptor sets of the first view.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// return: The pipeline bind count.
+  pub(crate) fn trace(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    gbuffer: &HalaSSRGBuffer,
+    view_descriptor_sets: (&HalaDescriptorSet, &HalaDescriptorSet),
+    width: u32,
+    height: u32,
+  ) -> Result<u64, HalaRendererError> {
+    let program = self.trace_program.as_ref().ok_or(HalaRendererError::new("The SSR trace program is none!", None))?;
+    let ssr_image = self.ssr_image.as_ref().ok_or(HalaRendererError::new("The SSR image is none!", None))?;
+    let prev_scene_color_image = self.scene_color_images.get(1 - self.get_color_index()).ok_or(HalaRendererError::new("The SSR scene color image is none!", None))?;
+    let descriptor_set = self.get_descriptor_set()?;
+    let depth_image = gbuffer.depth_image;
+    let is_reset = self.frame_index == 0;
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "SSR Trace", [0.0, 1.0, 1.0, 1.0]);
+    }
+
+    let gbuffer_barrier = |image: &HalaImage| hala_gfx::HalaImageBarrierInfo {
+      old_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+      src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+      dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+      src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+      image: image.raw,
+      ..Default::default()
+    };
+    command_buffers.set_image_barriers(
+      index,
+      &[
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+          new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          aspect_mask: get_depth_aspect_mask(depth_image.format),
+          image: depth_image.raw,
+          ..Default::default()
+        },
+        gbuffer_barrier(gbuffer.albedo_image),
+        gbuffer_barrier(gbuffer.normal_image),
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: if is_reset { hala_gfx::HalaImageLayout::UNDEFINED } else { hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL },
+          new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::NONE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: prev_scene_color_image.raw,
+          ..Default::default()
+        },
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::UNDEFINED,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: ssr_image.raw,
+          ..Default::default()
+        },
+      ],
+    );
+
+    let push_constants = [
+      self.max_steps.to_le_bytes(),
+      self.thickness.to_le_bytes(),
+      (is_reset as u32).to_le_bytes(),
+      0u32.to_le_bytes(),
+    ].concat();
+    program.bind(index, command_buffers, &[view_descriptor_sets.0, view_descriptor_sets.1, descriptor_set]);
+    program.push_constants(index, command_buffers, 0, push_constants.as_slice());
+    program.dispatch(index, command_buffers, (width + 7) / 8, (height + 7) / 8, 1);
+
+    // The composite pass reads the SSR image, and the lighting pass reads the depth as the input attachment.
+    command_buffers.set_image_barriers(
+      index,
+      &[
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::GENERAL,
+          new_layout: hala_gfx::HalaImageLayout::GENERAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+          image: ssr_image.raw,
+          ..Default::default()
+        },
+        hala_gfx::HalaImageBarrierInfo {
+          old_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          new_layout: hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+          src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+          dst_access_mask: hala_gfx::HalaAccessFlags2::INPUT_ATTACHMENT_READ,
+          src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+          dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+          aspect_mask: get_depth_aspect_mask(depth_image.format),
+          image: depth_image.raw,
+          ..Default::default()
+        },
+      ],
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    Ok(1)
+  }
+
+  /// Begin the rendering of the lighting into the current scene color image.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param context: The GFX context.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param clear_color: The clear color.
+  /// param clear_depth_stencil: The clear depth and stencil.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn begin_lighting(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    context: &HalaContext,
+    width: u32,
+    height: u32,
+    clear_color: [f32; 4],
+    clear_depth_stencil: (f32, u32),
+  ) -> Result<(), HalaRendererError> {
+    let depth_image = self.depth_image.as_ref().ok_or(HalaRendererError::new("The SSR depth image is none!", None))?;
+
+    begin_post_process_rendering(
+      index,
+      command_buffers,
+      context,
+      self.get_scene_color_image()?,
+      depth_image,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+      false,
+      width,
+      height,
+      clear_color,
+      clear_depth_stencil,
+    );
+
+    Ok(())
+  }
+
+  /// Prepare the current scene color image to be read by the composite pass, the rendering of the lighting must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn prepare(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let scene_color_image = self.get_scene_color_image()?;
+
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::COLOR_ATTACHMENT_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: scene_color_image.raw,
+        ..Default::default()
+      }],
+    );
+
+    Ok(0)
+  }
+
+  /// Composite the SSR with the current scene color into the current rendering.
+  /// The composite shaders draw a full screen triangle strip of 4 vertices with the SSR set at the set 0.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.composite_graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The SSR composite graphics pipeline is none!", None))?;
+
+    bind_full_screen_pipeline(index, command_buffers, graphics_pipeline, self.get_descriptor_set()?);
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
@@ -0,0 +1,472 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaCommandBufferSet,
+  HalaContext,
+  HalaDescriptorPool,
+  HalaDescriptorSet,
+  HalaGraphicsPipeline,
+  HalaImage,
+  HalaPipelineCache,
+  HalaShader,
+};
+
+use crate::error::HalaRendererError;
+use crate::compute_program::{
+  HalaComputeProgramDesc,
+  HalaComputeProgram,
+};
+use crate::renderer::get_depth_aspect_mask;
+
+use super::post_process::{
+  HalaPostProcessTarget,
+  load_vertex_fragment_shaders,
+  create_full_screen_graphics_pipeline,
+  post_process_binding,
+  bind_full_screen_pipeline,
+};
+
+/// The sample count of the Halton jitter sequence of the TAA.
+const TAA_JITTER_SAMPLES: u64 = 8;
+
+/// The TAA(temporal anti-aliasing) pass, the scene is rendered into the TAA color and depth images with the jittered projection,
+/// then the resolve program blends them into the history images and the present pass copies the history to the next target.
+pub(crate) struct HalaTAAPass {
+  pub(crate) is_enabled: bool,
+  pub(crate) resolve_file_path: Option<String>,
+  pub(crate) present_shaders: Vec<HalaShader>,
+  pub(crate) resolve_program: Option<HalaComputeProgram>,
+  pub(crate) present_graphics_pipeline: Option<HalaGraphicsPipeline>,
+  pub(crate) target: Option<HalaPostProcessTarget>,
+  pub(crate) history_images: Vec<HalaImage>,
+  pub(crate) descriptor_sets: Vec<HalaDescriptorSet>,
+  // The frames accumulated in the history since the last reset, 0 means the history is invalid.
+  pub(crate) frame_index: u64,
+  pub(crate) jitter: glam::Vec2,
+  pub(crate) reprojection_mtx: glam::Mat4,
+  pub(crate) prev_vp_mtx: glam::Mat4,
+}
+
+/// The Default implementation of the TAA pass.
+impl Default for HalaTAAPass {
+  fn default() -> Self {
+    Self {
+      is_enabled: false,
+      resolve_file_path: None,
+      present_shaders: Vec::new(),
+      resolve_program: None,
+      present_graphics_pipeline: None,
+      target: None,
+      history_images: Vec::new(),
+      descriptor_sets: Vec::new(),
+      frame_index: 0,
+      jitter: glam::Vec2::ZERO,
+      reprojection_mtx: glam::Mat4::IDENTITY,
+      prev_vp_mtx: glam::Mat4::IDENTITY,
+    }
+  }
+}
+
+/// The implementation of the TAA pass.
+impl HalaTAAPass {
+
+  /// Get the jitter of a frame in the NDC, it is the Halton(2, 3) sequence scaled to one pixel.
+  /// param frame_index: The frame index since the last reset.
+  /// param width: The width of the viewport.
+  /// param height: The height of the viewport.
+  /// return: The jitter.
+  pub(crate) fn get_jitter_for(frame_index: u64, width: f32, height: f32) -> glam::Vec2 {
+    let sample_index = frame_index % TAA_JITTER_SAMPLES + 1;
+    glam::Vec2::new(
+      (halton(sample_index, 2) - 0.5) * 2.0 / width,
+      (halton(sample_index, 3) - 0.5) * 2.0 / height,
+    )
+  }
+
+  /// Set the shaders of the TAA, they must be set before commit.
+  /// param context: The GFX context.
+  /// param resolve_file_path: The resolve compute shader file path.
+  /// param present_vertex_file_path: The present pass vertex shader file path.
+  /// param present_fragment_file_path: The present pass fragment shader file path.
+  /// param debug_name: The debug name of the shaders.
+  /// return: The result.
+  pub(crate) fn set_shaders_with_file(
+    &mut self,
+    context: &HalaContext,
+    resolve_file_path: &str,
+    present_vertex_file_path: &str,
+    present_fragment_file_path: &str,
+    debug_name: &str,
+  ) -> Result<(), HalaRendererError> {
+    if self.resolve_program.is_some() {
+      return Err(HalaRendererError::new("The TAA shaders must be set before commit!", None));
+    }
+
+    self.present_shaders = load_vertex_fragment_shaders(
+      context,
+      present_vertex_file_path,
+      present_fragment_file_path,
+      &format!("{}_present", debug_name),
+    )?;
+    self.resolve_file_path = Some(resolve_file_path.to_string());
+
+    Ok(())
+  }
+
+  /// Enable or disable the TAA, the history is reset.
+  /// param enable: Whether to enable the TAA.
+  pub(crate) fn enable(&mut self, enable: bool) {
+    self.is_enabled = enable;
+    self.frame_index = 0;
+    self.jitter = glam::Vec2::ZERO;
+  }
+
+  /// Get the descriptors and the descriptor set count reserved by commit.
+  /// return: The descriptor sizes and the set count.
+  pub(crate) fn get_descriptor_sizes(&self) -> (Vec<(hala_gfx::HalaDescriptorType, usize)>, usize) {
+    if self.resolve_file_path.is_some() {
+      (
+        vec![
+          (hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, 4),
+          (hala_gfx::HalaDescriptorType::STORAGE_IMAGE, 4),
+        ],
+        2,
+      )
+    } else {
+      (Vec::new(), 0)
+    }
+  }
+
+  /// Create the target, the history images, the descriptor sets, the program and the present pipeline, the previous ones are replaced.
+  /// The history is reset.
+  /// param context: The GFX context.
+  /// param descriptor_pool: The descriptor pool.
+  /// param pipeline_cache: The pipeline cache.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param depth_compare_op: The depth compare operation of the renderer.
+  /// param gbuffer_depth_image: The G-Buffer depth image of the deferred rendering, the resolve reprojects with it instead of the TAA depth.
+  /// return: The result.
+  #[allow(clippy::too_many_arguments)]
+  pub(crate) fn commit(
+    &mut self,
+    context: &HalaContext,
+    descriptor_pool: Rc<RefCell<HalaDescriptorPool>>,
+    pipeline_cache: &HalaPipelineCache,
+    width: u32,
+    height: u32,
+    depth_compare_op: hala_gfx::HalaCompareOp,
+    gbuffer_depth_image: Option<&HalaImage>,
+  ) -> Result<(), HalaRendererError> {
+    let resolve_file_path = match self.resolve_file_path.as_ref() {
+      Some(resolve_file_path) => resolve_file_path,
+      None => return Ok(()),
+    };
+
+    // The resolve program reads the color and the depth of the target.
+    let target = HalaPostProcessTarget::new(
+      context,
+      width,
+      height,
+      hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+      true,
+      "taa",
+    )?;
+    let mut history_images = Vec::with_capacity(2);
+    for history_index in 0..2 {
+      history_images.push(HalaImage::new_2d(
+        Rc::clone(&context.logical_device),
+        hala_gfx::HalaImageUsageFlags::STORAGE,
+        hala_gfx::HalaFormat::R16G16B16A16_SFLOAT,
+        width,
+        height,
+        1,
+        1,
+        hala_gfx::HalaMemoryLocation::GpuOnly,
+        &format!("taa_history_{}.image", history_index),
+      )?);
+    }
+
+    // The descriptor set is selected by the frame parity instead of the image index, so each one is a static descriptor set.
+    // The set 0 reads the history 1 and writes the history 0, the set 1 is the reverse.
+    let resolve_depth_image = gbuffer_depth_image.unwrap_or(&target.depth_image);
+    let stage_flags = hala_gfx::HalaShaderStageFlags::COMPUTE | hala_gfx::HalaShaderStageFlags::FRAGMENT;
+    let mut descriptor_sets = Vec::with_capacity(2);
+    for history_index in 0..2 {
+      let descriptor_set = HalaDescriptorSet::new_static(
+        Rc::clone(&context.logical_device),
+        Rc::clone(&descriptor_pool),
+        hala_gfx::HalaDescriptorSetLayout::new(
+          Rc::clone(&context.logical_device),
+          &[
+            post_process_binding(0, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Current color image.
+            post_process_binding(1, hala_gfx::HalaDescriptorType::SAMPLED_IMAGE, stage_flags),  // Current depth image.
+            post_process_binding(2, hala_gfx::HalaDescriptorType::STORAGE_IMAGE, stage_flags),  // Previous history image.
+            post_process_binding(3, hala_gfx::HalaDescriptorType::STORAGE_IMAGE, stage_flags),  // Current history image.
+          ],
+          &format!("taa_{}.descriptor_set_layout", history_index),
+        )?,
+        0,
+        &format!("taa_{}.descriptor_set", history_index),
+      )?;
+      descriptor_set.update_sampled_images(0, 0, &[&target.color_image]);
+      descriptor_set.update_sampled_images(0, 1, &[resolve_depth_image]);
+      descriptor_set.update_storage_images(0, 2, &[&history_images[1 - history_index]]);
+      descriptor_set.update_storage_images(0, 3, &[&history_images[history_index]]);
+      descriptor_sets.push(descriptor_set);
+    }
+
+    let resolve_program = HalaComputeProgram::new(
+      Rc::clone(&context.logical_device),
+      &[&descriptor_sets[0].layout],
+      &HalaComputeProgramDesc {
+        shader_file_path: resolve_file_path.clone(),
+        push_constant_size: 80, // Reprojection matrix, Jitter, Reset and Padding.
+        bindings: Vec::new(),
+      },
+      Some(pipeline_cache),
+      "taa_resolve",
+    )?;
+    let present_graphics_pipeline = create_full_screen_graphics_pipeline(
+      context,
+      &[&descriptor_sets[0].layout],
+      &[],
+      self.present_shaders.as_slice(),
+      depth_compare_op,
+      pipeline_cache,
+      "taa_present",
+    )?;
+
+    self.target = Some(target);
+    self.history_images = history_images;
+    self.descriptor_sets = descriptor_sets;
+    self.resolve_program = Some(resolve_program);
+    self.present_graphics_pipeline = Some(present_graphics_pipeline);
+    self.frame_index = 0;
+
+    Ok(())
+  }
+
+  /// Whether the TAA is enabled and its resources are committed.
+  /// return: True if the TAA is active.
+  pub(crate) fn is_active(&self) -> bool {
+    self.is_enabled && self.resolve_program.is_some()
+  }
+
+  /// Get the target which the scene renders into.
+  /// return: The target.
+  pub(crate) fn get_target(&self) -> Result<&HalaPostProcessTarget, HalaRendererError> {
+    self.target.as_ref().ok_or(HalaRendererError::new("The TAA target is none!", None))
+  }
+
+  /// Update the jitter and the reprojection matrix of the current frame.
+  /// Jitter the projection by the sub-pixel offset in the clip space, it works for both the perspective and the orthographic projection.
+  /// The history is reprojected by the unjittered view-projection matrices.
+  /// param unjittered_vp_mtx: The unjittered view-projection matrix of the first view.
+  /// param width: The width of the viewport of the first view.
+  /// param height: The height of the viewport of the first view.
+  /// return: The jitter matrix which is applied to the projection matrix.
+  pub(crate) fn update_jitter(&mut self, unjittered_vp_mtx: glam::Mat4, width: f32, height: f32) -> glam::Mat4 {
+    let jitter = Self::get_jitter_for(self.frame_index, width, height);
+    self.jitter = jitter;
+    self.reprojection_mtx = self.prev_vp_mtx * unjittered_vp_mtx.inverse();
+    self.prev_vp_mtx = unjittered_vp_mtx;
+    glam::Mat4::from_translation(glam::Vec3::new(jitter.x, jitter.y, 0.0))
+  }
+
+  /// Advance the history after the frame is recorded.
+  pub(crate) fn advance(&mut self) {
+    self.frame_index += 1;
+  }
+
+  /// Get the descriptor set of the current frame and the index of the history it writes.
+  /// return: The descriptor set and the history index.
+  fn get_current_descriptor_set(&self) -> Result<(&HalaDescriptorSet, usize), HalaRendererError> {
+    let history_index = (self.frame_index % 2) as usize;
+    let descriptor_set = self.descriptor_sets.get(history_index).ok_or(HalaRendererError::new("The TAA descriptor set is none!", None))?;
+    Ok((descriptor_set, history_index))
+  }
+
+  /// Resolve the TAA into the current history.
+  /// The resolve program runs 8 x 8 groups with the push constants: the reprojection matrix(the previous view-projection
+  /// multiplied by the inverse of the current one, both unjittered), the jitter in the NDC, the reset flag and a padding.
+  /// It reprojects the previous history by the depth, clamps it to the neighborhood of the current color and blends them,
+  /// the reset flag is 1 if the previous history is invalid. The NDC of a pixel follows the flipped viewport, so y is up.
+  /// The rendering into the TAA images must be ended.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// param width: The render width.
+  /// param height: The render height.
+  /// param gbuffer_depth_image: The G-Buffer depth image of the deferred rendering, it is read as the input attachment by the lighting.
+  /// return: The pipeline bind count.
+  pub(crate) fn prepare(
+    &self,
+    index: usize,
+    command_buffers: &HalaCommandBufferSet,
+    width: u32,
+    height: u32,
+    gbuffer_depth_image: Option<&HalaImage>,
+  ) -> Result<u64, HalaRendererError> {
+    let program = self.resolve_program.as_ref().ok_or(HalaRendererError::new("The TAA resolve program is none!", None))?;
+    let target = self.get_target()?;
+    let (depth_image, depth_old_layout, depth_src_access_mask) = match gbuffer_depth_image {
+      Some(depth_image) => (
+        depth_image,
+        hala_gfx::HalaImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        hala_gfx::HalaAccessFlags2::INPUT_ATTACHMENT_READ,
+      ),
+      None => (
+        &target.depth_image,
+        hala_gfx::HalaImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        hala_gfx::HalaAccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+      ),
+    };
+    let (descriptor_set, history_index) = self.get_current_descriptor_set()?;
+    let is_reset = self.frame_index == 0;
+
+    if cfg!(debug_assertions) {
+      command_buffers.begin_debug_label(index, "TAA Resolve", [1.0, 0.0, 1.0, 1.0]);
+    }
+
+    // The compute shader reads the color, the depth and the previous history, and writes the current history.
+    let history_old_layout = if is_reset { hala_gfx::HalaImageLayout::UNDEFINED } else { hala_gfx::HalaImageLayout::GENERAL };
+    let mut barriers = vec![
+      target.get_read_barrier(),
+      hala_gfx::HalaImageBarrierInfo {
+        old_layout: depth_old_layout,
+        new_layout: hala_gfx::HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        src_access_mask: depth_src_access_mask,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::EARLY_FRAGMENT_TESTS
+          | hala_gfx::HalaPipelineStageFlags2::LATE_FRAGMENT_TESTS
+          | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: get_depth_aspect_mask(depth_image.format),
+        image: depth_image.raw,
+        ..Default::default()
+      },
+    ];
+    for history_image in self.history_images.iter() {
+      barriers.push(hala_gfx::HalaImageBarrierInfo {
+        old_layout: history_old_layout,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ | hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER | hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: history_image.raw,
+        ..Default::default()
+      });
+    }
+    command_buffers.set_image_barriers(index, barriers.as_slice());
+
+    let mut push_constants = Vec::with_capacity(80);
+    push_constants.extend(self.reprojection_mtx.to_cols_array().iter().flat_map(|value| value.to_le_bytes()));
+    push_constants.extend(self.jitter.to_array().iter().flat_map(|value| value.to_le_bytes()));
+    push_constants.extend_from_slice(&(is_reset as u32).to_le_bytes());
+    push_constants.extend_from_slice(&0u32.to_le_bytes());
+    program.bind(index, command_buffers, &[descriptor_set]);
+    program.push_constants(index, command_buffers, 0, push_constants.as_slice());
+    program.dispatch(index, command_buffers, (width + 7) / 8, (height + 7) / 8, 1);
+
+    // The present pass reads the current history.
+    command_buffers.set_image_barriers(
+      index,
+      &[hala_gfx::HalaImageBarrierInfo {
+        old_layout: hala_gfx::HalaImageLayout::GENERAL,
+        new_layout: hala_gfx::HalaImageLayout::GENERAL,
+        src_access_mask: hala_gfx::HalaAccessFlags2::SHADER_WRITE,
+        dst_access_mask: hala_gfx::HalaAccessFlags2::SHADER_READ,
+        src_stage_mask: hala_gfx::HalaPipelineStageFlags2::COMPUTE_SHADER,
+        dst_stage_mask: hala_gfx::HalaPipelineStageFlags2::FRAGMENT_SHADER,
+        aspect_mask: hala_gfx::HalaImageAspectFlags::COLOR,
+        image: self.history_images[history_index].raw,
+        ..Default::default()
+      }],
+    );
+
+    if cfg!(debug_assertions) {
+      command_buffers.end_debug_label(index);
+    }
+
+    Ok(0)
+  }
+
+  /// Present the current history into the current rendering.
+  /// The present pass reads the current history and draws a full screen triangle strip of 4 vertices.
+  /// param index: The index of the current image.
+  /// param command_buffers: The command buffers.
+  /// return: The pipeline bind count.
+  pub(crate) fn draw(&self, index: usize, command_buffers: &HalaCommandBufferSet) -> Result<u64, HalaRendererError> {
+    let graphics_pipeline = self.present_graphics_pipeline.as_ref().ok_or(HalaRendererError::new("The TAA present graphics pipeline is none!", None))?;
+    let (descriptor_set, _) = self.get_current_descriptor_set()?;
+
+    bind_full_screen_pipeline(index, command_buffers, graphics_pipeline, descriptor_set);
+    command_buffers.draw(index, 4, 1, 0, 0);
+
+    Ok(1)
+  }
+
+}
+
+/// Get the element of the Halton low discrepancy sequence.
+/// param index: The index, starts from 1.
+/// param base: The base.
+/// return: The element in [0, 1).
+fn halton(mut index: u64, base: u64) -> f32 {
+  let mut f = 1.0;
+  let mut r = 0.0;
+  while index > 0 {
+    f /= base as f32;
+    r += f * (index % base) as f32;
+    index /= base;
+  }
+  r
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_halton() {
+    assert_eq!(halton(1, 2), 0.5);
+    assert_eq!(halton(2, 2), 0.25);
+    assert_eq!(halton(3, 2), 0.75);
+    assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+    assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+    assert!((halton(3, 3) - 1.0 / 9.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_jitter_is_sub_pixel_and_repeats() {
+    for frame_index in 0..TAA_JITTER_SAMPLES {
+      let jitter = HalaTAAPass::get_jitter_for(frame_index, 1920.0, 1080.0);
+      // The jitter is within half of a pixel, a pixel is 2 / size in the NDC.
+      assert!(jitter.x.abs() <= 1.0 / 1920.0);
+      assert!(jitter.y.abs() <= 1.0 / 1080.0);
+      assert_eq!(jitter, HalaTAAPass::get_jitter_for(frame_index + TAA_JITTER_SAMPLES, 1920.0, 1080.0));
+    }
+  }
+
+  #[test]
+  fn test_update_jitter_reprojection() {
+    let mut pass = HalaTAAPass::default();
+    let vp_mtx_0 = glam::Mat4::from_translation(glam::Vec3::new(1.0, 0.0, 0.0));
+    let vp_mtx_1 = glam::Mat4::from_translation(glam::Vec3::new(2.0, 0.0, 0.0));
+
+    let jitter_mtx = pass.update_jitter(vp_mtx_0, 100.0, 100.0);
+    assert_eq!(jitter_mtx.w_axis.truncate(), glam::Vec3::new(pass.jitter.x, pass.jitter.y, 0.0));
+    pass.advance();
+    pass.update_jitter(vp_mtx_1, 100.0, 100.0);
+
+    // The reprojection matrix maps the current unjittered clip space to the previous one.
+    let position = glam::Vec4::new(0.5, 0.5, 0.5, 1.0);
+    let reprojected = pass.reprojection_mtx * (vp_mtx_1 * position);
+    assert!((reprojected - vp_mtx_0 * position).abs().max_element() < 1e-5);
+    assert_eq!(pass.prev_vp_mtx, vp_mtx_1);
+  }
+
+}
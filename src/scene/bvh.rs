@@ -0,0 +1,708 @@
+use glam::{Mat4, Vec3};
+use rayon::prelude::*;
+
+use crate::error::HalaRendererError;
+use super::cpu;
+use super::HalaBounds;
+use super::picking::{
+  HalaPickResult,
+  intersect_bounds,
+  intersect_triangle,
+};
+
+/// The max triangle count of a leaf of the primitive trees.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// The bin count of the SAH split.
+const SAH_BINS: usize = 16;
+
+/// A triangle of the scene returned by the overlap queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HalaBvhTriangle {
+  pub node_index: u32,
+  pub mesh_index: u32,
+  pub prim_index: u32,
+  pub triangle_index: u32,
+}
+
+/// The closest point on the triangles of the scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaBvhClosestPoint {
+  pub triangle: HalaBvhTriangle,
+  /// The world space closest point.
+  pub position: Vec3,
+  /// The world space distance from the query point.
+  pub distance: f32,
+}
+
+/// A node of the tree.
+/// The leaf references count items from offset, the interior node has count 0 and its children at offset and offset + 1.
+#[derive(Debug, Clone, Copy)]
+struct HalaBvhNode {
+  min: Vec3,
+  max: Vec3,
+  offset: u32,
+  count: u32,
+}
+
+/// The tree over the bounds of the items, the triangles of a primitive or the instances of the scene.
+#[derive(Debug, Default)]
+struct HalaBvhTree {
+  nodes: Vec<HalaBvhNode>,
+  items: Vec<u32>,
+}
+
+/// The tree over the triangles of a primitive in its local space, it is shared by all instances of the primitive.
+struct HalaBvhPrimitive {
+  tree: HalaBvhTree,
+  triangles: Vec<[Vec3; 3]>,
+}
+
+/// A primitive instanced by a node.
+struct HalaBvhInstance {
+  node_index: u32,
+  mesh_index: u32,
+  prim_index: u32,
+  primitive_index: u32,
+  local_to_world: Mat4,
+  world_to_local: Mat4,
+}
+
+/// The CPU bounding volume hierarchy of the scene triangles for the ray, the overlap and the distance queries.
+/// Like the BLAS and the TLAS on the GPU, each primitive has a tree in its local space built with the binned SAH,
+/// and the top tree over the world bounds of the primitive instances is rebuilt by refit when only the node transforms change.
+pub struct HalaBvh {
+  primitives: Vec<HalaBvhPrimitive>,
+  primitive_offsets: Vec<u32>,
+  instances: Vec<HalaBvhInstance>,
+  tree: HalaBvhTree,
+}
+
+/// The implementation of the BVH.
+impl HalaBvh {
+
+  /// Build the BVH of the scene, the primitive trees are built in parallel.
+  /// param scene: The CPU scene.
+  /// return: The BVH.
+  pub fn new(scene: &cpu::HalaScene) -> Self {
+    let mut primitive_offsets = Vec::with_capacity(scene.meshes.len());
+    let mut sources = Vec::new();
+    for mesh in scene.meshes.iter() {
+      primitive_offsets.push(sources.len() as u32);
+      sources.extend(mesh.primitives.iter());
+    }
+    let primitives = sources.par_iter()
+      .map(|primitive| HalaBvhPrimitive::new(primitive))
+      .collect();
+
+    let mut bvh = Self {
+      primitives,
+      primitive_offsets,
+      instances: Vec::new(),
+      tree: HalaBvhTree::default(),
+    };
+    bvh.update_instances(scene);
+    bvh
+  }
+
+  /// Refit the BVH after the node transforms of the scene are changed, the primitive trees are kept.
+  /// The BVH must be rebuilt if the meshes are changed.
+  /// param scene: The CPU scene which the BVH is built from.
+  /// return: The result.
+  pub fn refit(&mut self, scene: &cpu::HalaScene) -> Result<(), HalaRendererError> {
+    let num_of_primitives = scene.meshes.iter().map(|mesh| mesh.primitives.len()).sum::<usize>();
+    if scene.meshes.len() != self.primitive_offsets.len() || num_of_primitives != self.primitives.len() {
+      return Err(HalaRendererError::new("The meshes of the scene are changed, rebuild the BVH instead of refitting it!", None));
+    }
+
+    self.update_instances(scene);
+    Ok(())
+  }
+
+  /// Get the closest hit of a ray.
+  /// Both sides of the triangles are hit.
+  /// param ray_origin: The world space origin of the ray.
+  /// param ray_dir: The world space direction of the ray, it is normalized here.
+  /// param t_max: The max distance.
+  /// return: The closest hit, none if the ray hits nothing.
+  pub fn intersect_ray(&self, ray_origin: Vec3, ray_dir: Vec3, t_max: f32) -> Option<HalaPickResult> {
+    let ray_dir = ray_dir.try_normalize()?;
+    let mut closest = None;
+    self.traverse_ray(ray_origin, ray_dir, t_max, |instance, triangle_index, t, barycentrics, local_normal| {
+      closest = Some(HalaPickResult {
+        node_index: instance.node_index,
+        mesh_index: instance.mesh_index,
+        prim_index: instance.prim_index,
+        triangle_index,
+        t,
+        position: ray_origin + ray_dir * t,
+        normal: instance.world_to_local.transpose().transform_vector3(local_normal).normalize_or_zero(),
+        barycentrics,
+      });
+      t
+    });
+    closest
+  }
+
+  /// Check if a ray hits any triangle, the traversal stops at the first hit like a shadow ray.
+  /// param ray_origin: The world space origin of the ray.
+  /// param ray_dir: The world space direction of the ray, it is normalized here.
+  /// param t_max: The max distance.
+  /// return: True if the ray hits any triangle.
+  pub fn intersect_ray_any(&self, ray_origin: Vec3, ray_dir: Vec3, t_max: f32) -> bool {
+    let ray_dir = match ray_dir.try_normalize() {
+      Some(ray_dir) => ray_dir,
+      None => return false,
+    };
+    let mut is_hit = false;
+    self.traverse_ray(ray_origin, ray_dir, t_max, |_, _, _, _, _| {
+      is_hit = true;
+      f32::NEG_INFINITY
+    });
+    is_hit
+  }
+
+  /// Get the closest hits of a batch of rays in parallel.
+  /// param rays: The world space origins and directions of the rays.
+  /// param t_max: The max distance.
+  /// return: The closest hit of each ray.
+  pub fn intersect_rays(&self, rays: &[(Vec3, Vec3)], t_max: f32) -> Vec<Option<HalaPickResult>> {
+    rays.par_iter()
+      .map(|&(ray_origin, ray_dir)| self.intersect_ray(ray_origin, ray_dir, t_max))
+      .collect()
+  }
+
+  /// Get the triangles which overlap the bounds.
+  /// param bounds: The world space bounds.
+  /// return: The triangles.
+  pub fn overlap_aabb(&self, bounds: &HalaBounds) -> Vec<HalaBvhTriangle> {
+    let center = Vec3::from(bounds.center);
+    let extents = Vec3::from(bounds.extents);
+    let (min, max) = (center - extents, center + extents);
+    let overlap_distance = |node_min: Vec3, node_max: Vec3| {
+      if node_min.cmple(max).all() && node_max.cmpge(min).all() {
+        Some(0.0)
+      } else {
+        None
+      }
+    };
+
+    let mut triangles = Vec::new();
+    self.tree.traverse(f32::INFINITY, overlap_distance, |instance_index, cutoff| {
+      let instance = &self.instances[instance_index as usize];
+      let primitive = &self.primitives[instance.primitive_index as usize];
+      primitive.tree.traverse(
+        cutoff,
+        |node_min, node_max| {
+          let (node_min, node_max) = transform_bounds(&instance.local_to_world, node_min, node_max);
+          overlap_distance(node_min, node_max)
+        },
+        |triangle_index, cutoff| {
+          let triangle = instance.transform_triangle(&primitive.triangles[triangle_index as usize]);
+          if overlap_triangle_bounds(center, extents, &triangle) {
+            triangles.push(instance.get_triangle(triangle_index));
+          }
+          cutoff
+        },
+      )
+    });
+    triangles
+  }
+
+  /// Get the closest point on the triangles to a point.
+  /// param point: The world space point.
+  /// param max_distance: The max distance from the point.
+  /// return: The closest point, none if no triangle is within the max distance.
+  pub fn closest_point(&self, point: Vec3, max_distance: f32) -> Option<HalaBvhClosestPoint> {
+    let point_distance = |node_min: Vec3, node_max: Vec3| Some((node_min - point).max(point - node_max).max(Vec3::ZERO).length());
+
+    let mut closest = None;
+    self.tree.traverse(max_distance, point_distance, |instance_index, cutoff| {
+      let instance = &self.instances[instance_index as usize];
+      let primitive = &self.primitives[instance.primitive_index as usize];
+      primitive.tree.traverse(
+        cutoff,
+        |node_min, node_max| {
+          let (node_min, node_max) = transform_bounds(&instance.local_to_world, node_min, node_max);
+          point_distance(node_min, node_max)
+        },
+        |triangle_index, cutoff| {
+          let triangle = instance.transform_triangle(&primitive.triangles[triangle_index as usize]);
+          let position = closest_point_on_triangle(point, &triangle);
+          let distance = position.distance(point);
+          if distance > cutoff {
+            return cutoff;
+          }
+          closest = Some(HalaBvhClosestPoint {
+            triangle: instance.get_triangle(triangle_index),
+            position,
+            distance,
+          });
+          distance
+        },
+      )
+    });
+    closest
+  }
+
+  /// Traverse the triangles hit by a ray from near to far.
+  /// param ray_origin: The world space origin of the ray.
+  /// param ray_dir: The world space normalized direction of the ray.
+  /// param t_max: The max distance.
+  /// param on_hit: The function called with the instance, the triangle index, the distance, the barycentrics and the local normal of a hit closer than the previous ones,
+  /// it returns the new max distance.
+  fn traverse_ray<F>(&self, ray_origin: Vec3, ray_dir: Vec3, t_max: f32, mut on_hit: F)
+    where F: FnMut(&HalaBvhInstance, u32, f32, glam::Vec2, Vec3) -> f32
+  {
+    let inv_dir = ray_dir.recip();
+    self.tree.traverse(
+      t_max,
+      |node_min, node_max| intersect_bounds(node_min, node_max, ray_origin, inv_dir),
+      |instance_index, cutoff| {
+        // The distance is kept in the local space because the direction is transformed without the normalization.
+        let instance = &self.instances[instance_index as usize];
+        let primitive = &self.primitives[instance.primitive_index as usize];
+        let local_origin = instance.world_to_local.transform_point3(ray_origin);
+        let local_dir = instance.world_to_local.transform_vector3(ray_dir);
+        let local_inv_dir = local_dir.recip();
+        primitive.tree.traverse(
+          cutoff,
+          |node_min, node_max| intersect_bounds(node_min, node_max, local_origin, local_inv_dir),
+          |triangle_index, cutoff| {
+            let [p0, p1, p2] = primitive.triangles[triangle_index as usize];
+            match intersect_triangle(local_origin, local_dir, p0, p1, p2, cutoff) {
+              Some((t, barycentrics)) => on_hit(instance, triangle_index, t, barycentrics, (p1 - p0).cross(p2 - p0)),
+              None => cutoff,
+            }
+          },
+        )
+      },
+    );
+  }
+
+  /// Rebuild the instances and the top tree with the node transforms.
+  /// param scene: The CPU scene.
+  fn update_instances(&mut self, scene: &cpu::HalaScene) {
+    self.instances.clear();
+    let mut bounds = Vec::new();
+    for (node_index, node) in scene.nodes.iter().enumerate() {
+      let primitive_offset = match self.primitive_offsets.get(node.mesh_index as usize) {
+        Some(primitive_offset) => *primitive_offset,
+        None => continue,
+      };
+      for prim_index in 0..scene.meshes[node.mesh_index as usize].primitives.len() as u32 {
+        let primitive_index = primitive_offset + prim_index;
+        let root = match self.primitives[primitive_index as usize].tree.nodes.first() {
+          Some(root) => root,
+          None => continue,
+        };
+        bounds.push(transform_bounds(&node.world_transform, root.min, root.max));
+        self.instances.push(HalaBvhInstance {
+          node_index: node_index as u32,
+          mesh_index: node.mesh_index,
+          prim_index,
+          primitive_index,
+          local_to_world: node.world_transform,
+          world_to_local: node.world_transform.inverse(),
+        });
+      }
+    }
+
+    self.tree = HalaBvhTree::new(&bounds, 1);
+  }
+
+}
+
+/// The implementation of the primitive tree.
+impl HalaBvhPrimitive {
+
+  /// Build the tree of the primitive triangles.
+  /// param primitive: The primitive.
+  /// return: The primitive tree.
+  fn new(primitive: &cpu::mesh::HalaPrimitive) -> Self {
+    let triangles = primitive.indices.chunks_exact(3)
+      .map(|triangle| [triangle[0], triangle[1], triangle[2]].map(|index| Vec3::from(primitive.vertices[index as usize].position)))
+      .collect::<Vec<_>>();
+    let bounds = triangles.iter()
+      .map(|[p0, p1, p2]| (p0.min(*p1).min(*p2), p0.max(*p1).max(*p2)))
+      .collect::<Vec<_>>();
+
+    Self {
+      tree: HalaBvhTree::new(&bounds, MAX_LEAF_TRIANGLES),
+      triangles,
+    }
+  }
+
+}
+
+/// The implementation of the instance.
+impl HalaBvhInstance {
+
+  /// Transform a local space triangle to the world space.
+  /// param triangle: The local space triangle.
+  /// return: The world space triangle.
+  fn transform_triangle(&self, triangle: &[Vec3; 3]) -> [Vec3; 3] {
+    triangle.map(|position| self.local_to_world.transform_point3(position))
+  }
+
+  /// Get the scene reference of a triangle of the instance.
+  /// param triangle_index: The triangle index in the primitive.
+  /// return: The triangle.
+  fn get_triangle(&self, triangle_index: u32) -> HalaBvhTriangle {
+    HalaBvhTriangle {
+      node_index: self.node_index,
+      mesh_index: self.mesh_index,
+      prim_index: self.prim_index,
+      triangle_index,
+    }
+  }
+
+}
+
+/// The implementation of the tree.
+impl HalaBvhTree {
+
+  /// Build the tree with the binned SAH over the centroids.
+  /// param bounds: The bounds(min, max) of each item.
+  /// param max_leaf_size: The max item count of a leaf.
+  /// return: The tree.
+  fn new(bounds: &[(Vec3, Vec3)], max_leaf_size: usize) -> Self {
+    let mut tree = Self {
+      nodes: Vec::with_capacity(bounds.len() * 2),
+      items: (0..bounds.len() as u32).collect(),
+    };
+    if bounds.is_empty() {
+      return tree;
+    }
+
+    let centroids = bounds.iter().map(|(min, max)| (*min + *max) * 0.5).collect::<Vec<_>>();
+    tree.nodes.push(HalaBvhNode { min: Vec3::ZERO, max: Vec3::ZERO, offset: 0, count: 0 });
+    let mut stack = vec![(0usize, 0usize, bounds.len())];
+    while let Some((node_index, start, end)) = stack.pop() {
+      let items = &mut tree.items[start..end];
+      let (mut min, mut max) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+      let (mut centroid_min, mut centroid_max) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+      for &item in items.iter() {
+        min = min.min(bounds[item as usize].0);
+        max = max.max(bounds[item as usize].1);
+        centroid_min = centroid_min.min(centroids[item as usize]);
+        centroid_max = centroid_max.max(centroids[item as usize]);
+      }
+      tree.nodes[node_index].min = min;
+      tree.nodes[node_index].max = max;
+
+      let count = end - start;
+      let centroid_extents = centroid_max - centroid_min;
+      let axis = if centroid_extents.x >= centroid_extents.y && centroid_extents.x >= centroid_extents.z {
+        0
+      } else if centroid_extents.y >= centroid_extents.z {
+        1
+      } else {
+        2
+      };
+      if count <= max_leaf_size || centroid_extents[axis] <= 0.0 {
+        tree.nodes[node_index].offset = start as u32;
+        tree.nodes[node_index].count = count as u32;
+        continue;
+      }
+
+      // Bin the centroids along the longest axis and split at the bin boundary with the least surface area cost.
+      let scale = SAH_BINS as f32 / centroid_extents[axis];
+      let get_bin = |item: u32| (((centroids[item as usize][axis] - centroid_min[axis]) * scale) as usize).min(SAH_BINS - 1);
+      let mut bin_counts = [0usize; SAH_BINS];
+      let mut bin_bounds = [(Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)); SAH_BINS];
+      for &item in items.iter() {
+        let bin = get_bin(item);
+        bin_counts[bin] += 1;
+        bin_bounds[bin].0 = bin_bounds[bin].0.min(bounds[item as usize].0);
+        bin_bounds[bin].1 = bin_bounds[bin].1.max(bounds[item as usize].1);
+      }
+      let mut left_costs = [0f32; SAH_BINS];
+      let (mut left_min, mut left_max, mut left_count) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN), 0);
+      for bin in 0..SAH_BINS - 1 {
+        left_min = left_min.min(bin_bounds[bin].0);
+        left_max = left_max.max(bin_bounds[bin].1);
+        left_count += bin_counts[bin];
+        left_costs[bin + 1] = get_half_area(left_min, left_max) * left_count as f32;
+      }
+      let (mut right_min, mut right_max, mut right_count) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN), 0);
+      let mut best_split = SAH_BINS / 2;
+      let mut best_cost = f32::MAX;
+      for bin in (1..SAH_BINS).rev() {
+        right_min = right_min.min(bin_bounds[bin].0);
+        right_max = right_max.max(bin_bounds[bin].1);
+        right_count += bin_counts[bin];
+        let cost = left_costs[bin] + get_half_area(right_min, right_max) * right_count as f32;
+        if right_count < count && cost < best_cost {
+          best_cost = cost;
+          best_split = bin;
+        }
+      }
+
+      // Partition the items by the split bin, fall back to the median if all items are on one side.
+      let mut middle = 0;
+      for i in 0..count {
+        if get_bin(items[i]) < best_split {
+          items.swap(i, middle);
+          middle += 1;
+        }
+      }
+      if middle == 0 || middle == count {
+        middle = count / 2;
+        items.select_nth_unstable_by(middle, |a, b| centroids[*a as usize][axis].total_cmp(&centroids[*b as usize][axis]));
+      }
+
+      let child_index = tree.nodes.len();
+      tree.nodes[node_index].offset = child_index as u32;
+      tree.nodes.push(HalaBvhNode { min: Vec3::ZERO, max: Vec3::ZERO, offset: 0, count: 0 });
+      tree.nodes.push(HalaBvhNode { min: Vec3::ZERO, max: Vec3::ZERO, offset: 0, count: 0 });
+      stack.push((child_index, start, start + middle));
+      stack.push((child_index + 1, start + middle, end));
+    }
+
+    tree
+  }
+
+  /// Traverse the nodes from near to far and visit the items of the leaves.
+  /// param cutoff: The initial max distance, the nodes farther than it are skipped.
+  /// param node_distance: The function returns the distance to the node bounds(min, max), none to skip the node.
+  /// param visit_item: The function called with the item and the current max distance, it returns the new max distance.
+  /// return: The final max distance.
+  fn traverse<N, V>(&self, mut cutoff: f32, mut node_distance: N, mut visit_item: V) -> f32
+    where N: FnMut(Vec3, Vec3) -> Option<f32>, V: FnMut(u32, f32) -> f32
+  {
+    let root = match self.nodes.first() {
+      Some(root) => root,
+      None => return cutoff,
+    };
+    let mut stack = match node_distance(root.min, root.max) {
+      Some(distance) => vec![(0u32, distance)],
+      None => return cutoff,
+    };
+
+    while let Some((node_index, distance)) = stack.pop() {
+      if distance > cutoff {
+        continue;
+      }
+
+      let node = &self.nodes[node_index as usize];
+      if node.count > 0 {
+        for &item in self.items[node.offset as usize..(node.offset + node.count) as usize].iter() {
+          cutoff = visit_item(item, cutoff);
+        }
+        continue;
+      }
+
+      // Push the farther child first, so the nearer one is visited first.
+      let children = [node.offset, node.offset + 1].map(|child_index| {
+        let child = &self.nodes[child_index as usize];
+        node_distance(child.min, child.max).map(|distance| (child_index, distance))
+      });
+      match children {
+        [Some(a), Some(b)] => {
+          let (near, far) = if a.1 <= b.1 { (a, b) } else { (b, a) };
+          stack.push(far);
+          stack.push(near);
+        },
+        [Some(child), None] | [None, Some(child)] => stack.push(child),
+        [None, None] => (),
+      }
+    }
+
+    cutoff
+  }
+
+}
+
+/// Get the half surface area of the bounds.
+/// param min: The minimum of the bounds.
+/// param max: The maximum of the bounds.
+/// return: The half surface area, 0 if the bounds are empty.
+fn get_half_area(min: Vec3, max: Vec3) -> f32 {
+  let size = (max - min).max(Vec3::ZERO);
+  size.x * size.y + size.y * size.z + size.z * size.x
+}
+
+/// Transform the bounds and get the bounds of the result.
+/// param mtx: The transform matrix.
+/// param min: The minimum of the bounds.
+/// param max: The maximum of the bounds.
+/// return: The minimum and the maximum of the transformed bounds.
+fn transform_bounds(mtx: &Mat4, min: Vec3, max: Vec3) -> (Vec3, Vec3) {
+  let center = mtx.transform_point3((min + max) * 0.5);
+  let extents = (max - min) * 0.5;
+  let extents = mtx.x_axis.truncate().abs() * extents.x
+    + mtx.y_axis.truncate().abs() * extents.y
+    + mtx.z_axis.truncate().abs() * extents.z;
+  (center - extents, center + extents)
+}
+
+/// Check if a triangle overlaps the bounds with the separating axis test.
+/// param center: The center of the bounds.
+/// param extents: The extents of the bounds.
+/// param triangle: The triangle.
+/// return: True if they overlap.
+fn overlap_triangle_bounds(center: Vec3, extents: Vec3, triangle: &[Vec3; 3]) -> bool {
+  let vertices = triangle.map(|position| position - center);
+  let check_axis = |axis: Vec3| {
+    let distances = vertices.map(|vertex| axis.dot(vertex));
+    let radius = extents.dot(axis.abs());
+    distances[0].min(distances[1]).min(distances[2]) <= radius && distances[0].max(distances[1]).max(distances[2]) >= -radius
+  };
+
+  // The axes of the bounds, the normal of the triangle and the cross products of their edges.
+  if !(check_axis(Vec3::X) && check_axis(Vec3::Y) && check_axis(Vec3::Z)) {
+    return false;
+  }
+  let edges = [vertices[1] - vertices[0], vertices[2] - vertices[1], vertices[0] - vertices[2]];
+  if !check_axis(edges[0].cross(edges[1])) {
+    return false;
+  }
+  edges.iter().all(|edge| [Vec3::X, Vec3::Y, Vec3::Z].iter().all(|axis| check_axis(axis.cross(*edge))))
+}
+
+/// Get the closest point on a triangle to a point, see Real-Time Collision Detection 5.1.5.
+/// param point: The point.
+/// param triangle: The triangle.
+/// return: The closest point on the triangle.
+fn closest_point_on_triangle(point: Vec3, triangle: &[Vec3; 3]) -> Vec3 {
+  let [a, b, c] = *triangle;
+  let ab = b - a;
+  let ac = c - a;
+  let ap = point - a;
+  let d1 = ab.dot(ap);
+  let d2 = ac.dot(ap);
+  if d1 <= 0.0 && d2 <= 0.0 {
+    return a;
+  }
+
+  let bp = point - b;
+  let d3 = ab.dot(bp);
+  let d4 = ac.dot(bp);
+  if d3 >= 0.0 && d4 <= d3 {
+    return b;
+  }
+
+  let vc = d1 * d4 - d3 * d2;
+  if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+    return a + ab * (d1 / (d1 - d3));
+  }
+
+  let cp = point - c;
+  let d5 = ab.dot(cp);
+  let d6 = ac.dot(cp);
+  if d6 >= 0.0 && d5 <= d6 {
+    return c;
+  }
+
+  let vb = d5 * d2 - d1 * d6;
+  if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+    return a + ac * (d2 / (d2 - d6));
+  }
+
+  let va = d3 * d6 - d5 * d4;
+  if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+    return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+  }
+
+  // The point projects inside the face.
+  let denom = 1.0 / (va + vb + vc);
+  a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::scene::cpu::material::HalaMaterial;
+  use crate::scene::cpu::shapes;
+
+  /// Build a scene of 2 cubes with the edge length 2, centered at the translations.
+  /// param translations: The translations of the cube nodes.
+  /// return: The scene.
+  fn build_cubes(translations: &[Vec3]) -> cpu::HalaScene {
+    let mut builder = cpu::HalaScene::builder();
+    let material_index = builder.add_material(HalaMaterial::default());
+    let mesh_index = builder.add_mesh(shapes::cube(2.0), material_index);
+    for (index, translation) in translations.iter().enumerate() {
+      builder.add_node(&format!("cube{}", index), None, Mat4::from_translation(*translation), Some(mesh_index), None, None);
+    }
+    builder.build().unwrap()
+  }
+
+  #[test]
+  fn test_ray_hit() {
+    let scene = build_cubes(&[Vec3::ZERO]);
+    let bvh = HalaBvh::new(&scene);
+
+    let hit = bvh.intersect_ray(Vec3::new(0.25, -0.5, 5.0), Vec3::NEG_Z, f32::INFINITY).unwrap();
+    assert_eq!(hit.node_index, 0);
+    assert!((hit.t - 4.0).abs() < 1e-5);
+    assert!(hit.position.abs_diff_eq(Vec3::new(0.25, -0.5, 1.0), 1e-5));
+    assert!(hit.normal.abs_diff_eq(Vec3::Z, 1e-5));
+    assert!(bvh.intersect_ray_any(Vec3::new(0.25, -0.5, 5.0), Vec3::NEG_Z, f32::INFINITY));
+  }
+
+  #[test]
+  fn test_ray_miss() {
+    let scene = build_cubes(&[Vec3::ZERO]);
+    let bvh = HalaBvh::new(&scene);
+
+    // Beside the cube, away from the cube and before the cube.
+    assert!(bvh.intersect_ray(Vec3::new(3.0, 0.0, 5.0), Vec3::NEG_Z, f32::INFINITY).is_none());
+    assert!(bvh.intersect_ray(Vec3::new(0.0, 0.0, 5.0), Vec3::Z, f32::INFINITY).is_none());
+    assert!(bvh.intersect_ray(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z, 3.5).is_none());
+    assert!(!bvh.intersect_ray_any(Vec3::new(3.0, 0.0, 5.0), Vec3::NEG_Z, f32::INFINITY));
+  }
+
+  #[test]
+  fn test_nearest_hit() {
+    // The far cube is added first, so the nearest hit does not depend on the node order.
+    let scene = build_cubes(&[Vec3::new(0.0, 0.0, -6.0), Vec3::ZERO]);
+    let bvh = HalaBvh::new(&scene);
+
+    let hit = bvh.intersect_ray(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z, f32::INFINITY).unwrap();
+    assert_eq!(hit.node_index, 1);
+    assert!((hit.t - 4.0).abs() < 1e-5);
+
+    // From the other side the far cube is the nearest one.
+    let hit = bvh.intersect_ray(Vec3::new(0.0, 0.0, -12.0), Vec3::Z, f32::INFINITY).unwrap();
+    assert_eq!(hit.node_index, 0);
+    assert!((hit.t - 5.0).abs() < 1e-5);
+
+    // The batch returns the same hits in the ray order.
+    let hits = bvh.intersect_rays(&[(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z), (Vec3::new(3.0, 0.0, 5.0), Vec3::NEG_Z)], f32::INFINITY);
+    assert_eq!(hits[0].map(|hit| hit.node_index), Some(1));
+    assert!(hits[1].is_none());
+  }
+
+  #[test]
+  fn test_refit() {
+    let mut scene = build_cubes(&[Vec3::ZERO]);
+    let mut bvh = HalaBvh::new(&scene);
+
+    scene.nodes[0].local_transform = Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0));
+    scene.update_transforms();
+    bvh.refit(&scene).unwrap();
+
+    assert!(bvh.intersect_ray(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z, f32::INFINITY).is_none());
+    let hit = bvh.intersect_ray(Vec3::new(10.0, 0.0, 5.0), Vec3::NEG_Z, f32::INFINITY).unwrap();
+    assert!((hit.t - 4.0).abs() < 1e-5);
+  }
+
+  #[test]
+  fn test_overlap_and_closest_point() {
+    let scene = build_cubes(&[Vec3::ZERO]);
+    let bvh = HalaBvh::new(&scene);
+
+    // The small box touches only the +Z face(2 triangles).
+    let triangles = bvh.overlap_aabb(&HalaBounds::new([0.0, 0.0, 1.0], [0.1, 0.1, 0.1]));
+    assert_eq!(triangles.len(), 2);
+    assert!(bvh.overlap_aabb(&HalaBounds::new([0.0, 0.0, 5.0], [0.1, 0.1, 0.1])).is_empty());
+
+    let closest = bvh.closest_point(Vec3::new(0.0, 0.0, 3.0), f32::INFINITY).unwrap();
+    assert!((closest.distance - 2.0).abs() < 1e-5);
+    assert!(closest.position.abs_diff_eq(Vec3::new(0.0, 0.0, 1.0), 1e-5));
+    assert!(bvh.closest_point(Vec3::new(0.0, 0.0, 3.0), 1.0).is_none());
+  }
+
+}
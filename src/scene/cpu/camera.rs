@@ -1,6 +1,60 @@
 use glam::Mat4;
+use serde::{
+  Deserialize,
+  Serialize,
+};
+
+/// The depth convention of the projection and the depth test.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaDepthConvention(u8);
+impl HalaDepthConvention {
+  /// The near plane maps to 1 and the far plane maps to 0, the depth test uses greater.
+  pub const REVERSE_Z: Self = Self(0);
+  /// The near plane maps to 0 and the far plane maps to 1, the depth test uses less.
+  pub const STANDARD_Z: Self = Self(1);
+
+  /// Whether the convention is reverse Z.
+  /// return: True if the convention is reverse Z.
+  pub fn is_reverse_z(&self) -> bool {
+    *self == Self::REVERSE_Z
+  }
+
+  /// Get the depth clear value, it is the far plane depth.
+  /// return: The depth clear value.
+  pub fn get_clear_depth(&self) -> f32 {
+    if self.is_reverse_z() { 0.0 } else { 1.0 }
+  }
+}
+
+/// The Default implementation of the depth convention.
+impl Default for HalaDepthConvention {
+  fn default() -> Self {
+    Self::REVERSE_Z
+  }
+}
+
+/// The type of the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaCameraType(u8);
+impl HalaCameraType {
+  pub const PERSPECTIVE: Self = Self(0);
+  pub const ORTHOGRAPHIC: Self = Self(1);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::PERSPECTIVE,
+      1 => Self::ORTHOGRAPHIC,
+      _ => panic!("Invalid camera type."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+}
 
 /// A perspective camera in the scene.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct HalaPerspectiveCamera {
   pub aspect: f32,
   pub yfov: f32,
@@ -13,14 +67,19 @@ pub struct HalaPerspectiveCamera {
 }
 
 /// A orthographic camera in the scene.
+/// The xmag and ymag are the half width and the half height of the view volume.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct HalaOrthographicCamera {
   pub xmag: f32,
   pub ymag: f32,
+  pub znear: f32,
+  pub zfar: f32,
 
   pub orthography: Mat4,
 }
 
 /// A camera in the scene.
+#[derive(Clone, Serialize, Deserialize)]
 pub enum HalaCamera {
   Perspective(HalaPerspectiveCamera),
   Orthographic(HalaOrthographicCamera),
@@ -28,10 +87,51 @@ pub enum HalaCamera {
 
 impl HalaCamera {
 
-  pub fn get_proj_matrix(&self) -> Mat4 {
+  /// Get the type of the camera.
+  /// return: The camera type.
+  pub fn get_type(&self) -> HalaCameraType {
+    match self {
+      HalaCamera::Perspective(_) => HalaCameraType::PERSPECTIVE,
+      HalaCamera::Orthographic(_) => HalaCameraType::ORTHOGRAPHIC,
+    }
+  }
+
+  /// Get the projection matrix of the camera.
+  /// The stored perspective projection is infinite reverse Z, the standard Z one uses the far plane.
+  /// The stored orthographic projection is reverse Z, the standard Z one swaps the near and far planes back.
+  /// param depth_convention: The depth convention of the renderer.
+  /// return: The projection matrix.
+  pub fn get_proj_matrix(&self, depth_convention: HalaDepthConvention) -> Mat4 {
+    match self {
+      HalaCamera::Perspective(camera) => if depth_convention.is_reverse_z() {
+        camera.projection
+      } else {
+        Mat4::perspective_rh(camera.yfov, camera.aspect, camera.znear, camera.zfar)
+      },
+      HalaCamera::Orthographic(camera) => if depth_convention.is_reverse_z() {
+        camera.orthography
+      } else {
+        Mat4::orthographic_rh(-camera.xmag, camera.xmag, -camera.ymag, camera.ymag, camera.znear, camera.zfar)
+      },
+    }
+  }
+
+  /// Set the near and far planes and rebuild the stored projection.
+  /// The stored perspective projection is infinite, so its far plane is only used by the standard Z projection.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance.
+  pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) {
     match self {
-      HalaCamera::Perspective(camera) => camera.projection,
-      HalaCamera::Orthographic(camera) => camera.orthography,
+      HalaCamera::Perspective(camera) => {
+        camera.znear = znear;
+        camera.zfar = zfar;
+        camera.projection = Mat4::perspective_infinite_reverse_rh(camera.yfov, camera.aspect, znear);
+      },
+      HalaCamera::Orthographic(camera) => {
+        camera.znear = znear;
+        camera.zfar = zfar;
+        camera.orthography = Mat4::orthographic_rh(-camera.xmag, camera.xmag, -camera.ymag, camera.ymag, zfar, znear);
+      },
     }
   }
 
@@ -1,17 +1,24 @@
 use std::path::Path;
 
 use image::GenericImageView;
+use serde::{
+  Deserialize,
+  Serialize,
+};
 
 use hala_gfx::HalaFormat;
 
 use crate::error::HalaRendererError;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum HalaImageDataType {
   ByteData(Vec<u8>),
   FloatData(Vec<f32>),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct HalaImageData {
+  #[serde(with = "format_serde")]
   pub format: HalaFormat,
   pub width: u32,
   pub height: u32,
@@ -27,7 +34,7 @@ impl HalaImageData {
     let path = path.as_ref();
 
     let img = image::open(path)
-      .map_err(|e| HalaRendererError::new(&format!("Failed to open image \"{}\".", path.to_string_lossy()), Some(Box::new(e))))?;
+      .map_err(|e| HalaRendererError::scene_load("Failed to open image.", path, Some(Box::new(e))))?;
     let (width, height) = img.dimensions();
 
     let (format, data, num_of_bytes) = match img.color() {
@@ -46,7 +53,7 @@ impl HalaImageData {
         let num_of_bytes = data.len() * std::mem::size_of::<f32>();
         (HalaFormat::R32G32B32A32_SFLOAT, HalaImageDataType::FloatData(data), num_of_bytes)
       },
-      color_type => return Err(HalaRendererError::new(&format!("Unsupported color type: {:?}", color_type), None)),
+      color_type => return Err(HalaRendererError::scene_load(&format!("Unsupported color type: {:?}", color_type), path, None)),
     };
 
     Ok(Self {
@@ -57,4 +64,89 @@ impl HalaImageData {
       num_of_bytes,
     })
   }
-}
\ No newline at end of file
+  /// Create a 1x1 image data with the average color of the image.
+  /// It is used as the placeholder before the full resolution image is resident.
+  /// return: The placeholder image data.
+  pub fn to_placeholder(&self) -> Self {
+    let num_of_pixels = (self.width as usize * self.height as usize).max(1);
+    let (data_type, num_of_bytes) = match self.data_type {
+      HalaImageDataType::ByteData(ref data) => {
+        let num_of_channels = data.len() / num_of_pixels;
+        let mut sums = vec![0u64; num_of_channels];
+        for pixel in data.chunks_exact(num_of_channels.max(1)) {
+          for (sum, value) in sums.iter_mut().zip(pixel.iter()) {
+            *sum += *value as u64;
+          }
+        }
+        let average = sums.iter().map(|sum| (sum / num_of_pixels as u64) as u8).collect::<Vec<_>>();
+        let num_of_bytes = average.len();
+        (HalaImageDataType::ByteData(average), num_of_bytes)
+      },
+      HalaImageDataType::FloatData(ref data) => {
+        let num_of_channels = data.len() / num_of_pixels;
+        let mut sums = vec![0f64; num_of_channels];
+        for pixel in data.chunks_exact(num_of_channels.max(1)) {
+          for (sum, value) in sums.iter_mut().zip(pixel.iter()) {
+            *sum += *value as f64;
+          }
+        }
+        let average = sums.iter().map(|sum| (sum / num_of_pixels as f64) as f32).collect::<Vec<_>>();
+        let num_of_bytes = average.len() * std::mem::size_of::<f32>();
+        (HalaImageDataType::FloatData(average), num_of_bytes)
+      },
+    };
+
+    Self {
+      format: self.format,
+      width: 1,
+      height: 1,
+      data_type,
+      num_of_bytes,
+    }
+  }
+}
+
+/// The formats of the image data which can be saved in the binary scene, the index in the array is serialized.
+/// Append the new formats at the end, the order is a part of the binary scene format.
+const SERIALIZABLE_FORMATS: [HalaFormat; 12] = [
+  HalaFormat::UNDEFINED,
+  HalaFormat::R8_UNORM,
+  HalaFormat::R8G8_UNORM,
+  HalaFormat::R8G8B8_UNORM,
+  HalaFormat::R8G8B8A8_SRGB,
+  HalaFormat::B8G8R8A8_UNORM,
+  HalaFormat::R16_UNORM,
+  HalaFormat::R16G16_UNORM,
+  HalaFormat::R16G16B16_UNORM,
+  HalaFormat::R16G16B16A16_UNORM,
+  HalaFormat::R32G32B32_SFLOAT,
+  HalaFormat::R32G32B32A32_SFLOAT,
+];
+
+/// Serialize the format by its index in SERIALIZABLE_FORMATS, HalaFormat does not implement serde.
+mod format_serde {
+  use serde::{
+    de::Error as _,
+    ser::Error as _,
+    Deserialize,
+    Deserializer,
+    Serializer,
+  };
+
+  use hala_gfx::HalaFormat;
+
+  use super::SERIALIZABLE_FORMATS;
+
+  pub fn serialize<S: Serializer>(format: &HalaFormat, serializer: S) -> Result<S::Ok, S::Error> {
+    let index = SERIALIZABLE_FORMATS.iter().position(|serializable_format| serializable_format == format)
+      .ok_or_else(|| S::Error::custom("The image format can not be serialized."))?;
+    serializer.serialize_u8(index as u8)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HalaFormat, D::Error> {
+    let index = u8::deserialize(deserializer)?;
+    SERIALIZABLE_FORMATS.get(index as usize)
+      .copied()
+      .ok_or_else(|| D::Error::custom(format!("The image format index {} is invalid.", index)))
+  }
+}
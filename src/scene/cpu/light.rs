@@ -1,7 +1,11 @@
 use glam::Vec3;
+use serde::{
+  Deserialize,
+  Serialize,
+};
 
 /// The type of the light.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HalaLightType(u8);
 impl HalaLightType {
   pub const POINT: Self = Self(0);
@@ -27,13 +31,132 @@ impl HalaLightType {
 }
 
 /// A light source in the scene.
+/// Use the typed constructors and getters instead of the params tuple, the uploader reads the params by the getters.
+#[derive(Serialize, Deserialize)]
 pub struct HalaLight {
   pub color: Vec3,
   pub intensity: f32,
   pub light_type: HalaLightType,
-  /// For directional light, param0 is the angle of the soft shadow edge.
-  /// For spot light, param0 is the cosine of the inner cone angle, param1 is the cosine of the outer cone angle.
+  /// For point light, params are unused.
+  /// For directional light, param0 is the full cone angle of the soft shadow edge in radians, param1 is unused.
+  /// For spot light, param0 is the inner cone angle, param1 is the outer cone angle, both in radians from the axis.
   /// For quad light, param0 is the width, param1 is the height.
   /// For sphere light, param0 is the radius, param1 is unused.
   pub params: (f32, f32),
+  /// The range of the point light and the spot light(KHR_lights_punctual range), the intensity falls to zero at the range.
+  /// None is infinite, the falloff is pure inverse square.
+  pub range: Option<f32>,
+}
+
+/// The implementation of the light.
+impl HalaLight {
+
+  /// Create a point light at the origin of its node.
+  /// param color: The color of the light.
+  /// param intensity: The intensity of the light.
+  /// return: The light.
+  pub fn point(color: Vec3, intensity: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      light_type: HalaLightType::POINT,
+      params: (0.0, 0.0),
+      range: None,
+    }
+  }
+
+  /// Create a directional light along the -Z axis of its node.
+  /// param color: The color of the light.
+  /// param intensity: The intensity of the light.
+  /// param angular_radius: The angular radius of the light source in radians, 0 for the hard shadow.
+  /// return: The light.
+  pub fn directional(color: Vec3, intensity: f32, angular_radius: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      light_type: HalaLightType::DIRECTIONAL,
+      params: (angular_radius * 2.0, 0.0),
+      range: None,
+    }
+  }
+
+  /// Create a spot light along the -Z axis of its node.
+  /// param color: The color of the light.
+  /// param intensity: The intensity of the light.
+  /// param inner_angle: The inner cone angle from the axis in radians.
+  /// param outer_angle: The outer cone angle from the axis in radians, it is swapped with the inner angle if it is smaller.
+  /// return: The light.
+  pub fn spot(color: Vec3, intensity: f32, inner_angle: f32, outer_angle: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      light_type: HalaLightType::SPOT,
+      params: (inner_angle.min(outer_angle), inner_angle.max(outer_angle)),
+      range: None,
+    }
+  }
+
+  /// Create a single side quad light on the XY plane of its node, centered at the origin and facing +Z.
+  /// param color: The color of the light.
+  /// param intensity: The intensity of the light.
+  /// param width: The width along the X axis.
+  /// param height: The height along the Y axis.
+  /// return: The light.
+  pub fn quad(color: Vec3, intensity: f32, width: f32, height: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      light_type: HalaLightType::QUAD,
+      params: (width, height),
+      range: None,
+    }
+  }
+
+  /// Create a sphere light at the origin of its node.
+  /// param color: The color of the light.
+  /// param intensity: The intensity of the light.
+  /// param radius: The radius of the sphere.
+  /// return: The light.
+  pub fn sphere(color: Vec3, intensity: f32, radius: f32) -> Self {
+    Self {
+      color,
+      intensity,
+      light_type: HalaLightType::SPHERE,
+      params: (radius, 0.0),
+      range: None,
+    }
+  }
+
+  /// Set the range of the point light or the spot light.
+  /// param range: The range, none is infinite.
+  /// return: The light.
+  pub fn with_range(mut self, range: Option<f32>) -> Self {
+    self.range = range.filter(|range| *range > 0.0);
+    self
+  }
+
+  /// Get the full cone angle of the directional light.
+  /// return: The cone angle in radians.
+  pub fn get_directional_cone_angle(&self) -> f32 {
+    self.params.0
+  }
+
+  /// Get the cone angles of the spot light.
+  /// return: The inner and the outer cone angles in radians.
+  pub fn get_spot_angles(&self) -> (f32, f32) {
+    self.params
+  }
+
+  /// Get the size of the quad light.
+  /// return: The width and the height.
+  pub fn get_quad_size(&self) -> (f32, f32) {
+    self.params
+  }
+
+  /// Get the radius of the sphere light.
+  /// return: The radius.
+  pub fn get_sphere_radius(&self) -> f32 {
+    self.params.0
+  }
+
 }
\ No newline at end of file
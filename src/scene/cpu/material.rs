@@ -1,7 +1,11 @@
 use glam::Vec3;
+use serde::{
+  Deserialize,
+  Serialize,
+};
 
 /// The type of the material.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Serialize, Deserialize)]
 pub struct HalaMaterialType(u8);
 impl HalaMaterialType {
   pub const DIFFUSE: Self = Self(0);
@@ -21,6 +25,7 @@ impl HalaMaterialType {
 }
 
 /// A material for objects.
+#[derive(Serialize, Deserialize)]
 pub struct HalaMaterial {
   pub _type: HalaMaterialType,
   pub base_color: Vec3,
@@ -47,8 +52,56 @@ pub struct HalaMaterial {
   pub metallic_roughness_map_index: u32,
 }
 
+/// The default implementation of the material, the values are the glTF defaults.
+impl Default for HalaMaterial {
+  fn default() -> Self {
+    Self {
+      _type: HalaMaterialType::DIFFUSE,
+      base_color: Vec3::ONE,
+      opacity: 1.0,
+      emission: Vec3::ZERO,
+      anisotropic: 0.0,
+      metallic: 1.0,
+      roughness: 1.0,
+      subsurface: 0.0,
+      specular_tint: 0.0,
+      sheen: 0.0,
+      sheen_tint: 0.0,
+      clearcoat: 0.0,
+      clearcoat_roughness: 0.0,
+      clearcoat_tint: Vec3::ONE,
+      specular_transmission: 0.0,
+      ior: 1.5,
+
+      medium: HalaMedium {
+        _type: HalaMediumType::NONE,
+        color: Vec3::ZERO,
+        density: 0.0,
+        anisotropy: 0.0,
+      },
+
+      base_color_map_index: u32::MAX,
+      emission_map_index: u32::MAX,
+      normal_map_index: u32::MAX,
+      metallic_roughness_map_index: u32::MAX,
+    }
+  }
+}
+
+/// The implementation of the material.
+impl HalaMaterial {
+
+  /// Whether the material emits light.
+  /// The emission is the glTF emissive factor multiplied by the emissive strength, the emission map is modulated by it.
+  /// return: True if the emission is not black.
+  pub fn is_emissive(&self) -> bool {
+    self.emission.max_element() > 0.0
+  }
+
+}
+
 /// The type of medium.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, Serialize, Deserialize)]
 pub struct HalaMediumType(u8);
 impl HalaMediumType {
   pub const NONE: Self = Self(0);
@@ -72,6 +125,7 @@ impl HalaMediumType {
 }
 
 /// A medium for objects.
+#[derive(Serialize, Deserialize)]
 pub struct HalaMedium {
   pub _type: HalaMediumType,
   pub color: Vec3,
@@ -1,8 +1,13 @@
+use serde::{
+  Deserialize,
+  Serialize,
+};
 use crate::scene::{
   HalaVertex,
   HalaMeshlet,
 };
 
+#[derive(Serialize, Deserialize)]
 pub struct HalaPrimitive {
   pub indices: Vec<u32>,
   pub vertices: Vec<HalaVertex>,
@@ -13,6 +18,7 @@ pub struct HalaPrimitive {
 }
 
 /// A mesh is a collection of vertices and indices that define a 3D object.
+#[derive(Serialize, Deserialize)]
 pub struct HalaMesh {
   pub primitives: Vec<HalaPrimitive>,
 }
\ No newline at end of file
@@ -5,5 +5,11 @@ pub mod mesh;
 pub mod light;
 pub mod camera;
 pub mod scene;
+pub mod shapes;
 
-pub use scene::HalaScene;
\ No newline at end of file
+pub use scene::{
+  HalaScene,
+  HalaSceneStats,
+  HalaSceneBuilder,
+  HalaPrimitiveBounds,
+};
\ No newline at end of file
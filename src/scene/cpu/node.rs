@@ -1,4 +1,10 @@
+use serde::{
+  Deserialize,
+  Serialize,
+};
+
 /// A node is a collection of transforms and child indices.
+#[derive(Serialize, Deserialize)]
 pub struct HalaNode {
   pub name: String,
   pub parent: Option<u32>,
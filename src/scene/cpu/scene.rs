@@ -1,19 +1,87 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::collections::BTreeMap;
+use std::io::Write;
+
+use serde::{
+  Deserialize,
+  Serialize,
+};
 
 use crate::error::HalaRendererError;
 use super::node::HalaNode;
-use super::mesh::HalaMesh;
+use super::mesh::{
+  HalaMesh,
+  HalaPrimitive,
+};
 use super::material::{
   HalaMaterial,
   HalaMediumType
 };
 use super::image_data::HalaImageData;
-use super::light::HalaLight;
-use super::camera::HalaCamera;
+use super::light::{
+  HalaLight,
+  HalaLightType,
+};
+use super::camera::{
+  HalaCamera,
+  HalaPerspectiveCamera,
+};
 use super::super::loader::HalaGltfLoader;
+use super::super::{
+  HalaBounds,
+  HalaVertex,
+};
+
+/// The statistics of a scene.
+#[derive(Debug, Clone)]
+pub struct HalaSceneStats {
+  pub num_of_triangles: usize,
+  pub num_of_vertices: usize,
+  pub num_of_meshlets: usize,
+  pub num_of_unique_materials: usize,
+  pub num_of_textures: usize,
+  pub num_of_texture_bytes: usize,
+  /// The light count indexed by the light type.
+  pub num_of_lights_by_type: [usize; 5],
+  /// The world space bounds of all mesh instances, zero size if the scene has no mesh.
+  pub bounds: HalaBounds,
+}
+
+/// The Display implementation of the scene statistics.
+impl std::fmt::Display for HalaSceneStats {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Triangles: {}, Vertices: {}, Meshlets: {}", self.num_of_triangles, self.num_of_vertices, self.num_of_meshlets)?;
+    writeln!(f, "Materials: {}, Textures: {}({:.2}MB)", self.num_of_unique_materials, self.num_of_textures, self.num_of_texture_bytes as f64 / (1024.0 * 1024.0))?;
+    writeln!(
+      f,
+      "Lights: point {}, directional {}, spot {}, quad {}, sphere {}",
+      self.num_of_lights_by_type[HalaLightType::POINT.to_u8() as usize],
+      self.num_of_lights_by_type[HalaLightType::DIRECTIONAL.to_u8() as usize],
+      self.num_of_lights_by_type[HalaLightType::SPOT.to_u8() as usize],
+      self.num_of_lights_by_type[HalaLightType::QUAD.to_u8() as usize],
+      self.num_of_lights_by_type[HalaLightType::SPHERE.to_u8() as usize],
+    )?;
+    write!(f, "Bounds: min {:?}, max {:?}", self.bounds.get_min(), self.bounds.get_max())
+  }
+}
+
+/// The world space bounds of a primitive instanced by a node.
+#[derive(Debug, Clone, Copy)]
+pub struct HalaPrimitiveBounds {
+  pub node_index: u32,
+  pub mesh_index: u32,
+  pub prim_index: u32,
+  pub bounds: HalaBounds,
+}
+
+/// The magic at the beginning of the binary scene file.
+const BINARY_SCENE_MAGIC: &[u8; 8] = b"HALASCN\0";
+
+/// The version of the binary scene format, increase it when any serialized type of the scene is changed.
+const BINARY_SCENE_VERSION: u32 = 1;
 
 /// A scene is a collection of objects and lights.
+#[derive(Serialize, Deserialize)]
 pub struct HalaScene {
   pub nodes: Vec<HalaNode>,
   pub meshes: Vec<HalaMesh>,
@@ -21,8 +89,12 @@ pub struct HalaScene {
   pub texture2image_mapping: BTreeMap<u32, u32>,
   pub image2data_mapping: BTreeMap<u32, u32>,
   pub image_data: Vec<HalaImageData>,
+  pub image_paths: Vec<Option<PathBuf>>, // The source file of each image data, none if embedded.
   pub lights: Vec<HalaLight>,
   pub cameras: Vec<HalaCamera>,
+  /// The cached bounds of each primitive instance for the ray casts, rebuilt with the world transforms.
+  #[serde(skip)]
+  pub(crate) primitive_bounds: Vec<HalaPrimitiveBounds>,
 }
 
 /// The Drop implementation of the scene.
@@ -41,12 +113,12 @@ impl HalaScene {
     // Check the file extension.
     let path = path.as_ref();
     let extension = path.extension()
-      .ok_or(HalaRendererError::new(&format!("Get file \"{:?}\" extension failed.", path), None))?;
+      .ok_or(HalaRendererError::scene_load("Get file extension failed.", path, None))?;
     let mut scene = match extension.to_str() {
       // glTF file.
       Some("gltf") => HalaGltfLoader::load(path),
       // Unsupported file.
-      _ => Err(HalaRendererError::new(&format!("Unsupported file \"{:?}\".", path), None)),
+      _ => Err(HalaRendererError::scene_load("Unsupported file.", path, None)),
     }?;
     scene.update_node_hierarchies();
 
@@ -54,6 +126,62 @@ impl HalaScene {
     Ok(scene)
   }
 
+  /// Save the scene to a binary file, which loads much faster than the source glTF file.
+  /// The image data and the meshlets which are built are saved too.
+  /// param path: The path to the binary file.
+  /// return: The result.
+  pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), HalaRendererError> {
+    let path = path.as_ref();
+    let file = std::fs::File::create(path)
+      .map_err(|e| HalaRendererError::io("Failed to create the binary scene file.", path, e))?;
+    let mut writer = std::io::BufWriter::new(file);
+    writer.write_all(BINARY_SCENE_MAGIC)
+      .and_then(|_| writer.write_all(&BINARY_SCENE_VERSION.to_le_bytes()))
+      .map_err(|e| HalaRendererError::io("Failed to write the binary scene header.", path, e))?;
+    bincode::serialize_into(&mut writer, self)
+      .map_err(|e| HalaRendererError::new(&format!("Failed to serialize the scene to \"{}\".", path.to_string_lossy()), Some(e)))?;
+    writer.flush()
+      .map_err(|e| HalaRendererError::io("Failed to write the binary scene file.", path, e))?;
+
+    log::debug!("A HalaScene saved to \"{}\".", path.to_string_lossy());
+    Ok(())
+  }
+
+  /// Load the scene from a binary file saved by save_binary.
+  /// param path: The path to the binary file.
+  /// return: The scene.
+  pub fn load_binary<P: AsRef<Path>>(path: P) -> Result<Self, HalaRendererError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path)
+      .map_err(|e| HalaRendererError::io("Failed to read the binary scene file.", path, e))?;
+    let header_size = BINARY_SCENE_MAGIC.len() + std::mem::size_of::<u32>();
+    if data.len() < header_size || &data[..BINARY_SCENE_MAGIC.len()] != BINARY_SCENE_MAGIC {
+      return Err(HalaRendererError::scene_load("The file is not a binary scene.", path, None));
+    }
+    let mut version_bytes = [0u8; 4];
+    version_bytes.copy_from_slice(&data[BINARY_SCENE_MAGIC.len()..header_size]);
+    let version = u32::from_le_bytes(version_bytes);
+    if version != BINARY_SCENE_VERSION {
+      return Err(HalaRendererError::scene_load(
+        &format!("The binary scene version {} is not the supported version {}, save it again from the source file.", version, BINARY_SCENE_VERSION),
+        path,
+        None));
+    }
+
+    let mut scene: Self = bincode::deserialize(&data[header_size..])
+      .map_err(|e| HalaRendererError::scene_load("Failed to deserialize the binary scene.", path, Some(e)))?;
+    scene.update_node_hierarchies();
+
+    log::debug!("A HalaScene loaded from \"{}\".", path.to_string_lossy());
+    Ok(scene)
+  }
+
+  /// Create a builder to construct a scene in code.
+  /// return: The scene builder.
+  pub fn builder() -> HalaSceneBuilder {
+    HalaSceneBuilder::default()
+  }
+
   /// Check if the scene has light.
   /// return: True if the scene has light, false otherwise.
   pub fn has_light(&self) -> bool {
@@ -94,8 +222,68 @@ impl HalaScene {
     false
   }
 
+  /// Get the statistics of the scene.
+  /// The triangle, vertex and meshlet counts are counted per mesh, not per instance.
+  /// return: The statistics.
+  pub fn stats(&self) -> HalaSceneStats {
+    let mut num_of_triangles = 0;
+    let mut num_of_vertices = 0;
+    let mut num_of_meshlets = 0;
+    let mut material_indices = std::collections::BTreeSet::new();
+    for mesh in self.meshes.iter() {
+      for primitive in mesh.primitives.iter() {
+        num_of_triangles += primitive.indices.len() / 3;
+        num_of_vertices += primitive.vertices.len();
+        num_of_meshlets += primitive.meshlets.len();
+        material_indices.insert(primitive.material_index);
+      }
+    }
+
+    let mut num_of_lights_by_type = [0; 5];
+    for light in self.lights.iter() {
+      num_of_lights_by_type[light.light_type.to_u8() as usize] += 1;
+    }
+
+    let mut bounds: Option<HalaBounds> = None;
+    for node in self.nodes.iter() {
+      let mesh = match self.meshes.get(node.mesh_index as usize) {
+        Some(mesh) => mesh,
+        None => continue,
+      };
+      for vertex in mesh.primitives.iter().flat_map(|primitive| primitive.vertices.iter()) {
+        let position = node.world_transform.transform_point3(glam::Vec3::from(vertex.position)).to_array();
+        match bounds.as_mut() {
+          Some(bounds) => bounds.encapsulate_point(position),
+          None => bounds = Some(HalaBounds::new(position, [0.0; 3])),
+        }
+      }
+    }
+
+    HalaSceneStats {
+      num_of_triangles,
+      num_of_vertices,
+      num_of_meshlets,
+      num_of_unique_materials: material_indices.len(),
+      num_of_textures: self.texture2image_mapping.len(),
+      num_of_texture_bytes: self.image_data.iter().map(|image_data| image_data.num_of_bytes).sum(),
+      num_of_lights_by_type,
+      bounds: bounds.unwrap_or(HalaBounds::new([0.0; 3], [0.0; 3])),
+    }
+  }
+
+  /// Get the cached world space bounds of each primitive instance.
+  /// return: The primitive bounds.
+  pub fn get_primitive_bounds(&self) -> &[HalaPrimitiveBounds] {
+    &self.primitive_bounds
+  }
+
+  /// Update the world transforms and the cached primitive bounds after the local transforms of the nodes are changed.
+  pub fn update_transforms(&mut self) {
+    self.update_node_hierarchies();
+  }
+
   /// Update the node hierarchies.
-  /// Set the children and world transform of each node.
+  /// Set the children and world transform of each node, then rebuild the primitive bounds.
   fn update_node_hierarchies(&mut self) {
     let mut temp_children = vec![vec![]; self.nodes.len()];
     let mut temp_world_transforms = vec![glam::Mat4::IDENTITY; self.nodes.len()];
@@ -111,5 +299,310 @@ impl HalaScene {
       temp_children[idx].clone_into(&mut node.children);
       node.world_transform = temp_world_transforms[idx];
     }
+    self.update_primitive_bounds();
+  }
+
+  /// Rebuild the world space bounds of each primitive instance.
+  fn update_primitive_bounds(&mut self) {
+    self.primitive_bounds.clear();
+    for (node_index, node) in self.nodes.iter().enumerate() {
+      let mesh = match self.meshes.get(node.mesh_index as usize) {
+        Some(mesh) => mesh,
+        None => continue,
+      };
+      for (prim_index, primitive) in mesh.primitives.iter().enumerate() {
+        let mut bounds: Option<HalaBounds> = None;
+        for vertex in primitive.vertices.iter() {
+          let position = node.world_transform.transform_point3(glam::Vec3::from(vertex.position)).to_array();
+          match bounds.as_mut() {
+            Some(bounds) => bounds.encapsulate_point(position),
+            None => bounds = Some(HalaBounds::new(position, [0.0; 3])),
+          }
+        }
+        if let Some(bounds) = bounds {
+          self.primitive_bounds.push(HalaPrimitiveBounds {
+            node_index: node_index as u32,
+            mesh_index: node.mesh_index,
+            prim_index: prim_index as u32,
+            bounds,
+          });
+        }
+      }
+    }
+  }
+}
+
+/// The builder to construct a scene in code without a glTF file.
+/// The add methods return the index of the added item, which is referenced by the following items.
+#[derive(Default)]
+pub struct HalaSceneBuilder {
+  nodes: Vec<HalaNode>,
+  meshes: Vec<HalaMesh>,
+  materials: Vec<HalaMaterial>,
+  lights: Vec<HalaLight>,
+  cameras: Vec<HalaCamera>,
+}
+
+/// The implementation of the scene builder.
+impl HalaSceneBuilder {
+
+  /// Add a material.
+  /// param material: The material, e.g. HalaMaterial::default() with the changed fields.
+  /// return: The material index.
+  pub fn add_material(&mut self, material: HalaMaterial) -> u32 {
+    self.materials.push(material);
+    (self.materials.len() - 1) as u32
+  }
+
+  /// Add a mesh, e.g. from the procedural shapes.
+  /// param mesh: The mesh.
+  /// param material_index: The material index of all primitives.
+  /// return: The mesh index.
+  pub fn add_mesh(&mut self, mut mesh: HalaMesh, material_index: u32) -> u32 {
+    for primitive in mesh.primitives.iter_mut() {
+      primitive.material_index = material_index;
+    }
+    self.meshes.push(mesh);
+    (self.meshes.len() - 1) as u32
+  }
+
+  /// Add a mesh with a single primitive, the meshlets are built by the renderer.
+  /// param vertices: The vertices, the tangents should be set for the normal maps.
+  /// param indices: The triangle list indices.
+  /// param material_index: The material index of the primitive.
+  /// return: The mesh index.
+  pub fn add_mesh_from_vertices_indices(&mut self, vertices: Vec<HalaVertex>, indices: Vec<u32>, material_index: u32) -> u32 {
+    self.meshes.push(HalaMesh {
+      primitives: vec![HalaPrimitive {
+        indices,
+        vertices,
+        material_index,
+        meshlets: Vec::new(),
+        meshlet_vertices: Vec::new(),
+        meshlet_primitives: Vec::new(),
+      }],
+    });
+    (self.meshes.len() - 1) as u32
+  }
+
+  /// Add a light, e.g. HalaLight::point or HalaLight::spot.
+  /// param light: The light.
+  /// return: The light index.
+  pub fn add_light(&mut self, light: HalaLight) -> u32 {
+    self.lights.push(light);
+    (self.lights.len() - 1) as u32
+  }
+
+  /// Add a perspective camera with the infinite reverse Z projection like the glTF loader.
+  /// param yfov: The vertical field of view in radians.
+  /// param aspect: The aspect ratio.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance, used by the standard Z depth convention.
+  /// return: The camera index.
+  pub fn add_camera_perspective(&mut self, yfov: f32, aspect: f32, znear: f32, zfar: f32) -> u32 {
+    self.cameras.push(HalaCamera::Perspective(HalaPerspectiveCamera {
+      aspect,
+      yfov,
+      znear,
+      zfar,
+      focal_distance: 10.0,
+      aperture: 0.0,
+      projection: glam::Mat4::perspective_infinite_reverse_rh(yfov, aspect, znear),
+    }));
+    (self.cameras.len() - 1) as u32
+  }
+
+  /// Add a node, the parent must be added before its children.
+  /// param name: The name of the node.
+  /// param parent: The parent node index.
+  /// param local_transform: The transform relative to the parent.
+  /// param mesh_index: The mesh index of the node.
+  /// param camera_index: The camera index of the node.
+  /// param light_index: The light index of the node.
+  /// return: The node index.
+  pub fn add_node(
+    &mut self,
+    name: &str,
+    parent: Option<u32>,
+    local_transform: glam::Mat4,
+    mesh_index: Option<u32>,
+    camera_index: Option<u32>,
+    light_index: Option<u32>,
+  ) -> u32 {
+    self.nodes.push(HalaNode {
+      name: name.to_string(),
+      parent,
+      local_transform,
+      mesh_index: mesh_index.unwrap_or(u32::MAX),
+      camera_index: camera_index.unwrap_or(u32::MAX),
+      light_index: light_index.unwrap_or(u32::MAX),
+      ..Default::default()
+    });
+    (self.nodes.len() - 1) as u32
   }
-}
\ No newline at end of file
+
+  /// Build the scene, the indices are checked and the world transforms are computed.
+  /// return: The scene.
+  pub fn build(self) -> Result<HalaScene, HalaRendererError> {
+    let check = |what: &str, index: u32, count: usize| -> Result<(), HalaRendererError> {
+      if index != u32::MAX && index as usize >= count {
+        return Err(HalaRendererError::new(&format!("The {} index {} is out of range {}!", what, index, count), None));
+      }
+      Ok(())
+    };
+    for (node_index, node) in self.nodes.iter().enumerate() {
+      if let Some(parent) = node.parent {
+        if parent as usize >= node_index {
+          return Err(HalaRendererError::new(&format!("The parent {} of the node {} must be added before it!", parent, node_index), None));
+        }
+      }
+      check("mesh", node.mesh_index, self.meshes.len())?;
+      check("camera", node.camera_index, self.cameras.len())?;
+      check("light", node.light_index, self.lights.len())?;
+    }
+    for primitive in self.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+      check("material", primitive.material_index, self.materials.len())?;
+      if primitive.indices.len() % 3 != 0 || primitive.indices.iter().any(|index| *index as usize >= primitive.vertices.len()) {
+        return Err(HalaRendererError::new("The indices of the mesh are not a valid triangle list!", None));
+      }
+    }
+
+    let mut scene = HalaScene {
+      nodes: self.nodes,
+      meshes: self.meshes,
+      materials: self.materials,
+      texture2image_mapping: BTreeMap::new(),
+      image2data_mapping: BTreeMap::new(),
+      image_data: Vec::new(),
+      image_paths: Vec::new(),
+      lights: self.lights,
+      cameras: self.cameras,
+      primitive_bounds: Vec::new(),
+    };
+    scene.update_node_hierarchies();
+
+    Ok(scene)
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use super::super::shapes;
+
+  /// Build a small scene with a cube under a parent node, a point light, a camera and an image.
+  /// return: The scene.
+  fn build_scene() -> HalaScene {
+    let mut builder = HalaScene::builder();
+    let material_index = builder.add_material(HalaMaterial {
+      base_color: glam::Vec3::new(0.8, 0.2, 0.1),
+      roughness: 0.25,
+      ..Default::default()
+    });
+    let mesh_index = builder.add_mesh(shapes::cube(2.0), material_index);
+    let light_index = builder.add_light(HalaLight::point(glam::Vec3::ONE, 10.0).with_range(Some(5.0)));
+    let camera_index = builder.add_camera_perspective(1.0, 16.0 / 9.0, 0.1, 100.0);
+    let root = builder.add_node("root", None, glam::Mat4::from_translation(glam::Vec3::new(1.0, 2.0, 3.0)), None, None, Some(light_index));
+    builder.add_node("cube", Some(root), glam::Mat4::from_scale(glam::Vec3::splat(2.0)), Some(mesh_index), None, None);
+    builder.add_node("camera", None, glam::Mat4::IDENTITY, None, Some(camera_index), None);
+
+    let mut scene = builder.build().expect("Build the scene failed.");
+    scene.texture2image_mapping.insert(0, 0);
+    scene.image2data_mapping.insert(0, 0);
+    scene.image_data.push(HalaImageData {
+      format: hala_gfx::HalaFormat::R8G8B8A8_SRGB,
+      width: 2,
+      height: 1,
+      data_type: super::super::image_data::HalaImageDataType::ByteData(vec![255, 0, 0, 255, 0, 255, 0, 255]),
+      num_of_bytes: 8,
+    });
+    scene.image_paths.push(Some(PathBuf::from("red_green.png")));
+    scene
+  }
+
+  /// Get a unique temporary path of the binary scene.
+  /// param name: The name of the test.
+  /// return: The path.
+  fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("hala_scene_{}_{}.bin", name, std::process::id()))
+  }
+
+  #[test]
+  fn test_binary_round_trip() {
+    let scene = build_scene();
+    let path = temp_path("round_trip");
+    scene.save_binary(&path).expect("Save the scene failed.");
+    let loaded = HalaScene::load_binary(&path);
+    let _ = std::fs::remove_file(&path);
+    let loaded = loaded.expect("Load the scene failed.");
+
+    assert_eq!(loaded.nodes.len(), scene.nodes.len());
+    for (a, b) in scene.nodes.iter().zip(loaded.nodes.iter()) {
+      assert_eq!(a.name, b.name);
+      assert_eq!(a.parent, b.parent);
+      assert_eq!(a.children, b.children);
+      assert_eq!(a.local_transform, b.local_transform);
+      assert_eq!(a.world_transform, b.world_transform);
+      assert_eq!((a.mesh_index, a.camera_index, a.light_index), (b.mesh_index, b.camera_index, b.light_index));
+    }
+    assert_eq!(loaded.nodes[0].children, [1]);
+
+    assert_eq!(loaded.meshes.len(), scene.meshes.len());
+    for (a, b) in scene.meshes.iter().flat_map(|mesh| mesh.primitives.iter()).zip(loaded.meshes.iter().flat_map(|mesh| mesh.primitives.iter())) {
+      assert_eq!(a.indices, b.indices);
+      assert_eq!(a.material_index, b.material_index);
+      assert_eq!(a.vertices.len(), b.vertices.len());
+      for (va, vb) in a.vertices.iter().zip(b.vertices.iter()) {
+        assert_eq!((va.position, va.normal, va.tangent, va.tex_coord), (vb.position, vb.normal, vb.tangent, vb.tex_coord));
+      }
+      assert_eq!(a.meshlet_vertices, b.meshlet_vertices);
+      assert_eq!(a.meshlet_primitives, b.meshlet_primitives);
+    }
+
+    assert_eq!(loaded.materials.len(), 1);
+    assert_eq!(loaded.materials[0].base_color, scene.materials[0].base_color);
+    assert_eq!(loaded.materials[0].roughness, scene.materials[0].roughness);
+    assert!(loaded.materials[0]._type == scene.materials[0]._type);
+
+    assert_eq!(loaded.lights.len(), 1);
+    assert_eq!(loaded.lights[0].light_type, HalaLightType::POINT);
+    assert_eq!(loaded.lights[0].color, scene.lights[0].color);
+    assert_eq!(loaded.lights[0].intensity, scene.lights[0].intensity);
+    assert_eq!(loaded.lights[0].range, Some(5.0));
+
+    assert_eq!(loaded.cameras.len(), 1);
+    match (&scene.cameras[0], &loaded.cameras[0]) {
+      (HalaCamera::Perspective(a), HalaCamera::Perspective(b)) => {
+        assert_eq!((a.aspect, a.yfov, a.znear, a.zfar), (b.aspect, b.yfov, b.znear, b.zfar));
+        assert_eq!(a.projection, b.projection);
+      },
+      _ => panic!("The camera is not perspective."),
+    }
+
+    // The skipped bounds are rebuilt after loading.
+    assert_eq!(loaded.primitive_bounds.len(), scene.primitive_bounds.len());
+
+    assert_eq!(loaded.texture2image_mapping, scene.texture2image_mapping);
+    assert_eq!(loaded.image2data_mapping, scene.image2data_mapping);
+    assert_eq!(loaded.image_paths, scene.image_paths);
+    assert_eq!(loaded.image_data.len(), 1);
+    let image = &loaded.image_data[0];
+    assert!(image.format == hala_gfx::HalaFormat::R8G8B8A8_SRGB);
+    assert_eq!((image.width, image.height, image.num_of_bytes), (2, 1, 8));
+    match &image.data_type {
+      super::super::image_data::HalaImageDataType::ByteData(data) => assert_eq!(data, &[255, 0, 0, 255, 0, 255, 0, 255]),
+      _ => panic!("The image data is not byte data."),
+    }
+  }
+
+  #[test]
+  fn test_binary_bad_header() {
+    let path = temp_path("bad_header");
+    std::fs::write(&path, b"NOTASCENE").expect("Write the file failed.");
+    let result = HalaScene::load_binary(&path);
+    let _ = std::fs::remove_file(&path);
+    assert!(result.is_err());
+  }
+
+}
@@ -0,0 +1,386 @@
+use std::f32::consts::PI;
+
+use glam::{Vec2, Vec3};
+
+use super::mesh::{
+  HalaMesh,
+  HalaPrimitive,
+};
+use super::super::HalaVertex;
+
+// The procedural meshes for the tests and the demos.
+// Each mesh has one primitive with the material index 0 and the triangle list indices, the meshlets are built by the renderer.
+// The triangles are counter clockwise seen from the outside, which matches the COUNTER_CLOCKWISE front face of the pipelines.
+// The tangent is the direction of the increasing U, so cross(normal, tangent) is the direction of the increasing V like HalaVertex expects.
+
+/// Create a plane on the XZ plane facing +Y and centered at the origin.
+/// U increases along +X and V increases along -Z.
+/// param width: The size along the X axis.
+/// param depth: The size along the Z axis.
+/// param subdivisions: The number of the quads along each axis, at least 1.
+/// return: The mesh.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> HalaMesh {
+  let subdivisions = subdivisions.max(1);
+  build_grid(subdivisions, subdivisions, false, false, |u, v| (
+    Vec3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth),
+    Vec3::Y,
+    Vec3::X,
+  ))
+}
+
+/// Create a cube centered at the origin, each face has its own vertices and the full UV range.
+/// param size: The edge length.
+/// return: The mesh.
+pub fn cube(size: f32) -> HalaMesh {
+  // The normal and the tangent of each face, the bitangent is cross(normal, tangent).
+  const FACES: [(Vec3, Vec3); 6] = [
+    (Vec3::X, Vec3::NEG_Z),
+    (Vec3::NEG_X, Vec3::Z),
+    (Vec3::Y, Vec3::X),
+    (Vec3::NEG_Y, Vec3::X),
+    (Vec3::Z, Vec3::X),
+    (Vec3::NEG_Z, Vec3::NEG_X),
+  ];
+
+  let half_size = size * 0.5;
+  let mut vertices = Vec::with_capacity(FACES.len() * 4);
+  let mut indices = Vec::with_capacity(FACES.len() * 6);
+  for (normal, tangent) in FACES {
+    let bitangent = normal.cross(tangent);
+    let base = vertices.len() as u32;
+    for (u, v) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+      let position = (normal + tangent * (u * 2.0 - 1.0) + bitangent * (v * 2.0 - 1.0)) * half_size;
+      vertices.push(make_vertex(position, normal, tangent, Vec2::new(u, v)));
+    }
+    push_quad(&mut indices, base, base + 1, base + 2, base + 3);
+  }
+
+  make_mesh(vertices, indices)
+}
+
+/// Create a UV sphere centered at the origin, the poles are on the Y axis.
+/// U increases around the Y axis and V increases from the north pole to the south pole.
+/// param radius: The radius.
+/// param rings: The number of the segments from pole to pole, at least 2.
+/// param sectors: The number of the segments around the Y axis, at least 3.
+/// return: The mesh.
+pub fn uv_sphere(radius: f32, rings: u32, sectors: u32) -> HalaMesh {
+  let rings = rings.max(2);
+  let sectors = sectors.max(3);
+  // The triangles touching the poles would be degenerated, so only one triangle of each quad is kept there.
+  build_grid(sectors, rings, true, true, |u, v| {
+    let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    let normal = Vec3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+    (normal * radius, normal, Vec3::new(-sin_phi, 0.0, cos_phi))
+  })
+}
+
+/// Create a capped cylinder centered at the origin along the Y axis.
+/// The side U increases around the Y axis and V increases from the top to the bottom,
+/// the caps are planar mapped like the plane seen from the outside.
+/// param radius: The radius.
+/// param height: The height.
+/// param sectors: The number of the segments around the Y axis, at least 3.
+/// return: The mesh.
+pub fn cylinder(radius: f32, height: f32, sectors: u32) -> HalaMesh {
+  let sectors = sectors.max(3);
+  let half_height = height * 0.5;
+  let mut mesh = build_grid(sectors, 1, false, false, |u, v| {
+    let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+    let normal = Vec3::new(cos_phi, 0.0, sin_phi);
+    (normal * radius + Vec3::new(0.0, half_height - v * height, 0.0), normal, Vec3::new(-sin_phi, 0.0, cos_phi))
+  });
+
+  let primitive = &mut mesh.primitives[0];
+  for (normal, v_sign) in [(Vec3::Y, -1.0), (Vec3::NEG_Y, 1.0)] {
+    let center = primitive.vertices.len() as u32;
+    primitive.vertices.push(make_vertex(normal * half_height, normal, Vec3::X, Vec2::new(0.5, 0.5)));
+    for i in 0..=sectors {
+      let (sin_phi, cos_phi) = (i as f32 / sectors as f32 * 2.0 * PI).sin_cos();
+      primitive.vertices.push(make_vertex(
+        Vec3::new(cos_phi * radius, normal.y * half_height, sin_phi * radius),
+        normal,
+        Vec3::X,
+        Vec2::new(0.5 + cos_phi * 0.5, 0.5 + v_sign * sin_phi * 0.5),
+      ));
+    }
+    for i in 0..sectors {
+      let (current, next) = (center + 1 + i, center + 2 + i);
+      // The ring winds clockwise around +Y, so the top cap reverses it.
+      if normal.y > 0.0 {
+        primitive.indices.extend_from_slice(&[center, next, current]);
+      } else {
+        primitive.indices.extend_from_slice(&[center, current, next]);
+      }
+    }
+  }
+
+  mesh
+}
+
+/// Create a torus centered at the origin around the Y axis.
+/// U increases around the Y axis and V increases around the tube starting from the outer equator.
+/// param major_radius: The distance from the center to the center of the tube.
+/// param minor_radius: The radius of the tube.
+/// param major_segments: The number of the segments around the Y axis, at least 3.
+/// param minor_segments: The number of the segments around the tube, at least 3.
+/// return: The mesh.
+pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> HalaMesh {
+  let major_segments = major_segments.max(3);
+  let minor_segments = minor_segments.max(3);
+  build_grid(major_segments, minor_segments, false, false, |u, v| {
+    let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+    let (sin_psi, cos_psi) = (v * 2.0 * PI).sin_cos();
+    let normal = Vec3::new(cos_psi * cos_phi, -sin_psi, cos_psi * sin_phi);
+    let center = Vec3::new(cos_phi, 0.0, sin_phi) * major_radius;
+    (center + normal * minor_radius, normal, Vec3::new(-sin_phi, 0.0, cos_phi))
+  })
+}
+
+/// Build a parametric surface on a grid of (columns + 1) x (rows + 1) vertices.
+/// The surface function must satisfy cross(dP/du, dP/dv) along the normal, so the quads are counter clockwise.
+/// param columns: The number of the quads along U.
+/// param rows: The number of the quads along V.
+/// param collapse_first_row: The first row of the vertices is one point, only one triangle of each first row quad is kept.
+/// param collapse_last_row: The last row of the vertices is one point, only one triangle of each last row quad is kept.
+/// param surface: The function from (u, v) to the position, the normal and the tangent.
+/// return: The mesh.
+fn build_grid<F>(columns: u32, rows: u32, collapse_first_row: bool, collapse_last_row: bool, surface: F) -> HalaMesh
+  where F: Fn(f32, f32) -> (Vec3, Vec3, Vec3)
+{
+  let mut vertices = Vec::with_capacity(((columns + 1) * (rows + 1)) as usize);
+  for j in 0..=rows {
+    let v = j as f32 / rows as f32;
+    for i in 0..=columns {
+      let u = i as f32 / columns as f32;
+      let (position, normal, tangent) = surface(u, v);
+      vertices.push(make_vertex(position, normal, tangent, Vec2::new(u, v)));
+    }
+  }
+
+  let mut indices = Vec::with_capacity((columns * rows * 6) as usize);
+  for j in 0..rows {
+    for i in 0..columns {
+      let a = j * (columns + 1) + i;
+      let (b, c, d) = (a + 1, a + columns + 1, a + columns + 2);
+      if collapse_first_row && j == 0 {
+        indices.extend_from_slice(&[a, d, c]);
+      } else if collapse_last_row && j == rows - 1 {
+        indices.extend_from_slice(&[a, b, d]);
+      } else {
+        push_quad(&mut indices, a, b, c, d);
+      }
+    }
+  }
+
+  make_mesh(vertices, indices)
+}
+
+/// Push the two triangles of a quad.
+/// param indices: The indices.
+/// param a: The vertex at (u, v).
+/// param b: The vertex at (u + 1, v).
+/// param c: The vertex at (u, v + 1).
+/// param d: The vertex at (u + 1, v + 1).
+fn push_quad(indices: &mut Vec<u32>, a: u32, b: u32, c: u32, d: u32) {
+  indices.extend_from_slice(&[a, b, d, a, d, c]);
+}
+
+/// Make a vertex.
+/// param position: The position.
+/// param normal: The unit normal.
+/// param tangent: The unit tangent.
+/// param tex_coord: The texture coordinate.
+/// return: The vertex.
+fn make_vertex(position: Vec3, normal: Vec3, tangent: Vec3, tex_coord: Vec2) -> HalaVertex {
+  HalaVertex {
+    position: position.into(),
+    normal: normal.into(),
+    tangent: tangent.into(),
+    tex_coord: tex_coord.into(),
+  }
+}
+
+/// Make a mesh with one primitive.
+/// param vertices: The vertices.
+/// param indices: The triangle list indices.
+/// return: The mesh.
+fn make_mesh(vertices: Vec<HalaVertex>, indices: Vec<u32>) -> HalaMesh {
+  HalaMesh {
+    primitives: vec![HalaPrimitive {
+      indices,
+      vertices,
+      material_index: 0,
+      meshlets: Vec::new(),
+      meshlet_vertices: Vec::new(),
+      meshlet_primitives: Vec::new(),
+    }],
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Check the index bounds, the unit normals and tangents, the winding and the UV directions of a mesh.
+  /// param mesh: The mesh.
+  /// param num_of_vertices: The expected vertex count.
+  /// param num_of_triangles: The expected triangle count.
+  fn check_mesh(mesh: &HalaMesh, num_of_vertices: usize, num_of_triangles: usize) {
+    assert_eq!(mesh.primitives.len(), 1);
+    let primitive = &mesh.primitives[0];
+    assert_eq!(primitive.vertices.len(), num_of_vertices);
+    assert_eq!(primitive.indices.len(), num_of_triangles * 3);
+    assert!(primitive.indices.iter().all(|index| (*index as usize) < primitive.vertices.len()));
+
+    for vertex in primitive.vertices.iter() {
+      let normal = Vec3::from(vertex.normal);
+      let tangent = Vec3::from(vertex.tangent);
+      assert!((normal.length() - 1.0).abs() < 1e-5, "The normal {:?} is not unit length.", normal);
+      assert!((tangent.length() - 1.0).abs() < 1e-5, "The tangent {:?} is not unit length.", tangent);
+      assert!(normal.dot(tangent).abs() < 1e-5, "The tangent {:?} is not orthogonal to the normal {:?}.", tangent, normal);
+    }
+
+    for triangle in primitive.indices.chunks_exact(3) {
+      let [v0, v1, v2] = [triangle[0], triangle[1], triangle[2]].map(|index| &primitive.vertices[index as usize]);
+      let (p0, p1, p2) = (Vec3::from(v0.position), Vec3::from(v1.position), Vec3::from(v2.position));
+      let (e1, e2) = (p1 - p0, p2 - p0);
+      let face_normal = e1.cross(e2);
+      assert!(face_normal.length() > 1e-6, "The triangle {:?} is degenerated.", triangle);
+
+      // Counter clockwise seen from the outside.
+      let vertex_normal = Vec3::from(v0.normal) + Vec3::from(v1.normal) + Vec3::from(v2.normal);
+      assert!(face_normal.dot(vertex_normal) > 0.0, "The triangle {:?} winds clockwise.", triangle);
+
+      // The tangent follows the increasing U and cross(normal, tangent) follows the increasing V.
+      let (uv0, uv1, uv2) = (Vec2::from(v0.tex_coord), Vec2::from(v1.tex_coord), Vec2::from(v2.tex_coord));
+      let (duv1, duv2) = (uv1 - uv0, uv2 - uv0);
+      let det = duv1.x * duv2.y - duv2.x * duv1.y;
+      if det.abs() < 1e-8 {
+        continue;
+      }
+      let dp_du = (e1 * duv2.y - e2 * duv1.y) / det;
+      let dp_dv = (e2 * duv1.x - e1 * duv2.x) / det;
+      for vertex in [v0, v1, v2] {
+        let (normal, tangent) = (Vec3::from(vertex.normal), Vec3::from(vertex.tangent));
+        assert!(dp_du.dot(tangent) > 0.0, "The tangent of the triangle {:?} does not follow U.", triangle);
+        assert!(dp_dv.dot(normal.cross(tangent)) > 0.0, "The bitangent of the triangle {:?} does not follow V.", triangle);
+      }
+    }
+  }
+
+  #[test]
+  fn test_plane() {
+    let mesh = plane(2.0, 3.0, 4);
+    check_mesh(&mesh, 5 * 5, 4 * 4 * 2);
+    for vertex in mesh.primitives[0].vertices.iter() {
+      assert_eq!(vertex.position[1], 0.0);
+      assert!(vertex.position[0].abs() <= 1.0 + 1e-6 && vertex.position[2].abs() <= 1.5 + 1e-6);
+    }
+
+    // The subdivisions are clamped to 1.
+    check_mesh(&plane(1.0, 1.0, 0), 4, 2);
+  }
+
+  #[test]
+  fn test_cube() {
+    let mesh = cube(2.0);
+    check_mesh(&mesh, 6 * 4, 6 * 2);
+    for vertex in mesh.primitives[0].vertices.iter() {
+      let position = Vec3::from(vertex.position);
+      assert!((position.abs().max_element() - 1.0).abs() < 1e-6);
+      // The position is on the face of its normal.
+      assert!((position.dot(Vec3::from(vertex.normal)) - 1.0).abs() < 1e-6);
+    }
+  }
+
+  #[test]
+  fn test_uv_sphere() {
+    let (rings, sectors) = (8, 12);
+    let mesh = uv_sphere(2.0, rings, sectors);
+    // The quads touching the poles keep one triangle.
+    check_mesh(&mesh, ((rings + 1) * (sectors + 1)) as usize, ((rings - 2) * sectors * 2 + 2 * sectors) as usize);
+    for vertex in mesh.primitives[0].vertices.iter() {
+      let position = Vec3::from(vertex.position);
+      assert!((position.length() - 2.0).abs() < 1e-5);
+      assert!((position / 2.0).abs_diff_eq(Vec3::from(vertex.normal), 1e-5));
+    }
+  }
+
+  #[test]
+  fn test_cylinder() {
+    let sectors = 12;
+    let mesh = cylinder(1.0, 2.0, sectors);
+    // The side has 2 rows of vertices, each cap has a center and a ring.
+    check_mesh(&mesh, ((sectors + 1) * 2 + (sectors + 2) * 2) as usize, (sectors * 2 + sectors * 2) as usize);
+    for vertex in mesh.primitives[0].vertices.iter() {
+      assert!(vertex.position[1].abs() <= 1.0 + 1e-6);
+    }
+  }
+
+  #[test]
+  fn test_torus() {
+    let (major_segments, minor_segments) = (12, 8);
+    let mesh = torus(2.0, 0.5, major_segments, minor_segments);
+    check_mesh(&mesh, ((major_segments + 1) * (minor_segments + 1)) as usize, (major_segments * minor_segments * 2) as usize);
+    for vertex in mesh.primitives[0].vertices.iter() {
+      // The distance from the tube center circle is the minor radius.
+      let position = Vec3::from(vertex.position);
+      let center = Vec3::new(position.x, 0.0, position.z).normalize() * 2.0;
+      assert!((position.distance(center) - 0.5).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  #[ignore = "requires a GPU"]
+  fn test_upload_cube() {
+    let gpu_req = hala_gfx::HalaGPURequirements {
+      width: 64,
+      height: 64,
+      ..Default::default()
+    };
+    let resources = crate::renderer::HalaRendererResources::new_headless(
+      "shapes_upload_test",
+      &gpu_req,
+      &[(hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, 1)],
+    ).unwrap();
+
+    let mut builder = super::super::HalaScene::builder();
+    let material_index = builder.add_material(super::super::material::HalaMaterial::default());
+    let mesh_index = builder.add_mesh(cube(2.0), material_index);
+    let light_index = builder.add_light(super::super::light::HalaLight::point(Vec3::ONE, 10.0));
+    let camera_index = builder.add_camera_perspective(1.0, 1.0, 0.1, 100.0);
+    builder.add_node("cube", None, glam::Mat4::IDENTITY, Some(mesh_index), None, Some(light_index));
+    builder.add_node("camera", None, glam::Mat4::from_translation(Vec3::new(0.0, 0.0, 5.0)), None, Some(camera_index), None);
+    let mut scene_in_cpu = builder.build().unwrap();
+
+    let context = resources.context.borrow();
+    let scene_in_gpu = crate::scene::loader::HalaSceneGPUUploader::upload(
+      &context,
+      &resources.graphics_command_buffers,
+      &resources.transfer_command_buffers,
+      &mut scene_in_cpu,
+      false,
+      &crate::scene::HalaLodConfig::default(),
+      crate::scene::HalaDepthConvention::default(),
+      false,
+      false,
+      false,
+      false,
+      &crate::scene::HalaMeshletConfig::default(),
+      false,
+      &[],
+      0,
+      None,
+    ).unwrap();
+
+    // The cube has 24 vertices and 36 indices in one primitive, the buffers may be aligned by the allocator.
+    assert_eq!(scene_in_gpu.stats.mesh_primitive_counts, [1]);
+    assert!(scene_in_gpu.stats.vertex_bytes >= (24 * std::mem::size_of::<HalaVertex>()) as u64);
+    assert!(scene_in_gpu.stats.index_bytes >= (36 * std::mem::size_of::<u32>()) as u64);
+
+    context.logical_device.borrow().wait_idle().unwrap();
+  }
+
+}
@@ -4,10 +4,15 @@ use glam::{
 };
 
 use crate::scene::cpu::node::HalaNode as HalaNodeInCPU;
-use crate::scene::cpu::camera::HalaCamera as HalaCameraInCPU;
+use crate::scene::cpu::camera::{
+  HalaCamera as HalaCameraInCPU,
+  HalaCameraType,
+};
 
 /// The camera information in the GPU.
+/// The ray generation shaders branch on the type, the orthographic camera stores the half width and height in place of the focal distance and aperture.
 #[repr(C, align(16))]
+#[derive(Clone, Copy)]
 pub struct HalaCamera {
   pub position: Vec3A,
   pub right: Vec3A,
@@ -41,7 +46,7 @@ impl HalaCamera {
           yfov: camera.yfov,
           focal_distance_or_xmag: camera.focal_distance,
           aperture_or_ymag: camera.aperture,
-          _type: 0,
+          _type: HalaCameraType::PERSPECTIVE.to_u8() as u32,
         }
       },
       HalaCameraInCPU::Orthographic(camera) => {
@@ -53,7 +58,7 @@ impl HalaCamera {
           yfov: 0.0,
           focal_distance_or_xmag: camera.xmag,
           aperture_or_ymag: camera.ymag,
-          _type: 1,
+          _type: HalaCameraType::ORTHOGRAPHIC.to_u8() as u32,
         }
       },
     }
@@ -29,4 +29,63 @@ pub struct HalaLight {
   // For quad light and sphere light, area is the area.
   pub area: f32,
   pub _type: u32,
+  // For point light and spot light, range is the distance where the windowed inverse square falloff reaches zero, 0 is infinite.
+  // The falloff is saturate(1 - (d / range)^4)^2 / d^2, for other lights, range is unused.
+  pub range: f32,
+}
+
+/// The emissive triangle light information in the GPU, used by the path tracer to sample the emissive meshes.
+/// Layout(std430, 64 bytes): p0, area, p1, primitive_index, p2, triangle_index, emission, material_index.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaTriangleLight {
+  // The vertex positions in the world space.
+  pub p0: Vec3,
+  // The area in the world space.
+  pub area: f32,
+  pub p1: Vec3,
+  // The index of the primitive in the primitive uniform buffers, same as the custom index of the instance.
+  pub primitive_index: u32,
+  pub p2: Vec3,
+  // The index of the triangle in the primitive, used to fetch the texture coordinates for the emission map.
+  pub triangle_index: u32,
+  // The emitted radiance, the emission of the material.
+  pub emission: Vec3,
+  pub material_index: u32,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::mem::{align_of, offset_of, size_of};
+
+  #[test]
+  fn test_light_layout() {
+    // The HLSL structure in the structured buffer, the float3 members are padded to 16 bytes except v:
+    // float3 intensity; float3 position; float3 u; float3 v; float radius; float area; uint type; float range;
+    assert_eq!(offset_of!(HalaLight, intensity), 0);
+    assert_eq!(offset_of!(HalaLight, position), 16);
+    assert_eq!(offset_of!(HalaLight, u), 32);
+    assert_eq!(offset_of!(HalaLight, v), 48);
+    assert_eq!(offset_of!(HalaLight, radius), 60);
+    assert_eq!(offset_of!(HalaLight, area), 64);
+    assert_eq!(offset_of!(HalaLight, _type), 68);
+    assert_eq!(offset_of!(HalaLight, range), 72);
+    assert_eq!(size_of::<HalaLight>(), 80);
+    assert_eq!(align_of::<HalaLight>(), 16);
+  }
+
+  #[test]
+  fn test_triangle_light_layout() {
+    assert_eq!(offset_of!(HalaTriangleLight, p0), 0);
+    assert_eq!(offset_of!(HalaTriangleLight, area), 12);
+    assert_eq!(offset_of!(HalaTriangleLight, p1), 16);
+    assert_eq!(offset_of!(HalaTriangleLight, primitive_index), 28);
+    assert_eq!(offset_of!(HalaTriangleLight, p2), 32);
+    assert_eq!(offset_of!(HalaTriangleLight, triangle_index), 44);
+    assert_eq!(offset_of!(HalaTriangleLight, emission), 48);
+    assert_eq!(offset_of!(HalaTriangleLight, material_index), 60);
+    assert_eq!(size_of::<HalaTriangleLight>(), 64);
+  }
+
 }
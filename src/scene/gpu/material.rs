@@ -13,6 +13,16 @@ pub struct HalaMedium {
 }
 
 /// The material information in the GPU.
+/// It is consumed by the shaders as a uniform buffer, the layout(std140 compatible, 144 bytes) is:
+/// offset   0: medium(color, density, anisotropy, type, 2 x padding).
+/// offset  32: base_color, opacity.
+/// offset  48: emission, anisotropic. The emission is the emissive factor multiplied by the emissive strength.
+/// offset  64: metallic, roughness, subsurface, specular_tint.
+/// offset  80: sheen, sheen_tint, clearcoat, clearcoat_roughness.
+/// offset  96: clearcoat_tint, specular_transmission.
+/// offset 112: ior, ax, ay, base_color_map_index.
+/// offset 128: normal_map_index, metallic_roughness_map_index, emission_map_index, type.
+/// The map indices are the texture indices of the scene(u32::MAX if none), the emission map is in sRGB and multiplied by the emission.
 #[repr(C, align(16))]
 pub struct HalaMaterial {
   pub medium: HalaMedium,
@@ -3,18 +3,26 @@ use hala_gfx::{
   HalaAccelerationStructure,
 };
 
-use crate::scene::HalaBounds;
+use crate::scene::{
+  HalaBounds,
+  HalaLod,
+};
 
 /// The primitive in the GPU.
+/// The vertex and index buffers are none if the scene uses the mega buffers, the offsets locate the primitive in them.
 pub struct HalaPrimitive {
-  pub vertex_buffer: HalaBuffer,
-  pub index_buffer: HalaBuffer,
+  pub vertex_buffer: Option<HalaBuffer>,
+  pub index_buffer: Option<HalaBuffer>,
+  pub vertex_offset: u32,
+  pub first_index: u32,
   pub vertex_count: u32,
   pub index_count: u32,
   pub material_index: u32,
   pub bounds: HalaBounds,
+  pub lods: Vec<HalaLod>,
 
   pub meshlet_count: u32,
+  pub first_meshlet: u32,
   pub meshlet_buffer: Option<HalaBuffer>,
   pub meshlet_vertex_buffer: Option<HalaBuffer>,
   pub meshlet_primitive_buffer: Option<HalaBuffer>,
@@ -36,4 +44,13 @@ pub struct HalaMeshData {
   pub material_index: u32,
   pub vertices: u64,
   pub indices: u64,
-}
\ No newline at end of file
+}
+
+/// The source of a ray tracing instance, used to rebuild the top level acceleration structure at runtime.
+/// The instance index is the custom index and the index of the primitive buffer.
+#[derive(Debug, Copy, Clone)]
+pub struct HalaInstanceSource {
+  pub mesh_index: u32,
+  pub primitive_index: u32,
+  pub data: HalaMeshData,
+}
@@ -3,9 +3,11 @@ pub mod light;
 pub mod material;
 pub mod mesh;
 pub mod scene;
+pub mod stats;
 
 pub use camera::HalaCamera;
-pub use light::HalaLight;
+pub use light::{HalaLight, HalaTriangleLight};
 pub use material::HalaMaterial;
 pub use mesh::{HalaPrimitive, HalaMesh};
-pub use scene::HalaScene;
\ No newline at end of file
+pub use scene::HalaScene;
+pub use stats::{HalaSceneGPUStats, HalaTextureStats};
\ No newline at end of file
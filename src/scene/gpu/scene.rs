@@ -5,12 +5,32 @@ use hala_gfx::{
   HalaAccelerationStructure,
 };
 
-use crate::scene::gpu::HalaMesh;
+use crate::error::HalaRendererError;
+use crate::scene::{
+  HalaBounds,
+  HalaVertex,
+  HalaDepthConvention,
+  cpu::camera::HalaCamera as HalaCameraInCPU,
+  cpu::image_data::HalaImageData,
+  gpu::{
+    HalaMesh,
+    HalaPrimitive,
+    HalaSceneGPUStats,
+  },
+};
+
+/// The min ratio of the near plane to the far plane of the fitted perspective cameras,
+/// it keeps the depth precision when the camera is inside the scene bounds.
+const MIN_NEAR_FAR_RATIO: f32 = 0.001;
 
 /// The scene in the GPU.
 pub struct HalaScene {
   pub camera_view_matrices: Vec<glam::Mat4>,
   pub camera_proj_matrices: Vec<glam::Mat4>,
+  // The source parameters and the GPU data of the cameras, used to rebuild the projections at runtime.
+  pub camera_sources: Vec<HalaCameraInCPU>,
+  pub camera_data: Vec<crate::scene::gpu::HalaCamera>,
+  pub depth_convention: HalaDepthConvention,
 
   pub cameras: HalaBuffer,
   pub lights: HalaBuffer,
@@ -18,20 +38,184 @@ pub struct HalaScene {
   pub materials: Vec<HalaBuffer>,
   pub material_types: Vec<u32>,
   pub material_deferred_flags: Vec<bool>,
+  // Whether the material emits light, the primitives with these materials can be sampled as mesh lights.
+  pub material_emissive_flags: Vec<bool>,
   pub textures: Vec<u32>, // indices to the images.
   pub samplers: Vec<HalaSampler>,
   pub images: Vec<HalaImage>,
+  // Used for texture streaming, the images are placeholders until their data are uploaded.
+  pub pending_images: Vec<(usize, HalaImageData)>,
   pub meshes: Vec<HalaMesh>,
 
   pub instances: Option<HalaBuffer>,
   pub tplas: Option<HalaAccelerationStructure>,
+  // The sources of the instances in the instance buffer except the light instance, one for each primitive buffer.
+  pub instance_sources: Vec<crate::scene::gpu::mesh::HalaInstanceSource>,
   pub primitives: Vec<HalaBuffer>,
   pub light_btlas: Option<HalaAccelerationStructure>,
 
   pub light_data: Vec<crate::scene::gpu::HalaLight>,
 
+  // Used for sampling the emissive meshes in the path tracer, none if the scene has no emissive materials.
+  pub triangle_lights: Option<HalaBuffer>,
+  // The normalized cumulative distribution over the triangle light power.
+  pub triangle_light_cdf: Option<HalaBuffer>,
+  pub num_of_triangle_lights: u32,
+  pub triangle_light_total_power: f32,
+
   // Used for global meshlets.
   pub meshlet_count: u32,
   pub meshlets: Option<HalaBuffer>,
   pub meshlet_draw_data: Option<HalaBuffer>,
+
+  // Used for mega buffers, all primitives are suballocated from them.
+  pub mega_vertex_buffer: Option<HalaBuffer>,
+  pub mega_index_buffer: Option<HalaBuffer>,
+  pub mega_meshlet_buffer: Option<HalaBuffer>,
+  pub mega_meshlet_vertex_buffer: Option<HalaBuffer>,
+  pub mega_meshlet_primitive_buffer: Option<HalaBuffer>,
+
+  // The memory usage of the scene, kept current by the uploader.
+  pub stats: HalaSceneGPUStats,
+}
+
+/// The implementation of the scene in the GPU.
+impl HalaScene {
+
+  /// Whether some images are still placeholders waiting for the full resolution data.
+  /// return: True if some images are pending.
+  pub fn has_pending_images(&self) -> bool {
+    !self.pending_images.is_empty()
+  }
+
+  /// Whether the primitives are suballocated from the mega buffers.
+  /// return: True if the scene uses the mega buffers.
+  pub fn use_mega_buffers(&self) -> bool {
+    self.mega_vertex_buffer.is_some()
+  }
+
+  /// Get the vertex buffer of the primitive.
+  /// param prim: The primitive.
+  /// return: The own vertex buffer of the primitive or the mega vertex buffer.
+  pub fn get_vertex_buffer<'a>(&'a self, prim: &'a HalaPrimitive) -> Option<&'a HalaBuffer> {
+    prim.vertex_buffer.as_ref().or(self.mega_vertex_buffer.as_ref())
+  }
+
+  /// Get the index buffer of the primitive.
+  /// param prim: The primitive.
+  /// return: The own index buffer of the primitive or the mega index buffer.
+  pub fn get_index_buffer<'a>(&'a self, prim: &'a HalaPrimitive) -> Option<&'a HalaBuffer> {
+    prim.index_buffer.as_ref().or(self.mega_index_buffer.as_ref())
+  }
+
+  /// Get the device address of the first vertex of the primitive.
+  /// param prim: The primitive.
+  /// return: The device address, 0 if the primitive has no vertex buffer.
+  pub fn get_vertex_address(&self, prim: &HalaPrimitive) -> u64 {
+    self.get_vertex_buffer(prim).map_or(0, |buffer| {
+      buffer.get_device_address() + prim.vertex_offset as u64 * std::mem::size_of::<HalaVertex>() as u64
+    })
+  }
+
+  /// Get the device address of the first index of the primitive.
+  /// param prim: The primitive.
+  /// return: The device address, 0 if the primitive has no index buffer.
+  pub fn get_index_address(&self, prim: &HalaPrimitive) -> u64 {
+    self.get_index_buffer(prim).map_or(0, |buffer| {
+      buffer.get_device_address() + prim.first_index as u64 * std::mem::size_of::<u32>() as u64
+    })
+  }
+
+  /// Get the primitives whose materials emit light.
+  /// return: The mesh index and the primitive index of the emissive primitives.
+  pub fn get_emissive_primitives(&self) -> Vec<(usize, usize)> {
+    self.meshes.iter().enumerate().flat_map(|(mesh_index, mesh)| {
+      mesh.primitives.iter().enumerate()
+        .filter(|(_, primitive)| self.material_emissive_flags.get(primitive.material_index as usize).copied().unwrap_or(false))
+        .map(move |(primitive_index, _)| (mesh_index, primitive_index))
+    }).collect()
+  }
+
+  /// Get the world space bounds of all primitives.
+  /// return: The bounds, none if the scene has no primitive.
+  pub fn get_bounds(&self) -> Option<HalaBounds> {
+    let mut scene_bounds: Option<HalaBounds> = None;
+    for mesh in self.meshes.iter() {
+      for primitive in mesh.primitives.iter() {
+        let (min, max) = (glam::Vec3::from(primitive.bounds.get_min()), glam::Vec3::from(primitive.bounds.get_max()));
+        for corner_index in 0..8 {
+          let corner = glam::Vec3::new(
+            if corner_index & 1 == 0 { min.x } else { max.x },
+            if corner_index & 2 == 0 { min.y } else { max.y },
+            if corner_index & 4 == 0 { min.z } else { max.z },
+          );
+          let position = mesh.transform.transform_point3(corner).to_array();
+          match scene_bounds.as_mut() {
+            Some(bounds) => bounds.encapsulate_point(position),
+            None => scene_bounds = Some(HalaBounds::new(position, [0.0; 3])),
+          }
+        }
+      }
+    }
+    scene_bounds
+  }
+
+  /// Set the near and far planes of the camera and rebuild its projection, the renderers use it from the next update.
+  /// param camera_index: The index of the camera.
+  /// param znear: The near plane distance, it must be positive for the perspective cameras.
+  /// param zfar: The far plane distance.
+  /// return: The result.
+  pub fn set_camera_clip_planes(&mut self, camera_index: usize, znear: f32, zfar: f32) -> Result<(), HalaRendererError> {
+    let camera = self.camera_sources.get_mut(camera_index)
+      .ok_or(HalaRendererError::new(&format!("The camera index {} is out of range.", camera_index), None))?;
+    let is_perspective = matches!(camera, HalaCameraInCPU::Perspective(_));
+    if (is_perspective && znear <= 0.0) || zfar <= znear {
+      return Err(HalaRendererError::validation(&format!("The near {} and far {} planes must satisfy 0 < near < far.", znear, zfar)));
+    }
+    camera.set_clip_planes(znear, zfar);
+    self.camera_proj_matrices[camera_index] = camera.get_proj_matrix(self.depth_convention);
+
+    Ok(())
+  }
+
+  /// Fit the near and far planes of the camera to the scene bounds seen from the camera position.
+  /// The planes enclose the depth range of the bounds with a small margin,
+  /// the near plane of the perspective cameras is at least MIN_NEAR_FAR_RATIO of the far plane.
+  /// param camera_index: The index of the camera.
+  /// return: The fitted near and far planes.
+  pub fn auto_fit_camera_clip_planes(&mut self, camera_index: usize) -> Result<(f32, f32), HalaRendererError> {
+    let bounds = self.get_bounds().ok_or(HalaRendererError::new("The scene has no primitive to fit the clip planes.", None))?;
+    let v_mtx = self.camera_view_matrices.get(camera_index)
+      .ok_or(HalaRendererError::new(&format!("The camera index {} is out of range.", camera_index), None))?;
+
+    // The camera looks at -Z in the view space.
+    let (min, max) = (glam::Vec3::from(bounds.get_min()), glam::Vec3::from(bounds.get_max()));
+    let (mut min_depth, mut max_depth) = (f32::MAX, f32::MIN);
+    for corner_index in 0..8 {
+      let corner = glam::Vec3::new(
+        if corner_index & 1 == 0 { min.x } else { max.x },
+        if corner_index & 2 == 0 { min.y } else { max.y },
+        if corner_index & 4 == 0 { min.z } else { max.z },
+      );
+      let depth = -v_mtx.transform_point3(corner).z;
+      min_depth = min_depth.min(depth);
+      max_depth = max_depth.max(depth);
+    }
+
+    let margin = ((max_depth - min_depth) * 0.01).max(1e-4);
+    let zfar = max_depth + margin;
+    let znear = match self.camera_sources.get(camera_index) {
+      Some(HalaCameraInCPU::Perspective(_)) => {
+        if zfar <= 0.0 {
+          return Err(HalaRendererError::new(&format!("The scene is behind the camera {}.", camera_index), None));
+        }
+        (min_depth - margin).max(zfar * MIN_NEAR_FAR_RATIO)
+      },
+      _ => min_depth - margin,
+    };
+    self.set_camera_clip_planes(camera_index, znear, zfar)?;
+
+    Ok((znear, zfar))
+  }
+
 }
\ No newline at end of file
@@ -0,0 +1,205 @@
+use hala_gfx::{
+  HalaBuffer,
+  HalaFormat,
+};
+
+use crate::scene::gpu::HalaScene;
+
+/// The statistics of the texture in the GPU.
+#[derive(Debug, Clone, Copy)]
+pub struct HalaTextureStats {
+  pub format: HalaFormat,
+  pub width: u32,
+  pub height: u32,
+  pub mip_levels: u32,
+  pub num_of_bytes: u64,
+}
+
+/// The statistics and the memory usage of the scene in the GPU.
+/// The buffer sizes are the sizes of the created buffers, the texture sizes include the full mip chain.
+#[derive(Debug, Clone, Default)]
+pub struct HalaSceneGPUStats {
+  pub vertex_bytes: u64,
+  pub index_bytes: u64,
+  pub meshlet_count: u64,
+  pub meshlet_bytes: u64,
+  pub material_bytes: u64,
+  pub camera_and_light_bytes: u64,
+  pub textures: Vec<HalaTextureStats>,
+  pub blas_bytes: u64,
+  pub tlas_bytes: u64,
+  pub ray_tracing_buffer_bytes: u64,
+  pub triangle_light_bytes: u64,
+  pub mesh_primitive_counts: Vec<u32>,
+}
+
+/// The implementation of the statistics of the scene in the GPU.
+impl HalaSceneGPUStats {
+
+  /// Get the bytes of the texture with the full mip chain.
+  /// param format: The format of the texture.
+  /// param width: The width of the texture.
+  /// param height: The height of the texture.
+  /// param num_of_bytes: The bytes of the first mip level.
+  /// param mip_levels: The number of the mip levels.
+  /// return: The texture statistics.
+  pub fn get_texture_stats(format: HalaFormat, width: u32, height: u32, num_of_bytes: usize, mip_levels: u32) -> HalaTextureStats {
+    let bytes_per_texel = (num_of_bytes as u64 / (width as u64 * height as u64).max(1)).max(1);
+    let mut total_bytes = 0u64;
+    for level in 0..mip_levels {
+      let level_width = (width >> level).max(1) as u64;
+      let level_height = (height >> level).max(1) as u64;
+      total_bytes += level_width * level_height * bytes_per_texel;
+    }
+
+    HalaTextureStats {
+      format,
+      width,
+      height,
+      mip_levels,
+      num_of_bytes: total_bytes,
+    }
+  }
+
+  /// Set the statistics of the texture, the list grows if the index is out of range.
+  /// param index: The index of the image.
+  /// param texture: The texture statistics.
+  pub fn set_texture(&mut self, index: usize, texture: HalaTextureStats) {
+    if index >= self.textures.len() {
+      self.textures.resize(index + 1, HalaTextureStats {
+        format: HalaFormat::UNDEFINED,
+        width: 0,
+        height: 0,
+        mip_levels: 0,
+        num_of_bytes: 0,
+      });
+    }
+    self.textures[index] = texture;
+  }
+
+  /// Recount the buffers and the acceleration structures of the scene, the textures are kept.
+  /// It should be called after the buffers of the scene are changed.
+  /// param scene: The scene in the GPU.
+  pub fn update_buffers(&mut self, scene: &HalaScene) {
+    let buffer_size = |buffer: Option<&HalaBuffer>| buffer.map_or(0, |buffer| buffer.size);
+
+    self.vertex_bytes = buffer_size(scene.mega_vertex_buffer.as_ref());
+    self.index_bytes = buffer_size(scene.mega_index_buffer.as_ref());
+    self.meshlet_count = scene.meshlet_count as u64;
+    self.meshlet_bytes = buffer_size(scene.mega_meshlet_buffer.as_ref())
+      + buffer_size(scene.mega_meshlet_vertex_buffer.as_ref())
+      + buffer_size(scene.mega_meshlet_primitive_buffer.as_ref())
+      + buffer_size(scene.meshlets.as_ref())
+      + buffer_size(scene.meshlet_draw_data.as_ref());
+    self.blas_bytes = scene.light_btlas.as_ref().map_or(0, |btlas| btlas.buffer.size);
+    self.mesh_primitive_counts = scene.meshes.iter().map(|mesh| mesh.primitives.len() as u32).collect();
+
+    let mut primitive_meshlet_count = 0u64;
+    for mesh in scene.meshes.iter() {
+      for prim in mesh.primitives.iter() {
+        self.vertex_bytes += buffer_size(prim.vertex_buffer.as_ref());
+        self.index_bytes += buffer_size(prim.index_buffer.as_ref());
+        self.meshlet_bytes += buffer_size(prim.meshlet_buffer.as_ref())
+          + buffer_size(prim.meshlet_vertex_buffer.as_ref())
+          + buffer_size(prim.meshlet_primitive_buffer.as_ref());
+        self.blas_bytes += prim.btlas.as_ref().map_or(0, |btlas| btlas.buffer.size);
+        primitive_meshlet_count += prim.meshlet_count as u64;
+      }
+    }
+    // The global meshlets are counted by the scene, otherwise by the primitives.
+    if self.meshlet_count == 0 {
+      self.meshlet_count = primitive_meshlet_count;
+    }
+
+    self.material_bytes = scene.materials.iter().map(|buffer| buffer.size).sum();
+    self.camera_and_light_bytes = scene.cameras.size + scene.lights.size + scene.light_aabbs.size;
+    self.tlas_bytes = scene.tplas.as_ref().map_or(0, |tplas| tplas.buffer.size);
+    self.ray_tracing_buffer_bytes = buffer_size(scene.instances.as_ref())
+      + scene.primitives.iter().map(|buffer| buffer.size).sum::<u64>();
+    self.triangle_light_bytes = buffer_size(scene.triangle_lights.as_ref()) + buffer_size(scene.triangle_light_cdf.as_ref());
+  }
+
+  /// Get the total bytes of the textures.
+  /// return: The total bytes.
+  pub fn get_texture_bytes(&self) -> u64 {
+    self.textures.iter().map(|texture| texture.num_of_bytes).sum()
+  }
+
+  /// Get the bytes of the textures grouped by the format.
+  /// return: The format, the number of the textures and the bytes in the order of the first appearance.
+  pub fn get_texture_bytes_by_format(&self) -> Vec<(HalaFormat, u32, u64)> {
+    let mut groups: Vec<(HalaFormat, u32, u64)> = Vec::new();
+    for texture in self.textures.iter() {
+      match groups.iter_mut().find(|(format, _, _)| *format == texture.format) {
+        Some((_, count, bytes)) => {
+          *count += 1;
+          *bytes += texture.num_of_bytes;
+        },
+        None => groups.push((texture.format, 1, texture.num_of_bytes)),
+      }
+    }
+    groups
+  }
+
+  /// Get the total bytes of the scene in the GPU.
+  /// return: The total bytes.
+  pub fn get_total_bytes(&self) -> u64 {
+    self.vertex_bytes
+      + self.index_bytes
+      + self.meshlet_bytes
+      + self.material_bytes
+      + self.camera_and_light_bytes
+      + self.get_texture_bytes()
+      + self.blas_bytes
+      + self.tlas_bytes
+      + self.ray_tracing_buffer_bytes
+      + self.triangle_light_bytes
+  }
+
+}
+
+/// Format the bytes in the binary units.
+/// param bytes: The bytes.
+/// return: The formatted string.
+fn format_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+  let mut value = bytes as f64;
+  let mut unit = 0;
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  if unit == 0 {
+    format!("{} {}", bytes, UNITS[unit])
+  } else {
+    format!("{:.2} {}", value, UNITS[unit])
+  }
+}
+
+/// The display implementation of the statistics of the scene in the GPU.
+impl std::fmt::Display for HalaSceneGPUStats {
+
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Scene GPU memory: {}", format_bytes(self.get_total_bytes()))?;
+    writeln!(
+      f,
+      "  Meshes: {} meshes, {} primitives",
+      self.mesh_primitive_counts.len(),
+      self.mesh_primitive_counts.iter().map(|count| *count as u64).sum::<u64>(),
+    )?;
+    writeln!(f, "  Vertices: {}", format_bytes(self.vertex_bytes))?;
+    writeln!(f, "  Indices: {}", format_bytes(self.index_bytes))?;
+    writeln!(f, "  Meshlets: {} meshlets, {}", self.meshlet_count, format_bytes(self.meshlet_bytes))?;
+    writeln!(f, "  Materials: {}", format_bytes(self.material_bytes))?;
+    writeln!(f, "  Cameras and lights: {}", format_bytes(self.camera_and_light_bytes))?;
+    writeln!(f, "  Textures: {} textures, {}", self.textures.len(), format_bytes(self.get_texture_bytes()))?;
+    for (format, count, bytes) in self.get_texture_bytes_by_format() {
+      writeln!(f, "    {:?}: {} textures, {}", format, count, format_bytes(bytes))?;
+    }
+    writeln!(f, "  BLAS: {}", format_bytes(self.blas_bytes))?;
+    writeln!(f, "  TLAS: {}", format_bytes(self.tlas_bytes))?;
+    writeln!(f, "  Ray tracing buffers: {}", format_bytes(self.ray_tracing_buffer_bytes))?;
+    write!(f, "  Triangle lights: {}", format_bytes(self.triangle_light_bytes))
+  }
+
+}
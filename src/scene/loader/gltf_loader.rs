@@ -121,13 +121,28 @@ impl HalaGltfLoader {
   pub fn load<P: AsRef<Path>>(path: P) -> Result<HalaScene, HalaRendererError> {
     let path = path.as_ref();
     let (gltf, mesh_data, image_data) = gltf::import(path)
-      .map_err(|err| HalaRendererError::new(&format!("Load glTF file \"{:?}\" failed.", path), Some(Box::new(err))))?;
+      .map_err(|err| HalaRendererError::scene_load("Load glTF file failed.", path, Some(Box::new(err))))?;
 
+    Self::load_document(path, &gltf, &mesh_data, image_data)
+  }
+
+  /// Load the scene from the imported glTF document.
+  /// param path The path of the glTF file, the relative image URIs are resolved against it.
+  /// param gltf The glTF document.
+  /// param mesh_data The buffer data.
+  /// param image_data The image data.
+  /// return The loaded scene.
+  fn load_document(
+    path: &Path,
+    gltf: &gltf::Document,
+    mesh_data: &[gltf::buffer::Data],
+    image_data: Vec<gltf::image::Data>,
+  ) -> Result<HalaScene, HalaRendererError> {
     // Load all nodes.
     let mut loaded_nodes = Vec::new();
     let scenes = gltf.scenes();
     if scenes.len() == 0 {
-      return Err(HalaRendererError::new(&format!("No scene in glTF file \"{:?}\".", path), None));
+      return Err(HalaRendererError::scene_load("No scene in glTF file.", path, None));
     } else if scenes.len() > 1 {
       log::warn!("More than one scene in glTF file \"{:?}\". Only the first scene will be loaded.", path);
     }
@@ -175,7 +190,7 @@ impl HalaGltfLoader {
     // Load all meshes.
     let mut loaded_meshes = Vec::new();
     for mesh in gltf.meshes() {
-      loaded_meshes.push(Self::load_mesh(&mesh, &mesh_data)?);
+      loaded_meshes.push(Self::load_mesh(&mesh, mesh_data)?);
     }
 
     // Load all materials.
@@ -199,6 +214,15 @@ impl HalaGltfLoader {
     for data in image_data {
       loaded_textures.push(Self::load_image_data(&data)?);
     }
+    let mut loaded_image_paths = vec![None; loaded_textures.len()];
+    for image in gltf.images() {
+      if let gltf::image::Source::Uri { uri, .. } = image.source() {
+        // Embedded data URIs can not be reloaded from the disk.
+        if !uri.starts_with("data:") && image.index() < loaded_image_paths.len() {
+          loaded_image_paths[image.index()] = Some(path.with_file_name(Self::decode_uri(uri)));
+        }
+      }
+    }
 
     // Load all lights.
     let mut loaded_lights = Vec::new();
@@ -221,11 +245,37 @@ impl HalaGltfLoader {
       texture2image_mapping: loaded_texture2image_mapping,
       image2data_mapping: loaded_image2data_mapping,
       image_data: loaded_textures,
+      image_paths: loaded_image_paths,
       lights: loaded_lights,
       cameras: loaded_cameras,
+      primitive_bounds: Vec::new(),
     })
   }
 
+  /// Decode the percent encoded characters of the relative URI, e.g. "base%20color.png" to "base color.png".
+  /// The invalid escape sequences are kept as they are.
+  /// param uri The URI.
+  /// return The decoded path.
+  fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+      if bytes[i] == b'%' && i + 2 < bytes.len() {
+        let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = hex {
+          decoded.push(byte);
+          i += 3;
+          continue;
+        }
+      }
+      decoded.push(bytes[i]);
+      i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+  }
+
   /// Load the mesh.
   /// param mesh The gltf mesh.
   /// param buffers The gltf buffers.
@@ -237,53 +287,44 @@ impl HalaGltfLoader {
     let mut loaded_primitives = Vec::new();
     for primitive in primitives {
       log::debug!("Loading primitive {} from mesh \"{}\".", primitive.index(), mesh_name);
+      let mode = primitive.mode();
+      if !matches!(mode, gltf::mesh::Mode::Triangles | gltf::mesh::Mode::TriangleStrip | gltf::mesh::Mode::TriangleFan) {
+        log::warn!("Skip primitive {} from mesh \"{}\", the mode {:?} is not supported.", primitive.index(), mesh_name, mode);
+        continue;
+      }
       let reader = primitive.reader(|i| Some(&buffers[i.index()]));
 
-      let indices = reader.read_indices()
-        .ok_or(HalaRendererError::new(&format!("Read indices from mesh \"{}\" failed.", mesh_name), None))?
-        .into_u32().collect::<Vec<_>>();
       let positions = reader.read_positions()
         .ok_or(HalaRendererError::new(&format!("Read positions from mesh \"{}\" failed.", mesh_name), None))?
         .map(Vec3::from).collect::<Vec<_>>();
-      let normals = reader.read_normals()
-        .ok_or(HalaRendererError::new(&format!("Read normals from mesh \"{}\" failed.", mesh_name), None))?
-        .map(Vec3::from).collect::<Vec<_>>();
-      let tex_coords = reader.read_tex_coords(0)
-        .ok_or(HalaRendererError::new(&format!("Read tex_coords from mesh \"{}\" failed.", mesh_name), None))?
-        .into_f32().map(Vec2::from).collect::<Vec<_>>();
+      // The non-indexed primitive draws the vertices in order.
+      let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..positions.len() as u32).collect::<Vec<_>>(),
+      };
+      // The rest of the pipeline only handles the triangle lists.
+      let indices = Self::to_triangle_list(mode, &indices);
+      let (indices, num_of_degenerate_triangles) = Self::remove_degenerate_triangles(&indices, &positions);
+      if num_of_degenerate_triangles > 0 {
+        log::debug!("Drop {} degenerate triangles from primitive {} of mesh \"{}\".", num_of_degenerate_triangles, primitive.index(), mesh_name);
+      }
+      let normals = if let Some(normals) = reader.read_normals() {
+        normals.map(Vec3::from).collect::<Vec<_>>()
+      } else {
+        log::debug!("Generate normals for primitive {} from mesh \"{}\".", primitive.index(), mesh_name);
+        Self::generate_normals(&indices, &positions)
+      };
+      let tex_coords = reader.read_tex_coords(0).map(|tex_coords| tex_coords.into_f32().map(Vec2::from).collect::<Vec<_>>());
 
+      // The handedness in w is baked into the tangent direction, see HalaVertex.
       let tangents = if let Some(tangents) = reader.read_tangents() {
-        tangents.map(|tangent| {
-          let t: [f32; 3] = [tangent[0] / tangent[3], tangent[1] / tangent[3], tangent[2] / tangent[3]];
-          Vec3::from(t)
-        }).collect::<Vec<_>>()
+        tangents.map(Vec4::from).collect::<Vec<_>>()
       } else {
-        // Fill the tangents with zero.
-        let mut tangents = vec![Vec3::ZERO; positions.len()];
-        // Calculate tangent.
-        for tri_indices in indices.chunks(3) {
-          let v0 = positions[tri_indices[0] as usize];
-          let v1 = positions[tri_indices[1] as usize];
-          let v2 = positions[tri_indices[2] as usize];
-          let uv0 = tex_coords[tri_indices[0] as usize];
-          let uv1 = tex_coords[tri_indices[1] as usize];
-          let uv2 = tex_coords[tri_indices[2] as usize];
-
-          let delta_pos1 = v1 - v0;
-          let delta_pos2 = v2 - v0;
-
-          let delta_uv1 = uv1 - uv0;
-          let delta_uv2 = uv2 - uv0;
-
-          let invdet = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-
-          let tangent = ((delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * invdet).normalize();
-          tangents[tri_indices[0] as usize] = tangent;
-          tangents[tri_indices[1] as usize] = tangent;
-          tangents[tri_indices[2] as usize] = tangent;
-        }
-        tangents
+        log::debug!("Generate tangents for primitive {} from mesh \"{}\".", primitive.index(), mesh_name);
+        Self::generate_tangents(&indices, &positions, &normals, tex_coords.as_deref())
       };
+      let tangents = tangents.iter().map(|tangent| Self::bake_handedness(*tangent)).collect::<Vec<_>>();
+      let tex_coords = tex_coords.unwrap_or_else(|| vec![Vec2::ZERO; positions.len()]);
 
       let mut vertices = Vec::new();
       for i in 0..positions.len() {
@@ -312,6 +353,130 @@ impl HalaGltfLoader {
     })
   }
 
+  /// Convert the indices of the triangle strip or fan to the triangle list.
+  /// The winding of the odd triangles in the strip is flipped back, so all triangles keep the winding of the first one.
+  /// param mode The primitive mode.
+  /// param indices The indices.
+  /// return The indices of the triangle list.
+  fn to_triangle_list(mode: gltf::mesh::Mode, indices: &[u32]) -> Vec<u32> {
+    let num_of_triangles = indices.len().saturating_sub(2);
+    match mode {
+      gltf::mesh::Mode::TriangleStrip => {
+        let mut list = Vec::with_capacity(num_of_triangles * 3);
+        for i in 0..num_of_triangles {
+          if i % 2 == 0 {
+            list.extend_from_slice(&[indices[i], indices[i + 1], indices[i + 2]]);
+          } else {
+            list.extend_from_slice(&[indices[i + 1], indices[i], indices[i + 2]]);
+          }
+        }
+        list
+      },
+      gltf::mesh::Mode::TriangleFan => {
+        let mut list = Vec::with_capacity(num_of_triangles * 3);
+        for i in 0..num_of_triangles {
+          list.extend_from_slice(&[indices[i + 1], indices[i + 2], indices[0]]);
+        }
+        list
+      },
+      _ => indices[..indices.len() / 3 * 3].to_vec(),
+    }
+  }
+
+  /// Remove the degenerate triangles which reuse a vertex or have no area.
+  /// param indices The indices of the triangle list.
+  /// param positions The positions.
+  /// return The indices without the degenerate triangles and the count of the removed triangles.
+  fn remove_degenerate_triangles(indices: &[u32], positions: &[Vec3]) -> (Vec<u32>, usize) {
+    let mut list = Vec::with_capacity(indices.len());
+    let mut num_of_degenerate_triangles = 0;
+    for tri_indices in indices.chunks_exact(3) {
+      let (i0, i1, i2) = (tri_indices[0], tri_indices[1], tri_indices[2]);
+      let is_degenerate = i0 == i1 || i1 == i2 || i2 == i0 || {
+        let (v0, v1, v2) = (positions[i0 as usize], positions[i1 as usize], positions[i2 as usize]);
+        (v1 - v0).cross(v2 - v0).length_squared() <= f32::MIN_POSITIVE
+      };
+      if is_degenerate {
+        num_of_degenerate_triangles += 1;
+      } else {
+        list.extend_from_slice(tri_indices);
+      }
+    }
+    (list, num_of_degenerate_triangles)
+  }
+
+  /// Generate the smooth normals of the primitive which has no normals.
+  /// The face normals are accumulated per vertex weighted by the triangle area.
+  /// param indices The indices of the triangle list.
+  /// param positions The positions.
+  /// return The normals.
+  fn generate_normals(indices: &[u32], positions: &[Vec3]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for tri_indices in indices.chunks_exact(3) {
+      let (i0, i1, i2) = (tri_indices[0] as usize, tri_indices[1] as usize, tri_indices[2] as usize);
+      // The length of the cross product is twice the triangle area.
+      let normal = (positions[i1] - positions[i0]).cross(positions[i2] - positions[i0]);
+      normals[i0] += normal;
+      normals[i1] += normal;
+      normals[i2] += normal;
+    }
+    normals.iter().map(|normal| normal.try_normalize().unwrap_or(Vec3::Y)).collect()
+  }
+
+  /// Generate the tangents of the primitive which has no tangents.
+  /// The triangle tangents and bitangents are accumulated per vertex weighted by the triangle area,
+  /// then the tangent is orthogonalized against the normal and the handedness is -1 if the UVs are mirrored.
+  /// The vertices without valid UVs use an arbitrary tangent perpendicular to the normal.
+  /// param indices The indices.
+  /// param positions The positions.
+  /// param normals The normals.
+  /// param tex_coords The texture coordinates.
+  /// return The tangents with the handedness in w like the glTF TANGENT attribute.
+  fn generate_tangents(indices: &[u32], positions: &[Vec3], normals: &[Vec3], tex_coords: Option<&[Vec2]>) -> Vec<Vec4> {
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+    if let Some(tex_coords) = tex_coords {
+      for tri_indices in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (tri_indices[0] as usize, tri_indices[1] as usize, tri_indices[2] as usize);
+
+        let delta_pos1 = positions[i1] - positions[i0];
+        let delta_pos2 = positions[i2] - positions[i0];
+        let delta_uv1 = tex_coords[i1] - tex_coords[i0];
+        let delta_uv2 = tex_coords[i2] - tex_coords[i0];
+
+        let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        if det.abs() <= f32::EPSILON {
+          continue;
+        }
+        // Keep the sign of the determinant but drop its magnitude, so the sum is weighted by the triangle area.
+        let r = det.signum();
+        let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+        let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r;
+        for i in [i0, i1, i2] {
+          tangents[i] += tangent;
+          bitangents[i] += bitangent;
+        }
+      }
+    }
+
+    tangents.iter().zip(bitangents.iter()).zip(normals.iter()).map(|((&tangent, &bitangent), &normal)| {
+      // Gram-Schmidt orthogonalize.
+      let tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+      if tangent == Vec3::ZERO {
+        return normal.any_orthonormal_vector().extend(1.0);
+      }
+      let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+      tangent.extend(handedness)
+    }).collect()
+  }
+
+  /// Bake the handedness into the tangent direction, so the bitangent is always cross(normal, tangent), see HalaVertex.
+  /// param tangent The tangent with the handedness in w.
+  /// return The tangent of the vertex.
+  fn bake_handedness(tangent: Vec4) -> Vec3 {
+    if tangent.w < 0.0 { -tangent.xyz() } else { tangent.xyz() }
+  }
+
   /// Load the material.
   /// param material The gltf material.
   /// return The loaded material.
@@ -332,6 +497,7 @@ impl HalaGltfLoader {
     let base_color: Vec4 = pbr.base_color_factor().into();
     let metallic = pbr.metallic_factor();
     let roughness = pbr.roughness_factor();
+    // The emissive factor is clamped to [0, 1] by glTF, KHR_materials_emissive_strength scales it for the bright surfaces.
     let mut emission: Vec3 = material.emissive_factor().into();
     if let Some(emissive_strength) = material.emissive_strength() {
       emission *= emissive_strength;
@@ -477,12 +643,18 @@ impl HalaGltfLoader {
       _ => {},
     }
     let params = (param0, param1);
+    // The range is only meaningful for the punctual lights, the non-positive range is treated as infinite.
+    let range = match light_type {
+      HalaLightType::POINT | HalaLightType::SPOT => light.range().filter(|range| *range > 0.0),
+      _ => None,
+    };
 
     Ok(HalaLight {
       color,
       intensity,
       light_type,
       params,
+      range,
     })
   }
 
@@ -499,11 +671,19 @@ impl HalaGltfLoader {
         let znear = orthographic.znear();
         let zfar = orthographic.zfar();
 
-        let orthography = glam::Mat4::orthographic_rh(-xmag, xmag, -ymag, ymag, znear, zfar);
+        if xmag == 0.0 || ymag == 0.0 || zfar == znear {
+          return Err(HalaRendererError::new(
+            &format!("The orthographic camera \"{}\" has an invalid view volume.", camera.name().unwrap_or("<Unnamed>")), None));
+        }
+
+        // Use reverse orthographic projection(depth range: 1 to 0), the near and far planes are swapped.
+        let orthography = glam::Mat4::orthographic_rh(-xmag, xmag, -ymag, ymag, zfar, znear);
 
         Ok(HalaCamera::Orthographic(HalaOrthographicCamera {
           xmag,
           ymag,
+          znear,
+          zfar,
           orthography,
         }))
       },
@@ -537,3 +717,367 @@ impl HalaGltfLoader {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::scene::HalaMeshletConfig;
+  use crate::scene::meshlet::build_meshlets;
+
+  /// The positions of a unit quad on the XY plane facing +Z, 2 triangles counter clockwise.
+  const QUAD_POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+  const QUAD_INDICES: [u32; 6] = [0, 1, 3, 0, 3, 2];
+
+  /// Generate the tangents of the unit quad.
+  /// param tex_coords: The texture coordinates of the quad vertices.
+  /// return: The tangents with the handedness in w.
+  fn generate_quad_tangents(tex_coords: Option<&[Vec2]>) -> Vec<Vec4> {
+    let positions = QUAD_POSITIONS.map(Vec3::from);
+    let normals = [Vec3::Z; 4];
+    HalaGltfLoader::generate_tangents(&QUAD_INDICES, &positions, &normals, tex_coords)
+  }
+
+  #[test]
+  fn test_generate_tangents() {
+    // U along +X and V along +Y.
+    let tex_coords = QUAD_POSITIONS.map(|p| Vec2::new(p[0], p[1]));
+    for tangent in generate_quad_tangents(Some(&tex_coords)) {
+      assert!(tangent.xyz().abs_diff_eq(Vec3::X, 1e-6), "The tangent {:?} is not +X.", tangent);
+      assert_eq!(tangent.w, 1.0);
+      assert!(HalaGltfLoader::bake_handedness(tangent).abs_diff_eq(Vec3::X, 1e-6));
+    }
+  }
+
+  #[test]
+  fn test_generate_mirrored_tangents() {
+    // U along -X, the bitangent cross(normal, tangent) is -Y but V is along +Y, so the handedness is -1.
+    let tex_coords = QUAD_POSITIONS.map(|p| Vec2::new(1.0 - p[0], p[1]));
+    for tangent in generate_quad_tangents(Some(&tex_coords)) {
+      assert!(tangent.xyz().abs_diff_eq(Vec3::NEG_X, 1e-6), "The tangent {:?} is not -X.", tangent);
+      assert_eq!(tangent.w, -1.0);
+      // The baked tangent gives the bitangent cross(normal, tangent) along +V.
+      let tangent = HalaGltfLoader::bake_handedness(tangent);
+      assert!(Vec3::Z.cross(tangent).abs_diff_eq(Vec3::Y, 1e-6));
+    }
+  }
+
+  #[test]
+  fn test_generate_tangents_without_tex_coords() {
+    for tangent in generate_quad_tangents(None) {
+      assert!((tangent.xyz().length() - 1.0).abs() < 1e-6);
+      assert!(tangent.xyz().dot(Vec3::Z).abs() < 1e-6);
+      assert_eq!(tangent.w, 1.0);
+    }
+  }
+
+  /// The buffer of the primitive fixture in a data URI, 132 bytes.
+  /// 0..48: the positions of the unit quad.
+  /// 48..120: the positions of the 2 triangles of the unit quad.
+  /// 120..130: the u16 strip indices [0, 1, 2, 3, 3] and 2 bytes padding.
+  const PRIMITIVES_BUFFER: &str = "data:application/octet-stream;base64,\
+    AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAACAPwAAgD8AAAAAAAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAACAPwAAgD8A\
+    AAAAAAAAAAAAAAAAAAAAAACAPwAAgD8AAAAAAAAAAAAAgD8AAAAAAAABAAIAAwADAAAA";
+
+  /// Try to load the scene from the in-memory glTF JSON.
+  /// param json: The glTF JSON.
+  /// return: The loaded scene.
+  fn try_load_from_json(json: &str) -> Result<HalaScene, HalaRendererError> {
+    let path = Path::new("memory.gltf");
+    let (document, buffers, images) = gltf::import_slice(json.as_bytes())
+      .map_err(|err| HalaRendererError::scene_load("Import the glTF fixture failed.", path, Some(Box::new(err))))?;
+    HalaGltfLoader::load_document(path, &document, &buffers, images)
+  }
+
+  /// Load the scene from the in-memory glTF JSON.
+  /// param json: The glTF JSON.
+  /// return: The loaded scene.
+  fn load_from_json(json: &str) -> HalaScene {
+    try_load_from_json(json).expect("Load the glTF fixture failed.")
+  }
+
+  /// Build the glTF JSON with a mesh of a non-indexed triangle list, an indexed triangle strip and a non-indexed triangle fan.
+  /// return: The glTF JSON.
+  fn primitives_json() -> String {
+    format!(r#"{{
+      "asset": {{ "version": "2.0" }},
+      "scene": 0,
+      "scenes": [{{ "nodes": [0] }}],
+      "nodes": [{{ "mesh": 0 }}],
+      "meshes": [{{
+        "primitives": [
+          {{ "attributes": {{ "POSITION": 1 }}, "mode": 4 }},
+          {{ "attributes": {{ "POSITION": 0 }}, "indices": 2, "mode": 5 }},
+          {{ "attributes": {{ "POSITION": 0 }}, "mode": 6 }}
+        ]
+      }}],
+      "buffers": [{{ "byteLength": 132, "uri": "{}" }}],
+      "bufferViews": [
+        {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+        {{ "buffer": 0, "byteOffset": 48, "byteLength": 72 }},
+        {{ "buffer": 0, "byteOffset": 120, "byteLength": 10 }}
+      ],
+      "accessors": [
+        {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+        {{ "bufferView": 1, "componentType": 5126, "count": 6, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }},
+        {{ "bufferView": 2, "componentType": 5123, "count": 5, "type": "SCALAR" }}
+      ]
+    }}"#, PRIMITIVES_BUFFER)
+  }
+
+  /// Load the mesh with a non-indexed triangle list, an indexed triangle strip and a non-indexed triangle fan.
+  /// return: The primitives of the mesh.
+  fn load_primitives() -> Vec<HalaPrimitive> {
+    let mut scene = load_from_json(&primitives_json());
+    assert_eq!(scene.meshes.len(), 1);
+    std::mem::take(&mut scene.meshes[0].primitives)
+  }
+
+  /// Build the meshlets of the primitive on the CPU and check every meshlet index is in range.
+  /// param primitive: The primitive.
+  fn check_meshlets(primitive: &HalaPrimitive) {
+    let mut meshlet_vertices = Vec::new();
+    let mut meshlet_primitives = Vec::new();
+    let meshlets = build_meshlets(
+      &primitive.vertices,
+      &primitive.indices,
+      &HalaMeshletConfig::default(),
+      &mut meshlet_vertices,
+      &mut meshlet_primitives,
+    ).unwrap();
+    assert_eq!(meshlets.iter().map(|meshlet| meshlet.num_of_primitives as usize).sum::<usize>(), primitive.indices.len() / 3);
+
+    for meshlet in meshlets.iter() {
+      let vertex_range = meshlet.offset_of_vertices as usize..(meshlet.offset_of_vertices + meshlet.num_of_vertices) as usize;
+      let primitive_range = meshlet.offset_of_primitives as usize..(meshlet.offset_of_primitives + meshlet.num_of_primitives) as usize;
+      assert!(vertex_range.end <= meshlet_vertices.len());
+      assert!(primitive_range.end <= meshlet_primitives.len());
+      // The meshlet vertices index the primitive vertices and the packed local indices index the meshlet vertices.
+      assert!(meshlet_vertices[vertex_range].iter().all(|index| (*index as usize) < primitive.vertices.len()));
+      for packed in meshlet_primitives[primitive_range].iter() {
+        for shift in [0, 8, 16] {
+          assert!((packed >> shift) & 0xff < meshlet.num_of_vertices, "The local index of {:#x} is out of range.", packed);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_load_non_indexed_primitive() {
+    let primitives = load_primitives();
+    assert_eq!(primitives.len(), 3);
+
+    let primitive = &primitives[0];
+    assert_eq!(primitive.indices, [0, 1, 2, 3, 4, 5]);
+    assert_eq!(primitive.vertices.len(), 6);
+    assert_eq!(primitive.material_index, u32::MAX);
+    // The generated normals face the counter clockwise front.
+    for vertex in primitive.vertices.iter() {
+      assert!(Vec3::from(vertex.normal).abs_diff_eq(Vec3::Z, 1e-6));
+    }
+    check_meshlets(primitive);
+  }
+
+  #[test]
+  fn test_load_triangle_strip_primitive() {
+    let primitives = load_primitives();
+
+    // The odd triangle swaps its first 2 vertices to keep the winding, the last degenerate triangle [2, 3, 3] is dropped.
+    let primitive = &primitives[1];
+    assert_eq!(primitive.indices, [0, 1, 2, 2, 1, 3]);
+    assert_eq!(primitive.vertices.len(), 4);
+    for vertex in primitive.vertices.iter() {
+      assert!(Vec3::from(vertex.normal).abs_diff_eq(Vec3::Z, 1e-6));
+    }
+    check_meshlets(primitive);
+  }
+
+  #[test]
+  fn test_load_triangle_fan_primitive() {
+    let primitives = load_primitives();
+
+    let primitive = &primitives[2];
+    assert_eq!(primitive.indices, [1, 2, 0, 2, 3, 0]);
+    assert_eq!(primitive.vertices.len(), 4);
+    check_meshlets(primitive);
+  }
+
+  #[test]
+  #[ignore = "requires a GPU"]
+  fn test_upload_primitives_for_mesh_shader() {
+    let gpu_req = hala_gfx::HalaGPURequirements {
+      width: 64,
+      height: 64,
+      ..Default::default()
+    };
+    let resources = crate::renderer::HalaRendererResources::new_headless(
+      "gltf_upload_test",
+      &gpu_req,
+      &[(hala_gfx::HalaDescriptorType::UNIFORM_BUFFER, 1)],
+    ).unwrap();
+    let mut scene_in_cpu = load_from_json(&primitives_json());
+
+    let context = resources.context.borrow();
+    let scene_in_gpu = super::super::HalaSceneGPUUploader::upload(
+      &context,
+      &resources.graphics_command_buffers,
+      &resources.transfer_command_buffers,
+      &mut scene_in_cpu,
+      false,
+      &crate::scene::HalaLodConfig::default(),
+      crate::scene::HalaDepthConvention::default(),
+      false,
+      false,
+      true,
+      false,
+      &HalaMeshletConfig::default(),
+      false,
+      &[],
+      0,
+      None,
+    ).unwrap();
+
+    // The 3 primitives have 2 triangles each, they fit in one meshlet.
+    assert_eq!(scene_in_gpu.stats.mesh_primitive_counts, [3]);
+    assert_eq!(scene_in_gpu.stats.meshlet_count, 3);
+    for primitive in scene_in_cpu.meshes[0].primitives.iter() {
+      assert_eq!(primitive.meshlets.len(), 1);
+    }
+
+    context.logical_device.borrow().wait_idle().unwrap();
+  }
+
+  /// Build the glTF JSON with a single orthographic camera.
+  /// param xmag: The half width of the view volume.
+  /// param ymag: The half height of the view volume.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance.
+  /// return: The glTF JSON.
+  fn orthographic_camera_json(xmag: f32, ymag: f32, znear: f32, zfar: f32) -> String {
+    format!(r#"{{
+      "asset": {{ "version": "2.0" }},
+      "scene": 0,
+      "scenes": [{{ "nodes": [0] }}],
+      "nodes": [{{ "camera": 0 }}],
+      "cameras": [{{
+        "type": "orthographic",
+        "orthographic": {{ "xmag": {}, "ymag": {}, "znear": {}, "zfar": {} }}
+      }}]
+    }}"#, xmag, ymag, znear, zfar)
+  }
+
+  #[test]
+  fn test_load_orthographic_camera() {
+    let scene = load_from_json(&orthographic_camera_json(2.0, 1.0, 1.0, 11.0));
+    assert_eq!(scene.nodes[0].camera_index, 0);
+    assert_eq!(scene.cameras.len(), 1);
+
+    let camera = match &scene.cameras[0] {
+      HalaCamera::Orthographic(camera) => camera,
+      _ => panic!("The camera is not orthographic."),
+    };
+    assert_eq!((camera.xmag, camera.ymag, camera.znear, camera.zfar), (2.0, 1.0, 1.0, 11.0));
+
+    let orthography = camera.orthography;
+    assert!((orthography.x_axis.x - 0.5).abs() < 1e-6);
+    assert!((orthography.y_axis.y - 1.0).abs() < 1e-6);
+    assert!((orthography.z_axis.z - 0.1).abs() < 1e-6);
+    assert!((orthography.w_axis.z - 1.1).abs() < 1e-6);
+    // The view volume corners map to the NDC corners, reverse depth puts the near plane at 1 and the far plane at 0.
+    let near_corner = orthography.project_point3(Vec3::new(2.0, 1.0, -1.0));
+    assert!(near_corner.abs_diff_eq(Vec3::new(1.0, 1.0, 1.0), 1e-6), "The near corner is {:?}.", near_corner);
+    let far_corner = orthography.project_point3(Vec3::new(-2.0, -1.0, -11.0));
+    assert!(far_corner.abs_diff_eq(Vec3::new(-1.0, -1.0, 0.0), 1e-6), "The far corner is {:?}.", far_corner);
+  }
+
+  /// Build the glTF JSON with the unit quad and an orthographic camera looking at it along -Z.
+  /// return: The glTF JSON.
+  fn orthographic_scene_json() -> String {
+    format!(r#"{{
+      "asset": {{ "version": "2.0" }},
+      "scene": 0,
+      "scenes": [{{ "nodes": [0, 1] }}],
+      "nodes": [
+        {{ "mesh": 0 }},
+        {{ "camera": 0, "translation": [0.5, 0.5, 5.0] }}
+      ],
+      "meshes": [{{
+        "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "mode": 4 }}]
+      }}],
+      "cameras": [{{
+        "type": "orthographic",
+        "orthographic": {{ "xmag": 1.0, "ymag": 0.5, "znear": 1.0, "zfar": 11.0 }}
+      }}],
+      "buffers": [{{ "byteLength": 132, "uri": "{}" }}],
+      "bufferViews": [{{ "buffer": 0, "byteOffset": 48, "byteLength": 72 }}],
+      "accessors": [
+        {{ "bufferView": 0, "componentType": 5126, "count": 6, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] }}
+      ]
+    }}"#, PRIMITIVES_BUFFER)
+  }
+
+  #[test]
+  #[ignore = "requires a GPU with the ray tracing support and the ray tracing shaders in shaders/rt compiled to SPIR-V"]
+  fn test_render_orthographic_scene() {
+    use crate::renderer::HalaRendererTrait;
+    use crate::rt_renderer::HalaRenderer;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 32;
+
+    let gpu_req = hala_gfx::HalaGPURequirements {
+      width: WIDTH,
+      height: HEIGHT,
+      require_ray_tracing: true,
+      ..Default::default()
+    };
+    let mut renderer = HalaRenderer::new_headless("orthographic_test", &gpu_req, 4, 2, 0).unwrap();
+
+    // The geometry hit group and the light hit group.
+    let shader_path = |name: &str| format!("{}/shaders/rt/{}.spv", env!("CARGO_MANIFEST_DIR"), name);
+    renderer.push_general_shader_with_file(
+      &shader_path("main.rgen"),
+      hala_gfx::HalaShaderStageFlags::RAYGEN,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "main",
+    ).unwrap();
+    renderer.push_general_shader_with_file(
+      &shader_path("main.rmiss"),
+      hala_gfx::HalaShaderStageFlags::MISS,
+      hala_gfx::HalaRayTracingShaderGroupType::GENERAL,
+      "main",
+    ).unwrap();
+    renderer.push_hit_shaders_with_file(Some(&shader_path("main.rchit")), None, None, "main").unwrap();
+    renderer.push_hit_shaders_with_file(Some(&shader_path("light.rchit")), None, Some(&shader_path("light.rint")), "light").unwrap();
+
+    let blue_noise_path = std::env::temp_dir().join("hala_renderer_orthographic_blue_noise.png");
+    image::RgbaImage::from_fn(4, 4, |x, y| image::Rgba([(x * 64) as u8, (y * 64) as u8, 0, 255])).save(&blue_noise_path).unwrap();
+    renderer.load_blue_noise_texture(&blue_noise_path).unwrap();
+
+    // The orthographic camera is uploaded and traced like the perspective one.
+    let mut scene_in_cpu = load_from_json(&orthographic_scene_json());
+    assert!(matches!(scene_in_cpu.cameras[0], HalaCamera::Orthographic(_)));
+    renderer.set_scene(&mut scene_in_cpu).unwrap();
+    renderer.commit().unwrap();
+    renderer.update(0.0, WIDTH, HEIGHT, |_, _| Ok(())).unwrap();
+    renderer.render().unwrap();
+    assert!(!renderer.data.is_device_lost);
+
+    renderer.wait_idle().unwrap();
+  }
+
+  #[test]
+  fn test_load_invalid_orthographic_camera() {
+    assert!(try_load_from_json(&orthographic_camera_json(0.0, 1.0, 1.0, 11.0)).is_err());
+    assert!(try_load_from_json(&orthographic_camera_json(2.0, 1.0, 1.0, 1.0)).is_err());
+  }
+
+  #[test]
+  fn test_decode_uri() {
+    assert_eq!(HalaGltfLoader::decode_uri("textures/base%20color.png"), "textures/base color.png");
+    assert_eq!(HalaGltfLoader::decode_uri("%E4%B8%AD%e6%96%87.png"), "\u{4e2d}\u{6587}.png");
+    assert_eq!(HalaGltfLoader::decode_uri("plain.png"), "plain.png");
+    assert_eq!(HalaGltfLoader::decode_uri("100%.png"), "100%.png");
+    assert_eq!(HalaGltfLoader::decode_uri("%zz%2"), "%zz%2");
+  }
+
+}
@@ -27,17 +27,24 @@ use hala_gfx::{
 
 use crate::{
   error::HalaRendererError,
+  renderer::HalaMemoryBudget,
   scene::{
     HalaVertex,
     HalaBounds,
     HalaMeshlet,
+    HalaMeshletConfig,
+    meshlet::build_meshlets,
+    HalaLod,
+    HalaLodConfig,
+    HalaDepthConvention,
   },
 };
 use super::super::cpu;
 use super::super::gpu;
+use super::transfer_batch::HalaTransferBatch;
 
 const MAX_CAMERA_COUNT: usize = 8;
-const MAX_LIGHT_COUNT: usize = 32;
+pub(crate) const MAX_LIGHT_COUNT: usize = 32;
 
 #[repr(C, align(4))]
 #[derive(Debug, Clone, Copy)]
@@ -51,24 +58,108 @@ pub struct HalaSceneGPUUploader;
 
 /// The implementation of the scene uploader.
 impl HalaSceneGPUUploader {
+  /// Get the hit group index of the lights for ray tracing.
+  /// The lights use the hit group following the last geometry hit group.
+  /// param material_hit_groups: The hit group index of each material type.
+  /// return: The hit group index of the lights.
+  pub fn get_light_hit_group(material_hit_groups: &[u32]) -> u32 {
+    material_hit_groups.iter().max().map_or(1, |hit_group| hit_group + 1)
+  }
+
+  /// Estimate the GPU memory bytes of the scene before uploading.
+  /// It counts the constant buffers, the vertex and index buffers, the meshlet buffers and the images with their mip chains.
+  /// The levels of detail and the acceleration structures are not counted, so the estimation is a lower bound.
+  /// param scene_in_cpu: The scene in the CPU.
+  /// param use_for_mesh_shader: Whether the scene is used for mesh shader.
+  /// return: The estimated bytes.
+  pub fn estimate_memory_size(scene_in_cpu: &cpu::HalaScene, use_for_mesh_shader: bool) -> u64 {
+    let mut size = (std::mem::size_of::<gpu::HalaCamera>() * MAX_CAMERA_COUNT) as u64
+      + (std::mem::size_of::<gpu::HalaLight>() * MAX_LIGHT_COUNT) as u64
+      + (std::mem::size_of::<HalaAABB>() * MAX_LIGHT_COUNT) as u64
+      + (std::mem::size_of::<gpu::HalaMaterial>() * scene_in_cpu.materials.len()) as u64;
+
+    for prim in scene_in_cpu.meshes.iter().flat_map(|mesh| mesh.primitives.iter()) {
+      size += (prim.vertices.len() * std::mem::size_of::<HalaVertex>()) as u64;
+      size += (prim.indices.len() * std::mem::size_of::<u32>()) as u64;
+      if use_for_mesh_shader {
+        // The meshlets are built while uploading, each vertex and each triangle take at least one u32.
+        size += ((prim.vertices.len() + prim.indices.len() / 3) * std::mem::size_of::<u32>()) as u64;
+      }
+    }
+
+    // The full mip chain takes about one third more than the top level.
+    for image_data in scene_in_cpu.image_data.iter() {
+      size += image_data.num_of_bytes as u64 * 4 / 3;
+    }
+
+    size
+  }
+
+  /// Check whether the scene fits in the GPU memory budget before allocating.
+  /// param scene_in_cpu: The scene in the CPU.
+  /// param use_for_mesh_shader: Whether the scene is used for mesh shader.
+  /// param memory_budget: The GPU memory budget.
+  /// return: The estimated bytes of the scene.
+  pub fn check_memory_budget(
+    scene_in_cpu: &cpu::HalaScene,
+    use_for_mesh_shader: bool,
+    memory_budget: &HalaMemoryBudget,
+  ) -> Result<u64, HalaRendererError> {
+    let required = Self::estimate_memory_size(scene_in_cpu, use_for_mesh_shader);
+    if required > memory_budget.available {
+      return Err(HalaRendererError::out_of_memory("scene", required, memory_budget.available));
+    }
+
+    Ok(required)
+  }
+
   /// Upload the scene to the GPU from the CPU for rasterization.
+  /// Without a transfer batch, each buffer copy is submitted to the transfer queue and waited by hala-gfx,
+  /// so the buffers are ready when it returns.
+  /// With a transfer batch, the buffer copies are only recorded, the caller submits the batch and acquires the buffers
+  /// on the graphics queue after its fence is signaled. The ray tracing scene can not use a transfer batch,
+  /// because the acceleration structures are built from the uploaded buffers.
+  /// The images are always uploaded by the graphics queue, because the mipmaps are generated by blitting.
   /// param context: The gfx context.
   /// param graphics_command_buffers: The graphics command buffers.
   /// param transfer_command_buffers: The transfer command buffers.
   /// param scene_in_cpu: The scene in the CPU.
+  /// param optimize_meshes: Whether to reorder the vertices and indices of the primitives in the CPU for the vertex cache, overdraw and vertex fetch.
+  /// param lod_config: The level of detail building configuration.
+  /// param depth_convention: The depth convention of the projection matrices.
+  /// param use_mega_buffers: Whether to suballocate all primitives from one vertex buffer, one index buffer and one set of meshlet buffers.
+  /// param stream_textures: Whether to upload 1x1 placeholders first and leave the full resolution images to stream_next_image.
   /// param use_for_mesh_shader: Whether the scene is used for mesh shader.
   /// param use_global_meshlets: Whether the scene uses global meshlets.
+  /// param meshlet_config: The meshlet building configuration for mesh shader.
   /// param use_for_ray_tracing: Whether the scene is used for ray tracing.
+  /// param material_hit_groups: The hit group index of each material type for ray tracing.
+  /// param max_triangle_lights: The max number of the emissive triangle lights for ray tracing, the weakest triangles are dropped.
+  /// param transfer_batch: The transfer batch to record the buffer copies, none to upload synchronously.
   /// return: The scene in the GPU.
   pub fn upload(
     context: &HalaContext,
     graphics_command_buffers: &HalaCommandBufferSet,
     transfer_command_buffers: &HalaCommandBufferSet,
     scene_in_cpu: &mut cpu::HalaScene,
+    optimize_meshes: bool,
+    lod_config: &HalaLodConfig,
+    depth_convention: HalaDepthConvention,
+    use_mega_buffers: bool,
+    stream_textures: bool,
     use_for_mesh_shader: bool,
     use_global_meshlets: bool,
+    meshlet_config: &HalaMeshletConfig,
     use_for_ray_tracing: bool,
+    material_hit_groups: &[u32],
+    max_triangle_lights: u32,
+    mut transfer_batch: Option<&mut HalaTransferBatch>,
   ) -> Result<gpu::HalaScene, HalaRendererError> {
+    if use_for_ray_tracing && transfer_batch.is_some() {
+      return Err(HalaRendererError::validation("The ray tracing scene can not be uploaded by a transfer batch."));
+    }
+    let transfer_batch = &mut transfer_batch;
+
     // Calculate the buffer size.
     let camera_buffer_size = (std::mem::size_of::<gpu::HalaCamera>() * MAX_CAMERA_COUNT) as u64;
     let light_buffer_size = (std::mem::size_of::<gpu::HalaLight>() * MAX_LIGHT_COUNT) as u64;
@@ -79,13 +170,17 @@ impl HalaSceneGPUUploader {
       std::cmp::max(camera_buffer_size, light_buffer_size),
       material_buffer_size);
 
-    // Create the staging buffer.
-    let staging_buffer = HalaBuffer::new(
-      Rc::clone(&context.logical_device),
-      max_buffer_size,
-      HalaBufferUsageFlags::TRANSFER_SRC,
-      HalaMemoryLocation::CpuToGpu,
-      "staging.buffer")?;
+    // Create the staging buffer, the transfer batch has its own staging memory.
+    let staging_buffer = if transfer_batch.is_none() {
+      Some(HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        max_buffer_size,
+        HalaBufferUsageFlags::TRANSFER_SRC,
+        HalaMemoryLocation::CpuToGpu,
+        "staging.buffer")?)
+    } else {
+      None
+    };
 
     // Create the camera buffer.
     let camera_buffer = HalaBuffer::new(
@@ -112,13 +207,15 @@ impl HalaSceneGPUUploader {
       let camera_node = scene_in_cpu.nodes.iter().find(|&node| node.camera_index == index as u32)
         .ok_or(HalaRendererError::new(&format!("The camera node of the camera {} is not found.", index), None))?;
       camera_view_matrices.push(camera_node.world_transform.inverse());
-      camera_proj_matrices.push(camera.get_proj_matrix());
+      camera_proj_matrices.push(camera.get_proj_matrix(depth_convention));
       cameras.push(gpu::HalaCamera::new(camera_node, camera));
     }
-    camera_buffer.update_gpu_memory_with_buffer_raw(
+    Self::update_buffer_raw(
+      transfer_batch,
+      &camera_buffer,
       cameras.as_ptr() as *const u8,
       std::mem::size_of::<gpu::HalaCamera>() * cameras.len(),
-      &staging_buffer,
+      staging_buffer.as_ref(),
       transfer_command_buffers)?;
 
     // Create the light buffer.
@@ -154,124 +251,7 @@ impl HalaSceneGPUUploader {
 
       let light_index = node.light_index as usize;
       let light_in_cpu = &scene_in_cpu.lights[light_index];
-      let (light, light_aabb) = match light_in_cpu.light_type {
-        cpu::light::HalaLightType::POINT => {
-          (
-            gpu::HalaLight {
-              intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
-              position: node.world_transform.w_axis.xyz().into(),
-              u: glam::Vec3A::ZERO,
-              v: glam::Vec3::ZERO,
-              radius: 0.0,
-              area: 0.0,
-              _type: 0,
-            },
-            HalaAABB {
-              min: [
-                node.world_transform.w_axis.x,
-                node.world_transform.w_axis.y,
-                node.world_transform.w_axis.z,
-              ],
-              max: [
-                node.world_transform.w_axis.x,
-                node.world_transform.w_axis.y,
-                node.world_transform.w_axis.z,
-              ],
-            }
-          )
-        },
-        cpu::light::HalaLightType::DIRECTIONAL => {
-          (
-            gpu::HalaLight {
-              intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
-              position: glam::Vec3A::ZERO,
-              u: (-node.world_transform.z_axis.xyz()).into(),
-              v: glam::Vec3::new((0.5 * light_in_cpu.params.0).cos(), 0.0, 0.0),
-              radius: 0.0,
-              area: 0.0,
-              _type: 1,
-            },
-            HalaAABB {
-              min: [0.0, 0.0, 0.0],
-              max: [0.0, 0.0, 0.0],
-            }
-          )
-        },
-        cpu::light::HalaLightType::SPOT => {
-          (
-            gpu::HalaLight {
-              intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
-              position: node.world_transform.w_axis.xyz().into(),
-              u: (-node.world_transform.z_axis.xyz()).into(),
-              v: glam::Vec3::new(light_in_cpu.params.0.cos(), light_in_cpu.params.1.cos(), 0.0),
-              radius: 0.0,
-              area: 0.0,
-              _type: 2,
-            },
-            HalaAABB {
-              min: [
-                node.world_transform.w_axis.x,
-                node.world_transform.w_axis.y,
-                node.world_transform.w_axis.z,
-              ],
-              max: [
-                node.world_transform.w_axis.x,
-                node.world_transform.w_axis.y,
-                node.world_transform.w_axis.z,
-              ],
-            }
-          )
-        },
-        cpu::light::HalaLightType::QUAD => {
-          let mut position = node.world_transform.w_axis.xyz();
-          position -= node.world_transform.x_axis.xyz() * light_in_cpu.params.0 * 0.5;
-          position -= node.world_transform.y_axis.xyz() * light_in_cpu.params.1 * 0.5;
-          let another = position + node.world_transform.x_axis.xyz() * light_in_cpu.params.0 + node.world_transform.y_axis.xyz() * light_in_cpu.params.1 + node.world_transform.z_axis.xyz() * 0.01;
-          (
-            gpu::HalaLight {
-              intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
-              position: position.into(),
-              u: (node.world_transform.x_axis.xyz() * light_in_cpu.params.0).into(),
-              v: node.world_transform.y_axis.xyz() * light_in_cpu.params.1,
-              radius: 0.0,
-              area: light_in_cpu.params.0 * light_in_cpu.params.1,
-              _type: 3,
-            },
-            HalaAABB {
-              min: [
-                position.x,
-                position.y,
-                position.z,
-              ],
-              max: [
-                another.x,
-                another.y,
-                another.z,
-              ],
-            }
-          )
-        },
-        cpu::light::HalaLightType::SPHERE => {
-          let min = node.world_transform.w_axis.xyz() - glam::Vec3::splat(light_in_cpu.params.0);
-          let max = node.world_transform.w_axis.xyz() + glam::Vec3::splat(light_in_cpu.params.0);
-          (
-            gpu::HalaLight {
-              intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
-              position: node.world_transform.w_axis.xyz().into(),
-              u: glam::Vec3A::ZERO,
-              v: glam::Vec3::ZERO,
-              radius: light_in_cpu.params.0,
-              area: 4.0 * std::f32::consts::PI * light_in_cpu.params.0 * light_in_cpu.params.0,
-              _type: 4,
-            },
-            HalaAABB {
-              min: [min.x, min.y, min.z],
-              max: [max.x, max.y, max.z],
-            }
-          )
-        },
-        _ => panic!("Invalid light type."),
-      };
+      let (light, light_aabb) = Self::convert_light(light_in_cpu, &node.world_transform);
 
       let min_x = f32::min(light_aabb.min[0], light_aabb.max[0]);
       let min_y = f32::min(light_aabb.min[1], light_aabb.max[1]);
@@ -291,21 +271,26 @@ impl HalaSceneGPUUploader {
         break;
       }
     }
-    light_buffer.update_gpu_memory_with_buffer_raw(
+    Self::update_buffer_raw(
+      transfer_batch,
+      &light_buffer,
       lights.as_ptr() as *const u8,
       std::mem::size_of::<gpu::HalaLight>() * lights.len(),
-      &staging_buffer,
+      staging_buffer.as_ref(),
       transfer_command_buffers)?;
-    light_aabb_buffer.update_gpu_memory_with_buffer_raw(
+    Self::update_buffer_raw(
+      transfer_batch,
+      &light_aabb_buffer,
       light_aabbs.as_ptr() as *const u8,
       std::mem::size_of::<HalaAABB>() * light_aabbs.len(),
-      &staging_buffer,
+      staging_buffer.as_ref(),
       transfer_command_buffers)?;
 
     // Create the material buffers.
     let mut material_buffers = Vec::with_capacity(scene_in_cpu.materials.len());
     let mut material_types = Vec::with_capacity(scene_in_cpu.materials.len());
     let mut material_deferred_flags = Vec::with_capacity(scene_in_cpu.materials.len());
+    let mut material_emissive_flags = Vec::with_capacity(scene_in_cpu.materials.len());
 
     // Copy the material data to GPU by the staging buffer.
     for (material_index, material) in scene_in_cpu.materials.iter().enumerate() {
@@ -319,15 +304,18 @@ impl HalaSceneGPUUploader {
         &format!("material_{}.buffer", material_index)
       )?;
 
-      material_buffer.update_gpu_memory_with_buffer_raw(
+      Self::update_buffer_raw(
+        transfer_batch,
+        &material_buffer,
         &gpu_material as *const gpu::HalaMaterial as *const u8,
         material_buffer_size as usize,
-        &staging_buffer,
+        staging_buffer.as_ref(),
         transfer_command_buffers)?;
 
       material_buffers.push(material_buffer);
       material_types.push(gpu_material._type);
       material_deferred_flags.push(material.opacity >= 1.0);
+      material_emissive_flags.push(material.is_emissive());
     }
 
     // Create the samplers and images.
@@ -354,7 +342,13 @@ impl HalaSceneGPUUploader {
     }
 
     let mut images = Vec::with_capacity(scene_in_cpu.image_data.len());
-    let max_texture_size = scene_in_cpu.image_data.iter().map(|texture| texture.num_of_bytes).max().unwrap_or(0);
+    let mut pending_images = Vec::new();
+    let mut stats = gpu::HalaSceneGPUStats::default();
+    let max_texture_size = if stream_textures {
+      scene_in_cpu.image_data.iter().map(|texture| texture.to_placeholder().num_of_bytes).max().unwrap_or(0)
+    } else {
+      scene_in_cpu.image_data.iter().map(|texture| texture.num_of_bytes).max().unwrap_or(0)
+    };
     if max_texture_size > 0 {
       let image_staging = HalaBuffer::new(
         Rc::clone(&context.logical_device),
@@ -363,97 +357,120 @@ impl HalaSceneGPUUploader {
         HalaMemoryLocation::CpuToGpu,
         "image_staging.buffer")?;
       for (index, texture) in scene_in_cpu.image_data.iter().enumerate() {
-        let max_mip_levels = texture.width.max(texture.height).next_power_of_two().trailing_zeros() + 1;
-        log::debug!("Texture {} has {} mip levels.", index, max_mip_levels);
-
-        let image = HalaImage::new_2d(
-          Rc::clone(&context.logical_device),
-          HalaImageUsageFlags::SAMPLED | HalaImageUsageFlags::TRANSFER_SRC | HalaImageUsageFlags::TRANSFER_DST,
-          texture.format,
-          texture.width,
-          texture.height,
-          max_mip_levels,
-          1,
-          HalaMemoryLocation::GpuOnly,
-          &format!("texture_{}.image", index)
-        )?;
-        match texture.data_type {
-          cpu::image_data::HalaImageDataType::ByteData(ref data) => {
-            image.update_gpu_memory_with_buffer(
-              data.as_slice(),
-                hala_gfx::HalaPipelineStageFlags2::TRANSFER,
-                hala_gfx::HalaAccessFlags2::TRANSFER_WRITE,
-                hala_gfx::HalaImageLayout::TRANSFER_DST_OPTIMAL,
-              &image_staging,
-              graphics_command_buffers)?;
-          },
-          cpu::image_data::HalaImageDataType::FloatData(ref data) => {
-            image.update_gpu_memory_with_buffer(
-              data.as_slice(),
-                hala_gfx::HalaPipelineStageFlags2::TRANSFER,
-                hala_gfx::HalaAccessFlags2::TRANSFER_WRITE,
-                hala_gfx::HalaImageLayout::TRANSFER_DST_OPTIMAL,
-              &image_staging,
-              graphics_command_buffers)?;
-          }
+        let image = if stream_textures {
+          let placeholder = texture.to_placeholder();
+          let image = Self::create_image_with_data(context, graphics_command_buffers, &image_staging, index, &placeholder)?;
+          stats.set_texture(index, Self::get_texture_stats(&placeholder));
+          pending_images.push((index, texture.clone()));
+          image
+        } else {
+          stats.set_texture(index, Self::get_texture_stats(texture));
+          Self::create_image_with_data(context, graphics_command_buffers, &image_staging, index, texture)?
         };
-        image.gen_mipmaps(graphics_command_buffers)?;
         images.push(image);
       }
     }
 
+    // Optimize the primitives before building the levels of detail and the meshlets.
+    if optimize_meshes {
+      for mesh in scene_in_cpu.meshes.iter_mut() {
+        for prim in mesh.primitives.iter_mut() {
+          Self::optimize_primitive(prim)?;
+        }
+      }
+    }
+
+    // Build the levels of detail, all levels of a primitive share one index buffer.
+    lod_config.validate()?;
+    let lod_data = scene_in_cpu.meshes.iter().map(
+      |mesh| mesh.primitives.iter().map(|prim| Self::build_lods(prim, lod_config)).collect::<Result<Vec<_>, _>>()
+    ).collect::<Result<Vec<_>, _>>()?;
+
     // Create the meshes.
     let mut meshes = Vec::with_capacity(scene_in_cpu.meshes.len());
     let vertex_size = std::mem::size_of::<HalaVertex>();
-    let max_vertex_buffer_size = scene_in_cpu.meshes.iter().map(
-      |mesh| mesh.primitives.iter().map(|prim| prim.vertices.len() * vertex_size).max().unwrap_or(0)
-    ).max().unwrap_or(0);
-    let max_index_buffer_size = scene_in_cpu.meshes.iter().map(
-      |mesh| mesh.primitives.iter().map(|prim| prim.indices.len() * std::mem::size_of::<u32>()).max().unwrap_or(0)
-    ).max().unwrap_or(0);
-    let mesh_staging_buffer_size = std::cmp::max(max_vertex_buffer_size, max_index_buffer_size) as u64;
-    let mesh_staging_buffer = HalaBuffer::new(
-      Rc::clone(&context.logical_device),
-      mesh_staging_buffer_size,
-      HalaBufferUsageFlags::TRANSFER_SRC,
-      HalaMemoryLocation::CpuToGpu,
-      "mesh_staging.buffer")?;
+    let vertex_buffer_usage = HalaBufferUsageFlags::VERTEX_BUFFER
+      | HalaBufferUsageFlags::TRANSFER_DST
+      | (if use_for_ray_tracing { HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY } else { HalaBufferUsageFlags::default() })
+      | HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
+      | HalaBufferUsageFlags::STORAGE_BUFFER;
+    let index_buffer_usage = HalaBufferUsageFlags::INDEX_BUFFER
+      | HalaBufferUsageFlags::TRANSFER_DST
+      | (if use_for_ray_tracing { HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY } else { HalaBufferUsageFlags::default() })
+      | HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
+      | HalaBufferUsageFlags::STORAGE_BUFFER;
+    let mesh_staging_buffer_size = if use_mega_buffers {
+      let total_vertex_buffer_size = scene_in_cpu.meshes.iter().map(
+        |mesh| mesh.primitives.iter().map(|prim| prim.vertices.len() * vertex_size).sum::<usize>()
+      ).sum::<usize>();
+      let total_index_buffer_size = lod_data.iter().map(
+        |mesh| mesh.iter().map(|(indices, _)| indices.len() * std::mem::size_of::<u32>()).sum::<usize>()
+      ).sum::<usize>();
+      std::cmp::max(total_vertex_buffer_size, total_index_buffer_size) as u64
+    } else {
+      let max_vertex_buffer_size = scene_in_cpu.meshes.iter().map(
+        |mesh| mesh.primitives.iter().map(|prim| prim.vertices.len() * vertex_size).max().unwrap_or(0)
+      ).max().unwrap_or(0);
+      let max_index_buffer_size = lod_data.iter().map(
+        |mesh| mesh.iter().map(|(indices, _)| indices.len() * std::mem::size_of::<u32>()).max().unwrap_or(0)
+      ).max().unwrap_or(0);
+      std::cmp::max(max_vertex_buffer_size, max_index_buffer_size) as u64
+    };
+    let mesh_staging_buffer = if transfer_batch.is_none() {
+      Some(HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        mesh_staging_buffer_size,
+        HalaBufferUsageFlags::TRANSFER_SRC,
+        HalaMemoryLocation::CpuToGpu,
+        "mesh_staging.buffer")?)
+    } else {
+      None
+    };
+    let mut mega_vertices = Vec::new();
+    let mut mega_indices = Vec::new();
     for (mesh_index, mesh) in scene_in_cpu.meshes.iter().enumerate() {
       let mut primitives = Vec::with_capacity(mesh.primitives.len());
       for (prim_index, prim) in mesh.primitives.iter().enumerate() {
-        let vertex_buffer_size = (prim.vertices.len() * std::mem::size_of::<HalaVertex>()) as u64;
-        let vertex_buffer = HalaBuffer::new(
-          Rc::clone(&context.logical_device),
-          vertex_buffer_size,
-          HalaBufferUsageFlags::VERTEX_BUFFER
-            | HalaBufferUsageFlags::TRANSFER_DST
-            | (if use_for_ray_tracing { HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY } else { HalaBufferUsageFlags::default() })
-            | HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
-            | HalaBufferUsageFlags::STORAGE_BUFFER,
-          HalaMemoryLocation::GpuOnly,
-          &format!("mesh_{}_prim_{}_vertex.buffer", mesh_index, prim_index))?;
-        vertex_buffer.update_gpu_memory_with_buffer_raw(
-          prim.vertices.as_ptr() as *const u8,
-          vertex_buffer_size as usize,
-          &mesh_staging_buffer,
-          transfer_command_buffers)?;
+        let (lod_indices, lods) = &lod_data[mesh_index][prim_index];
+        let (vertex_buffer, index_buffer, vertex_offset, first_index) = if use_mega_buffers {
+          // Suballocate the primitive from the mega buffers, the indices stay relative to the first vertex.
+          let offsets = (mega_vertices.len() as u32, mega_indices.len() as u32);
+          mega_vertices.extend_from_slice(&prim.vertices);
+          mega_indices.extend_from_slice(lod_indices);
+          (None, None, offsets.0, offsets.1)
+        } else {
+          let vertex_buffer_size = (prim.vertices.len() * vertex_size) as u64;
+          let vertex_buffer = HalaBuffer::new(
+            Rc::clone(&context.logical_device),
+            vertex_buffer_size,
+            vertex_buffer_usage,
+            HalaMemoryLocation::GpuOnly,
+            &format!("mesh_{}_prim_{}_vertex.buffer", mesh_index, prim_index))?;
+          Self::update_buffer_raw(
+            transfer_batch,
+            &vertex_buffer,
+            prim.vertices.as_ptr() as *const u8,
+            vertex_buffer_size as usize,
+            mesh_staging_buffer.as_ref(),
+            transfer_command_buffers)?;
 
-        let index_buffer_size = (prim.indices.len() * std::mem::size_of::<u32>()) as u64;
-        let index_buffer = HalaBuffer::new(
-          Rc::clone(&context.logical_device),
-          index_buffer_size,
-          HalaBufferUsageFlags::INDEX_BUFFER
-            | HalaBufferUsageFlags::TRANSFER_DST
-            | (if use_for_ray_tracing { HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY } else { HalaBufferUsageFlags::default() })
-            | HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
-            | HalaBufferUsageFlags::STORAGE_BUFFER,
-          HalaMemoryLocation::GpuOnly,
-          &format!("mesh_{}_prim_{}_index.buffer", mesh_index, prim_index))?;
-        index_buffer.update_gpu_memory_with_buffer_raw(
-          prim.indices.as_ptr() as *const u8,
-          index_buffer_size as usize,
-          &mesh_staging_buffer,
-          transfer_command_buffers)?;
+          let index_buffer_size = (lod_indices.len() * std::mem::size_of::<u32>()) as u64;
+          let index_buffer = HalaBuffer::new(
+            Rc::clone(&context.logical_device),
+            index_buffer_size,
+            index_buffer_usage,
+            HalaMemoryLocation::GpuOnly,
+            &format!("mesh_{}_prim_{}_index.buffer", mesh_index, prim_index))?;
+          Self::update_buffer_raw(
+            transfer_batch,
+            &index_buffer,
+            lod_indices.as_ptr() as *const u8,
+            index_buffer_size as usize,
+            mesh_staging_buffer.as_ref(),
+            transfer_command_buffers)?;
+
+          (Some(vertex_buffer), Some(index_buffer), 0, 0)
+        };
 
         let material_index = prim.material_index;
 
@@ -469,11 +486,15 @@ impl HalaSceneGPUUploader {
         primitives.push(gpu::HalaPrimitive {
           vertex_buffer,
           index_buffer,
+          vertex_offset,
+          first_index,
           vertex_count: prim.vertices.len() as u32,
           index_count: prim.indices.len() as u32,
           material_index,
           bounds,
+          lods: lods.clone(),
           meshlet_count: 0,
+          first_meshlet: 0,
           meshlet_buffer: None,
           meshlet_vertex_buffer: None,
           meshlet_primitive_buffer: None,
@@ -486,6 +507,39 @@ impl HalaSceneGPUUploader {
       });
     }
 
+    // Create the mega buffers.
+    let (mega_vertex_buffer, mega_index_buffer) = if use_mega_buffers && !mega_indices.is_empty() {
+      let mega_vertex_buffer = HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (mega_vertices.len() * vertex_size) as u64,
+        vertex_buffer_usage,
+        HalaMemoryLocation::GpuOnly,
+        "mega_vertex.buffer")?;
+      Self::update_buffer(
+        transfer_batch,
+        &mega_vertex_buffer,
+        mega_vertices.as_slice(),
+        mesh_staging_buffer.as_ref(),
+        transfer_command_buffers)?;
+
+      let mega_index_buffer = HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (mega_indices.len() * std::mem::size_of::<u32>()) as u64,
+        index_buffer_usage,
+        HalaMemoryLocation::GpuOnly,
+        "mega_index.buffer")?;
+      Self::update_buffer(
+        transfer_batch,
+        &mega_index_buffer,
+        mega_indices.as_slice(),
+        mesh_staging_buffer.as_ref(),
+        transfer_command_buffers)?;
+
+      (Some(mega_vertex_buffer), Some(mega_index_buffer))
+    } else {
+      (None, None)
+    };
+
     // Update the transform of the meshs.
     for node in scene_in_cpu.nodes.iter() {
       if node.mesh_index == u32::MAX {
@@ -500,24 +554,40 @@ impl HalaSceneGPUUploader {
     let mut scene_in_gpu = gpu::HalaScene {
       camera_view_matrices,
       camera_proj_matrices,
+      camera_sources: scene_in_cpu.cameras.iter().take(MAX_CAMERA_COUNT).cloned().collect(),
+      camera_data: cameras,
+      depth_convention,
       cameras: camera_buffer,
       lights: light_buffer,
       light_aabbs: light_aabb_buffer,
       materials: material_buffers,
       material_types,
       material_deferred_flags,
+      material_emissive_flags,
       textures,
       samplers,
       images,
+      pending_images,
       meshes,
       instances: None,
       tplas: None,
+      instance_sources: Vec::new(),
       primitives: Vec::new(),
       light_btlas: None,
       light_data: lights,
+      triangle_lights: None,
+      triangle_light_cdf: None,
+      num_of_triangle_lights: 0,
+      triangle_light_total_power: 0.0,
       meshlet_count: 0,
       meshlets: None,
       meshlet_draw_data: None,
+      mega_vertex_buffer,
+      mega_index_buffer,
+      mega_meshlet_buffer: None,
+      mega_meshlet_vertex_buffer: None,
+      mega_meshlet_primitive_buffer: None,
+      stats,
     };
 
     if use_for_mesh_shader {
@@ -527,7 +597,11 @@ impl HalaSceneGPUUploader {
         transfer_command_buffers,
         scene_in_cpu,
         &mut scene_in_gpu,
+        &lod_data,
+        use_mega_buffers,
         use_global_meshlets,
+        meshlet_config,
+        transfer_batch,
       )?;
     }
 
@@ -538,19 +612,452 @@ impl HalaSceneGPUUploader {
         transfer_command_buffers,
         scene_in_cpu,
         &mut scene_in_gpu,
+        material_hit_groups,
+        max_triangle_lights,
       )?;
     }
 
+    let mut stats = std::mem::take(&mut scene_in_gpu.stats);
+    stats.update_buffers(&scene_in_gpu);
+    scene_in_gpu.stats = stats;
+
     Ok(scene_in_gpu)
   }
 
+  /// Reload the image data of an image in the GPU scene.
+  /// A new image is always created, so the existing one can still be read by the in-flight frames.
+  /// The caller must retire the replaced image and rewrite the descriptor entries of the textures.
+  /// param context: The gfx context.
+  /// param command_buffers: The one time graphics command buffers which are not used by any frame.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param image_index: The index of the image.
+  /// param image_data: The new image data.
+  /// return: The replaced image.
+  pub fn reload_image(
+    context: &HalaContext,
+    command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &mut gpu::HalaScene,
+    image_index: usize,
+    image_data: &cpu::image_data::HalaImageData,
+  ) -> Result<HalaImage, HalaRendererError> {
+    let old_image = scene_in_gpu.images.get(image_index)
+      .ok_or(HalaRendererError::new(&format!("The image {} is not found.", image_index), None))?;
+    log::debug!(
+      "Texture {} changed from {:?}[{} x {}] to {:?}[{} x {}].",
+      image_index,
+      old_image.format, old_image.extent.width, old_image.extent.height,
+      image_data.format, image_data.width, image_data.height,
+    );
+
+    let image = Self::create_image_with_data(context, command_buffers, staging_buffer, image_index, image_data)?;
+    let old_image = std::mem::replace(&mut scene_in_gpu.images[image_index], image);
+    scene_in_gpu.stats.set_texture(image_index, Self::get_texture_stats(image_data));
+
+    Ok(old_image)
+  }
+
+  /// Upload the full resolution data of the next pending image and replace its placeholder.
+  /// The caller must retire the placeholder and rewrite the descriptor entries of the textures after the replacement.
+  /// param context: The gfx context.
+  /// param graphics_command_buffers: The one time graphics command buffers which are not used by any frame.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// return: The replaced placeholder image, none if no image is pending.
+  pub fn stream_next_image(
+    context: &HalaContext,
+    graphics_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &mut gpu::HalaScene,
+  ) -> Result<Option<HalaImage>, HalaRendererError> {
+    if scene_in_gpu.pending_images.is_empty() {
+      return Ok(None);
+    }
+
+    let (image_index, image_data) = scene_in_gpu.pending_images.remove(0);
+    let image = Self::create_image_with_data(context, graphics_command_buffers, staging_buffer, image_index, &image_data)?;
+    log::debug!("Texture {} is resident, {} textures are pending.", image_index, scene_in_gpu.pending_images.len());
+
+    let placeholder = std::mem::replace(
+      scene_in_gpu.images.get_mut(image_index)
+        .ok_or(HalaRendererError::new(&format!("The image {} is not found.", image_index), None))?,
+      image,
+    );
+    scene_in_gpu.stats.set_texture(image_index, Self::get_texture_stats(&image_data));
+
+    Ok(Some(placeholder))
+  }
+
+  /// Get the number of the mip levels of the full mip chain.
+  /// param image_data: The image data.
+  /// return: The number of the mip levels.
+  fn get_mip_levels(image_data: &cpu::image_data::HalaImageData) -> u32 {
+    image_data.width.max(image_data.height).next_power_of_two().trailing_zeros() + 1
+  }
+
+  /// Get the statistics of the image created with the image data.
+  /// param image_data: The image data.
+  /// return: The texture statistics.
+  fn get_texture_stats(image_data: &cpu::image_data::HalaImageData) -> gpu::HalaTextureStats {
+    gpu::HalaSceneGPUStats::get_texture_stats(
+      image_data.format,
+      image_data.width,
+      image_data.height,
+      image_data.num_of_bytes,
+      Self::get_mip_levels(image_data),
+    )
+  }
+
+  /// Create the image with the full mip chain and upload the image data to it.
+  /// param context: The gfx context.
+  /// param graphics_command_buffers: The graphics command buffers.
+  /// param staging_buffer: The staging buffer.
+  /// param image_index: The index of the image.
+  /// param image_data: The image data.
+  /// return: The image.
+  fn create_image_with_data(
+    context: &HalaContext,
+    graphics_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    image_index: usize,
+    image_data: &cpu::image_data::HalaImageData,
+  ) -> Result<HalaImage, HalaRendererError> {
+    let max_mip_levels = Self::get_mip_levels(image_data);
+    log::debug!("Texture {} has {} mip levels.", image_index, max_mip_levels);
+
+    let image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      HalaImageUsageFlags::SAMPLED | HalaImageUsageFlags::TRANSFER_SRC | HalaImageUsageFlags::TRANSFER_DST,
+      image_data.format,
+      image_data.width,
+      image_data.height,
+      max_mip_levels,
+      1,
+      HalaMemoryLocation::GpuOnly,
+      &format!("texture_{}.image", image_index)
+    )?;
+    Self::update_image_with_data(&image, image_data, staging_buffer, graphics_command_buffers)?;
+
+    Ok(image)
+  }
+
+  /// Set the perspective parameters of the camera and rebuild its projection.
+  /// The caller must make sure the camera buffer is not used by any in-flight frame.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param camera_index: The index of the camera.
+  /// param yfov: The vertical field of view in radians.
+  /// param znear: The near plane distance.
+  /// param zfar: The far plane distance, only used by the standard Z projection.
+  /// return: The result.
+  pub fn set_camera_perspective(
+    transfer_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &mut gpu::HalaScene,
+    camera_index: usize,
+    yfov: f32,
+    znear: f32,
+    zfar: f32,
+  ) -> Result<(), HalaRendererError> {
+    if yfov <= 0.0 || yfov >= std::f32::consts::PI {
+      return Err(HalaRendererError::validation(&format!("The field of view {} must be in (0, PI).", yfov)));
+    }
+    if znear <= 0.0 || zfar <= znear {
+      return Err(HalaRendererError::validation(&format!("The near {} and far {} planes must satisfy 0 < near < far.", znear, zfar)));
+    }
+    let depth_convention = scene_in_gpu.depth_convention;
+    let camera = match scene_in_gpu.camera_sources.get_mut(camera_index) {
+      Some(cpu::camera::HalaCamera::Perspective(camera)) => camera,
+      Some(_) => return Err(HalaRendererError::new(&format!("The camera {} is not a perspective camera.", camera_index), None)),
+      None => return Err(HalaRendererError::new(&format!("The camera index {} is out of range.", camera_index), None)),
+    };
+    camera.yfov = yfov;
+    camera.znear = znear;
+    camera.zfar = zfar;
+    // Keep the infinite reverse perspective projection(depth range: 1 to 0) as the loader.
+    camera.projection = glam::Mat4::perspective_infinite_reverse_rh(yfov, camera.aspect, znear);
+
+    scene_in_gpu.camera_proj_matrices[camera_index] = scene_in_gpu.camera_sources[camera_index].get_proj_matrix(depth_convention);
+    scene_in_gpu.camera_data[camera_index].yfov = yfov;
+
+    Self::update_camera_buffer(transfer_command_buffers, staging_buffer, scene_in_gpu)
+  }
+
+  /// Set the view matrix of the camera and update its position and axes in the camera buffer.
+  /// The caller must make sure the camera buffer is not used by any in-flight frame.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param camera_index: The index of the camera.
+  /// param v_mtx: The view matrix.
+  /// return: The result.
+  pub fn set_camera_view_matrix(
+    transfer_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &mut gpu::HalaScene,
+    camera_index: usize,
+    v_mtx: glam::Mat4,
+  ) -> Result<(), HalaRendererError> {
+    if camera_index >= scene_in_gpu.camera_view_matrices.len() {
+      return Err(HalaRendererError::new(&format!("The camera index {} is out of range.", camera_index), None));
+    }
+    let world_mtx = v_mtx.inverse();
+    if !world_mtx.is_finite() {
+      return Err(HalaRendererError::validation("The view matrix is not invertible."));
+    }
+
+    scene_in_gpu.camera_view_matrices[camera_index] = v_mtx;
+    let camera = &mut scene_in_gpu.camera_data[camera_index];
+    camera.position = world_mtx.w_axis.truncate().into();
+    camera.right = world_mtx.x_axis.truncate().into();
+    camera.up = world_mtx.y_axis.truncate().into();
+    camera.forward = -world_mtx.z_axis.truncate();
+
+    Self::update_camera_buffer(transfer_command_buffers, staging_buffer, scene_in_gpu)
+  }
+
+  /// Set the aperture and the focal distance of the camera.
+  /// The caller must make sure the camera buffer is not used by any in-flight frame.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param camera_index: The index of the camera.
+  /// param aperture: The aperture, 0 disables the depth of field.
+  /// param focal_distance: The focal distance.
+  /// return: The result.
+  pub fn set_camera_aperture_focal(
+    transfer_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &mut gpu::HalaScene,
+    camera_index: usize,
+    aperture: f32,
+    focal_distance: f32,
+  ) -> Result<(), HalaRendererError> {
+    if aperture < 0.0 || focal_distance <= 0.0 {
+      return Err(HalaRendererError::validation(&format!("The aperture {} must not be negative and the focal distance {} must be positive.", aperture, focal_distance)));
+    }
+    let camera = match scene_in_gpu.camera_sources.get_mut(camera_index) {
+      Some(cpu::camera::HalaCamera::Perspective(camera)) => camera,
+      Some(_) => return Err(HalaRendererError::new(&format!("The camera {} is not a perspective camera.", camera_index), None)),
+      None => return Err(HalaRendererError::new(&format!("The camera index {} is out of range.", camera_index), None)),
+    };
+    camera.aperture = aperture;
+    camera.focal_distance = focal_distance;
+
+    scene_in_gpu.camera_data[camera_index].aperture_or_ymag = aperture;
+    scene_in_gpu.camera_data[camera_index].focal_distance_or_xmag = focal_distance;
+
+    Self::update_camera_buffer(transfer_command_buffers, staging_buffer, scene_in_gpu)
+  }
+
+  /// Upload the camera data to the camera buffer.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param staging_buffer: The staging buffer.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// return: The result.
+  fn update_camera_buffer(
+    transfer_command_buffers: &HalaCommandBufferSet,
+    staging_buffer: &HalaBuffer,
+    scene_in_gpu: &gpu::HalaScene,
+  ) -> Result<(), HalaRendererError> {
+    scene_in_gpu.cameras.update_gpu_memory_with_buffer_raw(
+      scene_in_gpu.camera_data.as_ptr() as *const u8,
+      std::mem::size_of::<gpu::HalaCamera>() * scene_in_gpu.camera_data.len(),
+      staging_buffer,
+      transfer_command_buffers)?;
+
+    Ok(())
+  }
+
+  /// Update the buffer by the transfer batch if any, otherwise upload it synchronously by the staging buffer.
+  /// param transfer_batch: The transfer batch.
+  /// param buffer: The buffer.
+  /// param data: The data.
+  /// param staging_buffer: The staging buffer, it is none if the transfer batch is used.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// return: The result.
+  fn update_buffer<T: Copy>(
+    transfer_batch: &mut Option<&mut HalaTransferBatch>,
+    buffer: &HalaBuffer,
+    data: &[T],
+    staging_buffer: Option<&HalaBuffer>,
+    transfer_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<(), HalaRendererError> {
+    Self::update_buffer_raw(
+      transfer_batch,
+      buffer,
+      data.as_ptr() as *const u8,
+      std::mem::size_of_val(data),
+      staging_buffer,
+      transfer_command_buffers)
+  }
+
+  /// Update the buffer with the raw data by the transfer batch if any, otherwise upload it synchronously by the staging buffer.
+  /// param transfer_batch: The transfer batch.
+  /// param buffer: The buffer.
+  /// param data: The pointer of the data.
+  /// param size: The size of the data in bytes.
+  /// param staging_buffer: The staging buffer, it is none if the transfer batch is used.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// return: The result.
+  fn update_buffer_raw(
+    transfer_batch: &mut Option<&mut HalaTransferBatch>,
+    buffer: &HalaBuffer,
+    data: *const u8,
+    size: usize,
+    staging_buffer: Option<&HalaBuffer>,
+    transfer_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<(), HalaRendererError> {
+    match (transfer_batch, staging_buffer) {
+      (Some(transfer_batch), _) => transfer_batch.update_buffer_raw(buffer, data, size),
+      (None, Some(staging_buffer)) => Ok(buffer.update_gpu_memory_with_buffer_raw(data, size, staging_buffer, transfer_command_buffers)?),
+      (None, None) => Err(HalaRendererError::invalid_state("The staging buffer of the synchronous upload is none!")),
+    }
+  }
+
+  /// Upload the image data to the image and generate the mipmaps.
+  /// It uses the graphics queue, the transfer queue may not support the blit of the mipmap generation.
+  /// param image: The image.
+  /// param image_data: The image data.
+  /// param staging_buffer: The staging buffer.
+  /// param graphics_command_buffers: The graphics command buffers.
+  /// return: The result.
+  fn update_image_with_data(
+    image: &HalaImage,
+    image_data: &cpu::image_data::HalaImageData,
+    staging_buffer: &HalaBuffer,
+    graphics_command_buffers: &HalaCommandBufferSet,
+  ) -> Result<(), HalaRendererError> {
+    match image_data.data_type {
+      cpu::image_data::HalaImageDataType::ByteData(ref data) => {
+        image.update_gpu_memory_with_buffer(
+          data.as_slice(),
+          hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+          hala_gfx::HalaAccessFlags2::TRANSFER_WRITE,
+          hala_gfx::HalaImageLayout::TRANSFER_DST_OPTIMAL,
+          staging_buffer,
+          graphics_command_buffers)?;
+      },
+      cpu::image_data::HalaImageDataType::FloatData(ref data) => {
+        image.update_gpu_memory_with_buffer(
+          data.as_slice(),
+          hala_gfx::HalaPipelineStageFlags2::TRANSFER,
+          hala_gfx::HalaAccessFlags2::TRANSFER_WRITE,
+          hala_gfx::HalaImageLayout::TRANSFER_DST_OPTIMAL,
+          staging_buffer,
+          graphics_command_buffers)?;
+      }
+    };
+    image.gen_mipmaps(graphics_command_buffers)?;
+
+    Ok(())
+  }
+
+  /// Optimize the primitive with meshopt in place.
+  /// The triangles are reordered for the vertex cache and the overdraw, then the vertices are reordered for the vertex fetch.
+  /// param prim: The primitive in the CPU.
+  /// return: The result.
+  fn optimize_primitive(prim: &mut cpu::HalaPrimitive) -> Result<(), HalaRendererError> {
+    if prim.indices.is_empty() || prim.vertices.is_empty() {
+      return Ok(());
+    }
+
+    let mut indices = meshopt::optimize_vertex_cache(&prim.indices, prim.vertices.len());
+    {
+      let vertex_data_adapter = unsafe {
+        meshopt::VertexDataAdapter::new(
+          std::slice::from_raw_parts(prim.vertices.as_ptr() as *const u8, prim.vertices.len() * std::mem::size_of::<HalaVertex>()),
+          std::mem::size_of::<HalaVertex>(),
+          0,
+        ).map_err(|err| HalaRendererError::new("Failed to create vertex data adapter.", Some(Box::new(err))))?
+      };
+      // Allow 5% vertex cache degradation for less overdraw.
+      meshopt::optimize_overdraw_in_place(&mut indices, &vertex_data_adapter, 1.05);
+    }
+    prim.vertices = meshopt::optimize_vertex_fetch(&mut indices, &prim.vertices);
+    prim.indices = indices;
+
+    Ok(())
+  }
+
+  /// Build the levels of detail of the primitive with the meshopt simplification.
+  /// The simplification stops when the error exceeds the max error or the index count can not be reduced enough,
+  /// e.g. the UV or normal seams block the edge collapses, so such primitives only have the level 0.
+  /// param prim: The primitive in the CPU.
+  /// param lod_config: The level of detail configuration.
+  /// return: The indices of all levels and the levels.
+  fn build_lods(prim: &cpu::HalaPrimitive, lod_config: &HalaLodConfig) -> Result<(Vec<u32>, Vec<HalaLod>), HalaRendererError> {
+    let mut indices = prim.indices.clone();
+    let mut lods = vec![HalaLod {
+      first_index: 0,
+      index_count: indices.len() as u32,
+      first_meshlet: 0,
+      meshlet_count: 0,
+      error: 0.0,
+    }];
+    if lod_config.max_lods <= 1 || prim.indices.is_empty() {
+      return Ok((indices, lods));
+    }
+
+    let vertex_data_adapter = unsafe {
+      meshopt::VertexDataAdapter::new(
+        std::slice::from_raw_parts(prim.vertices.as_ptr() as *const u8, prim.vertices.len() * std::mem::size_of::<HalaVertex>()),
+        std::mem::size_of::<HalaVertex>(),
+        0,
+      ).map_err(|err| HalaRendererError::new("Failed to create vertex data adapter.", Some(Box::new(err))))?
+    };
+
+    let mut source = prim.indices.clone();
+    let mut total_error = 0f32;
+    while lods.len() < lod_config.max_lods {
+      let target_count = (source.len() as f32 * lod_config.reduction_ratio) as usize / 3 * 3;
+      if target_count < 3 {
+        break;
+      }
+
+      // Lock the borders, the seams are kept by the simplifier itself.
+      let mut error = 0f32;
+      let simplified = meshopt::simplify(
+        &source,
+        &vertex_data_adapter,
+        target_count,
+        lod_config.max_error,
+        meshopt::SimplifyOptions::LockBorder,
+        Some(&mut error),
+      );
+      total_error += error;
+      let min_reduction = source.len() as f32 * (1.0 + lod_config.reduction_ratio) * 0.5;
+      if simplified.is_empty() || simplified.len() as f32 > min_reduction || total_error > lod_config.max_error {
+        break;
+      }
+
+      lods.push(HalaLod {
+        first_index: indices.len() as u32,
+        index_count: simplified.len() as u32,
+        first_meshlet: 0,
+        meshlet_count: 0,
+        error: total_error,
+      });
+      indices.extend_from_slice(&simplified);
+      source = simplified;
+    }
+
+    Ok((indices, lods))
+  }
+
   /// Additively upload the scene to the GPU from the CPU for mesh shader.
   /// param context: The gfx context.
   /// param graphics_command_buffers: The graphics command buffers.
   /// param transfer_command_buffers: The transfer command buffers.
   /// param scene_in_cpu: The scene in the CPU.
   /// param scene_in_gpu: The scene in the GPU.
-  /// param use_global_meshlets: Whether the scene uses global meshlets.
+  /// param lod_data: The indices and the levels of detail of each primitive.
+  /// param use_mega_buffers: Whether to merge the meshlet buffers of all primitives.
+  /// param use_global_meshlets: Whether the scene uses global meshlets, only the level 0 is clusterized in this case.
+  /// param meshlet_config: The meshlet building configuration.
+  /// param transfer_batch: The transfer batch to record the buffer copies, none to upload synchronously.
   /// return: The result.
   fn additively_upload_for_mesh_shader(
     context: &HalaContext,
@@ -558,65 +1065,70 @@ impl HalaSceneGPUUploader {
     transfer_command_buffers: &HalaCommandBufferSet,
     scene_in_cpu: &mut cpu::HalaScene,
     scene_in_gpu: &mut gpu::HalaScene,
+    lod_data: &[Vec<(Vec<u32>, Vec<HalaLod>)>],
+    use_mega_buffers: bool,
     use_global_meshlets: bool,
+    meshlet_config: &HalaMeshletConfig,
+    transfer_batch: &mut Option<&mut HalaTransferBatch>,
   ) -> Result<(), HalaRendererError> {
+    meshlet_config.validate()?;
+
     let mut staging_buffer_size = 0u64;
 
     let mut global_meshlets = Vec::new();
     let mut draw_data = Vec::new();
     let mut draw_index = 0u32;
+    let mut mega_meshlet_vertex_count = 0u32;
+    let mut mega_meshlet_primitive_count = 0u32;
     for (mesh_index, mesh_in_cpu) in scene_in_cpu.meshes.iter_mut().enumerate() {
-      for prim_in_cpu in mesh_in_cpu.primitives.iter_mut() {
-        let vertex_data_adapter = unsafe {
-          meshopt::VertexDataAdapter::new(
-            std::slice::from_raw_parts(prim_in_cpu.vertices.as_ptr() as *const u8, prim_in_cpu.vertices.len() * std::mem::size_of::<HalaVertex>()),
-            std::mem::size_of::<HalaVertex>(),
-            0,
-          ).map_err(|err| HalaRendererError::new("Failed to create vertex data adapter.", Some(Box::new(err))))?
+      for (prim_index, prim_in_cpu) in mesh_in_cpu.primitives.iter_mut().enumerate() {
+        // Rebuild the meshlets, the offsets are biased by the previous primitives in the mega buffers.
+        prim_in_cpu.meshlets.clear();
+        prim_in_cpu.meshlet_vertices.clear();
+        prim_in_cpu.meshlet_primitives.clear();
+        let (vertex_base, primitive_base) = if use_mega_buffers {
+          (mega_meshlet_vertex_count, mega_meshlet_primitive_count)
+        } else {
+          (0, 0)
         };
-        let meshlets_in_cpu = meshopt::clusterize::build_meshlets(
-          prim_in_cpu.indices.as_slice(),
-          &vertex_data_adapter,
-          64,
-          124,
-          0.5,
-        );
-        for (meshlet_index, meshlet_in_cpu) in meshlets_in_cpu.meshlets.iter().enumerate() {
-          let wrapped_meshlet_in_cpu = meshlets_in_cpu.get(meshlet_index);
-          let bounds = meshopt::clusterize::compute_meshlet_bounds(
-            wrapped_meshlet_in_cpu,
-            &vertex_data_adapter,
-          );
-
-          assert!(meshlet_in_cpu.triangle_offset % 4 == 0, "The triangle offset of the meshlet is not a multiple of 4.");
-          assert!(wrapped_meshlet_in_cpu.triangles.len() % 3 == 0, "The triangle count of the meshlet is not a multiple of 3.");
-          let meshlet = HalaMeshlet {
-            center: bounds.center,
-            radius: bounds.radius,
-            cone_apex: bounds.cone_apex,
-            cone_axis: bounds.cone_axis,
-            cone_cutoff: bounds.cone_cutoff,
-            offset_of_vertices: prim_in_cpu.meshlet_vertices.len() as u32,
-            num_of_vertices: meshlet_in_cpu.vertex_count,
-            offset_of_primitives: prim_in_cpu.meshlet_primitives.len() as u32,
-            num_of_primitives: (wrapped_meshlet_in_cpu.triangles.len() / 3) as u32,
-            draw_index,
-          };
-          // log::info!("Meshlet: V[{}, {}], P[{}, {}]", meshlet.offset_of_vertices, meshlet.num_of_vertices, meshlet.offset_of_primitives, meshlet.num_of_primitives);
-
-          if use_global_meshlets {
-            global_meshlets.push(meshlet.clone());
-          } else {
-            prim_in_cpu.meshlets.push(meshlet);
-          }
-          for i in wrapped_meshlet_in_cpu.vertices.iter() {
-            prim_in_cpu.meshlet_vertices.push(*i);
+
+        let (lod_indices, lods) = &lod_data[mesh_index][prim_index];
+        for (lod_index, lod) in lods.iter().enumerate() {
+          // The global meshlets only support the level 0.
+          if use_global_meshlets && lod_index > 0 {
+            break;
           }
-          for c in wrapped_meshlet_in_cpu.triangles.chunks(3) {
-            prim_in_cpu.meshlet_primitives.push((c[0] as u32) | (c[1] as u32) << 8 | (c[2] as u32) << 16);
+          let first_meshlet = (if use_global_meshlets { global_meshlets.len() } else { prim_in_cpu.meshlets.len() }) as u32;
+          let meshlets = build_meshlets(
+            &prim_in_cpu.vertices,
+            &lod_indices[lod.first_index as usize..(lod.first_index + lod.index_count) as usize],
+            meshlet_config,
+            &mut prim_in_cpu.meshlet_vertices,
+            &mut prim_in_cpu.meshlet_primitives,
+          )?;
+          for mut meshlet in meshlets.into_iter() {
+            meshlet.offset_of_vertices += vertex_base;
+            meshlet.offset_of_primitives += primitive_base;
+            meshlet.draw_index = draw_index;
+            // log::info!("Meshlet: V[{}, {}], P[{}, {}]", meshlet.offset_of_vertices, meshlet.num_of_vertices, meshlet.offset_of_primitives, meshlet.num_of_primitives);
+
+            if use_global_meshlets {
+              global_meshlets.push(meshlet);
+            } else {
+              prim_in_cpu.meshlets.push(meshlet);
+            }
           }
+
+          // Record the meshlet range of the level.
+          let meshlet_count = (if use_global_meshlets { global_meshlets.len() } else { prim_in_cpu.meshlets.len() }) as u32 - first_meshlet;
+          let lod_in_gpu = &mut scene_in_gpu.meshes[mesh_index].primitives[prim_index].lods[lod_index];
+          lod_in_gpu.first_meshlet = first_meshlet;
+          lod_in_gpu.meshlet_count = meshlet_count;
         }
 
+        mega_meshlet_vertex_count += prim_in_cpu.meshlet_vertices.len() as u32;
+        mega_meshlet_primitive_count += prim_in_cpu.meshlet_primitives.len() as u32;
+
         if use_global_meshlets {
           draw_data.push(DrawData {
             object_index: mesh_index as u32,
@@ -641,23 +1153,54 @@ impl HalaSceneGPUUploader {
       }
     }
     let global_meshlet_count = global_meshlets.len();
+    if use_mega_buffers {
+      let mega_meshlet_buffer_size = scene_in_cpu.meshes.iter().map(
+        |mesh| mesh.primitives.iter().map(|prim| prim.meshlets.len()).sum::<usize>()
+      ).sum::<usize>() * std::mem::size_of::<HalaMeshlet>();
+      staging_buffer_size = std::cmp::max(
+        mega_meshlet_buffer_size as u64,
+        std::cmp::max(
+          mega_meshlet_vertex_count as u64 * std::mem::size_of::<u32>() as u64,
+          mega_meshlet_primitive_count as u64 * std::mem::size_of::<u32>() as u64,
+        )
+      );
+    }
 
     // Create staging buffer.
     let global_meshlet_buffer_size = if use_global_meshlets { (std::mem::size_of::<HalaMeshlet>() * global_meshlet_count) as u64 } else { 0 };
     let draw_data_buffer_size = if use_global_meshlets { (std::mem::size_of::<DrawData>() * draw_data.len()) as u64 } else { 0 };
-    let staging_buffer = HalaBuffer::new(
-      Rc::clone(&context.logical_device),
-      std::cmp::max(staging_buffer_size, std::cmp::max(global_meshlet_buffer_size, draw_data_buffer_size)),
-      HalaBufferUsageFlags::TRANSFER_SRC,
-      HalaMemoryLocation::CpuToGpu,
-      "staging.buffer")?;
+    let staging_buffer = if transfer_batch.is_none() {
+      Some(HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        std::cmp::max(staging_buffer_size, std::cmp::max(global_meshlet_buffer_size, draw_data_buffer_size)),
+        HalaBufferUsageFlags::TRANSFER_SRC,
+        HalaMemoryLocation::CpuToGpu,
+        "staging.buffer")?)
+    } else {
+      None
+    };
 
     // Create meshlet buffers.
+    let mut mega_meshlets = Vec::new();
+    let mut mega_meshlet_vertices = Vec::with_capacity(mega_meshlet_vertex_count as usize);
+    let mut mega_meshlet_primitives = Vec::with_capacity(mega_meshlet_primitive_count as usize);
     for (mesh_index, mesh) in scene_in_gpu.meshes.iter_mut().enumerate() {
       let mesh_in_cpu = &scene_in_cpu.meshes[mesh_index];
       for (prim_index, prim) in mesh.primitives.iter_mut().enumerate() {
         let prim_in_cpu = &mesh_in_cpu.primitives[prim_index];
 
+        // Merge into the mega buffers, the meshlet vertices index the mega vertex buffer directly.
+        if use_mega_buffers {
+          if !use_global_meshlets {
+            prim.meshlet_count = prim_in_cpu.meshlets.len() as u32;
+            prim.first_meshlet = mega_meshlets.len() as u32;
+            mega_meshlets.extend_from_slice(&prim_in_cpu.meshlets);
+          }
+          mega_meshlet_vertices.extend(prim_in_cpu.meshlet_vertices.iter().map(|i| i + prim.vertex_offset));
+          mega_meshlet_primitives.extend_from_slice(&prim_in_cpu.meshlet_primitives);
+          continue;
+        }
+
         // Create meshlet informatin buffer.
         if !use_global_meshlets {
           prim.meshlet_count = prim_in_cpu.meshlets.len() as u32;
@@ -673,10 +1216,12 @@ impl HalaSceneGPUUploader {
             HalaMemoryLocation::GpuOnly,
             &format!("meshlet_info_{}_{}.buffer", mesh_index, prim_index)
           )?;
-          meshlet_buffer.update_gpu_memory_with_buffer_raw(
+          Self::update_buffer_raw(
+            transfer_batch,
+            &meshlet_buffer,
             prim_in_cpu.meshlets.as_ptr() as *const u8,
             meshlet_buffer_size as usize,
-            &staging_buffer,
+            staging_buffer.as_ref(),
             transfer_command_buffers)?;
 
           prim.meshlet_buffer = Some(meshlet_buffer);
@@ -693,9 +1238,11 @@ impl HalaSceneGPUUploader {
           HalaMemoryLocation::GpuOnly,
           &format!("meshlet_vertex_{}_{}.buffer", mesh_index, prim_index)
         )?;
-        meshlet_vertex_buffer.update_gpu_memory_with_buffer(
+        Self::update_buffer(
+          transfer_batch,
+          &meshlet_vertex_buffer,
           prim_in_cpu.meshlet_vertices.as_slice(),
-          &staging_buffer,
+          staging_buffer.as_ref(),
           transfer_command_buffers)?;
 
         prim.meshlet_vertex_buffer = Some(meshlet_vertex_buffer);
@@ -711,15 +1258,72 @@ impl HalaSceneGPUUploader {
           HalaMemoryLocation::GpuOnly,
           &format!("meshlet_primitive_{}_{}.buffer", mesh_index, prim_index)
         )?;
-        meshlet_primitive_buffer.update_gpu_memory_with_buffer(
+        Self::update_buffer(
+          transfer_batch,
+          &meshlet_primitive_buffer,
           prim_in_cpu.meshlet_primitives.as_slice(),
-          &staging_buffer,
+          staging_buffer.as_ref(),
           transfer_command_buffers)?;
 
         prim.meshlet_primitive_buffer = Some(meshlet_primitive_buffer);
       }
     }
 
+    if use_mega_buffers && !mega_meshlet_primitives.is_empty() {
+      let storage_usage = HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
+        | HalaBufferUsageFlags::STORAGE_BUFFER
+        | HalaBufferUsageFlags::TRANSFER_DST;
+
+      // Create mega meshlet information buffer.
+      if !use_global_meshlets {
+        let mega_meshlet_buffer_size = (std::mem::size_of::<HalaMeshlet>() * mega_meshlets.len()) as u64;
+        let mega_meshlet_buffer = HalaBuffer::new(
+          Rc::clone(&context.logical_device),
+          mega_meshlet_buffer_size,
+          storage_usage,
+          HalaMemoryLocation::GpuOnly,
+          "mega_meshlet_info.buffer")?;
+        Self::update_buffer_raw(
+          transfer_batch,
+          &mega_meshlet_buffer,
+          mega_meshlets.as_ptr() as *const u8,
+          mega_meshlet_buffer_size as usize,
+          staging_buffer.as_ref(),
+          transfer_command_buffers)?;
+        scene_in_gpu.mega_meshlet_buffer = Some(mega_meshlet_buffer);
+      }
+
+      // Create mega meshlet vertex buffer.
+      let mega_meshlet_vertex_buffer = HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (std::mem::size_of::<u32>() * mega_meshlet_vertices.len()) as u64,
+        storage_usage,
+        HalaMemoryLocation::GpuOnly,
+        "mega_meshlet_vertex.buffer")?;
+      Self::update_buffer(
+        transfer_batch,
+        &mega_meshlet_vertex_buffer,
+        mega_meshlet_vertices.as_slice(),
+        staging_buffer.as_ref(),
+        transfer_command_buffers)?;
+      scene_in_gpu.mega_meshlet_vertex_buffer = Some(mega_meshlet_vertex_buffer);
+
+      // Create mega meshlet primitive buffer.
+      let mega_meshlet_primitive_buffer = HalaBuffer::new(
+        Rc::clone(&context.logical_device),
+        (std::mem::size_of::<u32>() * mega_meshlet_primitives.len()) as u64,
+        storage_usage,
+        HalaMemoryLocation::GpuOnly,
+        "mega_meshlet_primitive.buffer")?;
+      Self::update_buffer(
+        transfer_batch,
+        &mega_meshlet_primitive_buffer,
+        mega_meshlet_primitives.as_slice(),
+        staging_buffer.as_ref(),
+        transfer_command_buffers)?;
+      scene_in_gpu.mega_meshlet_primitive_buffer = Some(mega_meshlet_primitive_buffer);
+    }
+
     if use_global_meshlets {
       // Create global meshlet buffer.
       let global_meshlet_buffer = HalaBuffer::new(
@@ -732,10 +1336,12 @@ impl HalaSceneGPUUploader {
         "global_meshlet.buffer")?;
 
       // Upload the global meshlets.
-      global_meshlet_buffer.update_gpu_memory_with_buffer_raw(
+      Self::update_buffer_raw(
+        transfer_batch,
+        &global_meshlet_buffer,
         global_meshlets.as_ptr() as *const u8,
         global_meshlet_buffer_size as usize,
-        &staging_buffer,
+        staging_buffer.as_ref(),
         transfer_command_buffers)?;
 
       // Create the draw data buffer.
@@ -750,10 +1356,12 @@ impl HalaSceneGPUUploader {
       )?;
 
       // Upload the draw data.
-      draw_data_buffer.update_gpu_memory_with_buffer_raw(
+      Self::update_buffer_raw(
+        transfer_batch,
+        &draw_data_buffer,
         draw_data.as_ptr() as *const u8,
         draw_data_buffer_size as usize,
-        &staging_buffer,
+        staging_buffer.as_ref(),
         transfer_command_buffers)?;
 
       scene_in_gpu.meshlet_count = global_meshlet_count as u32;
@@ -770,17 +1378,25 @@ impl HalaSceneGPUUploader {
   /// param transfer_command_buffers: The transfer command buffers.
   /// param scene_in_cpu: The scene in the CPU.
   /// param scene_in_gpu: The scene in the GPU.
+  /// param material_hit_groups: The hit group index of each material type, the unmapped material types use the hit group 0.
+  /// param max_triangle_lights: The max number of the emissive triangle lights.
   /// return: The result.
   fn additively_upload_for_ray_tracing(
     context: &HalaContext,
     graphics_command_buffers: &HalaCommandBufferSet,
     transfer_command_buffers: &HalaCommandBufferSet,
     scene_in_cpu: &cpu::HalaScene,
-    scene_in_gpu: &mut gpu::HalaScene) -> Result<(), HalaRendererError>
+    scene_in_gpu: &mut gpu::HalaScene,
+    material_hit_groups: &[u32],
+    max_triangle_lights: u32) -> Result<(), HalaRendererError>
   {
     // Build bottom level acceleration structure for each mesh.
+    let primitive_addresses = scene_in_gpu.meshes.iter().map(
+      |mesh| mesh.primitives.iter().map(|prim| (scene_in_gpu.get_vertex_address(prim), scene_in_gpu.get_index_address(prim))).collect::<Vec<_>>()
+    ).collect::<Vec<_>>();
     for (mesh_index, mesh) in scene_in_gpu.meshes.iter_mut().enumerate() {
       for (prim_index, prim) in mesh.primitives.iter_mut().enumerate() {
+        let (vertex_address, index_address) = primitive_addresses[mesh_index][prim_index];
         let btlas = HalaAccelerationStructure::new(
           Rc::clone(&context.logical_device),
           graphics_command_buffers,
@@ -790,11 +1406,11 @@ impl HalaSceneGPUUploader {
             flags: hala_gfx::HalaGeometryFlags::OPAQUE,
             triangles_data: Some(HalaAccelerationStructureGeometryTrianglesData {
               vertex_format: hala_gfx::HalaFormat::R32G32B32_SFLOAT,
-              vertex_data_address: prim.vertex_buffer.get_device_address(),
+              vertex_data_address: vertex_address,
               vertex_stride: std::mem::size_of::<HalaVertex>() as u64,
               vertex_count: prim.vertex_count,
               index_type: hala_gfx::HalaIndexType::UINT32,
-              index_data_address: prim.index_buffer.get_device_address(),
+              index_data_address: index_address,
               transform_data_address: 0,
             }),
             aabbs_data: None,
@@ -839,9 +1455,8 @@ impl HalaSceneGPUUploader {
       "light.btlas",
     )?;
 
-    // Build top level instance buffer.
-    let mut primitives = Vec::new();
-    let mut instances = Vec::with_capacity(scene_in_cpu.nodes.len());
+    // Collect the top level instances, the custom index of an instance is the index of its primitive buffer.
+    let mut instance_sources = Vec::with_capacity(scene_in_cpu.nodes.len());
     for node in scene_in_cpu.nodes.iter() {
       if node.mesh_index == u32::MAX {
         continue;
@@ -849,67 +1464,34 @@ impl HalaSceneGPUUploader {
 
       let mesh_index = node.mesh_index as usize;
       let mesh = &scene_in_gpu.meshes[mesh_index];
-      for prim in mesh.primitives.iter() {
-        let as_instance = HalaAccelerationStructureInstance {
-          transform: [
-            node.world_transform.x_axis.x, node.world_transform.y_axis.x, node.world_transform.z_axis.x, node.world_transform.w_axis.x,
-            node.world_transform.x_axis.y, node.world_transform.y_axis.y, node.world_transform.z_axis.y, node.world_transform.w_axis.y,
-            node.world_transform.x_axis.z, node.world_transform.y_axis.z, node.world_transform.z_axis.z, node.world_transform.w_axis.z,
-          ],
-          custom_index: primitives.len() as u32,
-          mask: 0xff,
-          shader_binding_table_record_offset: 0,
-          shader_binding_table_flags: hala_gfx::HalaGeometryInstanceFlags::TRIANGLE_FACING_CULL_DISABLE,
-          acceleration_structure_device_address: prim.btlas.as_ref().unwrap_or_else(|| panic!("mesh_{} do NOT has btlas.", mesh_index)).address
-        };
-
-        primitives.push(gpu::mesh::HalaMeshData {
-          transform: node.world_transform,
-          material_index: prim.material_index,
-          vertices: prim.vertex_buffer.get_device_address(),
-          indices: prim.index_buffer.get_device_address(),
+      for (prim_index, prim) in mesh.primitives.iter().enumerate() {
+        instance_sources.push(gpu::mesh::HalaInstanceSource {
+          mesh_index: node.mesh_index,
+          primitive_index: prim_index as u32,
+          data: gpu::mesh::HalaMeshData {
+            transform: node.world_transform,
+            material_index: prim.material_index,
+            vertices: scene_in_gpu.get_vertex_address(prim),
+            indices: scene_in_gpu.get_index_address(prim),
+          },
         });
-
-        instances.push(as_instance.as_data());
       }
     }
 
-    let light_as_instance = HalaAccelerationStructureInstance {
-      transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
-      custom_index: 0u32,
-      mask: 0xff,
-      shader_binding_table_record_offset: 1,
-      shader_binding_table_flags: hala_gfx::HalaGeometryInstanceFlags::TRIANGLE_FACING_CULL_DISABLE,
-      acceleration_structure_device_address: light_btlas.address
-    };
-    instances.push(light_as_instance.as_data());
-
     // Perpare primitive buffers.
-    let primitives_buffer_size = std::mem::size_of_val(&primitives[0]) as u64;
+    let primitives_buffer_size = std::mem::size_of::<gpu::mesh::HalaMeshData>() as u64;
     let mut primitive_buffers = Vec::new();
 
-    // Create instances buffer.
-    let instances_buffer_size = (std::mem::size_of_val(&instances[0]) * instances.len()) as u64;
-    let instances_buffer = HalaBuffer::new(
-      Rc::clone(&context.logical_device),
-      instances_buffer_size,
-      HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
-        | HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY
-        | HalaBufferUsageFlags::TRANSFER_DST,
-      HalaMemoryLocation::GpuOnly,
-      "scene.instance_buffer",
-    )?;
-
     // Create staging buffer.
     let staging_buffer = HalaBuffer::new(
       Rc::clone(&context.logical_device),
-      std::cmp::max(primitives_buffer_size, instances_buffer_size),
+      primitives_buffer_size,
       HalaBufferUsageFlags::TRANSFER_SRC,
       HalaMemoryLocation::CpuToGpu,
       "staging.buffer")?;
 
     // Upload the primitive buffers.
-    for primitive in primitives.iter() {
+    for instance_source in instance_sources.iter() {
       let primitives_buffer = HalaBuffer::new(
         Rc::clone(&context.logical_device),
         primitives_buffer_size,
@@ -919,7 +1501,7 @@ impl HalaSceneGPUUploader {
       )?;
 
       primitives_buffer.update_gpu_memory_with_buffer(
-        std::slice::from_ref(primitive),
+        std::slice::from_ref(&instance_source.data),
         &staging_buffer,
         transfer_command_buffers
       )?;
@@ -927,7 +1509,101 @@ impl HalaSceneGPUUploader {
       primitive_buffers.push(primitives_buffer);
     }
 
+    scene_in_gpu.instance_sources = instance_sources;
+    scene_in_gpu.primitives = primitive_buffers;
+    scene_in_gpu.light_btlas = Some(light_btlas);
+    Self::build_top_level_acceleration_structure(
+      context,
+      graphics_command_buffers,
+      transfer_command_buffers,
+      scene_in_gpu,
+      material_hit_groups,
+      &[],
+      &[],
+    )?;
+
+    // The scenes without emissive materials skip the triangle lights.
+    if scene_in_gpu.material_emissive_flags.iter().any(|is_emissive| *is_emissive) {
+      Self::upload_triangle_lights(context, transfer_command_buffers, scene_in_cpu, scene_in_gpu, max_triangle_lights)?;
+    }
+
+    Ok(())
+  }
+
+  /// Build the top level acceleration structure from the instance sources of the scene, the light instance is the last one.
+  /// The hidden meshes are masked out by the zero instance mask, so no ray hits them,
+  /// and the hit groups follow the material types of the override materials.
+  /// The old instance buffer and acceleration structure are replaced, the caller should make sure they are not in use.
+  /// param context: The gfx context.
+  /// param graphics_command_buffers: The graphics command buffers.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param material_hit_groups: The hit group of each material type.
+  /// param mesh_visibilities: The visibility of each mesh, the meshes out of range are visible.
+  /// param material_overrides: The override material index of each mesh, none or out of range to use the materials of the primitives.
+  /// return: The result.
+  pub fn build_top_level_acceleration_structure(
+    context: &HalaContext,
+    graphics_command_buffers: &HalaCommandBufferSet,
+    transfer_command_buffers: &HalaCommandBufferSet,
+    scene_in_gpu: &mut gpu::HalaScene,
+    material_hit_groups: &[u32],
+    mesh_visibilities: &[bool],
+    material_overrides: &[Option<u32>]) -> Result<(), HalaRendererError>
+  {
+    let mut instances = Vec::with_capacity(scene_in_gpu.instance_sources.len() + 1);
+    for (custom_index, instance_source) in scene_in_gpu.instance_sources.iter().enumerate() {
+      let mesh_index = instance_source.mesh_index as usize;
+      let prim = &scene_in_gpu.meshes[mesh_index].primitives[instance_source.primitive_index as usize];
+      let transform = &instance_source.data.transform;
+      let is_visible = mesh_visibilities.get(mesh_index).copied().unwrap_or(true);
+      let material_index = material_overrides.get(mesh_index).copied().flatten().unwrap_or(instance_source.data.material_index);
+      let material_type = scene_in_gpu.material_types.get(material_index as usize).copied().unwrap_or(0);
+      let as_instance = HalaAccelerationStructureInstance {
+        transform: [
+          transform.x_axis.x, transform.y_axis.x, transform.z_axis.x, transform.w_axis.x,
+          transform.x_axis.y, transform.y_axis.y, transform.z_axis.y, transform.w_axis.y,
+          transform.x_axis.z, transform.y_axis.z, transform.z_axis.z, transform.w_axis.z,
+        ],
+        custom_index: custom_index as u32,
+        mask: if is_visible { 0xff } else { 0x00 },
+        shader_binding_table_record_offset: material_hit_groups.get(material_type as usize).copied().unwrap_or(0),
+        shader_binding_table_flags: hala_gfx::HalaGeometryInstanceFlags::TRIANGLE_FACING_CULL_DISABLE,
+        acceleration_structure_device_address: prim.btlas.as_ref().unwrap_or_else(|| panic!("mesh_{} do NOT has btlas.", mesh_index)).address
+      };
+      instances.push(as_instance.as_data());
+    }
+
+    let light_btlas = scene_in_gpu.light_btlas.as_ref().ok_or(HalaRendererError::new("The light bottom level acceleration structure is none!", None))?;
+    let light_as_instance = HalaAccelerationStructureInstance {
+      transform: [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+      custom_index: 0u32,
+      mask: 0xff,
+      shader_binding_table_record_offset: Self::get_light_hit_group(material_hit_groups),
+      shader_binding_table_flags: hala_gfx::HalaGeometryInstanceFlags::TRIANGLE_FACING_CULL_DISABLE,
+      acceleration_structure_device_address: light_btlas.address
+    };
+    instances.push(light_as_instance.as_data());
+
+    // Create instances buffer.
+    let instances_buffer_size = (std::mem::size_of_val(&instances[0]) * instances.len()) as u64;
+    let instances_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      instances_buffer_size,
+      HalaBufferUsageFlags::SHADER_DEVICE_ADDRESS
+        | HalaBufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY
+        | HalaBufferUsageFlags::TRANSFER_DST,
+      HalaMemoryLocation::GpuOnly,
+      "scene.instance_buffer",
+    )?;
+
     // Upload the instance buffer.
+    let staging_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      instances_buffer_size,
+      HalaBufferUsageFlags::TRANSFER_SRC,
+      HalaMemoryLocation::CpuToGpu,
+      "staging.buffer")?;
     instances_buffer.update_gpu_memory_with_buffer(
       instances.as_slice(),
       &staging_buffer,
@@ -960,10 +1636,388 @@ impl HalaSceneGPUUploader {
 
     scene_in_gpu.instances = Some(instances_buffer);
     scene_in_gpu.tplas = Some(tplas);
-    scene_in_gpu.primitives = primitive_buffers;
-    scene_in_gpu.light_btlas = Some(light_btlas);
 
     Ok(())
   }
 
-}
\ No newline at end of file
+  /// Rewrite the material index of the primitive buffers of the ray tracing instances.
+  /// The caller should make sure the primitive buffers are not in use.
+  /// param context: The gfx context.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param material_overrides: The override material index of each mesh, none or out of range to use the materials of the primitives.
+  /// return: The result.
+  pub fn update_primitive_materials(
+    context: &HalaContext,
+    transfer_command_buffers: &HalaCommandBufferSet,
+    scene_in_gpu: &gpu::HalaScene,
+    material_overrides: &[Option<u32>]) -> Result<(), HalaRendererError>
+  {
+    let staging_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      std::mem::size_of::<gpu::mesh::HalaMeshData>() as u64,
+      HalaBufferUsageFlags::TRANSFER_SRC,
+      HalaMemoryLocation::CpuToGpu,
+      "staging.buffer")?;
+
+    for (instance_source, primitives_buffer) in scene_in_gpu.instance_sources.iter().zip(scene_in_gpu.primitives.iter()) {
+      let mut data = instance_source.data;
+      if let Some(material_index) = material_overrides.get(instance_source.mesh_index as usize).copied().flatten() {
+        data.material_index = material_index;
+      }
+      primitives_buffer.update_gpu_memory_with_buffer(
+        std::slice::from_ref(&data),
+        &staging_buffer,
+        transfer_command_buffers
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Convert the light in the CPU to the light in the GPU and its AABB for ray tracing.
+  /// param light_in_cpu: The light in the CPU.
+  /// param world_transform: The world transform of the light node.
+  /// return: The light in the GPU and its AABB.
+  fn convert_light(light_in_cpu: &cpu::light::HalaLight, world_transform: &glam::Mat4) -> (gpu::HalaLight, HalaAABB) {
+    match light_in_cpu.light_type {
+      cpu::light::HalaLightType::POINT => {
+        (
+          gpu::HalaLight {
+            intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
+            position: world_transform.w_axis.xyz().into(),
+            u: glam::Vec3A::ZERO,
+            v: glam::Vec3::ZERO,
+            radius: 0.0,
+            area: 0.0,
+            _type: 0,
+            range: light_in_cpu.range.unwrap_or(0.0),
+          },
+          HalaAABB {
+            min: [
+              world_transform.w_axis.x,
+              world_transform.w_axis.y,
+              world_transform.w_axis.z,
+            ],
+            max: [
+              world_transform.w_axis.x,
+              world_transform.w_axis.y,
+              world_transform.w_axis.z,
+            ],
+          }
+        )
+      },
+      cpu::light::HalaLightType::DIRECTIONAL => {
+        (
+          gpu::HalaLight {
+            intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
+            position: glam::Vec3A::ZERO,
+            u: (-world_transform.z_axis.xyz()).into(),
+            v: glam::Vec3::new((0.5 * light_in_cpu.get_directional_cone_angle()).cos(), 0.0, 0.0),
+            radius: 0.0,
+            area: 0.0,
+            _type: 1,
+            range: 0.0,
+          },
+          HalaAABB {
+            min: [0.0, 0.0, 0.0],
+            max: [0.0, 0.0, 0.0],
+          }
+        )
+      },
+      cpu::light::HalaLightType::SPOT => {
+        let (inner_angle, outer_angle) = light_in_cpu.get_spot_angles();
+        (
+          gpu::HalaLight {
+            intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
+            position: world_transform.w_axis.xyz().into(),
+            u: (-world_transform.z_axis.xyz()).into(),
+            v: glam::Vec3::new(inner_angle.cos(), outer_angle.cos(), 0.0),
+            radius: 0.0,
+            area: 0.0,
+            _type: 2,
+            range: light_in_cpu.range.unwrap_or(0.0),
+          },
+          HalaAABB {
+            min: [
+              world_transform.w_axis.x,
+              world_transform.w_axis.y,
+              world_transform.w_axis.z,
+            ],
+            max: [
+              world_transform.w_axis.x,
+              world_transform.w_axis.y,
+              world_transform.w_axis.z,
+            ],
+          }
+        )
+      },
+      cpu::light::HalaLightType::QUAD => {
+        let (width, height) = light_in_cpu.get_quad_size();
+        let mut position = world_transform.w_axis.xyz();
+        position -= world_transform.x_axis.xyz() * width * 0.5;
+        position -= world_transform.y_axis.xyz() * height * 0.5;
+        let another = position + world_transform.x_axis.xyz() * width + world_transform.y_axis.xyz() * height + world_transform.z_axis.xyz() * 0.01;
+        (
+          gpu::HalaLight {
+            intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
+            position: position.into(),
+            u: (world_transform.x_axis.xyz() * width).into(),
+            v: world_transform.y_axis.xyz() * height,
+            radius: 0.0,
+            area: width * height,
+            _type: 3,
+            range: 0.0,
+          },
+          HalaAABB {
+            min: [
+              position.x,
+              position.y,
+              position.z,
+            ],
+            max: [
+              another.x,
+              another.y,
+              another.z,
+            ],
+          }
+        )
+      },
+      cpu::light::HalaLightType::SPHERE => {
+        let radius = light_in_cpu.get_sphere_radius();
+        let min = world_transform.w_axis.xyz() - glam::Vec3::splat(radius);
+        let max = world_transform.w_axis.xyz() + glam::Vec3::splat(radius);
+        (
+          gpu::HalaLight {
+            intensity: (light_in_cpu.color * light_in_cpu.intensity).into(),
+            position: world_transform.w_axis.xyz().into(),
+            u: glam::Vec3A::ZERO,
+            v: glam::Vec3::ZERO,
+            radius,
+            area: 4.0 * std::f32::consts::PI * radius * radius,
+            _type: 4,
+            range: 0.0,
+          },
+          HalaAABB {
+            min: [min.x, min.y, min.z],
+            max: [max.x, max.y, max.z],
+          }
+        )
+      },
+      _ => panic!("Invalid light type."),
+    }
+  }
+
+  /// Build the emissive triangle lights in the world space and upload them with the CDF over their power.
+  /// The primitive index of a triangle light follows the instance order of additively_upload_for_ray_tracing.
+  /// param context: The gfx context.
+  /// param transfer_command_buffers: The transfer command buffers.
+  /// param scene_in_cpu: The scene in the CPU.
+  /// param scene_in_gpu: The scene in the GPU.
+  /// param max_triangle_lights: The max number of the triangle lights, the weakest triangles are dropped if exceeded.
+  /// return: The result.
+  fn upload_triangle_lights(
+    context: &HalaContext,
+    transfer_command_buffers: &HalaCommandBufferSet,
+    scene_in_cpu: &cpu::HalaScene,
+    scene_in_gpu: &mut gpu::HalaScene,
+    max_triangle_lights: u32) -> Result<(), HalaRendererError>
+  {
+    let luminance = |color: glam::Vec3| color.dot(glam::Vec3::new(0.2126, 0.7152, 0.0722));
+
+    // Collect the triangles and their power.
+    let mut triangle_lights = Vec::new();
+    let mut powers = Vec::new();
+    let mut primitive_index = 0u32;
+    for node in scene_in_cpu.nodes.iter() {
+      if node.mesh_index == u32::MAX {
+        continue;
+      }
+
+      let mesh = &scene_in_cpu.meshes[node.mesh_index as usize];
+      for prim in mesh.primitives.iter() {
+        let material_index = prim.material_index as usize;
+        let is_emissive = scene_in_gpu.material_emissive_flags.get(material_index).copied().unwrap_or(false);
+        if is_emissive {
+          let emission = scene_in_cpu.materials[material_index].emission;
+          for (triangle_index, triangle) in prim.indices.chunks_exact(3).enumerate() {
+            let [p0, p1, p2] = [triangle[0], triangle[1], triangle[2]].map(
+              |index| node.world_transform.transform_point3(glam::Vec3::from(prim.vertices[index as usize].position))
+            );
+            let area = (p1 - p0).cross(p2 - p0).length() * 0.5;
+            if area <= 0.0 {
+              continue;
+            }
+
+            triangle_lights.push(gpu::HalaTriangleLight {
+              p0,
+              area,
+              p1,
+              primitive_index,
+              p2,
+              triangle_index: triangle_index as u32,
+              emission,
+              material_index: prim.material_index,
+            });
+            powers.push(luminance(emission) * area * std::f32::consts::PI);
+          }
+        }
+        primitive_index += 1;
+      }
+    }
+    if triangle_lights.is_empty() {
+      return Ok(());
+    }
+
+    // Keep the most powerful triangles if there are too many.
+    if triangle_lights.len() > max_triangle_lights as usize {
+      log::warn!(
+        "The scene has {} emissive triangles, only the {} most powerful ones are used as the triangle lights.",
+        triangle_lights.len(),
+        max_triangle_lights);
+      let mut order = (0..triangle_lights.len()).collect::<Vec<_>>();
+      order.sort_unstable_by(|a, b| powers[*b].total_cmp(&powers[*a]));
+      order.truncate(max_triangle_lights as usize);
+      triangle_lights = order.iter().map(|index| triangle_lights[*index]).collect();
+      powers = order.iter().map(|index| powers[*index]).collect();
+    }
+    if triangle_lights.is_empty() {
+      return Ok(());
+    }
+
+    // Build the normalized CDF.
+    let total_power = powers.iter().sum::<f32>();
+    let mut cdf = Vec::with_capacity(powers.len());
+    let mut accumulated_power = 0.0;
+    for power in powers.iter() {
+      accumulated_power += power;
+      cdf.push(if total_power > 0.0 { accumulated_power / total_power } else { (cdf.len() + 1) as f32 / powers.len() as f32 });
+    }
+
+    let triangle_lights_buffer_size = std::mem::size_of_val(triangle_lights.as_slice()) as u64;
+    let cdf_buffer_size = std::mem::size_of_val(cdf.as_slice()) as u64;
+    let triangle_lights_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      triangle_lights_buffer_size,
+      HalaBufferUsageFlags::STORAGE_BUFFER | HalaBufferUsageFlags::TRANSFER_DST,
+      HalaMemoryLocation::GpuOnly,
+      "scene.triangle_lights_buffer",
+    )?;
+    let cdf_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      cdf_buffer_size,
+      HalaBufferUsageFlags::STORAGE_BUFFER | HalaBufferUsageFlags::TRANSFER_DST,
+      HalaMemoryLocation::GpuOnly,
+      "scene.triangle_light_cdf_buffer",
+    )?;
+    let staging_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      std::cmp::max(triangle_lights_buffer_size, cdf_buffer_size),
+      HalaBufferUsageFlags::TRANSFER_SRC,
+      HalaMemoryLocation::CpuToGpu,
+      "staging.buffer")?;
+    triangle_lights_buffer.update_gpu_memory_with_buffer(
+      triangle_lights.as_slice(),
+      &staging_buffer,
+      transfer_command_buffers)?;
+    cdf_buffer.update_gpu_memory_with_buffer(
+      cdf.as_slice(),
+      &staging_buffer,
+      transfer_command_buffers)?;
+
+    scene_in_gpu.num_of_triangle_lights = triangle_lights.len() as u32;
+    scene_in_gpu.triangle_light_total_power = total_power;
+    scene_in_gpu.triangle_lights = Some(triangle_lights_buffer);
+    scene_in_gpu.triangle_light_cdf = Some(cdf_buffer);
+
+    Ok(())
+  }
+
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The world transform of the light node, at (1, 2, 3) and turned 90 degrees around +Y.
+  /// The X axis is -Z, the Y axis is +Y and the Z axis is +X in the world space.
+  fn world_transform() -> glam::Mat4 {
+    glam::Mat4::from_translation(glam::Vec3::new(1.0, 2.0, 3.0)) * glam::Mat4::from_rotation_y(std::f32::consts::FRAC_PI_2)
+  }
+
+  /// Convert the light at the world transform.
+  /// param light: The light in the CPU.
+  /// return: The light in the GPU and its AABB.
+  fn convert(light: cpu::light::HalaLight) -> (gpu::HalaLight, HalaAABB) {
+    HalaSceneGPUUploader::convert_light(&light, &world_transform())
+  }
+
+  /// Assert the vector equals the expected one within the tolerance.
+  /// param actual: The actual vector.
+  /// param expected: The expected vector.
+  fn assert_vec3(actual: glam::Vec3, expected: glam::Vec3) {
+    assert!(actual.abs_diff_eq(expected, 1e-5), "{:?} is not {:?}.", actual, expected);
+  }
+
+  #[test]
+  fn test_convert_point_light() {
+    let (light, aabb) = convert(cpu::light::HalaLight::point(glam::Vec3::new(1.0, 0.5, 0.25), 4.0).with_range(Some(5.0)));
+    assert_eq!(light._type, 0);
+    assert_vec3(light.intensity.into(), glam::Vec3::new(4.0, 2.0, 1.0));
+    assert_vec3(light.position.into(), glam::Vec3::new(1.0, 2.0, 3.0));
+    assert_eq!(light.range, 5.0);
+    assert_eq!((aabb.min, aabb.max), ([1.0, 2.0, 3.0], [1.0, 2.0, 3.0]));
+
+    // No range is infinite.
+    let (light, _) = convert(cpu::light::HalaLight::point(glam::Vec3::ONE, 1.0));
+    assert_eq!(light.range, 0.0);
+  }
+
+  #[test]
+  fn test_convert_directional_light() {
+    let (light, _) = convert(cpu::light::HalaLight::directional(glam::Vec3::ONE, 2.0, 0.1));
+    assert_eq!(light._type, 1);
+    assert_vec3(light.intensity.into(), glam::Vec3::splat(2.0));
+    // The light shines along the -Z axis of its node.
+    assert_vec3(light.u.into(), glam::Vec3::NEG_X);
+    // The cosine of the angular radius.
+    assert!((light.v.x - 0.1f32.cos()).abs() < 1e-6);
+    assert_eq!(light.range, 0.0);
+  }
+
+  #[test]
+  fn test_convert_spot_light() {
+    // The angles are swapped if the outer one is smaller.
+    let (light, _) = convert(cpu::light::HalaLight::spot(glam::Vec3::ONE, 1.0, 0.4, 0.2).with_range(Some(10.0)));
+    assert_eq!(light._type, 2);
+    assert_vec3(light.position.into(), glam::Vec3::new(1.0, 2.0, 3.0));
+    assert_vec3(light.u.into(), glam::Vec3::NEG_X);
+    assert!((light.v.x - 0.2f32.cos()).abs() < 1e-6);
+    assert!((light.v.y - 0.4f32.cos()).abs() < 1e-6);
+    assert_eq!(light.range, 10.0);
+  }
+
+  #[test]
+  fn test_convert_quad_light() {
+    let (light, aabb) = convert(cpu::light::HalaLight::quad(glam::Vec3::ONE, 1.0, 2.0, 1.0));
+    assert_eq!(light._type, 3);
+    // The position is the corner at -u/2 - v/2 from the node origin.
+    assert_vec3(light.position.into(), glam::Vec3::new(1.0, 1.5, 4.0));
+    assert_vec3(light.u.into(), glam::Vec3::new(0.0, 0.0, -2.0));
+    assert_vec3(light.v, glam::Vec3::Y);
+    assert_eq!(light.area, 2.0);
+    assert_vec3(glam::Vec3::from(aabb.min), glam::Vec3::new(1.0, 1.5, 4.0));
+    assert_vec3(glam::Vec3::from(aabb.max), glam::Vec3::new(1.01, 2.5, 2.0));
+  }
+
+  #[test]
+  fn test_convert_sphere_light() {
+    let (light, aabb) = convert(cpu::light::HalaLight::sphere(glam::Vec3::ONE, 1.0, 0.5));
+    assert_eq!(light._type, 4);
+    assert_vec3(light.position.into(), glam::Vec3::new(1.0, 2.0, 3.0));
+    assert_eq!(light.radius, 0.5);
+    assert!((light.area - std::f32::consts::PI).abs() < 1e-6);
+    assert_vec3(glam::Vec3::from(aabb.min), glam::Vec3::new(0.5, 1.5, 2.5));
+    assert_vec3(glam::Vec3::from(aabb.max), glam::Vec3::new(1.5, 2.5, 3.5));
+  }
+
+}
@@ -1,5 +1,7 @@
 pub mod gltf_loader;
 pub mod gpu_uploader;
+pub mod transfer_batch;
 
 pub use gltf_loader::*;
-pub use gpu_uploader::*;
\ No newline at end of file
+pub use gpu_uploader::*;
+pub use transfer_batch::*;
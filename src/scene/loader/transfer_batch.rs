@@ -0,0 +1,261 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use hala_gfx::{
+  HalaContext,
+  HalaLogicalDevice,
+  HalaBuffer,
+  HalaBufferUsageFlags,
+  HalaMemoryLocation,
+  HalaCommandBufferSet,
+  HalaBufferBarrierInfo,
+  HalaAccessFlags2,
+  HalaPipelineStageFlags2,
+  HalaFence,
+};
+
+use crate::error::HalaRendererError;
+
+/// The size of a staging chunk, the larger uploads get a chunk of their own size.
+pub const STAGING_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+/// The alignment of the staging suballocations.
+const STAGING_ALIGNMENT: u64 = 16;
+
+/// Get the offset of a suballocation in a staging chunk.
+/// param chunk_size: The size of the chunk.
+/// param offset: The first free offset of the chunk.
+/// param size: The size of the suballocation.
+/// return: The aligned offset, none if the suballocation does not fit in the chunk.
+pub(crate) fn get_staging_offset(chunk_size: u64, offset: u64, size: u64) -> Option<u64> {
+  let aligned_offset = offset.div_ceil(STAGING_ALIGNMENT) * STAGING_ALIGNMENT;
+  if aligned_offset + size <= chunk_size {
+    Some(aligned_offset)
+  } else {
+    None
+  }
+}
+
+/// The buffer uploads recorded to a one time command buffer of the transfer queue.
+/// The staging memory is suballocated from the staging chunks, which are kept alive until the transfer is complete.
+/// If the transfer queue family differs from the graphics one, each buffer is released by the transfer queue,
+/// and the graphics queue must acquire it before using it, see HalaPendingTransfer::record_acquire.
+pub struct HalaTransferBatch {
+  command_buffers: HalaCommandBufferSet,
+  staging_chunks: Vec<HalaBuffer>,
+  staging_offset: u64,
+  // The acquire barriers recorded by the graphics queue, empty if the queue families are the same.
+  acquire_barriers: Vec<HalaBufferBarrierInfo>,
+  // The transfer queue family index and the graphics queue family index if the ownership is transferred.
+  ownership_transfer: Option<(u32, u32)>,
+  logical_device: Rc<RefCell<HalaLogicalDevice>>,
+  name: String,
+
+  pub num_of_bytes: u64,
+}
+
+/// The implementation of the transfer batch.
+impl HalaTransferBatch {
+  /// Create a transfer batch and begin its command buffer.
+  /// param context: The gfx context.
+  /// param name: The name of the batch.
+  /// return: The transfer batch.
+  pub fn new(context: &HalaContext, name: &str) -> Result<Self, HalaRendererError> {
+    let command_buffers = HalaCommandBufferSet::new(
+      Rc::clone(&context.logical_device),
+      Rc::clone(&context.short_time_command_pools),
+      hala_gfx::HalaCommandBufferType::TRANSFER,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      1,
+      &format!("{}.command_buffers", name),
+    )?;
+    command_buffers.begin(0, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+
+    let ownership_transfer = {
+      let logical_device = context.logical_device.borrow();
+      if logical_device.transfer_queue_family_index == logical_device.graphics_queue_family_index {
+        None
+      } else {
+        Some((logical_device.transfer_queue_family_index, logical_device.graphics_queue_family_index))
+      }
+    };
+
+    Ok(Self {
+      command_buffers,
+      staging_chunks: Vec::new(),
+      staging_offset: 0,
+      acquire_barriers: Vec::new(),
+      ownership_transfer,
+      logical_device: Rc::clone(&context.logical_device),
+      name: name.to_string(),
+      num_of_bytes: 0,
+    })
+  }
+
+  /// Record the upload of the data to the buffer.
+  /// The buffer must be created with the exclusive sharing mode and must not be used until the transfer is complete.
+  /// param buffer: The destination buffer.
+  /// param data: The data.
+  /// return: The result.
+  pub fn update_buffer<T: Copy>(&mut self, buffer: &HalaBuffer, data: &[T]) -> Result<(), HalaRendererError> {
+    self.update_buffer_raw(buffer, data.as_ptr() as *const u8, std::mem::size_of_val(data))
+  }
+
+  /// Record the upload of the raw data to the buffer.
+  /// The buffer must be created with the exclusive sharing mode and must not be used until the transfer is complete.
+  /// param buffer: The destination buffer.
+  /// param data: The pointer of the data.
+  /// param size: The size of the data in bytes.
+  /// return: The result.
+  pub fn update_buffer_raw(&mut self, buffer: &HalaBuffer, data: *const u8, size: usize) -> Result<(), HalaRendererError> {
+    if size == 0 {
+      return Ok(());
+    }
+    if size as u64 > buffer.size {
+      return Err(HalaRendererError::validation(&format!(
+        "The upload of {} bytes exceeds the buffer of {} bytes.", size, buffer.size)));
+    }
+
+    // Suballocate the staging memory, a new chunk is created if the current one is full.
+    let offset = match self.staging_chunks.last().and_then(|chunk| get_staging_offset(chunk.size, self.staging_offset, size as u64)) {
+      Some(offset) => offset,
+      None => {
+        let chunk = HalaBuffer::new(
+          Rc::clone(&self.logical_device),
+          std::cmp::max(STAGING_CHUNK_SIZE, size as u64),
+          HalaBufferUsageFlags::TRANSFER_SRC,
+          HalaMemoryLocation::CpuToGpu,
+          &format!("{}_staging_{}.buffer", self.name, self.staging_chunks.len()))?;
+        self.staging_chunks.push(chunk);
+        0
+      }
+    };
+    let staging_chunk = self.staging_chunks.last().ok_or(HalaRendererError::invalid_state("The staging chunk is none!"))?;
+    let bytes = unsafe { std::slice::from_raw_parts(data, size) };
+    staging_chunk.update_memory(offset as usize, bytes)?;
+    self.staging_offset = offset + size as u64;
+
+    self.command_buffers.copy_buffer_2_buffer(0, staging_chunk, offset, buffer, 0, size as u64);
+
+    // Release the buffer from the transfer queue family, the graphics queue family acquires it.
+    if let Some((transfer_queue_family_index, graphics_queue_family_index)) = self.ownership_transfer {
+      self.command_buffers.set_buffer_barriers(
+        0,
+        &[HalaBufferBarrierInfo {
+          src_access_mask: HalaAccessFlags2::TRANSFER_WRITE,
+          dst_access_mask: HalaAccessFlags2::NONE,
+          src_stage_mask: HalaPipelineStageFlags2::TRANSFER,
+          dst_stage_mask: HalaPipelineStageFlags2::NONE,
+          src_queue_family_index: transfer_queue_family_index,
+          dst_queue_family_index: graphics_queue_family_index,
+          buffer: buffer.raw,
+          size: buffer.size,
+          ..Default::default()
+        }],
+      );
+      self.acquire_barriers.push(HalaBufferBarrierInfo {
+        src_access_mask: HalaAccessFlags2::NONE,
+        dst_access_mask: HalaAccessFlags2::MEMORY_READ,
+        src_stage_mask: HalaPipelineStageFlags2::NONE,
+        dst_stage_mask: HalaPipelineStageFlags2::ALL_COMMANDS,
+        src_queue_family_index: transfer_queue_family_index,
+        dst_queue_family_index: graphics_queue_family_index,
+        buffer: buffer.raw,
+        size: buffer.size,
+        ..Default::default()
+      });
+    }
+
+    self.num_of_bytes += size as u64;
+    Ok(())
+  }
+
+  /// Whether no upload is recorded.
+  /// return: True if the batch is empty.
+  pub fn is_empty(&self) -> bool {
+    self.num_of_bytes == 0
+  }
+
+  /// End the command buffer and submit it to the transfer queue with a fence.
+  /// The function returns without waiting, the transfer runs with the other work of the GPU.
+  /// return: The pending transfer.
+  pub fn submit(self) -> Result<HalaPendingTransfer, HalaRendererError> {
+    self.command_buffers.end(0)?;
+
+    let fence = HalaFence::new(Rc::clone(&self.logical_device), false, &format!("{}.fence", self.name))?;
+    self.logical_device.borrow().transfer_submit_with_fence(&self.command_buffers, 0, 0, &fence)?;
+    log::debug!("Submit the transfer batch \"{}\" of {} bytes.", self.name, self.num_of_bytes);
+
+    Ok(HalaPendingTransfer {
+      acquire_barriers: self.acquire_barriers,
+      fence,
+      _command_buffers: self.command_buffers,
+      _staging_chunks: self.staging_chunks,
+    })
+  }
+}
+
+/// The transfer batch submitted to the transfer queue.
+/// The command buffer and the staging chunks are kept alive until it is dropped,
+/// so it must not be dropped before the fence is signaled.
+pub struct HalaPendingTransfer {
+  acquire_barriers: Vec<HalaBufferBarrierInfo>,
+  fence: HalaFence,
+  _command_buffers: HalaCommandBufferSet,
+  _staging_chunks: Vec<HalaBuffer>,
+}
+
+/// The implementation of the pending transfer.
+impl HalaPendingTransfer {
+  /// Whether the transfer is complete, it does not block.
+  /// return: True if the fence is signaled.
+  pub fn is_complete(&self) -> Result<bool, HalaRendererError> {
+    Ok(self.fence.is_signaled()?)
+  }
+
+  /// Wait for the transfer on the CPU.
+  /// return: The result.
+  pub fn wait(&self) -> Result<(), HalaRendererError> {
+    self.fence.wait(u64::MAX)?;
+    Ok(())
+  }
+
+  /// Get the acquire barriers of the uploaded buffers, empty if the queue families are the same.
+  /// return: The acquire barriers.
+  pub fn get_acquire_barriers(&self) -> &[HalaBufferBarrierInfo] {
+    &self.acquire_barriers
+  }
+
+  /// Wait for the transfer and take the acquire barriers, the staging memory is freed.
+  /// The barriers must be recorded once by a graphics command buffer before the buffers are used.
+  /// return: The acquire barriers.
+  pub fn finish(self) -> Result<Vec<HalaBufferBarrierInfo>, HalaRendererError> {
+    self.wait()?;
+    Ok(self.acquire_barriers)
+  }
+
+  /// Record the acquire barriers of the uploaded buffers to a graphics command buffer.
+  /// The command buffer must be submitted after the fence is signaled, and only once.
+  /// param index: The index of the command buffer.
+  /// param command_buffers: The graphics command buffers.
+  pub fn record_acquire(&self, index: usize, command_buffers: &HalaCommandBufferSet) {
+    if !self.acquire_barriers.is_empty() {
+      command_buffers.set_buffer_barriers(index, &self.acquire_barriers);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_get_staging_offset() {
+    assert_eq!(get_staging_offset(64, 0, 64), Some(0));
+    assert_eq!(get_staging_offset(64, 1, 16), Some(16));
+    assert_eq!(get_staging_offset(64, 16, 16), Some(16));
+    assert_eq!(get_staging_offset(64, 33, 16), Some(48));
+    assert_eq!(get_staging_offset(64, 33, 17), None);
+    assert_eq!(get_staging_offset(64, 64, 1), None);
+  }
+
+}
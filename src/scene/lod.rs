@@ -0,0 +1,73 @@
+use crate::error::HalaRendererError;
+
+/// The level of detail of a primitive.
+/// The indices of all levels are stored in the same index buffer, the level 0 is the original mesh at the beginning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaLod {
+  /// The first index of the level in the index buffer.
+  pub first_index: u32,
+  /// The index count of the level.
+  pub index_count: u32,
+  /// The first meshlet of the level in the meshlet buffer, only valid for mesh shader.
+  pub first_meshlet: u32,
+  /// The meshlet count of the level, only valid for mesh shader.
+  pub meshlet_count: u32,
+  /// The simplification error relative to the primitive size, 0 for the level 0.
+  pub error: f32,
+}
+
+/// The level of detail building configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaLodConfig {
+  /// The maximum level count including the level 0, 1 disables the simplification.
+  pub max_lods: usize,
+  /// The target index count ratio of each level to the previous level in (0, 1).
+  pub reduction_ratio: f32,
+  /// The maximum simplification error relative to the primitive size.
+  /// The levels exceeding it are dropped, so the primitives whose seams can not be kept only have the level 0.
+  pub max_error: f32,
+}
+
+/// The Default implementation of the level of detail configuration.
+impl Default for HalaLodConfig {
+  fn default() -> Self {
+    Self {
+      max_lods: 1,
+      reduction_ratio: 0.5,
+      max_error: 0.05,
+    }
+  }
+}
+
+/// The implementation of the level of detail configuration.
+impl HalaLodConfig {
+
+  /// Create a new level of detail configuration.
+  /// param max_lods: The maximum level count including the level 0.
+  /// param reduction_ratio: The target index count ratio of each level to the previous level.
+  /// param max_error: The maximum simplification error relative to the primitive size.
+  /// return: The level of detail configuration.
+  pub fn new(max_lods: usize, reduction_ratio: f32, max_error: f32) -> Self {
+    Self {
+      max_lods,
+      reduction_ratio,
+      max_error,
+    }
+  }
+
+  /// Validate the level of detail configuration.
+  /// return: The result.
+  pub fn validate(&self) -> Result<(), HalaRendererError> {
+    if self.max_lods == 0 {
+      return Err(HalaRendererError::validation("The max LOD count must be at least 1."));
+    }
+    if self.reduction_ratio <= 0.0 || self.reduction_ratio >= 1.0 {
+      return Err(HalaRendererError::validation(&format!("The LOD reduction ratio {} must be in (0, 1).", self.reduction_ratio)));
+    }
+    if self.max_error < 0.0 {
+      return Err(HalaRendererError::validation(&format!("The LOD max error {} must not be negative.", self.max_error)));
+    }
+    Ok(())
+  }
+
+}
\ No newline at end of file
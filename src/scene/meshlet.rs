@@ -1,6 +1,17 @@
+use crate::error::HalaRendererError;
+use super::HalaVertex;
+use serde::{
+  Deserialize,
+  Serialize,
+};
+
 /// The meshlet.
+/// The bounds are in the object space of the primitive, transform them by the model matrix before culling.
+/// The cone fields follow the meshoptimizer convention, a meshlet is back-facing and can be culled if
+/// dot(normalize(cone_apex - camera_position), cone_axis) >= cone_cutoff.
+/// The sphere(center, radius) is used for frustum culling.
 #[repr(C, align(16))]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct HalaMeshlet {
   pub center: [f32; 3],
   pub radius: f32,
@@ -12,4 +23,123 @@ pub struct HalaMeshlet {
   pub offset_of_vertices: u32,
   pub offset_of_primitives: u32,
   pub draw_index: u32,
-}
\ No newline at end of file
+}
+
+/// The meshlet building configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaMeshletConfig {
+  /// The maximum vertex count of a meshlet, the local indices are packed as 8 bits so it must be in [3, 255].
+  pub max_vertices: usize,
+  /// The maximum triangle count of a meshlet, it must be in [1, 512] and a multiple of 4.
+  pub max_triangles: usize,
+  /// The weight of the cone culling in [0, 1], higher value produces meshlets with tighter cones.
+  pub cone_weight: f32,
+}
+
+/// The Default implementation of the meshlet configuration.
+impl Default for HalaMeshletConfig {
+  fn default() -> Self {
+    Self {
+      max_vertices: 64,
+      max_triangles: 124,
+      cone_weight: 0.5,
+    }
+  }
+}
+
+/// The implementation of the meshlet configuration.
+impl HalaMeshletConfig {
+
+  /// Create a new meshlet configuration.
+  /// param max_vertices: The maximum vertex count of a meshlet.
+  /// param max_triangles: The maximum triangle count of a meshlet.
+  /// param cone_weight: The weight of the cone culling.
+  /// return: The meshlet configuration.
+  pub fn new(max_vertices: usize, max_triangles: usize, cone_weight: f32) -> Self {
+    Self {
+      max_vertices,
+      max_triangles,
+      cone_weight,
+    }
+  }
+
+  /// Validate the meshlet configuration.
+  /// return: The result.
+  pub fn validate(&self) -> Result<(), HalaRendererError> {
+    if self.max_vertices < 3 || self.max_vertices > 255 {
+      return Err(HalaRendererError::validation(&format!("The max vertices {} of the meshlet must be in [3, 255].", self.max_vertices)));
+    }
+    if self.max_triangles == 0 || self.max_triangles > 512 || self.max_triangles % 4 != 0 {
+      return Err(HalaRendererError::validation(&format!("The max triangles {} of the meshlet must be in [1, 512] and a multiple of 4.", self.max_triangles)));
+    }
+    if !(0.0..=1.0).contains(&self.cone_weight) {
+      return Err(HalaRendererError::validation(&format!("The cone weight {} of the meshlet must be in [0, 1].", self.cone_weight)));
+    }
+    Ok(())
+  }
+
+}
+
+/// Build the meshlets of a triangle list on the CPU.
+/// The meshlet vertices are the indices of the vertices, the 3 local vertex indices of a meshlet primitive are packed as 8 bits.
+/// The offsets of the meshlets are the lengths of the output lists before the meshlet is appended and the draw index is 0,
+/// the caller biases them, e.g. by the previous primitives in the mega buffers.
+/// param vertices: The vertices.
+/// param indices: The triangle list indices.
+/// param config: The validated meshlet configuration.
+/// param meshlet_vertices: The meshlet vertices, the ones of the new meshlets are appended.
+/// param meshlet_primitives: The packed meshlet primitives, the ones of the new meshlets are appended.
+/// return: The new meshlets.
+pub fn build_meshlets(
+  vertices: &[HalaVertex],
+  indices: &[u32],
+  config: &HalaMeshletConfig,
+  meshlet_vertices: &mut Vec<u32>,
+  meshlet_primitives: &mut Vec<u32>,
+) -> Result<Vec<HalaMeshlet>, HalaRendererError> {
+  let vertex_data_adapter = unsafe {
+    meshopt::VertexDataAdapter::new(
+      std::slice::from_raw_parts(vertices.as_ptr() as *const u8, std::mem::size_of_val(vertices)),
+      std::mem::size_of::<HalaVertex>(),
+      0,
+    ).map_err(|err| HalaRendererError::new("Failed to create vertex data adapter.", Some(Box::new(err))))?
+  };
+  let meshlets_in_cpu = meshopt::clusterize::build_meshlets(
+    indices,
+    &vertex_data_adapter,
+    config.max_vertices,
+    config.max_triangles,
+    config.cone_weight,
+  );
+
+  let mut meshlets = Vec::with_capacity(meshlets_in_cpu.meshlets.len());
+  for (meshlet_index, meshlet_in_cpu) in meshlets_in_cpu.meshlets.iter().enumerate() {
+    let wrapped_meshlet_in_cpu = meshlets_in_cpu.get(meshlet_index);
+    let bounds = meshopt::clusterize::compute_meshlet_bounds(
+      wrapped_meshlet_in_cpu,
+      &vertex_data_adapter,
+    );
+
+    assert!(meshlet_in_cpu.triangle_offset % 4 == 0, "The triangle offset of the meshlet is not a multiple of 4.");
+    assert!(wrapped_meshlet_in_cpu.triangles.len() % 3 == 0, "The triangle count of the meshlet is not a multiple of 3.");
+    meshlets.push(HalaMeshlet {
+      center: bounds.center,
+      radius: bounds.radius,
+      cone_apex: bounds.cone_apex,
+      cone_axis: bounds.cone_axis,
+      cone_cutoff: bounds.cone_cutoff,
+      offset_of_vertices: meshlet_vertices.len() as u32,
+      num_of_vertices: meshlet_in_cpu.vertex_count,
+      offset_of_primitives: meshlet_primitives.len() as u32,
+      num_of_primitives: (wrapped_meshlet_in_cpu.triangles.len() / 3) as u32,
+      draw_index: 0,
+    });
+
+    meshlet_vertices.extend_from_slice(wrapped_meshlet_in_cpu.vertices);
+    for c in wrapped_meshlet_in_cpu.triangles.chunks(3) {
+      meshlet_primitives.push((c[0] as u32) | (c[1] as u32) << 8 | (c[2] as u32) << 16);
+    }
+  }
+
+  Ok(meshlets)
+}
@@ -2,9 +2,29 @@ pub mod loader;
 pub mod vertex;
 pub mod bounds;
 pub mod meshlet;
+pub mod lod;
 pub mod cpu;
 pub mod gpu;
+pub mod picking;
+pub mod bvh;
 
 pub use vertex::HalaVertex;
 pub use bounds::HalaBounds;
-pub use meshlet::HalaMeshlet;
\ No newline at end of file
+pub use meshlet::{
+  HalaMeshlet,
+  HalaMeshletConfig,
+};
+pub use cpu::camera::{
+  HalaCameraType,
+  HalaDepthConvention,
+};
+pub use picking::HalaPickResult;
+pub use bvh::{
+  HalaBvh,
+  HalaBvhTriangle,
+  HalaBvhClosestPoint,
+};
+pub use lod::{
+  HalaLod,
+  HalaLodConfig,
+};
\ No newline at end of file
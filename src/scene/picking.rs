@@ -0,0 +1,182 @@
+use glam::{Mat4, Vec2, Vec3, Vec4};
+
+use super::cpu;
+use super::HalaDepthConvention;
+
+/// The closest hit of a ray cast against the CPU scene.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaPickResult {
+  pub node_index: u32,
+  pub mesh_index: u32,
+  pub prim_index: u32,
+  pub triangle_index: u32,
+  /// The distance along the normalized ray direction.
+  pub t: f32,
+  /// The world space hit position.
+  pub position: Vec3,
+  /// The world space geometric normal of the triangle, it follows the winding and is not flipped toward the ray.
+  pub normal: Vec3,
+  /// The barycentric coordinates of the second and the third vertex, the first one is 1 - x - y.
+  pub barycentrics: Vec2,
+}
+
+/// Cast a ray against the triangles of the CPU scene and get the closest hit.
+/// The cached primitive bounds of the scene reject the primitives which the ray misses,
+/// the remaining ones are tested from near to far and the test stops when the next bounds are behind the closest hit.
+/// Both sides of the triangles are hit.
+/// param scene: The CPU scene, its cached bounds must be up to date(see HalaScene::update_transforms).
+/// param ray_origin: The world space origin of the ray.
+/// param ray_dir: The world space direction of the ray, it is normalized here.
+/// return: The closest hit, none if the ray hits nothing.
+pub fn pick(scene: &cpu::HalaScene, ray_origin: Vec3, ray_dir: Vec3) -> Option<HalaPickResult> {
+  let ray_dir = ray_dir.try_normalize()?;
+  let inv_dir = ray_dir.recip();
+
+  let mut candidates = scene.primitive_bounds.iter()
+    .filter_map(|primitive_bounds| {
+      let bounds = &primitive_bounds.bounds;
+      intersect_bounds(Vec3::from(bounds.get_min()), Vec3::from(bounds.get_max()), ray_origin, inv_dir).map(|t| (t, primitive_bounds))
+    })
+    .collect::<Vec<_>>();
+  candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+  let mut closest: Option<HalaPickResult> = None;
+  for (t_enter, primitive_bounds) in candidates {
+    if closest.is_some_and(|hit| hit.t < t_enter) {
+      break;
+    }
+
+    let node = &scene.nodes[primitive_bounds.node_index as usize];
+    let primitive = &scene.meshes[primitive_bounds.mesh_index as usize].primitives[primitive_bounds.prim_index as usize];
+
+    // Test in the local space, the distance is kept because the direction is transformed without the normalization.
+    let world_to_local = node.world_transform.inverse();
+    let local_origin = world_to_local.transform_point3(ray_origin);
+    let local_dir = world_to_local.transform_vector3(ray_dir);
+
+    let mut t_max = closest.map_or(f32::MAX, |hit| hit.t);
+    for (triangle_index, triangle) in primitive.indices.chunks_exact(3).enumerate() {
+      let p0 = Vec3::from(primitive.vertices[triangle[0] as usize].position);
+      let p1 = Vec3::from(primitive.vertices[triangle[1] as usize].position);
+      let p2 = Vec3::from(primitive.vertices[triangle[2] as usize].position);
+      if let Some((t, barycentrics)) = intersect_triangle(local_origin, local_dir, p0, p1, p2, t_max) {
+        t_max = t;
+        let local_normal = (p1 - p0).cross(p2 - p0);
+        closest = Some(HalaPickResult {
+          node_index: primitive_bounds.node_index,
+          mesh_index: primitive_bounds.mesh_index,
+          prim_index: primitive_bounds.prim_index,
+          triangle_index: triangle_index as u32,
+          t,
+          position: ray_origin + ray_dir * t,
+          normal: world_to_local.transpose().transform_vector3(local_normal).normalize_or_zero(),
+          barycentrics,
+        });
+      }
+    }
+  }
+
+  closest
+}
+
+/// Get the world space ray through a point of the viewport.
+/// The ray starts on the near plane, so it works for both the perspective and the orthographic projection.
+/// param v_mtx: The view matrix.
+/// param p_mtx: The projection matrix.
+/// param depth_convention: The depth convention of the projection matrix.
+/// param viewport: The viewport(x, y, width, height) in pixels, y is down.
+/// param x: The x coordinate in pixels.
+/// param y: The y coordinate in pixels.
+/// return: The origin and the normalized direction of the ray.
+pub fn get_screen_ray(
+  v_mtx: Mat4,
+  p_mtx: Mat4,
+  depth_convention: HalaDepthConvention,
+  viewport: (f32, f32, f32, f32),
+  x: f32,
+  y: f32,
+) -> (Vec3, Vec3) {
+  // The far plane of the infinite reverse Z projection can not be unprojected, use a depth halfway instead.
+  let near_depth = 1.0 - depth_convention.get_clear_depth();
+  let near_position = get_screen_position(v_mtx, p_mtx, viewport, x, y, near_depth);
+  let middle_position = get_screen_position(v_mtx, p_mtx, viewport, x, y, 0.5);
+
+  (near_position, (middle_position - near_position).normalize_or_zero())
+}
+
+/// Get the world space position of a point of the viewport at the depth.
+/// param v_mtx: The view matrix.
+/// param p_mtx: The projection matrix.
+/// param viewport: The viewport(x, y, width, height) in pixels, y is down.
+/// param x: The x coordinate in pixels.
+/// param y: The y coordinate in pixels.
+/// param depth: The depth in [0, 1] of the projection matrix.
+/// return: The world space position.
+pub fn get_screen_position(
+  v_mtx: Mat4,
+  p_mtx: Mat4,
+  viewport: (f32, f32, f32, f32),
+  x: f32,
+  y: f32,
+  depth: f32,
+) -> Vec3 {
+  // The viewport of the renderers is flipped, so the top of the viewport is +1 in the NDC.
+  let ndc_x = (x - viewport.0) / viewport.2 * 2.0 - 1.0;
+  let ndc_y = 1.0 - (y - viewport.1) / viewport.3 * 2.0;
+  let position = (p_mtx * v_mtx).inverse() * Vec4::new(ndc_x, ndc_y, depth, 1.0);
+  position.truncate() / position.w
+}
+
+/// Intersect a ray with the bounds with the slab test.
+/// param min: The minimum of the bounds.
+/// param max: The maximum of the bounds.
+/// param origin: The origin of the ray.
+/// param inv_dir: The reciprocal of the ray direction.
+/// return: The distance where the ray enters the bounds, 0 if the origin is inside, none if missed.
+pub(crate) fn intersect_bounds(min: Vec3, max: Vec3, origin: Vec3, inv_dir: Vec3) -> Option<f32> {
+  let t0 = (min - origin) * inv_dir;
+  let t1 = (max - origin) * inv_dir;
+  let t_enter = t0.min(t1).max_element().max(0.0);
+  let t_exit = t0.max(t1).min_element();
+  if t_enter <= t_exit {
+    Some(t_enter)
+  } else {
+    None
+  }
+}
+
+/// Intersect a ray with a triangle with the Möller–Trumbore algorithm.
+/// param origin: The origin of the ray.
+/// param dir: The direction of the ray.
+/// param p0: The first vertex.
+/// param p1: The second vertex.
+/// param p2: The third vertex.
+/// param t_max: The maximum distance.
+/// return: The distance and the barycentric coordinates of p1 and p2, none if missed.
+pub(crate) fn intersect_triangle(origin: Vec3, dir: Vec3, p0: Vec3, p1: Vec3, p2: Vec3, t_max: f32) -> Option<(f32, Vec2)> {
+  let edge1 = p1 - p0;
+  let edge2 = p2 - p0;
+  let p = dir.cross(edge2);
+  let det = edge1.dot(p);
+  if det.abs() <= f32::EPSILON * edge1.length() * edge2.length() * dir.length() {
+    return None;
+  }
+
+  let inv_det = 1.0 / det;
+  let s = origin - p0;
+  let u = s.dot(p) * inv_det;
+  if !(0.0..=1.0).contains(&u) {
+    return None;
+  }
+  let q = s.cross(edge1);
+  let v = dir.dot(q) * inv_det;
+  if v < 0.0 || u + v > 1.0 {
+    return None;
+  }
+  let t = edge2.dot(q) * inv_det;
+  if t < 0.0 || t >= t_max {
+    return None;
+  }
+
+  Some((t, Vec2::new(u, v)))
+}
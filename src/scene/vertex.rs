@@ -1,6 +1,13 @@
+use serde::{
+  Deserialize,
+  Serialize,
+};
+
 /// The vertex.
+/// The tangent has no handedness component, it is flipped for the mirrored UVs instead,
+/// so the bitangent is always reconstructed as cross(normal, tangent).
 #[repr(C, align(4))]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
 pub struct HalaVertex {
   pub position: [f32; 3],
   pub normal: [f32; 3],
@@ -0,0 +1,324 @@
+use ash::vk;
+
+use crate::error::HalaRendererError;
+
+/// Get the raw descriptor set bound for the frame, the static descriptor sets have one set for all frames.
+/// param descriptor_set: The descriptor set.
+/// param index: The index of the frame.
+/// return: The raw descriptor set.
+pub(crate) fn get_raw_descriptor_set(descriptor_set: &hala_gfx::HalaDescriptorSet, index: usize) -> vk::DescriptorSet {
+  descriptor_set.raw[index % descriptor_set.raw.len()]
+}
+
+/// The draw call of a secondary draw.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum HalaSecondaryDrawCall {
+  Indexed {
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    index_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+  },
+  MeshTasks {
+    group_count_x: u32,
+  },
+}
+
+/// A scene draw resolved on the recording thread with its state.
+/// It only holds the raw handles and the push constant bytes, so the chunks of the draws can be sent to the worker threads.
+/// The secondary command buffers inherit no state, so each chunk binds the state of its first draw and the changes after it.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HalaSecondaryDraw {
+  pub pipeline: vk::Pipeline,
+  pub pipeline_layout: vk::PipelineLayout,
+  pub descriptor_sets: [vk::DescriptorSet; 3],
+  // The flipped viewport(x, y, width, height) and the scissor(x, y, width, height) of the view.
+  pub viewport: (f32, f32, f32, f32),
+  pub scissor: (i32, i32, u32, u32),
+  pub push_constant_stages: vk::ShaderStageFlags,
+  pub push_constants: Vec<u8>,
+  pub call: HalaSecondaryDrawCall,
+  // The occlusion query pool and the query index of the draw.
+  pub occlusion_query: Option<(vk::QueryPool, u32)>,
+}
+
+/// The render pass or the dynamic rendering scope the secondary command buffers are executed in.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum HalaSecondaryInheritance {
+  RenderPass {
+    render_pass: vk::RenderPass,
+    subpass: u32,
+  },
+  Rendering {
+    color_formats: Vec<vk::Format>,
+    depth_format: vk::Format,
+    stencil_format: vk::Format,
+    samples: vk::SampleCountFlags,
+  },
+}
+
+/// The implementation of the secondary inheritance.
+impl HalaSecondaryInheritance {
+  /// Begin the secondary command buffer which continues the render pass or the rendering.
+  /// param device: The logical device.
+  /// param command_buffer: The secondary command buffer.
+  /// return: The result.
+  pub(crate) fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) -> Result<(), HalaRendererError> {
+    let mut rendering_info = match self {
+      Self::Rendering { color_formats, depth_format, stencil_format, samples } => Some(
+        vk::CommandBufferInheritanceRenderingInfo::default()
+          .color_attachment_formats(color_formats)
+          .depth_attachment_format(*depth_format)
+          .stencil_attachment_format(*stencil_format)
+          .rasterization_samples(*samples)
+      ),
+      Self::RenderPass { .. } => None,
+    };
+    let mut inheritance_info = match self {
+      Self::RenderPass { render_pass, subpass } => vk::CommandBufferInheritanceInfo::default()
+        .render_pass(*render_pass)
+        .subpass(*subpass),
+      Self::Rendering { .. } => vk::CommandBufferInheritanceInfo::default(),
+    };
+    if let Some(rendering_info) = rendering_info.as_mut() {
+      inheritance_info = inheritance_info.push_next(rendering_info);
+    }
+    let begin_info = vk::CommandBufferBeginInfo::default()
+      .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+      .inheritance_info(&inheritance_info);
+
+    unsafe { device.begin_command_buffer(command_buffer, &begin_info) }
+      .map_err(|err| HalaRendererError::new("Failed to begin the secondary command buffer.", Some(Box::new(err))))
+  }
+}
+
+/// The command pool of a recording thread.
+/// The pool is externally synchronized, so each worker thread records its chunks into the buffers of its own pool.
+/// All the buffers are freed by resetting the pool when the frame is recorded again.
+pub struct HalaSecondaryCommandPool {
+  device: ash::Device,
+  mesh_shader_device: Option<ash::ext::mesh_shader::Device>,
+  raw: vk::CommandPool,
+  command_buffers: Vec<vk::CommandBuffer>,
+  num_of_used_command_buffers: usize,
+}
+
+/// The Drop trait implementation of the secondary command pool.
+impl Drop for HalaSecondaryCommandPool {
+  fn drop(&mut self) {
+    unsafe {
+      self.device.destroy_command_pool(self.raw, None);
+    }
+  }
+}
+
+/// The implementation of the secondary command pool.
+impl HalaSecondaryCommandPool {
+  /// Create a command pool of the queue family.
+  /// param device: The logical device.
+  /// param mesh_shader_device: The mesh shader functions, none if the mesh shader is not used.
+  /// param queue_family_index: The graphics queue family index.
+  /// return: The command pool.
+  pub fn new(
+    device: &ash::Device,
+    mesh_shader_device: Option<&ash::ext::mesh_shader::Device>,
+    queue_family_index: u32,
+  ) -> Result<Self, HalaRendererError> {
+    let create_info = vk::CommandPoolCreateInfo::default()
+      .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+      .queue_family_index(queue_family_index);
+    let raw = unsafe { device.create_command_pool(&create_info, None) }
+      .map_err(|err| HalaRendererError::new("Failed to create the secondary command pool.", Some(Box::new(err))))?;
+
+    Ok(Self {
+      device: device.clone(),
+      mesh_shader_device: mesh_shader_device.cloned(),
+      raw,
+      command_buffers: Vec::new(),
+      num_of_used_command_buffers: 0,
+    })
+  }
+
+  /// Reset the pool, the command buffers allocated from it can be reused.
+  /// The command buffers executed by the frame must have completed on the GPU.
+  /// return: The result.
+  pub fn reset(&mut self) -> Result<(), HalaRendererError> {
+    unsafe { self.device.reset_command_pool(self.raw, vk::CommandPoolResetFlags::empty()) }
+      .map_err(|err| HalaRendererError::new("Failed to reset the secondary command pool.", Some(Box::new(err))))?;
+    self.num_of_used_command_buffers = 0;
+    Ok(())
+  }
+
+  /// Get an unused secondary command buffer, a new one is allocated if all of them are used.
+  /// return: The command buffer.
+  fn get_command_buffer(&mut self) -> Result<vk::CommandBuffer, HalaRendererError> {
+    if self.num_of_used_command_buffers == self.command_buffers.len() {
+      let allocate_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(self.raw)
+        .level(vk::CommandBufferLevel::SECONDARY)
+        .command_buffer_count(1);
+      let command_buffers = unsafe { self.device.allocate_command_buffers(&allocate_info) }
+        .map_err(|err| HalaRendererError::new("Failed to allocate the secondary command buffer.", Some(Box::new(err))))?;
+      self.command_buffers.extend(command_buffers);
+    }
+
+    let command_buffer = self.command_buffers[self.num_of_used_command_buffers];
+    self.num_of_used_command_buffers += 1;
+    Ok(command_buffer)
+  }
+
+  /// Record the draws into a secondary command buffer which continues the render pass or the rendering of the inheritance.
+  /// param inheritance: The render pass or the rendering scope.
+  /// param draws: The draws in the order of the serial recording.
+  /// return: The command buffer and the pipeline bind count.
+  pub(crate) fn record(
+    &mut self,
+    inheritance: &HalaSecondaryInheritance,
+    draws: &[HalaSecondaryDraw],
+  ) -> Result<(vk::CommandBuffer, u64), HalaRendererError> {
+    let command_buffer = self.get_command_buffer()?;
+
+    inheritance.begin(&self.device, command_buffer)?;
+
+    let mut num_of_pipeline_binds = 0u64;
+    unsafe {
+      let mut last_draw: Option<&HalaSecondaryDraw> = None;
+      for draw in draws.iter() {
+        if !matches!(last_draw, Some(last_draw) if last_draw.viewport == draw.viewport && last_draw.scissor == draw.scissor) {
+          let (x, y, width, height) = draw.viewport;
+          self.device.cmd_set_viewport(command_buffer, 0, &[vk::Viewport { x, y, width, height, min_depth: 0.0, max_depth: 1.0 }]);
+          let (x, y, width, height) = draw.scissor;
+          self.device.cmd_set_scissor(command_buffer, 0, &[vk::Rect2D { offset: vk::Offset2D { x, y }, extent: vk::Extent2D { width, height } }]);
+        }
+        if !matches!(last_draw, Some(last_draw) if last_draw.pipeline == draw.pipeline) {
+          self.device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, draw.pipeline);
+          num_of_pipeline_binds += 1;
+        }
+        if !matches!(last_draw, Some(last_draw) if last_draw.descriptor_sets == draw.descriptor_sets) {
+          self.device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, draw.pipeline_layout, 0, &draw.descriptor_sets, &[]);
+        }
+
+        self.device.cmd_push_constants(command_buffer, draw.pipeline_layout, draw.push_constant_stages, 0, &draw.push_constants);
+        if let Some((query_pool, query_index)) = draw.occlusion_query {
+          self.device.cmd_begin_query(command_buffer, query_pool, query_index, vk::QueryControlFlags::PRECISE);
+        }
+        match draw.call {
+          HalaSecondaryDrawCall::Indexed { vertex_buffer, index_buffer, index_count, first_index, vertex_offset } => {
+            self.device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+            self.device.cmd_bind_index_buffer(command_buffer, index_buffer, 0, vk::IndexType::UINT32);
+            self.device.cmd_draw_indexed(command_buffer, index_count, 1, first_index, vertex_offset, 0);
+          },
+          HalaSecondaryDrawCall::MeshTasks { group_count_x } => {
+            let mesh_shader_device = self.mesh_shader_device.as_ref()
+              .ok_or(HalaRendererError::invalid_state("The secondary command pool has no mesh shader functions!"))?;
+            mesh_shader_device.cmd_draw_mesh_tasks(command_buffer, group_count_x, 1, 1);
+          },
+        }
+        if let Some((query_pool, query_index)) = draw.occlusion_query {
+          self.device.cmd_end_query(command_buffer, query_pool, query_index);
+        }
+
+        last_draw = Some(draw);
+      }
+
+      self.device.end_command_buffer(command_buffer)
+        .map_err(|err| HalaRendererError::new("Failed to end the secondary command buffer.", Some(Box::new(err))))?;
+    }
+
+    Ok((command_buffer, num_of_pipeline_binds))
+  }
+}
+
+/// Split the draws into the contiguous chunks of the recording threads.
+/// Executing the chunks in order keeps the draw order of the serial recording, so the image does not depend on the thread count.
+/// param draws: The draws.
+/// param num_of_threads: The number of the recording threads.
+/// return: The chunks, at most one per thread and none is empty.
+pub(crate) fn split_draws(draws: &[HalaSecondaryDraw], num_of_threads: usize) -> Vec<&[HalaSecondaryDraw]> {
+  if draws.is_empty() {
+    return Vec::new();
+  }
+  let num_of_chunks = num_of_threads.clamp(1, draws.len());
+  let chunk_size = draws.len() / num_of_chunks;
+  let remainder = draws.len() % num_of_chunks;
+
+  // The first chunks take one more draw of the remainder.
+  let mut chunks = Vec::with_capacity(num_of_chunks);
+  let mut offset = 0;
+  for i in 0..num_of_chunks {
+    let size = chunk_size + if i < remainder { 1 } else { 0 };
+    chunks.push(&draws[offset..offset + size]);
+    offset += size;
+  }
+  chunks
+}
+
+/// Record the chunks of the draws into the secondary command buffers on the worker threads.
+/// The chunk i is recorded by the pool i, so no pool is shared between the threads.
+/// param command_pools: The command pools of the recording threads.
+/// param inheritance: The render pass or the rendering scope.
+/// param draws: The draws in the order of the serial recording.
+/// return: The secondary command buffers in the execution order and the pipeline bind count.
+pub(crate) fn record_draws_in_parallel(
+  command_pools: &mut [HalaSecondaryCommandPool],
+  inheritance: &HalaSecondaryInheritance,
+  draws: &[HalaSecondaryDraw],
+) -> Result<(Vec<vk::CommandBuffer>, u64), HalaRendererError> {
+  let chunks = split_draws(draws, command_pools.len());
+
+  let results = std::thread::scope(|scope| {
+    let handles = chunks.into_iter()
+      .zip(command_pools.iter_mut())
+      .map(|(chunk, command_pool)| scope.spawn(move || command_pool.record(inheritance, chunk)))
+      .collect::<Vec<_>>();
+    handles.into_iter()
+      .map(|handle| handle.join().unwrap_or_else(|_| Err(HalaRendererError::new("The recording thread panicked!", None))))
+      .collect::<Vec<_>>()
+  });
+
+  let mut command_buffers = Vec::with_capacity(results.len());
+  let mut num_of_pipeline_binds = 0u64;
+  for result in results {
+    let (command_buffer, num_of_binds) = result?;
+    command_buffers.push(command_buffer);
+    num_of_pipeline_binds += num_of_binds;
+  }
+  Ok((command_buffers, num_of_pipeline_binds))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn make_draws(count: u32) -> Vec<HalaSecondaryDraw> {
+    (0..count).map(|i| HalaSecondaryDraw {
+      pipeline: vk::Pipeline::null(),
+      pipeline_layout: vk::PipelineLayout::null(),
+      descriptor_sets: [vk::DescriptorSet::null(); 3],
+      viewport: (0.0, 0.0, 1.0, 1.0),
+      scissor: (0, 0, 1, 1),
+      push_constant_stages: vk::ShaderStageFlags::VERTEX,
+      push_constants: i.to_le_bytes().to_vec(),
+      call: HalaSecondaryDrawCall::MeshTasks { group_count_x: i },
+      occlusion_query: None,
+    }).collect()
+  }
+
+  #[test]
+  fn test_split_draws() {
+    let draws = make_draws(10);
+    for num_of_threads in 1..=12 {
+      let chunks = split_draws(&draws, num_of_threads);
+      assert_eq!(chunks.len(), num_of_threads.min(draws.len()));
+      assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+      // The concatenated chunks keep the serial order.
+      assert_eq!(chunks.concat(), draws);
+      let sizes = chunks.iter().map(|chunk| chunk.len()).collect::<Vec<_>>();
+      assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+    }
+    assert!(split_draws(&[], 4).is_empty());
+    assert_eq!(split_draws(&draws, 0).len(), 1);
+  }
+
+}
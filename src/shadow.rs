@@ -0,0 +1,527 @@
+use std::rc::Rc;
+
+use hala_gfx::{
+  HalaBuffer,
+  HalaContext,
+  HalaDescriptorSet,
+  HalaImage,
+  HalaSampler,
+};
+
+use crate::error::HalaRendererError;
+use crate::scene::{
+  gpu,
+  HalaDepthConvention,
+};
+use crate::scene::loader::gpu_uploader::MAX_LIGHT_COUNT;
+
+/// The max tile count of the shadow atlas, the tiles beyond it are not allocated.
+pub const MAX_SHADOW_TILES: usize = 64;
+
+/// The max sample count of the blocker search and the filter of the soft shadows.
+pub const MAX_SHADOW_SAMPLES: u32 = 64;
+
+/// The format of the shadow atlas.
+const SHADOW_ATLAS_FORMAT: hala_gfx::HalaFormat = hala_gfx::HalaFormat::D32_SFLOAT;
+
+/// The forward and the up directions of the cube faces in the order +X, -X, +Y, -Y, +Z and -Z.
+const CUBE_FACE_DIRECTIONS: [(glam::Vec3, glam::Vec3); 6] = [
+  (glam::Vec3::X, glam::Vec3::NEG_Y),
+  (glam::Vec3::NEG_X, glam::Vec3::NEG_Y),
+  (glam::Vec3::Y, glam::Vec3::Z),
+  (glam::Vec3::NEG_Y, glam::Vec3::NEG_Z),
+  (glam::Vec3::Z, glam::Vec3::NEG_Y),
+  (glam::Vec3::NEG_Z, glam::Vec3::NEG_Y),
+];
+
+/// The config of the shadow maps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HalaShadowConfig {
+  /// The width and the height of the shadow atlas in texels.
+  pub atlas_size: u32,
+  /// The width and the height of each shadow map(tile) in the atlas, the atlas size must be a multiple of it.
+  pub tile_size: u32,
+  /// The constant bias subtracted from the receiver depth in the light space, applied by the sampling shader.
+  pub depth_bias: f32,
+  /// The offset of the receiver position along its normal in the texel size, applied by the sampling shader.
+  pub normal_bias: f32,
+  /// The near plane distance of the perspective shadow maps.
+  pub near_plane: f32,
+  /// The sample count of the blocker search of the percentage-closer soft shadows(PCSS), 0 disables PCSS.
+  /// The penumbra widens with the light size and the distance from the blocker to the receiver.
+  pub blocker_search_samples: u32,
+  /// The sample count of the percentage-closer filter, 1 is a single hard shadow test.
+  pub filter_samples: u32,
+}
+
+/// The Default implementation of the shadow config.
+impl Default for HalaShadowConfig {
+  fn default() -> Self {
+    Self {
+      atlas_size: 4096,
+      tile_size: 1024,
+      depth_bias: 0.0005,
+      normal_bias: 1.0,
+      near_plane: 0.05,
+      blocker_search_samples: 0,
+      filter_samples: 1,
+    }
+  }
+}
+
+/// The shadow map of a light face in the GPU.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaShadowTile {
+  // The view-projection matrix of the light face.
+  pub vp_mtx: glam::Mat4,
+  // The offset and the scale of the tile in the atlas UV, uv = (ndc.xy * (0.5, -0.5) + 0.5) * scale + offset.
+  pub uv_rect: glam::Vec4,
+}
+
+/// The shadow uniform buffer, the binding 5 of the static descriptor set.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct HalaShadowUniform {
+  // The first tile index and the tile count of each light in the light order(zw are unused), the count is 0 if the light has no shadow.
+  pub light_tiles: [[u32; 4]; MAX_LIGHT_COUNT],
+  pub tiles: [HalaShadowTile; MAX_SHADOW_TILES],
+  // The atlas size, the depth bias, the normal bias and the tile count.
+  pub params: glam::Vec4,
+  // The soft shadow parameters of each light in the light order:
+  //   x is the light size in the tile UV, it is per unit distance for the perspective tiles and per unit depth for the orthographic tile,
+  //   y and z are the near and the far planes to linearize the depth, w is 1 for the orthographic tile and 0 for the perspective tiles.
+  // The blocker search radius and the penumbra width are HalaShadowAtlas::get_blocker_search_radius and HalaShadowAtlas::get_penumbra_width,
+  // x is 0 for the lights without size.
+  pub light_params: [glam::Vec4; MAX_LIGHT_COUNT],
+  // The blocker search sample count and the filter sample count, zw are unused.
+  pub soft_params: glam::Vec4,
+}
+
+/// The shadow atlas packs the shadow maps of all lights into one depth image, so the shaders bind one texture for all lights.
+/// The light types(the _type of gpu::HalaLight) use the tiles as follows:
+///   the point(0), the quad(3) and the sphere(4) lights render 6 tiles of the cube faces in the order +X, -X, +Y, -Y, +Z and -Z,
+///   the shader selects the face by the major axis of the direction from the light to the receiver;
+///   the directional light(1) renders 1 orthographic tile fitted to the scene bounds;
+///   the spot light(2) renders 1 perspective tile covering its outer cone.
+/// The tiles are allocated in the light order, the lights which do not fit have no shadow.
+/// The light size of the soft shadows reuses the area light parameters of gpu::HalaLight:
+///   the sphere light uses its radius, the quad light uses the radius of the disk with the same area,
+///   the directional light uses the tangent of its angular radius, the point and the spot lights have no size.
+pub struct HalaShadowAtlas {
+  pub(crate) config: HalaShadowConfig,
+  pub(crate) depth_convention: HalaDepthConvention,
+  pub(crate) image: HalaImage,
+  pub(crate) sampler: HalaSampler,
+  pub(crate) uniform_buffer: HalaBuffer,
+  pub(crate) num_of_tiles: usize,
+}
+
+/// The implementation of the shadow atlas.
+impl HalaShadowAtlas {
+
+  /// Create a new shadow atlas without any tile, call update with the scene to allocate the tiles.
+  /// param context: The GFX context.
+  /// param config: The shadow config.
+  /// param depth_convention: The depth convention of the shadow maps, the same as the scene.
+  /// return: The shadow atlas.
+  pub fn new(context: &HalaContext, config: HalaShadowConfig, depth_convention: HalaDepthConvention) -> Result<Self, HalaRendererError> {
+    if config.tile_size == 0 || config.atlas_size < config.tile_size || config.atlas_size % config.tile_size != 0 {
+      return Err(HalaRendererError::new(
+        &format!("The shadow atlas size {} must be a multiple of the tile size {}!", config.atlas_size, config.tile_size), None));
+    }
+    Self::check_soft_shadow_samples(config.blocker_search_samples, config.filter_samples)?;
+
+    let image = HalaImage::new_2d(
+      Rc::clone(&context.logical_device),
+      hala_gfx::HalaImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | hala_gfx::HalaImageUsageFlags::SAMPLED,
+      SHADOW_ATLAS_FORMAT,
+      config.atlas_size,
+      config.atlas_size,
+      1,
+      1,
+      hala_gfx::HalaMemoryLocation::GpuOnly,
+      "shadow_atlas.image",
+    )?;
+
+    // The depth is compared by the shader, so the texels are not filtered.
+    let sampler = HalaSampler::new(
+      Rc::clone(&context.logical_device),
+      (hala_gfx::HalaFilter::NEAREST, hala_gfx::HalaFilter::NEAREST),
+      hala_gfx::HalaSamplerMipmapMode::NEAREST,
+      (hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE, hala_gfx::HalaSamplerAddressMode::CLAMP_TO_EDGE),
+      0.0,
+      false,
+      0.0,
+      (0.0, 0.0),
+      "shadow_atlas.sampler",
+    )?;
+
+    let uniform_buffer = HalaBuffer::new(
+      Rc::clone(&context.logical_device),
+      std::mem::size_of::<HalaShadowUniform>() as u64,
+      hala_gfx::HalaBufferUsageFlags::UNIFORM_BUFFER,
+      hala_gfx::HalaMemoryLocation::CpuToGpu,
+      "shadow.uniform_buffer",
+    )?;
+
+    Ok(Self {
+      config,
+      depth_convention,
+      image,
+      sampler,
+      uniform_buffer,
+      num_of_tiles: 0,
+    })
+  }
+
+  /// Check the sample counts of the soft shadows.
+  /// param blocker_search_samples: The sample count of the blocker search, 0 disables PCSS.
+  /// param filter_samples: The sample count of the filter.
+  /// return: The result.
+  pub fn check_soft_shadow_samples(blocker_search_samples: u32, filter_samples: u32) -> Result<(), HalaRendererError> {
+    if blocker_search_samples > MAX_SHADOW_SAMPLES || filter_samples == 0 || filter_samples > MAX_SHADOW_SAMPLES {
+      return Err(HalaRendererError::new(
+        &format!(
+          "The shadow blocker search samples {} must be in [0, {}] and the filter samples {} must be in [1, {}]!",
+          blocker_search_samples, MAX_SHADOW_SAMPLES, filter_samples, MAX_SHADOW_SAMPLES,
+        ),
+        None,
+      ));
+    }
+
+    Ok(())
+  }
+
+  /// Set the sample counts of the soft shadows, call update to upload them.
+  /// param blocker_search_samples: The sample count of the blocker search, 0 disables PCSS.
+  /// param filter_samples: The sample count of the filter.
+  /// return: The result.
+  pub fn set_soft_shadow_samples(&mut self, blocker_search_samples: u32, filter_samples: u32) -> Result<(), HalaRendererError> {
+    Self::check_soft_shadow_samples(blocker_search_samples, filter_samples)?;
+    self.config.blocker_search_samples = blocker_search_samples;
+    self.config.filter_samples = filter_samples;
+
+    Ok(())
+  }
+
+  /// Get the tile count of a light type.
+  /// param light_type: The _type of gpu::HalaLight.
+  /// return: The tile count.
+  pub fn get_light_tile_count(light_type: u32) -> usize {
+    match light_type {
+      1 | 2 => 1,
+      _ => 6,
+    }
+  }
+
+  /// Get the max tile count of the atlas.
+  /// return: The tile count.
+  pub fn get_max_tile_count(&self) -> usize {
+    let tiles_per_row = (self.config.atlas_size / self.config.tile_size) as usize;
+    (tiles_per_row * tiles_per_row).min(MAX_SHADOW_TILES)
+  }
+
+  /// Get the allocated tile count.
+  /// return: The tile count.
+  pub fn get_tile_count(&self) -> usize {
+    self.num_of_tiles
+  }
+
+  /// Get the config.
+  /// return: The shadow config.
+  pub fn get_config(&self) -> &HalaShadowConfig {
+    &self.config
+  }
+
+  /// Get the shadow atlas image.
+  /// return: The depth image.
+  pub fn get_image(&self) -> &HalaImage {
+    &self.image
+  }
+
+  /// Get the rect of the tile in the atlas.
+  /// param tile_index: The tile index.
+  /// return: The rect(x, y, width, height) in texels.
+  pub fn get_tile_rect(&self, tile_index: usize) -> (i32, i32, u32, u32) {
+    let tiles_per_row = (self.config.atlas_size / self.config.tile_size) as usize;
+    let tile_size = self.config.tile_size;
+    (
+      ((tile_index % tiles_per_row) as u32 * tile_size) as i32,
+      ((tile_index / tiles_per_row) as u32 * tile_size) as i32,
+      tile_size,
+      tile_size,
+    )
+  }
+
+  /// Allocate the tiles of the lights and upload the shadow uniform buffer.
+  /// The lights of the scene do not move, so it is called once by the commit of the renderer.
+  /// param scene: The scene in the GPU.
+  /// return: The result.
+  pub fn update(&mut self, scene: &gpu::HalaScene) -> Result<(), HalaRendererError> {
+    let max_tile_count = self.get_max_tile_count();
+    let bounds = scene.get_bounds();
+    let (scene_center, scene_radius) = match bounds.as_ref() {
+      Some(bounds) => (glam::Vec3::from(bounds.center), glam::Vec3::from(bounds.extents).length().max(self.config.near_plane)),
+      None => (glam::Vec3::ZERO, 1.0),
+    };
+
+    let mut uniform = Self::get_empty_uniform();
+    let mut num_of_tiles = 0usize;
+    for (light_index, light) in scene.light_data.iter().enumerate().take(MAX_LIGHT_COUNT) {
+      let tile_count = Self::get_light_tile_count(light._type);
+      if num_of_tiles + tile_count > max_tile_count {
+        log::warn!("The shadow atlas is full, the light {} and the following lights have no shadow.", light_index);
+        break;
+      }
+
+      let position = glam::Vec3::from(light.position);
+      // The perspective shadow maps end at the light range or the farthest point of the scene.
+      let far_plane = if light.range > 0.0 {
+        light.range
+      } else {
+        (scene_center - position).length() + scene_radius
+      }.max(self.config.near_plane * 2.0);
+      let light_size = Self::get_light_size(light);
+      let (vp_mtxs, light_params) = match light._type {
+        1 => {
+          let direction = glam::Vec3::from(light.u).normalize_or_zero();
+          let v_mtx = glam::Mat4::look_to_rh(scene_center - direction * scene_radius, direction, Self::get_up_direction(direction));
+          (
+            vec![self.get_orthographic(scene_radius, 2.0 * scene_radius) * v_mtx],
+            // The tile covers 2 * scene radius, the penumbra grows by the light size per unit depth.
+            glam::Vec4::new(light_size / (2.0 * scene_radius), 0.0, 2.0 * scene_radius, 1.0),
+          )
+        },
+        2 => {
+          let direction = glam::Vec3::from(light.u).normalize_or_zero();
+          let fov = (2.0 * light.v.y.clamp(-1.0, 1.0).acos()).clamp(0.01, std::f32::consts::PI - 0.01);
+          let v_mtx = glam::Mat4::look_to_rh(position, direction, Self::get_up_direction(direction));
+          (
+            vec![self.get_perspective(fov, far_plane) * v_mtx],
+            glam::Vec4::new(light_size / (2.0 * (0.5 * fov).tan()), self.config.near_plane, far_plane, 0.0),
+          )
+        },
+        _ => {
+          let p_mtx = self.get_perspective(std::f32::consts::FRAC_PI_2, far_plane);
+          (
+            CUBE_FACE_DIRECTIONS.iter()
+              .map(|(direction, up)| p_mtx * glam::Mat4::look_to_rh(position, *direction, *up))
+              .collect(),
+            // The cube face covers 2 units at the unit distance.
+            glam::Vec4::new(light_size * 0.5, self.config.near_plane, far_plane, 0.0),
+          )
+        },
+      };
+
+      uniform.light_tiles[light_index] = [num_of_tiles as u32, tile_count as u32, 0, 0];
+      uniform.light_params[light_index] = light_params;
+      for vp_mtx in vp_mtxs {
+        let (x, y, width, height) = self.get_tile_rect(num_of_tiles);
+        let atlas_size = self.config.atlas_size as f32;
+        uniform.tiles[num_of_tiles] = HalaShadowTile {
+          vp_mtx,
+          uv_rect: glam::Vec4::new(x as f32 / atlas_size, y as f32 / atlas_size, width as f32 / atlas_size, height as f32 / atlas_size),
+        };
+        num_of_tiles += 1;
+      }
+    }
+    uniform.params = glam::Vec4::new(self.config.atlas_size as f32, self.config.depth_bias, self.config.normal_bias, num_of_tiles as f32);
+    uniform.soft_params = self.get_soft_params();
+
+    self.uniform_buffer.update_memory(0, &[uniform])?;
+    self.num_of_tiles = num_of_tiles;
+
+    Ok(())
+  }
+
+  /// Remove all tiles, the shaders see no shadow for all lights.
+  /// return: The result.
+  pub fn clear(&mut self) -> Result<(), HalaRendererError> {
+    let mut uniform = Self::get_empty_uniform();
+    uniform.params = glam::Vec4::new(self.config.atlas_size as f32, self.config.depth_bias, self.config.normal_bias, 0.0);
+    uniform.soft_params = self.get_soft_params();
+
+    self.uniform_buffer.update_memory(0, &[uniform])?;
+    self.num_of_tiles = 0;
+
+    Ok(())
+  }
+
+  /// Write the shadow atlas, the sampler and the uniform buffer into the bindings 3, 4 and 5 of a static descriptor set.
+  /// param static_descriptor_set: The static descriptor set.
+  /// param num_of_frames: The set count of the static descriptor set.
+  pub fn update_descriptor_set(&self, static_descriptor_set: &HalaDescriptorSet, num_of_frames: usize) {
+    for index in 0..num_of_frames {
+      static_descriptor_set.update_sampled_images(index, 3, &[&self.image]);
+      static_descriptor_set.update_samplers(index, 4, &[&self.sampler]);
+      static_descriptor_set.update_uniform_buffers(index, 5, &[&self.uniform_buffer]);
+    }
+  }
+
+  /// Get the shadow uniform without any tile.
+  /// return: The shadow uniform.
+  fn get_empty_uniform() -> HalaShadowUniform {
+    HalaShadowUniform {
+      light_tiles: [[0; 4]; MAX_LIGHT_COUNT],
+      tiles: [HalaShadowTile { vp_mtx: glam::Mat4::IDENTITY, uv_rect: glam::Vec4::ZERO }; MAX_SHADOW_TILES],
+      params: glam::Vec4::ZERO,
+      light_params: [glam::Vec4::ZERO; MAX_LIGHT_COUNT],
+      soft_params: glam::Vec4::ZERO,
+    }
+  }
+
+  /// Get the soft shadow parameters of the shadow uniform.
+  /// return: The blocker search sample count and the filter sample count.
+  fn get_soft_params(&self) -> glam::Vec4 {
+    glam::Vec4::new(self.config.blocker_search_samples as f32, self.config.filter_samples as f32, 0.0, 0.0)
+  }
+
+  /// Get the blocker search radius of PCSS in the tile UV, the same as the sampling shader.
+  /// param light_params: The soft shadow parameters of the light in the shadow uniform.
+  /// param receiver_distance: The linear distance(the depth for the orthographic tile) from the light to the receiver.
+  /// return: The search radius, 0 for the lights without size.
+  pub fn get_blocker_search_radius(light_params: glam::Vec4, receiver_distance: f32) -> f32 {
+    if light_params.w > 0.0 {
+      light_params.x * (receiver_distance - light_params.y).max(0.0)
+    } else if receiver_distance > 0.0 {
+      light_params.x * (receiver_distance - light_params.y).max(0.0) / receiver_distance
+    } else {
+      0.0
+    }
+  }
+
+  /// Get the penumbra width of PCSS in the tile UV from the average blocker distance, the same as the sampling shader.
+  /// The width is 0 when the blocker touches the receiver and grows with the distance between them.
+  /// param light_params: The soft shadow parameters of the light in the shadow uniform.
+  /// param blocker_distance: The average linear distance(the depth for the orthographic tile) from the light to the blockers.
+  /// param receiver_distance: The linear distance(the depth for the orthographic tile) from the light to the receiver.
+  /// return: The penumbra width, 0 if the blocker is not in front of the receiver.
+  pub fn get_penumbra_width(light_params: glam::Vec4, blocker_distance: f32, receiver_distance: f32) -> f32 {
+    let distance = (receiver_distance - blocker_distance).max(0.0);
+    if light_params.w > 0.0 {
+      light_params.x * distance
+    } else if blocker_distance > 0.0 {
+      light_params.x * distance / blocker_distance
+    } else {
+      0.0
+    }
+  }
+
+  /// Get the size of a light for the soft shadows.
+  /// param light: The light in the GPU.
+  /// return: The radius in the world space for the local lights, the tangent of the angular radius for the directional light.
+  fn get_light_size(light: &gpu::HalaLight) -> f32 {
+    match light._type {
+      1 => {
+        let cos_angle = light.v.x.clamp(-1.0, 1.0);
+        if cos_angle > 0.0 { (1.0 - cos_angle * cos_angle).sqrt() / cos_angle } else { 0.0 }
+      },
+      3 => (light.area.max(0.0) / std::f32::consts::PI).sqrt(),
+      4 => light.radius.max(0.0),
+      _ => 0.0,
+    }
+  }
+
+  /// Get an up direction which is not parallel to the forward direction.
+  /// param forward: The normalized forward direction.
+  /// return: The up direction.
+  fn get_up_direction(forward: glam::Vec3) -> glam::Vec3 {
+    if forward.y.abs() > 0.99 { glam::Vec3::Z } else { glam::Vec3::Y }
+  }
+
+  /// Get the square perspective projection of the depth convention.
+  /// param fov: The field of view in radians.
+  /// param far_plane: The far plane distance.
+  /// return: The projection matrix.
+  fn get_perspective(&self, fov: f32, far_plane: f32) -> glam::Mat4 {
+    if self.depth_convention.is_reverse_z() {
+      glam::Mat4::perspective_rh(fov, 1.0, far_plane, self.config.near_plane)
+    } else {
+      glam::Mat4::perspective_rh(fov, 1.0, self.config.near_plane, far_plane)
+    }
+  }
+
+  /// Get the square orthographic projection of the depth convention.
+  /// param half_size: The half width and the half height of the view volume.
+  /// param far_plane: The far plane distance, the near plane is 0.
+  /// return: The projection matrix.
+  fn get_orthographic(&self, half_size: f32, far_plane: f32) -> glam::Mat4 {
+    if self.depth_convention.is_reverse_z() {
+      glam::Mat4::orthographic_rh(-half_size, half_size, -half_size, half_size, far_plane, 0.0)
+    } else {
+      glam::Mat4::orthographic_rh(-half_size, half_size, -half_size, half_size, 0.0, far_plane)
+    }
+  }
+
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// The soft shadow parameters of a perspective tile with the light size 0.1 and the near plane 0.05.
+  const PERSPECTIVE_PARAMS: glam::Vec4 = glam::Vec4::new(0.1, 0.05, 100.0, 0.0);
+  /// The soft shadow parameters of an orthographic tile with the light size 0.01 per unit depth.
+  const ORTHOGRAPHIC_PARAMS: glam::Vec4 = glam::Vec4::new(0.01, 0.0, 20.0, 1.0);
+
+  #[test]
+  fn test_penumbra_zero_at_contact() {
+    for params in [PERSPECTIVE_PARAMS, ORTHOGRAPHIC_PARAMS] {
+      for distance in [0.5, 1.0, 10.0] {
+        assert_eq!(HalaShadowAtlas::get_penumbra_width(params, distance, distance), 0.0);
+      }
+      // The blocker behind the receiver casts no penumbra.
+      assert_eq!(HalaShadowAtlas::get_penumbra_width(params, 2.0, 1.0), 0.0);
+    }
+    // The lights without size have hard shadows.
+    assert_eq!(HalaShadowAtlas::get_penumbra_width(glam::Vec4::new(0.0, 0.05, 100.0, 0.0), 1.0, 5.0), 0.0);
+  }
+
+  #[test]
+  fn test_penumbra_monotonic() {
+    for params in [PERSPECTIVE_PARAMS, ORTHOGRAPHIC_PARAMS] {
+      // The penumbra widens as the receiver moves away from the blocker.
+      let blocker_distance = 2.0;
+      let mut last_width = 0.0;
+      for step in 1..=32 {
+        let width = HalaShadowAtlas::get_penumbra_width(params, blocker_distance, blocker_distance + step as f32 * 0.25);
+        assert!(width > last_width, "The penumbra width {} is not wider than {} at step {}.", width, last_width, step);
+        last_width = width;
+      }
+    }
+
+    // The penumbra narrows as the blocker moves toward the receiver.
+    let receiver_distance = 10.0;
+    let mut last_width = f32::MAX;
+    for step in 1..40 {
+      let width = HalaShadowAtlas::get_penumbra_width(PERSPECTIVE_PARAMS, step as f32 * 0.25, receiver_distance);
+      assert!(width < last_width, "The penumbra width {} is not narrower than {} at step {}.", width, last_width, step);
+      last_width = width;
+    }
+  }
+
+  #[test]
+  fn test_penumbra_similar_triangles() {
+    // The light of the size w at the distance 0, the blocker at d_b and the receiver at d_r: w_p = w * (d_r - d_b) / d_b.
+    let width = HalaShadowAtlas::get_penumbra_width(PERSPECTIVE_PARAMS, 2.0, 6.0);
+    assert!((width - 0.1 * 4.0 / 2.0).abs() < 1e-6);
+    let width = HalaShadowAtlas::get_penumbra_width(ORTHOGRAPHIC_PARAMS, 2.0, 6.0);
+    assert!((width - 0.01 * 4.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_blocker_search_radius() {
+    // Nothing in front of the near plane blocks the receiver.
+    assert_eq!(HalaShadowAtlas::get_blocker_search_radius(PERSPECTIVE_PARAMS, 0.05), 0.0);
+    let mut last_radius = 0.0;
+    for step in 1..=16 {
+      let radius = HalaShadowAtlas::get_blocker_search_radius(PERSPECTIVE_PARAMS, step as f32);
+      assert!(radius > last_radius && radius < PERSPECTIVE_PARAMS.x);
+      last_radius = radius;
+    }
+    // The orthographic search radius covers the penumbra of a blocker at the depth 0.
+    assert!((HalaShadowAtlas::get_blocker_search_radius(ORTHOGRAPHIC_PARAMS, 5.0) - 0.05).abs() < 1e-6);
+  }
+
+}
@@ -0,0 +1,317 @@
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+
+use ash::vk;
+
+use hala_gfx::{
+  HalaContext,
+  HalaLogicalDevice,
+  HalaBuffer,
+  HalaBufferUsageFlags,
+  HalaMemoryLocation,
+  HalaImage,
+  HalaImageLayout,
+  HalaImageAspectFlags,
+  HalaCommandBufferSet,
+  HalaBufferBarrierInfo,
+  HalaImageBarrierInfo,
+  HalaAccessFlags2,
+  HalaPipelineStageFlags2,
+  HalaSemaphore,
+};
+
+use crate::error::HalaRendererError;
+use crate::scene::loader::transfer_batch::get_staging_offset;
+
+/// The size of each staging buffer of the ring.
+pub const STREAMING_STAGING_SIZE: u64 = 32 * 1024 * 1024;
+
+/// The destination of a streaming upload.
+/// The target must not be in use by the graphics queue, e.g. a new buffer or image of the streamed content.
+/// The existing resources are replaced instead of overwritten, and the replaced ones are retired.
+pub enum HalaUploadTarget<'a> {
+  /// The buffer and the offset in bytes.
+  Buffer(&'a HalaBuffer, u64),
+  /// The image with one mip level, it ends in the shader read only layout.
+  Image(&'a HalaImage),
+}
+
+/// The streaming uploader of the renderer resources.
+/// The uploads are copied to a ring of staging buffers and recorded to a dedicated transfer command buffer set without waiting.
+/// flush_uploads submits the batch to the transfer queue, it signals a timeline semaphore which the next graphics submit waits.
+/// If the transfer queue family differs from the graphics one, the targets are released by the transfer queue
+/// and acquired by the next graphics frame.
+pub struct HalaStreamingUploader {
+  // One command buffer and one staging buffer for each batch of the ring.
+  command_buffers: HalaCommandBufferSet,
+  staging_buffers: Vec<HalaBuffer>,
+  // The timeline value of the last batch of each slot, the slot is reused after it is reached.
+  staging_values: Vec<u64>,
+  // The dedicated staging buffers of the uploads larger than a staging buffer of the ring.
+  oversized_staging_buffers: Vec<Vec<HalaBuffer>>,
+  slot_index: usize,
+  staging_offset: u64,
+  is_recording: bool,
+  // The release barriers are recorded to the batch, the acquire barriers to the next graphics frame.
+  acquire_buffer_barriers: Vec<HalaBufferBarrierInfo>,
+  acquire_image_barriers: Vec<HalaImageBarrierInfo>,
+  pending_acquire_buffer_barriers: Vec<HalaBufferBarrierInfo>,
+  pending_acquire_image_barriers: Vec<HalaImageBarrierInfo>,
+  // The numbers of the pending acquire barriers recorded by the current graphics frame,
+  // a batch flushed after the recording is acquired by the next frame.
+  recorded_acquire_counts: Cell<(usize, usize)>,
+  // The transfer queue family index and the graphics queue family index if the ownership is transferred.
+  ownership_transfer: Option<(u32, u32)>,
+  logical_device: Rc<RefCell<HalaLogicalDevice>>,
+
+  // The timeline value signaled by the last submitted batch, and the value waited by the next graphics submit.
+  pub timeline: HalaSemaphore,
+  pub timeline_value: u64,
+  pub pending_graphics_wait: Option<u64>,
+}
+
+/// The implementation of the streaming uploader.
+impl HalaStreamingUploader {
+  /// Create the streaming uploader.
+  /// param context: The gfx context.
+  /// param num_of_slots: The number of the batches in the ring.
+  /// return: The streaming uploader.
+  pub fn new(context: &HalaContext, num_of_slots: usize) -> Result<Self, HalaRendererError> {
+    let logical_device = Rc::clone(&context.logical_device);
+    let command_buffers = HalaCommandBufferSet::new(
+      Rc::clone(&logical_device),
+      Rc::clone(&context.command_pools),
+      hala_gfx::HalaCommandBufferType::TRANSFER,
+      hala_gfx::HalaCommandBufferLevel::PRIMARY,
+      num_of_slots,
+      "streaming_transfer.cmd_buffer",
+    )?;
+    let staging_buffers = (0..num_of_slots).map(|index| HalaBuffer::new(
+      Rc::clone(&logical_device),
+      STREAMING_STAGING_SIZE,
+      HalaBufferUsageFlags::TRANSFER_SRC,
+      HalaMemoryLocation::CpuToGpu,
+      &format!("streaming_staging_{}.buffer", index),
+    )).collect::<Result<Vec<_>, _>>()?;
+    let timeline = HalaSemaphore::new_timeline(
+      Rc::clone(&logical_device),
+      0,
+      "streaming.timeline_semaphore",
+    )?;
+
+    let ownership_transfer = {
+      let logical_device = logical_device.borrow();
+      if logical_device.transfer_queue_family_index == logical_device.graphics_queue_family_index {
+        None
+      } else {
+        Some((logical_device.transfer_queue_family_index, logical_device.graphics_queue_family_index))
+      }
+    };
+
+    Ok(Self {
+      command_buffers,
+      staging_buffers,
+      staging_values: vec![0; num_of_slots],
+      oversized_staging_buffers: (0..num_of_slots).map(|_| Vec::new()).collect(),
+      slot_index: 0,
+      staging_offset: 0,
+      is_recording: false,
+      acquire_buffer_barriers: Vec::new(),
+      acquire_image_barriers: Vec::new(),
+      pending_acquire_buffer_barriers: Vec::new(),
+      pending_acquire_image_barriers: Vec::new(),
+      recorded_acquire_counts: Cell::new((0, 0)),
+      ownership_transfer,
+      logical_device,
+      timeline,
+      timeline_value: 0,
+      pending_graphics_wait: None,
+    })
+  }
+
+  /// Copy the data to the staging ring and record the upload to the current batch without waiting.
+  /// The batch is flushed first if the data does not fit in the rest of its staging buffer.
+  /// param data: The data.
+  /// param target: The upload target.
+  /// return: The timeline value of the batch, the target can be referenced by the frames after it is submitted.
+  pub fn enqueue_upload(&mut self, data: &[u8], target: HalaUploadTarget) -> Result<u64, HalaRendererError> {
+    if let HalaUploadTarget::Buffer(buffer, offset) = &target {
+      if offset + data.len() as u64 > buffer.size {
+        return Err(HalaRendererError::validation(&format!(
+          "The upload of {} bytes at the offset {} exceeds the buffer of {} bytes.", data.len(), offset, buffer.size)));
+      }
+    }
+
+    // Suballocate the staging memory from the ring, the larger uploads get a dedicated staging buffer.
+    let size = data.len() as u64;
+    let is_oversized = size > STREAMING_STAGING_SIZE;
+    if !is_oversized && self.is_recording && get_staging_offset(STREAMING_STAGING_SIZE, self.staging_offset, size).is_none() {
+      self.flush_uploads()?;
+    }
+    self.begin_batch()?;
+    let index = self.slot_index;
+    let (staging_buffer, staging_offset) = if is_oversized {
+      let staging_buffer = HalaBuffer::new(
+        Rc::clone(&self.logical_device),
+        size,
+        HalaBufferUsageFlags::TRANSFER_SRC,
+        HalaMemoryLocation::CpuToGpu,
+        &format!("streaming_staging_{}_{}.buffer", index, self.oversized_staging_buffers[index].len()),
+      )?;
+      self.oversized_staging_buffers[index].push(staging_buffer);
+      (&self.oversized_staging_buffers[index][self.oversized_staging_buffers[index].len() - 1], 0)
+    } else {
+      let staging_offset = get_staging_offset(STREAMING_STAGING_SIZE, self.staging_offset, size)
+        .ok_or(HalaRendererError::invalid_state("The staging buffer of the streaming uploader is full!"))?;
+      self.staging_offset = staging_offset + size;
+      (&self.staging_buffers[index], staging_offset)
+    };
+    staging_buffer.update_memory(staging_offset as usize, data)?;
+
+    match target {
+      HalaUploadTarget::Buffer(buffer, offset) => {
+        self.command_buffers.copy_buffer_2_buffer(index, staging_buffer, staging_offset, buffer, offset, size);
+
+        if let Some((transfer_queue_family_index, graphics_queue_family_index)) = self.ownership_transfer {
+          let barrier = |is_release: bool| HalaBufferBarrierInfo {
+            src_access_mask: if is_release { HalaAccessFlags2::TRANSFER_WRITE } else { HalaAccessFlags2::NONE },
+            dst_access_mask: if is_release { HalaAccessFlags2::NONE } else { HalaAccessFlags2::MEMORY_READ },
+            src_stage_mask: if is_release { HalaPipelineStageFlags2::TRANSFER } else { HalaPipelineStageFlags2::NONE },
+            dst_stage_mask: if is_release { HalaPipelineStageFlags2::NONE } else { HalaPipelineStageFlags2::ALL_COMMANDS },
+            src_queue_family_index: transfer_queue_family_index,
+            dst_queue_family_index: graphics_queue_family_index,
+            buffer: buffer.raw,
+            size: buffer.size,
+            ..Default::default()
+          };
+          self.command_buffers.set_buffer_barriers(index, &[barrier(true)]);
+          self.acquire_buffer_barriers.push(barrier(false));
+        }
+      },
+      HalaUploadTarget::Image(image) => {
+        self.command_buffers.set_image_barriers(
+          index,
+          &[HalaImageBarrierInfo {
+            old_layout: HalaImageLayout::UNDEFINED,
+            new_layout: HalaImageLayout::TRANSFER_DST_OPTIMAL,
+            src_access_mask: HalaAccessFlags2::NONE,
+            dst_access_mask: HalaAccessFlags2::TRANSFER_WRITE,
+            src_stage_mask: HalaPipelineStageFlags2::NONE,
+            dst_stage_mask: HalaPipelineStageFlags2::TRANSFER,
+            aspect_mask: HalaImageAspectFlags::COLOR,
+            image: image.raw,
+            ..Default::default()
+          }],
+        );
+        self.command_buffers.copy_buffer_2_image(index, staging_buffer, staging_offset, image, HalaImageLayout::TRANSFER_DST_OPTIMAL);
+
+        // The layout transition is a part of the release and the acquire, they must match.
+        let (src_queue_family_index, dst_queue_family_index) = self.ownership_transfer
+          .unwrap_or((vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED));
+        let barrier = |is_release: bool| HalaImageBarrierInfo {
+          old_layout: HalaImageLayout::TRANSFER_DST_OPTIMAL,
+          new_layout: HalaImageLayout::SHADER_READ_ONLY_OPTIMAL,
+          src_access_mask: if is_release { HalaAccessFlags2::TRANSFER_WRITE } else { HalaAccessFlags2::NONE },
+          dst_access_mask: if is_release { HalaAccessFlags2::NONE } else { HalaAccessFlags2::SHADER_READ },
+          src_stage_mask: if is_release { HalaPipelineStageFlags2::TRANSFER } else { HalaPipelineStageFlags2::NONE },
+          dst_stage_mask: if is_release { HalaPipelineStageFlags2::NONE } else { HalaPipelineStageFlags2::ALL_COMMANDS },
+          src_queue_family_index,
+          dst_queue_family_index,
+          aspect_mask: HalaImageAspectFlags::COLOR,
+          image: image.raw,
+          ..Default::default()
+        };
+        self.command_buffers.set_image_barriers(index, &[barrier(true)]);
+        if self.ownership_transfer.is_some() {
+          self.acquire_image_barriers.push(barrier(false));
+        }
+      },
+    }
+
+    Ok(self.timeline_value + 1)
+  }
+
+  /// Begin the batch of the current slot if it is not recording.
+  /// The slot is reused after its last batch is complete, which is usually several frames ago.
+  /// return: The result.
+  fn begin_batch(&mut self) -> Result<(), HalaRendererError> {
+    if self.is_recording {
+      return Ok(());
+    }
+
+    let index = self.slot_index;
+    self.timeline.wait(self.staging_values[index], u64::MAX)?;
+    self.oversized_staging_buffers[index].clear();
+    self.command_buffers.reset(index, false)?;
+    self.command_buffers.begin(index, hala_gfx::HalaCommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+    self.staging_offset = 0;
+    self.is_recording = true;
+
+    Ok(())
+  }
+
+  /// Submit the recorded batch to the transfer queue, it signals the next timeline value without waiting.
+  /// The next graphics submit waits the value and acquires the targets.
+  /// return: The timeline value of the batch, none if no upload is recorded.
+  pub fn flush_uploads(&mut self) -> Result<Option<u64>, HalaRendererError> {
+    if !self.is_recording {
+      return Ok(None);
+    }
+
+    let index = self.slot_index;
+    let timeline_value = self.timeline_value + 1;
+    self.command_buffers.end(index)?;
+    self.logical_device.borrow().transfer_submit_with_semaphores(
+      &self.command_buffers,
+      index,
+      0,
+      &[],
+      &[(&self.timeline, timeline_value, HalaPipelineStageFlags2::ALL_COMMANDS)],
+    )?;
+
+    self.timeline_value = timeline_value;
+    self.staging_values[index] = timeline_value;
+    self.pending_graphics_wait = Some(timeline_value);
+    self.pending_acquire_buffer_barriers.append(&mut self.acquire_buffer_barriers);
+    self.pending_acquire_image_barriers.append(&mut self.acquire_image_barriers);
+    self.slot_index = (index + 1) % self.staging_buffers.len();
+    self.is_recording = false;
+
+    Ok(Some(timeline_value))
+  }
+
+  /// Whether the batch of the timeline value is complete on the GPU.
+  /// param timeline_value: The timeline value returned by enqueue_upload or flush_uploads.
+  /// return: True if the batch is complete.
+  pub fn is_upload_complete(&self, timeline_value: u64) -> Result<bool, HalaRendererError> {
+    Ok(timeline_value <= self.timeline_value && self.timeline.get_value()? >= timeline_value)
+  }
+
+  /// Record the acquire barriers of the submitted batches to the graphics command buffer.
+  /// The command buffer must be submitted with the wait of take_graphics_wait.
+  /// param index: The index of the frame.
+  /// param command_buffers: The graphics command buffers.
+  pub fn record_acquire(&self, index: usize, command_buffers: &HalaCommandBufferSet) {
+    if !self.pending_acquire_buffer_barriers.is_empty() {
+      command_buffers.set_buffer_barriers(index, &self.pending_acquire_buffer_barriers);
+    }
+    if !self.pending_acquire_image_barriers.is_empty() {
+      command_buffers.set_image_barriers(index, &self.pending_acquire_image_barriers);
+    }
+    self.recorded_acquire_counts.set((self.pending_acquire_buffer_barriers.len(), self.pending_acquire_image_barriers.len()));
+  }
+
+  /// Take the timeline value waited by the graphics submit and drop the acquire barriers recorded by the frame.
+  /// If a batch is flushed after the recording, its barriers and its value are kept for the next frame.
+  /// return: The timeline value, none if no batch is submitted since the last graphics submit.
+  pub fn take_graphics_wait(&mut self) -> Option<u64> {
+    let (num_of_buffer_barriers, num_of_image_barriers) = self.recorded_acquire_counts.replace((0, 0));
+    self.pending_acquire_buffer_barriers.drain(..num_of_buffer_barriers);
+    self.pending_acquire_image_barriers.drain(..num_of_image_barriers);
+    if self.pending_acquire_buffer_barriers.is_empty() && self.pending_acquire_image_barriers.is_empty() {
+      self.pending_graphics_wait.take()
+    } else {
+      self.pending_graphics_wait
+    }
+  }
+}
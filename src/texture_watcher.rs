@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::scene::cpu;
+
+/// The texture watcher.
+/// It polls the source files of the scene images and reports the changed ones.
+pub struct HalaTextureWatcher {
+  // Image index, file path and last modified time.
+  entries: Vec<(usize, PathBuf, Option<SystemTime>)>,
+}
+
+/// The implementation of the texture watcher.
+impl HalaTextureWatcher {
+
+  /// Create a new texture watcher with the image paths recorded by the scene loader.
+  /// param scene: The scene in the CPU.
+  /// return: The texture watcher.
+  pub fn new(scene: &cpu::HalaScene) -> Self {
+    let entries = scene.image_paths.iter().enumerate()
+      .filter_map(|(image_index, path)| path.as_ref().map(|path| (image_index, path.clone(), Self::get_modified_time(path))))
+      .collect();
+
+    Self {
+      entries,
+    }
+  }
+
+  /// Watch a file for the image.
+  /// param image_index: The index of the image in the scene.
+  /// param path: The file path.
+  pub fn watch<P: AsRef<Path>>(&mut self, image_index: usize, path: P) {
+    let path = path.as_ref();
+    let modified_time = Self::get_modified_time(path);
+    match self.entries.iter_mut().find(|(index, _, _)| *index == image_index) {
+      Some(entry) => *entry = (image_index, path.to_path_buf(), modified_time),
+      None => self.entries.push((image_index, path.to_path_buf(), modified_time)),
+    }
+  }
+
+  /// Poll the watched files.
+  /// return: The image indices and the file paths of the changed files.
+  pub fn poll(&mut self) -> Vec<(usize, PathBuf)> {
+    let mut changed = Vec::new();
+    for (image_index, path, last_modified_time) in self.entries.iter_mut() {
+      let modified_time = Self::get_modified_time(path);
+      // The file may be missing while the painting tool is saving it, wait for the next poll.
+      if modified_time.is_some() && modified_time != *last_modified_time {
+        *last_modified_time = modified_time;
+        changed.push((*image_index, path.clone()));
+      }
+    }
+    changed
+  }
+
+  /// Get the last modified time of the file.
+  /// param path: The file path.
+  /// return: The last modified time.
+  fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+  }
+
+}
\ No newline at end of file
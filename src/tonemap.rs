@@ -0,0 +1,241 @@
+/// The type of the tone mapping operator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HalaToneMapType(u8);
+impl HalaToneMapType {
+  pub const LINEAR: Self = Self(0);
+  pub const REINHARD: Self = Self(1);
+  pub const ACES_FITTED: Self = Self(2);
+  pub const ACES_APPROX: Self = Self(3);
+  pub const UNCHARTED2: Self = Self(4);
+  pub const AGX: Self = Self(5);
+
+  pub fn from_u8(value: u8) -> Self {
+    match value {
+      0 => Self::LINEAR,
+      1 => Self::REINHARD,
+      2 => Self::ACES_FITTED,
+      3 => Self::ACES_APPROX,
+      4 => Self::UNCHARTED2,
+      5 => Self::AGX,
+      _ => panic!("Invalid tone map type."),
+    }
+  }
+
+  pub fn to_u8(&self) -> u8 {
+    self.0
+  }
+
+  /// Get the tone map type from the legacy flags.
+  /// param enable_tonemap: Enable the tonemap or not.
+  /// param enable_aces: Enable the ACES tonemap or not.
+  /// param use_simple_aces: Use the simple ACES tonemap or not.
+  /// return: The tone map type.
+  pub fn from_flags(enable_tonemap: bool, enable_aces: bool, use_simple_aces: bool) -> Self {
+    if !enable_tonemap {
+      Self::LINEAR
+    } else if !enable_aces {
+      Self::REINHARD
+    } else if use_simple_aces {
+      Self::ACES_APPROX
+    } else {
+      Self::ACES_FITTED
+    }
+  }
+
+  /// Get the legacy flags of the tone map type.
+  /// Uncharted 2 and AgX have no legacy flags and are Reinhard in the flags, the shaders select the operator by
+  /// the tone map type with ApplyToneMap of shaders/tonemap.hlsl.
+  /// return: The flags(enable_tonemap, enable_aces, use_simple_aces).
+  pub fn to_flags(&self) -> (bool, bool, bool) {
+    match *self {
+      Self::LINEAR => (false, false, false),
+      Self::ACES_FITTED => (true, true, false),
+      Self::ACES_APPROX => (true, true, true),
+      _ => (true, false, false),
+    }
+  }
+
+  /// Apply the tone mapping operator to the linear color.
+  /// param color: The linear HDR color.
+  /// return: The tone mapped color.
+  pub fn apply(&self, color: glam::Vec3) -> glam::Vec3 {
+    match *self {
+      Self::LINEAR => color,
+      Self::REINHARD => reinhard(color, 1.5),
+      Self::ACES_FITTED => aces_fitted(color),
+      Self::ACES_APPROX => aces_approx(color),
+      Self::UNCHARTED2 => uncharted2(color),
+      Self::AGX => agx(color),
+      _ => panic!("Invalid tone map type."),
+    }
+  }
+}
+
+/// Calculate the luminance of the color.
+/// param c: The linear color.
+/// return: The luminance.
+fn luminance(c: glam::Vec3) -> f32 {
+  0.212671 * c.x + 0.715160 * c.y + 0.072169 * c.z
+}
+
+/// The Reinhard tone mapping on luminance.
+/// param c: The linear color.
+/// param limit: The white point.
+/// return: The tone mapped color.
+fn reinhard(c: glam::Vec3, limit: f32) -> glam::Vec3 {
+  c * 1.0 / (1.0 + luminance(c) / limit)
+}
+
+/// The fitted ACES tone mapping.
+/// param color: The linear color.
+/// return: The tone mapped color.
+fn aces_fitted(color: glam::Vec3) -> glam::Vec3 {
+  const ACES_INPUT_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
+    glam::Vec3::new(0.59719, 0.07600, 0.02840),
+    glam::Vec3::new(0.35458, 0.90834, 0.13383),
+    glam::Vec3::new(0.04823, 0.01566, 0.83777)
+  );
+  const ACES_OUTPUT_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
+    glam::Vec3::new(1.60475, -0.10208, -0.00327),
+    glam::Vec3::new(-0.53108, 1.10813, -0.07276),
+    glam::Vec3::new(-0.07367, -0.00605, 1.07602)
+  );
+  let rrt_odt_fit = |v: glam::Vec3| -> glam::Vec3 {
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.432951) + 0.238081;
+    a / b
+  };
+
+  let mut color = ACES_INPUT_MATRIX * color;
+  color = rrt_odt_fit(color);
+  color = ACES_OUTPUT_MATRIX * color;
+  color.clamp(glam::Vec3::ZERO, glam::Vec3::ONE)
+}
+
+/// The approximated ACES tone mapping(Narkowicz).
+/// param c: The linear color.
+/// return: The tone mapped color.
+fn aces_approx(c: glam::Vec3) -> glam::Vec3 {
+  const A: f32 = 2.51;
+  const B: f32 = 0.03;
+  const Y: f32 = 2.43;
+  const D: f32 = 0.59;
+  const E: f32 = 0.14;
+
+  let r = (c * (A * c + B)) / (c * (Y * c + D) + E);
+  r.clamp(glam::Vec3::ZERO, glam::Vec3::ONE)
+}
+
+/// The Uncharted 2 filmic tone mapping(Hable).
+/// param c: The linear color.
+/// return: The tone mapped color.
+fn uncharted2(c: glam::Vec3) -> glam::Vec3 {
+  const A: f32 = 0.15;
+  const B: f32 = 0.50;
+  const C: f32 = 0.10;
+  const D: f32 = 0.20;
+  const E: f32 = 0.02;
+  const F: f32 = 0.30;
+  const W: f32 = 11.2;
+  const EXPOSURE_BIAS: f32 = 2.0;
+
+  let partial = |x: glam::Vec3| -> glam::Vec3 {
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+  };
+  let white_scale = glam::Vec3::ONE / partial(glam::Vec3::splat(W));
+  (partial(c * EXPOSURE_BIAS) * white_scale).clamp(glam::Vec3::ZERO, glam::Vec3::ONE)
+}
+
+/// The AgX tone mapping with the default look.
+/// param c: The linear color.
+/// return: The tone mapped linear color.
+fn agx(c: glam::Vec3) -> glam::Vec3 {
+  const AGX_INSET_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
+    glam::Vec3::new(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+    glam::Vec3::new(0.0784335999999992, 0.878468636469772, 0.0784336),
+    glam::Vec3::new(0.0792237451477643, 0.0791661274605434, 0.879142973793104)
+  );
+  const AGX_OUTSET_MATRIX: glam::Mat3 = glam::Mat3::from_cols(
+    glam::Vec3::new(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+    glam::Vec3::new(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+    glam::Vec3::new(-0.0990297440797205, -0.0989611768448433, 1.15107367264116)
+  );
+  const MIN_EV: f32 = -12.47393;
+  const MAX_EV: f32 = 4.026069;
+
+  // Encode to the log domain.
+  let v = AGX_INSET_MATRIX * c.max(glam::Vec3::splat(1e-10));
+  let v = glam::Vec3::new(v.x.log2(), v.y.log2(), v.z.log2()).clamp(glam::Vec3::splat(MIN_EV), glam::Vec3::splat(MAX_EV));
+  let x = (v - MIN_EV) / (MAX_EV - MIN_EV);
+
+  // Apply the sigmoid curve.
+  let x2 = x * x;
+  let x4 = x2 * x2;
+  let v = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+  // Decode back to the linear domain.
+  let v = (AGX_OUTSET_MATRIX * v).clamp(glam::Vec3::ZERO, glam::Vec3::ONE);
+  glam::Vec3::new(v.x.powf(2.2), v.y.powf(2.2), v.z.powf(2.2))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALL_TYPES: [HalaToneMapType; 6] = [
+    HalaToneMapType::LINEAR,
+    HalaToneMapType::REINHARD,
+    HalaToneMapType::ACES_FITTED,
+    HalaToneMapType::ACES_APPROX,
+    HalaToneMapType::UNCHARTED2,
+    HalaToneMapType::AGX,
+  ];
+
+  #[test]
+  fn test_legacy_flags() {
+    for tonemap_type in [HalaToneMapType::LINEAR, HalaToneMapType::REINHARD, HalaToneMapType::ACES_FITTED, HalaToneMapType::ACES_APPROX] {
+      let (enable_tonemap, enable_aces, use_simple_aces) = tonemap_type.to_flags();
+      assert_eq!(HalaToneMapType::from_flags(enable_tonemap, enable_aces, use_simple_aces), tonemap_type);
+    }
+    for tonemap_type in [HalaToneMapType::UNCHARTED2, HalaToneMapType::AGX] {
+      assert_eq!(tonemap_type.to_flags(), HalaToneMapType::REINHARD.to_flags());
+    }
+  }
+
+  #[test]
+  fn test_operators() {
+    for tonemap_type in ALL_TYPES {
+      assert_eq!(HalaToneMapType::from_u8(tonemap_type.to_u8()), tonemap_type);
+
+      // The black stays black and the brighter input is not darker.
+      let black = tonemap_type.apply(glam::Vec3::ZERO);
+      assert!(black.max_element() < 1e-3, "{:?} maps the black to {:?}.", tonemap_type, black);
+      let mut last_value = black.x;
+      for step in 1..=64 {
+        let value = tonemap_type.apply(glam::Vec3::splat(step as f32 * 0.25)).x;
+        assert!(value >= last_value - 1e-6, "{:?} is not monotonic at step {}.", tonemap_type, step);
+        last_value = value;
+      }
+    }
+
+    // The filmic operators compress the HDR input into [0, 1], Reinhard on luminance keeps the hue and may exceed 1.
+    for tonemap_type in ALL_TYPES.iter().skip(2) {
+      let color = tonemap_type.apply(glam::Vec3::new(100.0, 10.0, 1.0));
+      assert!(color.min_element() >= 0.0 && color.max_element() <= 1.0, "{:?} maps the HDR input to {:?}.", tonemap_type, color);
+    }
+    assert_eq!(HalaToneMapType::LINEAR.apply(glam::Vec3::splat(2.0)), glam::Vec3::splat(2.0));
+  }
+
+  #[test]
+  fn test_distinct_operators() {
+    // Each operator is implemented, none falls back to another one.
+    let color = glam::Vec3::new(0.8, 0.4, 0.2);
+    let results = ALL_TYPES.map(|tonemap_type| tonemap_type.apply(color));
+    for i in 0..results.len() {
+      for j in (i + 1)..results.len() {
+        assert!(!results[i].abs_diff_eq(results[j], 1e-4), "{:?} and {:?} are the same.", ALL_TYPES[i], ALL_TYPES[j]);
+      }
+    }
+  }
+
+}